@@ -0,0 +1,178 @@
+//! Differential testing against GCC: each test is compiled both by racoon
+//! and by a cross GCC, against the same runtime library, and both programs
+//! are run under QEMU on the same input, their outputs and exit codes
+//! compared. A difference points at a miscompilation, often where the
+//! calling convention or the semantics of an operation is got wrong.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitCode};
+use std::time::Duration;
+
+use clap::Parser;
+
+use racoon::testing::collect;
+use racoon::testing::qemu::{self, Outcome};
+
+/// The SysY runtime library, which both compilers link in.
+const RUNTIME: &str = include_str!("../runtime/sylib.c");
+
+/// Declarations of the runtime library, which GCC needs to call it.
+const RUNTIME_HEADER: &str = include_str!("../runtime/sylib.h");
+
+#[derive(Parser, Debug)]
+#[command(name = "difftest",
+          about = "Compare programs compiled by racoon with those compiled by GCC, run under QEMU")]
+struct Options {
+    /// The SysY sources to test, or directories of them. The input of each
+    /// is the file of the same name ending in `.in`, if any
+    #[arg(required = true)]
+    tests: Vec<PathBuf>,
+
+    /// The racoon to test, by default the one next to this program
+    #[arg(long)]
+    racoon: Option<PathBuf>,
+
+    /// The GCC to compare with, which racoon also links with
+    #[arg(long, default_value = "arm-linux-gnueabihf-gcc")]
+    gcc: String,
+
+    /// The QEMU user mode emulator running both programs
+    #[arg(long, default_value = "qemu-arm")]
+    qemu: String,
+
+    /// Where the C library of the target is, for QEMU to load
+    #[arg(long, default_value = "/usr/arm-linux-gnueabihf")]
+    sysroot: PathBuf,
+
+    /// Seconds a program may run before it is killed
+    #[arg(long, default_value = "10")]
+    timeout: u64,
+
+    /// Flags passed on to racoon, as `-- -O2 --stack-protector`
+    #[arg(last = true)]
+    racoon_args: Vec<String>,
+}
+
+/// How a test went.
+enum Verdict {
+    Pass,
+    /// GCC could not compile the test, which says nothing of racoon.
+    Skip(String),
+    Fail(String),
+}
+
+fn main() -> ExitCode {
+    let options = Options::parse();
+    let racoon = options.racoon.clone().unwrap_or_else(|| {
+        let this = std::env::current_exe().expect("where this program is");
+        this.with_file_name(format!("racoon{}", std::env::consts::EXE_SUFFIX))
+    });
+
+    let mut tests = vec![];
+    for path in &options.tests {
+        if let Err(e) = collect(path, false, &mut tests) {
+            eprintln!("error: could not read `{}`: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+    tests.sort();
+
+    let dir = std::env::temp_dir().join(format!("racoon-difftest-{}", process::id()));
+    if let Err(e) = fs::create_dir_all(&dir)
+        .and_then(|()| fs::write(dir.join("sylib.c"), RUNTIME))
+        .and_then(|()| fs::write(dir.join("sylib.h"), RUNTIME_HEADER)) {
+        eprintln!("error: could not write to `{}`: {e}", dir.display());
+        return ExitCode::FAILURE;
+    }
+
+    let (mut passed, mut skipped, mut failed) = (0, 0, 0);
+    for test in &tests {
+        match run_test(&options, &racoon, &dir, test) {
+            Verdict::Pass => {
+                passed += 1;
+                println!("PASS {}", test.display());
+            }
+            Verdict::Skip(why) => {
+                skipped += 1;
+                println!("SKIP {}: {why}", test.display());
+            }
+            Verdict::Fail(why) => {
+                failed += 1;
+                println!("FAIL {}: {why}", test.display());
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(&dir);
+
+    println!("{passed} passed, {failed} failed, {skipped} skipped");
+    if failed == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+/// Compiles `test` both ways in `dir`, where the runtime library is, and
+/// compares how the programs run.
+fn run_test(options: &Options, racoon: &Path, dir: &Path, test: &Path) -> Verdict {
+    let (ours, theirs) = (dir.join("racoon.out"), dir.join("gcc.out"));
+    let _ = fs::remove_file(&ours);
+    let _ = fs::remove_file(&theirs);
+
+    let mut command = Command::new(&options.gcc);
+    command.args(["-x", "c", "-std=gnu99", "-w", "-march=armv7-a", "-mfloat-abi=hard", "-O2", "-include"])
+        .arg(dir.join("sylib.h"))
+        .arg("-o").arg(&theirs)
+        .arg(test).arg(dir.join("sylib.c"));
+    if let Err(why) = compile(command) {
+        return Verdict::Skip(format!("`{}` {why}", options.gcc));
+    }
+
+    let mut command = Command::new(racoon);
+    command.arg(test).arg("-o").arg(&ours).arg("--linker").arg(&options.gcc).args(&options.racoon_args);
+    if let Err(why) = compile(command) {
+        return Verdict::Fail(format!("racoon {why}"));
+    }
+
+    let input = test.with_extension("in");
+    let input = input.is_file().then_some(input);
+    let timeout = Duration::from_secs(options.timeout);
+    let run = |program: &Path| qemu::run(&options.qemu, &options.sysroot, program, input.as_deref(), timeout).map(|x| x.0);
+    let (ours, theirs) = match (run(&ours), run(&theirs)) {
+        (Ok(ours), Ok(theirs)) => (ours, theirs),
+        (Err(e), _) | (_, Err(e)) => return Verdict::Fail(format!("could not run `{}`: {e}", options.qemu)),
+    };
+    match compare(&ours, &theirs) {
+        None => Verdict::Pass,
+        Some(why) => Verdict::Fail(why),
+    }
+}
+
+/// Runs the compiler `command`, with what it printed if it failed.
+fn compile(mut command: Command) -> Result<(), String> {
+    match command.output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!("failed: {}\n{}", output.status, String::from_utf8_lossy(&output.stderr).trim_end())),
+        Err(e) => Err(format!("could not be run: {e}")),
+    }
+}
+
+/// How `ours` differs from `theirs`, if it does.
+fn compare(ours: &Outcome, theirs: &Outcome) -> Option<String> {
+    let (Outcome::Exited { code, stdout }, Outcome::Exited { code: expected, stdout: output }) = (ours, theirs) else {
+        return match (ours, theirs) {
+            (Outcome::TimedOut, Outcome::TimedOut) => None,
+            (Outcome::TimedOut, _) => Some("timed out, but not when compiled by GCC".into()),
+            _ => Some("timed out when compiled by GCC, but not by racoon".into()),
+        };
+    };
+    if code != expected {
+        let show = |code: &Option<i32>| code.map_or_else(|| "a signal".into(), |x| x.to_string());
+        return Some(format!("exited with {}, but with {} when compiled by GCC", show(code), show(expected)));
+    }
+    if stdout != output {
+        let line = stdout.iter().zip(output)
+            .take_while(|(x, y)| x == y)
+            .filter(|(&x, _)| x == b'\n')
+            .count();
+        return Some(format!("output differs from that compiled by GCC at line {}", line + 1));
+    }
+    None
+}
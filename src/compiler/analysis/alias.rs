@@ -0,0 +1,132 @@
+use crate::compiler::ir::value::{
+    constant::Constant,
+    func::IrFunc,
+    inst::{CastOp, InstKind},
+    module::Module,
+    ty::IrTy,
+    value::Operand,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasResult {
+    NoAlias,
+    MayAlias,
+    /// Both pointers address exactly the same memory.
+    MustAlias,
+}
+
+/// Answers whether two pointers in a function may address overlapping
+/// memory. Answers must be conservative: `NoAlias` and `MustAlias` only when
+/// it is certain.
+pub trait AliasAnalysis {
+    fn alias(&self, func: &IrFunc, a: &Operand, b: &Operand) -> AliasResult;
+
+    fn may_alias(&self, func: &IrFunc, a: &Operand, b: &Operand) -> bool {
+        self.alias(func, a, b) != AliasResult::NoAlias
+    }
+}
+
+/// Alias analysis from where pointers come from alone: distinct allocas and
+/// globals never overlap, neither do constant offsets into the same object
+/// that are far enough apart, and a `noalias` parameter overlaps nothing
+/// not derived from it.
+#[derive(Debug, Clone, Copy)]
+pub struct BasicAliasAnalysis<'a> {
+    module: &'a Module,
+}
+
+/// A pointer as an offset from the object it points into, in words.
+#[derive(Debug)]
+struct Location {
+    base: Operand,
+    offset: Option<i64>,
+    size: usize,
+}
+
+impl<'a> BasicAliasAnalysis<'a> {
+    #[must_use] pub fn new(module: &'a Module) -> BasicAliasAnalysis<'a> {
+        BasicAliasAnalysis { module }
+    }
+
+    fn locate(self, func: &IrFunc, ptr: &Operand) -> Location {
+        let size = IrTy::deptr_of(&self.module.operand_ty(func, ptr)).map_or(0, |x| x.size_in_words());
+        let mut base = ptr.clone();
+        let mut offset = Some(0_i64);
+        loop {
+            let gep = match base.as_inst().map(|&x| &func.inst_arena[x].kind) {
+                Some(InstKind::GEP(gep)) => gep,
+                // a pointer cast to another type still points to where it did
+                Some(InstKind::Cast(cast)) if cast.op == CastOp::Bitcast => {
+                    base = cast.ori_val.clone();
+                    continue;
+                }
+                _ => break,
+            };
+            let mut ty = IrTy::deptr_of(&self.module.operand_ty(func, &gep.ptr)).unwrap_or_default();
+            for (pos, idx) in gep.indices.iter().enumerate() {
+                if pos > 0 {
+                    ty = ty.as_array().map(|x| x.1.as_ref().clone()).unwrap_or_default();
+                }
+                let scale = i64::try_from(ty.size_in_words()).unwrap_or(i64::MAX);
+                offset = match (offset, idx) {
+                    (Some(offset), Operand::Const(Constant::Int(idx))) => {
+                        i64::from(*idx).checked_mul(scale).and_then(|x| offset.checked_add(x))
+                    }
+                    _ => None,
+                };
+            }
+            base = gep.ptr.clone();
+        }
+        Location { base, offset, size }
+    }
+
+    /// Whether `base` is an object distinct from every other identified one.
+    fn is_identified(func: &IrFunc, base: &Operand) -> bool {
+        matches!(base, Operand::Global(_)) || Self::is_alloca(func, base)
+    }
+
+    fn is_noalias(func: &IrFunc, base: &Operand) -> bool {
+        base.as_param().is_some_and(|&x| func.param_arena[x].noalias)
+    }
+
+    fn is_alloca(func: &IrFunc, base: &Operand) -> bool {
+        base.as_inst().is_some_and(|&x| matches!(func.inst_arena[x].kind, InstKind::Alloca(_)))
+    }
+}
+
+impl AliasAnalysis for BasicAliasAnalysis<'_> {
+    fn alias(&self, func: &IrFunc, a: &Operand, b: &Operand) -> AliasResult {
+        if a == b {
+            return AliasResult::MustAlias;
+        }
+        let a = self.locate(func, a);
+        let b = self.locate(func, b);
+
+        if a.base == b.base {
+            let (Some(a_offset), Some(b_offset)) = (a.offset, b.offset) else {
+                return AliasResult::MayAlias;
+            };
+            let a_end = a_offset + i64::try_from(a.size).unwrap_or(i64::MAX);
+            let b_end = b_offset + i64::try_from(b.size).unwrap_or(i64::MAX);
+            return if a_offset == b_offset && a.size == b.size {
+                AliasResult::MustAlias
+            } else if a_end <= b_offset || b_end <= a_offset {
+                AliasResult::NoAlias
+            } else {
+                AliasResult::MayAlias
+            };
+        }
+
+        let distinct = (Self::is_identified(func, &a.base) && Self::is_identified(func, &b.base))
+            // a parameter cannot point to the callee's own stack
+            || (Self::is_alloca(func, &a.base) && matches!(b.base, Operand::Param(_)))
+            || (Self::is_alloca(func, &b.base) && matches!(a.base, Operand::Param(_)))
+            || Self::is_noalias(func, &a.base)
+            || Self::is_noalias(func, &b.base);
+        if distinct {
+            AliasResult::NoAlias
+        } else {
+            AliasResult::MayAlias
+        }
+    }
+}
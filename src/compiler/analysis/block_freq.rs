@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use crate::compiler::analysis::{
+    dominance::{DomTree, PostDominators},
+    loops::{LoopInfo, Loops},
+    AnalysisManager,
+    FuncAnalysis,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{BinaryInstOp, Br, InstKind},
+        value::Operand,
+    },
+};
+
+/// Probability of a branch staying in its loop rather than leaving it.
+const LOOP_PROB: f64 = 0.88;
+/// Probability of a comparison for equality, or of an integer being
+/// negative, being false.
+const OPCODE_PROB: f64 = 0.84;
+/// Probability of a branch not going to a side that calls a function.
+const CALL_PROB: f64 = 0.78;
+/// Probability of a branch not going to a side that returns.
+const RETURN_PROB: f64 = 0.72;
+
+/// Most times a loop is estimated to run its header per entry, for loops
+/// the heuristics tell to almost never exit.
+const MAX_LOOP_SCALE: f64 = 1024.0;
+
+/// A block running less than once per this many calls is cold.
+const COLD_RATIO: f64 = 8.0;
+
+/// How often each block of a function runs per call, estimated from the
+/// shape of the code alone, with the static heuristics of Ball and Larus:
+/// branches tend to stay in loops, and not to go to sides that call or
+/// return, or on equality tests being true. Heuristics that apply to the
+/// same branch are combined as independent evidence.
+///
+/// Blocks unreachable from the entry never run.
+#[derive(Debug, Clone)]
+pub struct BlockFrequency {
+    freqs: HashMap<BBId, f64>,
+}
+
+impl BlockFrequency {
+    #[must_use] pub fn new(func: &IrFunc, loop_info: &LoopInfo, post_dom_tree: &DomTree) -> BlockFrequency {
+        let Some(entry) = func.first_block else {
+            return BlockFrequency { freqs: HashMap::new() };
+        };
+        let rpo = func.reverse_postorder();
+        let probs: HashMap<_, _> = rpo.iter()
+            .map(|&bb| (bb, edge_probs(func, loop_info, post_dom_tree, bb)))
+            .collect();
+        let mut preds: HashMap<BBId, Vec<(BBId, f64)>> = HashMap::new();
+        for (&from, edges) in &probs {
+            for (&to, &prob) in edges {
+                preds.entry(to).or_default().push((from, prob));
+            }
+        }
+        let headers: HashMap<_, _> = loop_info.loops().map(|(_, lp)| (lp.header, lp)).collect();
+        let is_back_edge = |from: BBId, to: BBId| headers.get(&to).is_some_and(|lp| lp.contains(from));
+
+        // the mass entering a block along forward edges, starting with 1 at
+        // `start`, scaled at the header of each inner loop by how many times
+        // it runs per entry
+        let propagate = |start: BBId, region: &dyn Fn(BBId) -> bool, scales: &HashMap<BBId, f64>| {
+            let mut mass: HashMap<BBId, f64> = HashMap::new();
+            for &bb in rpo.iter().filter(|&&x| region(x)) {
+                let incoming = if bb == start {
+                    1.0
+                } else {
+                    preds.get(&bb).into_iter().flatten()
+                        .filter(|&&(from, _)| region(from) && !is_back_edge(from, bb))
+                        .map(|(from, prob)| mass.get(from).copied().unwrap_or(0.0) * prob)
+                        .sum()
+                };
+                mass.insert(bb, incoming * scales.get(&bb).copied().unwrap_or(1.0));
+            }
+            mass
+        };
+
+        // inner loops first, so that their scale is known in their parent
+        let mut scales = HashMap::new();
+        for loop_id in loop_info.innermost_first() {
+            let lp = loop_info.get(loop_id);
+            let mass = propagate(lp.header, &|x| lp.contains(x), &scales);
+            let back: f64 = lp.latches.iter()
+                .map(|x| mass.get(x).copied().unwrap_or(0.0) * probs[x].get(&lp.header).copied().unwrap_or(0.0))
+                .sum();
+            scales.insert(lp.header, (1.0 / (1.0 - back).max(0.0)).min(MAX_LOOP_SCALE));
+        }
+        let freqs = propagate(entry, &|_| true, &scales);
+        BlockFrequency { freqs }
+    }
+
+    /// Estimated number of times `bb` runs per call of the function.
+    #[must_use] pub fn freq(&self, bb: BBId) -> f64 {
+        self.freqs.get(&bb).copied().unwrap_or(0.0)
+    }
+
+    /// Whether `bb` is estimated to run rarely enough that it is best kept
+    /// out of the way of the rest of the function.
+    #[must_use] pub fn is_cold(&self, bb: BBId) -> bool {
+        self.freq(bb) * COLD_RATIO < 1.0
+    }
+}
+
+/// Probability of `bb` going to each of its successors.
+fn edge_probs(func: &IrFunc, loop_info: &LoopInfo, post_dom_tree: &DomTree, bb: BBId) -> HashMap<BBId, f64> {
+    let mut probs = HashMap::new();
+    let Some(InstKind::Br(br)) = func.terminator(bb).map(|x| &func.inst_arena[x].kind) else {
+        return probs;
+    };
+    let (cond, true_bb, false_bb) = match br {
+        Br::Br { cond, true_bb, false_bb } if true_bb != false_bb => (cond, *true_bb, *false_bb),
+        _ => {
+            let targets = br.targets();
+            #[allow(clippy::cast_precision_loss)]
+            let share = 1.0 / targets.len() as f64;
+            for target in targets {
+                *probs.entry(target).or_default() += share;
+            }
+            return probs;
+        }
+    };
+
+    // each heuristic gives the probability of the branch being taken
+    let mut heuristics = vec![];
+    if let Some(lp) = loop_info.loop_of(bb).map(|x| loop_info.get(x)) {
+        match (lp.contains(true_bb), lp.contains(false_bb)) {
+            (true, false) => heuristics.push(LOOP_PROB),
+            (false, true) => heuristics.push(1.0 - LOOP_PROB),
+            _ => {}
+        }
+    }
+    if let Some(prob) = opcode_prob(func, cond) {
+        heuristics.push(prob);
+    }
+    // a side both go to anyway tells nothing about which is taken
+    let side_prob = |is_side: &dyn Fn(BBId) -> bool, prob: f64| {
+        let side = |x: BBId| is_side(x) && !post_dom_tree.dominates(x, bb);
+        match (side(true_bb), side(false_bb)) {
+            (true, false) => Some(1.0 - prob),
+            (false, true) => Some(prob),
+            _ => None,
+        }
+    };
+    let calls = |x: BBId| func.inst_arena.items_iter(func.bb_arena[x].insts_head, None)
+        .any(|(_, inst)| matches!(inst.kind, InstKind::Call(_)));
+    let returns = |x: BBId| func.terminator(x).is_some_and(|x| matches!(func.inst_arena[x].kind, InstKind::RetInst(_)));
+    heuristics.extend(side_prob(&calls, CALL_PROB));
+    heuristics.extend(side_prob(&returns, RETURN_PROB));
+
+    let taken = heuristics.into_iter().fold(0.5, |a, b| a * b / (a * b + (1.0 - a) * (1.0 - b)));
+    probs.insert(true_bb, taken);
+    probs.insert(false_bb, 1.0 - taken);
+    probs
+}
+
+/// Probability of `cond` being true from its comparison alone.
+fn opcode_prob(func: &IrFunc, cond: &Operand) -> Option<f64> {
+    let InstKind::Binary(binary) = &func.inst_arena[*cond.as_inst()?].kind else {
+        return None;
+    };
+    let zero = Operand::Const(Constant::Int(0));
+    match binary.op {
+        BinaryInstOp::Eq => Some(1.0 - OPCODE_PROB),
+        BinaryInstOp::Ne => Some(OPCODE_PROB),
+        BinaryInstOp::Lt | BinaryInstOp::Le if binary.right == zero => Some(1.0 - OPCODE_PROB),
+        BinaryInstOp::Gt | BinaryInstOp::Ge if binary.right == zero => Some(OPCODE_PROB),
+        _ => None,
+    }
+}
+
+/// [`BlockFrequency::new`] as a cached analysis.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockFreqs;
+
+impl FuncAnalysis for BlockFreqs {
+    type Result = BlockFrequency;
+
+    fn run(func_id: FuncId, func: &IrFunc, analyses: &mut AnalysisManager) -> BlockFrequency {
+        let loop_info = analyses.get::<Loops>(func_id, func);
+        let post_dom_tree = analyses.get::<PostDominators>(func_id, func);
+        BlockFrequency::new(func, &loop_info, &post_dom_tree)
+    }
+}
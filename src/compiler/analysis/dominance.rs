@@ -0,0 +1,263 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::analysis::{AnalysisManager, FuncAnalysis};
+use crate::compiler::ir::{
+    arena::{BBId, FuncId},
+    value::{func::IrFunc, inst::InstKind},
+};
+
+/// A dominator or post-dominator tree over the blocks of one function,
+/// computed with the Cooper-Harvey-Kennedy iterative algorithm.
+///
+/// Only blocks reachable from the entry are in either tree, and a
+/// post-dominator tree also leaves out blocks that cannot reach a `ret`
+/// (e.g. those inside an infinite loop). A post-dominator tree is rooted at
+/// every `ret` block, as if they all jumped to one virtual exit.
+#[derive(Debug, Clone)]
+pub struct DomTree {
+    roots: Vec<BBId>,
+    /// Immediate dominator of every block in the tree, `None` for roots.
+    idoms: HashMap<BBId, Option<BBId>>,
+    children: HashMap<BBId, Vec<BBId>>,
+    /// Predecessors in the direction the tree was built for, i.e.
+    /// successors in the CFG for a post-dominator tree.
+    preds: HashMap<BBId, Vec<BBId>>,
+    /// Preorder and postorder number of every block in the tree.
+    dfs_nums: HashMap<BBId, (usize, usize)>,
+}
+
+impl DomTree {
+    #[must_use] pub fn dominators(func: &IrFunc) -> DomTree {
+        let Some(entry) = func.first_block else {
+            return DomTree::build(&[], |_| vec![], HashMap::new());
+        };
+        let reachable = func.reverse_postorder();
+        let preds = pred_map(func, &reachable);
+        DomTree::build(&[entry], |bb| func.succs(bb), preds)
+    }
+
+    #[must_use] pub fn post_dominators(func: &IrFunc) -> DomTree {
+        let reachable = func.reverse_postorder();
+        let exits: Vec<_> = reachable.iter()
+            .copied()
+            .filter(|&bb| func.terminator(bb)
+                .and_then(|x| func.get_inst(x))
+                .is_some_and(|x| matches!(x.kind, InstKind::RetInst(_))))
+            .collect();
+        let rev_succs = pred_map(func, &reachable);
+        let rev_preds = reachable.iter().map(|&bb| (bb, func.succs(bb))).collect();
+        DomTree::build(&exits, |bb| rev_succs.get(&bb).cloned().unwrap_or_default(), rev_preds)
+    }
+
+    /// Builds the tree for the graph reachable from `roots` through `succs`.
+    fn build(roots: &[BBId], succs: impl Fn(BBId) -> Vec<BBId>, preds: HashMap<BBId, Vec<BBId>>) -> DomTree {
+        // number the nodes in reverse postorder, 0 being a virtual root
+        // above all the real ones
+        let mut order = postorder(roots, &succs);
+        order.reverse();
+        let nums: HashMap<_, _> = order.iter().enumerate().map(|(idx, &bb)| (bb, idx + 1)).collect();
+        let node_preds: Vec<Vec<usize>> = order.iter()
+            .map(|bb| {
+                let mut node_preds: Vec<_> = preds.get(bb)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|x| nums.get(x).copied())
+                    .collect();
+                if roots.contains(bb) {
+                    node_preds.push(0);
+                }
+                node_preds
+            })
+            .collect();
+
+        let mut idoms: Vec<Option<usize>> = vec![None; order.len() + 1];
+        idoms[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in 1..=order.len() {
+                let new_idom = node_preds[node - 1].iter()
+                    .copied()
+                    .filter(|&x| idoms[x].is_some())
+                    .reduce(|a, b| intersect(&idoms, a, b));
+                if new_idom.is_some() && idoms[node] != new_idom {
+                    idoms[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let idoms: HashMap<_, _> = order.iter()
+            .enumerate()
+            .map(|(idx, &bb)| {
+                let idom = idoms[idx + 1].expect("Unreachable node in dominator tree");
+                (bb, (idom != 0).then(|| order[idom - 1]))
+            })
+            .collect();
+        let mut children: HashMap<BBId, Vec<BBId>> = HashMap::new();
+        for &bb in &order {
+            if let Some(idom) = idoms[&bb] {
+                children.entry(idom).or_default().push(bb);
+            }
+        }
+
+        let mut tree = DomTree {
+            roots: roots.to_vec(),
+            idoms,
+            children,
+            preds,
+            dfs_nums: HashMap::new(),
+        };
+        tree.number();
+        tree
+    }
+
+    fn number(&mut self) {
+        let mut cnt = 0;
+        let mut stack: Vec<(BBId, usize)> = self.roots.iter().rev().map(|&x| (x, 0)).collect();
+        let mut pre_nums = HashMap::new();
+        while let Some((bb, child_idx)) = stack.pop() {
+            if child_idx == 0 {
+                pre_nums.insert(bb, cnt);
+                cnt += 1;
+            }
+            if let Some(&child) = self.children(bb).get(child_idx) {
+                stack.push((bb, child_idx + 1));
+                stack.push((child, 0));
+            } else {
+                self.dfs_nums.insert(bb, (pre_nums[&bb], cnt));
+                cnt += 1;
+            }
+        }
+    }
+
+    #[must_use] pub fn roots(&self) -> &[BBId] {
+        &self.roots
+    }
+
+    #[must_use] pub fn contains(&self, bb: BBId) -> bool {
+        self.idoms.contains_key(&bb)
+    }
+
+    /// Immediate dominator of `bb`, `None` for a root or a block not in the
+    /// tree.
+    #[must_use] pub fn idom(&self, bb: BBId) -> Option<BBId> {
+        self.idoms.get(&bb).copied().flatten()
+    }
+
+    #[must_use] pub fn children(&self, bb: BBId) -> &[BBId] {
+        self.children.get(&bb).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether `a` dominates `b`. Every block dominates itself.
+    #[must_use] pub fn dominates(&self, a: BBId, b: BBId) -> bool {
+        let (Some(&(a_pre, a_post)), Some(&(b_pre, b_post))) = (self.dfs_nums.get(&a), self.dfs_nums.get(&b)) else {
+            return false;
+        };
+        a_pre <= b_pre && b_post <= a_post
+    }
+
+    #[must_use] pub fn strictly_dominates(&self, a: BBId, b: BBId) -> bool {
+        a != b && self.dominates(a, b)
+    }
+
+    /// The dominance frontier of every block in the tree: the blocks where
+    /// its dominance ends. On a post-dominator tree these are the
+    /// post-dominance frontiers, i.e. the branches each block is control
+    /// dependent on.
+    #[must_use] pub fn frontiers(&self) -> HashMap<BBId, HashSet<BBId>> {
+        let mut frontiers: HashMap<BBId, HashSet<BBId>> = self.idoms.keys()
+            .map(|&bb| (bb, HashSet::new()))
+            .collect();
+        for (&bb, preds) in &self.preds {
+            let preds: Vec<_> = preds.iter().filter(|&&x| self.contains(x)).collect();
+            if !self.contains(bb) || preds.len() < 2 {
+                continue;
+            }
+            let idom = self.idom(bb);
+            for &pred in preds {
+                let mut runner = Some(pred);
+                while let Some(cur) = runner.filter(|&x| Some(x) != idom) {
+                    frontiers.entry(cur).or_default().insert(bb);
+                    runner = self.idom(cur);
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+/// Walks up from two nodes to their nearest common dominator. Nodes are
+/// numbered in reverse postorder, so a dominator always has the smaller
+/// number.
+fn intersect(idoms: &[Option<usize>], mut a: usize, mut b: usize) -> usize {
+    while a != b {
+        while a > b {
+            a = idoms[a].unwrap();
+        }
+        while b > a {
+            b = idoms[b].unwrap();
+        }
+    }
+    a
+}
+
+fn postorder(roots: &[BBId], succs: impl Fn(BBId) -> Vec<BBId>) -> Vec<BBId> {
+    let mut order = vec![];
+    let mut visited = HashSet::new();
+    for &root in roots {
+        if !visited.insert(root) {
+            continue;
+        }
+        let mut stack = vec![(root, succs(root), 0)];
+        while let Some((bb, bb_succs, idx)) = stack.last_mut() {
+            if let Some(&succ) = bb_succs.get(*idx) {
+                *idx += 1;
+                if visited.insert(succ) {
+                    stack.push((succ, succs(succ), 0));
+                }
+            } else {
+                order.push(*bb);
+                stack.pop();
+            }
+        }
+    }
+    order
+}
+
+/// CFG predecessors of every block in `blocks`, counting only edges between
+/// blocks in it.
+fn pred_map(func: &IrFunc, blocks: &[BBId]) -> HashMap<BBId, Vec<BBId>> {
+    let blocks: HashSet<_> = blocks.iter().copied().collect();
+    let mut preds: HashMap<BBId, Vec<BBId>> = blocks.iter().map(|&bb| (bb, vec![])).collect();
+    for (from, to) in func.edges() {
+        if blocks.contains(&from) && blocks.contains(&to) {
+            preds.get_mut(&to).unwrap().push(from);
+        }
+    }
+    preds
+}
+
+/// [`DomTree::dominators`] as a cached analysis.
+#[derive(Debug, Clone, Copy)]
+pub struct Dominators;
+
+impl FuncAnalysis for Dominators {
+    type Result = DomTree;
+
+    fn run(_func_id: FuncId, func: &IrFunc, _analyses: &mut AnalysisManager) -> DomTree {
+        DomTree::dominators(func)
+    }
+}
+
+/// [`DomTree::post_dominators`] as a cached analysis.
+#[derive(Debug, Clone, Copy)]
+pub struct PostDominators;
+
+impl FuncAnalysis for PostDominators {
+    type Result = DomTree;
+
+    fn run(_func_id: FuncId, func: &IrFunc, _analyses: &mut AnalysisManager) -> DomTree {
+        DomTree::post_dominators(func)
+    }
+}
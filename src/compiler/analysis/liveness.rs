@@ -0,0 +1,88 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::analysis::{AnalysisManager, FuncAnalysis};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId},
+    value::{func::IrFunc, value::Operand},
+};
+
+/// Instructions and parameters whose value is still needed on entry to and
+/// exit from each reachable block. Memory is not tracked, only SSA values.
+#[derive(Debug, Clone, Default)]
+pub struct LiveVars {
+    live_in: HashMap<BBId, HashSet<Operand>>,
+    live_out: HashMap<BBId, HashSet<Operand>>,
+}
+
+impl LiveVars {
+    #[must_use] pub fn new(func: &IrFunc) -> LiveVars {
+        let mut live = LiveVars::default();
+        let order = func.postorder();
+        for &bb in &order {
+            live.live_in.insert(bb, HashSet::new());
+            live.live_out.insert(bb, HashSet::new());
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in &order {
+                let live_out: HashSet<_> = func.succs(bb).iter()
+                    .flat_map(|succ| &live.live_in[succ])
+                    .cloned()
+                    .collect();
+                let live_in = transfer(func, bb, live_out.clone());
+                if live_in != live.live_in[&bb] {
+                    changed = true;
+                    live.live_in.insert(bb, live_in);
+                }
+                live.live_out.insert(bb, live_out);
+            }
+        }
+        live
+    }
+
+    /// Values live on entry to `bb`, empty for an unreachable block.
+    pub fn live_in(&self, bb: BBId) -> impl Iterator<Item = &Operand> {
+        self.live_in.get(&bb).into_iter().flatten()
+    }
+
+    /// Values live on exit from `bb`, empty for an unreachable block.
+    pub fn live_out(&self, bb: BBId) -> impl Iterator<Item = &Operand> {
+        self.live_out.get(&bb).into_iter().flatten()
+    }
+
+    #[must_use] pub fn is_live_in(&self, bb: BBId, value: &Operand) -> bool {
+        self.live_in.get(&bb).is_some_and(|x| x.contains(value))
+    }
+
+    #[must_use] pub fn is_live_out(&self, bb: BBId, value: &Operand) -> bool {
+        self.live_out.get(&bb).is_some_and(|x| x.contains(value))
+    }
+}
+
+/// Walks `bb` backwards from the values live at its end.
+fn transfer(func: &IrFunc, bb: BBId, mut live: HashSet<Operand>) -> HashSet<Operand> {
+    let insts: Vec<_> = func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None).collect();
+    for (inst_id, inst) in insts.into_iter().rev() {
+        live.remove(&Operand::Inst(inst_id));
+        live.extend(inst.kind.operands()
+            .into_iter()
+            .filter(|x| matches!(x, Operand::Inst(_) | Operand::Param(_)))
+            .cloned());
+    }
+    live
+}
+
+/// [`LiveVars`] as a cached analysis.
+#[derive(Debug, Clone, Copy)]
+pub struct Liveness;
+
+impl FuncAnalysis for Liveness {
+    type Result = LiveVars;
+
+    fn run(_func_id: FuncId, func: &IrFunc, _analyses: &mut AnalysisManager) -> LiveVars {
+        LiveVars::new(func)
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use crate::compiler::analysis::{AnalysisManager, FuncAnalysis, dominance::{DomTree, Dominators}};
+use crate::compiler::ir::{
+    arena::{BBId, FuncId},
+    value::{func::IrFunc, inst::{Br, InstKind}, ty::IrTy},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LoopId(usize);
+
+/// A natural loop: the blocks that can reach a back edge to `header` without
+/// passing through it. Back edges sharing a header form one loop.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    pub header: BBId,
+    /// Sources of the back edges.
+    pub latches: Vec<BBId>,
+    pub blocks: HashSet<BBId>,
+    pub parent: Option<LoopId>,
+    pub children: Vec<LoopId>,
+    /// 1 for an outermost loop.
+    pub depth: usize,
+}
+
+impl Loop {
+    #[must_use] pub fn contains(&self, bb: BBId) -> bool {
+        self.blocks.contains(&bb)
+    }
+
+    /// Blocks in the loop with a successor outside of it.
+    #[must_use] pub fn exiting_blocks(&self, func: &IrFunc) -> Vec<BBId> {
+        func.bb_ids()
+            .filter(|&bb| self.contains(bb) && func.succs(bb).iter().any(|&x| !self.contains(x)))
+            .collect()
+    }
+
+    /// Blocks outside the loop with a predecessor in it.
+    #[must_use] pub fn exit_blocks(&self, func: &IrFunc) -> Vec<BBId> {
+        let exits: HashSet<_> = self.exiting_blocks(func)
+            .into_iter()
+            .flat_map(|bb| func.succs(bb))
+            .filter(|&x| !self.contains(x))
+            .collect();
+        func.bb_ids().filter(|x| exits.contains(x)).collect()
+    }
+
+    /// The only block entering the loop, if it jumps nowhere else.
+    #[must_use] pub fn preheader(&self, func: &IrFunc) -> Option<BBId> {
+        let outside: Vec<_> = func.preds(self.header)
+            .into_iter()
+            .filter(|&x| !self.contains(x))
+            .collect();
+        match outside[..] {
+            [pred] if func.succs(pred) == [self.header] => Some(pred),
+            _ => None,
+        }
+    }
+}
+
+/// Every natural loop of a function and how they nest.
+#[derive(Debug, Clone, Default)]
+pub struct LoopInfo {
+    loops: Vec<Loop>,
+}
+
+impl LoopInfo {
+    #[must_use] pub fn new(func: &IrFunc, dom_tree: &DomTree) -> LoopInfo {
+        let mut loops: Vec<Loop> = vec![];
+        for bb in func.reverse_postorder() {
+            let latches: Vec<_> = func.preds(bb)
+                .into_iter()
+                .filter(|&x| dom_tree.dominates(bb, x))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+
+            let mut blocks = HashSet::from([bb]);
+            let mut stack = latches.clone();
+            while let Some(cur) = stack.pop() {
+                if dom_tree.contains(cur) && blocks.insert(cur) {
+                    stack.extend(func.preds(cur));
+                }
+            }
+            loops.push(Loop { header: bb, latches, blocks, parent: None, children: vec![], depth: 1 });
+        }
+
+        // the parent is the smallest other loop around the header, and loops
+        // are sorted by header in reverse postorder so parents come first
+        for idx in 0..loops.len() {
+            let parent = (0..loops.len())
+                .filter(|&x| x != idx && loops[x].contains(loops[idx].header))
+                .min_by_key(|&x| loops[x].blocks.len());
+            if let Some(parent) = parent {
+                loops[idx].parent = Some(LoopId(parent));
+                loops[idx].depth = loops[parent].depth + 1;
+                loops[parent].children.push(LoopId(idx));
+            }
+        }
+        LoopInfo { loops }
+    }
+
+    pub fn loops(&self) -> impl Iterator<Item = (LoopId, &Loop)> {
+        self.loops.iter().enumerate().map(|(idx, x)| (LoopId(idx), x))
+    }
+
+    #[must_use] pub fn get(&self, loop_id: LoopId) -> &Loop {
+        &self.loops[loop_id.0]
+    }
+
+    #[must_use] pub fn top_level(&self) -> Vec<LoopId> {
+        self.loops().filter(|(_, x)| x.parent.is_none()).map(|(id, _)| id).collect()
+    }
+
+    /// Loops from the innermost out, so that each comes before its parent.
+    #[must_use] pub fn innermost_first(&self) -> Vec<LoopId> {
+        (0..self.loops.len()).rev().map(LoopId).collect()
+    }
+
+    /// The innermost loop containing `bb`.
+    #[must_use] pub fn loop_of(&self, bb: BBId) -> Option<LoopId> {
+        self.loops()
+            .filter(|(_, x)| x.contains(bb))
+            .max_by_key(|(_, x)| x.depth)
+            .map(|(id, _)| id)
+    }
+
+    /// Number of loops around `bb`, 0 outside of any loop.
+    #[must_use] pub fn depth(&self, bb: BBId) -> usize {
+        self.loop_of(bb).map_or(0, |x| self.get(x).depth)
+    }
+}
+
+/// Gives `lp` a preheader if it lacks one, by routing every edge entering
+/// the loop through a new block. Returns the preheader.
+pub fn insert_preheader(func: &mut IrFunc, lp: &Loop) -> BBId {
+    if let Some(preheader) = lp.preheader(func) {
+        return preheader;
+    }
+    let preheader = func.build_bb_before_cur(lp.header);
+    if func.first_block == Some(lp.header) {
+        func.first_block = Some(preheader);
+    }
+    let outside: Vec<_> = func.preds(lp.header)
+        .into_iter()
+        .filter(|&x| !lp.contains(x))
+        .collect();
+    for pred in outside {
+        func.replace_succ(pred, lp.header, preheader);
+    }
+    func.build_inst_at_end(InstKind::Br(Br::Jump { nxt_bb: lp.header }), IrTy::Void, preheader);
+    preheader
+}
+
+/// [`LoopInfo`] as a cached analysis.
+#[derive(Debug, Clone, Copy)]
+pub struct Loops;
+
+impl FuncAnalysis for Loops {
+    type Result = LoopInfo;
+
+    fn run(func_id: FuncId, func: &IrFunc, analyses: &mut AnalysisManager) -> LoopInfo {
+        let dom_tree = analyses.get::<Dominators>(func_id, func);
+        LoopInfo::new(func, &dom_tree)
+    }
+}
@@ -0,0 +1,113 @@
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::compiler::ir::{arena::FuncId, value::func::IrFunc};
+
+pub mod alias;
+pub mod block_freq;
+pub mod dominance;
+pub mod liveness;
+pub mod loops;
+pub mod range;
+pub mod scev;
+pub mod stats;
+
+/// A fact computed from one function, cached by [`AnalysisManager`] until a
+/// pass changes the function without preserving it.
+pub trait FuncAnalysis: 'static {
+    type Result: 'static;
+
+    /// Computes the result from scratch. Other analyses it builds on should
+    /// be taken from `analyses` so they are shared with later passes.
+    fn run(func_id: FuncId, func: &IrFunc, analyses: &mut AnalysisManager) -> Self::Result;
+}
+
+/// The analyses a pass left valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreservedAnalyses {
+    all: bool,
+    preserved: HashSet<TypeId>,
+}
+
+impl PreservedAnalyses {
+    /// Nothing changed.
+    #[must_use] pub fn all() -> PreservedAnalyses {
+        PreservedAnalyses { all: true, preserved: HashSet::new() }
+    }
+
+    #[must_use] pub fn none() -> PreservedAnalyses {
+        PreservedAnalyses { all: false, preserved: HashSet::new() }
+    }
+
+    /// Keeps `A` valid even though the function changed, e.g. dominators
+    /// after a pass that only rewrote instructions in place.
+    #[must_use] pub fn preserve<A: FuncAnalysis>(mut self) -> PreservedAnalyses {
+        self.preserved.insert(TypeId::of::<A>());
+        self
+    }
+
+    #[must_use] pub fn is_all(&self) -> bool {
+        self.all
+    }
+
+    #[must_use] pub fn is_preserved<A: FuncAnalysis>(&self) -> bool {
+        self.is_preserved_id(TypeId::of::<A>())
+    }
+
+    fn is_preserved_id(&self, analysis: TypeId) -> bool {
+        self.all || self.preserved.contains(&analysis)
+    }
+
+    /// What is still valid after running `self` and then `other`.
+    #[must_use] pub fn intersect(self, other: PreservedAnalyses) -> PreservedAnalyses {
+        match (self.all, other.all) {
+            (true, _) => other,
+            (_, true) => self,
+            _ => PreservedAnalyses {
+                all: false,
+                preserved: self.preserved.intersection(&other.preserved).copied().collect(),
+            },
+        }
+    }
+}
+
+/// Cache of analysis results for every function of a module.
+#[derive(Debug, Default)]
+pub struct AnalysisManager {
+    results: HashMap<(FuncId, TypeId), Rc<dyn Any>>,
+}
+
+impl AnalysisManager {
+    #[must_use] pub fn new() -> AnalysisManager {
+        AnalysisManager::default()
+    }
+
+    /// The result of `A` on `func`, computed only if it is not cached.
+    /// `func` must be the function `func_id` names.
+    pub fn get<A: FuncAnalysis>(&mut self, func_id: FuncId, func: &IrFunc) -> Rc<A::Result> {
+        let key = (func_id, TypeId::of::<A>());
+        if let Some(result) = self.results.get(&key) {
+            return Rc::clone(result).downcast().unwrap_or_else(|_| unreachable!());
+        }
+        let result = Rc::new(A::run(func_id, func, self));
+        self.results.insert(key, Rc::clone(&result) as Rc<dyn Any>);
+        result
+    }
+
+    /// The cached result of `A` on `func_id`, without computing it.
+    #[must_use] pub fn get_cached<A: FuncAnalysis>(&self, func_id: FuncId) -> Option<Rc<A::Result>> {
+        let result = self.results.get(&(func_id, TypeId::of::<A>()))?;
+        Rc::clone(result).downcast().ok()
+    }
+
+    /// Drops the results on `func_id` that `preserved` does not keep.
+    pub fn invalidate(&mut self, func_id: FuncId, preserved: &PreservedAnalyses) {
+        self.results.retain(|&(func, analysis), _| func != func_id || preserved.is_preserved_id(analysis));
+    }
+
+    /// Drops the results on every function that `preserved` does not keep.
+    pub fn invalidate_all(&mut self, preserved: &PreservedAnalyses) {
+        self.results.retain(|&(_, analysis), _| preserved.is_preserved_id(analysis));
+    }
+}
@@ -0,0 +1,312 @@
+use std::rc::Rc;
+
+use crate::compiler::analysis::{
+    AnalysisManager,
+    FuncAnalysis,
+    dominance::{DomTree, Dominators},
+    loops::{LoopInfo, Loops},
+    scev::{InductionVars, ScalarEvolution},
+};
+use crate::compiler::ir::{
+    arena::{BBId, FuncId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{BinaryInstOp, Br, CastOp, InstKind},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+
+/// The inclusive range of values an `i32` (or `i1`) may take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueRange {
+    pub lo: i32,
+    pub hi: i32,
+}
+
+impl ValueRange {
+    #[must_use] pub fn full() -> ValueRange {
+        ValueRange { lo: i32::MIN, hi: i32::MAX }
+    }
+
+    #[must_use] pub fn constant(val: i32) -> ValueRange {
+        ValueRange { lo: val, hi: val }
+    }
+
+    fn bool() -> ValueRange {
+        ValueRange { lo: 0, hi: 1 }
+    }
+
+    /// The smallest range holding every value in `lo..=hi`, or the full
+    /// range if some of them do not fit in an `i32`.
+    fn from_i64(lo: i64, hi: i64) -> ValueRange {
+        match (i32::try_from(lo), i32::try_from(hi)) {
+            (Ok(lo), Ok(hi)) => ValueRange { lo, hi },
+            _ => ValueRange::full(),
+        }
+    }
+
+    #[must_use] pub fn as_const(self) -> Option<i32> {
+        (self.lo == self.hi).then_some(self.lo)
+    }
+
+    #[must_use] pub fn contains(self, val: i32) -> bool {
+        self.lo <= val && val <= self.hi
+    }
+
+    /// Values in both ranges. An empty intersection means the code is
+    /// unreachable, and is given as the range of `self` left unchanged.
+    #[must_use] pub fn intersect(self, other: ValueRange) -> ValueRange {
+        let (lo, hi) = (self.lo.max(other.lo), self.hi.min(other.hi));
+        if lo <= hi { ValueRange { lo, hi } } else { self }
+    }
+
+    #[must_use] pub fn union(self, other: ValueRange) -> ValueRange {
+        ValueRange { lo: self.lo.min(other.lo), hi: self.hi.max(other.hi) }
+    }
+}
+
+/// Ranges of the integer values of a function, from constants, the
+/// comparisons guarding each block and the bounds of counted loops.
+///
+/// Ranges are computed on demand, since a value may be narrower in blocks
+/// guarded by a comparison on it than where it is defined. A guard narrows
+/// the value it compares, not later loads of the same variable.
+#[derive(Debug, Clone)]
+pub struct RangeInfo {
+    dom_tree: Rc<DomTree>,
+    loop_info: Rc<LoopInfo>,
+    scev: Rc<ScalarEvolution>,
+}
+
+impl RangeInfo {
+    #[must_use] pub fn new(dom_tree: Rc<DomTree>, loop_info: Rc<LoopInfo>, scev: Rc<ScalarEvolution>) -> RangeInfo {
+        RangeInfo { dom_tree, loop_info, scev }
+    }
+
+    /// Range of `value` where it is defined.
+    #[must_use] pub fn range_of(&self, func: &IrFunc, value: &Operand) -> ValueRange {
+        match value {
+            Operand::Inst(x) => self.range_at(func, value, func.inst_arena[*x].bb),
+            _ => leaf_range(value),
+        }
+    }
+
+    /// Range of `value` when used in `bb`.
+    #[must_use] pub fn range_at(&self, func: &IrFunc, value: &Operand, bb: BBId) -> ValueRange {
+        let Operand::Inst(inst_id) = value else {
+            return leaf_range(value);
+        };
+        let inst = &func.inst_arena[*inst_id];
+        let range = match &inst.kind {
+            // wider values are only ever truncated, not followed
+            InstKind::Binary(binary) if matches!(inst.ty, IrTy::Int(bits) if bits <= 32) => {
+                let left = self.range_at(func, &binary.left, bb);
+                let right = self.range_at(func, &binary.right, bb);
+                binary_range(binary.op, left, right)
+            }
+            InstKind::Cast(cast) if cast.op == CastOp::ZExt => self.range_at(func, &cast.ori_val, bb),
+            InstKind::Select(select) => match self.range_at(func, &select.cond, bb).as_const() {
+                Some(0) => self.range_at(func, &select.false_val, bb),
+                Some(_) => self.range_at(func, &select.true_val, bb),
+                None => self.range_at(func, &select.true_val, bb).union(self.range_at(func, &select.false_val, bb)),
+            },
+            _ => ValueRange::full(),
+        };
+        let range = range.intersect(self.loop_range(func, value, inst.bb));
+        self.guards(func, bb)
+            .into_iter()
+            .fold(range, |range, (cond, is_true, guard_bb)| {
+                range.intersect(self.guard_range(func, value, &cond, is_true, guard_bb))
+            })
+    }
+
+    /// Whether the branch ending `bb` always goes one way, and which.
+    #[must_use] pub fn branch_outcome(&self, func: &IrFunc, bb: BBId) -> Option<bool> {
+        let InstKind::Br(Br::Br { cond, .. }) = &func.inst_arena[func.terminator(bb)?].kind else {
+            return None;
+        };
+        self.range_at(func, cond, bb).as_const().map(|x| x != 0)
+    }
+
+    /// Branch conditions known to hold on entry to `bb`, each with whether
+    /// it is known true or false and the block it was tested in. A
+    /// condition holds if the edge taken when it does is the only way into
+    /// a block dominating `bb`.
+    fn guards(&self, func: &IrFunc, bb: BBId) -> Vec<(Operand, bool, BBId)> {
+        let mut guards = vec![];
+        let mut cur = Some(bb);
+        while let Some(dominator) = cur {
+            if let [pred] = func.preds(dominator)[..] {
+                let terminator = func.terminator(pred).map(|x| &func.inst_arena[x].kind);
+                if let Some(InstKind::Br(Br::Br { cond, true_bb, false_bb })) = terminator {
+                    if true_bb != false_bb {
+                        guards.push((cond.clone(), *true_bb == dominator, pred));
+                    }
+                }
+            }
+            cur = self.dom_tree.idom(dominator);
+        }
+        guards
+    }
+
+    /// What `cond` being `is_true` says about `value`.
+    fn guard_range(&self, func: &IrFunc, value: &Operand, cond: &Operand, is_true: bool, guard_bb: BBId) -> ValueRange {
+        if cond == value {
+            return ValueRange::constant(i32::from(is_true));
+        }
+        let Some(InstKind::Binary(cmp)) = cond.as_inst().map(|&x| &func.inst_arena[x].kind) else {
+            return ValueRange::full();
+        };
+        let (op, other) = if &cmp.left == value {
+            (Some(cmp.op), &cmp.right)
+        } else if &cmp.right == value {
+            (cmp.op.swapped(), &cmp.left)
+        } else {
+            return ValueRange::full();
+        };
+        let op = if is_true { op } else { op.and_then(BinaryInstOp::negated) };
+        let Some(op) = op else {
+            return ValueRange::full();
+        };
+
+        let other = self.range_at(func, other, guard_bb);
+        let (lo, hi) = (i64::from(other.lo), i64::from(other.hi));
+        let (min, max) = (i64::from(i32::MIN), i64::from(i32::MAX));
+        match op {
+            BinaryInstOp::Lt => ValueRange::from_i64(min, hi - 1),
+            BinaryInstOp::Le => ValueRange::from_i64(min, hi),
+            BinaryInstOp::Gt => ValueRange::from_i64(lo + 1, max),
+            BinaryInstOp::Ge => ValueRange::from_i64(lo, max),
+            BinaryInstOp::Eq => other,
+            _ => ValueRange::full(),
+        }
+    }
+
+    /// Bounds of `value` from the counted loops around `bb`, if it evolves
+    /// in them.
+    fn loop_range(&self, func: &IrFunc, value: &Operand, bb: BBId) -> ValueRange {
+        let mut range = ValueRange::full();
+        let mut cur = self.loop_info.loop_of(bb);
+        while let Some(loop_id) = cur {
+            let lp = self.loop_info.get(loop_id);
+            let rec = self.scev.evolution_of(func, loop_id, value);
+            if let (Some(rec), Some(trip_count)) = (rec, self.scev.trip_count(loop_id)) {
+                // the header also runs once more to leave the loop
+                let last_iter = if bb == lp.header { trip_count } else { trip_count.saturating_sub(1) };
+                let first = rec.start;
+                let last = i64::try_from(last_iter).ok()
+                    .and_then(|x| x.checked_mul(rec.step))
+                    .and_then(|x| first.map(|first| first + x));
+                if let (Some(first), Some(last)) = (first, last) {
+                    range = range.intersect(ValueRange::from_i64(first.min(last), first.max(last)));
+                }
+            }
+            cur = lp.parent;
+        }
+        range
+    }
+}
+
+/// Range of a value that is not an instruction.
+fn leaf_range(value: &Operand) -> ValueRange {
+    match value {
+        Operand::Const(Constant::Int(x)) => ValueRange::constant(*x),
+        _ => ValueRange::full(),
+    }
+}
+
+fn binary_range(op: BinaryInstOp, left: ValueRange, right: ValueRange) -> ValueRange {
+    let (l_lo, l_hi) = (i64::from(left.lo), i64::from(left.hi));
+    let (r_lo, r_hi) = (i64::from(right.lo), i64::from(right.hi));
+    let decided = |always: bool, never: bool| match (always, never) {
+        (true, _) => ValueRange::constant(1),
+        (_, true) => ValueRange::constant(0),
+        _ => ValueRange::bool(),
+    };
+    match op {
+        BinaryInstOp::Add => ValueRange::from_i64(l_lo + r_lo, l_hi + r_hi),
+        BinaryInstOp::Sub => ValueRange::from_i64(l_lo - r_hi, l_hi - r_lo),
+        BinaryInstOp::Mul => {
+            let corners = [l_lo * r_lo, l_lo * r_hi, l_hi * r_lo, l_hi * r_hi];
+            ValueRange::from_i64(*corners.iter().min().unwrap(), *corners.iter().max().unwrap())
+        }
+        BinaryInstOp::Div => div_range(left, right),
+        BinaryInstOp::Mod => mod_range(left, right),
+        BinaryInstOp::Lt => decided(l_hi < r_lo, l_lo >= r_hi),
+        BinaryInstOp::Le => decided(l_hi <= r_lo, l_lo > r_hi),
+        BinaryInstOp::Gt => decided(l_lo > r_hi, l_hi <= r_lo),
+        BinaryInstOp::Ge => decided(l_lo >= r_hi, l_hi < r_lo),
+        BinaryInstOp::Eq => decided(left.as_const().is_some() && left == right, l_hi < r_lo || r_hi < l_lo),
+        BinaryInstOp::Ne => decided(l_hi < r_lo || r_hi < l_lo, left.as_const().is_some() && left == right),
+        BinaryInstOp::And | BinaryInstOp::Or if left.lo >= 0 && left.hi <= 1 && right.lo >= 0 && right.hi <= 1 => {
+            let is_and = matches!(op, BinaryInstOp::And);
+            if is_and {
+                decided(left.lo == 1 && right.lo == 1, left.hi == 0 || right.hi == 0)
+            } else {
+                decided(left.lo == 1 || right.lo == 1, left.hi == 0 && right.hi == 0)
+            }
+        }
+        BinaryInstOp::And | BinaryInstOp::Or => ValueRange::full(),
+        BinaryInstOp::Shl | BinaryInstOp::AShr | BinaryInstOp::LShr => shift_range(op, left, right),
+    }
+}
+
+/// Range of a shift by a constant amount. Logical shifts are only followed
+/// for values that are not negative, where they agree with the others.
+fn shift_range(op: BinaryInstOp, left: ValueRange, right: ValueRange) -> ValueRange {
+    let Some(amount) = right.as_const().filter(|x| (0..32).contains(x)) else {
+        return ValueRange::full();
+    };
+    let (lo, hi) = (i64::from(left.lo), i64::from(left.hi));
+    match op {
+        BinaryInstOp::Shl => ValueRange::from_i64(lo << amount, hi << amount),
+        BinaryInstOp::LShr if lo < 0 => ValueRange::full(),
+        _ => ValueRange::from_i64(lo >> amount, hi >> amount),
+    }
+}
+
+/// Range of `left / right`, truncating, over the nonzero divisors.
+fn div_range(left: ValueRange, right: ValueRange) -> ValueRange {
+    let divisors = [
+        (right.lo <= -1).then(|| (right.lo, right.hi.min(-1))),
+        (right.hi >= 1).then(|| (right.lo.max(1), right.hi)),
+    ];
+    // division is monotone in each operand while the divisor keeps its sign
+    divisors.into_iter()
+        .flatten()
+        .map(|(lo, hi)| {
+            let corners: Vec<_> = [left.lo, left.hi].into_iter()
+                .flat_map(|x| [lo, hi].map(|y| i64::from(x) / i64::from(y)))
+                .collect();
+            ValueRange::from_i64(*corners.iter().min().unwrap(), *corners.iter().max().unwrap())
+        })
+        .reduce(ValueRange::union)
+        .unwrap_or_else(ValueRange::full)
+}
+
+/// Range of `left % right`: the sign of `left`, and smaller in magnitude
+/// than both `left` and the largest divisor.
+fn mod_range(left: ValueRange, right: ValueRange) -> ValueRange {
+    let max_divisor = i64::from(right.lo).abs().max(i64::from(right.hi).abs());
+    let bound = max_divisor - 1;
+    let lo = if left.lo >= 0 { 0 } else { (-bound).max(i64::from(left.lo)) };
+    let hi = if left.hi <= 0 { 0 } else { bound.min(i64::from(left.hi)) };
+    ValueRange::from_i64(lo, hi)
+}
+
+/// [`RangeInfo`] as a cached analysis.
+#[derive(Debug, Clone, Copy)]
+pub struct ValueRanges;
+
+impl FuncAnalysis for ValueRanges {
+    type Result = RangeInfo;
+
+    fn run(func_id: FuncId, func: &IrFunc, analyses: &mut AnalysisManager) -> RangeInfo {
+        let dom_tree = analyses.get::<Dominators>(func_id, func);
+        let loop_info = analyses.get::<Loops>(func_id, func);
+        let scev = analyses.get::<InductionVars>(func_id, func);
+        RangeInfo::new(dom_tree, loop_info, scev)
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::compiler::analysis::{
+    AnalysisManager,
+    FuncAnalysis,
+    dominance::{DomTree, Dominators},
+    loops::{Loop, LoopId, LoopInfo, Loops},
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{BinaryInstOp, Br, InstKind},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+
+/// An affine recurrence `{start, +, step}`: the value is `start + k * step`
+/// on the `k`-th iteration of its loop, counting from 0. `start` is `None`
+/// when it is not a known constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddRec {
+    pub start: Option<i64>,
+    pub step: i64,
+}
+
+impl AddRec {
+    fn add(self, other: AddRec) -> Option<AddRec> {
+        Some(AddRec {
+            start: self.start.zip(other.start).and_then(|(x, y)| x.checked_add(y)),
+            step: self.step.checked_add(other.step)?,
+        })
+    }
+
+    fn mul(self, factor: i64) -> Option<AddRec> {
+        Some(AddRec {
+            start: self.start.and_then(|x| x.checked_mul(factor)),
+            step: self.step.checked_mul(factor)?,
+        })
+    }
+
+    fn invariant(val: i64) -> AddRec {
+        AddRec { start: Some(val), step: 0 }
+    }
+}
+
+/// A local variable stepped by a constant once per iteration of a loop.
+#[derive(Debug, Clone, Copy)]
+pub struct InductionVar {
+    /// The `alloca` holding the variable.
+    pub var: InstId,
+    /// The only store to it inside the loop.
+    pub update: InstId,
+    /// Its value at the top of each iteration.
+    pub rec: AddRec,
+}
+
+#[derive(Debug, Clone)]
+struct LoopEvolution {
+    ivs: Vec<InductionVar>,
+    trip_count: Option<u64>,
+}
+
+/// Induction variables and trip counts of every loop in a function.
+///
+/// Variables live in memory until they are promoted to registers, so an
+/// induction variable is a scalar `alloca` that is only ever loaded and
+/// stored, and that the loop updates with `v = v + c` or `v = v - c`
+/// exactly once per iteration.
+#[derive(Debug, Clone)]
+pub struct ScalarEvolution {
+    dom_tree: Rc<DomTree>,
+    loop_info: Rc<LoopInfo>,
+    loops: HashMap<LoopId, LoopEvolution>,
+}
+
+impl ScalarEvolution {
+    #[must_use] pub fn new(func: &IrFunc, dom_tree: Rc<DomTree>, loop_info: Rc<LoopInfo>) -> ScalarEvolution {
+        let mut scev = ScalarEvolution { dom_tree, loop_info, loops: HashMap::new() };
+        let mut preds: HashMap<_, Vec<_>> = HashMap::new();
+        for (from, to) in func.edges() {
+            preds.entry(to).or_default().push(from);
+        }
+        let vars: Vec<_> = func.inst_arena.iter()
+            .filter(|(inst_id, inst)| matches!(inst.kind, InstKind::Alloca(_)) && is_local_scalar(func, *inst_id))
+            .map(|(inst_id, _)| inst_id)
+            .collect();
+        for (loop_id, lp) in scev.loop_info.loops() {
+            let ivs = vars.iter()
+                .filter_map(|&var| scev.find_iv(func, &preds, lp, var))
+                .collect();
+            scev.loops.insert(loop_id, LoopEvolution { ivs, trip_count: None });
+        }
+        let trip_counts: Vec<_> = scev.loop_info.loops()
+            .map(|(loop_id, lp)| (loop_id, scev.find_trip_count(func, loop_id, lp)))
+            .collect();
+        for (loop_id, trip_count) in trip_counts {
+            if let Some(evolution) = scev.loops.get_mut(&loop_id) {
+                evolution.trip_count = trip_count;
+            }
+        }
+        scev
+    }
+
+    #[must_use] pub fn induction_vars(&self, loop_id: LoopId) -> &[InductionVar] {
+        self.loops.get(&loop_id).map_or(&[], |x| x.ivs.as_slice())
+    }
+
+    /// Number of times the body of the loop runs, when the loop only exits
+    /// from its header through a comparison of an induction variable with a
+    /// constant.
+    #[must_use] pub fn trip_count(&self, loop_id: LoopId) -> Option<u64> {
+        self.loops.get(&loop_id)?.trip_count
+    }
+
+    /// How `value` evolves over the iterations of `loop_id`, if it is an
+    /// affine function of the induction variables. Values defined outside
+    /// of the loop do not evolve in it and are not handled, except for
+    /// constants.
+    #[must_use] pub fn evolution_of(&self, func: &IrFunc, loop_id: LoopId, value: &Operand) -> Option<AddRec> {
+        let inst_id = match value {
+            Operand::Const(Constant::Int(x)) => return Some(AddRec::invariant(i64::from(*x))),
+            Operand::Inst(x) => *x,
+            _ => return None,
+        };
+        let lp = self.loop_info.get(loop_id);
+        let inst = func.get_inst(inst_id)?;
+        if !lp.contains(inst.bb) {
+            return None;
+        }
+        match &inst.kind {
+            InstKind::Load(load) => {
+                let var = *load.addr.as_inst()?;
+                let iv = self.induction_vars(loop_id).iter().find(|x| x.var == var)?;
+                let update = func.get_inst(iv.update)?;
+                if self.runs_before(func, inst_id, iv.update) {
+                    Some(iv.rec)
+                } else if self.dom_tree.strictly_dominates(update.bb, inst.bb) || self.runs_before(func, iv.update, inst_id) {
+                    iv.rec.add(AddRec { start: Some(iv.rec.step), step: 0 })
+                } else {
+                    None
+                }
+            }
+            InstKind::Binary(binary) => {
+                let left = self.evolution_of(func, loop_id, &binary.left)?;
+                let right = self.evolution_of(func, loop_id, &binary.right)?;
+                match binary.op {
+                    BinaryInstOp::Add => left.add(right),
+                    BinaryInstOp::Sub => left.add(right.mul(-1)?),
+                    BinaryInstOp::Mul if left.step == 0 => right.mul(left.start?),
+                    BinaryInstOp::Mul if right.step == 0 => left.mul(right.start?),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `a` always runs before `b` in the same iteration: it is
+    /// earlier in the same block or in a block dominating that of `b`.
+    fn runs_before(&self, func: &IrFunc, a: InstId, b: InstId) -> bool {
+        let (a_bb, b_bb) = (func.inst_arena[a].bb, func.inst_arena[b].bb);
+        if a_bb != b_bb {
+            return self.dom_tree.strictly_dominates(a_bb, b_bb);
+        }
+        func.inst_arena.items_iter(Some(a), None).any(|(x, _)| x == b) && a != b
+    }
+
+    fn find_iv(&self, func: &IrFunc, preds: &HashMap<BBId, Vec<BBId>>, lp: &Loop, var: InstId) -> Option<InductionVar> {
+        let stores: Vec<_> = func.users(&Operand::Inst(var))
+            .into_iter()
+            .filter(|&x| matches!(func.inst_arena[x].kind, InstKind::Store(_)))
+            .collect();
+        let mut inner_stores = stores.iter().filter(|&&x| lp.contains(func.inst_arena[x].bb));
+        let update = *inner_stores.next()?;
+        if inner_stores.next().is_some() {
+            return None;
+        }
+
+        // the update must run exactly once on every trip around the loop
+        let update_bb = func.inst_arena[update].bb;
+        let in_this_loop = self.loop_info.loop_of(update_bb)
+            .is_some_and(|x| self.loop_info.get(x).header == lp.header);
+        if update_bb == lp.header || !in_this_loop || !lp.latches.iter().all(|&x| self.dom_tree.dominates(update_bb, x)) {
+            return None;
+        }
+
+        let InstKind::Store(store) = &func.inst_arena[update].kind else {
+            unreachable!()
+        };
+        let InstKind::Binary(binary) = &func.inst_arena[*store.data.as_inst()?].kind else {
+            return None;
+        };
+        // any load in the loop before the update reads the variable as of
+        // this trip, the update being its only store in the loop; after
+        // store-to-load forwarding it is often that of the header
+        let is_load_of_var = |operand: &Operand| operand.as_inst().is_some_and(|&x| {
+            let inst = &func.inst_arena[x];
+            matches!(&inst.kind, InstKind::Load(load) if load.addr == Operand::Inst(var))
+                && lp.contains(inst.bb)
+                && self.runs_before(func, x, update)
+        });
+        let step = match (binary.op, &binary.left, &binary.right) {
+            (BinaryInstOp::Add, x, Operand::Const(Constant::Int(c)))
+            | (BinaryInstOp::Add, Operand::Const(Constant::Int(c)), x) if is_load_of_var(x) => i64::from(*c),
+            (BinaryInstOp::Sub, x, Operand::Const(Constant::Int(c))) if is_load_of_var(x) => -i64::from(*c),
+            _ => return None,
+        };
+
+        let start = self.start_value(func, preds, lp, &stores);
+        Some(InductionVar { var, update, rec: AddRec { start, step } })
+    }
+
+    /// The constant a variable holds on entry to `lp`, from the last of its
+    /// `stores` on the way to the preheader. `preds` are the predecessors of
+    /// every block.
+    fn start_value(&self, func: &IrFunc, preds: &HashMap<BBId, Vec<BBId>>, lp: &Loop, stores: &[InstId]) -> Option<i64> {
+        let preheader = lp.preheader(func)?;
+        let mut bb = Some(preheader);
+        let init = loop {
+            let cur = bb?;
+            let insts: Vec<_> = func.inst_arena.items_iter(func.bb_arena[cur].insts_head, None).collect();
+            let store = insts.into_iter()
+                .rev()
+                .map(|(inst_id, _)| inst_id)
+                .find(|x| stores.contains(x));
+            if let Some(store) = store {
+                break store;
+            }
+            bb = self.dom_tree.idom(cur);
+        };
+
+        // no other store may sit on a path from it to the loop
+        let init_bb = func.inst_arena[init].bb;
+        let reaching_loop = walk(preheader, |x| preds.get(&x).cloned().unwrap_or_default(), lp);
+        let after_init: HashSet<_> = func.succs(init_bb)
+            .into_iter()
+            .flat_map(|x| walk(x, |x| func.succs(x), lp))
+            .collect();
+        let clobbered = stores.iter().any(|&x| {
+            let bb = func.inst_arena[x].bb;
+            x != init && bb != init_bb && !lp.contains(bb) && reaching_loop.contains(&bb) && after_init.contains(&bb)
+        });
+        if clobbered {
+            return None;
+        }
+
+        let InstKind::Store(store) = &func.inst_arena[init].kind else {
+            unreachable!()
+        };
+        store.data.as_const().and_then(|x| x.as_int()).map(|&x| i64::from(x))
+    }
+
+    fn find_trip_count(&self, func: &IrFunc, loop_id: LoopId, lp: &Loop) -> Option<u64> {
+        if lp.exiting_blocks(func) != [lp.header] {
+            return None;
+        }
+        let terminator = func.terminator(lp.header)?;
+        let InstKind::Br(Br::Br { cond, true_bb, .. }) = &func.inst_arena[terminator].kind else {
+            return None;
+        };
+        let InstKind::Binary(cmp) = &func.inst_arena[*cond.as_inst()?].kind else {
+            return None;
+        };
+        let left = self.evolution_of(func, loop_id, &cmp.left)?;
+        let right = self.evolution_of(func, loop_id, &cmp.right)?;
+        // compare the recurrence against a bound, both sides affine
+        let (rec, bound, op) = match (left.step, right.step) {
+            (_, 0) => (left, right.start?, cmp.op),
+            (0, _) => (right, left.start?, cmp.op.swapped()?),
+            _ => return None,
+        };
+        let op = if lp.contains(*true_bb) { op } else { op.negated()? };
+        trip_count(rec.start?, rec.step, op, bound)
+    }
+}
+
+/// Whether `var` is a scalar local whose address is only used to load from
+/// and store to it.
+fn is_local_scalar(func: &IrFunc, var: InstId) -> bool {
+    let InstKind::Alloca(alloca) = &func.inst_arena[var].kind else {
+        return false;
+    };
+    let addr = Operand::Inst(var);
+    matches!(alloca.alloca_ty, IrTy::Int(_)) && func.users(&addr).into_iter().all(|x| match &func.inst_arena[x].kind {
+        InstKind::Load(_) => true,
+        InstKind::Store(store) => store.data != addr,
+        _ => false,
+    })
+}
+
+/// Blocks reached from `from` through `next` without entering `lp`.
+fn walk(from: BBId, next: impl Fn(BBId) -> Vec<BBId>, lp: &Loop) -> HashSet<BBId> {
+    let mut visited = HashSet::new();
+    let mut stack = vec![from];
+    while let Some(bb) = stack.pop() {
+        if !lp.contains(bb) && visited.insert(bb) {
+            stack.extend(next(bb));
+        }
+    }
+    visited
+}
+
+/// The first `k` for which `start + k * step op bound` is false, if the
+/// value stays within `i32` until then.
+fn trip_count(start: i64, step: i64, op: BinaryInstOp, bound: i64) -> Option<u64> {
+    let holds = |k: i64| {
+        let val = start + k * step;
+        match op {
+            BinaryInstOp::Lt => val < bound,
+            BinaryInstOp::Le => val <= bound,
+            BinaryInstOp::Gt => val > bound,
+            BinaryInstOp::Ge => val >= bound,
+            BinaryInstOp::Eq => val == bound,
+            _ => val != bound,
+        }
+    };
+    if !holds(0) {
+        return Some(0);
+    }
+    if step == 0 {
+        return None;
+    }
+
+    let count = match op {
+        BinaryInstOp::Lt if step > 0 => (bound - start + step - 1) / step,
+        BinaryInstOp::Le if step > 0 => (bound - start) / step + 1,
+        BinaryInstOp::Gt if step < 0 => (start - bound - step - 1) / -step,
+        BinaryInstOp::Ge if step < 0 => (start - bound) / -step + 1,
+        BinaryInstOp::Eq => 1,
+        BinaryInstOp::Ne if (bound - start) % step == 0 && (bound - start) / step > 0 => (bound - start) / step,
+        // never false before the variable wraps around
+        _ => return None,
+    };
+    // the value that ends the loop must not have wrapped around either
+    let end = count.checked_mul(step).and_then(|x| x.checked_add(start))?;
+    i32::try_from(end).ok()?;
+    u64::try_from(count).ok()
+}
+
+/// [`ScalarEvolution`] as a cached analysis.
+#[derive(Debug, Clone, Copy)]
+pub struct InductionVars;
+
+impl FuncAnalysis for InductionVars {
+    type Result = ScalarEvolution;
+
+    fn run(func_id: FuncId, func: &IrFunc, analyses: &mut AnalysisManager) -> ScalarEvolution {
+        let dom_tree = analyses.get::<Dominators>(func_id, func);
+        let loop_info = analyses.get::<Loops>(func_id, func);
+        ScalarEvolution::new(func, dom_tree, loop_info)
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::compiler::analysis::{AnalysisManager, FuncAnalysis};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::FuncId,
+    value::{func::IrFunc, inst::InstKind, module::Module},
+};
+
+/// Size of a function, for cost models deciding whether growing it pays off
+/// and for `--stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FuncStats {
+    pub blocks: usize,
+    pub insts: usize,
+    /// Instructions of each opcode, see [`InstKind::opcode`].
+    pub insts_by_opcode: BTreeMap<&'static str, usize>,
+    pub allocas: usize,
+    pub call_sites: usize,
+}
+
+impl FuncStats {
+    #[must_use] pub fn new(func: &IrFunc) -> FuncStats {
+        let mut stats = FuncStats::default();
+        for bb in func.bb_ids() {
+            stats.blocks += 1;
+            for (_, inst) in func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None) {
+                stats.insts += 1;
+                *stats.insts_by_opcode.entry(inst.kind.opcode()).or_default() += 1;
+                match inst.kind {
+                    InstKind::Alloca(_) => stats.allocas += 1,
+                    InstKind::Call(_) => stats.call_sites += 1,
+                    _ => {}
+                }
+            }
+        }
+        stats
+    }
+
+    /// Adds the counts of `other` to those of `self`.
+    pub fn add(&mut self, other: &FuncStats) {
+        self.blocks += other.blocks;
+        self.insts += other.insts;
+        for (&opcode, &cnt) in &other.insts_by_opcode {
+            *self.insts_by_opcode.entry(opcode).or_default() += cnt;
+        }
+        self.allocas += other.allocas;
+        self.call_sites += other.call_sites;
+    }
+}
+
+/// [`FuncStats::new`] as a cached analysis.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats;
+
+impl FuncAnalysis for Stats {
+    type Result = FuncStats;
+
+    fn run(_func_id: FuncId, func: &IrFunc, _analyses: &mut AnalysisManager) -> FuncStats {
+        FuncStats::new(func)
+    }
+}
+
+/// Sizes of every function defined in a module, and their sum.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleStats {
+    /// By function name, in the order the functions are printed.
+    pub funcs: Vec<(String, FuncStats)>,
+    pub globals: usize,
+    pub total: FuncStats,
+}
+
+impl ModuleStats {
+    #[must_use] pub fn new(module: &Module) -> ModuleStats {
+        let mut stats = ModuleStats { globals: module.global_arena.len(), ..ModuleStats::default() };
+        for (_, func) in module.func_arena.items_iter(module.first_func, None).filter(|(_, x)| !x.is_builtin) {
+            let func_stats = FuncStats::new(func);
+            stats.total.add(&func_stats);
+            stats.funcs.push((func.name.clone(), func_stats));
+        }
+        stats
+    }
+}
+
+impl Display for ModuleStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self.funcs.iter().map(|(name, _)| name.len()).chain(["function".len()]).max().unwrap_or_default();
+        writeln!(f, "{:width$}  {:>6}  {:>6}  {:>7}  {:>5}", "function", "blocks", "insts", "allocas", "calls")?;
+        let totals = std::iter::once(("total", &self.total));
+        for (name, stats) in self.funcs.iter().map(|(name, stats)| (name.as_str(), stats)).chain(totals) {
+            writeln!(f, "{name:width$}  {:>6}  {:>6}  {:>7}  {:>5}", stats.blocks, stats.insts, stats.allocas, stats.call_sites)?;
+        }
+        writeln!(f, "{} globals", self.globals)?;
+        writeln!(f, "instructions by opcode:")?;
+        for (opcode, cnt) in &self.total.insts_by_opcode {
+            writeln!(f, "  {opcode:13}  {cnt:>6}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+
+use crate::compiler::backend::arm::{
+    branch,
+    inst::{Address, ArmInst, Cond, HalfWord, Operand2, FP, IP, LR, PC, Q0, SP},
+};
+use crate::compiler::ir::value::value::Linkage;
+use crate::compiler::mir::{BlockId, DataObject, MachineFunc, MachineInst, MachineModule, Reg};
+
+/// The module as GNU assembly for ARMv7-A in the ARM instruction set, for
+/// a Linux system with the hard-float ABI.
+impl Display for MachineModule<ArmInst> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "    .arch armv7-a")?;
+        // present on every core with the virtualization extensions, such as
+        // the Cortex-A7 and A15
+        writeln!(f, "    .arch_extension idiv")?;
+    writeln!(f, "    .fpu neon")?;
+    // Tag_ABI_VFP_args, for the linker to take the code as hard-float
+    writeln!(f, "    .eabi_attribute 28, 1")?;
+        writeln!(f, "    .syntax unified")?;
+        writeln!(f, "    .arm")?;
+        if !self.funcs.is_empty() {
+            writeln!(f, "    .text")?;
+        }
+        for (i, func) in self.funcs.iter().enumerate() {
+            fmt_func(f, i, func)?;
+        }
+        for data in &self.data {
+            fmt_data(f, data)?;
+        }
+        Ok(())
+    }
+}
+
+fn fmt_func(f: &mut Formatter<'_>, index: usize, func: &MachineFunc<ArmInst>) -> std::fmt::Result {
+    let name = &func.name;
+    writeln!(f)?;
+    if func.linkage == Linkage::External {
+        writeln!(f, "    .global {name}")?;
+    }
+    writeln!(f, "    .p2align 2")?;
+    writeln!(f, "    .type {name}, %function")?;
+    writeln!(f, "{name}:")?;
+    let targets: HashSet<_> = func.blocks.iter()
+        .flat_map(|x| &x.insts)
+        .flat_map(MachineInst::targets)
+        .collect();
+    let far = branch::out_of_range(func);
+    for (i, block) in func.blocks.iter().enumerate() {
+        if targets.contains(&BlockId(i)) {
+            writeln!(f, "{}:", block_label(index, BlockId(i)))?;
+        }
+        // the block printed next, empty ones printing nothing
+        let next = (i + 1..func.blocks.len()).find(|&x| !func.blocks[x].insts.is_empty());
+        for (j, inst) in block.insts.iter().enumerate() {
+            // control reaches the next block without a branch to it
+            let last = j + 1 == block.insts.len();
+            if let ArmInst::B { cond, target } = inst {
+                if last && *cond == Cond::Al && Some(target.0) == next {
+                    continue;
+                }
+                if far.contains(&(i, j)) {
+                    // branched over unless the condition holds
+                    let over = format!(".LBB{index}_{i}_{j}_far");
+                    if *cond != Cond::Al {
+                        writeln!(f, "    b{} {over}", cond.negated().suffix())?;
+                    }
+                    writeln!(f, "    ldr pc, [pc, #-4]")?;
+                    writeln!(f, "    .word {}", block_label(index, *target))?;
+                    if *cond != Cond::Al {
+                        writeln!(f, "{over}:")?;
+                    }
+                    continue;
+                }
+            }
+            for line in lines(inst, index) {
+                if line.ends_with(':') {
+                    writeln!(f, "{line}")?;
+                } else {
+                    writeln!(f, "    {line}")?;
+                }
+            }
+        }
+    }
+    writeln!(f, "    .size {name}, .-{name}")
+}
+
+fn fmt_data(f: &mut Formatter<'_>, data: &DataObject) -> std::fmt::Result {
+    let name = &data.name;
+    writeln!(f)?;
+    if data.is_zero() {
+        writeln!(f, "    .bss")?;
+    } else if data.readonly {
+        writeln!(f, "    .section .rodata")?;
+    } else {
+        writeln!(f, "    .data")?;
+    }
+    if data.linkage == Linkage::External {
+        writeln!(f, "    .global {name}")?;
+    }
+    writeln!(f, "    .p2align 2")?;
+    writeln!(f, "    .type {name}, %object")?;
+    writeln!(f, "{name}:")?;
+    for chunk in data.words.chunks(8) {
+        writeln!(f, "    .word {}", chunk.iter().join(", "))?;
+    }
+    let zeros = data.size - data.words.len();
+    if zeros > 0 || data.size == 0 {
+        writeln!(f, "    .zero {}", 4 * zeros.max(1))?;
+    }
+    writeln!(f, "    .size {name}, {}", 4 * data.size.max(1))
+}
+
+fn block_label(func: usize, block: BlockId) -> String {
+    format!(".LBB{func}_{}", block.0)
+}
+
+fn literal_label(func: usize, label: u32) -> String {
+    format!(".LCPI{func}_{label}")
+}
+
+fn reg(reg: Reg) -> String {
+    match reg {
+        Reg::Phys(FP) => String::from("fp"),
+        Reg::Phys(IP) => String::from("ip"),
+        Reg::Phys(SP) => String::from("sp"),
+        Reg::Phys(LR) => String::from("lr"),
+        Reg::Phys(PC) => String::from("pc"),
+        Reg::Phys(x) if x >= Q0 => format!("q{}", x - Q0),
+        reg => reg.to_string(),
+    }
+}
+
+fn reg_list(regs: &[u8]) -> String {
+    format!("{{{}}}", regs.iter().map(|&x| reg(Reg::Phys(x))).join(", "))
+}
+
+fn operand2(operand: &Operand2) -> String {
+    match *operand {
+        Operand2::Imm(imm) => format!("#{imm}"),
+        Operand2::Reg(x) => reg(x),
+        Operand2::Shifted(x, shift, amount) => format!("{}, {shift} #{amount}", reg(x)),
+        Operand2::RegShifted(x, shift, amount) => format!("{}, {shift} {}", reg(x), reg(amount)),
+    }
+}
+
+fn address(addr: &Address) -> String {
+    match *addr {
+        Address::Imm(base, 0) => format!("[{}]", reg(base)),
+        Address::Imm(base, offset) => format!("[{}, #{offset}]", reg(base)),
+        Address::Reg(base, index, 0) => format!("[{}, {}]", reg(base), reg(index)),
+        Address::Reg(base, index, shift) => format!("[{}, {}, lsl #{shift}]", reg(base), reg(index)),
+        Address::Frame(..) => unreachable!("frame objects are addressed from fp once the frame is lowered"),
+    }
+}
+
+/// The doubleword registers making up the vector register `reg`, as `vld1`
+/// and `vst1` list them.
+fn doublewords(reg: Reg) -> String {
+    let Reg::Phys(x) = reg else { unreachable!("registers are allocated before printing") };
+    let d = 2 * (x - Q0);
+    format!("{{d{d}, d{}}}", d + 1)
+}
+
+/// The doubleword register and lane within it holding lane `lane` of the
+/// vector register `reg`.
+fn lane(reg: Reg, lane: u8) -> String {
+    let Reg::Phys(x) = reg else { unreachable!("registers are allocated before printing") };
+    format!("d{}[{}]", 2 * (x - Q0) + lane / 2, lane % 2)
+}
+
+/// The address of a `vld1` or `vst1`, in a register once the frame is
+/// lowered, with the alignment hint it is known to meet.
+fn vector_address(addr: &Address, align: u32) -> String {
+    let Address::Imm(base, 0) = *addr else { unreachable!("vector accesses are off a register once the frame is lowered") };
+    match align {
+        16.. => format!("[{}:128]", reg(base)),
+        8 => format!("[{}:64]", reg(base)),
+        _ => format!("[{}]", reg(base)),
+    }
+}
+
+/// The lines of assembly `inst` of the function with the given index is
+/// printed as.
+fn lines(inst: &ArmInst, func: usize) -> Vec<String> {
+    match inst {
+        ArmInst::Mov { cond, dst, src } => match *src {
+            // as the shift instruction it is
+            Operand2::Shifted(src, shift, amount) => vec![format!("{shift}{} {}, {}, #{amount}", cond.suffix(), reg(*dst), reg(src))],
+            Operand2::RegShifted(src, shift, amount) => vec![format!("{shift}{} {}, {}, {}", cond.suffix(), reg(*dst), reg(src), reg(amount))],
+            _ => vec![format!("mov{} {}, {}", cond.suffix(), reg(*dst), operand2(src))],
+        },
+        ArmInst::Mvn { cond, dst, src } => vec![format!("mvn{} {}, {}", cond.suffix(), reg(*dst), operand2(src))],
+        ArmInst::Movw { cond, dst, half } => match half {
+            HalfWord::Imm(imm) => vec![format!("movw{} {}, #{imm}", cond.suffix(), reg(*dst))],
+            HalfWord::Symbol(symbol) => vec![format!("movw{} {}, #:lower16:{symbol}", cond.suffix(), reg(*dst))],
+        },
+        ArmInst::Movt { cond, dst, half } => match half {
+            HalfWord::Imm(imm) => vec![format!("movt{} {}, #{imm}", cond.suffix(), reg(*dst))],
+            HalfWord::Symbol(symbol) => vec![format!("movt{} {}, #:upper16:{symbol}", cond.suffix(), reg(*dst))],
+        },
+        ArmInst::LdrLit { cond, dst, label } => vec![format!("ldr{} {}, {}", cond.suffix(), reg(*dst), literal_label(func, *label))],
+        ArmInst::Binary { op, cond, set_flags, dst, left, right } => {
+            let s = if *set_flags { "s" } else { "" };
+            vec![format!("{}{s}{} {}, {}, {}", op.name(), cond.suffix(), reg(*dst), reg(*left), operand2(right))]
+        }
+        ArmInst::Mul { dst, left, right } => vec![format!("mul {}, {}, {}", reg(*dst), reg(*left), reg(*right))],
+        ArmInst::Mla { dst, left, right, acc, sub } => {
+            let name = if *sub { "mls" } else { "mla" };
+            vec![format!("{name} {}, {}, {}, {}", reg(*dst), reg(*left), reg(*right), reg(*acc))]
+        }
+        ArmInst::LongMul { signed, lo, hi, left, right } => {
+            let name = if *signed { "smull" } else { "umull" };
+            vec![format!("{name} {}, {}, {}, {}", reg(*lo), reg(*hi), reg(*left), reg(*right))]
+        }
+        ArmInst::Sdiv { dst, left, right } => vec![format!("sdiv {}, {}, {}", reg(*dst), reg(*left), reg(*right))],
+        ArmInst::Cmp { left, right, neg } => {
+            let name = if *neg { "cmn" } else { "cmp" };
+            vec![format!("{name} {}, {}", reg(*left), operand2(right))]
+        }
+        ArmInst::Ldr { cond, dst, addr } => vec![format!("ldr{} {}, {}", cond.suffix(), reg(*dst), address(addr))],
+        ArmInst::Str { cond, src, addr } => vec![format!("str{} {}, {}", cond.suffix(), reg(*src), address(addr))],
+        ArmInst::Ldrd { lo, hi, addr } => vec![format!("ldrd {}, {}, {}", reg(*lo), reg(*hi), address(addr))],
+        ArmInst::Strd { lo, hi, addr } => vec![format!("strd {}, {}, {}", reg(*lo), reg(*hi), address(addr))],
+        ArmInst::Ldm { mode, base, regs } => vec![format!("ldm{} {}, {{{}}}", mode.suffix(), reg(*base), regs.iter().map(|&x| reg(x)).join(", "))],
+        ArmInst::Stm { mode, base, regs } => vec![format!("stm{} {}, {{{}}}", mode.suffix(), reg(*base), regs.iter().map(|&x| reg(x)).join(", "))],
+        ArmInst::VLoad { dst, addr, align } => vec![format!("vld1.32 {}, {}", doublewords(*dst), vector_address(addr, *align))],
+        ArmInst::VStore { src, addr, align } => vec![format!("vst1.32 {}, {}", doublewords(*src), vector_address(addr, *align))],
+        ArmInst::VBinary { op, dst, left, right } => vec![format!("{} {}, {}, {}", op.name(), reg(*dst), reg(*left), reg(*right))],
+        ArmInst::VMov { dst, src } => vec![format!("vmov {}, {}", reg(*dst), reg(*src))],
+        ArmInst::VMovImm { dst, imm } => vec![format!("vmov.i32 {}, #{imm}", reg(*dst))],
+        ArmInst::VDup { dst, src } => vec![format!("vdup.32 {}, {}", reg(*dst), reg(*src))],
+        ArmInst::VSetLane { dst, lane: x, src } => vec![format!("vmov.32 {}, {}", lane(*dst, *x), reg(*src))],
+        ArmInst::VGetLane { dst, src, lane: x } => vec![format!("vmov.32 {}, {}", reg(*dst), lane(*src, *x))],
+        ArmInst::B { cond, target } => vec![format!("b{} {}", cond.suffix(), block_label(func, *target))],
+        ArmInst::Bl { func, .. } => vec![format!("bl {func}")],
+        ArmInst::TailCall { func, regs, .. } => vec![String::from("mov sp, fp"), format!("pop {}", reg_list(regs)), format!("b {func}")],
+        ArmInst::Prologue { regs } => vec![format!("push {}", reg_list(regs)), String::from("mov fp, sp")],
+        ArmInst::Epilogue { regs } => vec![String::from("mov sp, fp"), format!("pop {}", reg_list(regs))],
+        ArmInst::Pool { entries, skip } => {
+            // the label after the pool is named after its first entry
+            let end = format!("{}_end", literal_label(func, entries[0].0));
+            let mut pool = vec![];
+            if *skip {
+                pool.push(format!("b {end}"));
+            }
+            for (label, literal) in entries {
+                pool.extend([format!("{}:", literal_label(func, *label)), format!(".word {literal}")]);
+            }
+            if *skip {
+                pool.push(format!("{end}:"));
+            }
+            pool
+        }
+        ArmInst::FrameAddr { .. } | ArmInst::Ret { .. } => unreachable!("frame pseudo-instructions are gone once the frame is lowered"),
+        ArmInst::LoadImm { .. } | ArmInst::LoadAddr { .. } => unreachable!("constants are built by instructions once legalized"),
+        ArmInst::Div { .. } => unreachable!("division is lowered by legalization"),
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+
+use crate::compiler::backend::arm::inst::{ArmInst, Cond};
+use crate::compiler::mir::{BlockId, MachineFunc};
+
+/// Farthest a `b` reaches from `pc + 8`, its offset being 24 bits of words.
+const BRANCH_RANGE: usize = 1 << 25;
+
+/// Swaps the targets of a conditional branch followed by a branch to the
+/// next block, negating its condition, for control to fall through to the
+/// next block instead of a branch over the other.
+pub(super) fn invert(func: &mut MachineFunc<ArmInst>) {
+    for i in 0..func.blocks.len() {
+        let next = BlockId(i + 1);
+        if let [.., ArmInst::B { cond, target }, ArmInst::B { cond: Cond::Al, target: other }] = &mut func.blocks[i].insts[..] {
+            if *cond != Cond::Al && *target == next {
+                *cond = cond.negated();
+                std::mem::swap(target, other);
+            }
+        }
+    }
+}
+
+/// The branches of `func` that may not reach their target, by block and
+/// index, to jump through an address stored after them instead.
+///
+/// Addresses are counted with every instruction at its largest, branches
+/// as jumps through an address too, so that no branch goes out of reach
+/// once others become such jumps.
+pub(super) fn out_of_range(func: &MachineFunc<ArmInst>) -> HashSet<(usize, usize)> {
+    let mut starts = vec![];
+    let mut addr = 0;
+    for block in &func.blocks {
+        starts.push(addr);
+        addr += block.insts.iter().map(ArmInst::size).sum::<usize>();
+    }
+    let mut far = HashSet::new();
+    for (i, block) in func.blocks.iter().enumerate() {
+        let mut addr = starts[i];
+        for (j, inst) in block.insts.iter().enumerate() {
+            if let ArmInst::B { target, .. } = inst {
+                if starts[target.0].abs_diff(addr + 8) > BRANCH_RANGE {
+                    far.insert((i, j));
+                }
+            }
+            addr += inst.size();
+        }
+    }
+    far
+}
@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+
+use object::write::{Object, Relocation, StandardSection, Symbol, SymbolId, SymbolSection};
+use object::{
+    elf, Architecture, BinaryFormat, Endianness, FileFlags, RelocationFlags, SectionKind, SymbolFlags, SymbolKind,
+    SymbolScope,
+};
+
+use crate::compiler::backend::arm::{
+    branch,
+    inst::{Address, ArmInst, BinaryOp, BlockMode, Cond, HalfWord, Literal, Operand2, Shift, VectorOp, FP, PC, Q0, SP},
+};
+use crate::compiler::ir::value::value::Linkage;
+use crate::compiler::mir::{BlockId, MachineFunc, MachineModule, Reg};
+
+/// The module as a relocatable ELF object for the hard-float ABI, with the
+/// code and data the assembly it prints as assembles to.
+///
+/// Branches within a function and loads from its literal pools are resolved
+/// here. Calls, addresses of symbols and the addresses far branches jump
+/// through are left to the linker, their addends stored in the code as the
+/// ABI has them.
+pub(super) fn write(module: &MachineModule<ArmInst>) -> Vec<u8> {
+    let mut obj = Object::new(BinaryFormat::Elf, Architecture::Arm, Endianness::Little);
+    obj.flags = FileFlags::Elf { os_abi: elf::ELFOSABI_NONE, abi_version: 0, e_flags: elf::EF_ARM_EABI_VER5 | elf::EF_ARM_ABI_FLOAT_HARD };
+
+    // every symbol defined is made first, for code to refer to those defined
+    // after it
+    let scope = |linkage| match linkage {
+        Linkage::External => SymbolScope::Dynamic,
+        Linkage::Internal => SymbolScope::Compilation,
+    };
+    let defined = |obj: &mut Object, name: &str, kind, linkage| {
+        obj.add_symbol(Symbol {
+            name: name.as_bytes().to_vec(),
+            value: 0,
+            size: 0,
+            kind,
+            scope: scope(linkage),
+            weak: false,
+            section: SymbolSection::Undefined,
+            flags: SymbolFlags::None,
+        })
+    };
+    let funcs: Vec<_> = module.funcs.iter().map(|x| defined(&mut obj, &x.name, SymbolKind::Text, x.linkage)).collect();
+    let data: Vec<_> = module.data.iter().map(|x| defined(&mut obj, &x.name, SymbolKind::Data, x.linkage)).collect();
+    let mut symbols: HashMap<String, SymbolId> = module.funcs.iter().map(|x| x.name.clone()).zip(funcs.iter().copied())
+        .chain(module.data.iter().map(|x| x.name.clone()).zip(data.iter().copied()))
+        .collect();
+
+    let text = obj.section_id(StandardSection::Text);
+    for (func, symbol) in module.funcs.iter().zip(funcs) {
+        // the first pass finds where the blocks and literals are, which the
+        // second encodes branches and loads to
+        let mut encoder = Encoder::new(func, vec![], HashMap::new());
+        encoder.encode();
+        let mut encoder = Encoder::new(func, encoder.block_starts, encoder.literal_starts);
+        encoder.encode();
+
+        let bytes: Vec<u8> = encoder.code.iter().flat_map(|x| x.to_le_bytes()).collect();
+        let start = obj.append_section_data(text, &bytes, 4);
+        obj.set_symbol_data(symbol, text, start, bytes.len() as u64);
+        for (offset, is_code) in encoder.mapping {
+            add_mapping_symbol(&mut obj, text, start + offset as u64, is_code);
+        }
+        for (offset, fixup) in encoder.fixups {
+            let (target, r_type) = match fixup {
+                Fixup::Call(name) => (external(&mut obj, &mut symbols, name), elf::R_ARM_CALL),
+                Fixup::Jump(name) => (external(&mut obj, &mut symbols, name), elf::R_ARM_JUMP24),
+                Fixup::Movw(name) => (external(&mut obj, &mut symbols, name), elf::R_ARM_MOVW_ABS_NC),
+                Fixup::Movt(name) => (external(&mut obj, &mut symbols, name), elf::R_ARM_MOVT_ABS),
+                Fixup::Word(name) => (external(&mut obj, &mut symbols, name), elf::R_ARM_ABS32),
+                Fixup::Block(block) => {
+                    // the address of the block, from the start of the section
+                    let at = usize::try_from(start).unwrap() + offset;
+                    let addend = u32::try_from(start).unwrap() + u32::try_from(block).unwrap();
+                    obj.section_mut(text).data_mut()[at..at + 4].copy_from_slice(&addend.to_le_bytes());
+                    (obj.section_symbol(text), elf::R_ARM_ABS32)
+                }
+            };
+            let relocation = Relocation {
+                offset: start + offset as u64,
+                symbol: target,
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type },
+            };
+            obj.add_relocation(text, relocation).unwrap();
+        }
+    }
+
+    for (object, symbol) in module.data.iter().zip(data) {
+        let size = 4 * object.size.max(1) as u64;
+        if object.is_zero() {
+            let bss = obj.section_id(StandardSection::UninitializedData);
+            let start = obj.append_section_bss(bss, size, 4);
+            obj.set_symbol_data(symbol, bss, start, size);
+        } else {
+            let section = obj.section_id(if object.readonly { StandardSection::ReadOnlyData } else { StandardSection::Data });
+            let mut bytes: Vec<u8> = object.words.iter().flat_map(|x| x.to_le_bytes()).collect();
+            bytes.resize(usize::try_from(size).unwrap(), 0);
+            let start = obj.append_section_data(section, &bytes, 4);
+            obj.set_symbol_data(symbol, section, start, size);
+        }
+    }
+
+    let attributes = obj.add_section(vec![], b".ARM.attributes".to_vec(), SectionKind::Elf(elf::SHT_ARM_ATTRIBUTES));
+    obj.append_section_data(attributes, &build_attributes(), 1);
+    obj.write().expect("the object is well-formed")
+}
+
+/// The symbol `name`, undefined if the module does not define it.
+fn external(obj: &mut Object, symbols: &mut HashMap<String, SymbolId>, name: String) -> SymbolId {
+    *symbols.entry(name).or_insert_with_key(|name| {
+        obj.add_symbol(Symbol {
+            name: name.as_bytes().to_vec(),
+            value: 0,
+            size: 0,
+            kind: SymbolKind::Unknown,
+            scope: SymbolScope::Dynamic,
+            weak: false,
+            section: SymbolSection::Undefined,
+            flags: SymbolFlags::None,
+        })
+    })
+}
+
+/// Marks code from `offset` on as ARM instructions, `$a`, or data, `$d`, as
+/// the ABI asks for disassemblers and linkers to tell them apart.
+fn add_mapping_symbol(obj: &mut Object, section: object::write::SectionId, offset: u64, is_code: bool) {
+    obj.add_symbol(Symbol {
+        name: if is_code { b"$a".to_vec() } else { b"$d".to_vec() },
+        value: offset,
+        size: 0,
+        kind: SymbolKind::Label,
+        scope: SymbolScope::Compilation,
+        weak: false,
+        section: SymbolSection::Section(section),
+        flags: SymbolFlags::None,
+    });
+}
+
+/// The build attributes of the code: ARMv7-A with `sdiv` and NEON, passing
+/// arguments as the hard-float ABI does.
+fn build_attributes() -> Vec<u8> {
+    const TAG_FILE: u8 = 1;
+    const TAG_CPU_NAME: u8 = 5;
+    const TAG_CPU_ARCH: u8 = 6;
+    const TAG_CPU_ARCH_PROFILE: u8 = 7;
+    const TAG_ARM_ISA_USE: u8 = 8;
+    const TAG_FP_ARCH: u8 = 10;
+    const TAG_ADVANCED_SIMD_ARCH: u8 = 12;
+    const TAG_ABI_VFP_ARGS: u8 = 28;
+    const TAG_DIV_USE: u8 = 44;
+
+    let mut attributes = vec![TAG_CPU_NAME];
+    attributes.extend(b"7-A\0");
+    // ARMv7, the A profile, VFPv3 and NEON, the values all below 128 and
+    // so a byte each
+    attributes.extend([
+        TAG_CPU_ARCH, 10,
+        TAG_CPU_ARCH_PROFILE, b'A',
+        TAG_ARM_ISA_USE, 1,
+        TAG_FP_ARCH, 3,
+        TAG_ADVANCED_SIMD_ARCH, 1,
+        TAG_ABI_VFP_ARGS, 1,
+        TAG_DIV_USE, 2,
+    ]);
+    let mut file = vec![TAG_FILE];
+    file.extend((u32::try_from(attributes.len()).unwrap() + 5).to_le_bytes());
+    file.extend(attributes);
+    let mut vendor = b"aeabi\0".to_vec();
+    vendor.extend(file);
+    let mut section = vec![b'A'];
+    section.extend((u32::try_from(vendor.len()).unwrap() + 4).to_le_bytes());
+    section.extend(vendor);
+    section
+}
+
+/// What a word of code refers to, for the linker to fill in.
+enum Fixup {
+    Call(String),
+    /// A function jumped to, by a `b`.
+    Jump(String),
+    /// The low half of the address of a symbol, in a `movw`.
+    Movw(String),
+    /// The high half, in a `movt`.
+    Movt(String),
+    /// The address of a symbol, in a literal pool.
+    Word(String),
+    /// The address of the block at the given offset into the function.
+    Block(usize),
+}
+
+/// Encodes a function, given where its blocks and literals are found to be.
+struct Encoder<'a> {
+    func: &'a MachineFunc<ArmInst>,
+    code: Vec<u32>,
+    fixups: Vec<(usize, Fixup)>,
+    /// Where code and data start, by offset.
+    mapping: Vec<(usize, bool)>,
+    blocks: Vec<usize>,
+    literals: HashMap<u32, usize>,
+    /// Where the blocks and literals are, as encoded.
+    block_starts: Vec<usize>,
+    literal_starts: HashMap<u32, usize>,
+}
+
+impl<'a> Encoder<'a> {
+    fn new(func: &'a MachineFunc<ArmInst>, blocks: Vec<usize>, literals: HashMap<u32, usize>) -> Encoder<'a> {
+        Encoder {
+            func,
+            code: vec![],
+            fixups: vec![],
+            mapping: vec![(0, true)],
+            blocks,
+            literals,
+            block_starts: vec![],
+            literal_starts: HashMap::new(),
+        }
+    }
+
+    /// The offset of the next word.
+    fn here(&self) -> usize {
+        4 * self.code.len()
+    }
+
+    /// Encodes the function as [`Display`](std::fmt::Display) prints it.
+    fn encode(&mut self) {
+        let func = self.func;
+        let far = branch::out_of_range(func);
+        for (i, block) in func.blocks.iter().enumerate() {
+            self.block_starts.push(self.here());
+            // the block encoded next, empty ones encoding nothing
+            let next = (i + 1..func.blocks.len()).find(|&x| !func.blocks[x].insts.is_empty());
+            for (j, inst) in block.insts.iter().enumerate() {
+                let last = j + 1 == block.insts.len();
+                match *inst {
+                    ArmInst::B { cond, target } if last && cond == Cond::Al && Some(target.0) == next => {}
+                    ArmInst::B { cond, target } if far.contains(&(i, j)) => {
+                        // branched over unless the condition holds
+                        if cond != Cond::Al {
+                            self.code.push(cond_bits(cond.negated()) | 0x0a00_0001);
+                        }
+                        // ldr pc, [pc, #-4]
+                        self.code.push(0xe51f_f004);
+                        self.mapping.push((self.here(), false));
+                        self.fixups.push((self.here(), Fixup::Block(self.block(target))));
+                        self.code.push(0);
+                        self.mapping.push((self.here(), true));
+                    }
+                    _ => self.inst(inst),
+                }
+            }
+        }
+    }
+
+    fn block(&self, block: BlockId) -> usize {
+        self.blocks.get(block.0).copied().unwrap_or(0)
+    }
+
+    /// The offset of `target` from the `pc` an instruction here reads,
+    /// eight bytes ahead.
+    fn pc_relative(&self, target: usize) -> i32 {
+        i32::try_from(target).unwrap() - i32::try_from(self.here() + 8).unwrap()
+    }
+
+    fn inst(&mut self, inst: &ArmInst) {
+        let word = match inst {
+            ArmInst::Mov { cond, dst, src } => data_processing(*cond, 0b1101, false, 0, r(*dst), *src),
+            ArmInst::Mvn { cond, dst, src } => data_processing(*cond, 0b1111, false, 0, r(*dst), *src),
+            ArmInst::Movw { cond, dst, half } | ArmInst::Movt { cond, dst, half } => {
+                let top = matches!(inst, ArmInst::Movt { .. });
+                let imm = match half {
+                    HalfWord::Imm(imm) => u32::from(*imm),
+                    HalfWord::Symbol(symbol) => {
+                        let fixup = if top { Fixup::Movt(symbol.clone()) } else { Fixup::Movw(symbol.clone()) };
+                        self.fixups.push((self.here(), fixup));
+                        0
+                    }
+                };
+                cond_bits(*cond) | 0x0300_0000 | u32::from(top) << 22 | (imm >> 12) << 16 | r(*dst) << 12 | (imm & 0xfff)
+            }
+            ArmInst::LdrLit { cond, dst, label } => {
+                let target = self.literals.get(label).copied().unwrap_or(0);
+                load_store(*cond, true, r(*dst), u32::from(PC), self.pc_relative(target))
+            }
+            ArmInst::Binary { op, cond, set_flags, dst, left, right } => {
+                data_processing(*cond, opcode(*op), *set_flags, r(*left), r(*dst), *right)
+            }
+            ArmInst::Cmp { left, right, neg } => {
+                data_processing(Cond::Al, if *neg { 0b1011 } else { 0b1010 }, true, r(*left), 0, *right)
+            }
+            ArmInst::Mul { dst, left, right } => 0xe000_0090 | r(*dst) << 16 | r(*right) << 8 | r(*left),
+            ArmInst::Mla { dst, left, right, acc, sub } => {
+                let base = if *sub { 0xe060_0090 } else { 0xe020_0090 };
+                base | r(*dst) << 16 | r(*acc) << 12 | r(*right) << 8 | r(*left)
+            }
+            ArmInst::LongMul { signed, lo, hi, left, right } => {
+                let base = if *signed { 0xe0c0_0090 } else { 0xe080_0090 };
+                base | r(*hi) << 16 | r(*lo) << 12 | r(*right) << 8 | r(*left)
+            }
+            ArmInst::Sdiv { dst, left, right } => 0xe710_f010 | r(*dst) << 16 | r(*right) << 8 | r(*left),
+            ArmInst::Ldr { cond, dst, addr } => memory(*cond, true, r(*dst), *addr),
+            ArmInst::Str { cond, src, addr } => memory(*cond, false, r(*src), *addr),
+            ArmInst::Ldrd { lo, addr, .. } => dual(true, r(*lo), *addr),
+            ArmInst::Strd { lo, addr, .. } => dual(false, r(*lo), *addr),
+            ArmInst::Ldm { mode, base, regs } => block_transfer(true, *mode, r(*base), false, regs.iter().map(|&x| r(x))),
+            ArmInst::Stm { mode, base, regs } => block_transfer(false, *mode, r(*base), false, regs.iter().map(|&x| r(x))),
+            ArmInst::VLoad { .. }
+            | ArmInst::VStore { .. }
+            | ArmInst::VBinary { .. }
+            | ArmInst::VMov { .. }
+            | ArmInst::VMovImm { .. }
+            | ArmInst::VDup { .. }
+            | ArmInst::VSetLane { .. }
+            | ArmInst::VGetLane { .. } => vector(inst),
+            ArmInst::B { cond, target } => {
+                let offset = self.pc_relative(self.block(*target));
+                cond_bits(*cond) | 0x0a00_0000 | (offset >> 2).cast_unsigned() & 0x00ff_ffff
+            }
+            ArmInst::Bl { func, .. } => {
+                // the addend, -8 for the pc being read ahead, in words
+                self.fixups.push((self.here(), Fixup::Call(func.clone())));
+                0xebff_fffe
+            }
+            ArmInst::Prologue { regs } => {
+                self.code.push(block_transfer(false, BlockMode::Db, u32::from(SP), true, regs.iter().map(|&x| u32::from(x))));
+                data_processing(Cond::Al, 0b1101, false, 0, u32::from(FP), Operand2::Reg(Reg::Phys(SP)))
+            }
+            ArmInst::Epilogue { regs } => {
+                self.code.push(data_processing(Cond::Al, 0b1101, false, 0, u32::from(SP), Operand2::Reg(Reg::Phys(FP))));
+                block_transfer(true, BlockMode::Ia, u32::from(SP), true, regs.iter().map(|&x| u32::from(x)))
+            }
+            ArmInst::TailCall { func, regs, .. } => {
+                self.code.push(data_processing(Cond::Al, 0b1101, false, 0, u32::from(SP), Operand2::Reg(Reg::Phys(FP))));
+                self.code.push(block_transfer(true, BlockMode::Ia, u32::from(SP), true, regs.iter().map(|&x| u32::from(x))));
+                self.fixups.push((self.here(), Fixup::Jump(func.clone())));
+                0xeaff_fffe
+            }
+            ArmInst::Pool { entries, skip } => {
+                let words = u32::try_from(entries.len()).unwrap();
+                if *skip {
+                    // b over the entries
+                    self.code.push(0xea00_0000 | (words - 1) & 0x00ff_ffff);
+                }
+                self.mapping.push((self.here(), false));
+                for (label, literal) in entries {
+                    self.literal_starts.insert(*label, self.here());
+                    let word = match literal {
+                        Literal::Imm(imm) => imm.cast_unsigned(),
+                        Literal::Symbol(symbol) => {
+                            self.fixups.push((self.here(), Fixup::Word(symbol.clone())));
+                            0
+                        }
+                    };
+                    self.code.push(word);
+                }
+                self.mapping.push((self.here(), true));
+                return;
+            }
+            ArmInst::FrameAddr { .. } | ArmInst::Ret { .. } => unreachable!("frame pseudo-instructions are gone once the frame is lowered"),
+            ArmInst::LoadImm { .. } | ArmInst::LoadAddr { .. } => unreachable!("constants are built by instructions once legalized"),
+            ArmInst::Div { .. } => unreachable!("division is lowered by legalization"),
+        };
+        self.code.push(word);
+    }
+}
+
+/// The opcode field of a data-processing instruction doing `op`.
+fn opcode(op: BinaryOp) -> u32 {
+    match op {
+        BinaryOp::And => 0b0000,
+        BinaryOp::Eor => 0b0001,
+        BinaryOp::Sub => 0b0010,
+        BinaryOp::Rsb => 0b0011,
+        BinaryOp::Add => 0b0100,
+        BinaryOp::Adc => 0b0101,
+        BinaryOp::Sbc => 0b0110,
+        BinaryOp::Orr => 0b1100,
+        BinaryOp::Bic => 0b1110,
+    }
+}
+
+/// The encoding of a NEON instruction.
+fn vector(inst: &ArmInst) -> u32 {
+    match inst {
+        ArmInst::VLoad { dst, addr, align } => vector_memory(true, *dst, *addr, *align),
+        ArmInst::VStore { src, addr, align } => vector_memory(false, *src, *addr, *align),
+        ArmInst::VBinary { op, dst, left, right } => {
+            let base = match op {
+                VectorOp::Add => 0xf220_0840,
+                VectorOp::Sub => 0xf320_0840,
+                VectorOp::Mul => 0xf220_0950,
+                VectorOp::And => 0xf200_0150,
+                VectorOp::Orr => 0xf220_0150,
+            };
+            base | vd(*dst) | vn(*left) | vm(*right)
+        }
+        // vorr with both operands the source
+        ArmInst::VMov { dst, src } => 0xf220_0150 | vd(*dst) | vn(*src) | vm(*src),
+        ArmInst::VMovImm { dst, imm } => {
+            let imm = u32::from(*imm);
+            0xf280_0050 | (imm >> 7) << 24 | (imm >> 4 & 0b111) << 16 | vd(*dst) | (imm & 0xf)
+        }
+        ArmInst::VDup { dst, src } => {
+            let (d, v) = q_parts(*dst);
+            0xeea0_0b10 | v << 16 | r(*src) << 12 | d << 7
+        }
+        ArmInst::VSetLane { dst, lane, src } => {
+            let (d, v, x) = lane_parts(*dst, *lane);
+            0xee00_0b10 | x << 21 | v << 16 | r(*src) << 12 | d << 7
+        }
+        ArmInst::VGetLane { dst, src, lane } => {
+            let (n, v, x) = lane_parts(*src, *lane);
+            0xee10_0b10 | x << 21 | v << 16 | r(*dst) << 12 | n << 7
+        }
+        _ => unreachable!("not a vector instruction"),
+    }
+}
+
+/// The number of a core register.
+fn r(reg: Reg) -> u32 {
+    let Reg::Phys(x) = reg else { unreachable!("registers are allocated before encoding") };
+    u32::from(x)
+}
+
+/// The high bit and the low four bits of the number of the first
+/// doubleword register making up the vector register `reg`.
+fn q_parts(reg: Reg) -> (u32, u32) {
+    let d = 2 * (r(reg) - u32::from(Q0));
+    (d >> 4, d & 0xf)
+}
+
+/// As [`q_parts`], for the doubleword register holding lane `lane`, with the
+/// lane within it.
+fn lane_parts(reg: Reg, lane: u8) -> (u32, u32, u32) {
+    let d = 2 * (r(reg) - u32::from(Q0)) + u32::from(lane / 2);
+    (d >> 4, d & 0xf, u32::from(lane % 2))
+}
+
+/// The destination register field of a NEON instruction on vectors.
+fn vd(reg: Reg) -> u32 {
+    let (d, v) = q_parts(reg);
+    d << 22 | v << 12
+}
+
+/// The first operand register field.
+fn vn(reg: Reg) -> u32 {
+    let (n, v) = q_parts(reg);
+    n << 7 | v << 16
+}
+
+/// The second operand register field.
+fn vm(reg: Reg) -> u32 {
+    let (m, v) = q_parts(reg);
+    m << 5 | v
+}
+
+fn cond_bits(cond: Cond) -> u32 {
+    let bits = match cond {
+        Cond::Eq => 0x0,
+        Cond::Ne => 0x1,
+        Cond::Hs => 0x2,
+        Cond::Lo => 0x3,
+        Cond::Ge => 0xa,
+        Cond::Lt => 0xb,
+        Cond::Gt => 0xc,
+        Cond::Le => 0xd,
+        Cond::Al => 0xe,
+    };
+    bits << 28
+}
+
+fn shift_bits(shift: Shift) -> u32 {
+    match shift {
+        Shift::Lsl => 0b00,
+        Shift::Lsr => 0b01,
+        Shift::Asr => 0b10,
+    }
+}
+
+/// A data-processing instruction, `rd = rn op src`.
+fn data_processing(cond: Cond, opcode: u32, set_flags: bool, rn: u32, rd: u32, src: Operand2) -> u32 {
+    let src = match src {
+        Operand2::Imm(imm) => {
+            // an 8-bit value rotated right by twice the rotation
+            let imm = imm.cast_unsigned();
+            let rot = (0..16).find(|&x| imm.rotate_left(2 * x) <= 0xff).expect("an operand2 immediate");
+            1 << 25 | rot << 8 | imm.rotate_left(2 * rot)
+        }
+        Operand2::Reg(x) => r(x),
+        // a shift by 32 is encoded as by 0
+        Operand2::Shifted(x, shift, amount) => (u32::from(amount) & 0x1f) << 7 | shift_bits(shift) << 5 | r(x),
+        Operand2::RegShifted(x, shift, amount) => r(amount) << 8 | shift_bits(shift) << 5 | 1 << 4 | r(x),
+    };
+    cond_bits(cond) | opcode << 21 | u32::from(set_flags) << 20 | rn << 16 | rd << 12 | src
+}
+
+/// An `ldr` or `str` of `rt` at `rn + offset`, the offset within 4095 bytes.
+fn load_store(cond: Cond, load: bool, rt: u32, rn: u32, offset: i32) -> u32 {
+    cond_bits(cond) | 0x0500_0000 | u32::from(offset >= 0) << 23 | u32::from(load) << 20 | rn << 16 | rt << 12
+        | offset.unsigned_abs()
+}
+
+fn memory(cond: Cond, load: bool, rt: u32, addr: Address) -> u32 {
+    match addr {
+        Address::Imm(base, offset) => load_store(cond, load, rt, r(base), offset),
+        Address::Reg(base, index, shift) => {
+            cond_bits(cond) | 0x0780_0000 | u32::from(load) << 20 | r(base) << 16 | rt << 12 | u32::from(shift) << 7 | r(index)
+        }
+        Address::Frame(..) => unreachable!("frame objects are addressed from fp once the frame is lowered"),
+    }
+}
+
+/// An `ldrd` or `strd` of `rt` and the next register.
+fn dual(load: bool, rt: u32, addr: Address) -> u32 {
+    let op = if load { 0b1101 } else { 0b1111 } << 4;
+    match addr {
+        Address::Imm(base, offset) => {
+            let imm = offset.unsigned_abs();
+            0xe140_0000 | u32::from(offset >= 0) << 23 | r(base) << 16 | rt << 12 | (imm >> 4) << 8 | op | (imm & 0xf)
+        }
+        Address::Reg(base, index, 0) => 0xe180_0000 | r(base) << 16 | rt << 12 | op | r(index),
+        Address::Reg(..) => unreachable!("ldrd and strd take no shifted index"),
+        Address::Frame(..) => unreachable!("frame objects are addressed from fp once the frame is lowered"),
+    }
+}
+
+/// An `ldm` or `stm` of `regs`, writing the stepped address back to the
+/// base if `writeback`, as `push` and `pop` do.
+fn block_transfer(load: bool, mode: BlockMode, base: u32, writeback: bool, regs: impl Iterator<Item = u32>) -> u32 {
+    let (before, up) = match mode {
+        BlockMode::Ia => (false, true),
+        BlockMode::Ib => (true, true),
+        BlockMode::Da => (false, false),
+        BlockMode::Db => (true, false),
+    };
+    let list = regs.fold(0, |list, x| list | 1 << x);
+    0xe800_0000 | u32::from(before) << 24 | u32::from(up) << 23 | u32::from(writeback) << 21 | u32::from(load) << 20
+        | base << 16 | list
+}
+
+/// A `vld1.32` or `vst1.32` of the two doubleword registers making up `reg`,
+/// with the alignment hint `align` meets.
+fn vector_memory(load: bool, reg: Reg, addr: Address, align: u32) -> u32 {
+    let Address::Imm(base, 0) = addr else { unreachable!("vector accesses are off a register once the frame is lowered") };
+    let hint = match align {
+        16.. => 0b10,
+        8 => 0b01,
+        _ => 0b00,
+    };
+    0xf400_0a8f | u32::from(load) << 21 | vd(reg) | r(base) << 16 | hint << 4
+}
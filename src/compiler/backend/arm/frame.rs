@@ -0,0 +1,148 @@
+use crate::compiler::backend::arm::inst::{Address, ArmInst, BinaryOp, Cond, Operand2, FP, IP, LR, PC, SP};
+use crate::compiler::mir::{Frame, FrameObjKind, MachineFunc, Reg};
+use crate::compiler::target::{arm::is_operand2, Target};
+
+/// Widest offset from a base register a load or store takes.
+const MAX_OFFSET: i32 = 4095;
+
+/// Lays out the frame of `func` once its registers are allocated, and
+/// replaces the frame pseudo-instructions with the code they stand for.
+///
+/// The frame pointer points at the registers saved on entry, with the
+/// arguments the caller passed on the stack above them and the frame
+/// objects below, so that each is addressed at a fixed offset from it:
+///
+/// ```text
+/// fp + 4 * n      arguments passed on the stack
+///                 saved registers, the frame pointer and lr last
+/// fp              <- fp
+///                 the canary, with stack protection
+///                 frame objects, the smallest nearest
+/// sp + outgoing   arguments to pass on the stack
+/// sp              <- sp, aligned as the target has it at calls
+/// ```
+///
+/// Small objects come first, so that scalars and spill slots stay in reach
+/// of the offsets loads and stores take however large the arrays are. The
+/// stack pointer is aligned to 8 bytes on entry, and so is the frame
+/// pointer whenever an object needs it, an extra register being saved to
+/// keep their count even.
+pub(super) fn lower(func: &mut MachineFunc<ArmInst>, target: &Target) {
+    let written = func.written_phys_regs();
+    let frame = &mut func.frame;
+    let mut saved: Vec<u8> = target.registers.callee_saved.iter()
+        .copied()
+        .filter(|x| written.contains(x) && ![FP, LR].contains(x))
+        .collect();
+    saved.sort_unstable();
+    saved.extend([FP, LR]);
+    if saved.len() % 2 == 1 && frame.objects.iter().any(|x| x.align > 4) {
+        saved.insert(saved.len() - 1, IP);
+    }
+
+    let pushed = 4 * u32::try_from(saved.len()).unwrap();
+    let mut placed = vec![];
+    for (idx, obj) in frame.objects.iter_mut().enumerate() {
+        match obj.kind {
+            FrameObjKind::Incoming(offset) => obj.offset = Some(i32::try_from(pushed + offset).unwrap()),
+            _ if obj.alias.is_none() => placed.push(idx),
+            _ => {}
+        }
+    }
+    placed.sort_by_key(|&x| (frame.objects[x].kind != FrameObjKind::Canary, frame.objects[x].size));
+    let mut size = 0u32;
+    for idx in placed {
+        let obj = &mut frame.objects[idx];
+        size = (size + obj.size).next_multiple_of(obj.align);
+        obj.offset = Some(-i32::try_from(size).unwrap());
+    }
+    // the stack pointer stays aligned, the saved registers taking 4 bytes
+    // each
+    let size = (pushed + size + frame.outgoing).next_multiple_of(target.calling_convention.stack_align) - pushed;
+
+    let mut restored = saved.clone();
+    *restored.last_mut().unwrap() = PC;
+    for block in &mut func.blocks {
+        let insts = std::mem::take(&mut block.insts);
+        for inst in insts {
+            match inst {
+                ArmInst::Ret { .. } => block.insts.push(ArmInst::Epilogue { regs: restored.clone() }),
+                ArmInst::TailCall { func, args, .. } => block.insts.push(ArmInst::TailCall { func, args, regs: saved.clone() }),
+                ArmInst::FrameAddr { dst, obj, offset } => {
+                    let offset = frame.offset(obj) + offset;
+                    block.insts.extend(add_imm(dst, Reg::Phys(FP), offset));
+                }
+                ArmInst::Ldr { cond, dst, addr } => {
+                    let (addr, fixup) = lower_address(addr, frame);
+                    block.insts.extend(fixup);
+                    block.insts.push(ArmInst::Ldr { cond, dst, addr });
+                }
+                ArmInst::Str { cond, src, addr } => {
+                    let (addr, fixup) = lower_address(addr, frame);
+                    block.insts.extend(fixup);
+                    block.insts.push(ArmInst::Str { cond, src, addr });
+                }
+                ArmInst::VLoad { dst, addr, align } => {
+                    let (addr, fixup) = lower_vector_address(addr, frame);
+                    block.insts.extend(fixup);
+                    block.insts.push(ArmInst::VLoad { dst, addr, align });
+                }
+                ArmInst::VStore { src, addr, align } => {
+                    let (addr, fixup) = lower_vector_address(addr, frame);
+                    block.insts.extend(fixup);
+                    block.insts.push(ArmInst::VStore { src, addr, align });
+                }
+                inst => block.insts.push(inst),
+            }
+        }
+    }
+    let mut entry = vec![ArmInst::Prologue { regs: saved }];
+    if size > 0 {
+        entry.extend(add_imm(Reg::Phys(SP), Reg::Phys(SP), -i32::try_from(size).unwrap()));
+    }
+    func.blocks[0].insts.splice(0..0, entry);
+}
+
+/// `addr` with frame objects addressed from the frame pointer, and the
+/// instructions building an offset too wide for a load or store in `ip`.
+fn lower_address(addr: Address, frame: &Frame) -> (Address, Vec<ArmInst>) {
+    let (base, offset) = match addr {
+        Address::Frame(obj, offset) => (Reg::Phys(FP), frame.offset(obj) + offset),
+        Address::Imm(base, offset) => (base, offset),
+        Address::Reg(..) => return (addr, vec![]),
+    };
+    if (-MAX_OFFSET..=MAX_OFFSET).contains(&offset) {
+        (Address::Imm(base, offset), vec![])
+    } else {
+        (Address::Reg(base, Reg::Phys(IP), 0), vec![ArmInst::LoadImm { cond: Cond::Al, dst: Reg::Phys(IP), imm: offset }])
+    }
+}
+
+/// `addr` as a register alone, as `vld1` and `vst1` take it, and the
+/// instructions computing it in `ip` if it has an offset.
+fn lower_vector_address(addr: Address, frame: &Frame) -> (Address, Vec<ArmInst>) {
+    let (base, offset) = match addr {
+        Address::Frame(obj, offset) => (Reg::Phys(FP), frame.offset(obj) + offset),
+        Address::Imm(base, offset) => (base, offset),
+        Address::Reg(..) => unreachable!("vector accesses are off a single register"),
+    };
+    if offset == 0 {
+        (Address::Imm(base, 0), vec![])
+    } else {
+        (Address::Imm(Reg::Phys(IP), 0), add_imm(Reg::Phys(IP), base, offset))
+    }
+}
+
+/// `dst = base + imm`, building `imm` in `ip` if no `add` or `sub` takes it.
+fn add_imm(dst: Reg, base: Reg, imm: i32) -> Vec<ArmInst> {
+    if is_operand2(imm.cast_unsigned()) {
+        vec![ArmInst::binary(BinaryOp::Add, dst, base, Operand2::Imm(imm))]
+    } else if is_operand2(imm.wrapping_neg().cast_unsigned()) {
+        vec![ArmInst::binary(BinaryOp::Sub, dst, base, Operand2::Imm(imm.wrapping_neg()))]
+    } else {
+        vec![
+            ArmInst::LoadImm { cond: Cond::Al, dst: Reg::Phys(IP), imm },
+            ArmInst::binary(BinaryOp::Add, dst, base, Operand2::Reg(Reg::Phys(IP))),
+        ]
+    }
+}
@@ -0,0 +1,106 @@
+use crate::compiler::backend::arm::inst::{ArmInst, Cond};
+use crate::compiler::mir::{BlockId, MachineFunc, MachineInst};
+
+/// Cycles a mispredicted branch costs, about the length of the pipeline of
+/// the Cortex-A7 to A15.
+const MISPREDICT_PENALTY: f64 = 10.0;
+
+/// Most instructions predicated in place of a branch, which beyond it would
+/// run mostly for nothing however unpredictable the branch.
+const MAX_PREDICATED: usize = 6;
+
+/// Replaces the short arms of branches with instructions predicated on the
+/// condition of the branch, once the frame is lowered.
+///
+/// A block ending in a conditional branch to `then` and a branch to `else`
+/// is merged with `then` if it branches on to `else`, the triangle of an
+/// `if` without `else`, and with both if they branch to the same block, the
+/// diamond of an `if` with `else`, provided that they have no other
+/// predecessor and that each of their instructions can be predicated. The
+/// arms run for nothing when the condition fails, so they are merged only
+/// if that costs less than the branches and their mispredictions would,
+/// which the frequencies of the blocks tell.
+pub(super) fn if_convert(func: &mut MachineFunc<ArmInst>) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let preds = func.preds();
+        for block in func.block_ids() {
+            if let Some(merged) = convert(func, block, &preds) {
+                let insts = &mut func.blocks[block.0].insts;
+                insts.truncate(insts.len() - 2);
+                insts.extend(merged);
+                changed = true;
+                break;
+            }
+        }
+    }
+}
+
+/// The instructions replacing the branches ending `block`, and its arms
+/// emptied, if worth it.
+fn convert(func: &mut MachineFunc<ArmInst>, block: BlockId, preds: &[Vec<BlockId>]) -> Option<Vec<ArmInst>> {
+    let insts = &func.blocks[block.0].insts;
+    let [ref head @ .., ArmInst::B { cond, target: then }, ArmInst::B { cond: Cond::Al, target: other }] = insts[..] else {
+        return None;
+    };
+    // the arms are reached by these branches alone
+    if cond == Cond::Al || head.iter().any(|x| !x.targets().is_empty()) {
+        return None;
+    }
+    let sole_pred = |arm: BlockId| arm != block && preds[arm.0] == [block];
+    // the instructions of `arm` predicated on `cond`, and the block it
+    // branches to after
+    let arm = |arm: BlockId, cond: Cond| -> Option<(Vec<ArmInst>, BlockId)> {
+        let [ref body @ .., ArmInst::B { cond: Cond::Al, target }] = func.blocks[arm.0].insts[..] else {
+            return None;
+        };
+        let body = body.iter().map(|x| x.predicated(cond)).collect::<Option<Vec<_>>>()?;
+        Some((body, target))
+    };
+    // the share of the runs of `of` going through `arm`, even when the
+    // frequencies are unknown
+    let share = |arm: BlockId, of: f64| {
+        let share = func.blocks[arm.0].freq / of;
+        if share.is_finite() { share.clamp(0.0, 1.0) } else { 0.5 }
+    };
+
+    // each arm with how often it runs per run of `block`
+    let (arms, join) = match (arm(then, cond), arm(other, cond.negated())) {
+        (Some((then_body, then_join)), Some((other_body, other_join)))
+            if then_join == other_join && sole_pred(then) && sole_pred(other) =>
+        {
+            let taken = share(then, func.blocks[then.0].freq + func.blocks[other.0].freq);
+            (vec![(then, then_body, taken), (other, other_body, 1.0 - taken)], then_join)
+        }
+        (Some((body, join)), _) if join == other && sole_pred(then) => {
+            (vec![(then, body, share(then, func.blocks[block.0].freq))], other)
+        }
+        (_, Some((body, join))) if join == then && sole_pred(other) => {
+            (vec![(other, body, share(other, func.blocks[block.0].freq))], then)
+        }
+        _ => return None,
+    };
+
+    // cycles per run of `block`: one per instruction of the arms run and
+    // for the branch, and the penalty when it goes the less likely way,
+    // against one per instruction of every arm once merged
+    let predicated: usize = arms.iter().map(|(_, body, _)| body.len()).sum();
+    #[allow(clippy::cast_precision_loss)]
+    let branched = 1.0
+        + arms.iter().map(|(_, body, share)| body.len() as f64 * share).sum::<f64>()
+        + MISPREDICT_PENALTY * arms[0].2.min(1.0 - arms[0].2);
+    #[allow(clippy::cast_precision_loss)]
+    let cost = predicated as f64;
+    if predicated > MAX_PREDICATED || cost > branched {
+        return None;
+    }
+
+    let mut merged = vec![];
+    for (arm, body, _) in arms {
+        merged.extend(body);
+        func.blocks[arm.0].insts.clear();
+    }
+    merged.push(ArmInst::B { cond: Cond::Al, target: join });
+    Some(merged)
+}
@@ -0,0 +1,735 @@
+use std::fmt::{Display, Formatter};
+
+use crate::compiler::mir::{BlockId, FrameObjId, FrameRef, MachineInst, MemAccess, Reg, RegClass, RegisterInfo};
+
+pub const R0: u8 = 0;
+/// The frame pointer, from which frame objects are addressed.
+pub const FP: u8 = 11;
+/// The intra-procedure scratch register, never allocated so that frame
+/// lowering can use it to build offsets too wide for an instruction.
+pub const IP: u8 = 12;
+pub const SP: u8 = 13;
+pub const LR: u8 = 14;
+pub const PC: u8 = 15;
+/// The first of the 128-bit NEON registers `q0` to `q15`, numbered on from
+/// the core registers. Each is the pair of doubleword registers `d2n` and
+/// `d2n+1`.
+pub const Q0: u8 = 16;
+
+/// Registers of the arguments and return value of a call.
+pub const ARG_REGS: u8 = 4;
+
+pub const REGISTER_INFO: RegisterInfo = RegisterInfo {
+    // those the callee saves come first, for values live across calls
+    allocatable: &[4, 5, 6, 7, 8, 9, 10, 0, 1, 2, 3, LR],
+    callee_saved: &[4, 5, 6, 7, 8, 9, 10, FP, LR],
+    // those a callee may overwrite, so that the prologue need not save
+    // `q4` to `q7`
+    vector_allocatable: &[Q0 + 8, Q0 + 9, Q0 + 10, Q0 + 11, Q0 + 12, Q0 + 13, Q0 + 14, Q0 + 15, Q0, Q0 + 1, Q0 + 2, Q0 + 3],
+    vector_bytes: 16,
+};
+
+/// A condition on the flags set by the last comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cond {
+    Al,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Unsigned `>=`, or a carry.
+    Hs,
+    /// Unsigned `<`, or no carry.
+    Lo,
+}
+
+impl Cond {
+    /// The condition holding when this one does not.
+    ///
+    /// # Panics
+    ///
+    /// On [`Cond::Al`], which always holds.
+    #[must_use] pub fn negated(self) -> Cond {
+        match self {
+            Cond::Eq => Cond::Ne,
+            Cond::Ne => Cond::Eq,
+            Cond::Lt => Cond::Ge,
+            Cond::Le => Cond::Gt,
+            Cond::Gt => Cond::Le,
+            Cond::Ge => Cond::Lt,
+            Cond::Hs => Cond::Lo,
+            Cond::Lo => Cond::Hs,
+            Cond::Al => panic!("`al` has no negation"),
+        }
+    }
+
+    /// The suffix of an instruction executed under the condition.
+    #[must_use] pub fn suffix(self) -> &'static str {
+        match self {
+            Cond::Al => "",
+            Cond::Eq => "eq",
+            Cond::Ne => "ne",
+            Cond::Lt => "lt",
+            Cond::Le => "le",
+            Cond::Gt => "gt",
+            Cond::Ge => "ge",
+            Cond::Hs => "hs",
+            Cond::Lo => "lo",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shift {
+    Lsl,
+    Lsr,
+    Asr,
+}
+
+impl Display for Shift {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shift::Lsl => write!(f, "lsl"),
+            Shift::Lsr => write!(f, "lsr"),
+            Shift::Asr => write!(f, "asr"),
+        }
+    }
+}
+
+/// The flexible second operand of data-processing instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand2 {
+    /// An immediate [`is_operand2`](crate::compiler::target::arm::is_operand2)
+    /// accepts.
+    Imm(i32),
+    Reg(Reg),
+    /// A register shifted by 1 to 31 bits.
+    Shifted(Reg, Shift, u8),
+    /// A register shifted by the low byte of another.
+    RegShifted(Reg, Shift, Reg),
+}
+
+impl Operand2 {
+    fn regs(&self) -> Vec<Reg> {
+        match *self {
+            Operand2::Imm(_) => vec![],
+            Operand2::Reg(x) | Operand2::Shifted(x, ..) => vec![x],
+            Operand2::RegShifted(x, _, y) => vec![x, y],
+        }
+    }
+
+    fn map_regs(&mut self, f: &mut dyn FnMut(Reg) -> Reg) {
+        match self {
+            Operand2::Imm(_) => {}
+            Operand2::Reg(x) | Operand2::Shifted(x, ..) => *x = f(*x),
+            Operand2::RegShifted(x, _, y) => {
+                *x = f(*x);
+                *y = f(*y);
+            }
+        }
+    }
+}
+
+/// The address of a load or store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Address {
+    /// `base + offset`, the offset within 4095 bytes either way once the
+    /// frame is lowered.
+    Imm(Reg, i32),
+    /// `base + (index << shift)`.
+    Reg(Reg, Reg, u8),
+    /// `offset` bytes into a frame object.
+    Frame(FrameObjId, i32),
+}
+
+impl Address {
+    fn regs(&self) -> Vec<Reg> {
+        match *self {
+            Address::Imm(base, _) => vec![base],
+            Address::Reg(base, index, _) => vec![base, index],
+            Address::Frame(..) => vec![],
+        }
+    }
+
+    fn map_regs(&mut self, f: &mut dyn FnMut(Reg) -> Reg) {
+        match self {
+            Address::Imm(base, _) => *base = f(*base),
+            Address::Reg(base, index, _) => {
+                *base = f(*base);
+                *index = f(*index);
+            }
+            Address::Frame(..) => {}
+        }
+    }
+}
+
+/// How `ldm` and `stm` step through memory from their base register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockMode {
+    /// From the base up.
+    Ia,
+    /// From the word above the base up.
+    Ib,
+    /// Up to the base.
+    Da,
+    /// Up to the word below the base.
+    Db,
+}
+
+impl BlockMode {
+    #[must_use] pub fn suffix(self) -> &'static str {
+        match self {
+            BlockMode::Ia => "ia",
+            BlockMode::Ib => "ib",
+            BlockMode::Da => "da",
+            BlockMode::Db => "db",
+        }
+    }
+}
+
+/// NEON instructions of the form `dst = left op right` on four words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VectorOp {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Orr,
+}
+
+impl VectorOp {
+    #[must_use] pub fn name(self) -> &'static str {
+        match self {
+            VectorOp::Add => "vadd.i32",
+            VectorOp::Sub => "vsub.i32",
+            VectorOp::Mul => "vmul.i32",
+            VectorOp::And => "vand",
+            VectorOp::Orr => "vorr",
+        }
+    }
+}
+
+/// The divisor of a [`ArmInst::Div`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Divisor {
+    Reg(Reg),
+    Imm(i32),
+}
+
+/// What `movw` or `movt` puts in half of a register.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HalfWord {
+    Imm(u16),
+    /// The same half of the address of a symbol.
+    Symbol(String),
+}
+
+/// A word of a literal pool.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Literal {
+    Imm(i32),
+    /// The address of a symbol.
+    Symbol(String),
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::Imm(imm) => write!(f, "{imm}"),
+            Literal::Symbol(symbol) => write!(f, "{symbol}"),
+        }
+    }
+}
+
+/// Data-processing instructions of the form `dst = left op right`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    /// Add with carry.
+    Adc,
+    Sub,
+    /// Subtract with carry.
+    Sbc,
+    /// Reverse subtract, `right - left`.
+    Rsb,
+    And,
+    Orr,
+    Eor,
+    /// `left & !right`.
+    Bic,
+}
+
+impl BinaryOp {
+    #[must_use] pub fn name(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Adc => "adc",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Sbc => "sbc",
+            BinaryOp::Rsb => "rsb",
+            BinaryOp::And => "and",
+            BinaryOp::Orr => "orr",
+            BinaryOp::Eor => "eor",
+            BinaryOp::Bic => "bic",
+        }
+    }
+}
+
+/// An instruction of ARMv7-A, or a pseudo-instruction standing for a few of
+/// them until the frame is laid out and the function legalized.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArmInst {
+    Mov { cond: Cond, dst: Reg, src: Operand2 },
+    /// Moves the complement of `src`.
+    Mvn { cond: Cond, dst: Reg, src: Operand2 },
+    /// Any 32-bit constant, built with a `mov` or an `mvn` if either takes
+    /// it, else with a `movw` and `movt` pair or loaded from a literal pool.
+    LoadImm { cond: Cond, dst: Reg, imm: i32 },
+    /// The address of a symbol, built with a `movw` and `movt` pair or
+    /// loaded from a literal pool.
+    LoadAddr { dst: Reg, symbol: String },
+    /// Sets the low half of `dst`, clearing the high one.
+    Movw { cond: Cond, dst: Reg, half: HalfWord },
+    /// Sets the high half of `dst`, keeping the low one.
+    Movt { cond: Cond, dst: Reg, half: HalfWord },
+    /// Loads the entry of a literal pool with the given label, which must be
+    /// within 4095 bytes of `pc + 8`.
+    LdrLit { cond: Cond, dst: Reg, label: u32 },
+    Binary { op: BinaryOp, cond: Cond, set_flags: bool, dst: Reg, left: Reg, right: Operand2 },
+    Mul { dst: Reg, left: Reg, right: Reg },
+    /// `acc + left * right`, or `acc - left * right` as an `mls`.
+    Mla { dst: Reg, left: Reg, right: Reg, acc: Reg, sub: bool },
+    /// The 64-bit product of `left` and `right`, as an `smull` or `umull`.
+    LongMul { signed: bool, lo: Reg, hi: Reg, left: Reg, right: Reg },
+    Sdiv { dst: Reg, left: Reg, right: Reg },
+    /// The quotient of signed `left` and `right` rounded towards zero, or
+    /// the remainder if `rem`, computed as the core allows once legalized.
+    Div { rem: bool, dst: Reg, left: Reg, right: Divisor },
+    /// Sets the flags on `left - right`, or on `left + right` as a `cmn`.
+    Cmp { left: Reg, right: Operand2, neg: bool },
+    Ldr { cond: Cond, dst: Reg, addr: Address },
+    Str { cond: Cond, src: Reg, addr: Address },
+    /// Loads the two words at `addr` into `lo` and `hi`, an even register
+    /// and the next, the offset within 255 bytes either way.
+    Ldrd { lo: Reg, hi: Reg, addr: Address },
+    /// Stores `lo` and `hi` as [`ArmInst::Ldrd`] loads them.
+    Strd { lo: Reg, hi: Reg, addr: Address },
+    /// Loads `regs` from consecutive words from `base` as `mode` steps, the
+    /// lowest register from the lowest address.
+    Ldm { mode: BlockMode, base: Reg, regs: Vec<Reg> },
+    /// Stores `regs` as [`ArmInst::Ldm`] loads them.
+    Stm { mode: BlockMode, base: Reg, regs: Vec<Reg> },
+    /// The address `offset` bytes into a frame object.
+    FrameAddr { dst: Reg, obj: FrameObjId, offset: i32 },
+    /// Loads the vector `dst` from `addr`, known to be aligned to `align`
+    /// bytes, with a `vld1` from an address in a register once the frame is
+    /// lowered.
+    VLoad { dst: Reg, addr: Address, align: u32 },
+    /// Stores the vector `src` as [`ArmInst::VLoad`] loads it.
+    VStore { src: Reg, addr: Address, align: u32 },
+    VBinary { op: VectorOp, dst: Reg, left: Reg, right: Reg },
+    /// Copies the vector `src` to `dst`.
+    VMov { dst: Reg, src: Reg },
+    /// Sets every lane of `dst` to `imm`.
+    VMovImm { dst: Reg, imm: u8 },
+    /// Sets every lane of `dst` to the core register `src`.
+    VDup { dst: Reg, src: Reg },
+    /// Sets lane `lane` of `dst` to the core register `src`, keeping the
+    /// others.
+    VSetLane { dst: Reg, lane: u8, src: Reg },
+    /// Copies lane `lane` of `src` to the core register `dst`.
+    VGetLane { dst: Reg, src: Reg, lane: u8 },
+    B { cond: Cond, target: BlockId },
+    /// Calls `func` with its first `args` arguments in `r0` and on, its
+    /// result coming back in `r0`.
+    Bl { func: String, args: u8 },
+    /// Jumps to `func` in place of returning, with its first `args`
+    /// arguments in `r0` and on, for it to return to the caller. Once the
+    /// frame is lowered, first frees it and restores `regs`, the registers
+    /// the prologue saved, the link register last.
+    TailCall { func: String, args: u8, regs: Vec<u8> },
+    /// Returns, with the result in `r0` if `has_val`.
+    Ret { has_val: bool },
+    /// The frame of the function: saves `regs`, including the frame pointer
+    /// and the link register, and points the frame pointer at them.
+    Prologue { regs: Vec<u8> },
+    /// Frees the frame, restores the registers the prologue saved and
+    /// returns.
+    Epilogue { regs: Vec<u8> },
+    /// A literal pool, its entries labelled, with a branch over it if
+    /// control would otherwise run into it.
+    Pool { entries: Vec<(u32, Literal)>, skip: bool },
+}
+
+impl ArmInst {
+    /// A move of register `src` into `dst`.
+    #[must_use] pub fn mov(dst: Reg, src: Reg) -> ArmInst {
+        ArmInst::Mov { cond: Cond::Al, dst, src: Operand2::Reg(src) }
+    }
+
+    /// `dst = left op right`, unconditionally and leaving the flags alone.
+    #[must_use] pub fn binary(op: BinaryOp, dst: Reg, left: Reg, right: Operand2) -> ArmInst {
+        ArmInst::Binary { op, cond: Cond::Al, set_flags: false, dst, left, right }
+    }
+
+    /// The condition the instruction runs under.
+    #[must_use] pub fn cond(&self) -> Cond {
+        match self {
+            ArmInst::Mov { cond, .. }
+            | ArmInst::Mvn { cond, .. }
+            | ArmInst::LoadImm { cond, .. }
+            | ArmInst::Movw { cond, .. }
+            | ArmInst::Movt { cond, .. }
+            | ArmInst::LdrLit { cond, .. }
+            | ArmInst::Binary { cond, .. }
+            | ArmInst::Ldr { cond, .. }
+            | ArmInst::Str { cond, .. }
+            | ArmInst::B { cond, .. } => *cond,
+            _ => Cond::Al,
+        }
+    }
+
+    /// The instruction run only under `cond`, if it can be and runs always.
+    /// Those setting the flags cannot, for the condition to hold throughout.
+    #[must_use] pub fn predicated(&self, cond: Cond) -> Option<ArmInst> {
+        let mut inst = self.clone();
+        match &mut inst {
+            ArmInst::Mov { cond: old, .. }
+            | ArmInst::Mvn { cond: old, .. }
+            | ArmInst::LoadImm { cond: old, .. }
+            | ArmInst::Binary { cond: old, set_flags: false, .. }
+            | ArmInst::Ldr { cond: old, .. }
+            | ArmInst::Str { cond: old, .. } if *old == Cond::Al => *old = cond,
+            _ => return None,
+        }
+        Some(inst)
+    }
+
+    /// Bytes of code the instruction takes, at most.
+    #[must_use] pub fn size(&self) -> usize {
+        match self {
+            // branches as jumps through an address, if out of range
+            ArmInst::LoadImm { .. }
+            | ArmInst::LoadAddr { .. }
+            | ArmInst::Prologue { .. }
+            | ArmInst::Epilogue { .. }
+            | ArmInst::B { cond: Cond::Al, .. } => 8,
+            ArmInst::B { .. } | ArmInst::TailCall { .. } => 12,
+            ArmInst::Pool { entries, skip } => 4 * (entries.len() + usize::from(*skip)),
+            _ => 4,
+        }
+    }
+}
+
+impl MachineInst for ArmInst {
+    fn defs(&self) -> Vec<Reg> {
+        match self {
+            ArmInst::Mov { dst, .. }
+            | ArmInst::Mvn { dst, .. }
+            | ArmInst::LoadImm { dst, .. }
+            | ArmInst::LoadAddr { dst, .. }
+            | ArmInst::Movw { dst, .. }
+            | ArmInst::Movt { dst, .. }
+            | ArmInst::LdrLit { dst, .. }
+            | ArmInst::Binary { dst, .. }
+            | ArmInst::Mul { dst, .. }
+            | ArmInst::Mla { dst, .. }
+            | ArmInst::Sdiv { dst, .. }
+            | ArmInst::Div { dst, .. }
+            | ArmInst::Ldr { dst, .. }
+            | ArmInst::FrameAddr { dst, .. }
+            | ArmInst::VLoad { dst, .. }
+            | ArmInst::VBinary { dst, .. }
+            | ArmInst::VMov { dst, .. }
+            | ArmInst::VMovImm { dst, .. }
+            | ArmInst::VDup { dst, .. }
+            | ArmInst::VSetLane { dst, .. }
+            | ArmInst::VGetLane { dst, .. } => vec![*dst],
+            ArmInst::LongMul { lo, hi, .. } | ArmInst::Ldrd { lo, hi, .. } => vec![*lo, *hi],
+            ArmInst::Ldm { regs, .. } => regs.clone(),
+            ArmInst::Bl { .. } => vec![Reg::Phys(R0)],
+            ArmInst::Prologue { .. } => vec![Reg::Phys(FP), Reg::Phys(SP)],
+            ArmInst::Epilogue { regs } | ArmInst::TailCall { regs, .. } => regs.iter().map(|&x| Reg::Phys(x)).collect(),
+            ArmInst::Cmp { .. }
+            | ArmInst::Str { .. }
+            | ArmInst::Strd { .. }
+            | ArmInst::Stm { .. }
+            | ArmInst::VStore { .. }
+            | ArmInst::B { .. }
+            | ArmInst::Ret { .. }
+            | ArmInst::Pool { .. } => vec![],
+        }
+    }
+
+    fn uses(&self) -> Vec<Reg> {
+        let mut uses = match self {
+            ArmInst::Mov { src, .. } | ArmInst::Mvn { src, .. } => src.regs(),
+            ArmInst::LoadImm { .. }
+            | ArmInst::LoadAddr { .. }
+            | ArmInst::Movw { .. }
+            | ArmInst::LdrLit { .. }
+            | ArmInst::FrameAddr { .. }
+            | ArmInst::VMovImm { .. }
+            | ArmInst::B { .. }
+            | ArmInst::Pool { .. } => vec![],
+            ArmInst::Movt { dst, .. } => vec![*dst],
+            ArmInst::VMov { src, .. } | ArmInst::VDup { src, .. } | ArmInst::VGetLane { src, .. } => vec![*src],
+            ArmInst::VSetLane { dst, src, .. } => vec![*dst, *src],
+            ArmInst::Binary { left, right, .. } | ArmInst::Cmp { left, right, .. } => {
+                std::iter::once(*left).chain(right.regs()).collect()
+            }
+            ArmInst::Mul { left, right, .. }
+            | ArmInst::Sdiv { left, right, .. }
+            | ArmInst::LongMul { left, right, .. }
+            | ArmInst::VBinary { left, right, .. }
+            | ArmInst::Div { left, right: Divisor::Reg(right), .. } => vec![*left, *right],
+            ArmInst::Mla { left, right, acc, .. } => vec![*left, *right, *acc],
+            ArmInst::Div { left, right: Divisor::Imm(_), .. } => vec![*left],
+            ArmInst::Ldr { addr, .. } | ArmInst::Ldrd { addr, .. } | ArmInst::VLoad { addr, .. } => addr.regs(),
+            ArmInst::Str { src, addr, .. } | ArmInst::VStore { src, addr, .. } => std::iter::once(*src).chain(addr.regs()).collect(),
+            ArmInst::Strd { lo, hi, addr } => [*lo, *hi].into_iter().chain(addr.regs()).collect(),
+            ArmInst::Ldm { base, .. } => vec![*base],
+            ArmInst::Stm { base, regs, .. } => std::iter::once(*base).chain(regs.iter().copied()).collect(),
+            ArmInst::Bl { args, .. } => (0..*args).map(Reg::Phys).collect(),
+            ArmInst::TailCall { args, .. } => (0..*args).map(Reg::Phys).chain([Reg::Phys(FP)]).collect(),
+            ArmInst::Ret { has_val } => if *has_val { vec![Reg::Phys(R0)] } else { vec![] },
+            ArmInst::Prologue { regs } => regs.iter().map(|&x| Reg::Phys(x)).chain([Reg::Phys(SP)]).collect(),
+            ArmInst::Epilogue { .. } => vec![Reg::Phys(FP)],
+        };
+        // the old value stays if the condition fails
+        if self.cond() != Cond::Al {
+            uses.extend(self.defs());
+        }
+        uses
+    }
+
+    fn map_regs(&mut self, f: &mut dyn FnMut(Reg) -> Reg) {
+        match self {
+            ArmInst::Mov { dst, src, .. } | ArmInst::Mvn { dst, src, .. } => {
+                src.map_regs(f);
+                *dst = f(*dst);
+            }
+            ArmInst::LoadImm { dst, .. }
+            | ArmInst::LoadAddr { dst, .. }
+            | ArmInst::Movw { dst, .. }
+            | ArmInst::Movt { dst, .. }
+            | ArmInst::LdrLit { dst, .. }
+            | ArmInst::FrameAddr { dst, .. }
+            | ArmInst::VMovImm { dst, .. } => *dst = f(*dst),
+            ArmInst::Binary { dst, left, right, .. } => {
+                *left = f(*left);
+                right.map_regs(f);
+                *dst = f(*dst);
+            }
+            ArmInst::Cmp { left, right, .. } => {
+                *left = f(*left);
+                right.map_regs(f);
+            }
+            ArmInst::Mul { dst, left, right } | ArmInst::Sdiv { dst, left, right } | ArmInst::VBinary { dst, left, right, .. } => {
+                *left = f(*left);
+                *right = f(*right);
+                *dst = f(*dst);
+            }
+            ArmInst::Div { dst, left, right, .. } => {
+                *left = f(*left);
+                if let Divisor::Reg(right) = right {
+                    *right = f(*right);
+                }
+                *dst = f(*dst);
+            }
+            ArmInst::Mla { dst, left, right, acc, .. } => {
+                *left = f(*left);
+                *right = f(*right);
+                *acc = f(*acc);
+                *dst = f(*dst);
+            }
+            ArmInst::LongMul { lo, hi, left, right, .. } => {
+                *left = f(*left);
+                *right = f(*right);
+                *lo = f(*lo);
+                *hi = f(*hi);
+            }
+            ArmInst::Ldr { dst, addr, .. } | ArmInst::VLoad { dst, addr, .. } => {
+                addr.map_regs(f);
+                *dst = f(*dst);
+            }
+            ArmInst::Str { src, addr, .. } | ArmInst::VStore { src, addr, .. } => {
+                *src = f(*src);
+                addr.map_regs(f);
+            }
+            ArmInst::Ldrd { lo, hi, addr } | ArmInst::Strd { lo, hi, addr } => {
+                addr.map_regs(f);
+                *lo = f(*lo);
+                *hi = f(*hi);
+            }
+            ArmInst::Ldm { base, regs, .. } | ArmInst::Stm { base, regs, .. } => {
+                *base = f(*base);
+                for reg in regs {
+                    *reg = f(*reg);
+                }
+            }
+            ArmInst::VMov { dst, src }
+            | ArmInst::VDup { dst, src }
+            | ArmInst::VSetLane { dst, src, .. }
+            | ArmInst::VGetLane { dst, src, .. } => {
+                *src = f(*src);
+                *dst = f(*dst);
+            }
+            ArmInst::B { .. }
+            | ArmInst::Bl { .. }
+            | ArmInst::TailCall { .. }
+            | ArmInst::Ret { .. }
+            | ArmInst::Prologue { .. }
+            | ArmInst::Epilogue { .. }
+            | ArmInst::Pool { .. } => {}
+        }
+    }
+
+    fn clobbers(&self) -> Vec<Reg> {
+        match self {
+            // those the callee need not preserve, of the NEON registers all
+            // but `q4` to `q7`
+            ArmInst::Bl { .. } => [1, 2, 3, IP, LR]
+                .into_iter()
+                .chain(Q0..Q0 + 4)
+                .chain(Q0 + 8..Q0 + 16)
+                .map(Reg::Phys)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn is_rematerializable(&self) -> bool {
+        matches!(
+            self,
+            ArmInst::Mov { cond: Cond::Al, src: Operand2::Imm(_), .. }
+                | ArmInst::Mvn { cond: Cond::Al, src: Operand2::Imm(_), .. }
+                | ArmInst::LoadImm { cond: Cond::Al, .. }
+                | ArmInst::LoadAddr { .. }
+                | ArmInst::FrameAddr { .. }
+                | ArmInst::VMovImm { .. }
+        )
+    }
+
+    fn frame_refs(&self) -> Vec<FrameRef> {
+        match *self {
+            ArmInst::Ldr { addr: Address::Frame(obj, _), .. } | ArmInst::VLoad { addr: Address::Frame(obj, _), .. } => {
+                vec![FrameRef::Load(obj)]
+            }
+            ArmInst::Str { addr: Address::Frame(obj, offset), .. } => vec![FrameRef::Store { obj, offset, size: 4 }],
+            ArmInst::VStore { addr: Address::Frame(obj, offset), .. } => vec![FrameRef::Store { obj, offset, size: 16 }],
+            ArmInst::FrameAddr { obj, .. } => vec![FrameRef::Address(obj)],
+            _ => vec![],
+        }
+    }
+
+    /// Those of the Cortex-A7: a load's result is ready three cycles after
+    /// it issues, a multiply's three or four, and a division's some ten.
+    fn latency(&self) -> u32 {
+        match self {
+            ArmInst::LoadImm { .. } | ArmInst::LoadAddr { .. } => 2,
+            ArmInst::Ldr { .. }
+            | ArmInst::LdrLit { .. }
+            | ArmInst::Ldrd { .. }
+            | ArmInst::Ldm { .. }
+            | ArmInst::Mul { .. }
+            | ArmInst::Mla { .. }
+            | ArmInst::VBinary { .. }
+            | ArmInst::VDup { .. }
+            | ArmInst::VSetLane { .. }
+            | ArmInst::VGetLane { .. } => 3,
+            ArmInst::LongMul { .. } | ArmInst::VLoad { .. } => 4,
+            ArmInst::Sdiv { .. } | ArmInst::Div { .. } => 10,
+            _ => 1,
+        }
+    }
+
+    fn memory(&self) -> Option<MemAccess> {
+        let at = |addr: &Address, size: u32| match *addr {
+            Address::Imm(base, offset) => Some((base, offset, size)),
+            Address::Reg(..) | Address::Frame(..) => None,
+        };
+        let block = |mode: BlockMode, base: Reg, regs: &[Reg]| {
+            let size = 4 * u32::try_from(regs.len()).unwrap();
+            let offset = match mode {
+                BlockMode::Ia => 0,
+                BlockMode::Ib => 4,
+                BlockMode::Da => 4 - size.cast_signed(),
+                BlockMode::Db => -size.cast_signed(),
+            };
+            Some((base, offset, size))
+        };
+        match self {
+            ArmInst::Ldr { addr, .. } => Some(MemAccess { store: false, at: at(addr, 4) }),
+            ArmInst::Str { addr, .. } => Some(MemAccess { store: true, at: at(addr, 4) }),
+            ArmInst::Ldrd { addr, .. } => Some(MemAccess { store: false, at: at(addr, 8) }),
+            ArmInst::Strd { addr, .. } => Some(MemAccess { store: true, at: at(addr, 8) }),
+            ArmInst::VLoad { addr, .. } => Some(MemAccess { store: false, at: at(addr, 16) }),
+            ArmInst::VStore { addr, .. } => Some(MemAccess { store: true, at: at(addr, 16) }),
+            ArmInst::Ldm { mode, base, regs } => Some(MemAccess { store: false, at: block(*mode, *base, regs) }),
+            ArmInst::Stm { mode, base, regs } => Some(MemAccess { store: true, at: block(*mode, *base, regs) }),
+            _ => None,
+        }
+    }
+
+    fn touches_flags(&self) -> bool {
+        self.cond() != Cond::Al
+            || matches!(
+                self,
+                ArmInst::Cmp { .. } | ArmInst::Binary { set_flags: true, .. } | ArmInst::Binary { op: BinaryOp::Adc | BinaryOp::Sbc, .. }
+            )
+    }
+
+    fn is_schedule_barrier(&self) -> bool {
+        matches!(
+            self,
+            ArmInst::B { .. }
+                | ArmInst::Bl { .. }
+                | ArmInst::TailCall { .. }
+                | ArmInst::Ret { .. }
+                | ArmInst::Prologue { .. }
+                | ArmInst::Epilogue { .. }
+                | ArmInst::Pool { .. }
+        )
+    }
+
+    fn as_move(&self) -> Option<(Reg, Reg)> {
+        match self {
+            ArmInst::Mov { cond: Cond::Al, dst, src: Operand2::Reg(src) } | ArmInst::VMov { dst, src } => Some((*dst, *src)),
+            _ => None,
+        }
+    }
+
+    fn targets(&self) -> Vec<BlockId> {
+        match self {
+            ArmInst::B { target, .. } => vec![*target],
+            _ => vec![],
+        }
+    }
+
+    fn map_targets(&mut self, f: &mut dyn FnMut(BlockId) -> BlockId) {
+        if let ArmInst::B { target, .. } = self {
+            *target = f(*target);
+        }
+    }
+
+    fn is_terminator(&self) -> bool {
+        matches!(self, ArmInst::B { cond: Cond::Al, .. } | ArmInst::TailCall { .. } | ArmInst::Ret { .. } | ArmInst::Epilogue { .. })
+    }
+
+    fn load_slot(dst: Reg, slot: FrameObjId, class: RegClass) -> ArmInst {
+        match class {
+            RegClass::General => ArmInst::Ldr { cond: Cond::Al, dst, addr: Address::Frame(slot, 0) },
+            RegClass::Vector => ArmInst::VLoad { dst, addr: Address::Frame(slot, 0), align: 8 },
+        }
+    }
+
+    fn store_slot(src: Reg, slot: FrameObjId, class: RegClass) -> ArmInst {
+        match class {
+            RegClass::General => ArmInst::Str { cond: Cond::Al, src, addr: Address::Frame(slot, 0) },
+            RegClass::Vector => ArmInst::VStore { src, addr: Address::Frame(slot, 0), align: 8 },
+        }
+    }
+}
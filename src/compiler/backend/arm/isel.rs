@@ -0,0 +1,945 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::analysis::{block_freq::BlockFreqs, AnalysisManager};
+use crate::compiler::backend::arm::{
+    inst::{Address, ArmInst, BinaryOp, Cond, Divisor, Operand2, Shift, VectorOp, SP},
+    CodegenError,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{BinaryInstOp, Br, Call, Cast, CastOp, InstKind, RetInst},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::mir::{BlockId, DataObject, FrameObjId, FrameObjKind, MachineBlock, MachineFunc, MachineModule, Reg, RegClass};
+use crate::compiler::pass::tail_call;
+use crate::compiler::target::{arm::is_operand2, Target};
+
+/// Selects the instructions of every function of `module` defined in it.
+/// Vectors of four `i32` are held in NEON registers if `target` has them,
+/// and split into a register a lane otherwise. With `stack_protector`,
+/// functions with local arrays check a canary on return.
+pub(super) fn select(module: &Module, target: &Target, stack_protector: bool) -> Result<MachineModule<ArmInst>, CodegenError> {
+    let mut analyses = AnalysisManager::new();
+    let mut funcs = vec![];
+    for (func_id, func) in module.func_arena.items_iter(module.first_func, None) {
+        if func.first_block.is_some() {
+            funcs.push(FuncSelector::new(module, func_id, func, target, stack_protector, &mut analyses).select()?);
+        }
+    }
+    let data = module.global_arena.items_iter(module.first_global, None)
+        .map(|(_, global)| DataObject::new(global))
+        .collect();
+    Ok(MachineModule { funcs, data })
+}
+
+/// A piece of a value that fits a register: an `i32`, `i1` or pointer, a
+/// half of an `i64` or a lane of a vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Piece {
+    Reg(Reg),
+    Imm(i32),
+}
+
+/// The registers a value of type `ty` is split into, the low half of an
+/// `i64` first.
+fn piece_count(ty: &IrTy) -> Result<usize, CodegenError> {
+    match ty {
+        IrTy::Void => Ok(0),
+        IrTy::Int(bits) if *bits <= 32 => Ok(1),
+        IrTy::Int(64) => Ok(2),
+        IrTy::Ptr(_) => Ok(1),
+        IrTy::Vector(lanes, elem_ty) => Ok(lanes * piece_count(elem_ty)?),
+        _ => Err(CodegenError::Unsupported("values of this type")),
+    }
+}
+
+/// Lanes of the vectors held in NEON registers.
+const NEON_LANES: u8 = 4;
+
+/// The NEON instruction computing `op` lane by lane, if there is one.
+fn vector_op(op: BinaryInstOp) -> Option<VectorOp> {
+    match op {
+        BinaryInstOp::Add => Some(VectorOp::Add),
+        BinaryInstOp::Sub => Some(VectorOp::Sub),
+        BinaryInstOp::Mul => Some(VectorOp::Mul),
+        BinaryInstOp::And => Some(VectorOp::And),
+        BinaryInstOp::Or => Some(VectorOp::Orr),
+        _ => None,
+    }
+}
+
+/// Alignment of `ty` in memory, in bytes: doublewords and vectors are
+/// aligned to 8, as the procedure call standard has them.
+fn align_of(ty: &IrTy) -> u32 {
+    match ty {
+        IrTy::Int(64) | IrTy::Vector(..) => 8,
+        IrTy::Array(_, elem_ty) => align_of(elem_ty),
+        _ => 4,
+    }
+}
+
+struct FuncSelector<'a> {
+    module: &'a Module,
+    func: &'a IrFunc,
+    mfunc: MachineFunc<ArmInst>,
+    blocks: HashMap<BBId, BlockId>,
+    values: HashMap<Operand, Vec<Reg>>,
+    allocas: HashMap<InstId, FrameObjId>,
+    /// Comparisons used only by the branch or select after them in their
+    /// block, which set the flags right before it instead of a register.
+    fused: HashSet<InstId>,
+    /// The machine, whose features say whether vectors of four `i32` are
+    /// held in NEON registers.
+    target: &'a Target,
+    /// The copy of the stack guard checked on return, with stack
+    /// protection.
+    canary: Option<FrameObjId>,
+    /// The block calling `__stack_chk_fail`, once a check needs it.
+    stack_fail: Option<BlockId>,
+    cur: BlockId,
+}
+
+impl<'a> FuncSelector<'a> {
+    fn new(
+        module: &'a Module,
+        func_id: FuncId,
+        func: &'a IrFunc,
+        target: &'a Target,
+        stack_protector: bool,
+        analyses: &mut AnalysisManager,
+    ) -> FuncSelector<'a> {
+        let freqs = analyses.get::<BlockFreqs>(func_id, func);
+        let mut mfunc = MachineFunc::new(&func.name, func.linkage);
+        // a block of its own takes the parameters out of their registers,
+        // as the first block of the IR may be branched back to
+        mfunc.blocks.push(MachineBlock { insts: vec![], freq: 1.0 });
+        let mut blocks = HashMap::new();
+        for bb in func.bb_ids() {
+            blocks.insert(bb, BlockId(mfunc.blocks.len()));
+            mfunc.blocks.push(MachineBlock { insts: vec![], freq: freqs.freq(bb) });
+        }
+        let has_arrays = func.bb_ids()
+            .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None))
+            .any(|(_, inst)| matches!(&inst.kind, InstKind::Alloca(x) if matches!(x.alloca_ty, IrTy::Array(..))));
+        let canary = (stack_protector && has_arrays).then(|| mfunc.frame.add(4, 4, FrameObjKind::Canary));
+        let mut allocas = HashMap::new();
+        let mut fused = HashSet::new();
+        for bb in func.bb_ids() {
+            for (inst_id, inst) in func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None) {
+                match &inst.kind {
+                    InstKind::Alloca(alloca) => {
+                        let size = u32::try_from(alloca.alloca_ty.size_in_words() * 4).unwrap();
+                        allocas.insert(inst_id, mfunc.frame.add(size, align_of(&alloca.alloca_ty), FrameObjKind::Local));
+                    }
+                    InstKind::Binary(binary) if binary.op.is_cmp() && piece_count(&inst.ty).is_ok_and(|x| x == 1) => {
+                        let users = func.users(&inst_id.into());
+                        let fusable = match users[..] {
+                            [user] => {
+                                let user = &func.inst_arena[user];
+                                user.bb == bb && match &user.kind {
+                                    InstKind::Br(Br::Br { cond, .. }) => cond == &Operand::Inst(inst_id),
+                                    InstKind::Select(select) => select.cond == Operand::Inst(inst_id)
+                                        && select.true_val != Operand::Inst(inst_id)
+                                        && select.false_val != Operand::Inst(inst_id),
+                                    _ => false,
+                                }
+                            }
+                            _ => false,
+                        };
+                        if fusable {
+                            fused.insert(inst_id);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        FuncSelector { module, func, mfunc, blocks, values: HashMap::new(), allocas, fused, target, canary, stack_fail: None, cur: BlockId(0) }
+    }
+
+    fn select(mut self) -> Result<MachineFunc<ArmInst>, CodegenError> {
+        let func = self.func;
+        let mut words = 0;
+        for &param_id in &func.params {
+            let ty = self.module.operand_ty(func, &param_id.into());
+            if self.is_neon(&ty) {
+                self.lane_by_lane(param_id.into(), |this| this.param(&param_id.into(), &mut words))?;
+            } else {
+                self.param(&param_id.into(), &mut words)?;
+            }
+        }
+        if let Some(canary) = self.canary {
+            let guard = self.stack_guard();
+            self.emit(ArmInst::Str { cond: Cond::Al, src: guard, addr: Address::Frame(canary, 0) });
+        }
+        let entry = self.block(func.first_block.unwrap());
+        self.emit(ArmInst::B { cond: Cond::Al, target: entry });
+        for bb in func.bb_ids() {
+            self.cur = self.blocks[&bb];
+            let tail = self.tail_call_in(bb);
+            for (inst_id, inst) in func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None) {
+                match &inst.kind {
+                    InstKind::Call(call) if tail == Some(inst_id) => {
+                        // the return after it is left out
+                        self.tail_call(call)?;
+                        break;
+                    }
+                    _ => self.select_inst(inst_id)?,
+                }
+            }
+        }
+        Ok(self.mfunc)
+    }
+
+    /// The call marked [`Call::is_tail`] ending `bb`, if it can be a jump:
+    /// its arguments all fit in registers, so that the caller's frame can
+    /// be freed before it, and the return after it is in `bb` or returns
+    /// nothing, so that no block is left returning its result.
+    fn tail_call_in(&self, bb: BBId) -> Option<InstId> {
+        let (call, ret) = tail_call::tail_call(self.func, bb)?;
+        let InstKind::Call(call_inst) = &self.func.inst_arena[call].kind else {
+            unreachable!()
+        };
+        let words = call_inst.args.iter()
+            .map(|arg| piece_count(&self.module.operand_ty(self.func, arg)))
+            .sum::<Result<usize, _>>()
+            .ok()?;
+        let ret_ty = &self.func.inst_arena[call].ty;
+        let fits = words <= usize::from(self.target.calling_convention.arg_regs) && piece_count(ret_ty).is_ok_and(|x| x <= 1);
+        (call_inst.is_tail && fits && (self.func.inst_arena[ret].bb == bb || *ret_ty == IrTy::Void)).then_some(call)
+    }
+
+    /// Copies the parameter `param` out of the registers or stack words the
+    /// caller passed it in, from the `words`th on.
+    fn param(&mut self, param: &Operand, words: &mut u32) -> Result<(), CodegenError> {
+        for reg in self.regs(param)? {
+            let arg_regs = self.target.calling_convention.arg_regs;
+            if let Some(arg_reg) = u8::try_from(*words).ok().filter(|&x| x < arg_regs) {
+                self.emit(ArmInst::mov(reg, Reg::Phys(arg_reg)));
+            } else {
+                let kind = FrameObjKind::Incoming(4 * (*words - u32::from(arg_regs)));
+                let obj = self.mfunc.frame.add(4, 4, kind);
+                self.emit(ArmInst::Ldr { cond: Cond::Al, dst: reg, addr: Address::Frame(obj, 0) });
+            }
+            *words += 1;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, inst: ArmInst) {
+        self.mfunc.blocks[self.cur.0].insts.push(inst);
+    }
+
+    /// The registers holding the value of an instruction or parameter.
+    fn regs(&mut self, value: &Operand) -> Result<Vec<Reg>, CodegenError> {
+        if let Some(regs) = self.values.get(value) {
+            return Ok(regs.clone());
+        }
+        let ty = self.module.operand_ty(self.func, value);
+        let regs: Vec<_> = if self.is_neon(&ty) {
+            vec![self.mfunc.new_vreg_in(RegClass::Vector)]
+        } else {
+            (0..piece_count(&ty)?).map(|_| self.mfunc.new_vreg()).collect()
+        };
+        self.values.insert(value.clone(), regs.clone());
+        Ok(regs)
+    }
+
+    /// The pieces of `operand`, with addresses built in new registers.
+    fn pieces(&mut self, operand: &Operand) -> Result<Vec<Piece>, CodegenError> {
+        Ok(match operand {
+            Operand::Const(Constant::Int(x)) => vec![Piece::Imm(*x)],
+            // any value does, zero is the cheapest to build
+            Operand::Const(Constant::Undef(ty) | Constant::Poison(ty)) => vec![Piece::Imm(0); piece_count(ty)?],
+            Operand::Const(Constant::Array { .. }) | Operand::BB(_) => unreachable!(),
+            Operand::Global(global_id) => {
+                let dst = self.mfunc.new_vreg();
+                self.emit(ArmInst::LoadAddr { dst, symbol: self.module.global_arena[*global_id].name.clone() });
+                vec![Piece::Reg(dst)]
+            }
+            Operand::Inst(inst_id) if self.allocas.contains_key(inst_id) => {
+                let dst = self.mfunc.new_vreg();
+                self.emit(ArmInst::FrameAddr { dst, obj: self.allocas[inst_id], offset: 0 });
+                vec![Piece::Reg(dst)]
+            }
+            Operand::Inst(_) | Operand::Param(_) if self.is_neon(&self.module.operand_ty(self.func, operand)) => {
+                let src = self.regs(operand)?[0];
+                (0..NEON_LANES)
+                    .map(|lane| {
+                        let dst = self.mfunc.new_vreg();
+                        self.emit(ArmInst::VGetLane { dst, src, lane });
+                        Piece::Reg(dst)
+                    })
+                    .collect()
+            }
+            Operand::Inst(_) | Operand::Param(_) => self.regs(operand)?.into_iter().map(Piece::Reg).collect(),
+        })
+    }
+
+    /// Whether values of type `ty` are held in NEON registers.
+    fn is_neon(&self, ty: &IrTy) -> bool {
+        self.target.features.neon && matches!(ty, IrTy::Vector(lanes, elem_ty) if *lanes == usize::from(NEON_LANES) && **elem_ty == IrTy::int())
+    }
+
+    /// The NEON register holding the vector `operand`, zero if undefined.
+    fn vector(&mut self, operand: &Operand) -> Result<Reg, CodegenError> {
+        match operand {
+            Operand::Const(Constant::Undef(_) | Constant::Poison(_)) => {
+                let dst = self.mfunc.new_vreg_in(RegClass::Vector);
+                self.emit(ArmInst::VMovImm { dst, imm: 0 });
+                Ok(dst)
+            }
+            _ => Ok(self.regs(operand)?[0]),
+        }
+    }
+
+    /// The element `vector` holds in every lane defined, if it is built by
+    /// inserting the same element into undefined lanes from the first on.
+    fn splat_of(&self, vector: &Operand) -> Option<Operand> {
+        let InstKind::InsertElement(insert) = &self.func.inst_arena[*vector.as_inst()?].kind else {
+            return None;
+        };
+        let from_undef = matches!(insert.vector, Operand::Const(Constant::Undef(_) | Constant::Poison(_)));
+        (insert.lane == 0 && from_undef || self.splat_of(&insert.vector).as_ref() == Some(&insert.elem)).then(|| insert.elem.clone())
+    }
+
+    /// Selects what defines the vector `value` held in a NEON register as
+    /// if it were split into a register a lane, then gathers the lanes.
+    fn lane_by_lane(
+        &mut self,
+        value: Operand,
+        select: impl FnOnce(&mut Self) -> Result<(), CodegenError>,
+    ) -> Result<(), CodegenError> {
+        let vector = self.regs(&value)?[0];
+        let lanes: Vec<_> = (0..NEON_LANES).map(|_| self.mfunc.new_vreg()).collect();
+        self.values.insert(value.clone(), lanes.clone());
+        let selected = select(self);
+        self.values.insert(value, vec![vector]);
+        selected?;
+        self.emit(ArmInst::VDup { dst: vector, src: lanes[0] });
+        for (lane, &src) in (1..).zip(&lanes[1..]) {
+            self.emit(ArmInst::VSetLane { dst: vector, lane, src });
+        }
+        Ok(())
+    }
+
+    /// Where the vector at `addr` is, and the alignment it is known to
+    /// have: that of its frame object if it has one, and of its lanes
+    /// otherwise.
+    fn vector_address(&mut self, addr: &Operand) -> Result<(Address, u32), CodegenError> {
+        let address = self.address(addr, 0)?;
+        let align = match address {
+            // more than the frame pointer is aligned to is not kept
+            Address::Frame(obj, _) => self.mfunc.frame.objects[obj.0].align.min(8),
+            _ => 4,
+        };
+        Ok((address, align))
+    }
+
+    /// The single piece of a scalar operand.
+    fn piece(&mut self, operand: &Operand) -> Result<Piece, CodegenError> {
+        Ok(self.pieces(operand)?[0])
+    }
+
+    /// `piece` in a register, building it in a new one if it is a constant.
+    fn reg_of(&mut self, piece: Piece) -> Reg {
+        match piece {
+            Piece::Reg(reg) => reg,
+            Piece::Imm(imm) => {
+                let dst = self.mfunc.new_vreg();
+                self.emit(ArmInst::LoadImm { cond: Cond::Al, dst, imm });
+                dst
+            }
+        }
+    }
+
+    /// `piece` as the second operand of a data-processing instruction.
+    fn operand2(&mut self, piece: Piece) -> Operand2 {
+        match piece {
+            Piece::Imm(imm) if is_operand2(imm.cast_unsigned()) => Operand2::Imm(imm),
+            piece => Operand2::Reg(self.reg_of(piece)),
+        }
+    }
+
+    /// Copies `piece` into `dst`.
+    fn mov_piece(&mut self, dst: Reg, piece: Piece) {
+        match piece {
+            Piece::Reg(src) => self.emit(ArmInst::mov(dst, src)),
+            Piece::Imm(imm) => self.emit(ArmInst::LoadImm { cond: Cond::Al, dst, imm }),
+        }
+    }
+
+    /// Where `addr` points, plus `offset` bytes.
+    fn address(&mut self, addr: &Operand, offset: i32) -> Result<Address, CodegenError> {
+        if let Some(&obj) = addr.as_inst().and_then(|x| self.allocas.get(x)) {
+            return Ok(Address::Frame(obj, offset));
+        }
+        let base = self.piece(addr)?;
+        Ok(Address::Imm(self.reg_of(base), offset))
+    }
+
+    fn block(&self, bb: BBId) -> BlockId {
+        self.blocks[&bb]
+    }
+
+    fn select_inst(&mut self, inst_id: InstId) -> Result<(), CodegenError> {
+        let inst = &self.func.inst_arena[inst_id];
+        let ty = match &inst.kind {
+            InstKind::Store(store) => self.module.operand_ty(self.func, &store.data),
+            _ => inst.ty.clone(),
+        };
+        if self.is_neon(&ty) {
+            return self.select_vector(inst_id);
+        }
+        self.select_scalar(inst_id)
+    }
+
+    /// Selects an instruction computing or storing a vector held in a NEON
+    /// register, as the NEON instruction doing so if there is one.
+    fn select_vector(&mut self, inst_id: InstId) -> Result<(), CodegenError> {
+        let inst = &self.func.inst_arena[inst_id];
+        if let InstKind::Store(store) = &inst.kind {
+            let src = self.vector(&store.data)?;
+            let (addr, align) = self.vector_address(&store.addr)?;
+            self.emit(ArmInst::VStore { src, addr, align });
+            return Ok(());
+        }
+        let dst = self.regs(&inst_id.into())?[0];
+        match &inst.kind {
+            InstKind::Binary(binary) if vector_op(binary.op).is_some() => {
+                let op = vector_op(binary.op).unwrap();
+                let left = self.vector(&binary.left)?;
+                let right = self.vector(&binary.right)?;
+                self.emit(ArmInst::VBinary { op, dst, left, right });
+            }
+            InstKind::Load(load) => {
+                let (addr, align) = self.vector_address(&load.addr)?;
+                self.emit(ArmInst::VLoad { dst, addr, align });
+            }
+            InstKind::InsertElement(insert) if self.splat_of(&inst_id.into()).is_some() => {
+                // a splat built lane by lane is built at once, by the last
+                // insertion, the lanes not inserted yet being undefined
+                let elem = self.splat_of(&inst_id.into());
+                let continued = matches!(self.func.users(&inst_id.into())[..], [user] if self.splat_of(&user.into()) == elem);
+                if !continued {
+                    let src = self.piece(&insert.elem)?;
+                    let src = self.reg_of(src);
+                    self.emit(ArmInst::VDup { dst, src });
+                }
+            }
+            InstKind::InsertElement(insert) => {
+                let elem = self.piece(&insert.elem)?;
+                let elem = self.reg_of(elem);
+                if let Operand::Const(Constant::Undef(_) | Constant::Poison(_)) = insert.vector {
+                    self.emit(ArmInst::VDup { dst, src: elem });
+                } else {
+                    let src = self.vector(&insert.vector)?;
+                    self.emit(ArmInst::VMov { dst, src });
+                    self.emit(ArmInst::VSetLane { dst, lane: u8::try_from(insert.lane).unwrap(), src: elem });
+                }
+            }
+            _ => self.lane_by_lane(inst_id.into(), |this| this.select_scalar(inst_id))?,
+        }
+        Ok(())
+    }
+
+    fn select_scalar(&mut self, inst_id: InstId) -> Result<(), CodegenError> {
+        let inst = &self.func.inst_arena[inst_id];
+        let ty = inst.ty.clone();
+        match &inst.kind {
+            InstKind::Alloca(_) => {}
+            InstKind::Binary(_) if self.fused.contains(&inst_id) => {}
+            InstKind::Binary(binary) => {
+                let dsts = self.regs(&inst_id.into())?;
+                let lefts = self.pieces(&binary.left)?;
+                let rights = self.pieces(&binary.right)?;
+                match &ty {
+                    IrTy::Int(64) => self.binary_wide(binary.op, &dsts, &lefts, &rights)?,
+                    _ => {
+                        for ((dst, left), right) in dsts.into_iter().zip(lefts).zip(rights) {
+                            self.binary(binary.op, dst, left, right);
+                        }
+                    }
+                }
+            }
+            InstKind::Load(load) => {
+                let dsts = self.regs(&inst_id.into())?;
+                for (i, dst) in (0..).zip(dsts) {
+                    let addr = self.address(&load.addr, 4 * i)?;
+                    self.emit(ArmInst::Ldr { cond: Cond::Al, dst, addr });
+                }
+            }
+            InstKind::Store(store) => {
+                let pieces = self.pieces(&store.data)?;
+                for (i, piece) in (0..).zip(pieces) {
+                    let src = self.reg_of(piece);
+                    let addr = self.address(&store.addr, 4 * i)?;
+                    self.emit(ArmInst::Str { cond: Cond::Al, src, addr });
+                }
+            }
+            InstKind::GEP(gep) => {
+                let dst = self.regs(&inst_id.into())?[0];
+                self.gep(dst, &gep.ptr, &gep.indices)?;
+            }
+            InstKind::Cast(cast) => self.cast(inst_id, cast)?,
+            InstKind::Call(call) => self.call(inst_id, call)?,
+            InstKind::MemSet(memset) => {
+                let dst = self.piece(&memset.dst)?;
+                self.call_runtime("memset", &[dst, Piece::Imm(i32::from(memset.byte)), Piece::Imm(byte_len(memset.len))]);
+            }
+            InstKind::MemCpy(memcpy) => {
+                let (dst, src) = (self.piece(&memcpy.dst)?, self.piece(&memcpy.src)?);
+                self.call_runtime("memcpy", &[dst, src, Piece::Imm(byte_len(memcpy.len))]);
+            }
+            InstKind::Select(select) => {
+                let dsts = self.regs(&inst_id.into())?;
+                let trues = self.pieces(&select.true_val)?;
+                let falses = self.pieces(&select.false_val)?;
+                let trues: Vec<_> = trues.into_iter().map(|x| self.operand2(x)).collect();
+                let cond = self.cond(&select.cond)?;
+                for ((dst, true_val), false_val) in dsts.into_iter().zip(trues).zip(falses) {
+                    self.mov_piece(dst, false_val);
+                    self.emit(ArmInst::Mov { cond, dst, src: true_val });
+                }
+            }
+            InstKind::InsertElement(insert) => {
+                let dsts = self.regs(&inst_id.into())?;
+                let lanes = self.pieces(&insert.vector)?;
+                let elem = self.piece(&insert.elem)?;
+                for (i, (dst, lane)) in dsts.into_iter().zip(lanes).enumerate() {
+                    self.mov_piece(dst, if i == insert.lane { elem } else { lane });
+                }
+            }
+            InstKind::Br(Br::Jump { nxt_bb }) => {
+                let target = self.block(*nxt_bb);
+                self.emit(ArmInst::B { cond: Cond::Al, target });
+            }
+            InstKind::Br(Br::Br { cond, true_bb, false_bb }) => {
+                let cond = self.cond(cond)?;
+                let (true_bb, false_bb) = (self.block(*true_bb), self.block(*false_bb));
+                self.emit(ArmInst::B { cond, target: true_bb });
+                self.emit(ArmInst::B { cond: Cond::Al, target: false_bb });
+            }
+            InstKind::Br(Br::Switch { cond, cases, default }) => {
+                let val = self.piece(cond)?;
+                let val = self.reg_of(val);
+                for &(case, bb) in cases {
+                    self.cmp(val, Piece::Imm(case));
+                    let target = self.block(bb);
+                    self.emit(ArmInst::B { cond: Cond::Eq, target });
+                }
+                let target = self.block(*default);
+                self.emit(ArmInst::B { cond: Cond::Al, target });
+            }
+            InstKind::RetInst(ret) => self.ret(ret)?,
+        }
+        Ok(())
+    }
+
+    fn cast(&mut self, inst_id: InstId, cast: &Cast) -> Result<(), CodegenError> {
+        let dsts = self.regs(&inst_id.into())?;
+        let pieces = self.pieces(&cast.ori_val)?;
+        let from = self.module.operand_ty(self.func, &cast.ori_val);
+        match (cast.op, &from, &cast.target_ty) {
+            (CastOp::SExt, IrTy::Int(1), _) => {
+                let src = self.reg_of(pieces[0]);
+                self.emit(ArmInst::binary(BinaryOp::Rsb, dsts[0], src, Operand2::Imm(0)));
+                if let Some(&hi) = dsts.get(1) {
+                    self.emit(ArmInst::mov(hi, dsts[0]));
+                }
+            }
+            (CastOp::SExt, _, IrTy::Int(64)) => {
+                self.mov_piece(dsts[0], pieces[0]);
+                self.emit(ArmInst::Mov { cond: Cond::Al, dst: dsts[1], src: Operand2::Shifted(dsts[0], Shift::Asr, 31) });
+            }
+            (CastOp::ZExt, _, IrTy::Int(64)) => {
+                self.mov_piece(dsts[0], pieces[0]);
+                self.emit(ArmInst::LoadImm { cond: Cond::Al, dst: dsts[1], imm: 0 });
+            }
+            (CastOp::Trunc, _, IrTy::Int(1)) => {
+                let src = self.reg_of(pieces[0]);
+                self.emit(ArmInst::binary(BinaryOp::And, dsts[0], src, Operand2::Imm(1)));
+            }
+            // an `i1` is already held as 0 or 1, and a narrower
+            // integer only keeps the low half of an `i64`
+            _ => {
+                for (dst, piece) in dsts.into_iter().zip(pieces) {
+                    self.mov_piece(dst, piece);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls with the first four words of arguments in `r0`-`r3` and the
+    /// rest on the stack, the result coming back in `r0`.
+    fn call(&mut self, inst_id: InstId, call: &Call) -> Result<(), CodegenError> {
+        let args = self.pass_args(call)?;
+        let callee = &self.module.func_arena[call.func_id];
+        self.emit(ArmInst::Bl { func: callee.name.clone(), args });
+        match self.regs(&inst_id.into())?[..] {
+            [] => {}
+            [dst] => self.emit(ArmInst::mov(dst, Reg::Phys(self.target.calling_convention.result_reg))),
+            _ => return Err(CodegenError::Unsupported("results wider than a word")),
+        }
+        Ok(())
+    }
+
+    /// Checks the canary with stack protection and jumps to the callee of
+    /// `call`, its arguments all in registers, for it to return to the
+    /// caller in place of this function.
+    fn tail_call(&mut self, call: &Call) -> Result<(), CodegenError> {
+        if let Some(canary) = self.canary {
+            self.check_canary(canary);
+        }
+        let args = self.pass_args(call)?;
+        let callee = &self.module.func_arena[call.func_id];
+        self.emit(ArmInst::TailCall { func: callee.name.clone(), args, regs: vec![] });
+        Ok(())
+    }
+
+    /// Moves the arguments of `call` where the callee takes them, returning
+    /// how many are in registers.
+    fn pass_args(&mut self, call: &Call) -> Result<u8, CodegenError> {
+        let mut args = vec![];
+        for arg in &call.args {
+            for piece in self.pieces(arg)? {
+                // constants too, before any argument register is set
+                args.push(self.reg_of(piece));
+            }
+        }
+        let convention = self.target.calling_convention;
+        let (in_regs, on_stack) = args.split_at(args.len().min(usize::from(convention.arg_regs)));
+        for (i, &src) in (0..).zip(on_stack) {
+            self.emit(ArmInst::Str { cond: Cond::Al, src, addr: Address::Imm(Reg::Phys(SP), 4 * i) });
+        }
+        let outgoing = 4 * u32::try_from(on_stack.len()).unwrap();
+        self.mfunc.frame.outgoing = self.mfunc.frame.outgoing.max(outgoing);
+        for (i, &arg) in (0..).zip(in_regs) {
+            self.emit(ArmInst::mov(Reg::Phys(i), arg));
+        }
+        Ok(u8::try_from(in_regs.len()).unwrap())
+    }
+
+    /// Checks the canary with stack protection, moves the result, if any,
+    /// to where the caller expects it, and returns.
+    fn ret(&mut self, ret: &RetInst) -> Result<(), CodegenError> {
+        if let Some(canary) = self.canary {
+            self.check_canary(canary);
+        }
+        if let Some(val) = &ret.val {
+            let pieces = self.pieces(val)?;
+            let [piece] = pieces[..] else {
+                return Err(CodegenError::Unsupported("results wider than a word"));
+            };
+            self.mov_piece(Reg::Phys(self.target.calling_convention.result_reg), piece);
+        }
+        self.emit(ArmInst::Ret { has_val: ret.val.is_some() });
+        Ok(())
+    }
+
+    /// Loads the stack guard, the value the C library picks at random for
+    /// stack protection.
+    fn stack_guard(&mut self) -> Reg {
+        let addr = self.mfunc.new_vreg();
+        self.emit(ArmInst::LoadAddr { dst: addr, symbol: String::from("__stack_chk_guard") });
+        let guard = self.mfunc.new_vreg();
+        self.emit(ArmInst::Ldr { cond: Cond::Al, dst: guard, addr: Address::Imm(addr, 0) });
+        guard
+    }
+
+    /// Compares `canary` with the stack guard it was set to on entry,
+    /// calling `__stack_chk_fail` to abort if a write past the end of an
+    /// array changed it, and goes on in a block of its own otherwise.
+    fn check_canary(&mut self, canary: FrameObjId) {
+        let guard = self.stack_guard();
+        let saved = self.mfunc.new_vreg();
+        self.emit(ArmInst::Ldr { cond: Cond::Al, dst: saved, addr: Address::Frame(canary, 0) });
+        self.emit(ArmInst::Cmp { left: guard, right: Operand2::Reg(saved), neg: false });
+        let fail = *self.stack_fail.get_or_insert_with(|| {
+            // it does not return, and so needs no branch after
+            let call = ArmInst::Bl { func: String::from("__stack_chk_fail"), args: 0 };
+            self.mfunc.blocks.push(MachineBlock { insts: vec![call], freq: 0.0 });
+            BlockId(self.mfunc.blocks.len() - 1)
+        });
+        self.emit(ArmInst::B { cond: Cond::Ne, target: fail });
+        let ok = BlockId(self.mfunc.blocks.len());
+        self.mfunc.blocks.push(MachineBlock { insts: vec![], freq: self.mfunc.blocks[self.cur.0].freq });
+        self.emit(ArmInst::B { cond: Cond::Al, target: ok });
+        self.cur = ok;
+    }
+
+    /// Calls the library function `func` with `args` in `r0` onward.
+    fn call_runtime(&mut self, func: &str, args: &[Piece]) {
+        for (i, &arg) in (0..).zip(args) {
+            self.mov_piece(Reg::Phys(i), arg);
+        }
+        self.emit(ArmInst::Bl { func: String::from(func), args: u8::try_from(args.len()).unwrap() });
+    }
+
+    /// Sets the flags for a branch or select on the `i1` `cond`, returning
+    /// the condition to test them for.
+    fn cond(&mut self, cond: &Operand) -> Result<Cond, CodegenError> {
+        if let Some(inst_id) = cond.as_inst().filter(|x| self.fused.contains(x)) {
+            let InstKind::Binary(binary) = &self.func.inst_arena[*inst_id].kind else {
+                unreachable!()
+            };
+            let (left, right) = (self.piece(&binary.left)?, self.piece(&binary.right)?);
+            return Ok(self.compare(binary.op, left, right));
+        }
+        let cond = self.piece(cond)?;
+        let cond = self.reg_of(cond);
+        self.cmp(cond, Piece::Imm(0));
+        Ok(Cond::Ne)
+    }
+
+    /// Compares `left` and `right`, returning the condition that holds if
+    /// `left op right` does.
+    fn compare(&mut self, op: BinaryInstOp, left: Piece, right: Piece) -> Cond {
+        let (op, left, right) = match (left, right) {
+            (Piece::Imm(_), Piece::Reg(_)) => (op.swapped().unwrap(), right, left),
+            _ => (op, left, right),
+        };
+        let left = self.reg_of(left);
+        self.cmp(left, right);
+        match op {
+            BinaryInstOp::Eq => Cond::Eq,
+            BinaryInstOp::Ne => Cond::Ne,
+            BinaryInstOp::Lt => Cond::Lt,
+            BinaryInstOp::Le => Cond::Le,
+            BinaryInstOp::Gt => Cond::Gt,
+            BinaryInstOp::Ge => Cond::Ge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Sets the flags on `left - right`.
+    fn cmp(&mut self, left: Reg, right: Piece) {
+        match right {
+            Piece::Imm(imm) if !is_operand2(imm.cast_unsigned()) && is_operand2(imm.wrapping_neg().cast_unsigned()) => {
+                self.emit(ArmInst::Cmp { left, right: Operand2::Imm(imm.wrapping_neg()), neg: true });
+            }
+            right => {
+                let right = self.operand2(right);
+                self.emit(ArmInst::Cmp { left, right, neg: false });
+            }
+        }
+    }
+
+    /// `dst = left op right` on 32-bit integers.
+    fn binary(&mut self, op: BinaryInstOp, dst: Reg, left: Piece, right: Piece) {
+        use BinaryInstOp::{AShr, Add, And, Div, Eq, Ge, Gt, LShr, Le, Lt, Mod, Mul, Ne, Or, Shl, Sub};
+        // constants go on the right, where instructions take them
+        let (left, right) = match (left, right) {
+            (Piece::Imm(_), Piece::Reg(_)) if op.is_commutative() => (right, left),
+            _ => (left, right),
+        };
+        match op {
+            Add | Sub => {
+                let (op, right) = match (op, right) {
+                    (_, Piece::Imm(imm)) if !is_operand2(imm.cast_unsigned()) && is_operand2(imm.wrapping_neg().cast_unsigned()) => {
+                        (if op == Add { BinaryOp::Sub } else { BinaryOp::Add }, Piece::Imm(imm.wrapping_neg()))
+                    }
+                    (Add, _) => (BinaryOp::Add, right),
+                    _ => (BinaryOp::Sub, right),
+                };
+                match (op, left) {
+                    (BinaryOp::Sub, Piece::Imm(imm)) if is_operand2(imm.cast_unsigned()) => {
+                        let right = self.reg_of(right);
+                        self.emit(ArmInst::binary(BinaryOp::Rsb, dst, right, Operand2::Imm(imm)));
+                    }
+                    _ => {
+                        let left = self.reg_of(left);
+                        let right = self.operand2(right);
+                        self.emit(ArmInst::binary(op, dst, left, right));
+                    }
+                }
+            }
+            And | Or => {
+                let left = self.reg_of(left);
+                match right {
+                    Piece::Imm(imm) if op == And && !is_operand2(imm.cast_unsigned()) && is_operand2((!imm).cast_unsigned()) => {
+                        self.emit(ArmInst::binary(BinaryOp::Bic, dst, left, Operand2::Imm(!imm)));
+                    }
+                    _ => {
+                        let right = self.operand2(right);
+                        self.emit(ArmInst::binary(if op == And { BinaryOp::And } else { BinaryOp::Orr }, dst, left, right));
+                    }
+                }
+            }
+            Mul => match right {
+                Piece::Imm(imm) if imm > 0 && imm.count_ones() == 1 => {
+                    let left = self.reg_of(left);
+                    let shift = u8::try_from(imm.trailing_zeros()).unwrap();
+                    let src = if shift == 0 { Operand2::Reg(left) } else { Operand2::Shifted(left, Shift::Lsl, shift) };
+                    self.emit(ArmInst::Mov { cond: Cond::Al, dst, src });
+                }
+                _ => {
+                    let (left, right) = (self.reg_of(left), self.reg_of(right));
+                    self.emit(ArmInst::Mul { dst, left, right });
+                }
+            },
+            Div | Mod => {
+                let left = self.reg_of(left);
+                let right = match right {
+                    Piece::Reg(x) => Divisor::Reg(x),
+                    Piece::Imm(x) => Divisor::Imm(x),
+                };
+                self.emit(ArmInst::Div { rem: op == Mod, dst, left, right });
+            }
+            Shl | AShr | LShr => {
+                let shift = match op {
+                    Shl => Shift::Lsl,
+                    AShr => Shift::Asr,
+                    _ => Shift::Lsr,
+                };
+                let left = self.reg_of(left);
+                let src = match right {
+                    Piece::Imm(0) => Operand2::Reg(left),
+                    Piece::Imm(imm @ 1..=31) => Operand2::Shifted(left, shift, u8::try_from(imm).unwrap()),
+                    // poison, any value does
+                    Piece::Imm(_) => Operand2::Imm(0),
+                    Piece::Reg(amount) => Operand2::RegShifted(left, shift, amount),
+                };
+                self.emit(ArmInst::Mov { cond: Cond::Al, dst, src });
+            }
+            Lt | Le | Gt | Ge | Eq | Ne => {
+                let cond = self.compare(op, left, right);
+                self.emit(ArmInst::Mov { cond: Cond::Al, dst, src: Operand2::Imm(0) });
+                self.emit(ArmInst::Mov { cond, dst, src: Operand2::Imm(1) });
+            }
+        }
+    }
+
+    /// `dst = left op right` on 64-bit integers, each as its low and high
+    /// words. Only the operations strength reduction builds are supported.
+    fn binary_wide(&mut self, op: BinaryInstOp, dst: &[Reg], left: &[Piece], right: &[Piece]) -> Result<(), CodegenError> {
+        let (lo, hi) = (dst[0], dst[1]);
+        // constants are 32 bits wide whatever their type, sign-extended here
+        let widen = |pieces: &[Piece]| match *pieces {
+            [Piece::Imm(x)] => vec![Piece::Imm(x), Piece::Imm(x >> 31)],
+            _ => pieces.to_vec(),
+        };
+        let (left, right) = (&widen(left)[..], &widen(right)[..]);
+        let left: Vec<_> = left.iter().map(|&x| self.reg_of(x)).collect();
+        match (op, right) {
+            (BinaryInstOp::Add | BinaryInstOp::Sub, _) => {
+                let right: Vec<_> = right.iter().map(|&x| self.operand2(x)).collect();
+                let (low_op, high_op) = if op == BinaryInstOp::Add { (BinaryOp::Add, BinaryOp::Adc) } else { (BinaryOp::Sub, BinaryOp::Sbc) };
+                self.emit(ArmInst::Binary { op: low_op, cond: Cond::Al, set_flags: true, dst: lo, left: left[0], right: right[0] });
+                self.emit(ArmInst::binary(high_op, hi, left[1], right[1]));
+            }
+            (BinaryInstOp::Mul, _) => {
+                let right: Vec<_> = right.iter().map(|&x| self.reg_of(x)).collect();
+                let cross = self.mfunc.new_vreg();
+                self.emit(ArmInst::LongMul { signed: false, lo, hi: cross, left: left[0], right: right[0] });
+                let partial = self.mfunc.new_vreg();
+                self.emit(ArmInst::Mla { dst: partial, left: left[0], right: right[1], acc: cross, sub: false });
+                self.emit(ArmInst::Mla { dst: hi, left: left[1], right: right[0], acc: partial, sub: false });
+            }
+            (BinaryInstOp::Shl | BinaryInstOp::AShr | BinaryInstOp::LShr, &[Piece::Imm(amount), _]) => {
+                self.shift_wide(op, lo, hi, left[0], left[1], amount);
+            }
+            _ => return Err(CodegenError::Unsupported("this operation on 64-bit integers")),
+        }
+        Ok(())
+    }
+
+    /// Shifts the 64-bit integer `(src_lo, src_hi)` by a constant.
+    fn shift_wide(&mut self, op: BinaryInstOp, lo: Reg, hi: Reg, src_lo: Reg, src_hi: Reg, amount: i32) {
+        let shifted = |reg: Reg, shift: Shift, amount: i32| match amount {
+            0 => Operand2::Reg(reg),
+            1..=31 => Operand2::Shifted(reg, shift, u8::try_from(amount).unwrap()),
+            _ if shift == Shift::Asr => Operand2::Shifted(reg, Shift::Asr, 31),
+            _ => Operand2::Imm(0),
+        };
+        let mov = |dst: Reg, src: Operand2| ArmInst::Mov { cond: Cond::Al, dst, src };
+        let amount = amount.clamp(0, 64);
+        match op {
+            BinaryInstOp::Shl if amount >= 32 => {
+                self.emit(mov(hi, shifted(src_lo, Shift::Lsl, amount - 32)));
+                self.emit(mov(lo, Operand2::Imm(0)));
+            }
+            BinaryInstOp::Shl => {
+                self.emit(mov(hi, shifted(src_hi, Shift::Lsl, amount)));
+                if amount > 0 {
+                    self.emit(ArmInst::binary(BinaryOp::Orr, hi, hi, Operand2::Shifted(src_lo, Shift::Lsr, u8::try_from(32 - amount).unwrap())));
+                }
+                self.emit(mov(lo, shifted(src_lo, Shift::Lsl, amount)));
+            }
+            _ => {
+                let shift = if op == BinaryInstOp::AShr { Shift::Asr } else { Shift::Lsr };
+                if amount >= 32 {
+                    self.emit(mov(lo, shifted(src_hi, shift, amount - 32)));
+                } else {
+                    self.emit(mov(lo, shifted(src_lo, Shift::Lsr, amount)));
+                    if amount > 0 {
+                        self.emit(ArmInst::binary(BinaryOp::Orr, lo, lo, Operand2::Shifted(src_hi, Shift::Lsl, u8::try_from(32 - amount).unwrap())));
+                    }
+                }
+                self.emit(mov(hi, shifted(src_hi, shift, amount)));
+            }
+        }
+    }
+
+    /// `dst = ptr + offset of indices`, as `getelementptr` computes it.
+    fn gep(&mut self, dst: Reg, ptr: &Operand, indices: &[Operand]) -> Result<(), CodegenError> {
+        let mut ty = self.module.operand_ty(self.func, ptr);
+        let mut offset: i32 = 0;
+        let mut scaled = vec![];
+        for index in indices {
+            ty = match ty {
+                IrTy::Ptr(ty) | IrTy::Array(_, ty) | IrTy::Vector(_, ty) => *ty,
+                _ => unreachable!(),
+            };
+            let size = i32::try_from(ty.size_in_words() * 4).unwrap();
+            match self.piece(index)? {
+                Piece::Imm(imm) => offset = offset.wrapping_add(imm.wrapping_mul(size)),
+                Piece::Reg(reg) => scaled.push((reg, size)),
+            }
+        }
+
+        let mut base = if let Some(&obj) = ptr.as_inst().and_then(|x| self.allocas.get(x)) {
+            let base = self.mfunc.new_vreg();
+            self.emit(ArmInst::FrameAddr { dst: base, obj, offset });
+            base
+        } else {
+            let base = self.piece(ptr)?;
+            let base = self.reg_of(base);
+            if offset == 0 {
+                base
+            } else {
+                let sum = self.mfunc.new_vreg();
+                self.binary(BinaryInstOp::Add, sum, Piece::Reg(base), Piece::Imm(offset));
+                sum
+            }
+        };
+        for (index, size) in scaled {
+            let sum = self.mfunc.new_vreg();
+            if size.count_ones() == 1 {
+                let shift = u8::try_from(size.trailing_zeros()).unwrap();
+                let right = if shift == 0 { Operand2::Reg(index) } else { Operand2::Shifted(index, Shift::Lsl, shift) };
+                self.emit(ArmInst::binary(BinaryOp::Add, sum, base, right));
+            } else {
+                let size = self.reg_of(Piece::Imm(size));
+                self.emit(ArmInst::Mla { dst: sum, left: index, right: size, acc: base, sub: false });
+            }
+            base = sum;
+        }
+        self.emit(ArmInst::mov(dst, base));
+        Ok(())
+    }
+}
+
+/// The bytes in `words` words, as the length argument of `memset` and
+/// `memcpy`.
+fn byte_len(words: usize) -> i32 {
+    i32::try_from(words * 4).unwrap()
+}
@@ -0,0 +1,243 @@
+use crate::compiler::backend::arm::{
+    inst::{ArmInst, BinaryOp, Cond, Divisor, HalfWord, Literal, Operand2, Shift, R0},
+    Features,
+};
+use crate::compiler::mir::{MachineFunc, MachineInst, Reg};
+use crate::compiler::pass::strength_reduce::magic;
+use crate::compiler::target::arm::is_operand2;
+
+/// Farthest the end of a literal pool gets from the first load of it. An
+/// `ldr` reaches 4095 bytes from `pc + 8` either way, and loads placed after
+/// the pool are no farther from it than that first one.
+const POOL_RANGE: usize = 4000;
+
+/// Replaces each division of `func` with the instructions computing it:
+/// shifts by a power of two, a multiplication by a "magic number" by any
+/// other constant, and else an `sdiv`, or a call to the run-time library of
+/// the ABI on cores without one. Runs before registers are allocated, for
+/// the call to take its arguments in the registers it needs.
+pub(super) fn lower_division(func: &mut MachineFunc<ArmInst>, features: Features) {
+    for block in 0..func.blocks.len() {
+        let insts = std::mem::take(&mut func.blocks[block].insts);
+        for inst in insts {
+            let lowered = match inst {
+                ArmInst::Div { rem, dst, left, right } => divide(func, rem, dst, left, right, features),
+                inst => vec![inst],
+            };
+            func.blocks[block].insts.extend(lowered);
+        }
+    }
+}
+
+fn divide(func: &mut MachineFunc<ArmInst>, rem: bool, dst: Reg, left: Reg, right: Divisor, features: Features) -> Vec<ArmInst> {
+    match right {
+        Divisor::Imm(imm) if imm != 0 && imm != i32::MIN => divide_by_constant(func, rem, dst, left, imm),
+        Divisor::Imm(imm) => {
+            let right = func.new_vreg();
+            let mut insts = vec![ArmInst::LoadImm { cond: Cond::Al, dst: right, imm }];
+            insts.extend(divide(func, rem, dst, left, Divisor::Reg(right), features));
+            insts
+        }
+        Divisor::Reg(right) if features.hwdiv => {
+            if rem {
+                let quotient = func.new_vreg();
+                vec![
+                    ArmInst::Sdiv { dst: quotient, left, right },
+                    ArmInst::Mla { dst, left: quotient, right, acc: left, sub: true },
+                ]
+            } else {
+                vec![ArmInst::Sdiv { dst, left, right }]
+            }
+        }
+        Divisor::Reg(right) => {
+            // the remainder comes back in `r1`
+            let (callee, result) = if rem { ("__aeabi_idivmod", 1) } else { ("__aeabi_idiv", R0) };
+            vec![
+                ArmInst::mov(Reg::Phys(R0), left),
+                ArmInst::mov(Reg::Phys(1), right),
+                ArmInst::Bl { func: String::from(callee), args: 2 },
+                ArmInst::mov(dst, Reg::Phys(result)),
+            ]
+        }
+    }
+}
+
+/// `x / d` or `x % d` for a constant `d`, neither 0 nor `i32::MIN`.
+fn divide_by_constant(func: &mut MachineFunc<ArmInst>, rem: bool, dst: Reg, x: Reg, d: i32) -> Vec<ArmInst> {
+    let shifted = |reg: Reg, shift: Shift, amount: u32| Operand2::Shifted(reg, shift, u8::try_from(amount).unwrap());
+    let abs = d.unsigned_abs();
+    let mut insts = vec![];
+    if abs == 1 {
+        insts.push(match (rem, d) {
+            (true, _) => ArmInst::Mov { cond: Cond::Al, dst, src: Operand2::Imm(0) },
+            (false, 1) => ArmInst::mov(dst, x),
+            (false, _) => ArmInst::binary(BinaryOp::Rsb, dst, x, Operand2::Imm(0)),
+        });
+        return insts;
+    }
+
+    // the quotient by `abs`, the remainder being the same by `d`
+    let quotient = if abs.is_power_of_two() {
+        // negative `x` is biased by `abs - 1`, for the shift to round
+        // towards zero
+        let k = abs.trailing_zeros();
+        let sign = if k == 1 {
+            x
+        } else {
+            let sign = func.new_vreg();
+            insts.push(ArmInst::Mov { cond: Cond::Al, dst: sign, src: shifted(x, Shift::Asr, 31) });
+            sign
+        };
+        let biased = func.new_vreg();
+        insts.push(ArmInst::binary(BinaryOp::Add, biased, x, shifted(sign, Shift::Lsr, 32 - k)));
+        let quotient = func.new_vreg();
+        insts.push(ArmInst::Mov { cond: Cond::Al, dst: quotient, src: shifted(biased, Shift::Asr, k) });
+        if rem {
+            insts.push(ArmInst::binary(BinaryOp::Sub, dst, x, shifted(quotient, Shift::Lsl, k)));
+            return insts;
+        }
+        quotient
+    } else {
+        // the high word of the product, as strength reduction builds it
+        let (multiplier, shift) = magic(abs);
+        let (factor, lo, hi) = (func.new_vreg(), func.new_vreg(), func.new_vreg());
+        insts.push(ArmInst::LoadImm { cond: Cond::Al, dst: factor, imm: multiplier });
+        insts.push(ArmInst::LongMul { signed: true, lo, hi, left: x, right: factor });
+        let mut quotient = hi;
+        if multiplier < 0 {
+            let sum = func.new_vreg();
+            insts.push(ArmInst::binary(BinaryOp::Add, sum, quotient, Operand2::Reg(x)));
+            quotient = sum;
+        }
+        if shift > 0 {
+            let shifted_quotient = func.new_vreg();
+            insts.push(ArmInst::Mov { cond: Cond::Al, dst: shifted_quotient, src: shifted(quotient, Shift::Asr, shift) });
+            quotient = shifted_quotient;
+        }
+        // floor to truncation: add 1 for negative `x`
+        let rounded = func.new_vreg();
+        insts.push(ArmInst::binary(BinaryOp::Add, rounded, quotient, shifted(x, Shift::Lsr, 31)));
+        if rem {
+            let divisor = func.new_vreg();
+            insts.push(ArmInst::LoadImm { cond: Cond::Al, dst: divisor, imm: abs.cast_signed() });
+            insts.push(ArmInst::Mla { dst, left: rounded, right: divisor, acc: x, sub: true });
+            return insts;
+        }
+        rounded
+    };
+    insts.push(if d > 0 {
+        ArmInst::mov(dst, quotient)
+    } else {
+        ArmInst::binary(BinaryOp::Rsb, dst, quotient, Operand2::Imm(0))
+    });
+    insts
+}
+
+/// Replaces the constants and addresses of `func` with the instructions
+/// building them: a `mov` or an `mvn` if either takes the value, else a
+/// `movw` and `movt` pair if the core has them, else a load from a literal
+/// pool. Runs once the frame is lowered, which builds constants of its own.
+///
+/// Pools go after a block ending in a branch or a return where they can,
+/// and in the middle of a block, with a branch over them, when none is near
+/// enough to the loads.
+pub(super) fn legalize(func: &mut MachineFunc<ArmInst>, features: Features) {
+    let mut literals = vec![];
+    for block in &mut func.blocks {
+        let insts = std::mem::take(&mut block.insts);
+        for inst in insts {
+            match inst {
+                ArmInst::LoadImm { cond, dst, imm } => block.insts.extend(load_imm(cond, dst, imm, features, &mut literals)),
+                ArmInst::LoadAddr { dst, symbol } if features.movw_movt => block.insts.extend([
+                    ArmInst::Movw { cond: Cond::Al, dst, half: HalfWord::Symbol(symbol.clone()) },
+                    ArmInst::Movt { cond: Cond::Al, dst, half: HalfWord::Symbol(symbol) },
+                ]),
+                ArmInst::LoadAddr { dst, symbol } => {
+                    block.insts.push(load_literal(Cond::Al, dst, Literal::Symbol(symbol), &mut literals));
+                }
+                inst => block.insts.push(inst),
+            }
+        }
+    }
+    if !literals.is_empty() {
+        place_pools(func, &literals);
+    }
+}
+
+fn load_imm(cond: Cond, dst: Reg, imm: i32, features: Features, literals: &mut Vec<Literal>) -> Vec<ArmInst> {
+    let bits = imm.cast_unsigned();
+    if is_operand2(bits) {
+        vec![ArmInst::Mov { cond, dst, src: Operand2::Imm(imm) }]
+    } else if is_operand2(!bits) {
+        vec![ArmInst::Mvn { cond, dst, src: Operand2::Imm(!imm) }]
+    } else if features.movw_movt {
+        let low = ArmInst::Movw { cond, dst, half: HalfWord::Imm(u16::try_from(bits & 0xffff).unwrap()) };
+        if bits >> 16 == 0 {
+            vec![low]
+        } else {
+            vec![low, ArmInst::Movt { cond, dst, half: HalfWord::Imm(u16::try_from(bits >> 16).unwrap()) }]
+        }
+    } else {
+        vec![load_literal(cond, dst, Literal::Imm(imm), literals)]
+    }
+}
+
+/// A load of `literal`, labelled by its index in `literals` until the pools
+/// are placed.
+fn load_literal(cond: Cond, dst: Reg, literal: Literal, literals: &mut Vec<Literal>) -> ArmInst {
+    literals.push(literal);
+    ArmInst::LdrLit { cond, dst, label: u32::try_from(literals.len() - 1).unwrap() }
+}
+
+/// Adds literal pools holding what the loads of `func` read, each within
+/// reach of its loads, and labels the loads by the pool entry they read.
+fn place_pools(func: &mut MachineFunc<ArmInst>, literals: &[Literal]) {
+    // the entries of the pool being filled, the address of its first load,
+    // and the block ending in a terminator since, with the address of its end
+    let mut pending: Vec<(u32, Literal)> = vec![];
+    let mut first = 0;
+    let mut barrier: Option<(usize, usize)> = None;
+    let mut next_label = 0;
+    let mut addr = 0;
+    for block in 0..func.blocks.len() {
+        let mut idx = 0;
+        while idx < func.blocks[block].insts.len() {
+            let size = func.blocks[block].insts[idx].size();
+            // room for one more entry and a branch over the pool
+            if !pending.is_empty() && addr + size + 4 * (pending.len() + 2) - first > POOL_RANGE {
+                let entries = std::mem::take(&mut pending);
+                if let Some((at, _)) = barrier.filter(|&(_, end)| end >= first) {
+                    addr += 4 * entries.len();
+                    func.blocks[at].insts.push(ArmInst::Pool { entries, skip: false });
+                } else {
+                    addr += 4 * (entries.len() + 1);
+                    func.blocks[block].insts.insert(idx, ArmInst::Pool { entries, skip: true });
+                    idx += 1;
+                }
+                barrier = None;
+            }
+
+            if let ArmInst::LdrLit { label, .. } = &mut func.blocks[block].insts[idx] {
+                let literal = &literals[*label as usize];
+                *label = if let Some(&(label, _)) = pending.iter().find(|(_, x)| x == literal) {
+                    label
+                } else {
+                    if pending.is_empty() {
+                        first = addr;
+                    }
+                    pending.push((next_label, literal.clone()));
+                    next_label += 1;
+                    next_label - 1
+                };
+            }
+            addr += size;
+            idx += 1;
+        }
+        if func.blocks[block].insts.last().is_some_and(MachineInst::is_terminator) {
+            barrier = Some((block, addr));
+        }
+    }
+    if !pending.is_empty() {
+        func.blocks.last_mut().unwrap().insts.push(ArmInst::Pool { entries: pending, skip: false });
+    }
+}
@@ -0,0 +1,178 @@
+//! The backend for 32-bit ARM, generating assembly for ARMv7-A.
+
+use std::fmt::{Display, Formatter};
+
+use crate::compiler::ir::value::module::Module;
+use crate::compiler::mir::{coloring, layout, regalloc, schedule, slots, MachineModule};
+use crate::compiler::pass::pipeline::OptLevel;
+use crate::compiler::target::{arm::ARMV7A, Target};
+
+mod asm;
+mod branch;
+mod elf;
+mod frame;
+mod ifcvt;
+pub mod inst;
+mod isel;
+mod legalize;
+mod peephole;
+
+use inst::ArmInst;
+
+/// Why a module cannot be compiled for ARM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodegenError {
+    /// The module uses something the backend does not support yet.
+    Unsupported(&'static str),
+}
+
+impl Display for CodegenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodegenError::Unsupported(what) => write!(f, "the ARM backend does not support {what}"),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+/// Optional parts of the architecture the code may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    /// `movw` and `movt`, from version 6T2 of the architecture on. Without
+    /// them, constants no `mov` or `mvn` takes are loaded from literal pools.
+    pub movw_movt: bool,
+    /// `sdiv` in the ARM instruction set, on cores with the virtualization
+    /// extensions. Without it, division calls the run-time library.
+    pub hwdiv: bool,
+    /// The Advanced SIMD extension, NEON, holding vectors of four `i32` in
+    /// its 128-bit registers. Without it, vectors are split into a register
+    /// a lane.
+    pub neon: bool,
+}
+
+impl Default for Features {
+    /// Those of ARMv7-A cores such as the Cortex-A7 and A15.
+    fn default() -> Self {
+        ARMV7A.features
+    }
+}
+
+/// Compiles `module` to machine code for ARM, ready to be printed as
+/// assembly. Registers are allocated by coloring from `O2` on, and spilled
+/// around each instruction below, which compiles faster. From `O1` on, frame
+/// objects never live at once share their memory, the code is tidied by
+/// peephole rewrites and blocks are laid out to fall through on their
+/// hottest edges, and from `O2` on, short arms of branches become
+/// predicated instructions and instructions are scheduled for an in-order
+/// core. Constants too wide for an instruction are built, division computed
+/// and vectors held, as the features of `target` allow. With
+/// `stack_protector`, functions with local arrays check on return that a
+/// write past the end of one did not reach the saved registers.
+///
+/// # Errors
+///
+/// If the module uses something the backend does not support.
+pub fn compile(
+    module: &Module,
+    opt_level: OptLevel,
+    target: &Target,
+    stack_protector: bool,
+) -> Result<MachineModule<ArmInst>, CodegenError> {
+    let mut machine_module = isel::select(module, target, stack_protector)?;
+    for func in &mut machine_module.funcs {
+        legalize::lower_division(func, target.features);
+        if opt_level >= OptLevel::O2 {
+            coloring::allocate(func, &target.registers);
+        } else {
+            regalloc::spill_everywhere(func, &target.registers);
+        }
+        if opt_level >= OptLevel::O1 {
+            slots::share_slots(func);
+        }
+        frame::lower(func, target);
+        if opt_level >= OptLevel::O2 {
+            ifcvt::if_convert(func);
+        }
+        if opt_level >= OptLevel::O1 {
+            peephole::optimize(func);
+        }
+        if opt_level >= OptLevel::O2 {
+            schedule::schedule(func);
+        }
+        if opt_level >= OptLevel::O1 {
+            layout::lay_out(func);
+            branch::invert(func);
+        }
+        legalize::legalize(func, target.features);
+    }
+    Ok(machine_module)
+}
+
+/// `module` as a relocatable ELF object, with the code and data its assembly
+/// would assemble to, for the system linker to take without an assembler.
+#[must_use] pub fn object(module: &MachineModule<ArmInst>) -> Vec<u8> {
+    elf::write(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::read::{Object, ObjectSection, ObjectSymbol, RelocationTarget};
+    use object::{elf, RelocationFlags};
+
+    use crate::compiler::ir_builder::{ir_builder::IrBuilder, name_resolver::NameResolver, type_checker::TypeChecker};
+    use crate::compiler::pass::PassManager;
+    use crate::compiler::syntax::{lexer::Lexer, parser::Parser};
+
+    use super::*;
+
+    /// Each function returning the result of calling the other.
+    const MUTUAL_RECURSION: &str = "
+        int is_odd(int n);
+        int is_even(int n) {
+            if (n == 0) return 1;
+            return is_odd(n - 1);
+        }
+        int is_odd(int n) {
+            if (n == 0) return 0;
+            return is_even(n - 1);
+        }
+        int main() {
+            putint(is_even(10000000));
+            return 0;
+        }";
+
+    /// `source` optimized and compiled at `O2`.
+    fn compile_source(source: &str) -> MachineModule<ArmInst> {
+        let mut program = Parser::new(Lexer::new(source.chars())).parse().unwrap();
+        NameResolver::new().resolve(&mut program).unwrap();
+        let typed = TypeChecker::new().check(&program).unwrap();
+        let mut ir_builder = IrBuilder::new();
+        ir_builder.visit(typed.program()).unwrap();
+        let mut module = ir_builder.ctx.cur_module;
+        PassManager::with_opt_level(OptLevel::O2, Target::default()).run(&mut module);
+        compile(&module, OptLevel::O2, &Target::default(), false).unwrap()
+    }
+
+    #[test]
+    fn tail_calls_are_jumps() {
+        let module = compile_source(MUTUAL_RECURSION);
+        let asm = module.to_string();
+        for callee in ["is_even", "is_odd"] {
+            assert!(asm.lines().any(|x| x.trim() == format!("b {callee}")), "no jump to {callee} in\n{asm}");
+        }
+
+        let object = object(&module);
+        let file = object::File::parse(&*object).unwrap();
+        let mut jumps: Vec<_> = file.sections()
+            .flat_map(|x| x.relocations())
+            .filter(|(_, x)| x.flags() == RelocationFlags::Elf { r_type: elf::R_ARM_JUMP24 })
+            .map(|(_, x)| match x.target() {
+                RelocationTarget::Symbol(symbol) => file.symbol_by_index(symbol).unwrap().name().unwrap().to_owned(),
+                target => panic!("a jump to {target:?}"),
+            })
+            .collect();
+        jumps.sort();
+        assert_eq!(jumps, ["is_even", "is_odd"]);
+    }
+}
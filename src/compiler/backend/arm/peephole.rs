@@ -0,0 +1,315 @@
+use crate::compiler::backend::arm::inst::{Address, ArmInst, BinaryOp, BlockMode, Cond, Operand2, LR, PC, SP};
+use crate::compiler::mir::{MachineFunc, MachineInst, Reg};
+
+/// Widest offset from a base register an `ldrd` or `strd` takes.
+const MAX_PAIR_OFFSET: i32 = 255;
+
+/// Tidies the code of `func` once its registers are allocated and its frame
+/// lowered, within each block:
+///
+/// - copies of a register to itself or back to where it came from go, and
+///   loads of what was just stored become copies of the stored register;
+/// - a comparison with zero of a result just computed goes, the instruction
+///   computing it setting the flags instead, if only `eq` and `ne` test them;
+/// - loads or stores of adjacent words off the same base become an `ldrd`
+///   or `strd`, or an `ldm` or `stm` if their offsets suit one;
+/// - comparisons whose flags are never tested go, and so does the setting
+///   of the flags by arithmetic.
+pub(super) fn optimize(func: &mut MachineFunc<ArmInst>) {
+    func.remove_identity_moves();
+    for block in &mut func.blocks {
+        forward_stores(&mut block.insts);
+        remove_copies_back(&mut block.insts);
+        merge_accesses(&mut block.insts);
+    }
+    let live_out = flags_live_out(func);
+    for (block, live_out) in func.blocks.iter_mut().zip(live_out) {
+        fold_compares(&mut block.insts, live_out);
+        remove_dead_flags(&mut block.insts, live_out);
+    }
+}
+
+/// Whether `inst` tests the flags, under a condition or for its carry.
+fn reads_flags(inst: &ArmInst) -> bool {
+    inst.cond() != Cond::Al || matches!(inst, ArmInst::Binary { op: BinaryOp::Adc | BinaryOp::Sbc, .. })
+}
+
+/// Whether `inst` sets the flags whatever they were, a call leaving them
+/// anything.
+fn writes_flags(inst: &ArmInst) -> bool {
+    matches!(
+        inst,
+        ArmInst::Cmp { .. } | ArmInst::Binary { cond: Cond::Al, set_flags: true, .. } | ArmInst::Bl { .. }
+    )
+}
+
+/// Whether `inst` may write memory a load reads.
+fn writes_memory(inst: &ArmInst) -> bool {
+    matches!(
+        inst,
+        ArmInst::Str { .. } | ArmInst::Strd { .. } | ArmInst::Stm { .. } | ArmInst::VStore { .. } | ArmInst::Bl { .. } | ArmInst::Prologue { .. }
+    )
+}
+
+/// Whether the flags may be tested after each block before being set again.
+fn flags_live_out(func: &MachineFunc<ArmInst>) -> Vec<bool> {
+    // whether each block tests the flags before setting them, and sets them
+    let (gen, kill): (Vec<bool>, Vec<bool>) = func.blocks.iter()
+        .map(|block| match block.insts.iter().find(|x| reads_flags(x) || writes_flags(x)) {
+            Some(inst) => (reads_flags(inst), true),
+            None => (false, false),
+        })
+        .unzip();
+    let succs: Vec<_> = func.block_ids().map(|x| func.succs(x)).collect();
+    let mut live_in = gen.clone();
+    let mut live_out = vec![false; func.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in (0..func.blocks.len()).rev() {
+            live_out[block] = succs[block].iter().any(|x| live_in[x.0]);
+            let new_in = gen[block] || (!kill[block] && live_out[block]);
+            if new_in != live_in[block] {
+                live_in[block] = new_in;
+                changed = true;
+            }
+        }
+    }
+    live_out
+}
+
+/// Replaces a load of the word a store just wrote with a copy of the
+/// register stored, or nothing if it loads into that register.
+fn forward_stores(insts: &mut Vec<ArmInst>) {
+    for i in 0..insts.len() {
+        if let ArmInst::Str { cond: Cond::Al, src, addr: addr @ Address::Imm(base, _) } = insts[i] {
+            for inst in insts.iter_mut().skip(i + 1) {
+                if let ArmInst::Ldr { cond: Cond::Al, dst, addr: loaded } = *inst {
+                    if loaded == addr {
+                        *inst = ArmInst::mov(dst, src);
+                    }
+                }
+                if writes_memory(inst) || inst.defs().iter().chain(&inst.clobbers()).any(|&x| x == src || x == base) {
+                    break;
+                }
+            }
+        }
+    }
+    insts.retain(|x| x.as_move().is_none_or(|(dst, src)| dst != src));
+}
+
+/// Drops copies of a register back to the one it was copied from, neither
+/// having been written since.
+fn remove_copies_back(insts: &mut Vec<ArmInst>) {
+    let mut removed = vec![false; insts.len()];
+    for i in 0..insts.len() {
+        let Some((dst, src)) = insts[i].as_move() else {
+            continue;
+        };
+        for j in i + 1..insts.len() {
+            let inst = &insts[j];
+            if inst.as_move() == Some((src, dst)) {
+                removed[j] = true;
+                continue;
+            }
+            if inst.defs().iter().chain(&inst.clobbers()).any(|&x| x == src || x == dst) {
+                break;
+            }
+        }
+    }
+    let mut removed = removed.into_iter();
+    insts.retain(|_| !removed.next().unwrap());
+}
+
+/// A word loaded or stored off a base register, unconditionally.
+#[derive(Debug, Clone, Copy)]
+struct Access {
+    load: bool,
+    reg: u8,
+    base: Reg,
+    offset: i32,
+}
+
+impl Access {
+    fn new(inst: &ArmInst) -> Option<Access> {
+        let (load, reg, addr) = match *inst {
+            ArmInst::Ldr { cond: Cond::Al, dst, addr } => (true, dst, addr),
+            ArmInst::Str { cond: Cond::Al, src, addr } => (false, src, addr),
+            _ => return None,
+        };
+        match (reg, addr) {
+            (Reg::Phys(reg), Address::Imm(base, offset)) if reg != SP && reg != PC => Some(Access { load, reg, base, offset }),
+            _ => None,
+        }
+    }
+}
+
+/// Merges the loads or stores of adjacent words off the same base register
+/// into as few instructions as it can.
+///
+/// A run of loads, or of stores, one right after another, may be done in
+/// any order as long as the loads write neither the base nor the same
+/// register twice and the stores write different words, so the run is
+/// sorted by address before merging the accesses of adjacent words that
+/// go to registers in increasing order.
+fn merge_accesses(insts: &mut Vec<ArmInst>) {
+    let mut merged = Vec::with_capacity(insts.len());
+    let mut i = 0;
+    while i < insts.len() {
+        let Some(first) = Access::new(&insts[i]) else {
+            merged.push(insts[i].clone());
+            i += 1;
+            continue;
+        };
+        let mut run = vec![first];
+        while let Some(next) = insts.get(i + run.len()).and_then(Access::new) {
+            let fits = next.load == first.load
+                && next.base == first.base
+                && run.iter().all(|x| x.offset != next.offset && (!next.load || x.reg != next.reg))
+                && !(next.load && next.base == Reg::Phys(next.reg));
+            if !fits {
+                break;
+            }
+            run.push(next);
+        }
+        if first.load && first.base == Reg::Phys(first.reg) {
+            run.truncate(1);
+        }
+        let len = run.len();
+        if len > 1 {
+            run.sort_by_key(|x| x.offset);
+            merged.extend(merge_run(&run));
+        } else {
+            merged.push(insts[i].clone());
+        }
+        i += len;
+    }
+    *insts = merged;
+}
+
+/// The instructions doing the accesses of `run`, sorted by address.
+fn merge_run(run: &[Access]) -> Vec<ArmInst> {
+    let mut insts = vec![];
+    let mut start = 0;
+    while start < run.len() {
+        // the accesses of adjacent words from `start`, to increasing registers
+        let mut end = start + 1;
+        while end < run.len() && run[end].offset == run[end - 1].offset + 4 && run[end].reg > run[end - 1].reg {
+            end += 1;
+        }
+        insts.extend(merge_adjacent(&run[start..end]));
+        start = end;
+    }
+    insts
+}
+
+/// The instructions doing the accesses of `run`, of adjacent words to
+/// increasing registers.
+fn merge_adjacent(run: &[Access]) -> Vec<ArmInst> {
+    let Access { load, base, offset, .. } = run[0];
+    let regs: Vec<Reg> = run.iter().map(|x| Reg::Phys(x.reg)).collect();
+    let last = offset + 4 * (i32::try_from(run.len()).unwrap() - 1);
+    let mode = match (offset, last) {
+        (0, _) => Some(BlockMode::Ia),
+        (4, _) => Some(BlockMode::Ib),
+        (_, 0) => Some(BlockMode::Da),
+        (_, -4) => Some(BlockMode::Db),
+        _ => None,
+    };
+    if let (Some(mode), true) = (mode, run.len() > 1) {
+        return vec![if load { ArmInst::Ldm { mode, base, regs } } else { ArmInst::Stm { mode, base, regs } }];
+    }
+
+    let single = |x: &Access| {
+        let addr = Address::Imm(x.base, x.offset);
+        if x.load {
+            ArmInst::Ldr { cond: Cond::Al, dst: Reg::Phys(x.reg), addr }
+        } else {
+            ArmInst::Str { cond: Cond::Al, src: Reg::Phys(x.reg), addr }
+        }
+    };
+    let mut insts = vec![];
+    let mut i = 0;
+    while i < run.len() {
+        let x = run[i];
+        // an even register below `lr` and the next
+        let pair = run.get(i + 1).filter(|y| {
+            x.reg.is_multiple_of(2) && x.reg < LR - 2 && y.reg == x.reg + 1 && (-MAX_PAIR_OFFSET..=MAX_PAIR_OFFSET).contains(&x.offset)
+        });
+        if let Some(y) = pair {
+            let (lo, hi, addr) = (Reg::Phys(x.reg), Reg::Phys(y.reg), Address::Imm(base, x.offset));
+            insts.push(if load { ArmInst::Ldrd { lo, hi, addr } } else { ArmInst::Strd { lo, hi, addr } });
+            i += 2;
+        } else {
+            insts.push(single(&x));
+            i += 1;
+        }
+    }
+    insts
+}
+
+/// Drops comparisons with zero of a register an arithmetic instruction
+/// wrote, setting the flags there instead, if they are only tested for
+/// equality before being set again. The flags of `subs` and the like agree
+/// with those of `cmp #0` on `z` alone.
+fn fold_compares(insts: &mut Vec<ArmInst>, live_out: bool) {
+    let mut i = 0;
+    while i < insts.len() {
+        let ArmInst::Cmp { left, right: Operand2::Imm(0), .. } = insts[i] else {
+            i += 1;
+            continue;
+        };
+        // the instruction writing `left`, nothing touching it or the flags since
+        let def = (0..i).rev().find(|&j| insts[j].defs().contains(&left) || insts[j].clobbers().contains(&left));
+        let clean = |j: usize| insts[j + 1..i].iter().all(|x| !reads_flags(x) && !writes_flags(x));
+        let folds = def.filter(|&j| clean(j)).filter(|&j| {
+            matches!(
+                insts[j],
+                ArmInst::Binary {
+                    op: BinaryOp::Add | BinaryOp::Sub | BinaryOp::Rsb | BinaryOp::And | BinaryOp::Orr | BinaryOp::Eor | BinaryOp::Bic,
+                    cond: Cond::Al,
+                    set_flags: false,
+                    ..
+                }
+            )
+        });
+        // the flags only tested for equality until set again
+        let mut tested = insts[i + 1..].iter().take_while(|x| !writes_flags(x)).filter(|x| reads_flags(x));
+        let equality = tested.all(|x| matches!(x.cond(), Cond::Eq | Cond::Ne) && !matches!(x, ArmInst::Binary { op: BinaryOp::Adc | BinaryOp::Sbc, .. }));
+        let reset = insts[i + 1..].iter().any(writes_flags);
+        match folds {
+            Some(j) if equality && (reset || !live_out) => {
+                if let ArmInst::Binary { set_flags, .. } = &mut insts[j] {
+                    *set_flags = true;
+                }
+                insts.remove(i);
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Drops comparisons whose flags are never tested, and stops arithmetic
+/// from setting flags never tested.
+fn remove_dead_flags(insts: &mut Vec<ArmInst>, live_out: bool) {
+    let mut live = live_out;
+    let mut removed = vec![false; insts.len()];
+    for (i, inst) in insts.iter_mut().enumerate().rev() {
+        if !live {
+            match inst {
+                ArmInst::Cmp { .. } => {
+                    removed[i] = true;
+                    continue;
+                }
+                ArmInst::Binary { op, cond: Cond::Al, set_flags, .. } if *set_flags && !matches!(op, BinaryOp::Adc | BinaryOp::Sbc) => {
+                    *set_flags = false;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        live = reads_flags(inst) || (live && !writes_flags(inst));
+    }
+    let mut removed = removed.into_iter();
+    insts.retain(|_| !removed.next().unwrap());
+}
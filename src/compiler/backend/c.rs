@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{BinaryInstOp, Br, CastOp, InstKind},
+        module::Module,
+        ty::IrTy,
+        value::{Linkage, Operand},
+    },
+};
+
+/// Keywords of C99, which no name in the source may be printed as.
+const KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum",
+    "extern", "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return",
+    "short", "signed", "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void",
+    "volatile", "while", "_Bool", "_Complex", "_Imaginary", "bool", "true", "false",
+];
+
+/// Prefix of the helpers the output defines for itself.
+const PREFIX: &str = "racoon_";
+
+/// A module as C99 source, for a C compiler to build on targets racoon has
+/// no backend for, or to compare what the IR means with what C compilers
+/// make of the same program.
+///
+/// Each function is printed as a C function of the same name, with a label
+/// per block and `goto`s between them, values as local variables named
+/// after the source variables they came from, and allocas as local arrays.
+/// Pointers keep the type they point to, so that `getelementptr` is printed
+/// as indexing:
+///
+/// ```text
+/// %a.idx = getelementptr [2 x [3 x i32]], [2 x [3 x i32]]* %a.addr, i32 0, i32 %i, i32 2
+/// ```
+///
+/// becomes `a_idx = &a[i][2];`.
+///
+/// Arithmetic wraps as it does in the IR, by computing it unsigned, and
+/// vectors are structs of their lanes, operated on one lane at a time. The
+/// output relies on nothing but `int` being 32 bits wide and conversions to
+/// signed types wrapping, as they do in every compiler in use.
+#[derive(Debug, Clone, Copy)]
+pub struct CSource<'a> {
+    module: &'a Module,
+}
+
+impl<'a> CSource<'a> {
+    #[must_use] pub fn new(module: &'a Module) -> CSource<'a> {
+        CSource { module }
+    }
+}
+
+impl Display for CSource<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let module = self.module;
+        let names = global_names(module);
+        writeln!(f, "#include <stdbool.h>")?;
+        writeln!(f, "#include <stdint.h>")?;
+        writeln!(f)?;
+
+        let funcs: Vec<_> = module.func_arena.items_iter(module.first_func, None).collect();
+        let vector_tys: Vec<_> = funcs.iter()
+            .flat_map(|(_, func)| func.inst_arena.values())
+            .filter_map(|inst| match &inst.ty {
+                IrTy::Ptr(ty) => matches!(**ty, IrTy::Vector(..)).then(|| (**ty).clone()),
+                ty => matches!(ty, IrTy::Vector(..)).then(|| ty.clone()),
+            })
+            .unique()
+            .collect();
+        for ty in &vector_tys {
+            let IrTy::Vector(lanes, elem_ty) = ty else {
+                unreachable!()
+            };
+            writeln!(f, "typedef struct {{ {}; }} {};", decl(elem_ty, &format!("lane[{lanes}]")), decl(ty, ""))?;
+        }
+        let uses_mem = |is_memset: bool| funcs.iter()
+            .flat_map(|(_, func)| func.inst_arena.values())
+            .any(|x| matches!((&x.kind, is_memset), (InstKind::MemSet(_), true) | (InstKind::MemCpy(_), false)));
+        if uses_mem(true) {
+            writeln!(f, "static void {PREFIX}memset(void *dst, int byte, uint32_t len) {{")?;
+            writeln!(f, "    for (uint8_t *d = dst; len--; ) *d++ = (uint8_t)byte;")?;
+            writeln!(f, "}}")?;
+        }
+        if uses_mem(false) {
+            writeln!(f, "static void {PREFIX}memcpy(void *dst, const void *src, uint32_t len) {{")?;
+            writeln!(f, "    const uint8_t *s = src;")?;
+            writeln!(f, "    for (uint8_t *d = dst; len--; ) *d++ = *s++;")?;
+            writeln!(f, "}}")?;
+        }
+        if !vector_tys.is_empty() || uses_mem(true) || uses_mem(false) {
+            writeln!(f)?;
+        }
+
+        // every function is declared first, as calls may come before the
+        // definition of the callee
+        for &(func_id, func) in &funcs {
+            let params = func.params.iter()
+                .map(|&x| decl(&func.param_arena[x].ty, ""))
+                .join(", ");
+            let params = if params.is_empty() { String::from("void") } else { params };
+            writeln!(f, "{}{};", linkage(func.linkage), decl(&func.ret_ty, &format!("{}({params})", names.funcs[&func_id])))?;
+        }
+        writeln!(f)?;
+
+        for (global_id, global) in module.global_arena.items_iter(module.first_global, None) {
+            let ty = IrTy::deptr_of(&global.ty).unwrap();
+            // not `const` even if it is, as its address is passed to
+            // functions taking pointers to what they do not write to
+            let name = &names.values[&Operand::Global(global_id)];
+            writeln!(f, "{}{} = {};", linkage(global.linkage), decl(&ty, name), initializer(&global.init_val))?;
+        }
+        if module.first_global.is_some() {
+            writeln!(f)?;
+        }
+
+        for (func_id, func) in funcs {
+            if func.first_block.is_some() {
+                FuncPrinter::new(module, func, &names).fmt_func(f, &names.funcs[&func_id])?;
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Names of the functions and globals, as they are printed.
+struct GlobalNames {
+    funcs: HashMap<FuncId, String>,
+    values: HashMap<Operand, String>,
+    taken: HashSet<String>,
+}
+
+/// Names functions and globals after themselves, unless C reserves the name
+/// for itself or for the headers included, in which case it gets a `_`
+/// appended. Runtime functions are linked by name and keep theirs.
+fn global_names(module: &Module) -> GlobalNames {
+    let mut names = GlobalNames { funcs: HashMap::new(), values: HashMap::new(), taken: HashSet::new() };
+    for (func_id, func) in module.func_arena.items_iter(module.first_func, None) {
+        let name = if func.is_builtin || func.name == "main" {
+            func.name.clone()
+        } else {
+            unique(&sanitize(&func.name), &names.taken)
+        };
+        names.taken.insert(name.clone());
+        names.funcs.insert(func_id, name);
+    }
+    for (global_id, global) in module.global_arena.items_iter(module.first_global, None) {
+        let name = unique(&sanitize(&global.name), &names.taken);
+        names.taken.insert(name.clone());
+        names.values.insert(Operand::Global(global_id), name);
+    }
+    names
+}
+
+/// `name` with the characters C does not allow in names replaced by `_`.
+fn sanitize(name: &str) -> String {
+    let name: String = name.chars().map(|x| if x.is_ascii_alphanumeric() { x } else { '_' }).collect();
+    if name.starts_with(|x: char| x.is_ascii_digit()) { format!("_{name}") } else { name }
+}
+
+/// `name`, changed to be reserved neither by C nor by the output, and not
+/// in `taken`: prefixed with `x` if it starts like a reserved name, with `_`
+/// appended if it is one, and then numbered as `name_1`, `name_2`, ...
+fn unique(name: &str, taken: &HashSet<String>) -> String {
+    let reserved_prefix = |x: &str| x.starts_with(PREFIX)
+        || x.starts_with("__")
+        || x.starts_with('_') && x[1..].starts_with(|x: char| x.is_ascii_uppercase())
+        || x.strip_prefix("vec").is_some_and(|x| x.trim_start_matches(|x: char| x.is_ascii_digit()).starts_with('_'));
+    let reserved = |x: &str| KEYWORDS.contains(&x)
+        // <stdint.h> claims `intN_t` and macros such as `INT32_MAX`
+        || x.ends_with("_t")
+        || x.starts_with(|x: char| x.is_ascii_uppercase()) && (x.ends_with("_MAX") || x.ends_with("_MIN") || x.ends_with("_C"));
+    let mut base = if reserved_prefix(name) { format!("x{name}") } else { name.to_string() };
+    if reserved(&base) {
+        base.push('_');
+    }
+    let mut name = base.clone();
+    for i in 1.. {
+        if !taken.contains(&name) {
+            break;
+        }
+        name = format!("{base}_{i}");
+    }
+    name
+}
+
+/// `static ` for functions and globals only visible in their own module.
+fn linkage(linkage: Linkage) -> &'static str {
+    match linkage {
+        Linkage::External => "",
+        Linkage::Internal => "static ",
+    }
+}
+
+/// The C type of an integer `bits` wide.
+fn int_ty(bits: usize) -> String {
+    if bits == 1 { String::from("bool") } else { format!("int{bits}_t") }
+}
+
+/// A declaration of `name` as a `ty`, as C writes it: `int32_t (*p)[3]` for
+/// a pointer to an array of 3 `i32`. An abstract declarator, to name the
+/// type alone, if `name` is empty.
+fn decl(ty: &IrTy, name: &str) -> String {
+    let with_name = |base: &str| if name.is_empty() { base.to_string() } else { format!("{base} {name}") };
+    match ty {
+        IrTy::Void => with_name("void"),
+        IrTy::Int(bits) => with_name(&int_ty(*bits)),
+        IrTy::Vector(lanes, elem_ty) => with_name(&format!("vec{lanes}_{elem_ty}")),
+        IrTy::Ptr(ty) if matches!(**ty, IrTy::Array(..)) => decl(ty, &format!("(*{name})")),
+        IrTy::Ptr(ty) => decl(ty, &format!("*{name}")),
+        IrTy::Array(len, ty) => decl(ty, &format!("{name}[{len}]")),
+        IrTy::Label | IrTy::Func(_) => unreachable!(),
+    }
+}
+
+/// An integer constant as a C expression.
+fn int_literal(val: i32) -> String {
+    // `-2147483648` is the negation of a literal too large for an `int`
+    if val == i32::MIN { String::from("INT32_MIN") } else { val.to_string() }
+}
+
+/// The initializer of a global holding `val`.
+fn initializer(val: &Constant) -> String {
+    match val {
+        Constant::Int(x) => int_literal(*x),
+        Constant::Array { elems, .. } if elems.is_empty() => String::from("{0}"),
+        Constant::Array { elems, .. } => format!("{{{}}}", elems.iter().map(initializer).join(", ")),
+        Constant::Undef(ty) | Constant::Poison(ty) => match ty {
+            IrTy::Int(_) => String::from("0"),
+            _ => String::from("{0}"),
+        },
+    }
+}
+
+/// Prints one function, with its own names for its values on top of those
+/// of the module.
+struct FuncPrinter<'a> {
+    module: &'a Module,
+    func: &'a IrFunc,
+    func_names: &'a HashMap<FuncId, String>,
+    names: HashMap<Operand, String>,
+    labels: HashMap<BBId, String>,
+}
+
+impl<'a> FuncPrinter<'a> {
+    fn new(module: &'a Module, func: &'a IrFunc, globals: &'a GlobalNames) -> FuncPrinter<'a> {
+        let mut taken = globals.taken.clone();
+        let mut names = globals.values.clone();
+        let mut cnt = 0;
+        let mut name = |operand: Operand, names: &mut HashMap<Operand, String>| {
+            // the variable is the memory it lives in, not its address
+            let name = if let Some(name) = func.value_names.get(&operand) {
+                sanitize(name.strip_suffix(".addr").unwrap_or(name))
+            } else {
+                cnt += 1;
+                format!("t{cnt}")
+            };
+            let name = unique(&name, &taken);
+            taken.insert(name.clone());
+            names.insert(operand, name);
+        };
+        for &param_id in &func.params {
+            name(param_id.into(), &mut names);
+        }
+        let mut labels = HashMap::new();
+        for (i, bb) in func.bb_ids().enumerate() {
+            labels.insert(bb, format!("bb{i}"));
+            for (inst_id, inst) in func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None) {
+                if inst.ty != IrTy::Void && !matches!(inst.kind, InstKind::Store(_) | InstKind::Br(_) | InstKind::RetInst(_)) {
+                    name(inst_id.into(), &mut names);
+                }
+            }
+        }
+        FuncPrinter { module, func, func_names: &globals.funcs, names, labels }
+    }
+
+    fn fmt_func(&self, f: &mut Formatter<'_>, name: &str) -> std::fmt::Result {
+        let func = self.func;
+        let params = func.params.iter()
+            .map(|&x| decl(&func.param_arena[x].ty, &self.names[&x.into()]))
+            .join(", ");
+        let params = if params.is_empty() { String::from("void") } else { params };
+        writeln!(f, "{}{} {{", linkage(func.linkage), decl(&func.ret_ty, &format!("{name}({params})")))?;
+
+        // every variable is declared up front, as jumps may skip over where
+        // it is first assigned
+        let insts: Vec<_> = func.bb_ids()
+            .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None))
+            .collect();
+        for &(inst_id, inst) in &insts {
+            let Some(name) = self.names.get(&inst_id.into()) else {
+                continue;
+            };
+            match &inst.kind {
+                InstKind::Alloca(alloca) => writeln!(f, "    {};", decl(&alloca.alloca_ty, name))?,
+                _ => writeln!(f, "    {};", decl(&inst.ty, name))?,
+            }
+        }
+
+        let targets: HashSet<_> = func.bb_ids().flat_map(|x| func.succs(x)).collect();
+        for bb in func.bb_ids() {
+            if targets.contains(&bb) {
+                writeln!(f, "{}:", self.labels[&bb])?;
+            }
+            for (inst_id, _) in func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None) {
+                for stmt in self.stmts(inst_id) {
+                    writeln!(f, "    {stmt}")?;
+                }
+            }
+        }
+        writeln!(f, "}}")
+    }
+
+    /// The statements `inst_id` is printed as.
+    fn stmts(&self, inst_id: InstId) -> Vec<String> {
+        let inst = &self.func.inst_arena[inst_id];
+        let dst = self.names.get(&inst_id.into());
+        let assign = |val: String| vec![format!("{} = {val};", dst.unwrap())];
+        match &inst.kind {
+            InstKind::Alloca(_) => vec![],
+            InstKind::Binary(binary) => {
+                let ty = self.module.operand_ty(self.func, &binary.left);
+                let ty = if let Operand::Const(_) = binary.left { self.module.operand_ty(self.func, &binary.right) } else { ty };
+                let (left, right) = (self.operand(&binary.left), self.operand(&binary.right));
+                match ty {
+                    IrTy::Vector(lanes, elem_ty) => (0..lanes)
+                        .map(|i| {
+                            let lane = |x: &str| format!("{x}.lane[{i}]");
+                            format!("{} = {};", lane(dst.unwrap()), binary_expr(binary.op, &elem_ty, &lane(&left), &lane(&right)))
+                        })
+                        .collect(),
+                    ty => assign(binary_expr(binary.op, &ty, &left, &right)),
+                }
+            }
+            InstKind::Load(load) => assign(self.deref(&load.addr)),
+            InstKind::Store(store) => vec![format!("{} = {};", self.deref(&store.addr), self.operand(&store.data))],
+            InstKind::GEP(gep) => {
+                let (base, indices) = match (&gep.ptr, gep.indices.split_first()) {
+                    // `(&a)[0]` is `a`
+                    (Operand::Global(_) | Operand::Inst(_), Some((Operand::Const(Constant::Int(0)), rest))) if self.is_storage(&gep.ptr) => {
+                        (self.names[&gep.ptr].clone(), rest)
+                    }
+                    (ptr, _) => (self.operand(ptr), &gep.indices[..]),
+                };
+                if indices.is_empty() {
+                    return assign(format!("&{base}"));
+                }
+                let indices = indices.iter().map(|x| format!("[{}]", self.operand(x))).join("");
+                assign(format!("&{base}{indices}"))
+            }
+            InstKind::Cast(cast) => {
+                let val = self.operand(&cast.ori_val);
+                let from = self.module.operand_ty(self.func, &cast.ori_val);
+                let (IrTy::Int(from), IrTy::Int(to)) = (&from, &cast.target_ty) else {
+                    return assign(format!("({}){val}", decl(&cast.target_ty, "")));
+                };
+                let to_ty = int_ty(*to);
+                assign(match cast.op {
+                    CastOp::ZExt if *from == 1 => format!("({to_ty}){val}"),
+                    CastOp::ZExt => format!("({to_ty})(u{}){val}", int_ty(*from)),
+                    CastOp::SExt if *from == 1 => format!("-({to_ty}){val}"),
+                    CastOp::Trunc if *to == 1 => format!("({val} & 1)"),
+                    CastOp::SExt | CastOp::Bitcast | CastOp::Trunc => format!("({to_ty}){val}"),
+                })
+            }
+            InstKind::Call(call) => {
+                let name = &self.func_names[&call.func_id];
+                let args = call.args.iter().map(|x| self.operand(x)).join(", ");
+                match dst {
+                    Some(_) => assign(format!("{name}({args})")),
+                    None => vec![format!("{name}({args});")],
+                }
+            }
+            InstKind::MemSet(memset) => {
+                vec![format!("{PREFIX}memset({}, {}, {});", self.operand(&memset.dst), memset.byte, memset.len * 4)]
+            }
+            InstKind::MemCpy(memcpy) => {
+                vec![format!("{PREFIX}memcpy({}, {}, {});", self.operand(&memcpy.dst), self.operand(&memcpy.src), memcpy.len * 4)]
+            }
+            InstKind::Select(select) => {
+                let [cond, true_val, false_val] = [&select.cond, &select.true_val, &select.false_val].map(|x| self.operand(x));
+                assign(format!("{cond} ? {true_val} : {false_val}"))
+            }
+            InstKind::InsertElement(insert) => {
+                let dst = dst.unwrap();
+                vec![
+                    format!("{dst} = {};", self.operand(&insert.vector)),
+                    format!("{dst}.lane[{}] = {};", insert.lane, self.operand(&insert.elem)),
+                ]
+            }
+            InstKind::Br(br) => {
+                let goto = |bb: &BBId| format!("goto {};", self.labels[bb]);
+                match br {
+                    Br::Br { cond, true_bb, false_bb } => {
+                        vec![format!("if ({}) {}", self.operand(cond), goto(true_bb)), goto(false_bb)]
+                    }
+                    Br::Jump { nxt_bb } => vec![goto(nxt_bb)],
+                    Br::Switch { cond, cases, default } => {
+                        let mut stmts = vec![format!("switch ({}) {{", self.operand(cond))];
+                        stmts.extend(cases.iter().map(|(val, bb)| format!("case {}: {}", int_literal(*val), goto(bb))));
+                        stmts.push(format!("default: {}", goto(default)));
+                        stmts.push(String::from("}"));
+                        stmts
+                    }
+                }
+            }
+            InstKind::RetInst(ret) => match &ret.val {
+                Some(val) => vec![format!("return {};", self.operand(val))],
+                None => vec![String::from("return;")],
+            },
+        }
+    }
+
+    /// Whether `operand` is the address of an alloca or a global, which are
+    /// printed as the variable they allocate.
+    fn is_storage(&self, operand: &Operand) -> bool {
+        match operand {
+            Operand::Global(_) => true,
+            Operand::Inst(x) => matches!(self.func.inst_arena[*x].kind, InstKind::Alloca(_)),
+            _ => false,
+        }
+    }
+
+    /// `operand` as a C expression.
+    fn operand(&self, operand: &Operand) -> String {
+        match operand {
+            _ if self.is_storage(operand) => format!("&{}", self.names[operand]),
+            Operand::Const(Constant::Int(x)) => int_literal(*x),
+            Operand::Const(Constant::Undef(ty) | Constant::Poison(ty)) => match ty {
+                IrTy::Vector(..) => format!("({}){{{{0}}}}", decl(ty, "")),
+                _ => String::from("0"),
+            },
+            Operand::Const(Constant::Array { .. }) | Operand::BB(_) => unreachable!(),
+            _ => self.names[operand].clone(),
+        }
+    }
+
+    /// What `addr` points to, as an lvalue.
+    fn deref(&self, addr: &Operand) -> String {
+        if self.is_storage(addr) { self.names[addr].clone() } else { format!("*{}", self.operand(addr)) }
+    }
+}
+
+/// `left op right` on integers of type `ty`, wrapping on overflow.
+fn binary_expr(op: BinaryInstOp, ty: &IrTy, left: &str, right: &str) -> String {
+    use BinaryInstOp::{AShr, Add, And, Div, Eq, Ge, Gt, LShr, Le, Lt, Mod, Mul, Ne, Or, Shl, Sub};
+    let IrTy::Int(bits) = ty else {
+        unreachable!()
+    };
+    let int_ty = int_ty(*bits);
+    let wrapping = |op: &str| match bits {
+        1 => format!("({left} {op} {right}) & 1"),
+        _ => format!("({int_ty})((u{int_ty}){left} {op} (u{int_ty}){right})"),
+    };
+    match op {
+        Add => wrapping("+"),
+        Sub => wrapping("-"),
+        Mul => wrapping("*"),
+        Shl => wrapping("<<"),
+        LShr => format!("({int_ty})((u{int_ty}){left} >> {right})"),
+        Div => format!("{left} / {right}"),
+        Mod => format!("{left} % {right}"),
+        AShr => format!("{left} >> {right}"),
+        And => format!("{left} & {right}"),
+        Or => format!("{left} | {right}"),
+        Lt => format!("{left} < {right}"),
+        Le => format!("{left} <= {right}"),
+        Gt => format!("{left} > {right}"),
+        Ge => format!("{left} >= {right}"),
+        Eq => format!("{left} == {right}"),
+        Ne => format!("{left} != {right}"),
+    }
+}
@@ -0,0 +1,2 @@
+pub mod arm;
+pub mod c;
@@ -0,0 +1,133 @@
+use std::fmt::{Display, Formatter};
+
+use serde::Serialize;
+
+use crate::compiler::span::{Pos, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A machine-applicable edit: replace the text covered by `span` with
+/// `replacement` (an empty span is an insertion).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct TextEdit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Suggestion {
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+}
+
+impl Suggestion {
+    #[must_use] pub fn insert(pos: Pos, text: &str) -> Suggestion {
+        Suggestion {
+            message: format!("insert `{text}`"),
+            edits: vec![TextEdit { span: Span { start: pos, end: pos }, replacement: String::from(text) }],
+        }
+    }
+
+    #[must_use] pub fn replace(message: &str, span: Span, text: &str) -> Suggestion {
+        Suggestion {
+            message: String::from(message),
+            edits: vec![TextEdit { span, replacement: String::from(text) }],
+        }
+    }
+}
+
+/// A secondary location that explains the primary one, e.g. an earlier
+/// declaration the reported code conflicts with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A message reported to the user, carrying a stable code (e.g. `E0201`).
+///
+/// Codes are part of the public interface: once assigned, a code is never
+/// reused or renumbered, so harnesses and editors can match on it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    /// The source file the spans are in, `None` if there is only the one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    #[must_use] pub fn error(code: &'static str, message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, code, message, file: None, span, labels: vec![], suggestions: vec![] }
+    }
+
+    #[must_use] pub fn warning(code: &'static str, message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, code, message, file: None, span, labels: vec![], suggestions: vec![] }
+    }
+
+    #[must_use] pub fn with_file(mut self, file: &str) -> Diagnostic {
+        self.file = Some(String::from(file));
+        self
+    }
+
+    #[must_use] pub fn with_label(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.push(Label { span, message: String::from(message) });
+        self
+    }
+
+    #[must_use] pub fn with_suggestion(mut self, suggestion: Suggestion) -> Diagnostic {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    #[must_use] pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic as a single-line JSON object.
+    #[must_use] pub fn to_json(&self) -> String {
+        // plain data with string keys, serialization cannot fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        match (&self.file, &self.span) {
+            (Some(file), Some(span)) => write!(f, "\n  --> {file}:{}:{}", span.start.lineno + 1, span.start.colno + 1)?,
+            (Some(file), None) => write!(f, "\n  --> {file}")?,
+            (None, Some(span)) => write!(f, "\n  --> {}:{}", span.start.lineno + 1, span.start.colno + 1)?,
+            (None, None) => {}
+        }
+        for label in &self.labels {
+            write!(f, "\n  = note: {} at {}:{}", label.message, label.span.start.lineno + 1, label.span.start.colno + 1)?;
+        }
+        for suggestion in &self.suggestions {
+            write!(f, "\n  = help: {}", suggestion.message)?;
+            if let Some(edit) = suggestion.edits.first() {
+                write!(f, " at {}:{}", edit.span.start.lineno + 1, edit.span.start.colno + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::io::{BufRead, Write};
+
+use super::{err::ExecError, Interpreter, Val};
+use crate::compiler::ir::arena::FuncId;
+use crate::compiler::pass::sanitize::{Check, TRAP_HANDLER};
+
+impl<R: BufRead, W: Write> Interpreter<'_, R, W> {
+    /// Runs one of the runtime library functions, see
+    /// [`BUILTIN_FUNCS`](crate::compiler::ir_builder::name_resolver::BUILTIN_FUNCS).
+    pub(super) fn call_builtin(&mut self, func_id: FuncId, args: &[Val]) -> Result<Option<Val>, ExecError> {
+        let module = self.module;
+        let name = module.get_func(func_id).unwrap().name.as_str();
+        match name {
+            "getint" => Ok(Some(Val::Int(self.read_int()?))),
+            "getch" => {
+                let ch = self.read_byte()?.map_or(-1, i32::from);
+                Ok(Some(Val::Int(ch)))
+            }
+            "getarray" => {
+                let addr = args[0].as_ptr();
+                let len = self.read_int()?;
+                for idx in 0..usize::try_from(len).unwrap_or(0) {
+                    let val = self.read_int()?;
+                    self.store(addr + idx, val)?;
+                }
+                Ok(Some(Val::Int(len)))
+            }
+            "putint" => {
+                write!(self.output, "{}", args[0].as_int())?;
+                Ok(None)
+            }
+            "putch" => {
+                self.output.write_all(&args[0].as_int().to_le_bytes()[..1])?;
+                Ok(None)
+            }
+            "putarray" => {
+                let len = args[0].as_int();
+                let addr = args[1].as_ptr();
+                write!(self.output, "{len}:")?;
+                for idx in 0..usize::try_from(len).unwrap_or(0) {
+                    write!(self.output, " {}", self.load(addr + idx)?)?;
+                }
+                writeln!(self.output)?;
+                Ok(None)
+            }
+            // nothing is timed when interpreting
+            "_sysy_starttime" | "_sysy_stoptime" => Ok(None),
+            TRAP_HANDLER => {
+                let check = Check::from_code(args[0].as_int()).expect("the sanitizers pass a check they know");
+                Err(ExecError::Trap(check, args[1].as_int(), args[2].as_int()))
+            }
+            _ => Err(ExecError::UndefinedFunction {
+                name: String::from(name),
+                file: module.debug_info.func_file(func_id).map(String::from),
+            }),
+        }
+    }
+
+    fn peek_byte(&mut self) -> Result<Option<u8>, ExecError> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, ExecError> {
+        let byte = self.peek_byte()?;
+        if byte.is_some() {
+            self.input.consume(1);
+        }
+        Ok(byte)
+    }
+
+    /// Reads a decimal integer like `scanf("%d")`, skipping leading
+    /// whitespace.
+    fn read_int(&mut self) -> Result<i32, ExecError> {
+        while self.peek_byte()?.is_some_and(|x| x.is_ascii_whitespace()) {
+            self.input.consume(1);
+        }
+
+        let is_neg = match self.peek_byte()? {
+            Some(sign @ (b'-' | b'+')) => {
+                self.input.consume(1);
+                sign == b'-'
+            }
+            _ => false,
+        };
+
+        let mut val: i32 = 0;
+        let mut has_digits = false;
+        while let Some(digit @ b'0'..=b'9') = self.peek_byte()? {
+            self.input.consume(1);
+            val = val.wrapping_mul(10).wrapping_add(i32::from(digit - b'0'));
+            has_digits = true;
+        }
+
+        if !has_digits {
+            return Err(ExecError::InvalidInput);
+        }
+        Ok(if is_neg { val.wrapping_neg() } else { val })
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use crate::compiler::pass::sanitize::Check;
+
+#[derive(Debug)]
+pub enum ExecError {
+    DivisionByZero,
+    OutOfBounds,
+    /// A call to a function only declared, in the given file if known.
+    UndefinedFunction { name: String, file: Option<String> },
+    InvalidInput,
+    Io(io::Error),
+    /// The step budget ran out, the program most likely does not terminate.
+    Timeout,
+    StackOverflow,
+    OutOfMemory,
+    /// A check the sanitizers inserted failed, at the given line and column
+    /// of the source.
+    Trap(Check, i32, i32),
+}
+
+impl Display for ExecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecError::DivisionByZero => write!(f, "division by zero"),
+            ExecError::OutOfBounds => write!(f, "memory access out of bounds"),
+            ExecError::UndefinedFunction { name, file: Some(file) } => write!(f, "call to undefined function `{name}`, declared in `{file}`"),
+            ExecError::UndefinedFunction { name, file: None } => write!(f, "call to undefined function `{name}`"),
+            ExecError::InvalidInput => write!(f, "expected an integer in the input"),
+            ExecError::Io(err) => write!(f, "i/o error: {err}"),
+            ExecError::Timeout => write!(f, "step limit exceeded"),
+            ExecError::StackOverflow => write!(f, "call depth limit exceeded"),
+            ExecError::OutOfMemory => write!(f, "memory limit exceeded"),
+            ExecError::Trap(check, line, col) => write!(f, "{} at {line}:{col}", check.message()),
+        }
+    }
+}
+
+impl From<io::Error> for ExecError {
+    fn from(err: io::Error) -> Self {
+        ExecError::Io(err)
+    }
+}
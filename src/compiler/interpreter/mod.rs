@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, GlobalId, InstId, ParamId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{BinaryInstOp, Br, CastOp, InsertElement, InstKind, Load, Store},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+
+use err::ExecError;
+
+pub mod err;
+mod builtin;
+
+/// A runtime value. Memory is a flat array of `i32` cells and a pointer is
+/// the index of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Val {
+    Int(i32),
+    /// An integer wider than 32 bits, which only ever lives in registers.
+    Wide(i64),
+    Ptr(usize),
+    /// A vector of integers, in the first `lanes` of `elems`.
+    Vector { elems: [i32; IrTy::MAX_LANES], lanes: usize },
+}
+
+impl Val {
+    fn as_int(self) -> i32 {
+        match self {
+            Val::Int(x) => x,
+            Val::Wide(_) | Val::Ptr(_) | Val::Vector { .. } => unreachable!(),
+        }
+    }
+
+    /// The value as a wide integer, constants being sign-extended.
+    fn as_wide(self) -> i64 {
+        match self {
+            Val::Int(x) => i64::from(x),
+            Val::Wide(x) => x,
+            Val::Ptr(_) | Val::Vector { .. } => unreachable!(),
+        }
+    }
+
+    fn as_ptr(self) -> usize {
+        match self {
+            Val::Ptr(x) => x,
+            Val::Int(_) | Val::Wide(_) | Val::Vector { .. } => unreachable!(),
+        }
+    }
+
+    /// A vector of `lanes` zeros.
+    fn vector(lanes: usize) -> Val {
+        Val::Vector { elems: [0; IrTy::MAX_LANES], lanes }
+    }
+}
+
+/// An active call. Calls are kept on an explicit stack rather than the host
+/// one, so deep recursion in the program cannot overflow the interpreter.
+#[derive(Debug)]
+struct Frame<'a> {
+    func: &'a IrFunc,
+    values: HashMap<InstId, Val>,
+    params: HashMap<ParamId, Val>,
+    /// Next instruction to execute.
+    pc: Option<InstId>,
+    /// Memory size on entry, the allocas above it are freed on return.
+    stack_base: usize,
+    /// Call instruction in the caller that receives the return value.
+    call_inst: Option<InstId>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(func: &'a IrFunc, args: Vec<Val>, stack_base: usize, call_inst: Option<InstId>) -> Frame<'a> {
+        let entry = func.first_block.expect("Function without body");
+        Frame {
+            func,
+            values: HashMap::new(),
+            params: func.params.iter().copied().zip(args).collect(),
+            pc: func.bb_arena.get(entry).unwrap().insts_head,
+            stack_base,
+            call_inst,
+        }
+    }
+}
+
+enum Flow {
+    Next(Option<Val>),
+    Jump(BBId),
+    Call(FuncId, Vec<Val>),
+    Return(Option<Val>),
+}
+
+/// Resource limits for one run, `None` meaning unlimited. Exceeding one
+/// aborts the run with [`ExecError::Timeout`], [`ExecError::StackOverflow`]
+/// or [`ExecError::OutOfMemory`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Instructions executed, builtins counting as one.
+    pub max_steps: Option<u64>,
+    pub max_call_depth: Option<usize>,
+    /// Bytes of globals and stack, at 4 bytes per `i32`.
+    pub max_memory: Option<usize>,
+}
+
+/// Executes a module directly, without going through LLVM. The program's
+/// input is read from `input` and its output written to `output`, so a
+/// test can pass a file or a byte slice and capture the output in a
+/// `Vec<u8>`.
+#[derive(Debug)]
+pub struct Interpreter<'a, R, W> {
+    module: &'a Module,
+    input: R,
+    output: W,
+    memory: Vec<i32>,
+    globals: HashMap<GlobalId, usize>,
+    limits: Limits,
+    steps: u64,
+}
+
+impl<'a, R: BufRead, W: Write> Interpreter<'a, R, W> {
+    pub fn new(module: &'a Module, input: R, output: W) -> Interpreter<'a, R, W> {
+        Interpreter {
+            module,
+            input,
+            output,
+            memory: vec![],
+            globals: HashMap::new(),
+            limits: Limits::default(),
+            steps: 0,
+        }
+    }
+
+    #[must_use] pub fn with_limits(mut self, limits: Limits) -> Interpreter<'a, R, W> {
+        self.limits = limits;
+        self
+    }
+
+    /// Runs `main` and returns its return value.
+    ///
+    /// # Errors
+    /// Fails on a runtime error in the program, an i/o error on its input or
+    /// output, or when it exceeds one of the [`Limits`]. Output written
+    /// before the failure is still flushed.
+    pub fn run(&mut self) -> Result<i32, ExecError> {
+        let main = self.module.func_arena.iter()
+            .find(|(_, func)| func.name == "main")
+            .map(|(func_id, _)| func_id)
+            .ok_or_else(|| ExecError::UndefinedFunction { name: String::from("main"), file: None })?;
+
+        self.memory.clear();
+        self.globals.clear();
+        self.steps = 0;
+        for (global_id, global) in &self.module.global_arena {
+            let mut cells = vec![];
+            flatten_constant(&global.init_val, &mut cells);
+            let addr = self.alloc(cells.len())?;
+            self.memory[addr..].copy_from_slice(&cells);
+            self.globals.insert(global_id, addr);
+        }
+
+        let ret_val = self.call(main, vec![]);
+        self.output.flush()?;
+        Ok(ret_val?.map_or(0, Val::as_int))
+    }
+
+    /// Calls `func_id` with `args` on its own, without running `main` or
+    /// setting up globals, as when evaluating at compile time a function
+    /// that touches neither memory outside its frame nor i/o. Returns the
+    /// return value of the function, if any.
+    ///
+    /// # Errors
+    /// As for [`Interpreter::run`].
+    pub fn call_func(&mut self, func_id: FuncId, args: &[i32]) -> Result<Option<i32>, ExecError> {
+        self.memory.clear();
+        self.globals.clear();
+        self.steps = 0;
+        let ret_val = self.call(func_id, args.iter().copied().map(Val::Int).collect())?;
+        Ok(ret_val.map(Val::as_int))
+    }
+
+    /// Instructions executed by the last run or call, as counted against
+    /// [`Limits::max_steps`].
+    #[must_use] pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Consumes the interpreter, returning the output handle.
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    fn call(&mut self, func_id: FuncId, args: Vec<Val>) -> Result<Option<Val>, ExecError> {
+        let module = self.module;
+        let func = module.get_func(func_id).unwrap();
+        if func.is_builtin {
+            return self.call_builtin(func_id, &args);
+        }
+
+        let mut frames = vec![Frame::new(func, args, self.memory.len(), None)];
+        loop {
+            let frame = frames.last_mut().unwrap();
+            let func = frame.func;
+            let inst_id = frame.pc.expect("Block without terminator");
+            let inst = func.get_inst(inst_id).unwrap();
+            frame.pc = inst.next;
+
+            self.steps += 1;
+            if self.limits.max_steps.is_some_and(|x| self.steps > x) {
+                return Err(ExecError::Timeout);
+            }
+
+            match self.exec_inst(frame, &inst.kind)? {
+                Flow::Next(val) => {
+                    if let Some(val) = val {
+                        frame.values.insert(inst_id, val);
+                    }
+                }
+                Flow::Jump(bb_id) => frame.pc = func.bb_arena.get(bb_id).unwrap().insts_head,
+                Flow::Call(callee_id, args) => {
+                    let callee = module.get_func(callee_id).unwrap();
+                    if callee.is_builtin {
+                        if let Some(val) = self.call_builtin(callee_id, &args)? {
+                            frame.values.insert(inst_id, val);
+                        }
+                    } else {
+                        if self.limits.max_call_depth.is_some_and(|x| frames.len() >= x) {
+                            return Err(ExecError::StackOverflow);
+                        }
+                        frames.push(Frame::new(callee, args, self.memory.len(), Some(inst_id)));
+                    }
+                }
+                Flow::Return(val) => {
+                    let frame = frames.pop().unwrap();
+                    self.memory.truncate(frame.stack_base);
+                    let Some(caller) = frames.last_mut() else {
+                        return Ok(val);
+                    };
+                    if let (Some(call_inst), Some(val)) = (frame.call_inst, val) {
+                        caller.values.insert(call_inst, val);
+                    }
+                }
+            }
+        }
+    }
+
+    fn exec_inst(&mut self, frame: &Frame, inst_kind: &InstKind) -> Result<Flow, ExecError> {
+        let flow = match inst_kind {
+            InstKind::Binary(binary_inst) => {
+                let left = eval(frame, &self.globals, &binary_inst.left);
+                let right = eval(frame, &self.globals, &binary_inst.right);
+                let val = match (left, right) {
+                    (Val::Int(left), Val::Int(right)) => Val::Int(exec_binary(binary_inst.op, left, right)?),
+                    (Val::Vector { elems: left, lanes }, Val::Vector { elems: right, .. }) => {
+                        let mut elems = [0; IrTy::MAX_LANES];
+                        for lane in 0..lanes {
+                            elems[lane] = exec_binary(binary_inst.op, left[lane], right[lane])?;
+                        }
+                        Val::Vector { elems, lanes }
+                    }
+                    _ => exec_wide_binary(binary_inst.op, left.as_wide(), right.as_wide())?,
+                };
+                Flow::Next(Some(val))
+            }
+            InstKind::Br(Br::Br { cond, true_bb, false_bb }) => {
+                let cond = eval(frame, &self.globals, cond).as_int();
+                Flow::Jump(if cond == 0 { *false_bb } else { *true_bb })
+            }
+            InstKind::Br(Br::Jump { nxt_bb }) => Flow::Jump(*nxt_bb),
+            InstKind::Br(Br::Switch { cond, cases, default }) => {
+                let cond = eval(frame, &self.globals, cond).as_int();
+                let case = cases.iter().find(|x| x.0 == cond);
+                Flow::Jump(case.map_or(*default, |x| x.1))
+            }
+            InstKind::RetInst(ret_inst) => {
+                Flow::Return(ret_inst.val.as_ref().map(|x| eval(frame, &self.globals, x)))
+            }
+            InstKind::Alloca(alloca_inst) => {
+                Flow::Next(Some(Val::Ptr(self.alloc(alloca_inst.alloca_ty.size_in_words())?)))
+            }
+            InstKind::Load(load_inst) => Flow::Next(Some(self.exec_load(frame, load_inst)?)),
+            InstKind::Store(store_inst) => {
+                self.exec_store(frame, store_inst)?;
+                Flow::Next(None)
+            }
+            InstKind::GEP(gep_inst) => {
+                let base = eval(frame, &self.globals, &gep_inst.ptr).as_ptr();
+                let mut ty = IrTy::deptr_of(&self.module.operand_ty(frame.func, &gep_inst.ptr)).unwrap();
+                let mut offset = 0_i64;
+                for (pos, idx) in gep_inst.indices.iter().enumerate() {
+                    if pos > 0 {
+                        ty = ty.as_array().unwrap().1.as_ref().clone();
+                    }
+                    let idx = i64::from(eval(frame, &self.globals, idx).as_int());
+                    offset += idx * i64::try_from(ty.size_in_words()).unwrap();
+                }
+                // pointers may step outside of memory as long as they are not
+                // dereferenced there, like one before an array walked backwards
+                let offset = isize::try_from(offset).map_err(|_| ExecError::OutOfBounds)?;
+                Flow::Next(Some(Val::Ptr(base.wrapping_add_signed(offset))))
+            }
+            InstKind::Cast(cast_inst) => {
+                let val = eval(frame, &self.globals, &cast_inst.ori_val);
+                let from = self.module.operand_ty(frame.func, &cast_inst.ori_val);
+                Flow::Next(Some(exec_cast(cast_inst.op, val, &from, &cast_inst.target_ty)))
+            }
+            InstKind::MemSet(memset_inst) => {
+                let dst = eval(frame, &self.globals, &memset_inst.dst).as_ptr();
+                let word = i32::from_ne_bytes([memset_inst.byte; 4]);
+                (dst..dst + memset_inst.len).try_for_each(|addr| self.store(addr, word))?;
+                Flow::Next(None)
+            }
+            InstKind::MemCpy(memcpy_inst) => {
+                let dst = eval(frame, &self.globals, &memcpy_inst.dst).as_ptr();
+                let src = eval(frame, &self.globals, &memcpy_inst.src).as_ptr();
+                let words = self.memory.get(src..src + memcpy_inst.len).ok_or(ExecError::OutOfBounds)?.to_vec();
+                self.memory.get_mut(dst..dst + memcpy_inst.len).ok_or(ExecError::OutOfBounds)?
+                    .copy_from_slice(&words);
+                Flow::Next(None)
+            }
+            InstKind::Select(select) => {
+                let cond = eval(frame, &self.globals, &select.cond).as_int();
+                let val = if cond == 0 { &select.false_val } else { &select.true_val };
+                Flow::Next(Some(eval(frame, &self.globals, val)))
+            }
+            InstKind::InsertElement(InsertElement { vector, elem, lane }) => {
+                let Val::Vector { mut elems, lanes } = eval(frame, &self.globals, vector) else {
+                    unreachable!()
+                };
+                elems[*lane] = eval(frame, &self.globals, elem).as_int();
+                Flow::Next(Some(Val::Vector { elems, lanes }))
+            }
+            InstKind::Call(call_inst) => {
+                let args = call_inst.args.iter()
+                    .map(|x| eval(frame, &self.globals, x))
+                    .collect();
+                Flow::Call(call_inst.func_id, args)
+            }
+        };
+        Ok(flow)
+    }
+
+    fn exec_load(&self, frame: &Frame, load_inst: &Load) -> Result<Val, ExecError> {
+        let addr = eval(frame, &self.globals, &load_inst.addr).as_ptr();
+        let cell = self.load(addr)?;
+        let val = match IrTy::deptr_of(&self.module.operand_ty(frame.func, &load_inst.addr)) {
+            Some(IrTy::Ptr(_)) => Val::Ptr(cell_to_ptr(cell)?),
+            Some(IrTy::Vector(lanes, _)) => {
+                let mut elems = [0; IrTy::MAX_LANES];
+                for (lane, elem) in elems.iter_mut().enumerate().take(lanes) {
+                    *elem = self.load(addr + lane)?;
+                }
+                Val::Vector { elems, lanes }
+            }
+            _ => Val::Int(cell),
+        };
+        Ok(val)
+    }
+
+    fn exec_store(&mut self, frame: &Frame, store_inst: &Store) -> Result<(), ExecError> {
+        let addr = eval(frame, &self.globals, &store_inst.addr).as_ptr();
+        if let Operand::Const(constant @ (Constant::Array { .. } | Constant::Undef(IrTy::Array(..)) | Constant::Poison(IrTy::Array(..)))) = &store_inst.data {
+            let mut cells = vec![];
+            flatten_constant(constant, &mut cells);
+            return cells.into_iter().enumerate()
+                .try_for_each(|(idx, x)| self.store(addr + idx, x));
+        }
+        match eval(frame, &self.globals, &store_inst.data) {
+            Val::Int(x) => self.store(addr, x),
+            Val::Ptr(x) => self.store(addr, ptr_to_cell(x)?),
+            Val::Vector { elems, lanes } => {
+                elems[..lanes].iter().enumerate()
+                    .try_for_each(|(lane, &x)| self.store(addr + lane, x))
+            }
+            Val::Wide(_) => unreachable!(),
+        }
+    }
+
+    /// Appends `cells` zeroed cells to memory, returning the first one.
+    fn alloc(&mut self, cells: usize) -> Result<usize, ExecError> {
+        let addr = self.memory.len();
+        let bytes = (addr + cells) * std::mem::size_of::<i32>();
+        if self.limits.max_memory.is_some_and(|x| bytes > x) {
+            return Err(ExecError::OutOfMemory);
+        }
+        self.memory.resize(addr + cells, 0);
+        Ok(addr)
+    }
+
+    fn load(&self, addr: usize) -> Result<i32, ExecError> {
+        self.memory.get(addr).copied().ok_or(ExecError::OutOfBounds)
+    }
+
+    fn store(&mut self, addr: usize, val: i32) -> Result<(), ExecError> {
+        let cell = self.memory.get_mut(addr).ok_or(ExecError::OutOfBounds)?;
+        *cell = val;
+        Ok(())
+    }
+}
+
+/// A pointer kept in memory takes one word, addresses before the first
+/// being negative.
+fn ptr_to_cell(ptr: usize) -> Result<i32, ExecError> {
+    i32::try_from(ptr.cast_signed()).map_err(|_| ExecError::OutOfBounds)
+}
+
+fn cell_to_ptr(cell: i32) -> Result<usize, ExecError> {
+    isize::try_from(cell).map(isize::cast_unsigned).map_err(|_| ExecError::OutOfBounds)
+}
+
+fn eval(frame: &Frame, globals: &HashMap<GlobalId, usize>, operand: &Operand) -> Val {
+    match operand {
+        Operand::Inst(x) => frame.values[x],
+        Operand::Param(x) => frame.params[x],
+        Operand::Global(x) => Val::Ptr(globals[x]),
+        Operand::Const(Constant::Int(x)) => Val::Int(*x),
+        // any value will do, zero makes runs reproducible
+        Operand::Const(Constant::Undef(IrTy::Ptr(_)) | Constant::Poison(IrTy::Ptr(_))) => Val::Ptr(0),
+        Operand::Const(Constant::Undef(IrTy::Vector(lanes, _)) | Constant::Poison(IrTy::Vector(lanes, _))) => Val::vector(*lanes),
+        Operand::Const(Constant::Undef(_) | Constant::Poison(_)) => Val::Int(0),
+        Operand::Const(Constant::Array { .. }) | Operand::BB(_) => unreachable!(),
+    }
+}
+
+fn exec_binary(op: BinaryInstOp, left: i32, right: i32) -> Result<i32, ExecError> {
+    match op.fold(left, right) {
+        Some(x) => Ok(x),
+        // shifting past the width gives poison, which is held as 0
+        None if is_shift(op) => Ok(0),
+        None => Err(ExecError::DivisionByZero),
+    }
+}
+
+/// Comparisons of wide integers give an `i1`, held like the others.
+fn exec_wide_binary(op: BinaryInstOp, left: i64, right: i64) -> Result<Val, ExecError> {
+    match op.fold_wide(left, right) {
+        Some(x) if op.is_cmp() => Ok(Val::Int(i32::from(x != 0))),
+        Some(x) => Ok(Val::Wide(x)),
+        None if is_shift(op) => Ok(Val::Wide(0)),
+        None => Err(ExecError::DivisionByZero),
+    }
+}
+
+fn is_shift(op: BinaryInstOp) -> bool {
+    matches!(op, BinaryInstOp::Shl | BinaryInstOp::AShr | BinaryInstOp::LShr)
+}
+
+fn exec_cast(op: CastOp, val: Val, from: &IrTy, to: &IrTy) -> Val {
+    match (val, from, to) {
+        (Val::Int(x), IrTy::Int(from), IrTy::Int(to)) if *to > 32 => match op {
+            CastOp::SExt => Val::Wide(i64::from(op.fold(x, *from, 32))),
+            _ => Val::Wide(i64::from(x.cast_unsigned())),
+        },
+        (Val::Int(x), IrTy::Int(from), IrTy::Int(to)) => Val::Int(op.fold(x, *from, *to)),
+        (Val::Wide(x), _, IrTy::Int(to)) => {
+            let [b0, b1, b2, b3, ..] = x.to_le_bytes();
+            Val::Int(op.fold(i32::from_le_bytes([b0, b1, b2, b3]), 32, *to))
+        }
+        _ => val,
+    }
+}
+
+/// Appends the cells of `constant` to `cells`, padding arrays with zeros.
+fn flatten_constant(constant: &Constant, cells: &mut Vec<i32>) {
+    match constant {
+        Constant::Int(x) => cells.push(*x),
+        Constant::Undef(ty) | Constant::Poison(ty) => cells.resize(cells.len() + ty.size_in_words(), 0),
+        Constant::Array { ty, elems } => {
+            let end = cells.len() + ty.size_in_words();
+            for elem in elems {
+                flatten_constant(elem, cells);
+            }
+            cells.resize(end, 0);
+        }
+    }
+}
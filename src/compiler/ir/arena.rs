@@ -1,14 +1,23 @@
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::ops::{Index, IndexMut};
 
 use slotmap::{Key, KeyData, new_key_type, SlotMap};
 
 use crate::compiler::intrusive_linkedlist::{IntrusiveLinkedList, IntrusiveLinkedListItem};
 
+/// The index of the slot a key names, dropping its version.
+fn slot_of(data: KeyData) -> u32 {
+    // A slotmap key packs the version in the high 32 bits and the slot index
+    // in the low 32 bits.
+    (data.as_ffi() & u64::from(u32::MAX)).try_into().unwrap()
+}
+
 macro_rules! setup_index {
     ($ty:ty) => {
         impl $ty {
             #[must_use] pub fn slot(self) -> u32 {
-                self.data().as_ffi() as u32
+                slot_of(self.data())
             }
 
             #[must_use] pub fn from_bits(v: u64) -> Self {
@@ -34,18 +43,236 @@ macro_rules! setup_index {
     };
 }
 
+/// Declares the key of an [`Arena`], wrapping a slotmap key of its own.
+macro_rules! setup_arena_key {
+    ($ty:ident, $raw:ident) => {
+        new_key_type! {
+            #[doc(hidden)]
+            pub struct $raw;
+        }
+
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $ty {
+            raw: $raw,
+            arena: ArenaTag,
+        }
+
+        impl ArenaKey for $ty {
+            type Raw = $raw;
+
+            fn new(raw: $raw, arena: ArenaTag) -> Self {
+                $ty { raw, arena }
+            }
+
+            fn raw(self) -> $raw {
+                self.raw
+            }
+
+            fn arena(self) -> ArenaTag {
+                self.arena
+            }
+        }
+
+        impl $ty {
+            #[must_use] pub fn slot(self) -> u32 {
+                slot_of(self.raw.data())
+            }
+        }
+
+        /// A null key, naming nothing in any arena.
+        impl Default for $ty {
+            fn default() -> Self {
+                $ty { raw: $raw::default(), arena: ArenaTag::NONE }
+            }
+        }
+
+        impl From<$ty> for u32 {
+            fn from(val: $ty) -> Self {
+                val.slot()
+            }
+        }
+
+        impl Debug for $ty {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}({:?})", stringify!($ty), self.raw.data())
+            }
+        }
+
+        impl Display for $ty {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "%{}{}", stringify!($ty), self.slot())
+            }
+        }
+    };
+}
+
 new_key_type! {
     pub struct GlobalId;
     pub struct FuncId;
-    pub struct BBId;
-    pub struct InstId;
-    pub struct ParamId;
 }
 
 setup_index!(GlobalId);
 setup_index!(FuncId);
-setup_index!(BBId);
-setup_index!(InstId);
+
+// blocks, instructions and parameters belong to a function, and their keys
+// would name something else if used with another one
+setup_arena_key!(BBId, RawBBId);
+setup_arena_key!(InstId, RawInstId);
+setup_arena_key!(ParamId, RawParamId);
+
+/// Which [`Arena`] issued a key. Only tracked in debug builds, so that it
+/// costs nothing otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ArenaTag(#[cfg(debug_assertions)] u32);
+
+impl ArenaTag {
+    #[cfg(debug_assertions)]
+    const NONE: ArenaTag = ArenaTag(u32::MAX);
+    #[cfg(not(debug_assertions))]
+    const NONE: ArenaTag = ArenaTag();
+
+    fn fresh() -> ArenaTag {
+        #[cfg(debug_assertions)]
+        {
+            static NEXT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+            ArenaTag(NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            ArenaTag()
+        }
+    }
+}
+
+pub trait ArenaKey: Copy + Eq + Hash + Debug {
+    type Raw: slotmap::Key;
+
+    fn new(raw: Self::Raw, arena: ArenaTag) -> Self;
+    fn raw(self) -> Self::Raw;
+    fn arena(self) -> ArenaTag;
+}
+
+/// A slotmap whose keys remember, in debug builds, the arena they came from,
+/// so that using the id of an instruction of one function with another
+/// panics instead of silently naming an unrelated instruction.
+#[derive(Debug)]
+pub struct Arena<K: ArenaKey, V> {
+    tag: ArenaTag,
+    slots: SlotMap<K::Raw, V>,
+}
+
+impl<K: ArenaKey, V> Default for Arena<K, V> {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+impl<K: ArenaKey, V> Arena<K, V> {
+    #[must_use] pub fn new() -> Arena<K, V> {
+        Arena { tag: ArenaTag::fresh(), slots: SlotMap::with_key() }
+    }
+
+    /// The slotmap key of `key`.
+    ///
+    /// # Panics
+    ///
+    /// If another arena issued `key`, in debug builds.
+    fn raw(&self, key: K) -> K::Raw {
+        assert!(key.arena() == self.tag, "{key:?} used with an arena of another function");
+        key.raw()
+    }
+
+    pub fn insert(&mut self, value: V) -> K {
+        K::new(self.slots.insert(value), self.tag)
+    }
+
+    pub fn remove(&mut self, key: K) -> Option<V> {
+        let raw = self.raw(key);
+        self.slots.remove(raw)
+    }
+
+    #[must_use] pub fn get(&self, key: K) -> Option<&V> {
+        self.slots.get(self.raw(key))
+    }
+
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        let raw = self.raw(key);
+        self.slots.get_mut(raw)
+    }
+
+    /// Whether `key` names a value of this arena. Keys from another arena
+    /// never do.
+    #[must_use] pub fn contains_key(&self, key: K) -> bool {
+        key.arena() == self.tag && self.slots.contains_key(key.raw())
+    }
+
+    #[must_use] pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[must_use] pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (K, &V)> {
+        self.slots.iter().map(|(raw, value)| (K::new(raw, self.tag), value))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (K, &mut V)> {
+        let tag = self.tag;
+        self.slots.iter_mut().map(move |(raw, value)| (K::new(raw, tag), value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.slots.keys().map(|raw| K::new(raw, self.tag))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.slots.values()
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.slots.values_mut()
+    }
+}
+
+impl<K: ArenaKey, V> Index<K> for Arena<K, V> {
+    type Output = V;
+
+    fn index(&self, key: K) -> &V {
+        &self.slots[self.raw(key)]
+    }
+}
+
+impl<K: ArenaKey, V> IndexMut<K> for Arena<K, V> {
+    fn index_mut(&mut self, key: K) -> &mut V {
+        let raw = self.raw(key);
+        &mut self.slots[raw]
+    }
+}
+
+impl<T, K> IntrusiveLinkedList<K> for Arena<K, T>
+    where T: IntrusiveLinkedListItem<Key=K>,
+          K: ArenaKey,
+{
+    type Item = T;
+
+    fn get_item(&self, key: K) -> &Self::Item {
+        &self[key]
+    }
+
+    fn get_item_mut(&mut self, key: K) -> &mut Self::Item {
+        &mut self[key]
+    }
+
+    fn insert_item(&mut self, item: Self::Item) -> K {
+        self.insert(item)
+    }
+
+    fn remove_item(&mut self, idx: K) -> Self::Item {
+        self.remove(idx).unwrap()
+    }
+}
 
 impl<T, Key> IntrusiveLinkedList<Key> for SlotMap<Key, T>
     where T: IntrusiveLinkedListItem<Key=Key>,
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, InstId},
+    value::{basic_block::BasicBlock, constant::Constant, func::IrFunc, inst::{Br, InstKind}, ty::IrTy, value::Operand},
+};
+
+/// Control flow graph queries, derived on demand from the terminator of each
+/// block so that they never go stale as passes rewrite branches.
+impl IrFunc {
+    /// Blocks in layout order, the entry block first.
+    pub fn bb_ids(&self) -> impl Iterator<Item = BBId> + '_ {
+        self.bb_arena.items_iter(self.first_block, None).map(|(bb_id, _)| bb_id)
+    }
+
+    /// The `br` or `ret` ending `bb`, if it has been built yet.
+    #[must_use] pub fn terminator(&self, bb: BBId) -> Option<InstId> {
+        let tail = self.get_bb(bb)?.insts_tail?;
+        match self.get_inst(tail)?.kind {
+            InstKind::Br(_) | InstKind::RetInst(_) => Some(tail),
+            _ => None,
+        }
+    }
+
+    /// Successors of `bb`, without duplicates.
+    #[must_use] pub fn succs(&self, bb: BBId) -> Vec<BBId> {
+        let Some(terminator) = self.terminator(bb).and_then(|x| self.get_inst(x)) else {
+            return vec![];
+        };
+        match &terminator.kind {
+            InstKind::Br(br) => br.targets().into_iter().unique().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Predecessors of `bb` in layout order. This scans every block; use
+    /// [`IrFunc::edges`] to build a map when querying many blocks.
+    #[must_use] pub fn preds(&self, bb: BBId) -> Vec<BBId> {
+        self.bb_ids()
+            .filter(|&x| self.succs(x).contains(&bb))
+            .collect()
+    }
+
+    /// Every edge `(from, to)` of the graph.
+    #[must_use] pub fn edges(&self) -> Vec<(BBId, BBId)> {
+        self.bb_ids()
+            .flat_map(|from| self.succs(from).into_iter().map(move |to| (from, to)))
+            .collect()
+    }
+
+    /// Blocks reachable from the entry, each after all of its successors
+    /// except along back edges.
+    #[must_use] pub fn postorder(&self) -> Vec<BBId> {
+        let mut order = vec![];
+        let Some(entry) = self.first_block else {
+            return order;
+        };
+
+        let mut visited = HashSet::from([entry]);
+        let mut stack = vec![(entry, self.succs(entry), 0)];
+        while let Some((bb, succs, idx)) = stack.last_mut() {
+            if let Some(&succ) = succs.get(*idx) {
+                *idx += 1;
+                if visited.insert(succ) {
+                    let succ_succs = self.succs(succ);
+                    stack.push((succ, succ_succs, 0));
+                }
+            } else {
+                order.push(*bb);
+                stack.pop();
+            }
+        }
+        order
+    }
+
+    /// Redirects every edge from `bb` to `old` so that it goes to `new`.
+    pub fn replace_succ(&mut self, bb: BBId, old: BBId, new: BBId) {
+        let Some(terminator) = self.terminator(bb) else {
+            return;
+        };
+        let InstKind::Br(br) = &mut self.inst_arena[terminator].kind else {
+            return;
+        };
+        for target in br.targets_mut() {
+            if *target == old {
+                *target = new;
+            }
+        }
+    }
+
+    /// Whether the edge from `from` to `to` is critical: `from` has other
+    /// successors and `to` other predecessors, so code cannot be placed to
+    /// run on that edge alone.
+    #[must_use] pub fn is_critical_edge(&self, from: BBId, to: BBId) -> bool {
+        self.succs(from).len() > 1 && self.preds(to).len() > 1
+    }
+
+    /// Puts a block that just jumps to `to` on the edge from `from`, placing
+    /// it after `from`, and returns it. Every target of the branch ending
+    /// `from` that is `to` goes through it.
+    pub fn split_edge(&mut self, from: BBId, to: BBId) -> BBId {
+        let bb = self.build_bb_after_cur(from);
+        self.build_inst_at_end(InstKind::Br(Br::Jump { nxt_bb: to }), IrTy::Void, bb);
+        self.replace_succ(from, to, bb);
+        bb
+    }
+
+    /// Splits every critical edge, returning the blocks put on them.
+    pub fn split_critical_edges(&mut self) -> Vec<BBId> {
+        let edges = self.edges();
+        let mut pred_cnts: HashMap<BBId, usize> = HashMap::new();
+        for &(_, to) in &edges {
+            *pred_cnts.entry(to).or_default() += 1;
+        }
+        edges.into_iter()
+            .filter(|&(from, to)| self.succs(from).len() > 1 && pred_cnts[&to] > 1)
+            .collect_vec()
+            .into_iter()
+            .map(|(from, to)| self.split_edge(from, to))
+            .collect()
+    }
+
+    /// Deletes `bb` and its instructions. Uses of their values elsewhere
+    /// become undef, so `bb` should be unreachable or those uses dead.
+    ///
+    /// # Panics
+    ///
+    /// If `bb` is the entry block.
+    pub fn remove_bb(&mut self, bb: BBId) {
+        assert_ne!(self.first_block, Some(bb), "Removing the entry block");
+        let inst_ids: Vec<_> = self.inst_arena.items_iter(self.bb_arena[bb].insts_head, None)
+            .map(|(inst_id, _)| inst_id)
+            .collect();
+        for &inst_id in &inst_ids {
+            let undef = Operand::Const(Constant::Undef(self.inst_arena[inst_id].ty.clone()));
+            self.replace_all_uses_with(&Operand::Inst(inst_id), &undef);
+        }
+        for inst_id in inst_ids {
+            self.remove_inst(inst_id);
+        }
+        self.bb_arena.detach(bb);
+        self.bb_arena.remove(bb);
+    }
+
+    /// Deletes the blocks not reachable from the entry, returning how many
+    /// there were.
+    pub fn remove_unreachable_bbs(&mut self) -> usize {
+        let reachable: HashSet<_> = self.postorder().into_iter().collect();
+        let unreachable: Vec<_> = self.bb_ids().filter(|x| !reachable.contains(x)).collect();
+        for &bb in &unreachable {
+            self.remove_bb(bb);
+        }
+        unreachable.len()
+    }
+
+    /// Appends the instructions of `bb` to `pred` in place of the jump ending
+    /// it, and deletes `bb`. `pred` must jump to `bb` and be its only
+    /// predecessor.
+    ///
+    /// # Panics
+    ///
+    /// If `pred` does not end in a jump to `bb`.
+    pub fn merge_bbs(&mut self, pred: BBId, bb: BBId) {
+        let jump = self.terminator(pred);
+        assert!(jump.is_some_and(|x| matches!(self.inst_arena[x].kind, InstKind::Br(Br::Jump { nxt_bb }) if nxt_bb == bb)),
+            "Merging {bb} into {pred}, which does not jump to it");
+        if let Some(jump) = jump {
+            self.remove_inst(jump);
+        }
+
+        let inst_ids: Vec<_> = self.inst_arena.items_iter(self.bb_arena[bb].insts_head, None)
+            .map(|(inst_id, _)| inst_id)
+            .collect();
+        for &inst_id in &inst_ids {
+            self.inst_arena[inst_id].bb = pred;
+        }
+        let BasicBlock { insts_head: head, insts_tail: tail, .. } = self.bb_arena[bb];
+        if let (Some(pred_tail), Some(head)) = (self.bb_arena[pred].insts_tail, head) {
+            self.inst_arena[pred_tail].next = Some(head);
+            self.inst_arena[head].prev = Some(pred_tail);
+        }
+        let pred_bb = &mut self.bb_arena[pred];
+        if pred_bb.insts_head.is_none() {
+            pred_bb.insts_head = head;
+        }
+        if tail.is_some() {
+            pred_bb.insts_tail = tail;
+        }
+
+        self.bb_arena.detach(bb);
+        self.bb_arena.remove(bb);
+    }
+
+    /// Moves the instructions after `inst_id` to a new block placed after
+    /// its own, and returns the new block. The block of `inst_id` is left
+    /// without a terminator for the caller to add, and the new one is as
+    /// cold as it.
+    pub fn split_bb(&mut self, inst_id: InstId) -> BBId {
+        let bb = self.inst_arena[inst_id].bb;
+        let new_bb = self.build_bb_after_cur(bb);
+        self.bb_arena[new_bb].is_cold = self.bb_arena[bb].is_cold;
+        let Some(next) = self.inst_arena[inst_id].next else {
+            return new_bb;
+        };
+        let moved: Vec<_> = self.inst_arena.items_iter(Some(next), None).map(|(inst_id, _)| inst_id).collect();
+        for &moved in &moved {
+            self.inst_arena[moved].bb = new_bb;
+        }
+        self.inst_arena[inst_id].next = None;
+        self.inst_arena[next].prev = None;
+        let tail = self.bb_arena[bb].insts_tail.replace(inst_id);
+        let new_bb_data = &mut self.bb_arena[new_bb];
+        new_bb_data.insts_head = Some(next);
+        new_bb_data.insts_tail = tail;
+        new_bb
+    }
+
+    /// Reachable blocks with each one before its successors except along
+    /// back edges, the usual order for forward data-flow problems.
+    #[must_use] pub fn reverse_postorder(&self) -> Vec<BBId> {
+        let mut order = self.postorder();
+        order.reverse();
+        order
+    }
+}
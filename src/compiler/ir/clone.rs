@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId},
+    value::{func::IrFunc, inst::InstKind, module::Module, value::Operand},
+};
+
+/// What each value and block of a function became in a copy of it.
+#[derive(Debug, Clone, Default)]
+pub struct ValueMap {
+    values: HashMap<Operand, Operand>,
+    bbs: HashMap<BBId, BBId>,
+}
+
+impl ValueMap {
+    #[must_use] pub fn new() -> ValueMap {
+        ValueMap::default()
+    }
+
+    /// Makes `old` map to `new`, e.g. a parameter to the argument passed for
+    /// it before copying the body of a function into a caller.
+    pub fn insert(&mut self, old: Operand, new: Operand) {
+        self.values.insert(old, new);
+    }
+
+    pub fn insert_bb(&mut self, old: BBId, new: BBId) {
+        self.bbs.insert(old, new);
+    }
+
+    #[must_use] pub fn get(&self, old: &Operand) -> Option<&Operand> {
+        self.values.get(old)
+    }
+
+    #[must_use] pub fn get_bb(&self, old: BBId) -> Option<BBId> {
+        self.bbs.get(&old).copied()
+    }
+
+    /// What `operand` maps to, or `operand` itself if it is not mapped, as
+    /// for constants and globals.
+    #[must_use] pub fn map(&self, operand: &Operand) -> Operand {
+        self.values.get(operand).unwrap_or(operand).clone()
+    }
+
+    #[must_use] pub fn map_bb(&self, bb: BBId) -> BBId {
+        self.get_bb(bb).unwrap_or(bb)
+    }
+
+    /// `inst_kind` with its operands and branch targets mapped.
+    #[must_use] pub fn map_inst_kind(&self, inst_kind: &InstKind) -> InstKind {
+        let mut inst_kind = inst_kind.clone();
+        for operand in inst_kind.operands_mut() {
+            *operand = self.map(operand);
+        }
+        if let InstKind::Br(br) = &mut inst_kind {
+            for target in br.targets_mut() {
+                *target = self.map_bb(*target);
+            }
+        }
+        inst_kind
+    }
+}
+
+impl IrFunc {
+    /// Copies every block of `self` to the end of `dst`, recording the new
+    /// blocks and instructions in `map`. Values `map` already maps, such as
+    /// parameters, are replaced in the copies. Returns the new blocks in the
+    /// order of the blocks they copy, the entry block first.
+    pub fn clone_blocks_into(&self, dst: &mut IrFunc, map: &mut ValueMap) -> Vec<BBId> {
+        let mut last = dst.bb_ids().last();
+        let bbs: Vec<_> = self.bb_ids()
+            .map(|bb| {
+                let new_bb = dst.build_bb();
+                if let Some(last) = last {
+                    dst.set_bb_after_cur(new_bb, last);
+                }
+                last = Some(new_bb);
+                map.insert_bb(bb, new_bb);
+                new_bb
+            })
+            .collect();
+
+        // an instruction may be used in a block laid out before its own, so
+        // operands are only mapped once every instruction has a copy
+        let mut new_insts = vec![];
+        for bb in self.bb_ids() {
+            for (inst_id, inst) in self.inst_arena.items_iter(self.bb_arena[bb].insts_head, None) {
+                let new_inst = dst.build_inst_at_end(inst.kind.clone(), inst.ty.clone(), map.map_bb(bb));
+                dst.inst_arena[new_inst].loc = inst.loc;
+                if let Some(name) = self.value_names.get(&inst_id.into()) {
+                    dst.value_names.insert(new_inst.into(), name.clone());
+                }
+                map.insert(inst_id.into(), new_inst.into());
+                new_insts.push(new_inst);
+            }
+        }
+        for new_inst in new_insts {
+            let inst_kind = map.map_inst_kind(&dst.inst_arena[new_inst].kind);
+            dst.set_inst_kind(new_inst, inst_kind);
+        }
+        bbs
+    }
+
+    /// Adds a copy of `self` to `module`, with the same name. The debug info
+    /// of the copy is left to the caller, see [`Module::clone_func`].
+    pub fn clone_into(&self, module: &mut Module) -> (FuncId, ValueMap) {
+        let mut func = IrFunc::new(&self.name, self.ret_ty.clone(), self.is_builtin);
+        func.linkage = self.linkage;
+        func.inline_hint = self.inline_hint;
+        let mut map = ValueMap::new();
+        for &param_id in &self.params {
+            let param = &self.param_arena[param_id];
+            let new_param = func.build_func_param(param.ty.clone());
+            func.param_arena[new_param].noalias = param.noalias;
+            if let Some(name) = self.value_names.get(&param_id.into()) {
+                func.value_names.insert(new_param.into(), name.clone());
+            }
+            map.insert(param_id.into(), new_param.into());
+        }
+        self.clone_blocks_into(&mut func, &mut map);
+        (module.build_func(func), map)
+    }
+}
+
+impl Module {
+    /// Adds a copy of `func_id` to the module, debug info included, for a
+    /// pass to specialize. The copy has the same name until renamed.
+    pub fn clone_func(&mut self, func_id: FuncId) -> (FuncId, ValueMap) {
+        let func = std::mem::take(&mut self.func_arena[func_id]);
+        let (clone_id, map) = func.clone_into(self);
+        self.func_arena[func_id] = func;
+
+        if let Some(scope) = self.debug_info.func(func_id) {
+            let (name, span) = (scope.name.clone(), scope.span);
+            self.debug_info.add_func(clone_id, &name, span);
+            self.debug_info.merge_func(func_id, clone_id, |x| map.get(x).cloned());
+        }
+        (clone_id, map)
+    }
+}
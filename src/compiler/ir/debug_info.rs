@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use crate::compiler::ir::{arena::{FuncId, GlobalId}, value::value::Operand};
+use crate::compiler::span::Span;
+
+/// What a debugger needs to map a module back to its source, on top of the
+/// location of each instruction: the file, the functions in it and where
+/// each variable lives.
+///
+/// Passes that move or delete values a variable lives in must keep this up
+/// to date, see [`DebugInfo::relocate_var`] and [`DebugInfo::merge_func`].
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    /// The source file, `None` if the module was not built from one.
+    pub file: Option<String>,
+    funcs: HashMap<FuncId, FuncScope>,
+    globals: Vec<VarInfo>,
+    /// The files of the functions and globals linked in from modules of
+    /// other files, the rest being from `file`.
+    func_files: HashMap<FuncId, String>,
+    global_files: HashMap<GlobalId, String>,
+}
+
+/// A function defined in the source and the variables declared in it.
+#[derive(Debug, Clone)]
+pub struct FuncScope {
+    pub name: String,
+    pub span: Span,
+    pub vars: Vec<VarInfo>,
+}
+
+/// A source variable and where its value is.
+#[derive(Debug, Clone)]
+pub struct VarInfo {
+    pub name: String,
+    /// The declaration.
+    pub span: Span,
+    /// The alloca, global or parameter holding it, `None` once optimized out.
+    pub loc: Option<Operand>,
+}
+
+impl DebugInfo {
+    #[must_use] pub fn new() -> DebugInfo {
+        DebugInfo::default()
+    }
+
+    /// Starts the scope of `func_id`, replacing any previous one such as
+    /// that of its prototype.
+    pub fn add_func(&mut self, func_id: FuncId, name: &str, span: Span) {
+        let scope = FuncScope { name: String::from(name), span, vars: vec![] };
+        self.funcs.insert(func_id, scope);
+    }
+
+    /// Declares a variable local to `func_id`, which must have a scope.
+    pub fn add_var(&mut self, func_id: FuncId, var: VarInfo) {
+        if let Some(scope) = self.funcs.get_mut(&func_id) {
+            scope.vars.push(var);
+        }
+    }
+
+    pub fn add_global(&mut self, var: VarInfo) {
+        self.globals.push(var);
+    }
+
+    #[must_use] pub fn func(&self, func_id: FuncId) -> Option<&FuncScope> {
+        self.funcs.get(&func_id)
+    }
+
+    pub fn funcs(&self) -> impl Iterator<Item = (FuncId, &FuncScope)> {
+        self.funcs.iter().map(|(&id, scope)| (id, scope))
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = &VarInfo> {
+        self.globals.iter()
+    }
+
+    /// The source file `func_id` is defined in, or for a declaration, first
+    /// declared in.
+    #[must_use] pub fn func_file(&self, func_id: FuncId) -> Option<&str> {
+        self.func_files.get(&func_id).or(self.file.as_ref()).map(String::as_str)
+    }
+
+    /// The source file `global_id` was defined in.
+    #[must_use] pub fn global_file(&self, global_id: GlobalId) -> Option<&str> {
+        self.global_files.get(&global_id).or(self.file.as_ref()).map(String::as_str)
+    }
+
+    /// The variable of `func_id` living in `loc`, globals included.
+    #[must_use] pub fn var_at(&self, func_id: FuncId, loc: &Operand) -> Option<&VarInfo> {
+        self.funcs.get(&func_id)
+            .into_iter()
+            .flat_map(|x| &x.vars)
+            .chain(&self.globals)
+            .find(|x| x.loc.as_ref() == Some(loc))
+    }
+
+    /// Moves the variables of `func_id` living in `old` to `new`, globals
+    /// included, or marks them optimized out if `new` is `None`. To be called
+    /// when a pass replaces or deletes an alloca, parameter or global.
+    pub fn relocate_var(&mut self, func_id: FuncId, old: &Operand, new: Option<&Operand>) {
+        self.funcs.get_mut(&func_id)
+            .into_iter()
+            .flat_map(|x| &mut x.vars)
+            .chain(&mut self.globals)
+            .filter(|x| x.loc.as_ref() == Some(old))
+            .for_each(|x| x.loc = new.cloned());
+    }
+
+    /// Moves the global variables living in `old` to `new`, for a pass
+    /// merging globals.
+    pub fn relocate_global(&mut self, old: &Operand, new: &Operand) {
+        self.globals.iter_mut()
+            .filter(|x| x.loc.as_ref() == Some(old))
+            .for_each(|x| x.loc = Some(new.clone()));
+    }
+
+    /// Marks the global variables living in `old` optimized out, for a pass
+    /// deleting it.
+    pub fn remove_global(&mut self, old: &Operand) {
+        self.globals.iter_mut()
+            .filter(|x| x.loc.as_ref() == Some(old))
+            .for_each(|x| x.loc = None);
+    }
+
+    /// Moves the global variables living in `old` into the scope of
+    /// `func_id`, living in `new`, for a pass turning a global into a local.
+    pub fn localize_global(&mut self, func_id: FuncId, old: &Operand, new: &Operand) {
+        let Some(scope) = self.funcs.get_mut(&func_id) else {
+            return;
+        };
+        let (moved, kept) = std::mem::take(&mut self.globals)
+            .into_iter()
+            .partition(|x| x.loc.as_ref() == Some(old));
+        self.globals = kept;
+        scope.vars.extend(moved.into_iter().map(|var: VarInfo| VarInfo { loc: Some(new.clone()), ..var }));
+    }
+
+    /// Copies the variables of `from` into `into`, for a pass copying the
+    /// body of one function into another. `map` gives where each location
+    /// of `from` ended up in `into`, variables it maps to nothing are
+    /// optimized out.
+    pub fn merge_func(&mut self, from: FuncId, into: FuncId, map: impl Fn(&Operand) -> Option<Operand>) {
+        let Some(vars) = self.funcs.get(&from).map(|x| x.vars.clone()) else {
+            return;
+        };
+        let vars = vars.into_iter()
+            .map(move |var| VarInfo { loc: var.loc.as_ref().and_then(&map), ..var });
+        if let Some(scope) = self.funcs.get_mut(&into) {
+            scope.vars.extend(vars);
+        }
+    }
+
+    /// Takes in the scopes and globals of `other`, for linking the module it
+    /// describes into this one. `func_map` gives what each function of
+    /// `other` became, scopes of functions it maps to nothing are dropped,
+    /// and `map` where each location ended up.
+    pub fn link(&mut self, other: DebugInfo, func_map: impl Fn(FuncId) -> Option<FuncId>, map: impl Fn(&Operand) -> Operand) {
+        let relocate = |var: VarInfo| VarInfo { loc: var.loc.as_ref().map(&map), ..var };
+        for (func_id, scope) in other.funcs {
+            if let Some(func_id) = func_map(func_id) {
+                let vars = scope.vars.into_iter().map(relocate).collect();
+                self.funcs.insert(func_id, FuncScope { vars, ..scope });
+            }
+        }
+        self.globals.extend(other.globals.into_iter().map(relocate));
+    }
+
+    /// Records that `func_id`, linked in, is from `file`.
+    pub(super) fn set_func_file(&mut self, func_id: FuncId, file: Option<&str>) {
+        if let Some(file) = file {
+            self.func_files.insert(func_id, String::from(file));
+        }
+    }
+
+    /// Records that `global_id`, linked in, is from `file`.
+    pub(super) fn set_global_file(&mut self, global_id: GlobalId, file: Option<&str>) {
+        if let Some(file) = file {
+            self.global_files.insert(global_id, String::from(file));
+        }
+    }
+
+    /// Forgets `func_id`, for a pass deleting it.
+    pub fn remove_func(&mut self, func_id: FuncId) -> Option<FuncScope> {
+        self.func_files.remove(&func_id);
+        self.funcs.remove(&func_id)
+    }
+}
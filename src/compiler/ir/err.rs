@@ -0,0 +1,87 @@
+use std::fmt::{Display, Formatter};
+
+use crate::compiler::ir::{arena::{BBId, InstId}, value::ty::IrTy};
+
+/// A broken invariant of the IR, found by [`Module::verify`].
+///
+/// [`Module::verify`]: crate::compiler::ir::value::module::Module::verify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The block is empty or does not end with a `br` or `ret`.
+    MissingTerminator { func: String, bb: BBId },
+    /// A `br` or `ret` in the middle of the block.
+    EarlyTerminator { func: String, inst: InstId },
+    /// An instruction in a block other than the one it says it is in.
+    WrongBlock { func: String, inst: InstId },
+    /// An operand naming an instruction, parameter or global that does not
+    /// exist.
+    DanglingOperand { func: String, inst: InstId },
+    /// An undef or poison constant of a type values cannot have, such as
+    /// `void`.
+    InvalidConstant { func: String, inst: InstId },
+    /// A branch to a block not in the function.
+    UnknownTarget { func: String, inst: InstId },
+    /// A branch on a value of the wrong type.
+    InvalidCond { func: String, inst: InstId },
+    /// A select on a value other than an `i1`, or between values of another
+    /// type than its own.
+    InvalidSelect { func: String, inst: InstId },
+    /// A cast between types it cannot convert between.
+    InvalidCast { func: String, inst: InstId },
+    /// A `memset` or `memcpy` on something other than a pointer.
+    InvalidMemOp { func: String, inst: InstId },
+    /// An `insertelement` into something other than a vector of its own
+    /// type, of an element of another type or past the last lane.
+    InvalidInsertElement { func: String, inst: InstId },
+    /// A switch with two cases for the same value.
+    DuplicateCase { func: String, inst: InstId, val: i32 },
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::MissingTerminator { func, bb } => write!(f, "block {bb} in `{func}` has no terminator"),
+            VerifyError::EarlyTerminator { func, inst } => write!(f, "terminator {inst} in `{func}` is not at the end of its block"),
+            VerifyError::WrongBlock { func, inst } => write!(f, "instruction {inst} in `{func}` is not in the block it refers to"),
+            VerifyError::DanglingOperand { func, inst } => write!(f, "instruction {inst} in `{func}` uses a value that does not exist"),
+            VerifyError::InvalidConstant { func, inst } => write!(f, "instruction {inst} in `{func}` uses a constant of an invalid type"),
+            VerifyError::UnknownTarget { func, inst } => write!(f, "branch {inst} in `{func}` goes to a block not in the function"),
+            VerifyError::InvalidCond { func, inst } => write!(f, "branch {inst} in `{func}` has a condition of the wrong type"),
+            VerifyError::InvalidSelect { func, inst } => write!(f, "select {inst} in `{func}` has operands of the wrong type"),
+            VerifyError::InvalidCast { func, inst } => write!(f, "cast {inst} in `{func}` cannot convert between its types"),
+            VerifyError::InvalidMemOp { func, inst } => write!(f, "memory operation {inst} in `{func}` is not given pointers"),
+            VerifyError::InvalidInsertElement { func, inst } => write!(f, "insertelement {inst} in `{func}` has operands of the wrong type"),
+            VerifyError::DuplicateCase { func, inst, val } => write!(f, "switch {inst} in `{func}` has more than one case for {val}"),
+        }
+    }
+}
+
+/// Why two modules cannot be linked, found by [`Module::link`].
+///
+/// [`Module::link`]: crate::compiler::ir::value::module::Module::link
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkError {
+    /// Both modules define the external function or global.
+    DuplicateSymbol { name: String, prev_file: Option<String>, file: Option<String> },
+    /// The name is a function in one module and a global in the other.
+    KindMismatch { name: String, func_file: Option<String>, global_file: Option<String> },
+    /// The function is declared or defined with another type in the other
+    /// module.
+    TypeMismatch { name: String, expected: IrTy, found: IrTy, prev_file: Option<String>, file: Option<String> },
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::DuplicateSymbol { name, prev_file: Some(prev_file), file: Some(file) } => write!(f, "`{name}` is defined in both `{prev_file}` and `{file}`"),
+            LinkError::DuplicateSymbol { name, .. } => write!(f, "`{name}` is defined more than once"),
+            LinkError::KindMismatch { name, func_file, global_file } => write!(f, "`{name}` is a function{} and a global{}", in_file(func_file.as_deref()), in_file(global_file.as_deref())),
+            LinkError::TypeMismatch { name, expected, found, prev_file, file } => write!(f, "`{name}` has type `{found}`{} but was declared as `{expected}`{}", in_file(file.as_deref()), in_file(prev_file.as_deref())),
+        }
+    }
+}
+
+/// Where a symbol is, as said after it, or nothing for a module of no file.
+fn in_file(file: Option<&str>) -> String {
+    file.map_or_else(String::new, |file| format!(" in `{file}`"))
+}
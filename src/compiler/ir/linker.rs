@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, GlobalId},
+    clone::ValueMap,
+    err::LinkError,
+    value::{
+        inst::InstKind,
+        module::Module,
+        value::{Linkage, Value},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Symbol {
+    Func(FuncId),
+    Global(GlobalId),
+}
+
+/// What becomes of a function of the module being linked in.
+#[derive(Debug, Clone, Copy)]
+enum FuncResolution {
+    /// Moved over as a new function.
+    Add,
+    /// Moved over in place of the declaration it defines, so that calls to
+    /// the declaration reach it.
+    Replace(FuncId),
+    /// A declaration of a function already there, dropped.
+    Resolve(FuncId),
+}
+
+/// How the symbols of a module are to be linked into another.
+#[derive(Debug, Default)]
+struct Resolution {
+    /// Functions not listed are added.
+    funcs: HashMap<FuncId, FuncResolution>,
+    /// New names of the internal symbols of the module linked in whose
+    /// names are taken.
+    renames: HashMap<Symbol, String>,
+    /// New names of the internal symbols of the module linked into, whose
+    /// names the other module uses for external symbols.
+    self_renames: HashMap<Symbol, String>,
+}
+
+impl Module {
+    /// Moves every function and global of `other` into `self`, as when
+    /// compiling several source files into one program.
+    ///
+    /// External symbols are matched by name: a declaration is resolved to
+    /// the definition in the other module, or merged with its declaration
+    /// there, and must have the same type. Internal symbols are never
+    /// matched, and are renamed if their name is taken.
+    ///
+    /// # Errors
+    ///
+    /// Fails, leaving `self` unchanged, if both modules define the same
+    /// external symbol or a symbol is matched with one of another kind or
+    /// type.
+    pub fn link(&mut self, mut other: Module) -> Result<(), LinkError> {
+        // resolve everything before moving anything, so that a failed link
+        // leaves `self` as it was
+        let Resolution { funcs: resolutions, mut renames, self_renames } = self.resolve(&other)?;
+
+        for (symbol, name) in self_renames {
+            match symbol {
+                Symbol::Func(func_id) => self.func_arena[func_id].name = name,
+                Symbol::Global(global_id) => self.global_arena[global_id].name = name,
+            }
+        }
+
+        let mut map = ValueMap::new();
+        let global_ids: Vec<_> = other.global_arena.items_iter(other.first_global, None)
+            .map(|(global_id, _)| global_id)
+            .collect();
+        for global_id in global_ids.into_iter().rev() {
+            let mut global = other.global_arena.remove(global_id).unwrap_or_else(|| unreachable!());
+            if let Some(name) = renames.remove(&Symbol::Global(global_id)) {
+                global.name = name;
+            }
+            (global.prev, global.next) = (None, None);
+            let new_id = self.build_global(global);
+            self.debug_info.set_global_file(new_id, other.debug_info.global_file(global_id));
+            map.insert(global_id.into(), new_id.into());
+        }
+
+        let mut func_map = HashMap::new();
+        let mut moved = vec![];
+        let func_ids: Vec<_> = other.func_arena.items_iter(other.first_func, None)
+            .map(|(func_id, _)| func_id)
+            .collect();
+        for func_id in func_ids.into_iter().rev() {
+            let mut func = other.func_arena.remove(func_id).unwrap_or_else(|| unreachable!());
+            if let Some(name) = renames.remove(&Symbol::Func(func_id)) {
+                func.name = name;
+            }
+            let new_id = match resolutions.get(&func_id).copied().unwrap_or(FuncResolution::Add) {
+                FuncResolution::Add => {
+                    (func.prev, func.next) = (None, None);
+                    self.build_func(func)
+                }
+                FuncResolution::Replace(prev_id) => {
+                    let prev_func = &mut self.func_arena[prev_id];
+                    (func.prev, func.next) = (prev_func.prev, prev_func.next);
+                    *prev_func = func;
+                    prev_id
+                }
+                FuncResolution::Resolve(prev_id) => {
+                    func_map.insert(func_id, prev_id);
+                    continue;
+                }
+            };
+            self.debug_info.set_func_file(new_id, other.debug_info.func_file(func_id));
+            func_map.insert(func_id, new_id);
+            moved.push(new_id);
+        }
+
+        for &func_id in &moved {
+            let func = &mut self.func_arena[func_id];
+            let inst_ids: Vec<_> = func.inst_arena.keys().collect();
+            for inst_id in inst_ids {
+                let mut inst_kind = map.map_inst_kind(&func.inst_arena[inst_id].kind);
+                if let InstKind::Call(call) = &mut inst_kind {
+                    call.func_id = func_map[&call.func_id];
+                }
+                func.set_inst_kind(inst_id, inst_kind);
+            }
+        }
+
+        let debug_info = std::mem::take(&mut other.debug_info);
+        self.debug_info.link(debug_info, |x| func_map.get(&x).copied(), |x| map.map(x));
+        Ok(())
+    }
+
+    fn resolve(&self, other: &Module) -> Result<Resolution, LinkError> {
+        let externals: HashMap<String, Symbol> = self.symbols()
+            .filter(|&(_, linkage, _)| linkage == Linkage::External)
+            .map(|(name, _, symbol)| (String::from(name), symbol))
+            .collect();
+        let mut taken: HashSet<String> = self.symbols()
+            .chain(other.symbols())
+            .map(|(name, _, _)| String::from(name))
+            .collect();
+
+        let mut resolution = Resolution::default();
+        for (name, linkage, symbol) in other.symbols() {
+            if linkage == Linkage::Internal {
+                if externals.contains_key(name) || self.internal(name).is_some() {
+                    resolution.renames.insert(symbol, fresh_name(name, &mut taken));
+                }
+                continue;
+            }
+            match (symbol, externals.get(name)) {
+                (Symbol::Func(func_id), Some(&Symbol::Func(prev_id))) => {
+                    let (func, prev_func) = (&other.func_arena[func_id], &self.func_arena[prev_id]);
+                    if func.get_ty() != prev_func.get_ty() {
+                        return Err(LinkError::TypeMismatch {
+                            name: String::from(name),
+                            expected: prev_func.get_ty().clone(),
+                            found: func.get_ty().clone(),
+                            prev_file: self.debug_info.func_file(prev_id).map(String::from),
+                            file: other.debug_info.func_file(func_id).map(String::from),
+                        });
+                    }
+                    let func_resolution = match (prev_func.is_builtin, func.is_builtin) {
+                        (_, true) => FuncResolution::Resolve(prev_id),
+                        (true, false) => FuncResolution::Replace(prev_id),
+                        (false, false) => return Err(LinkError::DuplicateSymbol {
+                            name: String::from(name),
+                            prev_file: self.debug_info.func_file(prev_id).map(String::from),
+                            file: other.debug_info.func_file(func_id).map(String::from),
+                        }),
+                    };
+                    resolution.funcs.insert(func_id, func_resolution);
+                }
+                (Symbol::Global(global_id), Some(&Symbol::Global(prev_id))) => {
+                    return Err(LinkError::DuplicateSymbol {
+                        name: String::from(name),
+                        prev_file: self.debug_info.global_file(prev_id).map(String::from),
+                        file: other.debug_info.global_file(global_id).map(String::from),
+                    });
+                }
+                (Symbol::Func(func_id), Some(&Symbol::Global(global_id))) => {
+                    return Err(LinkError::KindMismatch {
+                        name: String::from(name),
+                        func_file: other.debug_info.func_file(func_id).map(String::from),
+                        global_file: self.debug_info.global_file(global_id).map(String::from),
+                    });
+                }
+                (Symbol::Global(global_id), Some(&Symbol::Func(func_id))) => {
+                    return Err(LinkError::KindMismatch {
+                        name: String::from(name),
+                        func_file: self.debug_info.func_file(func_id).map(String::from),
+                        global_file: other.debug_info.global_file(global_id).map(String::from),
+                    });
+                }
+                (_, None) => {
+                    if let Some(symbol) = self.internal(name) {
+                        resolution.self_renames.insert(symbol, fresh_name(name, &mut taken));
+                    }
+                }
+            }
+        }
+        Ok(resolution)
+    }
+
+    fn symbols(&self) -> impl Iterator<Item = (&str, Linkage, Symbol)> {
+        let funcs = self.func_arena.iter()
+            .map(|(func_id, func)| (func.name.as_str(), func.linkage, Symbol::Func(func_id)));
+        let globals = self.global_arena.iter()
+            .map(|(global_id, global)| (global.name.as_str(), global.linkage, Symbol::Global(global_id)));
+        funcs.chain(globals)
+    }
+
+    fn internal(&self, name: &str) -> Option<Symbol> {
+        self.symbols()
+            .find(|&(x, linkage, _)| x == name && linkage == Linkage::Internal)
+            .map(|(_, _, symbol)| symbol)
+    }
+}
+
+/// `name` with the first numeric suffix not in `taken`, which it is then
+/// added to.
+fn fresh_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let mut n = 1;
+    while taken.contains(&format!("{name}.{n}")) {
+        n += 1;
+    }
+    let name = format!("{name}.{n}");
+    taken.insert(name.clone());
+    name
+}
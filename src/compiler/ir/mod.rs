@@ -1,3 +1,9 @@
 pub mod arena;
+pub mod cfg;
+pub mod clone;
+pub mod debug_info;
 pub mod err;
+pub mod linker;
+pub mod pattern;
+pub mod verifier;
 pub mod value;
\ No newline at end of file
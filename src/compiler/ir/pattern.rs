@@ -0,0 +1,428 @@
+use std::cell::RefCell;
+
+use crate::compiler::ir::value::{
+    constant::Constant,
+    func::IrFunc,
+    inst::{BinaryInstOp, CastOp, InstKind},
+    value::Operand,
+};
+
+/// A shape of operand, for writing peephole rewrites declaratively:
+///
+/// ```ignore
+/// let x = Capture::new();
+/// if m_add(m_bind(&x), m_int(0)).matches(func, &inst_id.into()) {
+///     func.replace_all_uses_with(&inst_id.into(), &x.get());
+/// }
+/// ```
+///
+/// Instructions are matched through the operand naming them. Captures are
+/// bound as the match proceeds, so those of a failed match are meaningless.
+pub trait Pattern {
+    /// Whether `operand`, as used in `func`, has this shape.
+    fn matches(&self, func: &IrFunc, operand: &Operand) -> bool;
+}
+
+impl<P: Pattern + ?Sized> Pattern for &P {
+    fn matches(&self, func: &IrFunc, operand: &Operand) -> bool {
+        (**self).matches(func, operand)
+    }
+}
+
+/// A value bound by a successful match.
+#[derive(Debug)]
+pub struct Capture<T>(RefCell<Option<T>>);
+
+impl<T: Clone> Capture<T> {
+    #[must_use] pub fn new() -> Capture<T> {
+        Capture(RefCell::new(None))
+    }
+
+    /// # Panics
+    ///
+    /// If nothing was bound, i.e. no pattern using the capture matched.
+    #[must_use] pub fn get(&self) -> T {
+        self.0.borrow().clone().expect("capture read before any match bound it")
+    }
+
+    fn set(&self, val: T) {
+        *self.0.borrow_mut() = Some(val);
+    }
+}
+
+impl<T: Clone> Default for Capture<T> {
+    fn default() -> Self {
+        Capture::new()
+    }
+}
+
+fn inst_kind<'f>(func: &'f IrFunc, operand: &Operand) -> Option<&'f InstKind> {
+    match operand {
+        Operand::Inst(inst_id) => func.inst_arena.get(*inst_id).map(|x| &x.kind),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Any;
+
+impl Pattern for Any {
+    fn matches(&self, _func: &IrFunc, _operand: &Operand) -> bool {
+        true
+    }
+}
+
+/// Anything.
+#[must_use] pub fn m_any() -> Any {
+    Any
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Int(i32);
+
+impl Pattern for Int {
+    fn matches(&self, _func: &IrFunc, operand: &Operand) -> bool {
+        matches!(operand, Operand::Const(Constant::Int(x)) if *x == self.0)
+    }
+}
+
+/// The integer constant `val`.
+#[must_use] pub fn m_int(val: i32) -> Int {
+    Int(val)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BindInt<'a>(&'a Capture<i32>);
+
+impl Pattern for BindInt<'_> {
+    fn matches(&self, _func: &IrFunc, operand: &Operand) -> bool {
+        let Operand::Const(Constant::Int(x)) = operand else {
+            return false;
+        };
+        self.0.set(*x);
+        true
+    }
+}
+
+/// Any integer constant, bound to `capture`.
+#[must_use] pub fn m_bind_int(capture: &Capture<i32>) -> BindInt<'_> {
+    BindInt(capture)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Undef;
+
+impl Pattern for Undef {
+    fn matches(&self, _func: &IrFunc, operand: &Operand) -> bool {
+        operand.as_const().is_some_and(Constant::is_undef)
+    }
+}
+
+/// An undef constant.
+#[must_use] pub fn m_undef() -> Undef {
+    Undef
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bind<'a, P> {
+    capture: &'a Capture<Operand>,
+    pattern: P,
+}
+
+impl<P: Pattern> Pattern for Bind<'_, P> {
+    fn matches(&self, func: &IrFunc, operand: &Operand) -> bool {
+        if !self.pattern.matches(func, operand) {
+            return false;
+        }
+        self.capture.set(operand.clone());
+        true
+    }
+}
+
+/// Anything, bound to `capture`.
+#[must_use] pub fn m_bind(capture: &Capture<Operand>) -> Bind<'_, Any> {
+    Bind { capture, pattern: Any }
+}
+
+/// What `pattern` matches, bound to `capture` as a whole.
+#[must_use] pub fn m_capture<P: Pattern>(capture: &Capture<Operand>, pattern: P) -> Bind<'_, P> {
+    Bind { capture, pattern }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Same<'a>(&'a Capture<Operand>);
+
+impl Pattern for Same<'_> {
+    fn matches(&self, _func: &IrFunc, operand: &Operand) -> bool {
+        self.0.0.borrow().as_ref() == Some(operand)
+    }
+}
+
+/// The operand `capture` was bound to earlier in the same match, as in
+/// `m_sub(m_bind(&x), m_same(&x))`.
+#[must_use] pub fn m_same(capture: &Capture<Operand>) -> Same<'_> {
+    Same(capture)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OneUse<P>(P);
+
+impl<P: Pattern> Pattern for OneUse<P> {
+    fn matches(&self, func: &IrFunc, operand: &Operand) -> bool {
+        func.users(operand).len() == 1 && self.0.matches(func, operand)
+    }
+}
+
+/// What `pattern` matches, used by a single instruction, so that it dies
+/// once that instruction is rewritten.
+#[must_use] pub fn m_one_use<P: Pattern>(pattern: P) -> OneUse<P> {
+    OneUse(pattern)
+}
+
+/// Which instructions a [`BinaryPattern`] accepts.
+#[derive(Debug, Clone, Copy)]
+enum BinaryOpPattern<'a> {
+    Is(BinaryInstOp),
+    /// Any comparison, bound to the capture.
+    Cmp(&'a Capture<BinaryInstOp>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BinaryPattern<'a, L, R> {
+    op: BinaryOpPattern<'a>,
+    left: L,
+    right: R,
+    commuted: bool,
+}
+
+impl<L: Pattern, R: Pattern> Pattern for BinaryPattern<'_, L, R> {
+    fn matches(&self, func: &IrFunc, operand: &Operand) -> bool {
+        let Some(InstKind::Binary(binary)) = inst_kind(func, operand) else {
+            return false;
+        };
+        match self.op {
+            BinaryOpPattern::Is(op) if op != binary.op => return false,
+            BinaryOpPattern::Cmp(_) if !binary.op.is_cmp() => return false,
+            BinaryOpPattern::Is(_) => {}
+            BinaryOpPattern::Cmp(capture) => capture.set(binary.op),
+        }
+        if self.left.matches(func, &binary.left) && self.right.matches(func, &binary.right) {
+            return true;
+        }
+        if !self.commuted {
+            return false;
+        }
+        if let BinaryOpPattern::Cmp(capture) = self.op {
+            capture.set(binary.op.swapped().unwrap_or(binary.op));
+        }
+        self.left.matches(func, &binary.right) && self.right.matches(func, &binary.left)
+    }
+}
+
+/// A binary instruction `op` with operands in this order.
+#[must_use] pub fn m_binary<L: Pattern, R: Pattern>(op: BinaryInstOp, left: L, right: R) -> BinaryPattern<'static, L, R> {
+    BinaryPattern { op: BinaryOpPattern::Is(op), left, right, commuted: false }
+}
+
+/// A binary instruction `op` with operands in either order. `op` should be
+/// commutative.
+#[must_use] pub fn m_commutative<L: Pattern, R: Pattern>(op: BinaryInstOp, left: L, right: R) -> BinaryPattern<'static, L, R> {
+    BinaryPattern { op: BinaryOpPattern::Is(op), left, right, commuted: true }
+}
+
+/// Any comparison, its predicate bound to `pred`.
+#[must_use] pub fn m_cmp<L: Pattern, R: Pattern>(pred: &Capture<BinaryInstOp>, left: L, right: R) -> BinaryPattern<'_, L, R> {
+    BinaryPattern { op: BinaryOpPattern::Cmp(pred), left, right, commuted: false }
+}
+
+/// Any comparison with operands in either order, its predicate bound to
+/// `pred` as if they were in this order.
+#[must_use] pub fn m_commuted_cmp<L: Pattern, R: Pattern>(pred: &Capture<BinaryInstOp>, left: L, right: R) -> BinaryPattern<'_, L, R> {
+    BinaryPattern { op: BinaryOpPattern::Cmp(pred), left, right, commuted: true }
+}
+
+#[must_use] pub fn m_add<L: Pattern, R: Pattern>(left: L, right: R) -> BinaryPattern<'static, L, R> {
+    m_binary(BinaryInstOp::Add, left, right)
+}
+
+#[must_use] pub fn m_sub<L: Pattern, R: Pattern>(left: L, right: R) -> BinaryPattern<'static, L, R> {
+    m_binary(BinaryInstOp::Sub, left, right)
+}
+
+#[must_use] pub fn m_mul<L: Pattern, R: Pattern>(left: L, right: R) -> BinaryPattern<'static, L, R> {
+    m_binary(BinaryInstOp::Mul, left, right)
+}
+
+#[must_use] pub fn m_div<L: Pattern, R: Pattern>(left: L, right: R) -> BinaryPattern<'static, L, R> {
+    m_binary(BinaryInstOp::Div, left, right)
+}
+
+#[must_use] pub fn m_mod<L: Pattern, R: Pattern>(left: L, right: R) -> BinaryPattern<'static, L, R> {
+    m_binary(BinaryInstOp::Mod, left, right)
+}
+
+/// `0 - val`, the way negations are written.
+#[must_use] pub fn m_neg<P: Pattern>(val: P) -> BinaryPattern<'static, Int, P> {
+    m_sub(m_int(0), val)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CastPattern<P> {
+    op: CastOp,
+    val: P,
+}
+
+impl<P: Pattern> Pattern for CastPattern<P> {
+    fn matches(&self, func: &IrFunc, operand: &Operand) -> bool {
+        match inst_kind(func, operand) {
+            Some(InstKind::Cast(cast)) => cast.op == self.op && self.val.matches(func, &cast.ori_val),
+            _ => false,
+        }
+    }
+}
+
+/// A cast `op` of what `val` matches.
+#[must_use] pub fn m_cast<P: Pattern>(op: CastOp, val: P) -> CastPattern<P> {
+    CastPattern { op, val }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SelectPattern<C, T, F> {
+    cond: C,
+    true_val: T,
+    false_val: F,
+}
+
+impl<C: Pattern, T: Pattern, F: Pattern> Pattern for SelectPattern<C, T, F> {
+    fn matches(&self, func: &IrFunc, operand: &Operand) -> bool {
+        match inst_kind(func, operand) {
+            Some(InstKind::Select(select)) => {
+                self.cond.matches(func, &select.cond)
+                    && self.true_val.matches(func, &select.true_val)
+                    && self.false_val.matches(func, &select.false_val)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[must_use] pub fn m_select<C: Pattern, T: Pattern, F: Pattern>(cond: C, true_val: T, false_val: F) -> SelectPattern<C, T, F> {
+    SelectPattern { cond, true_val, false_val }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LoadPattern<P>(P);
+
+impl<P: Pattern> Pattern for LoadPattern<P> {
+    fn matches(&self, func: &IrFunc, operand: &Operand) -> bool {
+        match inst_kind(func, operand) {
+            Some(InstKind::Load(load)) => self.0.matches(func, &load.addr),
+            _ => false,
+        }
+    }
+}
+
+/// A load from what `addr` matches.
+#[must_use] pub fn m_load<P: Pattern>(addr: P) -> LoadPattern<P> {
+    LoadPattern(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::ir::value::{inst::Binary, ty::IrTy};
+
+    use super::*;
+
+    /// A function of `a` and `b`, with a block to build in.
+    struct Builder {
+        func: IrFunc,
+        a: Operand,
+        b: Operand,
+    }
+
+    impl Builder {
+        fn new() -> Builder {
+            let mut func = IrFunc::new("f", IrTy::int(), false);
+            let a = func.build_func_param(IrTy::int()).into();
+            let b = func.build_func_param(IrTy::int()).into();
+            func.build_bb();
+            Builder { func, a, b }
+        }
+
+        fn binary(&mut self, op: BinaryInstOp, left: impl Into<Operand>, right: impl Into<Operand>) -> Operand {
+            let ty = if op.is_cmp() { IrTy::bool() } else { IrTy::int() };
+            let binary = Binary { op, left: left.into(), right: right.into() };
+            let bb = self.func.first_block.unwrap();
+            self.func.build_inst_at_end(InstKind::Binary(binary), ty, bb).into()
+        }
+    }
+
+    #[test]
+    fn binds_operands_and_constants() {
+        let mut f = Builder::new();
+        let a = f.a.clone();
+        let add = f.binary(BinaryInstOp::Add, a.clone(), 5);
+        let (x, c, whole) = (Capture::new(), Capture::new(), Capture::new());
+        assert!(m_capture(&whole, m_add(m_bind(&x), m_bind_int(&c))).matches(&f.func, &add));
+        assert_eq!(x.get(), a);
+        assert_eq!(c.get(), 5);
+        assert_eq!(whole.get(), add);
+    }
+
+    #[test]
+    fn rejects_other_shapes() {
+        let mut f = Builder::new();
+        let (a, b) = (f.a.clone(), f.b.clone());
+        let add = f.binary(BinaryInstOp::Add, a.clone(), 5);
+        let sub = f.binary(BinaryInstOp::Sub, a.clone(), b);
+        assert!(!m_add(m_any(), m_int(6)).matches(&f.func, &add));
+        assert!(!m_sub(m_any(), m_any()).matches(&f.func, &add));
+        assert!(!m_add(m_any(), m_any()).matches(&f.func, &a));
+        assert!(!m_int(5).matches(&f.func, &a));
+        assert!(!m_sub(m_any(), m_bind_int(&Capture::new())).matches(&f.func, &sub));
+        let x = Capture::new();
+        assert!(!m_sub(m_bind(&x), m_same(&x)).matches(&f.func, &sub));
+        let same = f.binary(BinaryInstOp::Sub, a.clone(), a);
+        assert!(m_sub(m_bind(&x), m_same(&x)).matches(&f.func, &same));
+    }
+
+    #[test]
+    fn matches_commutative_operands_in_either_order() {
+        let mut f = Builder::new();
+        let a = f.a.clone();
+        let add = f.binary(BinaryInstOp::Add, 5, a.clone());
+        let x = Capture::new();
+        assert!(!m_add(m_bind(&x), m_int(5)).matches(&f.func, &add));
+        assert!(m_commutative(BinaryInstOp::Add, m_bind(&x), m_int(5)).matches(&f.func, &add));
+        assert_eq!(x.get(), a);
+    }
+
+    #[test]
+    fn swaps_predicates_of_commuted_comparisons() {
+        let mut f = Builder::new();
+        let a = f.a.clone();
+        let lt = f.binary(BinaryInstOp::Lt, 5, a.clone());
+        let pred = Capture::new();
+        assert!(m_cmp(&pred, m_int(5), m_any()).matches(&f.func, &lt));
+        assert_eq!(pred.get(), BinaryInstOp::Lt);
+        assert!(!m_cmp(&pred, m_any(), m_int(5)).matches(&f.func, &lt));
+        // `5 < a` is `a > 5`
+        assert!(m_commuted_cmp(&pred, m_any(), m_int(5)).matches(&f.func, &lt));
+        assert_eq!(pred.get(), BinaryInstOp::Gt);
+
+        let add = f.binary(BinaryInstOp::Add, a, 5);
+        assert!(!m_cmp(&pred, m_any(), m_any()).matches(&f.func, &add));
+    }
+
+    #[test]
+    fn one_use_counts_users() {
+        let mut f = Builder::new();
+        let (a, b) = (f.a.clone(), f.b.clone());
+        let add = f.binary(BinaryInstOp::Add, a, b);
+        let mul = f.binary(BinaryInstOp::Mul, add.clone(), 2);
+        assert!(m_mul(m_one_use(m_add(m_any(), m_any())), m_int(2)).matches(&f.func, &mul));
+        f.binary(BinaryInstOp::Sub, add.clone(), 1);
+        assert!(!m_mul(m_one_use(m_add(m_any(), m_any())), m_int(2)).matches(&f.func, &mul));
+        assert!(m_mul(m_add(m_any(), m_any()), m_int(2)).matches(&f.func, &mul));
+    }
+}
@@ -13,6 +13,11 @@ pub struct BasicBlock {
 
     pub prev: Option<BBId>,
     pub next: Option<BBId>,
+
+    /// Whether the block is expected to run rarely, set by
+    /// [`HotColdSplit`](crate::compiler::pass::hot_cold_split::HotColdSplit)
+    /// for the backend to place it out of line.
+    pub is_cold: bool,
 }
 
 impl Value for BasicBlock {
@@ -3,13 +3,19 @@ use std::fmt::{Debug, Display, Formatter};
 use enum_as_inner::EnumAsInner;
 use itertools::Itertools;
 
-use super::{ty::IrTy, value::Value};
+use super::{inst::BinaryInstOp, ty::IrTy, value::Value};
 
 #[derive(Debug, Clone, Eq, Hash, EnumAsInner)]
 pub enum Constant {
     Int(i32),
     // empty vec represents zero initializer
     Array { ty: IrTy, elems: Vec<Constant> },
+    /// An unspecified value, which may differ at each use. Uninitialized
+    /// memory reads as undef.
+    Undef(IrTy),
+    /// The result of an operation that went wrong. Any operation on it gives
+    /// poison, and branching on it is undefined behavior.
+    Poison(IrTy),
 }
 
 impl Constant {
@@ -26,6 +32,40 @@ impl Constant {
     }
 }
 
+impl Constant {
+    #[must_use] pub fn is_undef(&self) -> bool {
+        matches!(self, Constant::Undef(_))
+    }
+
+    #[must_use] pub fn is_poison(&self) -> bool {
+        matches!(self, Constant::Poison(_))
+    }
+
+    /// Folds `left op right` when either is undef or poison, which any
+    /// constant folder must do this way to be consistent with the others.
+    /// `None` if neither is, or the result depends on the other operand.
+    ///
+    /// Poison spreads to the result. An undef operand stands for whatever
+    /// value makes the result simplest, so `undef * x` is 0, but it cannot
+    /// make a division trap or a shift go past the width, so `x / undef` and
+    /// `x << undef` are left alone.
+    #[must_use] pub fn fold_binary_undef(op: BinaryInstOp, left: &Constant, right: &Constant) -> Option<Constant> {
+        use BinaryInstOp::{AShr, Add, And, Div, Eq, Ge, Gt, LShr, Le, Lt, Mod, Mul, Ne, Or, Shl, Sub};
+        let result_ty = match op {
+            Lt | Le | Gt | Ge | Eq | Ne => IrTy::bool(),
+            _ => left.get_ty().clone(),
+        };
+        if left.is_poison() || right.is_poison() {
+            return Some(Constant::Poison(result_ty));
+        }
+        match (op, left.is_undef(), right.is_undef()) {
+            (_, false, false) | (Div | Mod | Shl | AShr | LShr, _, true) | (Or, _, _) => None,
+            (Mul | And | Div | Mod | Shl | AShr | LShr, _, _) => Some(Constant::Int(0)),
+            (Add | Sub | Lt | Le | Gt | Ge | Eq | Ne, _, _) => Some(Constant::Undef(result_ty)),
+        }
+    }
+}
+
 impl PartialEq for Constant {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -39,7 +79,7 @@ impl Value for Constant {
     fn get_ty(&self) -> &IrTy {
         match self {
             Constant::Int(_) => &IrTy::Int(32),
-            Constant::Array { ty, .. } => ty,
+            Constant::Array { ty, .. } | Constant::Undef(ty) | Constant::Poison(ty) => ty,
         }
     }
 }
@@ -63,7 +103,9 @@ impl Display for Constant {
                     write!(f, "{ty} [{data_str}]")
                 }
             }
-            _ => unreachable!()
+            Constant::Undef(ty) => write!(f, "{ty} undef"),
+            Constant::Poison(ty) => write!(f, "{ty} poison"),
+            Constant::Array { .. } => unreachable!()
         }
     }
 }
@@ -1,46 +1,76 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::{Display, Formatter};
 
 use itertools::Itertools;
 
 use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::arena::{BBId, InstId};
 use crate::compiler::ir::value::constant::Constant;
-use crate::compiler::ir::value::func::IrFunc;
-use crate::compiler::ir::value::inst::{BinaryInstOp, Br, InstKind};
+use crate::compiler::ir::value::func::{InlineHint, IrFunc, IrFuncParam};
+use crate::compiler::ir::value::inst::{BinaryInstOp, Br, CastOp, InstKind};
 use crate::compiler::ir::value::module::Module;
 use crate::compiler::ir::value::ty::IrTy;
-use crate::compiler::ir::value::value::{Operand, Value};
+use crate::compiler::ir::value::value::{Linkage, Operand, Value};
+
+/// How a module is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Ir,
+    /// See [`Module::debug_display`].
+    DebugIr,
+    /// See [`Module::llvm_display`].
+    Llvm { opaque_ptrs: bool },
+}
 
 #[derive(Debug, Clone)]
 struct VRegManager<'a> {
     cnt: usize,
-    map: HashMap<Operand, usize>,
+    map: HashMap<Operand, String>,
+    /// Uses of each source name so far, `None` unless printing names.
+    name_cnts: Option<HashMap<&'a str, usize>>,
+    opaque_ptrs: bool,
     module: &'a Module,
     func: &'a IrFunc,
 }
 
 impl<'a> VRegManager<'a> {
-    pub fn new(module: &'a Module, func: &'a IrFunc) -> VRegManager<'a> {
+    pub fn new(module: &'a Module, func: &'a IrFunc, style: Style) -> VRegManager<'a> {
         VRegManager {
             cnt: 0,
             map: HashMap::default(),
+            name_cnts: (style == Style::DebugIr).then(HashMap::default),
+            opaque_ptrs: style == Style::Llvm { opaque_ptrs: true },
             module,
             func,
         }
     }
 
-    pub fn build_vreg(&mut self, operand: Operand) -> usize {
-        let id = self.cnt;
-        self.cnt += 1;
-        self.map.insert(operand, id);
-        id
+    pub fn ty(&self, ty: &IrTy) -> String {
+        print_ty(ty, self.opaque_ptrs)
+    }
+
+    /// Names `operand` after its source variable if names are printed and it
+    /// has one (`a`, then `a.1`, `a.2`, ...), or numbers it otherwise.
+    pub fn build_vreg(&mut self, operand: Operand) -> String {
+        let vreg = if let (Some(name), Some(name_cnts)) = (self.func.value_names.get(&operand), &mut self.name_cnts) {
+            let cnt = name_cnts.entry(name.as_str()).or_insert(0);
+            let vreg = if *cnt == 0 { name.clone() } else { format!("{name}.{cnt}") };
+            *cnt += 1;
+            vreg
+        } else {
+            let id = self.cnt;
+            self.cnt += 1;
+            id.to_string()
+        };
+        self.map.insert(operand, vreg.clone());
+        vreg
     }
 
-    pub fn get_vreg(&self, operand: &Operand) -> Option<usize> {
-        self.map.get(operand).copied()
+    pub fn get_vreg(&self, operand: &Operand) -> Option<&str> {
+        self.map.get(operand).map(String::as_str)
     }
 
-    pub fn get_vreg_unwrap(&self, operand: &Operand) -> usize {
+    pub fn get_vreg_unwrap(&self, operand: &Operand) -> &str {
         self.get_vreg(operand).unwrap()
     }
 
@@ -48,207 +78,445 @@ impl<'a> VRegManager<'a> {
         match operand {
             Operand::Inst(x) => {
                 let ty = &self.func.inst_arena.get(*x).unwrap().ty;
-                format!("{} %{}", ty, self.get_vreg_unwrap(operand))
+                format!("{} %{}", self.ty(ty), self.get_vreg_unwrap(operand))
             }
             Operand::Const(x) => {
                 let ty = x.get_ty();
                 match x {
                     Constant::Int(x) => format!("{ty} {x}"),
-                    _ => unreachable!()
+                    Constant::Array { .. } | Constant::Undef(_) | Constant::Poison(_) => format!("{x}"),
                 }
             }
             Operand::Global(x) => {
                 let global = self.module.global_arena.get(*x).unwrap();
-                let ty = &global.ty;
+                let ty = self.ty(&global.ty);
                 let val = &global.name;
                 format!("{ty} @{val}")
             }
             Operand::Param(x) => {
                 let ty = &self.func.get_param(*x).unwrap().ty;
-                format!("{} %{}", ty, self.get_vreg_unwrap(operand))
+                format!("{} %{}", self.ty(ty), self.get_vreg_unwrap(operand))
             }
             Operand::BB(_) => {
                 format!("{} %{}", IrTy::Label, self.get_vreg_unwrap(operand))
             }
         }
     }
+
+    /// Numbers the parameters, blocks and instructions producing a value of
+    /// the function, in the order they are printed.
+    pub fn build_func_vregs(&mut self) {
+        let func = self.func;
+        for &param_id in &func.params {
+            self.build_vreg(param_id.into());
+        }
+        for (bb_id, bb) in func.bb_arena.items_iter(func.first_block, None) {
+            self.build_vreg(bb_id.into());
+            for (inst_id, inst) in func.inst_arena.items_iter(bb.insts_head, None) {
+                use InstKind::{Alloca, Binary, Call, Cast, GEP, InsertElement, Load, Select};
+                match &inst.kind {
+                    Binary(_) | Alloca(_) | Load(_) | GEP(_) | Cast(_) | Select(_) | InsertElement(_) => {
+                        self.build_vreg(inst_id.into());
+                    }
+                    Call(_) if matches!(inst.ty, IrTy::Int(_)) => {
+                        self.build_vreg(inst_id.into());
+                    }
+                    _ => {}
+                };
+            }
+        }
+    }
+
+    pub fn print_inst(&self, inst_id: InstId) -> String {
+        let inst = self.func.get_inst(inst_id).unwrap();
+        match &inst.kind {
+            InstKind::Binary(binary_inst) => {
+                let dst = self.get_vreg_unwrap(&inst_id.into());
+                let lhs = self.print(&binary_inst.left);
+                let rhs = match &binary_inst.right {
+                    Operand::Inst(_) | Operand::Param(_) => format!("%{}", self.get_vreg_unwrap(&binary_inst.right)),
+                    Operand::Const(Constant::Int(x)) => format!("{x}"),
+                    _ => unreachable!()
+                };
+
+                format!("%{} = {} {}, {}", dst, binary_inst.op, lhs, rhs)
+            }
+            InstKind::Br(branch_inst) => self.print_br(branch_inst),
+            InstKind::RetInst(return_inst) => {
+                match &return_inst.val {
+                    None => String::from("ret void"),
+                    Some(operand) => format!("ret {}", self.print(operand)),
+                }
+            }
+            InstKind::Alloca(alloca_inst) => {
+                let dst_ptr = self.get_vreg_unwrap(&Operand::from(inst_id));
+                format!("%{} = alloca {}", dst_ptr, self.ty(&alloca_inst.alloca_ty))
+            }
+            InstKind::Load(load_inst) => {
+                let dst = self.get_vreg_unwrap(&Operand::from(inst_id));
+                let addr = self.print(&load_inst.addr);
+                format!("%{} = load {}, {}{}", dst, self.ty(&inst.ty), addr, align(&inst.ty))
+            }
+            InstKind::Store(store_inst) => {
+                let data = self.print(&store_inst.data);
+                let addr = self.print(&store_inst.addr);
+                let align = align(&self.module.operand_ty(self.func, &store_inst.data));
+                format!("store {data}, {addr}{align}")
+            }
+            InstKind::GEP(gep_inst) => {
+                let indices = gep_inst.indices.iter()
+                    .map(|x| self.print(x)).join(", ");
+                let ty = match &gep_inst.ptr {
+                    Operand::Inst(inst) => &self.func.get_inst(*inst).unwrap().ty,
+                    Operand::Global(g) => &self.module.global_arena.get(*g).unwrap().ty,
+                    Operand::Param(p) => &self.func.get_param(*p).unwrap().ty,
+                    _ => unreachable!()
+                };
+                let ty = self.ty(&IrTy::deptr_of(ty).unwrap());
+
+                let dst = self.get_vreg_unwrap(&Operand::from(inst_id));
+                let addr = self.print(&gep_inst.ptr);
+
+                format!("%{dst} = getelementptr {ty}, {addr}, {indices}")
+            }
+            InstKind::Cast(cast_inst) => {
+                let dst = self.get_vreg_unwrap(&Operand::from(inst_id));
+                let src = self.print(&cast_inst.ori_val);
+                format!("%{} = {} {} to {}", dst, cast_inst.op, src, self.ty(&cast_inst.target_ty))
+            }
+            InstKind::Call(call_inst) => {
+                let callee = self.module.func_arena.get(call_inst.func_id).unwrap();
+                let tail = if call_inst.is_tail { "tail " } else { "" };
+                let call = match inst.ty {
+                    IrTy::Void => format!("{tail}call void"),
+                    IrTy::Int(_) => {
+                        let dst = self.get_vreg_unwrap(&Operand::from(inst_id));
+                        format!("%{dst} = {tail}call i32")
+                    }
+                    _ => unreachable!()
+                };
+                let args_str = call_inst.args.iter()
+                    .map(|x| self.print(x))
+                    .join(", ");
+                format!("{} @{}({})", call, callee.name, args_str)
+            }
+            InstKind::MemSet(_) | InstKind::MemCpy(_) => self.print_mem_intrinsic(&inst.kind),
+            InstKind::Select(select) => {
+                let dst = self.get_vreg_unwrap(&Operand::from(inst_id));
+                let [cond, true_val, false_val] = [&select.cond, &select.true_val, &select.false_val].map(|x| self.print(x));
+                format!("%{dst} = select {cond}, {true_val}, {false_val}")
+            }
+            InstKind::InsertElement(insert) => {
+                let dst = self.get_vreg_unwrap(&Operand::from(inst_id));
+                let [vector, elem] = [&insert.vector, &insert.elem].map(|x| self.print(x));
+                format!("%{dst} = insertelement {vector}, {elem}, i32 {}", insert.lane)
+            }
+        }
+    }
+
+    pub fn print_mem_intrinsic(&self, inst_kind: &InstKind) -> String {
+        let (name, _) = mem_intrinsic(self.module, self.func, inst_kind, self.opaque_ptrs).unwrap();
+        let args = match inst_kind {
+            InstKind::MemSet(x) => format!("{}, i8 {}, i32 {}", self.print(&x.dst), x.byte, x.len * WORD_BYTES),
+            InstKind::MemCpy(x) => format!("{}, {}, i32 {}", self.print(&x.dst), self.print(&x.src), x.len * WORD_BYTES),
+            _ => unreachable!(),
+        };
+        format!("call void @{name}({args}, i1 false)")
+    }
+
+    pub fn print_br(&self, br: &Br) -> String {
+        let print_bb = |bb: BBId| self.print(&Operand::from(bb));
+        match br {
+            Br::Br { cond, true_bb, false_bb } => {
+                format!("br {}, {}, {}", self.print(cond), print_bb(*true_bb), print_bb(*false_bb))
+            }
+            Br::Jump { nxt_bb } => format!("br {}", print_bb(*nxt_bb)),
+            Br::Switch { cond, cases, default } => {
+                let cases = cases.iter()
+                    .map(|&(val, bb)| format!("i32 {val}, {}", print_bb(bb)))
+                    .join(" ");
+                format!("switch {}, {} [ {cases} ]", self.print(cond), print_bb(*default))
+            }
+        }
+    }
+}
+
+const WORD_BYTES: usize = 4;
+
+/// The alignment to give a load or store of `ty`, with its leading comma.
+/// LLVM takes a vector to be aligned to its whole size by default, while
+/// vectors are only ever loaded from arrays of words.
+fn align(ty: &IrTy) -> String {
+    match ty {
+        IrTy::Vector(..) => format!(", align {WORD_BYTES}"),
+        _ => String::new(),
+    }
+}
+
+/// `ty` as printed, with every pointer type spelled `ptr` if `opaque_ptrs`.
+fn print_ty(ty: &IrTy, opaque_ptrs: bool) -> String {
+    match ty {
+        IrTy::Ptr(_) if opaque_ptrs => String::from("ptr"),
+        IrTy::Array(dim_size, elem_ty) => format!("[{dim_size} x {}]", print_ty(elem_ty, opaque_ptrs)),
+        _ => ty.to_string(),
+    }
+}
+
+/// The LLVM intrinsic a memory instruction is printed as a call to, as its
+/// name and parameter types.
+fn mem_intrinsic(module: &Module, func: &IrFunc, inst_kind: &InstKind, opaque_ptrs: bool) -> Option<(String, Vec<IrTy>)> {
+    let len_params = [IrTy::Int(32), IrTy::bool()];
+    let mangle = |ty: &IrTy| mangle(ty, opaque_ptrs);
+    match inst_kind {
+        InstKind::MemSet(x) => {
+            let dst_ty = module.operand_ty(func, &x.dst);
+            let name = format!("llvm.memset.{}.i32", mangle(&dst_ty));
+            Some((name, [dst_ty, IrTy::Int(8)].into_iter().chain(len_params).collect()))
+        }
+        InstKind::MemCpy(x) => {
+            let dst_ty = module.operand_ty(func, &x.dst);
+            let src_ty = module.operand_ty(func, &x.src);
+            let name = format!("llvm.memcpy.{}.{}.i32", mangle(&dst_ty), mangle(&src_ty));
+            Some((name, [dst_ty, src_ty].into_iter().chain(len_params).collect()))
+        }
+        _ => None,
+    }
+}
+
+/// How LLVM spells `ty` in the name of an intrinsic overloaded on it. Opaque
+/// pointers are all `p0`, whatever they point to.
+fn mangle(ty: &IrTy, opaque_ptrs: bool) -> String {
+    match ty {
+        IrTy::Int(x) => format!("i{x}"),
+        IrTy::Ptr(_) if opaque_ptrs => String::from("p0"),
+        IrTy::Ptr(x) => format!("p0{}", mangle(x, opaque_ptrs)),
+        IrTy::Array(siz, x) => format!("a{siz}{}", mangle(x, opaque_ptrs)),
+        IrTy::Vector(lanes, x) => format!("v{lanes}{}", mangle(x, opaque_ptrs)),
+        _ => unreachable!(),
+    }
+}
+
+/// `s` as an LLVM string literal, with quotes, backslashes and control
+/// characters escaped as hex.
+fn llvm_string(s: &str) -> String {
+    let escaped: String = s.bytes()
+        .map(|x| if x == b'"' || x == b'\\' || !(0x20..0x7f).contains(&x) { format!("\\{x:02X}") } else { char::from(x).to_string() })
+        .collect();
+    format!("\"{escaped}\"")
+}
+
+/// The function attribute asking for `hint`, with its leading space.
+fn inline_attr(hint: InlineHint) -> &'static str {
+    match hint {
+        InlineHint::Auto => "",
+        InlineHint::Always => " alwaysinline",
+        InlineHint::Never => " noinline",
+    }
+}
+
+/// External linkage is the default and is left implicit.
+fn linkage_prefix(linkage: Linkage) -> &'static str {
+    match linkage {
+        Linkage::External => "",
+        Linkage::Internal => "internal ",
+    }
 }
 
 impl Display for Module {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_module(f, Style::Ir)
+    }
+}
+
+/// See [`Module::debug_display`] and [`Module::llvm_display`].
+struct StyledModule<'a>(&'a Module, Style);
+
+impl Display for StyledModule<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_module(f, self.1)
+    }
+}
+
+impl Module {
+    /// Prints the module like [`Display`] does, except that values built for
+    /// a source variable are named after it (`%a.addr`, `%a.1`) instead of
+    /// numbered. Meant for reading the IR while debugging passes.
+    #[must_use] pub fn debug_display(&self) -> impl Display + '_ {
+        StyledModule(self, Style::DebugIr)
+    }
+
+    /// Prints the module as LLVM IR that `llc` and `clang` take as is, to
+    /// compile it with LLVM instead. Pointers are typed, as LLVM 14 and
+    /// earlier require, unless `opaque_ptrs`, as LLVM 17 and later do.
+    #[must_use] pub fn llvm_display(&self, opaque_ptrs: bool) -> impl Display + '_ {
+        StyledModule(self, Style::Llvm { opaque_ptrs })
+    }
+
+    /// Declarations of the intrinsics the module calls.
+    fn intrinsic_decls(&self, opaque_ptrs: bool) -> BTreeSet<String> {
+        self.func_arena.values()
+            .flat_map(|func| func.inst_arena.values().filter_map(|x| mem_intrinsic(self, func, &x.kind, opaque_ptrs)))
+            .map(|(name, params)| format!("declare void @{name}({})", params.iter().map(|x| print_ty(x, opaque_ptrs)).join(", ")))
+            .collect()
+    }
+
+    fn fmt_module(&self, f: &mut Formatter<'_>, style: Style) -> std::fmt::Result {
+        let is_llvm = matches!(style, Style::Llvm { .. });
+        let opaque_ptrs = style == Style::Llvm { opaque_ptrs: true };
+        if let (true, Some(file)) = (is_llvm, &self.debug_info.file) {
+            writeln!(f, "source_filename = {}", llvm_string(file))?;
+            writeln!(f)?;
+        }
+
         // print globals
         for (_, global) in self.global_arena.items_iter(self.first_global, None) {
-            writeln!(f, "@{} = global {}", global.name, global.init_val)?;
+            let kind = if global.is_const { "constant" } else { "global" };
+            writeln!(f, "@{} = {}{kind} {}", global.name, linkage_prefix(global.linkage), global.init_val)?;
+            writeln!(f)?;
+        }
+
+        for decl in self.intrinsic_decls(opaque_ptrs) {
+            writeln!(f, "{decl}")?;
             writeln!(f)?;
         }
 
         for (_, func) in self.func_arena.items_iter(self.first_func, None) {
             if func.is_builtin {
                 let param_str = func.params.iter()
-                    .map(|&param_id| {
-                        let param = func.get_param(param_id).unwrap();
-                        format!("{}", param.ty)
-                    })
+                    .map(|&param_id| print_param(func.get_param(param_id).unwrap(), opaque_ptrs))
                     .join(", ");
-                writeln!(f, "declare {} @{}({}) #1", func.ret_ty, func.name, param_str)?;
+                // the runtime library is linked in by the toolchain, which
+                // knows nothing of attribute group #1
+                let attrs = if is_llvm { "" } else { " #1" };
+                writeln!(f, "declare {} @{}({}){attrs}", func.ret_ty, func.name, param_str)?;
                 writeln!(f)?;
                 continue;
             }
-            let mut vregs = VRegManager::new(self, func);
-
-            // print params
-            let param_str = func.params.iter()
-                .map(|&param_id| {
-                    let param = func.get_param(param_id).unwrap();
-                    format!("{} %{}", param.ty, vregs.build_vreg(param_id.into()))
-                })
-                .join(", ");
-
-            writeln!(f, "define {} @{}({}) {{", func.ret_ty, func.name, param_str)?;
-
-            // build vregs
-            for (bb_id, bb) in func.bb_arena.items_iter(func.first_block, None) {
-                vregs.build_vreg(bb_id.into());
-
-                let mut inst_iter = bb.insts_head;
-                while let Some(inst_id) = inst_iter {
-                    use InstKind::{Alloca, Binary, Call, GEP, Load, ZExt};
-                    let inst = func.get_inst(inst_id).unwrap();
-                    match &inst.kind {
-                        Binary(_) | Alloca(_) | Load(_) | GEP(_) | ZExt(_) => {
-                            vregs.build_vreg(inst_id.into());
-                        }
-                        Call(_) if matches!(inst.ty, IrTy::Int(_)) => {
-                            vregs.build_vreg(inst_id.into());
-                        }
-                        _ => {}
-                    };
-                    inst_iter = inst.next;
-                }
+            self.fmt_func(f, func, style)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+
+    /// Prints the definition of `func`, one of the functions of the module.
+    fn fmt_func(&self, f: &mut Formatter<'_>, func: &IrFunc, style: Style) -> std::fmt::Result {
+        let opaque_ptrs = style == Style::Llvm { opaque_ptrs: true };
+        let mut vregs = VRegManager::new(self, func, style);
+        vregs.build_func_vregs();
+
+        let param_str = func.params.iter()
+            .map(|&param_id| {
+                let param = print_param(func.get_param(param_id).unwrap(), opaque_ptrs);
+                format!("{} %{}", param, vregs.get_vreg_unwrap(&param_id.into()))
+            })
+            .join(", ");
+
+        writeln!(f, "define {}{} @{}({}){} {{", linkage_prefix(func.linkage), func.ret_ty, func.name, param_str, inline_attr(func.inline_hint))?;
+
+        for (bb_id, bb) in func.bb_arena.items_iter(func.first_block, None) {
+            let cold = if bb.is_cold { "\t\t; cold" } else { "" };
+            writeln!(f, "{}:{cold}", vregs.get_vreg_unwrap(&bb_id.into()))?;
+            for (inst_id, _) in func.inst_arena.items_iter(bb.insts_head, None) {
+                writeln!(f, "\t{}", vregs.print_inst(inst_id))?;
             }
+        }
+        writeln!(f, "}}")
+    }
+}
 
-            for (bb_id, bb) in func.bb_arena.items_iter(func.first_block, None) {
-                writeln!(f, "{}:", vregs.get_vreg_unwrap(&bb_id.into()))?;
-
-                let mut inst_iter = bb.insts_head;
-                while let Some(inst_id) = inst_iter {
-                    write!(f, "\t")?;
-                    let inst = func.get_inst(inst_id).unwrap();
-
-                    match &inst.kind {
-                        InstKind::Binary(binary_inst) => {
-                            let dst = vregs.get_vreg_unwrap(&inst_id.into());
-                            let lhs = vregs.print(&binary_inst.left);
-                            let rhs = match &binary_inst.right {
-                                Operand::Inst(_) | Operand::Param(_) => format!("%{}", vregs.get_vreg_unwrap(&binary_inst.right)),
-                                Operand::Const(Constant::Int(x)) => format!("{x}"),
-                                _ => unreachable!()
-                            };
-
-                            writeln!(f, "%{} = {} {}, {}", dst, binary_inst.op, lhs, rhs)?;
-                        }
-                        InstKind::Br(branch_inst) => {
-                            match branch_inst {
-                                Br::Br { cond, true_bb, false_bb } => {
-                                    let cond = vregs.print(cond);
-                                    let true_bb = vregs.print(&Operand::from(*true_bb));
-                                    let false_bb = vregs.print(&Operand::from(*false_bb));
-                                    writeln!(f, "br {cond}, {true_bb}, {false_bb}")?;
-                                }
-                                Br::Jump { nxt_bb } => {
-                                    let nxt_bb = vregs.print(&Operand::from(*nxt_bb));
-                                    writeln!(f, "br {nxt_bb}")?;
-                                }
-                            };
-                        }
-                        InstKind::RetInst(return_inst) => {
-                            match &return_inst.val {
-                                None => writeln!(f, "ret void")?,
-                                Some(operand) => {
-                                    let ret_val = vregs.print(operand);
-                                    writeln!(f, "ret {ret_val}")?;
-                                },
-                            };
-                        }
-                        InstKind::Alloca(alloca_inst) => {
-                            let dst_ptr = vregs.get_vreg_unwrap(&Operand::from(inst_id));
-                            writeln!(f, "%{} = alloca {}", dst_ptr, alloca_inst.alloca_ty)?;
-                        }
-                        InstKind::Load(load_inst) => {
-                            let dst = vregs.get_vreg_unwrap(&Operand::from(inst_id));
-                            let addr = vregs.print(&load_inst.addr);
-                            writeln!(f, "%{} = load {}, {}", dst, inst.ty, addr)?;
-                        }
-                        InstKind::Store(store_inst) => {
-                            let data = vregs.print(&store_inst.data);
-                            let addr = vregs.print(&store_inst.addr);
-                            writeln!(f, "store {data}, {addr}")?;
-                        }
-                        InstKind::GEP(gep_inst) => {
-                            let indices = gep_inst.indices.iter()
-                                .map(|x| vregs.print(x)).join(", ");
-                            let ty = match &gep_inst.ptr {
-                                Operand::Inst(inst) => &func.get_inst(*inst).unwrap().ty,
-                                Operand::Global(g) => &self.global_arena.get(*g).unwrap().ty,
-                                Operand::Param(p) => &func.get_param(*p).unwrap().ty,
-                                _ => unreachable!()
-                            };
-                            let ty = IrTy::deptr_of(ty).unwrap();
-
-                            let dst = vregs.get_vreg_unwrap(&Operand::from(inst_id));
-                            let addr = vregs.print(&gep_inst.ptr);
-
-                            writeln!(f, "%{dst} = getelementptr {ty}, {addr}, {indices}")?;
-                        }
-                        InstKind::ZExt(zext_inst) => {
-                            let dst = vregs.get_vreg_unwrap(&Operand::from(inst_id));
-                            let src = vregs.print(&zext_inst.ori_val);
-                            writeln!(f, "%{} = zext {} to {}", dst, src, zext_inst.target_ty)?;
-                        }
-                        InstKind::Call(call_inst) => {
-                            let callee = self.func_arena.get(call_inst.func_id).unwrap();
-                            match inst.ty {
-                                IrTy::Void => write!(f, "call void ")?,
-                                IrTy::Int(_) => {
-                                    let dst = vregs.get_vreg_unwrap(&Operand::from(inst_id));
-                                    write!(f, "%{dst} = call i32 ")?;
-                                }
-                                _ => unreachable!()
-                            }
-                            let args_str = call_inst.args.iter()
-                                .map(|x| vregs.print(x))
-                                .join(", ");
-                            writeln!(f, "@{}({})", callee.name, args_str)?;
-                        }
-                    }
-                    inst_iter = inst.next;
+/// See [`IrFunc::display`].
+struct FuncDisplay<'a>(&'a Module, &'a IrFunc);
+
+impl Display for FuncDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_func(f, self.1, Style::Ir)
+    }
+}
+
+impl IrFunc {
+    /// The definition of the function, as [`Display`] for its module
+    /// `module` prints it.
+    #[must_use] pub fn display<'a>(&'a self, module: &'a Module) -> impl Display + 'a {
+        FuncDisplay(module, self)
+    }
+
+    /// The control flow graph as a Graphviz digraph, one node per block
+    /// listing its instructions as [`Module::debug_display`] prints them.
+    /// Conditional edges are labelled with the branch they are taken on.
+    #[must_use] pub fn to_dot<'a>(&'a self, module: &'a Module) -> impl Display + 'a {
+        DotFunc { module, func: self }
+    }
+}
+
+/// See [`IrFunc::to_dot`].
+struct DotFunc<'a> {
+    module: &'a Module,
+    func: &'a IrFunc,
+}
+
+impl Display for DotFunc<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let func = self.func;
+        let mut vregs = VRegManager::new(self.module, func, Style::DebugIr);
+        vregs.build_func_vregs();
+        let node = |bb: BBId| format!("bb{}", vregs.get_vreg_unwrap(&bb.into()));
+
+        writeln!(f, "digraph \"{}\" {{", escape_dot(&func.name))?;
+        writeln!(f, "\tnode [shape=box, fontname=\"monospace\"];")?;
+        for (bb_id, bb) in func.bb_arena.items_iter(func.first_block, None) {
+            write!(f, "\t{} [label=\"{}:\\l", node(bb_id), vregs.get_vreg_unwrap(&bb_id.into()))?;
+            for (inst_id, _) in func.inst_arena.items_iter(bb.insts_head, None) {
+                write!(f, "{}\\l", escape_dot(&vregs.print_inst(inst_id)))?;
+            }
+            writeln!(f, "\"];")?;
+        }
+        for bb_id in func.bb_ids() {
+            let Some(InstKind::Br(br)) = func.terminator(bb_id).map(|x| &func.inst_arena[x].kind) else {
+                continue;
+            };
+            let edges = match br {
+                Br::Br { true_bb, false_bb, .. } => vec![(*true_bb, Some(String::from("true"))), (*false_bb, Some(String::from("false")))],
+                Br::Jump { nxt_bb } => vec![(*nxt_bb, None)],
+                Br::Switch { cases, default, .. } => cases.iter()
+                    .map(|&(val, bb)| (bb, Some(val.to_string())))
+                    .chain([(*default, Some(String::from("default")))])
+                    .collect(),
+            };
+            for (succ, label) in edges {
+                write!(f, "\t{} -> {}", node(bb_id), node(succ))?;
+                if let Some(label) = label {
+                    write!(f, " [label=\"{label}\"]")?;
                 }
+                writeln!(f, ";")?;
             }
-            writeln!(f, "}}")?;
-            writeln!(f)?;
         }
-        Ok(())
+        writeln!(f, "}}")
     }
 }
 
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
 impl Display for BinaryInstOp {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        use BinaryInstOp::{Add, And, Div, Eq, Ge, Gt, Le, Lt, Mod, Mul, Ne, Or, Sub};
-        let op_str = match &self {
-            Add => "add",
-            Sub => "sub",
-            Mul => "mul",
-            Div => "sdiv",
-            Mod => "srem",
-            Lt => "icmp slt",
-            Le => "icmp sle",
-            Gt => "icmp sgt",
-            Ge => "icmp sge",
-            Eq => "icmp eq",
-            Ne => "icmp ne",
-            And => "and",
-            Or => "or",
-        };
-        write!(f, "{op_str}")
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Display for IrFuncParam {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", print_param(self, false))
+    }
+}
+
+fn print_param(param: &IrFuncParam, opaque_ptrs: bool) -> String {
+    let noalias = if param.noalias { " noalias" } else { "" };
+    format!("{}{noalias}", print_ty(&param.ty, opaque_ptrs))
+}
+
+impl Display for CastOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
     }
 }
@@ -1,13 +1,59 @@
-use slotmap::SlotMap;
+use std::collections::HashMap;
+
+use itertools::Itertools;
 
 use crate::compiler::intrusive_linkedlist::{IntrusiveLinkedList, IntrusiveLinkedListItem};
-use crate::compiler::ir::arena::{BBId, FuncId, InstId, ParamId};
-use crate::compiler::ir::value::{basic_block::BasicBlock, inst::{Inst, InstKind}, ty::IrTy, value::Value};
+use crate::compiler::ir::arena::{Arena, BBId, FuncId, InstId, ParamId};
+use crate::compiler::ir::value::{basic_block::BasicBlock, inst::{Inst, InstKind}, ty::IrTy, value::{Linkage, Operand, Value}};
+
+/// Where in a function to build an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertPoint {
+    Before(InstId),
+    After(InstId),
+    /// Before the first instruction of the block.
+    Start(BBId),
+    /// After the last instruction of the block.
+    End(BBId),
+}
+
+impl InsertPoint {
+    /// The block instructions built at the point end up in.
+    #[must_use] pub fn bb(self, func: &IrFunc) -> BBId {
+        match self {
+            InsertPoint::Before(x) | InsertPoint::After(x) => func.inst_arena[x].bb,
+            InsertPoint::Start(x) | InsertPoint::End(x) => x,
+        }
+    }
+
+    /// The point to build the next instruction at after building `inst_id`
+    /// at this one, so that instructions built one after another keep their
+    /// order.
+    #[must_use] pub fn after_built(self, inst_id: InstId) -> InsertPoint {
+        match self {
+            InsertPoint::Before(_) | InsertPoint::End(_) => self,
+            InsertPoint::After(_) | InsertPoint::Start(_) => InsertPoint::After(inst_id),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct IrFuncParam {
     pub ty: IrTy,
     pub pos: usize,
+    /// The pointer is the only way the function reaches the memory behind it.
+    pub noalias: bool,
+}
+
+/// What the source asked of inlining calls to a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InlineHint {
+    /// Left to the inliner's cost model.
+    #[default]
+    Auto,
+    /// Inlined wherever the call is not recursive.
+    Always,
+    Never,
 }
 
 #[derive(Debug, Default)]
@@ -15,14 +61,25 @@ pub struct IrFunc {
     pub name: String,
     pub ret_ty: IrTy,
     pub is_builtin: bool,
+    pub linkage: Linkage,
+    pub inline_hint: InlineHint,
     pub params: Vec<ParamId>,
     ty: IrTy,
 
     pub first_block: Option<BBId>,
 
-    pub param_arena: SlotMap<ParamId, IrFuncParam>,
-    pub inst_arena: SlotMap<InstId, Inst>,
-    pub bb_arena: SlotMap<BBId, BasicBlock>,
+    pub param_arena: Arena<ParamId, IrFuncParam>,
+    pub inst_arena: Arena<InstId, Inst>,
+    pub bb_arena: Arena<BBId, BasicBlock>,
+
+    /// Source variable each value came from, only used to name values when
+    /// printing, as [`Module::debug_display`](crate::compiler::ir::value::module::Module::debug_display)
+    /// and the C backend do.
+    pub value_names: HashMap<Operand, String>,
+    /// Instructions using each instruction, parameter and global, once per
+    /// use. Kept up to date by every method that adds, changes or removes an
+    /// instruction.
+    uses: HashMap<Operand, Vec<InstId>>,
 
     pub prev: Option<FuncId>,
     pub next: Option<FuncId>,
@@ -60,13 +117,18 @@ impl IrFunc {
             name: String::from(name),
             ret_ty: ret_ty.clone(),
             is_builtin,
+            linkage: Linkage::External,
+            inline_hint: InlineHint::Auto,
             params: vec![],
             first_block: None,
             ty: IrTy::func_of(ret_ty, vec![]),
 
-            param_arena: SlotMap::with_key(),
-            inst_arena: SlotMap::with_key(),
-            bb_arena: SlotMap::with_key(),
+            param_arena: Arena::new(),
+            inst_arena: Arena::new(),
+            bb_arena: Arena::new(),
+
+            value_names: HashMap::new(),
+            uses: HashMap::new(),
 
             prev: None,
             next: None
@@ -76,7 +138,7 @@ impl IrFunc {
     pub fn build_func_param(&mut self, ty: IrTy) -> ParamId {
         self.ty.as_func_mut().unwrap().params_ty.push(ty.clone());
         let pos = self.params.len();
-        let param = IrFuncParam { ty, pos };
+        let param = IrFuncParam { ty, pos, noalias: false };
         let param_id = self.param_arena.insert(param);
         self.params.push(param_id);
         param_id
@@ -92,18 +154,23 @@ impl IrFunc {
         self.param_arena.get(param_id)
     }
 
+    /// Changing the operands through this leaves the use lists stale, use
+    /// [`IrFunc::set_inst_kind`] or [`IrFunc::replace_all_uses_with`] instead.
     pub fn get_inst_mut(&mut self, inst_id: InstId) -> Option<&mut Inst> {
         self.inst_arena.get_mut(inst_id)
     }
 
     fn new_inst(&mut self, inst_kind: InstKind, ty: IrTy, bb: BBId) -> InstId {
-        self.inst_arena.insert(Inst {
+        let inst_id = self.inst_arena.insert(Inst {
             kind: inst_kind,
             ty,
+            loc: None,
             bb,
             prev: None,
             next: None
-        })
+        });
+        self.add_uses(inst_id);
+        inst_id
     }
 
     pub fn set_inst_before_cur(&mut self, before: InstId, cur_inst: InstId) {
@@ -160,6 +227,15 @@ impl IrFunc {
         new_inst_id
     }
 
+    pub fn build_inst_at(&mut self, inst_kind: InstKind, ty: IrTy, point: InsertPoint) -> InstId {
+        match point {
+            InsertPoint::Before(x) => self.build_inst_before_cur(inst_kind, ty, x),
+            InsertPoint::After(x) => self.build_inst_after_cur(inst_kind, ty, x),
+            InsertPoint::Start(x) => self.build_inst_at_start(inst_kind, ty, x),
+            InsertPoint::End(x) => self.build_inst_at_end(inst_kind, ty, x),
+        }
+    }
+
     pub fn build_inst_at_start(&mut self, inst_kind: InstKind, ty: IrTy, bb: BBId) -> InstId {
         let new_inst_id = self.new_inst(inst_kind, ty, bb);
         let bb = self.get_bb_mut(bb).unwrap();
@@ -219,4 +295,101 @@ impl IrFunc {
         }
         new_bb
     }
-}
\ No newline at end of file
+}
+
+impl IrFunc {
+    /// Instructions using `value`, each listed once in the order the uses
+    /// were made. Constants and blocks have no use list.
+    #[must_use] pub fn users(&self, value: &Operand) -> Vec<InstId> {
+        self.uses.get(value)
+            .into_iter()
+            .flatten()
+            .copied()
+            .unique()
+            .collect()
+    }
+
+    #[must_use] pub fn has_uses(&self, value: &Operand) -> bool {
+        self.uses.get(value).is_some_and(|x| !x.is_empty())
+    }
+
+    /// Makes every instruction using `old` use `new` instead.
+    pub fn replace_all_uses_with(&mut self, old: &Operand, new: &Operand) {
+        if old == new {
+            return;
+        }
+        let Some(users) = self.uses.remove(old) else {
+            return;
+        };
+        for &user in users.iter().unique() {
+            for operand in self.inst_arena[user].kind.operands_mut() {
+                if operand == old {
+                    *operand = new.clone();
+                }
+            }
+        }
+        if is_tracked(new) {
+            self.uses.entry(new.clone()).or_default().extend(users);
+        }
+    }
+
+    /// Replaces what `inst_id` does, updating the use lists of both its old
+    /// and new operands.
+    pub fn set_inst_kind(&mut self, inst_id: InstId, inst_kind: InstKind) {
+        self.drop_uses(inst_id);
+        self.inst_arena[inst_id].kind = inst_kind;
+        self.add_uses(inst_id);
+    }
+
+    /// Unlinks `inst_id` from its block and deletes it. The instruction
+    /// should have no users left.
+    pub fn remove_inst(&mut self, inst_id: InstId) -> Inst {
+        debug_assert!(!self.has_uses(&Operand::Inst(inst_id)), "Removing an instruction still in use");
+        self.unlink_inst(inst_id);
+
+        self.drop_uses(inst_id);
+        self.uses.remove(&Operand::Inst(inst_id));
+        self.value_names.remove(&Operand::Inst(inst_id));
+        self.inst_arena.remove_item(inst_id)
+    }
+
+    /// Moves `inst_id` from where it is to just before `before`, possibly in
+    /// another block.
+    pub fn move_inst_before(&mut self, inst_id: InstId, before: InstId) {
+        self.unlink_inst(inst_id);
+        self.set_inst_before_cur(inst_id, before);
+    }
+
+    /// Takes `inst_id` out of its block, leaving it in no list.
+    fn unlink_inst(&mut self, inst_id: InstId) {
+        let Inst { bb: bb_id, prev, next, .. } = self.inst_arena[inst_id];
+        let bb = &mut self.bb_arena[bb_id];
+        if bb.insts_head == Some(inst_id) {
+            bb.insts_head = next;
+        }
+        if bb.insts_tail == Some(inst_id) {
+            bb.insts_tail = prev;
+        }
+        self.inst_arena.detach(inst_id);
+    }
+
+    fn add_uses(&mut self, inst_id: InstId) {
+        let inst = &self.inst_arena[inst_id];
+        for operand in inst.kind.operands().into_iter().filter(|x| is_tracked(x)) {
+            self.uses.entry(operand.clone()).or_default().push(inst_id);
+        }
+    }
+
+    fn drop_uses(&mut self, inst_id: InstId) {
+        let inst = &self.inst_arena[inst_id];
+        for operand in inst.kind.operands().into_iter().filter(|x| is_tracked(x)) {
+            let users = self.uses.get_mut(operand).unwrap();
+            let pos = users.iter().position(|&x| x == inst_id).unwrap();
+            users.remove(pos);
+        }
+    }
+}
+
+fn is_tracked(value: &Operand) -> bool {
+    matches!(value, Operand::Inst(_) | Operand::Param(_) | Operand::Global(_))
+}
@@ -1,7 +1,7 @@
 use crate::compiler::intrusive_linkedlist::IntrusiveLinkedListItem;
 use crate::compiler::ir::{
     arena::GlobalId,
-    value::{ty::IrTy, value::Value, constant::Constant}
+    value::{ty::IrTy, value::{Linkage, Value}, constant::Constant}
 };
 
 #[derive(Debug, Clone)]
@@ -9,6 +9,10 @@ pub struct Global {
     pub ty: IrTy,
     pub name: String,
     pub init_val: Constant,
+    pub linkage: Linkage,
+    /// Never stored to, so that it can be placed in read-only data and
+    /// shared between declarations of the same value.
+    pub is_const: bool,
 
     pub prev: Option<GlobalId>,
     pub next: Option<GlobalId>,
@@ -20,6 +24,8 @@ impl Global {
             ty,
             name: String::from(name),
             init_val,
+            linkage: Linkage::External,
+            is_const: false,
             prev: None,
             next: None
         }
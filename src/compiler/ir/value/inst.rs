@@ -1,18 +1,46 @@
 use enum_as_inner::EnumAsInner;
 
 use crate::compiler::intrusive_linkedlist::IntrusiveLinkedListItem;
-use crate::compiler::syntax::ast::BinaryOp;
+use crate::compiler::span::Span;
+use crate::compiler::syntax::ast::{BinaryOp, NodeId};
 
 use super::{
     super::arena::{BBId, FuncId, InstId},
+    constant::Constant,
     ty::IrTy,
     value::{Operand, Value},
 };
 
+/// The source an instruction was built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SrcLoc {
+    pub span: Span,
+    /// The expression it computes, `None` for the statement-level parts of
+    /// the code such as branches.
+    pub node_id: Option<NodeId>,
+}
+
+impl SrcLoc {
+    /// The location of an instruction standing for both `a` and `b`, e.g.
+    /// when a pass folds two identical ones into one. Stepping onto it must
+    /// not suggest either source over the other, so differing spans merge
+    /// into no location at all.
+    #[must_use] pub fn merge(a: Option<SrcLoc>, b: Option<SrcLoc>) -> Option<SrcLoc> {
+        match (a, b) {
+            (Some(a), Some(b)) if a == b => Some(a),
+            (Some(a), Some(b)) if a.span == b.span => Some(SrcLoc { span: a.span, node_id: None }),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Inst {
     pub kind: InstKind,
     pub ty: IrTy,
+    /// `None` for instructions made up by the compiler, such as the default
+    /// `ret` at the end of a function.
+    pub loc: Option<SrcLoc>,
 
     pub bb: BBId,
     pub prev: Option<InstId>,
@@ -59,12 +87,76 @@ pub enum InstKind {
     Load(Load),
     Store(Store),
     GEP(GEP),
+    MemSet(MemSet),
+    MemCpy(MemCpy),
 
     // Conversion
-    ZExt(ZExt),
+    Cast(Cast),
 
     // Other
     Call(Call),
+    Select(Select),
+    InsertElement(InsertElement),
+}
+
+impl InstKind {
+    /// Values read by the instruction, in operand order. Branch targets are
+    /// not values and are left out.
+    #[must_use] pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            InstKind::Binary(x) => vec![&x.left, &x.right],
+            InstKind::Br(Br::Br { cond, .. } | Br::Switch { cond, .. }) => vec![cond],
+            InstKind::Br(Br::Jump { .. }) | InstKind::Alloca(_) => vec![],
+            InstKind::RetInst(x) => x.val.iter().collect(),
+            InstKind::Load(x) => vec![&x.addr],
+            InstKind::Store(x) => vec![&x.addr, &x.data],
+            InstKind::GEP(x) => std::iter::once(&x.ptr).chain(&x.indices).collect(),
+            InstKind::MemSet(x) => vec![&x.dst],
+            InstKind::MemCpy(x) => vec![&x.dst, &x.src],
+            InstKind::Cast(x) => vec![&x.ori_val],
+            InstKind::Call(x) => x.args.iter().collect(),
+            InstKind::Select(x) => vec![&x.cond, &x.true_val, &x.false_val],
+            InstKind::InsertElement(x) => vec![&x.vector, &x.elem],
+        }
+    }
+
+    /// The kind of instruction, as named in the printed IR.
+    #[must_use] pub fn opcode(&self) -> &'static str {
+        match self {
+            InstKind::Binary(x) => x.op.name(),
+            InstKind::Br(Br::Switch { .. }) => "switch",
+            InstKind::Br(_) => "br",
+            InstKind::RetInst(_) => "ret",
+            InstKind::Alloca(_) => "alloca",
+            InstKind::Load(_) => "load",
+            InstKind::Store(_) => "store",
+            InstKind::GEP(_) => "getelementptr",
+            InstKind::MemSet(_) => "memset",
+            InstKind::MemCpy(_) => "memcpy",
+            InstKind::Cast(x) => x.op.name(),
+            InstKind::Call(_) => "call",
+            InstKind::Select(_) => "select",
+            InstKind::InsertElement(_) => "insertelement",
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            InstKind::Binary(x) => vec![&mut x.left, &mut x.right],
+            InstKind::Br(Br::Br { cond, .. } | Br::Switch { cond, .. }) => vec![cond],
+            InstKind::Br(Br::Jump { .. }) | InstKind::Alloca(_) => vec![],
+            InstKind::RetInst(x) => x.val.iter_mut().collect(),
+            InstKind::Load(x) => vec![&mut x.addr],
+            InstKind::Store(x) => vec![&mut x.addr, &mut x.data],
+            InstKind::GEP(x) => std::iter::once(&mut x.ptr).chain(&mut x.indices).collect(),
+            InstKind::MemSet(x) => vec![&mut x.dst],
+            InstKind::MemCpy(x) => vec![&mut x.dst, &mut x.src],
+            InstKind::Cast(x) => vec![&mut x.ori_val],
+            InstKind::Call(x) => x.args.iter_mut().collect(),
+            InstKind::Select(x) => vec![&mut x.cond, &mut x.true_val, &mut x.false_val],
+            InstKind::InsertElement(x) => vec![&mut x.vector, &mut x.elem],
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -74,7 +166,7 @@ pub struct Binary {
     pub right: Operand,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BinaryInstOp {
     Add,
     Sub,
@@ -89,6 +181,126 @@ pub enum BinaryInstOp {
     Ne,
     And,
     Or,
+    Shl,
+    /// Shifts right, filling with the sign bit.
+    AShr,
+    /// Shifts right, filling with zeros.
+    LShr,
+}
+
+impl BinaryInstOp {
+    /// The LLVM instruction, with its predicate for comparisons.
+    #[must_use] pub fn name(self) -> &'static str {
+        use BinaryInstOp::{AShr, Add, And, Div, Eq, Ge, Gt, LShr, Le, Lt, Mod, Mul, Ne, Or, Shl, Sub};
+        match self {
+            Add => "add",
+            Sub => "sub",
+            Mul => "mul",
+            Div => "sdiv",
+            Mod => "srem",
+            Lt => "icmp slt",
+            Le => "icmp sle",
+            Gt => "icmp sgt",
+            Ge => "icmp sge",
+            Eq => "icmp eq",
+            Ne => "icmp ne",
+            And => "and",
+            Or => "or",
+            Shl => "shl",
+            AShr => "ashr",
+            LShr => "lshr",
+        }
+    }
+
+    /// `left op right`, wrapping on overflow, with comparisons giving 0 or
+    /// 1. `None` if it divides by zero or shifts by 32 bits or more.
+    #[must_use] pub fn fold(self, left: i32, right: i32) -> Option<i32> {
+        use BinaryInstOp::{AShr, Add, And, Div, Eq, Ge, Gt, LShr, Le, Lt, Mod, Mul, Ne, Or, Shl, Sub};
+        let shift = || u32::try_from(right).ok().filter(|&x| x < i32::BITS);
+        Some(match self {
+            Add => left.wrapping_add(right),
+            Sub => left.wrapping_sub(right),
+            Mul => left.wrapping_mul(right),
+            Div | Mod if right == 0 => return None,
+            Div => left.wrapping_div(right),
+            Mod => left.wrapping_rem(right),
+            Lt => i32::from(left < right),
+            Le => i32::from(left <= right),
+            Gt => i32::from(left > right),
+            Ge => i32::from(left >= right),
+            Eq => i32::from(left == right),
+            Ne => i32::from(left != right),
+            And => left & right,
+            Or => left | right,
+            Shl => left << shift()?,
+            AShr => left >> shift()?,
+            LShr => (left.cast_unsigned() >> shift()?).cast_signed(),
+        })
+    }
+
+    /// [`BinaryInstOp::fold`] for 64-bit operands.
+    #[must_use] pub fn fold_wide(self, left: i64, right: i64) -> Option<i64> {
+        use BinaryInstOp::{AShr, Add, And, Div, Eq, Ge, Gt, LShr, Le, Lt, Mod, Mul, Ne, Or, Shl, Sub};
+        let shift = || u32::try_from(right).ok().filter(|&x| x < i64::BITS);
+        Some(match self {
+            Add => left.wrapping_add(right),
+            Sub => left.wrapping_sub(right),
+            Mul => left.wrapping_mul(right),
+            Div | Mod if right == 0 => return None,
+            Div => left.wrapping_div(right),
+            Mod => left.wrapping_rem(right),
+            Lt => i64::from(left < right),
+            Le => i64::from(left <= right),
+            Gt => i64::from(left > right),
+            Ge => i64::from(left >= right),
+            Eq => i64::from(left == right),
+            Ne => i64::from(left != right),
+            And => left & right,
+            Or => left | right,
+            Shl => left << shift()?,
+            AShr => left >> shift()?,
+            LShr => (left.cast_unsigned() >> shift()?).cast_signed(),
+        })
+    }
+
+    /// Whether swapping the operands gives the same result.
+    #[must_use] pub fn is_commutative(self) -> bool {
+        use BinaryInstOp::{Add, And, Eq, Mul, Ne, Or};
+        matches!(self, Add | Mul | Eq | Ne | And | Or)
+    }
+
+    /// Whether the instruction is an `icmp`.
+    #[must_use] pub fn is_cmp(self) -> bool {
+        self.swapped().is_some()
+    }
+
+    /// The comparison giving the same result with its operands swapped.
+    #[must_use] pub fn swapped(self) -> Option<BinaryInstOp> {
+        use BinaryInstOp::{Eq, Ge, Gt, Le, Lt, Ne};
+        Some(match self {
+            Lt => Gt,
+            Le => Ge,
+            Gt => Lt,
+            Ge => Le,
+            Eq => Eq,
+            Ne => Ne,
+            _ => return None,
+        })
+    }
+
+    /// The comparison giving the opposite result.
+    #[must_use] pub fn negated(self) -> Option<BinaryInstOp> {
+        use BinaryInstOp::{Eq, Ge, Gt, Le, Lt, Ne};
+        Some(match self {
+            Lt => Ge,
+            Le => Gt,
+            Gt => Le,
+            Ge => Lt,
+            Eq => Ne,
+            Ne => Eq,
+            _ => return None,
+        })
+    }
 }
 
 impl BinaryOp {
@@ -115,6 +327,28 @@ impl BinaryOp {
 pub enum Br {
     Br { cond: Operand, true_bb: BBId, false_bb: BBId },
     Jump { nxt_bb: BBId },
+    /// Goes to the block of the case equal to `cond`, or to `default` if
+    /// none is. Case values are distinct.
+    Switch { cond: Operand, cases: Vec<(i32, BBId)>, default: BBId },
+}
+
+impl Br {
+    /// Blocks the branch may go to, with duplicates.
+    #[must_use] pub fn targets(&self) -> Vec<BBId> {
+        match self {
+            Br::Br { true_bb, false_bb, .. } => vec![*true_bb, *false_bb],
+            Br::Jump { nxt_bb } => vec![*nxt_bb],
+            Br::Switch { cases, default, .. } => std::iter::once(*default).chain(cases.iter().map(|x| x.1)).collect(),
+        }
+    }
+
+    pub fn targets_mut(&mut self) -> Vec<&mut BBId> {
+        match self {
+            Br::Br { true_bb, false_bb, .. } => vec![true_bb, false_bb],
+            Br::Jump { nxt_bb } => vec![nxt_bb],
+            Br::Switch { cases, default, .. } => std::iter::once(default).chain(cases.iter_mut().map(|x| &mut x.1)).collect(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -144,14 +378,120 @@ pub struct GEP {
     pub indices: Vec<Operand>,
 }
 
+/// Sets every byte of the `len` words from `dst` to `byte`.
+#[derive(Debug, Clone)]
+pub struct MemSet {
+    pub dst: Operand,
+    pub byte: u8,
+    pub len: usize,
+}
+
+/// Copies the `len` words from `src` to `dst`. The two must not overlap.
 #[derive(Debug, Clone)]
-pub struct ZExt {
+pub struct MemCpy {
+    pub dst: Operand,
+    pub src: Operand,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cast {
+    pub op: CastOp,
     pub ori_val: Operand,
     pub target_ty: IrTy,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CastOp {
+    /// Widens an integer, filling the new bits with zeros.
+    ZExt,
+    /// Widens an integer, filling the new bits with its sign bit.
+    SExt,
+    /// Narrows an integer, dropping its high bits.
+    Trunc,
+    /// Reinterprets a pointer as a pointer to another type.
+    Bitcast,
+}
+
+impl CastOp {
+    #[must_use] pub fn name(self) -> &'static str {
+        match self {
+            CastOp::ZExt => "zext",
+            CastOp::SExt => "sext",
+            CastOp::Trunc => "trunc",
+            CastOp::Bitcast => "bitcast",
+        }
+    }
+
+    /// Whether the cast can turn a value of type `from` into one of type `to`.
+    #[must_use] pub fn is_valid(self, from: &IrTy, to: &IrTy) -> bool {
+        match (self, from, to) {
+            (CastOp::ZExt | CastOp::SExt, IrTy::Int(from), IrTy::Int(to)) => from < to,
+            (CastOp::Trunc, IrTy::Int(from), IrTy::Int(to)) => from > to,
+            (CastOp::Bitcast, IrTy::Ptr(_), IrTy::Ptr(_)) => true,
+            // does nothing, but keeps a constant in a value of its own
+            (CastOp::Bitcast, IrTy::Int(from), IrTy::Int(to)) => from == to,
+            _ => false,
+        }
+    }
+
+    /// Casts `val`, an integer `from` bits wide, to one `to` bits wide.
+    /// Integers narrower than 32 bits are held zero-extended, so that an
+    /// `i1` is 0 or 1. Wider ones only keep their low 32 bits.
+    #[must_use] pub fn fold(self, val: i32, from: usize, to: usize) -> i32 {
+        let truncate = |x: i32, bits: usize| if bits >= 32 { x } else { x & ((1 << bits) - 1) };
+        let sign_extend = |x: i32, bits: usize| if bits >= 32 { x } else { (x << (32 - bits)) >> (32 - bits) };
+        match self {
+            CastOp::ZExt | CastOp::Bitcast => val,
+            CastOp::SExt => truncate(sign_extend(val, from), to),
+            CastOp::Trunc => truncate(val, to),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Call {
     pub func_id: FuncId,
     pub args: Vec<Operand>,
+    /// Whether the call is followed by a return of its value and the callee
+    /// cannot reach the caller's stack, so that a backend may emit it as a
+    /// jump. Printed as LLVM's `tail` marker.
+    pub is_tail: bool,
+}
+
+/// `true_val` if the `i1` `cond` is true, `false_val` otherwise, without
+/// branching.
+#[derive(Debug, Clone)]
+pub struct Select {
+    pub cond: Operand,
+    pub true_val: Operand,
+    pub false_val: Operand,
+}
+
+impl Select {
+    /// The operand the select always gives, if it can be told without
+    /// running it: either a constant condition or the same value on both
+    /// sides. An undef or poison condition or side may be taken to be
+    /// whatever suits.
+    #[must_use] pub fn fold(&self) -> Option<&Operand> {
+        let is_undef = |x: &Operand| x.as_const().is_some_and(|x| x.is_undef() || x.is_poison());
+        match &self.cond {
+            Operand::Const(Constant::Int(0)) => Some(&self.false_val),
+            Operand::Const(Constant::Int(_)) => Some(&self.true_val),
+            Operand::Const(Constant::Undef(_) | Constant::Poison(_)) => Some(if is_undef(&self.true_val) { &self.false_val } else { &self.true_val }),
+            _ if self.true_val == self.false_val => Some(&self.true_val),
+            _ if is_undef(&self.true_val) => Some(&self.false_val),
+            _ if is_undef(&self.false_val) => Some(&self.true_val),
+            _ => None,
+        }
+    }
+}
+
+/// `vector` with the element in lane `lane` replaced by `elem`. Chained
+/// from undef, it builds a vector out of scalars.
+#[derive(Debug, Clone)]
+pub struct InsertElement {
+    pub vector: Operand,
+    pub elem: Operand,
+    pub lane: usize,
 }
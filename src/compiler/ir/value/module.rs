@@ -1,7 +1,9 @@
 use slotmap::SlotMap;
 
 use crate::compiler::ir::{
-    arena::{FuncId, GlobalId},
+    arena::{FuncId, GlobalId, InstId},
+    debug_info::DebugInfo,
+    value::{ty::IrTy, value::{Operand, Value}},
 };
 
 use super::{func::IrFunc, global::Global};
@@ -13,6 +15,8 @@ pub struct Module {
 
     pub global_arena: SlotMap<GlobalId, Global>,
     pub func_arena: SlotMap<FuncId, IrFunc>,
+
+    pub debug_info: DebugInfo,
 }
 
 impl Module {
@@ -22,6 +26,7 @@ impl Module {
             first_global: None,
             global_arena: SlotMap::with_key(),
             func_arena: SlotMap::with_key(),
+            debug_info: DebugInfo::new(),
         }
     }
 
@@ -29,6 +34,9 @@ impl Module {
         let id = self.func_arena.insert(func);
         let func = self.func_arena.get_mut(id).unwrap();
         func.next = self.first_func;
+        if let Some(head) = self.first_func {
+            self.func_arena[head].prev = Some(id);
+        }
         self.first_func = Some(id);
         id
     }
@@ -37,10 +45,47 @@ impl Module {
         let id = self.global_arena.insert(global);
         let global = self.global_arena.get_mut(id).unwrap();
         global.next = self.first_global;
+        if let Some(head) = self.first_global {
+            self.global_arena[head].prev = Some(id);
+        }
         self.first_global = Some(id);
         id
     }
 
+    /// Unlinks `global_id` and takes it out of the module. Uses of it are
+    /// left dangling, so they should be replaced first.
+    pub fn remove_global(&mut self, global_id: GlobalId) -> Option<Global> {
+        let global = self.global_arena.get(global_id)?;
+        let (prev, next) = (global.prev, global.next);
+        if self.first_global == Some(global_id) {
+            self.first_global = next;
+        }
+        if let Some(prev) = prev {
+            self.global_arena[prev].next = next;
+        }
+        if let Some(next) = next {
+            self.global_arena[next].prev = prev;
+        }
+        self.global_arena.remove(global_id)
+    }
+
+    /// Unlinks `func_id` and takes it out of the module. Calls of it are
+    /// left dangling, so there should be none.
+    pub fn remove_func(&mut self, func_id: FuncId) -> Option<IrFunc> {
+        let func = self.func_arena.get(func_id)?;
+        let (prev, next) = (func.prev, func.next);
+        if self.first_func == Some(func_id) {
+            self.first_func = next;
+        }
+        if let Some(prev) = prev {
+            self.func_arena[prev].next = next;
+        }
+        if let Some(next) = next {
+            self.func_arena[next].prev = prev;
+        }
+        self.func_arena.remove(func_id)
+    }
+
     #[must_use] pub fn get_func(&self, func_id: FuncId) -> Option<&IrFunc> {
         self.func_arena.get(func_id)
     }
@@ -52,4 +97,24 @@ impl Module {
     #[must_use] pub fn get_global(&self, global_id: GlobalId) -> Option<&Global> {
         self.global_arena.get(global_id)
     }
+
+    /// Type of `operand` as used in `func`. Globals are pointers to their
+    /// initial value.
+    #[must_use] pub fn operand_ty(&self, func: &IrFunc, operand: &Operand) -> IrTy {
+        match operand {
+            Operand::Inst(x) => func.inst_arena[*x].ty.clone(),
+            Operand::Global(x) => self.global_arena[*x].ty.clone(),
+            Operand::Param(x) => func.param_arena[*x].ty.clone(),
+            Operand::Const(x) => x.get_ty().clone(),
+            Operand::BB(_) => IrTy::Label,
+        }
+    }
+
+    /// Instructions using `global_id`, across every function.
+    #[must_use] pub fn global_users(&self, global_id: GlobalId) -> Vec<(FuncId, InstId)> {
+        let global = Operand::Global(global_id);
+        self.func_arena.iter()
+            .flat_map(|(func_id, func)| func.users(&global).into_iter().map(move |x| (func_id, x)))
+            .collect()
+    }
 }
@@ -1,6 +1,7 @@
 use std::fmt::{Display, Formatter};
 
 use enum_as_inner::EnumAsInner;
+use itertools::Itertools;
 
 #[derive(Debug, Clone, Eq, Hash, EnumAsInner)]
 #[derive(Default)]
@@ -12,17 +13,20 @@ pub enum IrTy {
     Ptr(Box<IrTy>),
     Label,
     Array(usize, Box<IrTy>),
+    /// `lanes` values of an element type operated on at once, at most
+    /// [`IrTy::MAX_LANES`] of them.
+    Vector(usize, Box<IrTy>),
 }
 
 
 
 impl PartialEq<Self> for IrTy {
     fn eq(&self, other: &Self) -> bool {
-        use IrTy::{Array, Func, Int, Label, Ptr, Void};
+        use IrTy::{Array, Func, Int, Label, Ptr, Vector, Void};
         match (self, other) {
             (Void, Void) | (Label, Label) => true,
             (Int(x), Int(y)) => x == y,
-            (Array(siz1, ty1), Array(siz2, ty2)) => siz1 == siz2 && ty1 == ty2,
+            (Array(siz1, ty1), Array(siz2, ty2)) | (Vector(siz1, ty1), Vector(siz2, ty2)) => siz1 == siz2 && ty1 == ty2,
             (Ptr(x) | Array(_, x), Ptr(y) | Array(_, y)) => x.as_ref() == y.as_ref(),
             (Func(x), Func(y)) => x.as_ref() == y.as_ref(),
             _ => false,
@@ -39,6 +43,7 @@ pub struct FuncTy {
 impl PartialEq<Self> for FuncTy {
     fn eq(&self, other: &Self) -> bool {
         self.ret_ty == other.ret_ty &&
+            self.params_ty.len() == other.params_ty.len() &&
             self.params_ty.iter()
                 .zip(&other.params_ty)
                 .all(|(x, y)| x == y)
@@ -46,6 +51,10 @@ impl PartialEq<Self> for FuncTy {
 }
 
 impl IrTy {
+    /// Lanes of the widest vector type, so that a vector value fits in a
+    /// fixed size.
+    pub const MAX_LANES: usize = 16;
+
     #[must_use] pub fn bool() -> IrTy {
         IrTy::Int(1)
     }
@@ -71,6 +80,16 @@ impl IrTy {
             _ => None
         }
     }
+
+    /// Number of 32-bit words a value of this type takes in memory. Every
+    /// scalar, `i1` included, is stored as one word.
+    #[must_use] pub fn size_in_words(&self) -> usize {
+        match self {
+            IrTy::Int(_) | IrTy::Ptr(_) => 1,
+            IrTy::Array(siz, elem_ty) | IrTy::Vector(siz, elem_ty) => siz * elem_ty.size_in_words(),
+            _ => 0,
+        }
+    }
 }
 
 impl Display for IrTy {
@@ -81,7 +100,8 @@ impl Display for IrTy {
             IrTy::Ptr(t) => format!("{t}*"),
             IrTy::Label => String::from("label"),
             IrTy::Array(dim_size, elem_ty) => format!("[{dim_size} x {elem_ty}]"),
-            _ => unreachable!()
+            IrTy::Vector(lanes, elem_ty) => format!("<{lanes} x {elem_ty}>"),
+            IrTy::Func(func_ty) => format!("{} ({})", func_ty.ret_ty, func_ty.params_ty.iter().join(", ")),
         };
         write!(f, "{s}")
     }
@@ -6,6 +6,17 @@ pub trait Value {
     fn get_ty(&self) -> &IrTy;
 }
 
+/// Whether other modules can refer to a function or global by its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Linkage {
+    #[default]
+    External,
+    /// Only visible in its own module, so [`Module::link`] may rename it.
+    ///
+    /// [`Module::link`]: crate::compiler::ir::value::module::Module::link
+    Internal,
+}
+
 #[derive(Debug, Clone, Eq, Hash, EnumAsInner)]
 pub enum Operand {
     Inst(InstId),
@@ -41,6 +52,12 @@ impl From<ParamId> for Operand {
     }
 }
 
+impl From<GlobalId> for Operand {
+    fn from(global_id: GlobalId) -> Self {
+        Operand::Global(global_id)
+    }
+}
+
 impl From<i32> for Operand {
     fn from(i: i32) -> Self {
         Operand::Const(Constant::Int(i))
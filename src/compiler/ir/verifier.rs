@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::InstId,
+    err::VerifyError,
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Br, InstKind},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+
+impl Module {
+    /// Checks the invariants every pass may rely on and must keep: each
+    /// block ends with its only terminator, operands and branch targets
+    /// exist, and branches, selects, casts and vector inserts are on values
+    /// of the right type.
+    ///
+    /// # Errors
+    ///
+    /// The first broken invariant found.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        self.func_arena.values()
+            .filter(|x| !x.is_builtin)
+            .try_for_each(|func| self.verify_func(func))
+    }
+
+    fn verify_func(&self, func: &IrFunc) -> Result<(), VerifyError> {
+        let name = || func.name.clone();
+        for (bb_id, bb) in func.bb_arena.items_iter(func.first_block, None) {
+            if func.terminator(bb_id).is_none() {
+                return Err(VerifyError::MissingTerminator { func: name(), bb: bb_id });
+            }
+            for (inst_id, inst) in func.inst_arena.items_iter(bb.insts_head, None) {
+                if inst.bb != bb_id {
+                    return Err(VerifyError::WrongBlock { func: name(), inst: inst_id });
+                }
+                if matches!(inst.kind, InstKind::Br(_) | InstKind::RetInst(_)) && inst.next.is_some() {
+                    return Err(VerifyError::EarlyTerminator { func: name(), inst: inst_id });
+                }
+                if !inst.kind.operands().into_iter().all(|x| self.operand_exists(func, x)) {
+                    return Err(VerifyError::DanglingOperand { func: name(), inst: inst_id });
+                }
+                let invalid_const = |x: &&Operand| matches!(x,
+                    Operand::Const(Constant::Undef(ty) | Constant::Poison(ty)) if !matches!(ty, IrTy::Int(_) | IrTy::Ptr(_) | IrTy::Array(..) | IrTy::Vector(..)));
+                if inst.kind.operands().iter().any(invalid_const) {
+                    return Err(VerifyError::InvalidConstant { func: name(), inst: inst_id });
+                }
+                match &inst.kind {
+                    InstKind::Br(br) => self.verify_br(func, inst_id, br)?,
+                    InstKind::Select(select) => {
+                        let valid = self.operand_ty(func, &select.cond) == IrTy::Int(1)
+                            && [&select.true_val, &select.false_val].iter().all(|x| self.operand_ty(func, x) == inst.ty);
+                        if !valid {
+                            return Err(VerifyError::InvalidSelect { func: name(), inst: inst_id });
+                        }
+                    }
+                    InstKind::Cast(cast) => {
+                        let from = self.operand_ty(func, &cast.ori_val);
+                        if !cast.op.is_valid(&from, &cast.target_ty) || cast.target_ty != inst.ty {
+                            return Err(VerifyError::InvalidCast { func: name(), inst: inst_id });
+                        }
+                    }
+                    InstKind::InsertElement(insert) => {
+                        let valid = matches!(&inst.ty, IrTy::Vector(lanes, elem_ty)
+                            if insert.lane < *lanes && self.operand_ty(func, &insert.elem) == **elem_ty)
+                            && self.operand_ty(func, &insert.vector) == inst.ty;
+                        if !valid {
+                            return Err(VerifyError::InvalidInsertElement { func: name(), inst: inst_id });
+                        }
+                    }
+                    InstKind::MemSet(_) | InstKind::MemCpy(_)
+                        if !inst.kind.operands().into_iter().all(|x| matches!(self.operand_ty(func, x), IrTy::Ptr(_))) => {
+                        return Err(VerifyError::InvalidMemOp { func: name(), inst: inst_id });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_br(&self, func: &IrFunc, inst_id: InstId, br: &Br) -> Result<(), VerifyError> {
+        let (name, inst) = (func.name.clone(), inst_id);
+        let valid_cond = match br {
+            Br::Br { cond, .. } => self.operand_ty(func, cond) == IrTy::Int(1),
+            Br::Switch { cond, .. } => self.operand_ty(func, cond) == IrTy::Int(32),
+            Br::Jump { .. } => true,
+        };
+        if !valid_cond {
+            return Err(VerifyError::InvalidCond { func: name, inst });
+        }
+        if func.succs(func.inst_arena[inst_id].bb).into_iter().any(|x| !func.bb_arena.contains_key(x)) {
+            return Err(VerifyError::UnknownTarget { func: name, inst });
+        }
+        if let Br::Switch { cases, .. } = br {
+            let mut vals = HashSet::new();
+            if let Some(&(val, _)) = cases.iter().find(|x| !vals.insert(x.0)) {
+                return Err(VerifyError::DuplicateCase { func: name, inst, val });
+            }
+        }
+        Ok(())
+    }
+
+    fn operand_exists(&self, func: &IrFunc, operand: &Operand) -> bool {
+        match operand {
+            Operand::Inst(x) => func.inst_arena.contains_key(*x),
+            Operand::Param(x) => func.param_arena.contains_key(*x),
+            Operand::Global(x) => self.global_arena.contains_key(*x),
+            Operand::Const(_) | Operand::BB(_) => true,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::compiler::syntax::ast::DefId;
+
+/// Which functions call which, keyed by the functions' definitions when
+/// built by the type checker while it resolves call expressions, or by any
+/// other ordered id, e.g. by `FuncId` for the functions of a module.
+#[derive(Debug, Clone)]
+pub struct CallGraph<F = DefId> {
+    callees: BTreeMap<F, BTreeSet<F>>,
+}
+
+impl<F> Default for CallGraph<F> {
+    fn default() -> Self {
+        CallGraph { callees: BTreeMap::new() }
+    }
+}
+
+impl<F: Copy + Ord> CallGraph<F> {
+    pub fn add_func(&mut self, func: F) {
+        self.callees.entry(func).or_default();
+    }
+
+    /// Records that `func` calls `callee`.
+    pub fn add_call(&mut self, func: F, callee: F) {
+        self.add_func(callee);
+        self.callees.entry(func).or_default().insert(callee);
+    }
+
+    pub fn funcs(&self) -> impl Iterator<Item = F> + '_ {
+        self.callees.keys().copied()
+    }
+
+    pub fn callees(&self, func: F) -> impl Iterator<Item = F> + '_ {
+        self.callees.get(&func).into_iter().flatten().copied()
+    }
+
+    /// Whether some function in `scc` calls into it, i.e. the component is
+    /// recursive (a single function only if it calls itself).
+    #[must_use] pub fn is_recursive(&self, scc: &[F]) -> bool {
+        scc.iter().any(|&func| self.callees(func).any(|callee| scc.contains(&callee)))
+    }
+
+    /// Strongly connected components, callees before callers.
+    #[must_use] pub fn sccs(&self) -> Vec<Vec<F>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: BTreeMap::new(),
+            low_link: BTreeMap::new(),
+            stack: vec![],
+            on_stack: BTreeSet::new(),
+            sccs: vec![],
+        };
+        for func in self.funcs() {
+            if !tarjan.index.contains_key(&func) {
+                tarjan.visit(func);
+            }
+        }
+        tarjan.sccs
+    }
+}
+
+struct Tarjan<'a, F> {
+    graph: &'a CallGraph<F>,
+    index: BTreeMap<F, usize>,
+    low_link: BTreeMap<F, usize>,
+    stack: Vec<F>,
+    on_stack: BTreeSet<F>,
+    sccs: Vec<Vec<F>>,
+}
+
+impl<F: Copy + Ord> Tarjan<'_, F> {
+    fn visit(&mut self, func: F) {
+        let index = self.index.len();
+        self.index.insert(func, index);
+        self.low_link.insert(func, index);
+        self.stack.push(func);
+        self.on_stack.insert(func);
+
+        for callee in self.graph.callees(func) {
+            if !self.index.contains_key(&callee) {
+                self.visit(callee);
+                let low_link = self.low_link[&func].min(self.low_link[&callee]);
+                self.low_link.insert(func, low_link);
+            } else if self.on_stack.contains(&callee) {
+                let low_link = self.low_link[&func].min(self.index[&callee]);
+                self.low_link.insert(func, low_link);
+            }
+        }
+
+        if self.low_link[&func] == index {
+            let mut scc = vec![];
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(&member);
+                scc.push(member);
+                if member == func {
+                    break;
+                }
+            }
+            self.sccs.push(scc);
+        }
+    }
+}
@@ -8,17 +8,19 @@ use itertools::Itertools;
 
 use crate::compiler::ir::{
     arena::{BBId, FuncId, GlobalId, InstId, ParamId},
+    debug_info::VarInfo,
     value::{
         constant::Constant,
-        func::IrFunc,
+        func::{InsertPoint, IrFunc},
         global::Global,
-        inst::InstKind,
+        inst::{InstKind, SrcLoc},
         module::Module,
         ty::{FuncTy, IrTy},
         value::{Operand, Value},
     },
 };
-use crate::compiler::syntax::ast::{AstTy, LiteralExpr, LiteralKind};
+use crate::compiler::span::Span;
+use crate::compiler::syntax::ast::{AstTy, DefId, LiteralExpr, LiteralKind};
 
 #[derive(Debug, Clone, Copy, EnumAsInner)]
 pub enum IdInfo {
@@ -100,24 +102,54 @@ impl<T> ScopeBuilder<T> {
 
 #[derive(Debug)]
 pub struct Context {
-    pub scope_builder: ScopeBuilder<IdInfo>,
+    /// IR values bound to each resolved definition.
+    pub ids: HashMap<DefId, IdInfo>,
     pub cur_module: Module,
     cur_func: FuncId,
-    cur_bb: BBId,
+    /// Where instructions are built, the end of the current block unless a
+    /// pass moved it.
+    insert_point: InsertPoint,
+    /// Attached to every instruction built.
+    cur_loc: Option<SrcLoc>,
 }
 
 impl Context {
     pub fn new() -> Context {
         Context {
-            scope_builder: ScopeBuilder::new(),
+            ids: HashMap::new(),
             cur_module: Module::new(),
             cur_func: FuncId::default(),
-            cur_bb: BBId::default(),
+            insert_point: InsertPoint::End(BBId::default()),
+            cur_loc: None,
         }
     }
 
+    pub fn insert_id(&mut self, def_id: Option<DefId>, id: IdInfo) {
+        self.ids.insert(def_id.expect("Name not resolved"), id);
+    }
+
+    pub fn find_id(&self, def_id: Option<DefId>) -> IdInfo {
+        *def_id.and_then(|x| self.ids.get(&x)).expect("Name not resolved")
+    }
+
+    /// Records where the variable declared at `span` lives, for debuggers.
+    pub fn add_var_debug_info(&mut self, name: &str, span: Span, loc: Operand) {
+        let is_global = matches!(loc, Operand::Global(_));
+        let var = VarInfo { name: String::from(name), span, loc: Some(loc) };
+        if is_global {
+            self.cur_module.debug_info.add_global(var);
+        } else {
+            self.cur_module.debug_info.add_var(self.cur_func, var);
+        }
+    }
+
+    /// Records the source variable `value` was built for, for debug dumps.
+    pub fn set_value_name(&mut self, value: Operand, name: &str) {
+        self.get_cur_func_mut().value_names.insert(value, String::from(name));
+    }
+
     pub fn get_cur_bb_id(&self) -> BBId {
-        self.cur_bb
+        self.insert_point.bb(self.cur_module.get_func(self.cur_func).unwrap())
     }
 
     fn get_cur_func_mut(&mut self) -> &mut IrFunc {
@@ -134,25 +166,76 @@ impl Context {
         func_ty.as_func().unwrap()
     }
 
+    /// Builds at the end of `bb` from now on.
     pub fn set_cur_bb(&mut self, bb: BBId) {
-        self.cur_bb = bb;
+        self.insert_point = InsertPoint::End(bb);
+    }
+
+    pub fn insert_point(&self) -> InsertPoint {
+        self.insert_point
+    }
+
+    /// Builds at `point` from now on, returning the previous point to
+    /// restore once done with it.
+    pub fn set_insert_point(&mut self, point: InsertPoint) -> InsertPoint {
+        std::mem::replace(&mut self.insert_point, point)
     }
 
     pub fn set_cur_func(&mut self, func: FuncId) {
         self.cur_func = func;
     }
 
+    /// Sets the source of the instructions built from now on, returning the
+    /// previous one to restore once done with it.
+    pub fn set_cur_loc(&mut self, loc: Option<SrcLoc>) -> Option<SrcLoc> {
+        std::mem::replace(&mut self.cur_loc, loc)
+    }
+
+    /// Whether `bb` ends with a `br` or `ret`, after which nothing may be
+    /// built in it.
+    pub fn is_terminated(&self, bb: BBId) -> bool {
+        self.cur_module.get_func(self.cur_func).unwrap().terminator(bb).is_some()
+    }
+
+    pub fn is_cur_bb_terminated(&self) -> bool {
+        self.is_terminated(self.get_cur_bb_id())
+    }
+
+    /// Builds an instruction at `point`, which must not be past the
+    /// terminator of its block.
+    pub fn build_inst_at(&mut self, inst_kind: InstKind, ty: IrTy, point: InsertPoint) -> InstId {
+        if let InsertPoint::End(bb) = point {
+            assert!(!self.is_terminated(bb), "Block {bb} is already terminated");
+        }
+        let loc = self.cur_loc;
+        let func = self.get_cur_func_mut();
+        if let InsertPoint::After(x) = point {
+            assert!(func.terminator(func.inst_arena[x].bb) != Some(x), "Cannot build after terminator {x}");
+        }
+        let inst_id = func.build_inst_at(inst_kind, ty, point);
+        func.get_inst_mut(inst_id).unwrap().loc = loc;
+        inst_id
+    }
+
+    /// Builds an instruction at the insertion point, moving the point past
+    /// it.
+    pub fn build_inst(&mut self, inst_kind: InstKind, ty: IrTy) -> InstId {
+        let inst_id = self.build_inst_at(inst_kind, ty, self.insert_point);
+        self.insert_point = self.insert_point.after_built(inst_id);
+        inst_id
+    }
+
     pub fn build_inst_end(&mut self, inst_kind: InstKind, ty: IrTy, bb: BBId) -> InstId {
-        self.get_cur_func_mut().build_inst_at_end(inst_kind, ty, bb)
+        self.build_inst_at(inst_kind, ty, InsertPoint::End(bb))
     }
 
     pub fn build_inst_end_of_cur(&mut self, inst_kind: InstKind, ty: IrTy) -> InstId {
-        let cur_bb = self.cur_bb;
+        let cur_bb = self.get_cur_bb_id();
         self.build_inst_end(inst_kind, ty, cur_bb)
     }
 
     pub fn build_bb_after_cur(&mut self) -> BBId {
-        let bb = self.cur_bb;
+        let bb = self.get_cur_bb_id();
         self.get_cur_func_mut().build_bb_after_cur(bb)
     }
 
@@ -1,18 +1,105 @@
+use std::fmt::{Display, Formatter};
+
+use crate::compiler::diagnostic::{Diagnostic, Suggestion};
+use crate::compiler::span::Span;
 use crate::compiler::syntax::ast::AstTy;
 
 #[derive(Debug)]
 pub enum SemanticError {
-    TypeMismatch { expected: String, found: AstTy },
-    UnknownName(String),
-    DuplicateName(String),
-    WrongParamLength { expected: usize, found: usize },
-    ExpectedFunction(String),
-    BreakOutsideLoop,
-    ContinueOutsideLoop,
-    TooMuchElement,
-    IllegalArrayDim,
-    RequireConstant,
-    RequireLValue,
-    CannotModifyConstValue(String),
-    DerefToNotPtrType,
+    TypeMismatch { expected: String, found: AstTy, span: Span },
+    UnknownName { name: String, span: Span },
+    DuplicateName { name: String, span: Span },
+    WrongParamLength { expected: usize, found: usize, span: Span },
+    ExpectedFunction { name: String, span: Span },
+    BreakOutsideLoop { span: Span },
+    ContinueOutsideLoop { span: Span },
+    TooMuchElement { span: Span },
+    IllegalArrayDim { span: Span },
+    RequireConstant { span: Span },
+    RequireLValue { span: Span },
+    CannotModifyConstValue { name: String, span: Span },
+    DerefToNotPtrType { span: Span },
+    AssignInCondition { span: Span, op_span: Span },
+    SignatureMismatch { name: String, span: Span, prev_span: Span },
+}
+
+impl SemanticError {
+    #[must_use] pub fn code(&self) -> &'static str {
+        match self {
+            SemanticError::TypeMismatch { .. } => "E0200",
+            SemanticError::UnknownName { .. } => "E0201",
+            SemanticError::DuplicateName { .. } => "E0202",
+            SemanticError::WrongParamLength { .. } => "E0203",
+            SemanticError::ExpectedFunction { .. } => "E0204",
+            SemanticError::BreakOutsideLoop { .. } => "E0205",
+            SemanticError::ContinueOutsideLoop { .. } => "E0206",
+            SemanticError::TooMuchElement { .. } => "E0207",
+            SemanticError::IllegalArrayDim { .. } => "E0208",
+            SemanticError::RequireConstant { .. } => "E0209",
+            SemanticError::RequireLValue { .. } => "E0210",
+            SemanticError::CannotModifyConstValue { .. } => "E0211",
+            SemanticError::DerefToNotPtrType { .. } => "E0212",
+            SemanticError::AssignInCondition { .. } => "E0213",
+            SemanticError::SignatureMismatch { .. } => "E0214",
+        }
+    }
+
+    /// Where in the source the error is.
+    #[must_use] pub fn span(&self) -> Span {
+        match self {
+            SemanticError::TypeMismatch { span, .. }
+            | SemanticError::UnknownName { span, .. }
+            | SemanticError::DuplicateName { span, .. }
+            | SemanticError::WrongParamLength { span, .. }
+            | SemanticError::ExpectedFunction { span, .. }
+            | SemanticError::BreakOutsideLoop { span }
+            | SemanticError::ContinueOutsideLoop { span }
+            | SemanticError::TooMuchElement { span }
+            | SemanticError::IllegalArrayDim { span }
+            | SemanticError::RequireConstant { span }
+            | SemanticError::RequireLValue { span }
+            | SemanticError::CannotModifyConstValue { span, .. }
+            | SemanticError::DerefToNotPtrType { span }
+            | SemanticError::AssignInCondition { span, .. }
+            | SemanticError::SignatureMismatch { span, .. } => *span,
+        }
+    }
+}
+
+impl Display for SemanticError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SemanticError::TypeMismatch { expected, found, .. } =>
+                write!(f, "mismatched types: expected {expected}, found {found}"),
+            SemanticError::UnknownName { name, .. } => write!(f, "cannot find `{name}` in this scope"),
+            SemanticError::DuplicateName { name, .. } => write!(f, "`{name}` is defined multiple times"),
+            SemanticError::WrongParamLength { expected, found, .. } =>
+                write!(f, "expected {expected} arguments, found {found}"),
+            SemanticError::ExpectedFunction { name, .. } => write!(f, "`{name}` is not a function"),
+            SemanticError::BreakOutsideLoop { .. } => write!(f, "`break` outside of a loop"),
+            SemanticError::ContinueOutsideLoop { .. } => write!(f, "`continue` outside of a loop"),
+            SemanticError::TooMuchElement { .. } => write!(f, "too many elements in initializer"),
+            SemanticError::IllegalArrayDim { .. } => write!(f, "array dimension must be positive"),
+            SemanticError::RequireConstant { .. } => write!(f, "expected a constant expression"),
+            SemanticError::RequireLValue { .. } => write!(f, "expected an assignable expression"),
+            SemanticError::CannotModifyConstValue { name, .. } => write!(f, "cannot assign to constant `{name}`"),
+            SemanticError::DerefToNotPtrType { .. } => write!(f, "cannot index into a non-pointer value"),
+            SemanticError::AssignInCondition { .. } => write!(f, "assignment used as a condition"),
+            SemanticError::SignatureMismatch { name, .. } =>
+                write!(f, "`{name}` does not match its earlier declaration"),
+        }
+    }
+}
+
+impl From<SemanticError> for Diagnostic {
+    fn from(err: SemanticError) -> Self {
+        let diagnostic = Diagnostic::error(err.code(), err.to_string(), Some(err.span()));
+        match err {
+            SemanticError::AssignInCondition { op_span, .. } =>
+                diagnostic.with_suggestion(Suggestion::replace("use `==` to compare values", op_span, " == ")),
+            SemanticError::SignatureMismatch { prev_span, .. } =>
+                diagnostic.with_label(prev_span, "previously declared here"),
+            _ => diagnostic,
+        }
+    }
 }
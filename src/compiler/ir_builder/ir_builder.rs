@@ -4,21 +4,26 @@ use crate::compiler::ir::{
     arena::{BBId, InstId},
     value::{
         constant::Constant,
-        func::IrFunc,
+        func::{InlineHint, IrFunc},
         global::Global,
-        inst::{Alloca, Binary, BinaryInstOp, Br, Call, GEP, InstKind, Load, RetInst, Store, ZExt},
+        inst::{Alloca, Binary, BinaryInstOp, Br, Call, Cast, CastOp, GEP, InstKind, Load, MemSet, RetInst, SrcLoc, Store},
         ty::IrTy,
         value::Operand,
     },
 };
 use crate::compiler::span::Span;
-use crate::compiler::syntax::{ast::{AssignExpr, AstFunc, AstTy, BinaryExpr, BlockItem, BlockStmt, CallExpr, Decl, Expr, FuncParam, IfStmt, InitVal, InitValKind, LiteralExpr, LiteralKind, PrimitiveTy, Program, ProgramItem, ReturnStmt, Stmt, Subs, TyIdentKind, TypeIdent, UnaryExpr, UnaryOp, WhileStmt}, visitor::AstVisitor};
+use crate::compiler::syntax::{ast::{AssignExpr, AstFunc, AstTy, BinaryExpr, BlockItem, BlockStmt, CallExpr, CastExpr, Decl, Expr, FuncAttr, FuncParam, IfStmt, InitVal, InitValKind, LiteralExpr, LiteralKind, NodeId, PrimitiveTy, Program, ProgramItem, ReturnStmt, Stmt, Subs, TyIdentKind, TypeIdent, UnaryExpr, UnaryOp, WhileStmt}, visitor::AstVisitor};
 
 use super::{
     context::{Context, IdInfo},
     err::SemanticError,
+    name_resolver::builtin_def_id,
 };
 
+/// Words from which a local array initialized with one value that is not
+/// the same in every byte is filled by a loop rather than a store each.
+const FILL_LOOP_MIN: usize = 16;
+
 #[derive(Debug, Clone, Copy)]
 pub struct BCTarget {
     pub break_target: BBId,
@@ -60,78 +65,195 @@ impl IrBuilder {
         Some(self.loop_targets.last()?.continue_target)
     }
 
+    /// Runs `build` with the instructions it builds attributed to `span`
+    /// and, for an expression, `node_id`.
+    fn with_loc<T>(&mut self, span: Span, node_id: Option<NodeId>, build: impl FnOnce(&mut Self) -> T) -> T {
+        let node_id = node_id.filter(|&x| x != NodeId::DUMMY);
+        let old_loc = self.ctx.set_cur_loc(Some(SrcLoc { span, node_id }));
+        let result = build(self);
+        self.ctx.set_cur_loc(old_loc);
+        result
+    }
+
+    /// Reports the errors in `stmt` that building it would, without building
+    /// it. `in_loop` is whether it is in the body of a loop.
+    fn check_unreachable_stmt(stmt: &Stmt, in_loop: bool) -> Result<(), SemanticError> {
+        match stmt {
+            Stmt::Break(span) if !in_loop => Err(SemanticError::BreakOutsideLoop { span: *span }),
+            Stmt::Continue(span) if !in_loop => Err(SemanticError::ContinueOutsideLoop { span: *span }),
+            Stmt::Block(x) => x.block_items.iter()
+                .filter_map(|x| if let BlockItem::Stmt(x) = x { Some(x) } else { None })
+                .try_for_each(|x| Self::check_unreachable_stmt(x, in_loop)),
+            Stmt::If(x) => {
+                Self::check_unreachable_stmt(&x.then_block, in_loop)?;
+                x.else_block.as_ref().map_or(Ok(()), |x| Self::check_unreachable_stmt(x, in_loop))
+            }
+            Stmt::While(x) => Self::check_unreachable_stmt(&x.body, true),
+            _ => Ok(()),
+        }
+    }
+
     // fn set_bb_after(&mut self, after: BBId, cur: BBId) {
     //     self.ctx.set_bb_after(after, cur);
     // }
 
     fn build_decl_init_val(&mut self, init_val: &InitVal, base_addr: InstId) -> Result<(), SemanticError> {
-        match &init_val.kind {
-            InitValKind::Expr(expr) => {
-                let init_expr_id = self.visit_expr(expr)?;
+        if let InitValKind::Expr(expr) = &init_val.kind {
+            let init_expr_id = self.visit_expr(expr)?;
+            let store_inst = Store {
+                addr: base_addr.into(),
+                data: init_expr_id,
+            };
+            self.ctx.build_inst_end_of_cur(InstKind::Store(store_inst), IrTy::Void);
+            return Ok(());
+        }
 
-                let store_inst = Store {
-                    addr: base_addr.into(),
-                    data: init_expr_id,
+        // the elements are all evaluated first, in order, to tell what is
+        // left to store once the array is filled
+        let mut elems = vec![];
+        self.eval_array_init_val(init_val, &mut vec![0], &mut elems)?;
+        let ir_ty = IrTy::from(init_val.ty.clone());
+        let len = ir_ty.size_in_words();
+        let zero = Operand::Const(Constant::Int(0));
+        let zeros = len - elems.len() + elems.iter().filter(|x| x.1 == zero).count();
+
+        let uniform = match elems.first() {
+            Some((_, Operand::Const(Constant::Int(x)))) if elems.len() == len && elems.iter().all(|y| y.1 == elems[0].1) => Some(*x),
+            _ => None,
+        };
+        // elements missing from the initializer are zero, and clearing the
+        // whole array first is worth it when it saves storing half of it
+        let fill = uniform.or(Some(0).filter(|_| elems.len() < len || zeros * 2 >= len));
+        let filled = match fill {
+            Some(val) if val.to_le_bytes() == [val.to_le_bytes()[0]; 4] => {
+                let memset_inst = MemSet {
+                    dst: base_addr.into(),
+                    byte: val.to_le_bytes()[0],
+                    len,
                 };
-                self.ctx.build_inst_end_of_cur(InstKind::Store(store_inst), IrTy::Void);
+                self.ctx.build_inst_end_of_cur(InstKind::MemSet(memset_inst), IrTy::Void);
+                Some(val)
             }
-            InitValKind::ArrayVal(array_vals) => {
-                array_vals.iter().enumerate()
-                    .try_for_each(|(idx, val)| {
-                        let ir_ty = IrTy::from(init_val.ty.clone());
-                        let ty = ir_ty.as_array().unwrap().1.as_ref();
+            Some(val) if len >= FILL_LOOP_MIN => {
+                self.build_fill_loop(base_addr, val, len);
+                Some(val)
+            }
+            _ => None,
+        };
 
-                        let gep_inst = GEP {
-                            ptr: base_addr.into(),
-                            indices: vec![0.into(), (idx as i32).into()]
-                        };
-                        let gep_inst_id = self.ctx.build_inst_end_of_cur(
-                            InstKind::GEP(gep_inst),
-                            IrTy::ptr_of(ty)
-                        );
-                        Self::build_decl_init_val(self, val, gep_inst_id)
-                    })?;
+        for (indices, val) in elems {
+            if filled.is_some_and(|x| val == Operand::Const(Constant::Int(x))) {
+                continue;
+            }
+            let gep_inst = GEP {
+                ptr: base_addr.into(),
+                indices: indices.into_iter().map(Operand::from).collect(),
+            };
+            let gep_inst_id = self.ctx.build_inst_end_of_cur(InstKind::GEP(gep_inst), IrTy::ptr_of(&IrTy::int()));
+            let store_inst = Store {
+                addr: gep_inst_id.into(),
+                data: val,
+            };
+            self.ctx.build_inst_end_of_cur(InstKind::Store(store_inst), IrTy::Void);
+        }
+        Ok(())
+    }
+
+    /// Evaluates the elements given by the array initializer `init_val`,
+    /// pushing each to `elems` with its indices from the start of the array.
+    fn eval_array_init_val(&mut self, init_val: &InitVal, indices: &mut Vec<i32>, elems: &mut Vec<(Vec<i32>, Operand)>) -> Result<(), SemanticError> {
+        match &init_val.kind {
+            InitValKind::Expr(expr) => elems.push((indices.clone(), self.visit_expr(expr)?)),
+            InitValKind::ArrayVal(array_vals) => {
+                for (idx, val) in array_vals.iter().enumerate() {
+                    indices.push(idx as i32);
+                    self.eval_array_init_val(val, indices, elems)?;
+                    indices.pop();
+                }
             }
-            _ => unreachable!(),
+            InitValKind::Const(_) => unreachable!(),
         }
         Ok(())
     }
 
+    /// Stores `val` to each of the `len` words from `base_addr` with a loop.
+    fn build_fill_loop(&mut self, base_addr: InstId, val: i32, len: usize) {
+        let int_ptr = IrTy::ptr_of(&IrTy::int());
+        let cast_inst = Cast { op: CastOp::Bitcast, ori_val: base_addr.into(), target_ty: int_ptr.clone() };
+        let words = self.ctx.build_inst_end_of_cur(InstKind::Cast(cast_inst), int_ptr.clone());
+        let counter = self.ctx.build_inst_end_of_cur(InstKind::Alloca(Alloca { alloca_ty: IrTy::int() }), IrTy::ptr_of(&IrTy::int()));
+        self.ctx.build_inst_end_of_cur(InstKind::Store(Store { addr: counter.into(), data: 0.into() }), IrTy::Void);
+
+        let cond_bb = self.ctx.build_bb_after_cur();
+        self.ctx.build_inst_end_of_cur(InstKind::Br(Br::Jump { nxt_bb: cond_bb }), IrTy::Void);
+        self.ctx.set_cur_bb(cond_bb);
+        let idx = self.ctx.build_inst_end_of_cur(InstKind::Load(Load { addr: counter.into() }), IrTy::int());
+        let cmp = Binary { op: BinaryInstOp::Lt, left: idx.into(), right: i32::try_from(len).unwrap().into() };
+        let cond = self.ctx.build_inst_end_of_cur(InstKind::Binary(cmp), IrTy::bool());
+        let loop_bb = self.ctx.build_bb_after_cur();
+        let nxt_bb = self.ctx.build_bb_after_cur();
+        self.ctx.build_inst_end_of_cur(InstKind::Br(Br::Br { cond: cond.into(), true_bb: loop_bb, false_bb: nxt_bb }), IrTy::Void);
+
+        self.ctx.set_cur_bb(loop_bb);
+        let idx = self.ctx.build_inst_end_of_cur(InstKind::Load(Load { addr: counter.into() }), IrTy::int());
+        let gep_inst = GEP { ptr: words.into(), indices: vec![idx.into()] };
+        let addr = self.ctx.build_inst_end_of_cur(InstKind::GEP(gep_inst), int_ptr);
+        self.ctx.build_inst_end_of_cur(InstKind::Store(Store { addr: addr.into(), data: val.into() }), IrTy::Void);
+        let add = Binary { op: BinaryInstOp::Add, left: idx.into(), right: 1.into() };
+        let next_idx = self.ctx.build_inst_end_of_cur(InstKind::Binary(add), IrTy::int());
+        self.ctx.build_inst_end_of_cur(InstKind::Store(Store { addr: counter.into(), data: next_idx.into() }), IrTy::Void);
+        self.ctx.build_inst_end_of_cur(InstKind::Br(Br::Jump { nxt_bb: cond_bb }), IrTy::Void);
+
+        self.ctx.set_cur_bb(nxt_bb);
+    }
+
     fn push_built_in_funcs(&mut self) {
         // getint
         let func_getint = IrFunc::new("getint", IrTy::Int(32), true);
         let func_getint_id = self.ctx.cur_module.build_func(func_getint);
-        self.ctx.scope_builder.insert("getint", IdInfo::Func(func_getint_id));
+        self.ctx.insert_id(Some(builtin_def_id("getint")), IdInfo::Func(func_getint_id));
 
         // getch
         let func_getch = IrFunc::new("getch", IrTy::Int(32), true);
         let func_getch_id = self.ctx.cur_module.build_func(func_getch);
-        self.ctx.scope_builder.insert("getch", IdInfo::Func(func_getch_id));
+        self.ctx.insert_id(Some(builtin_def_id("getch")), IdInfo::Func(func_getch_id));
 
         // getarray
         let mut func_getarray = IrFunc::new("getarray", IrTy::Int(32), true);
         func_getarray.build_func_param(IrTy::Ptr(Box::from(IrTy::Int(32))));
         let func_getarray_id = self.ctx.cur_module.build_func(func_getarray);
-        self.ctx.scope_builder.insert("getarray", IdInfo::Func(func_getarray_id));
+        self.ctx.insert_id(Some(builtin_def_id("getarray")), IdInfo::Func(func_getarray_id));
 
         // putint
         let mut func_putint = IrFunc::new("putint", IrTy::Void, true);
         func_putint.build_func_param(IrTy::Int(32));
         let func_putint_id = self.ctx.cur_module.build_func(func_putint);
-        self.ctx.scope_builder.insert("putint", IdInfo::Func(func_putint_id));
+        self.ctx.insert_id(Some(builtin_def_id("putint")), IdInfo::Func(func_putint_id));
 
         // putch
         let mut func_putch = IrFunc::new("putch", IrTy::Void, true);
         func_putch.build_func_param(IrTy::Int(32));
         let func_putch_id = self.ctx.cur_module.build_func(func_putch);
-        self.ctx.scope_builder.insert("putch", IdInfo::Func(func_putch_id));
+        self.ctx.insert_id(Some(builtin_def_id("putch")), IdInfo::Func(func_putch_id));
 
         // putarray
         let mut func_putarray = IrFunc::new("putarray", IrTy::Int(32), true);
         func_putarray.build_func_param(IrTy::Int(32));
         func_putarray.build_func_param(IrTy::Ptr(Box::from(IrTy::Int(32))));
         let func_putarray_id = self.ctx.cur_module.build_func(func_putarray);
-        self.ctx.scope_builder.insert("putarray", IdInfo::Func(func_putarray_id));
+        self.ctx.insert_id(Some(builtin_def_id("putarray")), IdInfo::Func(func_putarray_id));
+
+        // starttime, called with its line
+        let mut func_starttime = IrFunc::new("_sysy_starttime", IrTy::Void, true);
+        func_starttime.build_func_param(IrTy::Int(32));
+        let func_starttime_id = self.ctx.cur_module.build_func(func_starttime);
+        self.ctx.insert_id(Some(builtin_def_id("starttime")), IdInfo::Func(func_starttime_id));
+
+        // stoptime, called with its line
+        let mut func_stoptime = IrFunc::new("_sysy_stoptime", IrTy::Void, true);
+        func_stoptime.build_func_param(IrTy::Int(32));
+        let func_stoptime_id = self.ctx.cur_module.build_func(func_stoptime);
+        self.ctx.insert_id(Some(builtin_def_id("stoptime")), IdInfo::Func(func_stoptime_id));
     }
 }
 
@@ -145,17 +267,13 @@ impl AstVisitor for IrBuilder {
     type TyResult = Result<IrTy, SemanticError>;
 
     fn visit_program(&mut self, program: &Program) -> Self::ProgramResult {
-        self.ctx.scope_builder.push_scope();
-
         self.push_built_in_funcs();
 
         program.program_items.iter()
             .try_for_each(|item| match item {
                 ProgramItem::Decl(x) => self.visit_global_decl(x),
                 ProgramItem::Func(x) => self.visit_func(x),
-            })?;
-        self.ctx.scope_builder.pop_scope();
-        Ok(())
+            })
     }
 
     fn visit_const_init_val(&mut self, init_val: &InitVal) -> Self::ConstInitValResult {
@@ -174,14 +292,16 @@ impl AstVisitor for IrBuilder {
                 Constant::build_zero(&ty)
             };
 
-            let global = Global::new(
+            let mut global = Global::new(
                 ptr_ty,
                 &sub_decl.ident.name,
                 const_init_val,
             );
+            global.is_const = decl.is_const;
             let global_id = self.ctx.build_global(global);
+            self.ctx.add_var_debug_info(&sub_decl.ident.name, sub_decl.span, global_id.into());
 
-            self.ctx.scope_builder.insert(&sub_decl.ident.name, IdInfo::Global(global_id));
+            self.ctx.insert_id(sub_decl.def_id, IdInfo::Global(global_id));
         }
 
         Ok(())
@@ -190,16 +310,48 @@ impl AstVisitor for IrBuilder {
     fn visit_func(&mut self, ast_func: &AstFunc) -> Self::FuncResult {
         let ret_ty = self.visit_ty(&ast_func.ret_ty_ident)?;
 
-        let func = IrFunc::new(
+        // a prototype is emitted as a declaration, which a later definition
+        // replaces in place so that earlier calls refer to it
+        let mut func = IrFunc::new(
             &ast_func.ident.name,
             ret_ty.clone(),
-            false,
+            ast_func.body.is_none(),
         );
-        let func_id = self.ctx.build_func(func);
-        self.ctx.scope_builder.insert(&ast_func.ident.name, IdInfo::Func(func_id));
+        func.inline_hint = inline_hint(&ast_func.attrs);
+        let prev_func_id = ast_func.def_id
+            .and_then(|x| self.ctx.ids.get(&x))
+            .and_then(|x| x.as_func().copied());
+        let func_id = match prev_func_id {
+            Some(_) if ast_func.body.is_none() => return Ok(()),
+            Some(func_id) => {
+                let prev_func = self.ctx.cur_module.get_func_mut(func_id).unwrap();
+                let (prev, next) = (prev_func.prev, prev_func.next);
+                // attributes may be given on the prototype only
+                if func.inline_hint == InlineHint::Auto {
+                    func.inline_hint = prev_func.inline_hint;
+                }
+                *prev_func = func;
+                prev_func.prev = prev;
+                prev_func.next = next;
+                func_id
+            }
+            None => {
+                let func_id = self.ctx.build_func(func);
+                self.ctx.insert_id(ast_func.def_id, IdInfo::Func(func_id));
+                func_id
+            }
+        };
         self.ctx.set_cur_func(func_id);
+        if ast_func.body.is_some() {
+            self.ctx.cur_module.debug_info.add_func(func_id, &ast_func.ident.name, ast_func.span);
+        }
 
-        self.ctx.scope_builder.push_scope();
+        let Some(body) = &ast_func.body else {
+            for param in &ast_func.params {
+                self.ctx.build_func_param(IrTy::from(param.ty.clone()));
+            }
+            return Ok(());
+        };
 
         // build bb
         let init_bb_id = self.ctx.build_bb();
@@ -207,9 +359,12 @@ impl AstVisitor for IrBuilder {
 
         // visit params
         ast_func.params.iter()
-            .try_for_each(|param| self.visit_func_param(param))?;
+            .try_for_each(|param| self.with_loc(param.span, None, |this| this.visit_func_param(param)))?;
 
-        self.visit_block_stmt(&ast_func.body)?;
+        self.visit_block_stmt(body)?;
+        if self.ctx.is_cur_bb_terminated() {
+            return Ok(());
+        }
 
         // add default return inst
         let ret_inst = match &ret_ty {
@@ -219,25 +374,27 @@ impl AstVisitor for IrBuilder {
         };
         self.ctx.build_inst_end_of_cur(InstKind::RetInst(ret_inst), IrTy::Void);
 
-        self.ctx.scope_builder.pop_scope();
-
         Ok(())
     }
 
     fn visit_func_param(&mut self, param: &FuncParam) -> Self::StmtResult {
         let ty = IrTy::from(param.ty.clone());
         let param_id = self.ctx.build_func_param(ty.clone());
+        self.ctx.set_value_name(param_id.into(), &param.ident.name);
 
         if let IrTy::Ptr(_) = ty {
-            self.ctx.scope_builder.insert(&param.ident.name, IdInfo::Param(param_id));
+            self.ctx.add_var_debug_info(&param.ident.name, param.span, param_id.into());
+            self.ctx.insert_id(param.def_id, IdInfo::Param(param_id));
         } else {
             let alloca_inst = Alloca { alloca_ty: ty.clone() };
             let alloca_addr = self.ctx.build_inst_end_of_cur(
                 InstKind::Alloca(alloca_inst),
                 IrTy::ptr_of(&ty),
             );
+            self.ctx.set_value_name(alloca_addr.into(), &format!("{}.addr", param.ident.name));
+            self.ctx.add_var_debug_info(&param.ident.name, param.span, alloca_addr.into());
 
-            self.ctx.scope_builder.insert(&param.ident.name, IdInfo::Inst(alloca_addr));
+            self.ctx.insert_id(param.def_id, IdInfo::Inst(alloca_addr));
 
             let store_inst = Store {
                 addr: alloca_addr.into(),
@@ -250,28 +407,29 @@ impl AstVisitor for IrBuilder {
     }
 
     fn visit_block_stmt(&mut self, stmt: &BlockStmt) -> Self::StmtResult {
-        self.ctx.scope_builder.push_scope();
         stmt.block_items.iter()
             .try_for_each(|sub_stmt| match sub_stmt {
                 BlockItem::Stmt(x) => self.visit_stmt(x),
                 BlockItem::Decl(x) => self.visit_decl_stmt(x),
-            })?;
-        self.ctx.scope_builder.pop_scope();
-
-        Ok(())
+            })
     }
 
     fn visit_stmt(&mut self, stmt: &Stmt) -> Self::StmtResult {
-        match stmt {
-            Stmt::Expr(x) => self.visit_expr_stmt(x),
-            Stmt::Block(x) => self.visit_block_stmt(x),
-            Stmt::If(x) => self.visit_if_stmt(x),
-            Stmt::While(x) => self.visit_while_stmt(x),
-            Stmt::Break(x) => self.visit_break_stmt(*x),
-            Stmt::Continue(x) => self.visit_continue_stmt(*x),
-            Stmt::Return(x) => self.visit_return_stmt(x),
-            Stmt::Empty(x) => self.visit_empty_stmt(*x),
+        // code after a `return`, `break` or `continue` cannot be reached,
+        // so it is not built
+        if self.ctx.is_cur_bb_terminated() {
+            return Self::check_unreachable_stmt(stmt, !self.loop_targets.is_empty());
         }
+        self.with_loc(stmt.span(), None, |this| match stmt {
+            Stmt::Expr(x) => this.visit_expr_stmt(x),
+            Stmt::Block(x) => this.visit_block_stmt(x),
+            Stmt::If(x) => this.visit_if_stmt(x),
+            Stmt::While(x) => this.visit_while_stmt(x),
+            Stmt::Break(x) => this.visit_break_stmt(*x),
+            Stmt::Continue(x) => this.visit_continue_stmt(*x),
+            Stmt::Return(x) => this.visit_return_stmt(x),
+            Stmt::Empty(x) => this.visit_empty_stmt(*x),
+        })
     }
 
     fn visit_init_val(&mut self, _init_val: &InitVal) -> Self::StmtResult {
@@ -279,21 +437,27 @@ impl AstVisitor for IrBuilder {
     }
 
     fn visit_decl_stmt(&mut self, decl: &Decl) -> Self::StmtResult {
-        for sub_decl in &decl.sub_decls {
+        // nothing can use a variable declared where it cannot be reached
+        if self.ctx.is_cur_bb_terminated() {
+            return Ok(());
+        }
+        decl.sub_decls.iter().try_for_each(|sub_decl| self.with_loc(sub_decl.span, None, |this| {
             let ty = IrTy::from(sub_decl.ty.clone());
 
             let alloca_inst = Alloca { alloca_ty: ty.clone() };
-            let alloca_addr = self.ctx.build_inst_end_of_cur(
+            let alloca_addr = this.ctx.build_inst_end_of_cur(
                 InstKind::Alloca(alloca_inst),
                 IrTy::ptr_of(&ty),
             );
-            self.ctx.scope_builder.insert(&sub_decl.ident.name, IdInfo::Inst(alloca_addr));
+            this.ctx.set_value_name(alloca_addr.into(), &format!("{}.addr", sub_decl.ident.name));
+            this.ctx.add_var_debug_info(&sub_decl.ident.name, sub_decl.span, alloca_addr.into());
+            this.ctx.insert_id(sub_decl.def_id, IdInfo::Inst(alloca_addr));
 
             if let Some(init_val) = &sub_decl.init_val {
-                self.build_decl_init_val(init_val, alloca_addr)?;
+                this.build_decl_init_val(init_val, alloca_addr)?;
             }
-        }
-        Ok(())
+            Ok(())
+        }))
     }
 
     fn visit_expr_stmt(&mut self, stmt: &Expr) -> Self::StmtResult {
@@ -322,35 +486,35 @@ impl AstVisitor for IrBuilder {
                 (None, None)
             };
 
-        // nxt bb
-        let nxt_bb = self.ctx.build_bb_after_cur();
+        // nxt bb, unless both branches return or leave the loop
+        let open_ends: Vec<_> = std::iter::once(then_bb_end)
+            .chain(else_bb_end)
+            .filter(|&x| !self.ctx.is_terminated(x))
+            .collect();
+        let nxt_bb = (else_bb.is_none() || !open_ends.is_empty())
+            .then(|| self.ctx.build_bb_after_cur());
 
         let cond_br_inst = Br::Br {
             cond,
             true_bb: then_bb,
-            false_bb: else_bb.unwrap_or(nxt_bb),
+            false_bb: else_bb.or(nxt_bb).unwrap(),
         };
         self.ctx.build_inst_end(
             InstKind::Br(cond_br_inst),
             IrTy::Void,
             old_bb);
 
-        let then_br_inst = Br::Jump { nxt_bb };
-        self.ctx.build_inst_end(
-            InstKind::Br(then_br_inst),
-            IrTy::Void,
-            then_bb_end);
-
-        if let Some(else_bb_end) = else_bb_end {
-            let else_br_inst = Br::Jump { nxt_bb };
-            self.ctx.build_inst_end(
-                InstKind::Br(else_br_inst),
-                IrTy::Void,
-                else_bb_end);
+        if let Some(nxt_bb) = nxt_bb {
+            for bb_end in open_ends {
+                let br_inst = Br::Jump { nxt_bb };
+                self.ctx.build_inst_end(
+                    InstKind::Br(br_inst),
+                    IrTy::Void,
+                    bb_end);
+            }
+            self.ctx.set_cur_bb(nxt_bb);
         }
 
-        self.ctx.set_cur_bb(nxt_bb);
-
         Ok(())
     }
 
@@ -389,43 +553,39 @@ impl AstVisitor for IrBuilder {
             IrTy::Void,
             cond_bb);
 
-        let loop_body_br_inst = Br::Jump { nxt_bb: cond_bb };
-        self.ctx.build_inst_end(
-            InstKind::Br(loop_body_br_inst),
-            IrTy::Void,
-            loop_end_bb);
+        if !self.ctx.is_terminated(loop_end_bb) {
+            let loop_body_br_inst = Br::Jump { nxt_bb: cond_bb };
+            self.ctx.build_inst_end(
+                InstKind::Br(loop_body_br_inst),
+                IrTy::Void,
+                loop_end_bb);
+        }
 
         self.ctx.set_cur_bb(nxt_bb);
         Ok(())
     }
 
-    fn visit_break_stmt(&mut self, _span: Span) -> Self::StmtResult {
+    fn visit_break_stmt(&mut self, span: Span) -> Self::StmtResult {
         let break_target = self.get_break_target()
-            .ok_or(SemanticError::BreakOutsideLoop)?;
+            .ok_or(SemanticError::BreakOutsideLoop { span })?;
 
         let break_br_inst = Br::Jump { nxt_bb: break_target };
         self.ctx.build_inst_end_of_cur(
             InstKind::Br(break_br_inst),
             IrTy::Void);
 
-        let nxt_bb = self.ctx.build_bb_after_cur();
-        self.ctx.set_cur_bb(nxt_bb);
-
         Ok(())
     }
 
-    fn visit_continue_stmt(&mut self, _span: Span) -> Self::StmtResult {
+    fn visit_continue_stmt(&mut self, span: Span) -> Self::StmtResult {
         let continue_target = self.get_continue_target()
-            .ok_or(SemanticError::ContinueOutsideLoop)?;
+            .ok_or(SemanticError::ContinueOutsideLoop { span })?;
 
         let continue_br_inst = Br::Jump { nxt_bb: continue_target };
         self.ctx.build_inst_end_of_cur(
             InstKind::Br(continue_br_inst),
             IrTy::Void);
 
-        let nxt_bb = self.ctx.build_bb_after_cur();
-        self.ctx.set_cur_bb(nxt_bb);
-
         Ok(())
     }
 
@@ -437,9 +597,6 @@ impl AstVisitor for IrBuilder {
         let ret_inst = RetInst { val };
         self.ctx.build_inst_end_of_cur(InstKind::RetInst(ret_inst), IrTy::Void);
 
-        let nxt_bb = self.ctx.build_bb_after_cur();
-        self.ctx.set_cur_bb(nxt_bb);
-
         Ok(())
     }
 
@@ -448,23 +605,21 @@ impl AstVisitor for IrBuilder {
     }
 
     fn visit_expr(&mut self, expr: &Expr) -> Self::ExprResult {
-        match expr {
-            Expr::LVal(_) => self.visit_lexpr(expr, false),
-            Expr::Assign(x) => self.visit_assign_expr(x),
-            Expr::Literal(x) => self.visit_literal_expr(x),
-            Expr::Unary(x) => self.visit_unary_expr(x),
-            Expr::Binary(x) => self.visit_binary_expr(x),
-            Expr::Call(x) => self.visit_call_expr(x),
-        }
+        self.with_loc(expr.span(), Some(expr.node_id()), |this| match expr {
+            Expr::LVal(_) => this.visit_lexpr(expr, false),
+            Expr::Assign(x) => this.visit_assign_expr(x),
+            Expr::Literal(x) => this.visit_literal_expr(x),
+            Expr::Unary(x) => this.visit_unary_expr(x),
+            Expr::Binary(x) => this.visit_binary_expr(x),
+            Expr::Call(x) => this.visit_call_expr(x),
+            Expr::Cast(x) => this.visit_cast_expr(x),
+        })
     }
 
     fn visit_lexpr(&mut self, expr: &Expr, is_lvalue: bool) -> Self::LExprResult {
         let lval = expr.as_l_val().unwrap();
         let ty = lval.ty.clone().into();
-        let mut addr = Operand::from(
-            *self.ctx.scope_builder
-                .find_name_rec(&lval.ident.name)
-                .unwrap());
+        let mut addr = Operand::from(self.ctx.find_id(lval.def_id));
 
         if let Some(Subs { subs, .. }) = &lval.subs {
             let mut indices = vec![];
@@ -483,6 +638,7 @@ impl AstVisitor for IrBuilder {
                 indices,
             };
             let gep = self.ctx.build_inst_end_of_cur(InstKind::GEP(gep_inst), IrTy::ptr_of(&ty));
+            self.ctx.set_value_name(gep.into(), &format!("{}.idx", lval.ident.name));
             addr = gep.into();
         }
 
@@ -492,6 +648,7 @@ impl AstVisitor for IrBuilder {
             let val_id = self.ctx.build_inst_end_of_cur(
                 InstKind::Load(Load { addr }),
                 ty);
+            self.ctx.set_value_name(val_id.into(), &lval.ident.name);
 
             Ok(val_id.into())
         }
@@ -513,11 +670,22 @@ impl AstVisitor for IrBuilder {
             LiteralKind::Integer(i) => Operand::Const(Constant::Int(i)),
             _ => unreachable!()
         };
+
+        // constants are always i32, so a folded condition needs an i1 made from it
+        if let AstTy::Bool = expr.ty {
+            let inst = Binary {
+                op: BinaryInstOp::Ne,
+                left: constant,
+                right: 0.into(),
+            };
+            let inst_id = self.ctx.build_inst_end_of_cur(InstKind::Binary(inst), IrTy::bool());
+            return Ok(inst_id.into());
+        }
         Ok(constant)
     }
 
     fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> Self::ExprResult {
-        let mut val = self.visit_expr(&expr.sub_expr)?;
+        let val = self.visit_expr(&expr.sub_expr)?;
         match expr.op {
             UnaryOp::Neg => {
                 let inst = Binary {
@@ -531,17 +699,8 @@ impl AstVisitor for IrBuilder {
             }
             UnaryOp::Pos => Ok(val),
             UnaryOp::Not => {
-                if let AstTy::Bool = expr.sub_expr.ty() {
-                    let zext_inst = ZExt {
-                        ori_val: val,
-                        target_ty: IrTy::int(),
-                    };
-                    let id = self.ctx.build_inst_end_of_cur(InstKind::ZExt(zext_inst), IrTy::int());
-                    val = id.into();
-                }
-
                 let inst = Binary {
-                    op: BinaryInstOp::Ne,
+                    op: BinaryInstOp::Eq,
                     left: val,
                     right: 0.into(),
                 };
@@ -564,10 +723,9 @@ impl AstVisitor for IrBuilder {
     }
 
     fn visit_call_expr(&mut self, expr: &CallExpr) -> Self::ExprResult {
-        let func_id = *self.ctx.scope_builder.find_name_rec(&expr.func.name).unwrap()
-            .as_func().unwrap();
+        let func_id = *self.ctx.find_id(expr.def_id).as_func().unwrap();
 
-        let args = expr.args.iter()
+        let mut args: Vec<Operand> = expr.args.iter()
             .map(|x| {
                 let expr_id = self.visit_expr(x)?;
                 match x.ty() {
@@ -590,14 +748,44 @@ impl AstVisitor for IrBuilder {
             })
             .try_collect()?;
 
+        // the timing functions take the line of the call, as the macros of
+        // the reference runtime pass `__LINE__`
+        if expr.def_id == Some(builtin_def_id("starttime")) || expr.def_id == Some(builtin_def_id("stoptime")) {
+            args.push(i32::try_from(expr.span.start.lineno + 1).unwrap().into());
+        }
+
         let ret_ty = self.ctx.get_func_ty(func_id).ret_ty.clone();
 
-        let call_inst = Call { func_id, args };
+        let call_inst = Call { func_id, args, is_tail: false };
         let call_inst_id = self.ctx.build_inst_end_of_cur(InstKind::Call(call_inst), ret_ty);
 
         Ok(call_inst_id.into())
     }
 
+    fn visit_cast_expr(&mut self, expr: &CastExpr) -> Self::ExprResult {
+        let val = self.visit_expr(&expr.sub_expr)?;
+        let inst_id = match (expr.sub_expr.ty(), &expr.ty) {
+            (AstTy::Bool, AstTy::Int) => {
+                let zext_inst = Cast {
+                    op: CastOp::ZExt,
+                    ori_val: val,
+                    target_ty: IrTy::int(),
+                };
+                self.ctx.build_inst_end_of_cur(InstKind::Cast(zext_inst), IrTy::int())
+            }
+            (AstTy::Int, AstTy::Bool) => {
+                let inst = Binary {
+                    op: BinaryInstOp::Ne,
+                    left: val,
+                    right: 0.into(),
+                };
+                self.ctx.build_inst_end_of_cur(InstKind::Binary(inst), IrTy::bool())
+            }
+            _ => unreachable!()
+        };
+        Ok(inst_id.into())
+    }
+
     fn visit_ty(&mut self, ty_def: &TypeIdent) -> Self::TyResult {
         let ty = match &ty_def.kind {
             TyIdentKind::Primitive(prim_ty) => match prim_ty {
@@ -609,3 +797,12 @@ impl AstVisitor for IrBuilder {
         Ok(ty)
     }
 }
+
+/// The last of `attrs` wins if they conflict.
+fn inline_hint(attrs: &[FuncAttr]) -> InlineHint {
+    match attrs.last() {
+        Some(FuncAttr::AlwaysInline) => InlineHint::Always,
+        Some(FuncAttr::NoInline) => InlineHint::Never,
+        None => InlineHint::Auto,
+    }
+}
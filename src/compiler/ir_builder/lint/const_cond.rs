@@ -0,0 +1,55 @@
+use crate::compiler::ir_builder::type_checker::TypedProgram;
+use crate::compiler::syntax::ast::{AstFunc, BlockItem, BlockStmt, Expr, Stmt};
+
+use super::{LintContext, CONSTANT_CONDITION};
+
+/// Warns on `if`/`while` conditions that constant folding reduced to a
+/// literal, which usually means a typo such as comparing a variable with
+/// itself or a constant in the wrong place.
+pub fn check_func(func: &AstFunc, typed_program: &TypedProgram, cx: &mut LintContext) {
+    let Some(body) = &func.body else {
+        return;
+    };
+    visit_block(body, typed_program, cx);
+}
+
+fn visit_block(block: &BlockStmt, typed_program: &TypedProgram, cx: &mut LintContext) {
+    for item in &block.block_items {
+        if let BlockItem::Stmt(stmt) = item {
+            visit_stmt(stmt, typed_program, cx);
+        }
+    }
+}
+
+fn visit_stmt(stmt: &Stmt, typed_program: &TypedProgram, cx: &mut LintContext) {
+    match stmt {
+        Stmt::Block(x) => visit_block(x, typed_program, cx),
+        Stmt::If(x) => {
+            check_cond(&x.cond, typed_program, cx);
+            visit_stmt(&x.then_block, typed_program, cx);
+            if let Some(else_block) = &x.else_block {
+                visit_stmt(else_block, typed_program, cx);
+            }
+        }
+        Stmt::While(x) => {
+            check_cond(&x.cond, typed_program, cx);
+            visit_stmt(&x.body, typed_program, cx);
+        }
+        _ => {}
+    }
+}
+
+fn check_cond(cond: &Expr, typed_program: &TypedProgram, cx: &mut LintContext) {
+    let mut cond = cond;
+    while let Expr::Cast(x) = cond {
+        cond = &x.sub_expr;
+    }
+    // a bare literal like `while (1)` is written on purpose
+    if matches!(cond, Expr::Literal(_)) {
+        return;
+    }
+    if let Some(val) = typed_program.const_val(cond.node_id()) {
+        let outcome = if val == 0 { "false" } else { "true" };
+        cx.emit(&CONSTANT_CONDITION, format!("condition is always {outcome}"), cond.span());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::compiler::span::Span;
+use crate::compiler::syntax::ast::{
+    BinaryOp, BlockItem, BlockStmt, Decl, DefId, Expr, InitVal, InitValKind, LVal, LiteralExpr, LiteralKind, Stmt, SubDecl, WhileStmt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Direction {
+    Forward,
+    Backward,
+}
+
+/// A dataflow analysis over the variables of one function, which [`solve`]
+/// runs directly over the AST, visiting what each statement reads, assigns
+/// and declares in the order the direction gives.
+pub(super) trait Analysis {
+    type State: Clone + PartialEq;
+
+    const DIRECTION: Direction;
+
+    /// The state of code never reached, which is the identity of `join`,
+    /// and also the state after the end of the function when going backward.
+    fn unreachable(&self) -> Self::State;
+
+    /// The state where two paths meet.
+    fn join(&self, a: Self::State, b: Self::State) -> Self::State;
+
+    /// `lval` is read. `report` is cleared while a loop is iterated, so that
+    /// what is found is reported once, from the state of the fixpoint.
+    fn read(&mut self, lval: &LVal, state: &mut Self::State, report: bool);
+
+    /// The whole of the variable `def_id` is assigned, at `span`.
+    fn assign(&mut self, def_id: DefId, name: &str, span: Span, state: &mut Self::State, report: bool);
+
+    /// `def_id` is declared without a value, as it is on every iteration of
+    /// a loop containing its declaration.
+    fn declare(&mut self, def_id: DefId, state: &mut Self::State);
+}
+
+/// Runs `analysis` over `body` from `state`, the state on entry going
+/// forward or on exit going backward, returning the state at the other end.
+/// Loops are iterated until the state at the head stops changing before
+/// anything in them is reported.
+pub(super) fn solve<A: Analysis>(analysis: &mut A, body: &BlockStmt, state: A::State) -> A::State {
+    let mut solver = Solver { analysis, loop_targets: vec![], is_reporting: true };
+    solver.visit_block(body, state)
+}
+
+/// States at the `break`s and `continue`s of the innermost loop: those
+/// reaching them going forward, and those after them going backward.
+struct LoopTargets<S> {
+    breaks: S,
+    continues: S,
+}
+
+struct Solver<'a, A: Analysis> {
+    analysis: &'a mut A,
+    loop_targets: Vec<LoopTargets<A::State>>,
+    is_reporting: bool,
+}
+
+impl<A: Analysis> Solver<'_, A> {
+    fn is_forward() -> bool {
+        A::DIRECTION == Direction::Forward
+    }
+
+    /// Visits `items` in the order of the analysis.
+    fn fold<T>(&mut self, items: &[T], state: A::State, visit: fn(&mut Self, &T, A::State) -> A::State) -> A::State {
+        if Self::is_forward() {
+            items.iter().fold(state, |state, x| visit(self, x, state))
+        } else {
+            items.iter().rev().fold(state, |state, x| visit(self, x, state))
+        }
+    }
+
+    fn visit_block(&mut self, block: &BlockStmt, state: A::State) -> A::State {
+        self.fold(&block.block_items, state, |this, item, state| match item {
+            BlockItem::Stmt(x) => this.visit_stmt(x, state),
+            BlockItem::Decl(x) => this.visit_decl(x, state),
+        })
+    }
+
+    fn visit_decl(&mut self, decl: &Decl, state: A::State) -> A::State {
+        self.fold(&decl.sub_decls, state, Self::visit_sub_decl)
+    }
+
+    fn visit_sub_decl(&mut self, sub_decl: &SubDecl, mut state: A::State) -> A::State {
+        let Some(init_val) = &sub_decl.init_val else {
+            if let Some(def_id) = sub_decl.def_id {
+                self.analysis.declare(def_id, &mut state);
+            }
+            return state;
+        };
+        if Self::is_forward() {
+            state = self.visit_init_val(init_val, state);
+        }
+        if let Some(def_id) = sub_decl.def_id {
+            self.analysis.assign(def_id, &sub_decl.ident.name, sub_decl.ident.span, &mut state, self.is_reporting);
+        }
+        if !Self::is_forward() {
+            state = self.visit_init_val(init_val, state);
+        }
+        state
+    }
+
+    fn visit_init_val(&mut self, init_val: &InitVal, state: A::State) -> A::State {
+        match &init_val.kind {
+            InitValKind::Expr(x) => self.visit_expr(x, state),
+            InitValKind::ArrayVal(vals) => self.fold(vals, state, Self::visit_init_val),
+            InitValKind::Const(_) => state,
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt, state: A::State) -> A::State {
+        match stmt {
+            Stmt::Expr(x) => self.visit_expr(x, state),
+            Stmt::Block(x) => self.visit_block(x, state),
+            Stmt::If(x) => {
+                let state = if Self::is_forward() { self.visit_expr(&x.cond, state) } else { state };
+                let then_state = self.visit_stmt(&x.then_block, state.clone());
+                let else_state = match &x.else_block {
+                    Some(else_block) => self.visit_stmt(else_block, state),
+                    None => state,
+                };
+                let state = self.analysis.join(then_state, else_state);
+                if Self::is_forward() { state } else { self.visit_expr(&x.cond, state) }
+            }
+            Stmt::While(x) => self.visit_while(x, &state),
+            Stmt::Break(_) => self.jump(state, |x| &mut x.breaks),
+            Stmt::Continue(_) => self.jump(state, |x| &mut x.continues),
+            Stmt::Return(x) => {
+                let state = if Self::is_forward() { state } else { self.analysis.unreachable() };
+                let state = match &x.val {
+                    Some(val) => self.visit_expr(val, state),
+                    None => state,
+                };
+                if Self::is_forward() { self.analysis.unreachable() } else { state }
+            }
+            Stmt::Empty(_) => state,
+        }
+    }
+
+    /// A `break` or `continue` to the `target` of the innermost loop.
+    fn jump(&mut self, state: A::State, target: fn(&mut LoopTargets<A::State>) -> &mut A::State) -> A::State {
+        let unreachable = self.analysis.unreachable();
+        let Some(targets) = self.loop_targets.last_mut() else {
+            return unreachable;
+        };
+        let target = target(targets);
+        if Self::is_forward() {
+            let joined = self.analysis.join(std::mem::replace(target, unreachable.clone()), state);
+            *target = joined;
+            unreachable
+        } else {
+            target.clone()
+        }
+    }
+
+    /// `state` is that before the loop going forward, and after it going
+    /// backward.
+    fn visit_while(&mut self, stmt: &WhileStmt, state: &A::State) -> A::State {
+        let is_reporting = self.is_reporting;
+        self.is_reporting = false;
+
+        let mut head = self.analysis.unreachable();
+        loop {
+            let (new_head, _) = self.visit_loop_once(stmt, state, &head);
+            if new_head == head {
+                break;
+            }
+            head = new_head;
+        }
+
+        self.is_reporting = is_reporting;
+        self.visit_loop_once(stmt, state, &head).1
+    }
+
+    /// The state at the head of the loop, before the condition, and the
+    /// state at the other end of the loop, given the state `head` assumed
+    /// at the head. A loop whose condition is always true is only left by
+    /// a `break`.
+    fn visit_loop_once(&mut self, stmt: &WhileStmt, state: &A::State, head: &A::State) -> (A::State, A::State) {
+        let falls_through = !is_always_true(&stmt.cond);
+        if Self::is_forward() {
+            let cond_state = self.visit_expr(&stmt.cond, head.clone());
+            let unreachable = self.analysis.unreachable();
+            self.loop_targets.push(LoopTargets { breaks: unreachable.clone(), continues: unreachable });
+            let body_state = self.visit_stmt(&stmt.body, cond_state.clone());
+            let targets = self.loop_targets.pop().unwrap();
+
+            let new_head = self.analysis.join(self.analysis.join(state.clone(), body_state), targets.continues);
+            let exit = if falls_through { self.analysis.join(cond_state, targets.breaks) } else { targets.breaks };
+            (new_head, exit)
+        } else {
+            self.loop_targets.push(LoopTargets { breaks: state.clone(), continues: head.clone() });
+            let body_state = self.visit_stmt(&stmt.body, head.clone());
+            self.loop_targets.pop();
+
+            let cond_state = if falls_through { self.analysis.join(body_state, state.clone()) } else { body_state };
+            let new_head = self.visit_expr(&stmt.cond, cond_state);
+            (new_head.clone(), new_head)
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr, mut state: A::State) -> A::State {
+        match expr {
+            Expr::LVal(x) => {
+                let subs: &[Expr] = x.subs.as_ref().map_or(&[], |x| &x.subs);
+                if Self::is_forward() {
+                    state = self.fold(subs, state, Self::visit_expr);
+                    self.analysis.read(x, &mut state, self.is_reporting);
+                    state
+                } else {
+                    self.analysis.read(x, &mut state, self.is_reporting);
+                    self.fold(subs, state, Self::visit_expr)
+                }
+            }
+            Expr::Assign(x) => {
+                let lhs = x.lhs.as_l_val().unwrap();
+                let subs: &[Expr] = lhs.subs.as_ref().map_or(&[], |x| &x.subs);
+                if Self::is_forward() {
+                    state = self.fold(subs, state, Self::visit_expr);
+                    state = self.visit_expr(&x.rhs, state);
+                }
+                if let (None, Some(def_id)) = (&lhs.subs, lhs.def_id) {
+                    self.analysis.assign(def_id, &lhs.ident.name, lhs.span, &mut state, self.is_reporting);
+                }
+                if !Self::is_forward() {
+                    state = self.visit_expr(&x.rhs, state);
+                    state = self.fold(subs, state, Self::visit_expr);
+                }
+                state
+            }
+            Expr::Literal(_) => state,
+            Expr::Unary(x) => self.visit_expr(&x.sub_expr, state),
+            Expr::Cast(x) => self.visit_expr(&x.sub_expr, state),
+            Expr::Binary(x) => {
+                let visit_rhs = |this: &mut Self, state: A::State| if matches!(x.op, BinaryOp::And | BinaryOp::Or) {
+                    // the right operand may be skipped
+                    let rhs_state = this.visit_expr(&x.rhs, state.clone());
+                    this.analysis.join(state, rhs_state)
+                } else {
+                    this.visit_expr(&x.rhs, state)
+                };
+                if Self::is_forward() {
+                    let state = self.visit_expr(&x.lhs, state);
+                    visit_rhs(self, state)
+                } else {
+                    let state = visit_rhs(self, state);
+                    self.visit_expr(&x.lhs, state)
+                }
+            }
+            Expr::Call(x) => self.fold(&x.args, state, Self::visit_expr),
+        }
+    }
+}
+
+/// Whether `cond` is a nonzero literal, as in `while (1)`.
+fn is_always_true(cond: &Expr) -> bool {
+    let mut cond = cond;
+    while let Expr::Cast(x) = cond {
+        cond = &x.sub_expr;
+    }
+    matches!(cond, Expr::Literal(LiteralExpr { kind: LiteralKind::Integer(x), .. }) if *x != 0)
+}
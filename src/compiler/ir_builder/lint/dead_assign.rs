@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+
+use crate::compiler::span::Span;
+use crate::compiler::syntax::ast::{AstFunc, DefId, LVal};
+
+use super::dataflow::{self, Analysis, Direction};
+use super::{local_scalars, LintContext, DEAD_ASSIGNMENT};
+
+/// Variables whose current value may still be read.
+type Live = HashSet<DefId>;
+
+/// Warns on assignments to local variables whose value is overwritten or
+/// goes out of scope before being read. This is the backward counterpart of
+/// [`super::definite_init`], a liveness analysis over the same variables
+/// solved by the same [`dataflow::solve`], with loops iterated to a fixpoint
+/// before anything is reported.
+pub fn check_func(func: &AstFunc, cx: &mut LintContext) {
+    let Some(body) = &func.body else {
+        return;
+    };
+    let mut checker = DeadAssign {
+        cx,
+        tracked: local_scalars(body).into_iter().collect(),
+    };
+    dataflow::solve(&mut checker, body, Live::new());
+}
+
+struct DeadAssign<'a, 'b> {
+    cx: &'a mut LintContext<'b>,
+    tracked: HashSet<DefId>,
+}
+
+impl Analysis for DeadAssign<'_, '_> {
+    type State = Live;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn unreachable(&self) -> Live {
+        Live::new()
+    }
+
+    fn join(&self, mut a: Live, b: Live) -> Live {
+        a.extend(b);
+        a
+    }
+
+    fn read(&mut self, lval: &LVal, live: &mut Live, _report: bool) {
+        if let Some(def_id) = lval.def_id {
+            live.insert(def_id);
+        }
+    }
+
+    fn assign(&mut self, def_id: DefId, name: &str, span: Span, live: &mut Live, report: bool) {
+        if !self.tracked.contains(&def_id) {
+            return;
+        }
+        if !live.remove(&def_id) && report {
+            self.cx.emit(
+                &DEAD_ASSIGNMENT,
+                format!("value assigned to `{name}` is never read"),
+                span,
+            );
+        }
+    }
+
+    fn declare(&mut self, def_id: DefId, live: &mut Live) {
+        live.remove(&def_id);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+
+use crate::compiler::span::Span;
+use crate::compiler::syntax::ast::{AstFunc, DefId, LVal};
+
+use super::dataflow::{self, Analysis, Direction};
+use super::{local_scalars, LintContext, UNINIT_READ};
+
+/// Variables definitely assigned at a program point, or `None` if the point
+/// is unreachable (which, as the identity of `meet`, assigns everything).
+type State = Option<HashSet<DefId>>;
+
+fn meet(a: State, b: State) -> State {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Warns on reads of local variables that are not assigned on every path
+/// reaching them. This is a forward must-analysis run directly over the AST
+/// by [`dataflow::solve`]: branches meet by intersection, and loops iterate
+/// until the state at the loop head stops shrinking.
+pub fn check_func(func: &AstFunc, cx: &mut LintContext) {
+    let Some(body) = &func.body else {
+        return;
+    };
+    let mut checker = DefiniteInit {
+        cx,
+        tracked: local_scalars(body).into_iter().collect(),
+        reported: HashSet::new(),
+    };
+    dataflow::solve(&mut checker, body, Some(HashSet::new()));
+}
+
+struct DefiniteInit<'a, 'b> {
+    cx: &'a mut LintContext<'b>,
+    tracked: HashSet<DefId>,
+    reported: HashSet<DefId>,
+}
+
+impl Analysis for DefiniteInit<'_, '_> {
+    type State = State;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn unreachable(&self) -> State {
+        None
+    }
+
+    fn join(&self, a: State, b: State) -> State {
+        meet(a, b)
+    }
+
+    fn read(&mut self, lval: &LVal, state: &mut State, report: bool) {
+        if let (Some(vars), Some(def_id)) = (&state, lval.def_id) {
+            if report && self.tracked.contains(&def_id) && !vars.contains(&def_id) && self.reported.insert(def_id) {
+                self.cx.emit(
+                    &UNINIT_READ,
+                    format!("`{}` may be used before it is assigned", lval.ident.name),
+                    lval.ident.span,
+                );
+            }
+        }
+    }
+
+    fn assign(&mut self, def_id: DefId, _name: &str, _span: Span, state: &mut State, _report: bool) {
+        if let Some(vars) = state {
+            vars.insert(def_id);
+        }
+    }
+
+    fn declare(&mut self, def_id: DefId, state: &mut State) {
+        // a declaration inside a loop starts uninitialized every time
+        if let Some(vars) = state {
+            vars.remove(&def_id);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use crate::compiler::diagnostic::Diagnostic;
+use crate::compiler::ir_builder::type_checker::TypedProgram;
+use crate::compiler::span::Span;
+use crate::compiler::syntax::ast::{BlockItem, BlockStmt, DefId, ProgramItem, Stmt};
+
+pub mod const_cond;
+mod dataflow;
+pub mod dead_assign;
+pub mod definite_init;
+pub mod partial_init;
+pub mod recursion;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// A check for code that is legal but most likely wrong. Lints run on the
+/// type-checked program and never stop compilation unless denied.
+#[derive(Debug, Clone, Copy)]
+pub struct Lint {
+    /// Name used on the command line, e.g. `--deny uninit-read`.
+    pub name: &'static str,
+    pub code: &'static str,
+    pub default_level: LintLevel,
+}
+
+pub const UNINIT_READ: Lint = Lint {
+    name: "uninit-read",
+    code: "W0001",
+    default_level: LintLevel::Warn,
+};
+
+pub const DEAD_ASSIGNMENT: Lint = Lint {
+    name: "dead-assignment",
+    code: "W0002",
+    default_level: LintLevel::Warn,
+};
+
+pub const CONSTANT_CONDITION: Lint = Lint {
+    name: "constant-condition",
+    code: "W0003",
+    default_level: LintLevel::Warn,
+};
+
+pub const UNBOUNDED_RECURSION: Lint = Lint {
+    name: "unbounded-recursion",
+    code: "W0004",
+    default_level: LintLevel::Warn,
+};
+
+pub const PARTIAL_INIT: Lint = Lint {
+    name: "partial-init",
+    code: "W0005",
+    default_level: LintLevel::Allow,
+};
+
+pub const LINTS: [&Lint; 5] = [
+    &UNINIT_READ,
+    &DEAD_ASSIGNMENT,
+    &CONSTANT_CONDITION,
+    &UNBOUNDED_RECURSION,
+    &PARTIAL_INIT,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    levels: HashMap<&'static str, LintLevel>,
+}
+
+impl LintConfig {
+    #[must_use] pub fn level(&self, lint: &Lint) -> LintLevel {
+        self.levels.get(lint.name).copied().unwrap_or(lint.default_level)
+    }
+
+    /// Overrides the level of the lint called `name`, returning `false` if
+    /// there is no such lint.
+    pub fn set(&mut self, name: &str, level: LintLevel) -> bool {
+        match LINTS.iter().find(|x| x.name == name) {
+            Some(lint) => {
+                self.levels.insert(lint.name, level);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LintContext<'a> {
+    config: &'a LintConfig,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> LintContext<'a> {
+    #[must_use] pub fn new(config: &'a LintConfig) -> LintContext<'a> {
+        LintContext { config, diagnostics: vec![] }
+    }
+
+    pub fn emit(&mut self, lint: &Lint, message: String, span: Span) {
+        let diagnostic = match self.config.level(lint) {
+            LintLevel::Allow => return,
+            LintLevel::Warn => Diagnostic::warning(lint.code, message, Some(span)),
+            LintLevel::Deny => Diagnostic::error(lint.code, message, Some(span)),
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    #[must_use] pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
+
+/// Runs every enabled lint over a type-checked program.
+#[must_use] pub fn check_program(typed_program: &TypedProgram, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut cx = LintContext::new(config);
+    for item in &typed_program.program().program_items {
+        if let ProgramItem::Func(func) = item {
+            definite_init::check_func(func, &mut cx);
+            dead_assign::check_func(func, &mut cx);
+            const_cond::check_func(func, typed_program, &mut cx);
+        }
+    }
+    recursion::check_program(typed_program, &mut cx);
+    partial_init::check_program(typed_program, &mut cx);
+    let mut diagnostics = cx.into_diagnostics();
+    diagnostics.sort_by_key(|x| x.span.map(|span| span.start.idx));
+    diagnostics
+}
+
+/// Local non-array variables declared in `block`, the only variables whose
+/// every access is visible to a lint looking at one function.
+pub(crate) fn local_scalars(block: &BlockStmt) -> Vec<DefId> {
+    let mut vars = vec![];
+    collect_local_scalars(block, &mut vars);
+    vars
+}
+
+fn collect_local_scalars(block: &BlockStmt, vars: &mut Vec<DefId>) {
+    for item in &block.block_items {
+        match item {
+            BlockItem::Decl(decl) => vars.extend(decl.sub_decls.iter()
+                .filter(|x| x.subs.is_none())
+                .filter_map(|x| x.def_id)),
+            BlockItem::Stmt(stmt) => collect_stmt_scalars(stmt, vars),
+        }
+    }
+}
+
+fn collect_stmt_scalars(stmt: &Stmt, vars: &mut Vec<DefId>) {
+    match stmt {
+        Stmt::Block(x) => collect_local_scalars(x, vars),
+        Stmt::If(x) => {
+            collect_stmt_scalars(&x.then_block, vars);
+            if let Some(else_block) = &x.else_block {
+                collect_stmt_scalars(else_block, vars);
+            }
+        }
+        Stmt::While(x) => collect_stmt_scalars(&x.body, vars),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::ir_builder::{name_resolver::NameResolver, type_checker::TypeChecker};
+    use crate::compiler::syntax::{lexer::Lexer, parser::Parser};
+
+    /// The codes of the diagnostics the lints give on `source`.
+    fn lint_codes(source: &str) -> Vec<&'static str> {
+        let mut program = Parser::new(Lexer::new(source.chars())).parse().unwrap();
+        NameResolver::new().resolve(&mut program).unwrap();
+        let typed = TypeChecker::new().check(&program).unwrap();
+        check_program(&typed, &LintConfig::default()).into_iter().map(|x| x.code).collect()
+    }
+
+    #[test]
+    fn uninit_read_after_loop() {
+        let source = "int main() { int a; int n = getint(); while (n) { a = 1; break; } putint(a); return 0; }";
+        assert_eq!(lint_codes(source), [UNINIT_READ.code]);
+    }
+
+    #[test]
+    fn infinite_loop_is_left_by_break() {
+        let source = "int main() { int a; while (1) { a = 1; break; } putint(a); return 0; }";
+        assert_eq!(lint_codes(source), Vec::<&str>::new());
+    }
+}
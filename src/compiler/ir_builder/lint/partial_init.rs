@@ -0,0 +1,86 @@
+use crate::compiler::ir_builder::type_checker::TypedProgram;
+use crate::compiler::span::Span;
+use crate::compiler::syntax::ast::{AstTy, BlockItem, BlockStmt, Decl, InitVal, InitValKind, LiteralExpr, LiteralKind, ProgramItem, Stmt};
+
+use super::{LintContext, PARTIAL_INIT};
+
+/// Reports brace initializers that list fewer elements than the array they
+/// initialize. The missing elements are zero-filled, so this is allowed by
+/// default; an empty `{}` is taken as an explicit zero-fill and never
+/// reported.
+pub fn check_program(typed_program: &TypedProgram, cx: &mut LintContext) {
+    for item in &typed_program.program().program_items {
+        match item {
+            ProgramItem::Decl(decl) => check_decl(decl, cx),
+            ProgramItem::Func(func) => {
+                if let Some(body) = &func.body {
+                    visit_block(body, cx);
+                }
+            }
+        }
+    }
+}
+
+fn visit_block(block: &BlockStmt, cx: &mut LintContext) {
+    for item in &block.block_items {
+        match item {
+            BlockItem::Decl(decl) => check_decl(decl, cx),
+            BlockItem::Stmt(stmt) => visit_stmt(stmt, cx),
+        }
+    }
+}
+
+fn visit_stmt(stmt: &Stmt, cx: &mut LintContext) {
+    match stmt {
+        Stmt::Block(x) => visit_block(x, cx),
+        Stmt::If(x) => {
+            visit_stmt(&x.then_block, cx);
+            if let Some(else_block) = &x.else_block {
+                visit_stmt(else_block, cx);
+            }
+        }
+        Stmt::While(x) => visit_stmt(&x.body, cx),
+        _ => {}
+    }
+}
+
+fn check_decl(decl: &Decl, cx: &mut LintContext) {
+    for init_val in decl.sub_decls.iter().filter_map(|x| x.init_val.as_ref()) {
+        check_init_val(init_val, cx);
+    }
+}
+
+fn check_init_val(init_val: &InitVal, cx: &mut LintContext) {
+    match &init_val.kind {
+        InitValKind::ArrayVal(vals) => {
+            if let AstTy::Array { siz, .. } = init_val.ty {
+                check_len(vals.len(), siz, init_val.span, cx);
+            }
+            for val in vals {
+                check_init_val(val, cx);
+            }
+        }
+        InitValKind::Const(literal) => check_literal(literal, cx),
+        InitValKind::Expr(_) => {}
+    }
+}
+
+fn check_literal(literal: &LiteralExpr, cx: &mut LintContext) {
+    if let LiteralKind::Array(siz, vals) = &literal.kind {
+        check_len(vals.len(), *siz, literal.span, cx);
+        for val in vals {
+            check_literal(val, cx);
+        }
+    }
+}
+
+fn check_len(len: usize, siz: usize, span: Span, cx: &mut LintContext) {
+    if len == 0 || len >= siz {
+        return;
+    }
+    cx.emit(
+        &PARTIAL_INIT,
+        format!("initializer lists {len} of {siz} elements; the rest are set to zero"),
+        span,
+    );
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use crate::compiler::ir_builder::type_checker::TypedProgram;
+use crate::compiler::syntax::ast::{AstFunc, BinaryOp, BlockItem, BlockStmt, DefId, Expr, InitValKind, InitVal, ProgramItem, Stmt};
+
+use super::{LintContext, UNBOUNDED_RECURSION};
+
+/// Warns on recursive call-graph components in which no function can return
+/// without calling back into the component, e.g. a recursive function whose
+/// base case is missing.
+pub fn check_program(typed_program: &TypedProgram, cx: &mut LintContext) {
+    let funcs: HashMap<DefId, (&AstFunc, &BlockStmt)> = typed_program.program().program_items.iter()
+        .filter_map(|item| match item {
+            ProgramItem::Func(func) => Some((func.def_id?, (func, func.body.as_ref()?))),
+            ProgramItem::Decl(_) => None,
+        })
+        .collect();
+
+    let call_graph = typed_program.call_graph();
+    for scc in call_graph.sccs() {
+        if !call_graph.is_recursive(&scc) {
+            continue;
+        }
+        let mut members: Vec<_> = scc.iter().filter_map(|x| funcs.get(x)).collect();
+        let can_return = members.iter().any(|(_, body)| {
+            let mut escape = Escape { scc: &scc, break_reached: vec![] };
+            let (falls_through, returns) = escape.visit_block(body, true);
+            falls_through || returns
+        });
+        if can_return {
+            continue;
+        }
+        members.sort_by_key(|(func, _)| func.ident.span.start.idx);
+        for (func, _) in members {
+            cx.emit(
+                &UNBOUNDED_RECURSION,
+                format!("function `{}` cannot return without recursing", func.ident.name),
+                func.ident.span,
+            );
+        }
+    }
+}
+
+/// Looks for a path through a function that returns without calling any
+/// member of `scc`. Each `visit_*` takes whether such a path reaches the
+/// node and returns whether one reaches the end of it, plus (for
+/// statements) whether one reaches a `return` inside it.
+struct Escape<'a> {
+    scc: &'a [DefId],
+    /// For each enclosing loop, whether a clean path reaches a `break`.
+    break_reached: Vec<bool>,
+}
+
+impl Escape<'_> {
+    fn visit_block(&mut self, block: &BlockStmt, reached: bool) -> (bool, bool) {
+        block.block_items.iter().fold((reached, false), |(reached, returns), item| {
+            let (reached, item_returns) = match item {
+                BlockItem::Stmt(x) => self.visit_stmt(x, reached),
+                BlockItem::Decl(x) => {
+                    let reached = x.sub_decls.iter()
+                        .filter_map(|x| x.init_val.as_ref())
+                        .fold(reached, |reached, x| self.visit_init_val(x, reached));
+                    (reached, false)
+                }
+            };
+            (reached, returns || item_returns)
+        })
+    }
+
+    fn visit_init_val(&self, init_val: &InitVal, reached: bool) -> bool {
+        match &init_val.kind {
+            InitValKind::Expr(x) => self.visit_expr(x, reached),
+            InitValKind::ArrayVal(vals) => vals.iter().fold(reached, |reached, x| self.visit_init_val(x, reached)),
+            InitValKind::Const(_) => reached,
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt, reached: bool) -> (bool, bool) {
+        match stmt {
+            Stmt::Expr(x) => (self.visit_expr(x, reached), false),
+            Stmt::Block(x) => self.visit_block(x, reached),
+            Stmt::If(x) => {
+                let reached = self.visit_expr(&x.cond, reached);
+                let (then_end, then_returns) = self.visit_stmt(&x.then_block, reached);
+                let (else_end, else_returns) = match &x.else_block {
+                    Some(else_block) => self.visit_stmt(else_block, reached),
+                    None => (reached, false),
+                };
+                (then_end || else_end, then_returns || else_returns)
+            }
+            Stmt::While(x) => {
+                let reached = self.visit_expr(&x.cond, reached);
+                self.break_reached.push(false);
+                let (_, returns) = self.visit_stmt(&x.body, reached);
+                let break_reached = self.break_reached.pop().unwrap();
+                (reached || break_reached, returns)
+            }
+            Stmt::Break(_) => {
+                if let Some(break_reached) = self.break_reached.last_mut() {
+                    *break_reached |= reached;
+                }
+                (false, false)
+            }
+            Stmt::Continue(_) => (false, false),
+            Stmt::Return(x) => {
+                let returns = match &x.val {
+                    Some(val) => self.visit_expr(val, reached),
+                    None => reached,
+                };
+                (false, returns)
+            }
+            Stmt::Empty(_) => (reached, false),
+        }
+    }
+
+    fn visit_expr(&self, expr: &Expr, reached: bool) -> bool {
+        match expr {
+            Expr::LVal(x) => match &x.subs {
+                Some(subs) => subs.subs.iter().fold(reached, |reached, x| self.visit_expr(x, reached)),
+                None => reached,
+            },
+            Expr::Assign(x) => {
+                let reached = self.visit_expr(&x.lhs, reached);
+                self.visit_expr(&x.rhs, reached)
+            }
+            Expr::Literal(_) => reached,
+            Expr::Unary(x) => self.visit_expr(&x.sub_expr, reached),
+            Expr::Cast(x) => self.visit_expr(&x.sub_expr, reached),
+            Expr::Binary(x) => {
+                let reached = self.visit_expr(&x.lhs, reached);
+                if matches!(x.op, BinaryOp::And | BinaryOp::Or) {
+                    // the right operand may be skipped
+                    reached
+                } else {
+                    self.visit_expr(&x.rhs, reached)
+                }
+            }
+            Expr::Call(x) => {
+                let reached = x.args.iter().fold(reached, |reached, x| self.visit_expr(x, reached));
+                reached && !x.def_id.is_some_and(|callee| self.scc.contains(&callee))
+            }
+        }
+    }
+}
@@ -1,5 +1,9 @@
+pub mod call_graph;
 pub mod err;
 
 mod context;
+pub mod name_resolver;
+pub mod semantic_model;
 pub mod ir_builder;
 pub mod type_checker;
+pub mod lint;
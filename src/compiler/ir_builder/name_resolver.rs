@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+
+use crate::compiler::span::{Pos, Span};
+use crate::compiler::syntax::ast::{AssignExpr, AstFunc, BinaryExpr, BlockItem, BlockStmt, CallExpr, CastExpr, Decl, DefId, Expr, FuncParam, Ident, IfStmt, InitVal, InitValKind, LiteralExpr, Program, ProgramItem, ReturnStmt, Stmt, SubDecl, Subs, TypeIdent, UnaryExpr, WhileStmt};
+use crate::compiler::syntax::visitor::AstVisitorMut;
+
+use super::{
+    context::ScopeBuilder,
+    err::SemanticError,
+};
+
+/// Runtime functions visible in every program, predeclared in this order so
+/// that the `n`-th one always gets `DefId(n)`. `starttime` and `stoptime`
+/// call `_sysy_starttime` and `_sysy_stoptime` with the line they are on.
+pub const BUILTIN_FUNCS: [&str; 8] = ["getint", "getch", "getarray", "putint", "putch", "putarray", "starttime", "stoptime"];
+
+/// # Panics
+/// Panics if `name` is not one of [`BUILTIN_FUNCS`].
+#[must_use] pub fn builtin_def_id(name: &str) -> DefId {
+    let idx = BUILTIN_FUNCS.iter()
+        .position(|&x| x == name)
+        .expect("Not a built-in function");
+    DefId(idx)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    BuiltinFunc,
+    Func,
+    Global,
+    Local,
+    Param,
+}
+
+#[derive(Debug, Clone)]
+pub struct DefInfo {
+    pub name: String,
+    pub kind: DefKind,
+    /// Span of the defining identifier, `None` for built-in functions.
+    pub span: Option<Span>,
+    /// Index of the scope the definition lives in.
+    pub scope: usize,
+}
+
+/// A lexical scope: the whole program, a function's parameter list or a
+/// block. Scopes are numbered in the order they are opened, so a nested
+/// scope always has a larger index than the scopes enclosing it.
+#[derive(Debug, Clone)]
+pub struct ScopeInfo {
+    pub span: Span,
+    pub parent: Option<usize>,
+    pub defs: Vec<DefId>,
+}
+
+#[derive(Debug, Default)]
+pub struct DefTable {
+    defs: Vec<DefInfo>,
+    scopes: Vec<ScopeInfo>,
+    /// Every identifier occurrence, definitions included.
+    refs: Vec<(Span, DefId)>,
+}
+
+impl DefTable {
+    #[must_use] pub fn get(&self, def_id: DefId) -> Option<&DefInfo> {
+        self.defs.get(def_id.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (DefId, &DefInfo)> {
+        self.defs.iter().enumerate().map(|(idx, def)| (DefId(idx), def))
+    }
+
+    #[must_use] pub fn scopes(&self) -> &[ScopeInfo] {
+        &self.scopes
+    }
+
+    #[must_use] pub fn refs(&self) -> &[(Span, DefId)] {
+        &self.refs
+    }
+
+    /// Innermost scope whose span contains `pos`.
+    #[must_use] pub fn scope_at(&self, pos: Pos) -> Option<usize> {
+        self.scopes.iter().rposition(|x| x.span.contains(pos))
+    }
+
+    fn push(&mut self, def: DefInfo) -> DefId {
+        let def_id = DefId(self.defs.len());
+        self.scopes[def.scope].defs.push(def_id);
+        if let Some(span) = def.span {
+            self.refs.push((span, def_id));
+        }
+        self.defs.push(def);
+        def_id
+    }
+}
+
+/// Binds every identifier to its definition, filling the `def_id` fields of
+/// the AST so that later passes never look names up again.
+#[derive(Debug)]
+pub struct NameResolver {
+    scopes: ScopeBuilder<DefId>,
+    scope_stack: Vec<usize>,
+    defs: DefTable,
+    /// Functions whose body has been seen, as opposed to only a prototype.
+    func_bodies: HashSet<DefId>,
+}
+
+impl Default for NameResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NameResolver {
+    #[must_use] pub fn new() -> NameResolver {
+        NameResolver {
+            scopes: ScopeBuilder::new(),
+            scope_stack: vec![],
+            defs: DefTable::default(),
+            func_bodies: HashSet::new(),
+        }
+    }
+
+    /// Resolves all names in `program`, returning the table of definitions.
+    ///
+    /// # Errors
+    /// Fails on unknown or duplicate names.
+    pub fn resolve(mut self, program: &mut Program) -> Result<DefTable, SemanticError> {
+        self.visit_program(program)?;
+        Ok(self.defs)
+    }
+
+    fn push_scope(&mut self, span: Span) {
+        self.scopes.push_scope();
+        self.defs.scopes.push(ScopeInfo {
+            span,
+            parent: self.scope_stack.last().copied(),
+            defs: vec![],
+        });
+        self.scope_stack.push(self.defs.scopes.len() - 1);
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop_scope();
+        self.scope_stack.pop();
+    }
+
+    fn define(&mut self, name: &str, kind: DefKind, span: Option<Span>) -> Result<DefId, SemanticError> {
+        let def_id = self.defs.push(DefInfo {
+            name: String::from(name),
+            kind,
+            span,
+            scope: *self.scope_stack.last().expect("No scope found"),
+        });
+        self.scopes.insert(name, def_id)
+            .ok_or_else(|| SemanticError::DuplicateName {
+                name: String::from(name),
+                span: span.expect("builtins are defined first, with no names to clash with"),
+            })?;
+        Ok(def_id)
+    }
+
+    /// Defines the function `func`, or binds it to the definition of an
+    /// earlier prototype of the same name. Two bodies are still duplicates.
+    fn define_func(&mut self, func: &AstFunc) -> Result<DefId, SemanticError> {
+        let prev = self.scopes.find_name_rec(&func.ident.name)
+            .copied()
+            .filter(|&x| self.defs.get(x).is_some_and(|def| def.kind == DefKind::Func));
+        let def_id = match prev {
+            Some(def_id) if func.body.is_none() || !self.func_bodies.contains(&def_id) => {
+                self.defs.refs.push((func.ident.span, def_id));
+                def_id
+            }
+            _ => self.define(&func.ident.name, DefKind::Func, Some(func.ident.span))?,
+        };
+        if func.body.is_some() {
+            self.func_bodies.insert(def_id);
+        }
+        Ok(def_id)
+    }
+
+    fn lookup(&mut self, ident: &Ident) -> Result<DefId, SemanticError> {
+        let def_id = self.scopes.find_name_rec(&ident.name)
+            .copied()
+            .ok_or_else(|| SemanticError::UnknownName { name: ident.name.clone(), span: ident.span })?;
+        self.defs.refs.push((ident.span, def_id));
+        Ok(def_id)
+    }
+
+    fn visit_subs(&mut self, subs: &mut Option<Subs>) -> Result<(), SemanticError> {
+        if let Some(subs) = subs {
+            subs.subs.iter_mut().try_for_each(|x| self.visit_expr(x))?;
+        }
+        Ok(())
+    }
+
+    fn visit_sub_decl(&mut self, sub_decl: &mut SubDecl, kind: DefKind) -> Result<(), SemanticError> {
+        self.visit_subs(&mut sub_decl.subs)?;
+        if let Some(init_val) = &mut sub_decl.init_val {
+            self.visit_init_val(init_val)?;
+        }
+        sub_decl.def_id = Some(self.define(&sub_decl.ident.name, kind, Some(sub_decl.ident.span))?);
+        Ok(())
+    }
+}
+
+impl AstVisitorMut for NameResolver {
+    type ProgramResult = Result<(), SemanticError>;
+    type ConstInitValResult = Result<(), SemanticError>;
+    type FuncResult = Result<(), SemanticError>;
+    type StmtResult = Result<(), SemanticError>;
+    type ExprResult = Result<(), SemanticError>;
+    type LExprResult = Result<(), SemanticError>;
+    type TyResult = Result<(), SemanticError>;
+
+    fn visit_program(&mut self, program: &mut Program) -> Self::ProgramResult {
+        self.push_scope(Span { start: Pos::ZERO, end: Pos::MAX });
+        for name in BUILTIN_FUNCS {
+            self.define(name, DefKind::BuiltinFunc, None)?;
+        }
+
+        program.program_items.iter_mut().try_for_each(|item| match item {
+            ProgramItem::Decl(x) => self.visit_global_decl(x),
+            ProgramItem::Func(x) => self.visit_func(x),
+        })?;
+        self.pop_scope();
+        Ok(())
+    }
+
+    fn visit_const_init_val(&mut self, init_val: &mut InitVal) -> Self::ConstInitValResult {
+        self.visit_init_val(init_val)
+    }
+
+    fn visit_global_decl(&mut self, decl: &mut Decl) -> Self::StmtResult {
+        decl.sub_decls.iter_mut()
+            .try_for_each(|x| self.visit_sub_decl(x, DefKind::Global))
+    }
+
+    fn visit_func(&mut self, func: &mut AstFunc) -> Self::FuncResult {
+        func.def_id = Some(self.define_func(func)?);
+
+        self.push_scope(func.span);
+        func.params.iter_mut().try_for_each(|x| self.visit_func_param(x))?;
+        if let Some(body) = &mut func.body {
+            self.visit_block_stmt(body)?;
+        }
+        self.pop_scope();
+        Ok(())
+    }
+
+    fn visit_func_param(&mut self, param: &mut FuncParam) -> Self::StmtResult {
+        self.visit_subs(&mut param.subs)?;
+        param.def_id = Some(self.define(&param.ident.name, DefKind::Param, Some(param.ident.span))?);
+        Ok(())
+    }
+
+    fn visit_block_stmt(&mut self, stmt: &mut BlockStmt) -> Self::StmtResult {
+        self.push_scope(stmt.span);
+        stmt.block_items.iter_mut().try_for_each(|item| match item {
+            BlockItem::Stmt(x) => self.visit_stmt(x),
+            BlockItem::Decl(x) => self.visit_decl_stmt(x),
+        })?;
+        self.pop_scope();
+        Ok(())
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut Stmt) -> Self::StmtResult {
+        match stmt {
+            Stmt::Expr(x) => self.visit_expr_stmt(x),
+            Stmt::Block(x) => self.visit_block_stmt(x),
+            Stmt::If(x) => self.visit_if_stmt(x),
+            Stmt::While(x) => self.visit_while_stmt(x),
+            Stmt::Break(x) => self.visit_break_stmt(*x),
+            Stmt::Continue(x) => self.visit_continue_stmt(*x),
+            Stmt::Return(x) => self.visit_return_stmt(x),
+            Stmt::Empty(x) => self.visit_empty_stmt(*x),
+        }
+    }
+
+    fn visit_init_val(&mut self, init_val: &mut InitVal) -> Self::StmtResult {
+        match &mut init_val.kind {
+            InitValKind::Expr(x) => self.visit_expr(x),
+            InitValKind::ArrayVal(vals) => vals.iter_mut().try_for_each(|x| self.visit_init_val(x)),
+            InitValKind::Const(_) => Ok(()),
+        }
+    }
+
+    fn visit_decl_stmt(&mut self, decl: &mut Decl) -> Self::StmtResult {
+        decl.sub_decls.iter_mut()
+            .try_for_each(|x| self.visit_sub_decl(x, DefKind::Local))
+    }
+
+    fn visit_expr_stmt(&mut self, stmt: &mut Expr) -> Self::StmtResult {
+        self.visit_expr(stmt)
+    }
+
+    fn visit_if_stmt(&mut self, stmt: &mut IfStmt) -> Self::StmtResult {
+        self.visit_expr(&mut stmt.cond)?;
+        self.visit_stmt(&mut stmt.then_block)?;
+        if let Some(else_block) = &mut stmt.else_block {
+            self.visit_stmt(else_block)?;
+        }
+        Ok(())
+    }
+
+    fn visit_while_stmt(&mut self, stmt: &mut WhileStmt) -> Self::StmtResult {
+        self.visit_expr(&mut stmt.cond)?;
+        self.visit_stmt(&mut stmt.body)
+    }
+
+    fn visit_break_stmt(&mut self, _span: Span) -> Self::StmtResult {
+        Ok(())
+    }
+
+    fn visit_continue_stmt(&mut self, _span: Span) -> Self::StmtResult {
+        Ok(())
+    }
+
+    fn visit_return_stmt(&mut self, stmt: &mut ReturnStmt) -> Self::StmtResult {
+        if let Some(val) = &mut stmt.val {
+            self.visit_expr(val)?;
+        }
+        Ok(())
+    }
+
+    fn visit_empty_stmt(&mut self, _span: Span) -> Self::StmtResult {
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) -> Self::ExprResult {
+        match expr {
+            Expr::LVal(_) => self.visit_lexpr(expr, false),
+            Expr::Assign(x) => self.visit_assign_expr(x),
+            Expr::Literal(x) => self.visit_literal_expr(x),
+            Expr::Unary(x) => self.visit_unary_expr(x),
+            Expr::Binary(x) => self.visit_binary_expr(x),
+            Expr::Call(x) => self.visit_call_expr(x),
+            Expr::Cast(x) => self.visit_cast_expr(x),
+        }
+    }
+
+    fn visit_lexpr(&mut self, expr: &mut Expr, _is_lvalue: bool) -> Self::LExprResult {
+        if let Expr::LVal(lval) = expr {
+            lval.def_id = Some(self.lookup(&lval.ident)?);
+            self.visit_subs(&mut lval.subs)?;
+        }
+        Ok(())
+    }
+
+    fn visit_assign_expr(&mut self, expr: &mut AssignExpr) -> Self::ExprResult {
+        self.visit_expr(&mut expr.lhs)?;
+        self.visit_expr(&mut expr.rhs)
+    }
+
+    fn visit_literal_expr(&mut self, _expr: &mut LiteralExpr) -> Self::ExprResult {
+        Ok(())
+    }
+
+    fn visit_unary_expr(&mut self, expr: &mut UnaryExpr) -> Self::ExprResult {
+        self.visit_expr(&mut expr.sub_expr)
+    }
+
+    fn visit_binary_expr(&mut self, expr: &mut BinaryExpr) -> Self::ExprResult {
+        self.visit_expr(&mut expr.lhs)?;
+        self.visit_expr(&mut expr.rhs)
+    }
+
+    fn visit_call_expr(&mut self, expr: &mut CallExpr) -> Self::ExprResult {
+        expr.def_id = Some(self.lookup(&expr.func)?);
+        expr.args.iter_mut().try_for_each(|x| self.visit_expr(x))
+    }
+
+    fn visit_cast_expr(&mut self, expr: &mut CastExpr) -> Self::ExprResult {
+        self.visit_expr(&mut expr.sub_expr)
+    }
+
+    fn visit_ty(&mut self, _ty_def: &mut TypeIdent) -> Self::TyResult {
+        Ok(())
+    }
+}
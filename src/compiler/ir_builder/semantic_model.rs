@@ -0,0 +1,93 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::span::{Pos, Span};
+use crate::compiler::syntax::ast::{AstTy, DefId, NodeId};
+
+use super::{
+    name_resolver::{DefInfo, DefTable},
+    type_checker::TypeChecker,
+};
+
+/// Read-only view of a checked program, answering the questions an editor
+/// asks: what does this identifier refer to, where is it defined, and what
+/// names are visible here.
+#[derive(Debug)]
+pub struct SemanticModel {
+    defs: DefTable,
+    tys: HashMap<DefId, AstTy>,
+    expr_tys: HashMap<NodeId, (Span, AstTy)>,
+}
+
+impl SemanticModel {
+    /// Combines the output of name resolution with the types recorded by a
+    /// type checker that has checked the same program.
+    #[must_use] pub fn new(defs: DefTable, checker: TypeChecker) -> SemanticModel {
+        SemanticModel {
+            defs,
+            tys: checker.tys.into_iter().map(|(def_id, info)| (def_id, info.ty)).collect(),
+            expr_tys: checker.expr_tys,
+        }
+    }
+
+    #[must_use] pub fn def(&self, def_id: DefId) -> Option<&DefInfo> {
+        self.defs.get(def_id)
+    }
+
+    #[must_use] pub fn def_ty(&self, def_id: DefId) -> Option<&AstTy> {
+        self.tys.get(&def_id)
+    }
+
+    /// Inferred type of the expression `node_id`.
+    #[must_use] pub fn type_of(&self, node_id: NodeId) -> Option<&AstTy> {
+        self.expr_tys.get(&node_id).map(|(_, ty)| ty)
+    }
+
+    /// Inferred type of the expression spanning exactly `span`. If several
+    /// nested expressions share the span, the innermost one wins.
+    #[must_use] pub fn type_at(&self, span: Span) -> Option<&AstTy> {
+        self.expr_tys.iter()
+            .filter(|(_, (x, _))| *x == span)
+            .min_by_key(|(node_id, _)| **node_id)
+            .map(|(_, (_, ty))| ty)
+    }
+
+    /// The symbol named by the identifier (use or definition) under `pos`.
+    #[must_use] pub fn symbol_at(&self, pos: Pos) -> Option<DefId> {
+        self.defs.refs().iter()
+            .find(|(span, _)| span.contains(pos))
+            .map(|(_, def_id)| *def_id)
+    }
+
+    /// Where `def_id` is defined, `None` for built-in functions.
+    #[must_use] pub fn definition_span(&self, def_id: DefId) -> Option<Span> {
+        self.defs.get(def_id)?.span
+    }
+
+    /// All uses and the definition of `def_id`.
+    pub fn references(&self, def_id: DefId) -> impl Iterator<Item = Span> + '_ {
+        self.defs.refs().iter()
+            .filter(move |(_, x)| *x == def_id)
+            .map(|(span, _)| *span)
+    }
+
+    /// Symbols visible at `pos`, innermost first. Shadowed names and names
+    /// defined after `pos` are left out.
+    #[must_use] pub fn symbols_in_scope(&self, pos: Pos) -> Vec<DefId> {
+        let scopes = self.defs.scopes();
+        let mut seen = HashSet::new();
+        let mut symbols = vec![];
+
+        let mut cur_scope = self.defs.scope_at(pos);
+        while let Some(scope) = cur_scope {
+            for &def_id in &scopes[scope].defs {
+                let Some(def) = self.defs.get(def_id) else { continue };
+                let is_visible = def.span.is_none_or(|x| x.start.idx <= pos.idx);
+                if is_visible && seen.insert(def.name.as_str()) {
+                    symbols.push(def_id);
+                }
+            }
+            cur_scope = scopes[scope].parent;
+        }
+        symbols
+    }
+}
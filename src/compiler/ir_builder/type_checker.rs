@@ -1,51 +1,125 @@
+use std::collections::HashMap;
+
 use itertools::Itertools;
 
 use crate::compiler::span::Span;
-use crate::compiler::syntax::ast::{AssignExpr, AstFunc, AstTy, BinaryExpr, BinaryOp, BlockItem, BlockStmt, CallExpr, Decl, Expr, FuncParam, IfStmt, InitVal, InitValKind, LiteralExpr, LiteralKind, PrimitiveTy, Program, ProgramItem, ReturnStmt, Stmt, Subs, TyIdentKind, TypeIdent, UnaryExpr, UnaryOp, WhileStmt};
+use crate::compiler::syntax::ast::{AssignExpr, AstFunc, AstTy, BinaryExpr, BinaryOp, BlockItem, BlockStmt, CallExpr, CastExpr, Decl, DefId, Expr, FuncParam, Ident, IfStmt, InitVal, InitValKind, LiteralExpr, LiteralKind, NodeId, PrimitiveTy, Program, ProgramItem, ReturnStmt, Stmt, Subs, TyIdentKind, TypeIdent, UnaryExpr, UnaryOp, WhileStmt};
 use crate::compiler::syntax::visitor::AstVisitorMut;
 
 use super::{
-    context::{ScopeBuilder, TyInfo},
+    call_graph::CallGraph,
+    context::TyInfo,
     err::SemanticError::{self, TypeMismatch},
+    name_resolver::builtin_def_id,
 };
 
 macro_rules! expect_type {
-    ($self:expr, $pat:pat) => {{
-        if matches!($self, $pat) {
+    ($expr:expr, $ty:expr) => {{
+        if std::mem::discriminant(&$expr.ty()) == std::mem::discriminant(&$ty) {
             Ok(())
         } else {
             Err(TypeMismatch {
-                expected: String::from(stringify!($pat)),
-                found: $self
+                expected: $ty.to_string(),
+                found: $expr.ty(),
+                span: $expr.span(),
             })
         }
     }};
 }
 
+/// A type-checked copy of a program: every `ty` field is filled and constant
+/// expressions are folded into literals, with the type of each expression of
+/// the source kept by its node id. Only [`TypeChecker::check`] builds one, so
+/// holding a `TypedProgram` means the checks have passed.
+#[derive(Debug, Clone)]
+pub struct TypedProgram {
+    program: Program,
+    expr_tys: HashMap<NodeId, AstTy>,
+    const_vals: HashMap<NodeId, i32>,
+    call_graph: CallGraph,
+}
+
+impl TypedProgram {
+    #[must_use] pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    #[must_use] pub fn into_program(self) -> Program {
+        self.program
+    }
+
+    /// Inferred type of the expression `node_id`, also for expressions of the
+    /// source that constant folding replaced.
+    #[must_use] pub fn type_of(&self, node_id: NodeId) -> Option<&AstTy> {
+        self.expr_tys.get(&node_id)
+    }
+
+    /// Value of the expression `node_id` if constant folding reduced it to
+    /// an integer (booleans are 0 or 1).
+    #[must_use] pub fn const_val(&self, node_id: NodeId) -> Option<i32> {
+        self.const_vals.get(&node_id).copied()
+    }
+
+    #[must_use] pub fn call_graph(&self) -> &CallGraph {
+        &self.call_graph
+    }
+}
+
 #[derive(Debug)]
 pub struct TypeChecker {
-    pub scopes: ScopeBuilder<TyInfo>,
+    /// Types of all definitions seen so far, keyed by the ids assigned in
+    /// name resolution.
+    pub tys: HashMap<DefId, TyInfo>,
+    /// Span and inferred type of every expression checked, recorded before
+    /// constant folding replaces it.
+    pub expr_tys: HashMap<NodeId, (Span, AstTy)>,
+    pub const_vals: HashMap<NodeId, i32>,
+    pub call_graph: CallGraph,
     pub cur_func_ret_ty: AstTy,
+    cur_func: Option<DefId>,
+    /// Identifier span of the first declaration of each function.
+    func_spans: HashMap<DefId, Span>,
 }
 
 impl TypeChecker {
     #[must_use] pub fn new() -> TypeChecker {
         TypeChecker {
-            scopes: ScopeBuilder::new(),
+            tys: HashMap::new(),
+            expr_tys: HashMap::new(),
+            const_vals: HashMap::new(),
+            call_graph: CallGraph::default(),
             cur_func_ret_ty: AstTy::Unknown,
+            cur_func: None,
+            func_spans: HashMap::new(),
         }
     }
+
+    /// Type checks `program` without touching it, returning the typed copy.
+    ///
+    /// # Errors
+    /// Fails on the first semantic error found.
+    pub fn check(&mut self, program: &Program) -> Result<TypedProgram, SemanticError> {
+        let mut typed = program.clone();
+        self.visit_program(&mut typed)?;
+        Ok(TypedProgram {
+            program: typed,
+            expr_tys: self.expr_tys.iter().map(|(&node_id, (_, ty))| (node_id, ty.clone())).collect(),
+            const_vals: self.const_vals.clone(),
+            call_graph: self.call_graph.clone(),
+        })
+    }
 }
 
 impl TypeChecker {
     fn fix_array_literal(literal: &mut LiteralExpr, expected_ty: &AstTy) -> Result<(), SemanticError> {
+        let span = literal.span;
         match (&mut literal.kind, expected_ty) {
             (LiteralKind::Integer(_), AstTy::Int) => Ok(()),
             (LiteralKind::Array(literal_siz, literal_vals),
                 AstTy::Array { siz: ty_siz, elem_ty })
             => {
                 if *literal_siz > *ty_siz {
-                    return Err(SemanticError::TooMuchElement);
+                    return Err(SemanticError::TooMuchElement { span });
                 }
 
                 literal_vals.iter_mut()
@@ -56,18 +130,19 @@ impl TypeChecker {
                 literal.ty = expected_ty.clone();
                 Ok(())
             }
-            _ => assert_type_eq(expected_ty, &AstTy::Unknown)
+            _ => assert_type_eq(expected_ty, &AstTy::Unknown, span)
         }
     }
 
     fn fix_array_init_val(init_val: &mut InitVal, expected_ty: &AstTy) -> Result<(), SemanticError> {
+        let span = init_val.span;
         match (&mut init_val.kind, expected_ty) {
-            (InitValKind::Expr(expr), _) => assert_type_eq(expected_ty, &expr.ty()),
+            (InitValKind::Expr(expr), _) => assert_type_eq(expected_ty, &expr.ty(), expr.span()),
             (InitValKind::ArrayVal(elem_vals),
                 AstTy::Array { siz: ty_siz, elem_ty })
             => {
                 if elem_vals.len() > *ty_siz {
-                    return Err(SemanticError::TooMuchElement);
+                    return Err(SemanticError::TooMuchElement { span });
                 }
 
                 elem_vals.iter_mut()
@@ -77,7 +152,7 @@ impl TypeChecker {
                 init_val.ty = expected_ty.clone();
                 Ok(())
             }
-            _ => assert_type_eq(expected_ty, &AstTy::Unknown)
+            _ => assert_type_eq(expected_ty, &AstTy::Unknown, span)
         }
     }
 
@@ -85,10 +160,10 @@ impl TypeChecker {
         if subs.is_some() {
             for sub in &mut subs.as_mut().unwrap().subs {
                 let literal = self.visit_expr(sub)?
-                    .ok_or(SemanticError::RequireConstant)?;
+                    .ok_or(SemanticError::RequireConstant { span: sub.span() })?;
 
                 if literal.get_int().unwrap() <= 0 {
-                    return Err(SemanticError::IllegalArrayDim);
+                    return Err(SemanticError::IllegalArrayDim { span: sub.span() });
                 }
                 *sub = Expr::Literal(literal);
             }
@@ -105,10 +180,26 @@ impl TypeChecker {
         Ok(ty)
     }
 
+    fn define(&mut self, def_id: Option<DefId>, info: TyInfo) {
+        self.tys.insert(def_id.expect("Name not resolved"), info);
+    }
+
+    fn lookup(&self, def_id: Option<DefId>, ident: &Ident) -> Result<&TyInfo, SemanticError> {
+        def_id.and_then(|x| self.tys.get(&x))
+            .ok_or_else(|| SemanticError::UnknownName { name: ident.name.clone(), span: ident.span })
+    }
+
+    fn record_expr_ty(&mut self, expr: &Expr) {
+        if expr.node_id() == NodeId::DUMMY {
+            return;
+        }
+        self.expr_tys.insert(expr.node_id(), (expr.span(), expr.ty()));
+    }
+
     fn push_built_in_funcs(&mut self) {
         // getint
-        self.scopes.insert(
-            "getint",
+        self.tys.insert(
+            builtin_def_id("getint"),
             TyInfo {
                 ty: AstTy::Func { ret_ty: Box::from(AstTy::Int), param_tys: vec![] },
                 const_val: None,
@@ -117,8 +208,8 @@ impl TypeChecker {
 
 
         // getch
-        self.scopes.insert(
-            "getch",
+        self.tys.insert(
+            builtin_def_id("getch"),
             TyInfo {
                 ty: AstTy::Func { ret_ty: Box::from(AstTy::Int), param_tys: vec![] },
                 const_val: None,
@@ -126,8 +217,8 @@ impl TypeChecker {
             });
 
         // getarray
-        self.scopes.insert(
-            "getarray",
+        self.tys.insert(
+            builtin_def_id("getarray"),
             TyInfo {
                 ty: AstTy::Func { ret_ty: Box::from(AstTy::Int), param_tys: vec![AstTy::Ptr(Box::new(AstTy::Int))] },
                 const_val: None,
@@ -135,8 +226,8 @@ impl TypeChecker {
             });
 
         // putint
-        self.scopes.insert(
-            "putint",
+        self.tys.insert(
+            builtin_def_id("putint"),
             TyInfo {
                 ty: AstTy::Func { ret_ty: Box::from(AstTy::Void), param_tys: vec![AstTy::Int] },
                 const_val: None,
@@ -144,8 +235,8 @@ impl TypeChecker {
             });
 
         // putch
-        self.scopes.insert(
-            "putch",
+        self.tys.insert(
+            builtin_def_id("putch"),
             TyInfo {
                 ty: AstTy::Func { ret_ty: Box::from(AstTy::Void), param_tys: vec![AstTy::Int] },
                 const_val: None,
@@ -153,8 +244,8 @@ impl TypeChecker {
             });
 
         // putarray
-        self.scopes.insert(
-            "putarray",
+        self.tys.insert(
+            builtin_def_id("putarray"),
             TyInfo {
                 ty: AstTy::Func {
                     ret_ty: Box::from(AstTy::Int),
@@ -162,6 +253,24 @@ impl TypeChecker {
                 const_val: None,
                 is_const: false,
             });
+
+        // starttime
+        self.tys.insert(
+            builtin_def_id("starttime"),
+            TyInfo {
+                ty: AstTy::Func { ret_ty: Box::from(AstTy::Void), param_tys: vec![] },
+                const_val: None,
+                is_const: false,
+            });
+
+        // stoptime
+        self.tys.insert(
+            builtin_def_id("stoptime"),
+            TyInfo {
+                ty: AstTy::Func { ret_ty: Box::from(AstTy::Void), param_tys: vec![] },
+                const_val: None,
+                is_const: false,
+            });
     }
 }
 
@@ -175,8 +284,6 @@ impl AstVisitorMut for TypeChecker {
     type TyResult = Result<AstTy, SemanticError>;
 
     fn visit_program(&mut self, program: &mut Program) -> Self::ProgramResult {
-        self.scopes.push_scope();
-
         self.push_built_in_funcs();
 
         program.program_items.iter_mut().try_for_each(|item| {
@@ -184,26 +291,27 @@ impl AstVisitorMut for TypeChecker {
                 ProgramItem::Decl(x) => self.visit_global_decl(x),
                 ProgramItem::Func(x) => self.visit_func(x),
             }
-        })?;
-        self.scopes.pop_scope();
-        Ok(())
+        })
     }
 
     fn visit_const_init_val(&mut self, init_val: &mut InitVal) -> Self::ConstInitValResult {
         match &mut init_val.kind {
             InitValKind::Expr(x) => {
-                let literal = match self.visit_expr(x)? {
-                    Some(x) => Ok(x),
-                    None => Err(SemanticError::RequireConstant),
-                };
-                init_val.ty = x.ty();
-                literal
+                let mut literal = self.visit_expr(x)?
+                    .ok_or(SemanticError::RequireConstant { span: x.span() })?;
+                // variables are all int, so a folded comparison is stored as one
+                if literal.ty == AstTy::Bool {
+                    literal.ty = AstTy::Int;
+                }
+                init_val.ty = literal.ty.clone();
+                Ok(literal)
             }
             InitValKind::ArrayVal(vals) => {
                 let literals: Vec<_> = vals.iter_mut()
                     .map(|x| self.visit_const_init_val(x))
                     .try_collect()?;
                 Ok(LiteralExpr {
+                    node_id: NodeId::DUMMY,
                     kind: LiteralKind::Array(literals.len(), literals),
                     span: init_val.span,
                     ty: AstTy::Unknown,
@@ -222,7 +330,7 @@ impl AstVisitorMut for TypeChecker {
             let init_val = if let Some(init_val) = &mut sub_decl.init_val {
                 let mut literal = self.visit_const_init_val(init_val)?;
                 match ty {
-                    AstTy::Int => assert_type_eq(&ty, &literal.ty)?,
+                    AstTy::Int => assert_type_eq(&ty, &literal.ty, init_val.span)?,
                     AstTy::Array { .. } => Self::fix_array_literal(&mut literal, &ty)?,
                     _ => unreachable!()
                 };
@@ -233,7 +341,7 @@ impl AstVisitorMut for TypeChecker {
             };
 
             if decl.is_const && init_val.is_none() {
-                return Err(SemanticError::RequireConstant);
+                return Err(SemanticError::RequireConstant { span: sub_decl.ident.span });
             }
 
             sub_decl.ty = ty.clone();
@@ -243,7 +351,7 @@ impl AstVisitorMut for TypeChecker {
                 const_val: if decl.is_const { init_val } else { None },
                 is_const: decl.is_const,
             };
-            self.scopes.insert(&sub_decl.ident.name, info);
+            self.define(sub_decl.def_id, info);
         }
         Ok(())
     }
@@ -259,28 +367,41 @@ impl AstVisitorMut for TypeChecker {
             .collect();
 
         let func_ty = AstTy::Func { ret_ty: Box::new(ret_ty), param_tys };
+        let def_id = ast_func.def_id.expect("Name not resolved");
+        if let Some(&prev_span) = self.func_spans.get(&def_id) {
+            if self.tys[&def_id].ty != func_ty {
+                return Err(SemanticError::SignatureMismatch {
+                    name: ast_func.ident.name.clone(),
+                    span: ast_func.ident.span,
+                    prev_span,
+                });
+            }
+        } else {
+            self.func_spans.insert(def_id, ast_func.ident.span);
+        }
+
         let func_info = TyInfo {
             ty: func_ty,
             const_val: None,
             is_const: false,
         };
-        self.scopes.insert(&ast_func.ident.name, func_info)
-            .ok_or(SemanticError::DuplicateName(ast_func.ident.name.clone()))?;
+        self.define(ast_func.def_id, func_info);
+        self.cur_func = ast_func.def_id;
+        self.call_graph.add_func(def_id);
+        let Some(body) = &mut ast_func.body else {
+            return Ok(());
+        };
 
-        self.scopes.push_scope();
         for param in &ast_func.params {
             let param_info = TyInfo {
                 ty: param.ty.clone(),
                 const_val: None,
                 is_const: false,
             };
-            self.scopes.insert(&param.ident.name, param_info)
-                .ok_or(SemanticError::DuplicateName(param.ident.name.clone()))?;
+            self.define(param.def_id, param_info);
         }
 
-        self.visit_block_stmt(&mut ast_func.body)?;
-        self.scopes.pop_scope();
-        Ok(())
+        self.visit_block_stmt(body)
     }
 
     fn visit_func_param(&mut self, param: &mut FuncParam) -> Self::StmtResult {
@@ -294,13 +415,10 @@ impl AstVisitorMut for TypeChecker {
     }
 
     fn visit_block_stmt(&mut self, stmt: &mut BlockStmt) -> Self::StmtResult {
-        self.scopes.push_scope();
         stmt.block_items.iter_mut().try_for_each(|sub_stmt| match sub_stmt {
             BlockItem::Stmt(x) => self.visit_stmt(x),
             BlockItem::Decl(x) => self.visit_decl_stmt(x),
-        })?;
-        self.scopes.pop_scope();
-        Ok(())
+        })
     }
 
     fn visit_stmt(&mut self, stmt: &mut Stmt) -> Self::StmtResult {
@@ -319,11 +437,11 @@ impl AstVisitorMut for TypeChecker {
     fn visit_init_val(&mut self, init_val: &mut InitVal) -> Self::StmtResult {
         match &mut init_val.kind {
             InitValKind::Expr(expr) => {
-                let literal = self.visit_expr(expr)?;
-                init_val.ty = expr.ty();
-                if let Some(literal) = literal {
-                    init_val.kind = InitValKind::Expr(Expr::Literal(literal));
+                if let Some(literal) = self.visit_expr(expr)? {
+                    *expr = Expr::Literal(literal);
                 }
+                coerce(expr, &AstTy::Int);
+                init_val.ty = expr.ty();
             }
             InitValKind::ArrayVal(vals) => {
                 vals.iter_mut()
@@ -343,14 +461,14 @@ impl AstVisitorMut for TypeChecker {
             if let Some(init_val) = &mut sub_decl.init_val {
                 self.visit_init_val(init_val)?;
                 match ty {
-                    AstTy::Int => assert_type_eq(&ty, &init_val.ty)?,
+                    AstTy::Int => assert_type_eq(&ty, &init_val.ty, init_val.span)?,
                     AstTy::Array { .. } => Self::fix_array_init_val(init_val, &ty)?,
                     _ => unreachable!()
                 };
             }
 
             if decl.is_const && sub_decl.init_val.is_none() {
-                return Err(SemanticError::RequireConstant);
+                return Err(SemanticError::RequireConstant { span: sub_decl.ident.span });
             }
 
             sub_decl.ty = ty.clone();
@@ -360,7 +478,7 @@ impl AstVisitorMut for TypeChecker {
                 const_val: None,
                 is_const: decl.is_const,
             };
-            self.scopes.insert(&sub_decl.ident.name, ty_info);
+            self.define(sub_decl.def_id, ty_info);
         }
         Ok(())
     }
@@ -371,8 +489,10 @@ impl AstVisitorMut for TypeChecker {
     }
 
     fn visit_if_stmt(&mut self, stmt: &mut IfStmt) -> Self::StmtResult {
+        check_not_assign(&stmt.cond)?;
         self.visit_expr(&mut stmt.cond)?;
-        expect_type!(stmt.cond.ty(), AstTy::Bool)?;
+        coerce(&mut stmt.cond, &AstTy::Bool);
+        expect_type!(stmt.cond, AstTy::Bool)?;
         self.visit_stmt(&mut stmt.then_block)?;
         if let Some(else_blk) = &mut stmt.else_block {
             self.visit_stmt(else_blk)?;
@@ -381,8 +501,10 @@ impl AstVisitorMut for TypeChecker {
     }
 
     fn visit_while_stmt(&mut self, stmt: &mut WhileStmt) -> Self::StmtResult {
+        check_not_assign(&stmt.cond)?;
         self.visit_expr(&mut stmt.cond)?;
-        expect_type!(stmt.cond.ty(), AstTy::Bool)?;
+        coerce(&mut stmt.cond, &AstTy::Bool);
+        expect_type!(stmt.cond, AstTy::Bool)?;
         self.visit_stmt(&mut stmt.body)?;
         Ok(())
     }
@@ -396,15 +518,16 @@ impl AstVisitorMut for TypeChecker {
     }
 
     fn visit_return_stmt(&mut self, stmt: &mut ReturnStmt) -> Self::StmtResult {
-        let ret_val_ty = match &mut stmt.val {
+        let (ret_val_ty, span) = match &mut stmt.val {
             Some(expr) => {
                 self.visit_expr(expr.as_mut())?;
-                expr.ty()
+                coerce(expr, &self.cur_func_ret_ty);
+                (expr.ty(), expr.span())
             }
-            None => AstTy::Void,
+            None => (AstTy::Void, stmt.span),
         };
 
-        assert_type_eq(&ret_val_ty, &self.cur_func_ret_ty)?;
+        assert_type_eq(&self.cur_func_ret_ty, &ret_val_ty, span)?;
         Ok(())
     }
 
@@ -413,25 +536,28 @@ impl AstVisitorMut for TypeChecker {
     }
 
     fn visit_expr(&mut self, expr: &mut Expr) -> Self::ExprResult {
-        match expr {
+        let literal = match expr {
             Expr::LVal(_) => self.visit_lexpr(expr, false),
             Expr::Assign(x) => self.visit_assign_expr(x),
             Expr::Literal(x) => self.visit_literal_expr(x),
             Expr::Unary(x) => self.visit_unary_expr(x),
             Expr::Binary(x) => self.visit_binary_expr(x),
-            Expr::Call(x) => self.visit_call_expr(x)
+            Expr::Call(x) => self.visit_call_expr(x),
+            Expr::Cast(x) => self.visit_cast_expr(x),
+        }?;
+        self.record_expr_ty(expr);
+        if let Some(val) = literal.as_ref().and_then(LiteralExpr::get_int) {
+            self.const_vals.insert(expr.node_id(), val);
         }
+        Ok(literal)
     }
 
     fn visit_lexpr(&mut self, expr: &mut Expr, is_lvalue: bool) -> Self::LExprResult {
         match expr {
             Expr::LVal(lval) => {
-                let lval_name = &lval.ident.name;
-                let ty_info = self.scopes.find_name_rec(lval_name)
-                    .ok_or(SemanticError::UnknownName(lval_name.clone()))?
-                    .clone();
+                let ty_info = self.lookup(lval.def_id, &lval.ident)?.clone();
                 if ty_info.is_const && is_lvalue {
-                    return Err(SemanticError::CannotModifyConstValue(lval_name.clone()));
+                    return Err(SemanticError::CannotModifyConstValue { name: lval.ident.name.clone(), span: lval.span });
                 }
 
                 lval.is_lvalue = is_lvalue;
@@ -442,14 +568,15 @@ impl AstVisitorMut for TypeChecker {
 
                     for sub in &mut *subs {
                         self.visit_expr(sub)?;
-                        expect_type!(sub.ty(), AstTy::Int)?;
+                        expect_type!(sub, AstTy::Int)?;
 
                         if let AstTy::Array { elem_ty, .. } | AstTy::Ptr(elem_ty) = cur_ty {
                             cur_ty = elem_ty.as_ref();
                         } else {
                             return Err(SemanticError::TypeMismatch {
-                                expected: String::from("ArrayType"),
+                                expected: String::from("an array"),
                                 found: (*cur_ty).clone(),
+                                span: lval.span,
                             })
                         }
                     }
@@ -465,17 +592,20 @@ impl AstVisitorMut for TypeChecker {
                     };
                 Ok(literal)
             }
-            _ => Err(SemanticError::RequireLValue)
+            _ => Err(SemanticError::RequireLValue { span: expr.span() })
         }
     }
 
     fn visit_assign_expr(&mut self, expr: &mut AssignExpr) -> Self::ExprResult {
         self.visit_lexpr(&mut expr.lhs, true)?;
+        self.record_expr_ty(&expr.lhs);
         let rval = self.visit_expr(&mut expr.rhs)?;
         if let Some(rval) = &rval {
             expr.rhs = Box::new(Expr::Literal(rval.clone()));
         }
-        expect_type!(expr.lhs.ty(), AstTy::Int | AstTy::Bool)?;
+        expect_type!(expr.lhs, AstTy::Int)?;
+        coerce(&mut expr.rhs, &expr.lhs.ty());
+        expr.ty = expr.lhs.ty();
         Ok(rval)
     }
 
@@ -492,32 +622,37 @@ impl AstVisitorMut for TypeChecker {
         if let Some(sub_expr_val) = &sub_expr_val {
             expr.sub_expr = Box::new(Expr::Literal(sub_expr_val.clone()));
         }
-        let sub_expr_ty = expr.sub_expr.ty();
+        match expr.op {
+            UnaryOp::Neg | UnaryOp::Pos => coerce(&mut expr.sub_expr, &AstTy::Int),
+            UnaryOp::Not => coerce(&mut expr.sub_expr, &AstTy::Bool),
+        }
 
         let result_val = match expr.op {
             UnaryOp::Neg => {
-                expect_type!(sub_expr_ty, AstTy::Int)?;
+                expect_type!(expr.sub_expr, AstTy::Int)?;
                 expr.ty = AstTy::Int;
 
                 sub_expr_val.and_then(|x| x.get_int())
                     .map(|x| LiteralExpr {
+                        node_id: expr.node_id,
                         kind: LiteralKind::Integer(-x),
                         span: expr.span,
                         ty: AstTy::Int,
                     })
             }
             UnaryOp::Pos => {
-                expect_type!(sub_expr_ty, AstTy::Int)?;
+                expect_type!(expr.sub_expr, AstTy::Int)?;
                 expr.ty = AstTy::Int;
                 sub_expr_val
             }
             UnaryOp::Not => {
-                expect_type!(sub_expr_ty, AstTy::Int | AstTy::Bool)?;
-                expr.ty = expr.sub_expr.ty();
+                expect_type!(expr.sub_expr, AstTy::Bool)?;
+                expr.ty = AstTy::Bool;
 
                 sub_expr_val.and_then(|x| x.get_int())
                     .map(|x| LiteralExpr {
-                        kind: LiteralKind::Integer(i32::from(x != 0)),
+                        node_id: expr.node_id,
+                        kind: LiteralKind::Integer(i32::from(x == 0)),
                         span: expr.span,
                         ty: expr.ty.clone(),
                     })
@@ -540,6 +675,13 @@ impl AstVisitorMut for TypeChecker {
             expr.rhs = Box::new(Expr::Literal(rval));
         }
 
+        let operand_ty = match op {
+            And | Or => AstTy::Bool,
+            Add | Sub | Mul | Div | Mod | Lt | Le | Gt | Ge | Eq | Ne => AstTy::Int,
+        };
+        coerce(&mut expr.lhs, &operand_ty);
+        coerce(&mut expr.rhs, &operand_ty);
+
         let legal = match (expr.lhs.ty(), expr.rhs.ty()) {
             (AstTy::Int, AstTy::Int) => matches!(op, Add | Sub | Mul | Div | Mod | Lt | Le | Gt | Ge | Eq | Ne),
             (AstTy::Bool, AstTy::Bool) => matches!(op, And | Or),
@@ -548,11 +690,9 @@ impl AstVisitorMut for TypeChecker {
 
         if !legal {
             return Err(SemanticError::TypeMismatch {
-                expected: match op {
-                    Add | Sub | Mul | Div | Mod | Lt | Le | Gt | Ge | Eq | Ne => String::from("AstTy::Int"),
-                    And | Or => String::from("AstTy::Bool"),
-                },
+                expected: operand_ty.to_string(),
                 found: expr.lhs.ty(),
+                span: expr.span,
             })
         }
 
@@ -584,6 +724,7 @@ impl AstVisitorMut for TypeChecker {
                 Or => i32::from(lval != 0 || rval != 0),
             };
             Some(LiteralExpr {
+                node_id: expr.node_id,
                 kind: LiteralKind::Integer(result),
                 span: Span { start: lspan.start, end: rspan.end },
                 ty: result_ty,
@@ -599,23 +740,30 @@ impl AstVisitorMut for TypeChecker {
         expr.args.iter_mut()
             .try_for_each(|arg| self.visit_expr(arg).and(Ok(())))?;
 
-        let func_name = &expr.func.name;
-        let (ret_ty, param_tys) = self.scopes.find_name_rec(func_name)
-            .ok_or(SemanticError::UnknownName(func_name.clone()))?
+        if let (Some(caller), Some(callee)) = (self.cur_func, expr.def_id) {
+            self.call_graph.add_call(caller, callee);
+        }
+
+        let (ret_ty, param_tys) = self.lookup(expr.def_id, &expr.func)?
             .ty.as_func()
-            .ok_or(SemanticError::ExpectedFunction(func_name.clone()))?;
+            .ok_or_else(|| SemanticError::ExpectedFunction { name: expr.func.name.clone(), span: expr.func.span })?;
 
+        expr.args.iter_mut()
+            .zip(param_tys)
+            .for_each(|(arg, ty)| coerce(arg, ty));
         expr.args.iter()
-            .map(Expr::ty)
             .zip(param_tys)
-            .try_for_each(|(expected, found)| {
-                assert_type_eq(&expected, found)
-            })?;
+            .try_for_each(|(arg, ty)| assert_type_eq(ty, &arg.ty(), arg.span()))?;
 
         expr.ty = ret_ty.as_ref().clone();
         Ok(None)
     }
 
+    fn visit_cast_expr(&mut self, _expr: &mut CastExpr) -> Self::ExprResult {
+        // casts are only built by `coerce`, after their operand was checked
+        Ok(None)
+    }
+
     fn visit_ty(&mut self, ty_def: &mut TypeIdent) -> Self::TyResult {
         let ty = match &ty_def.kind {
             TyIdentKind::Primitive(prim_ty) => match prim_ty {
@@ -627,12 +775,58 @@ impl AstVisitorMut for TypeChecker {
     }
 }
 
-fn assert_type_eq(expected: &AstTy, found: &AstTy) -> Result<(), SemanticError> {
+fn assert_type_eq(expected: &AstTy, found: &AstTy, span: Span) -> Result<(), SemanticError> {
     if expected != found {
         return Err(TypeMismatch {
-            expected: format!("{expected:?}"),
+            expected: expected.to_string(),
             found: found.clone(),
+            span,
         });
     }
     Ok(())
 }
+
+/// Rejects `if (a = b)`, which is almost always a typo for `==`.
+fn check_not_assign(cond: &Expr) -> Result<(), SemanticError> {
+    if let Expr::Assign(assign) = cond {
+        return Err(SemanticError::AssignInCondition {
+            span: assign.span,
+            op_span: Span { start: assign.lhs.span().end, end: assign.rhs.span().start },
+        });
+    }
+    Ok(())
+}
+
+/// Converts `expr` between the scalar types `int` and `bool` when it is used
+/// at the other one, wrapping it in a `CastExpr` (a folded literal is
+/// retyped in place instead). Any other mismatch is left for the caller to
+/// report.
+fn coerce(expr: &mut Expr, ty: &AstTy) {
+    let expr_ty = expr.ty();
+    if !matches!((&expr_ty, ty), (AstTy::Int, AstTy::Bool) | (AstTy::Bool, AstTy::Int)) {
+        return;
+    }
+
+    if let Expr::Literal(literal) = expr {
+        if let (LiteralKind::Integer(x), AstTy::Bool) = (&mut literal.kind, ty) {
+            *x = i32::from(*x != 0);
+        }
+        literal.ty = ty.clone();
+        return;
+    }
+
+    let span = expr.span();
+    let placeholder = Expr::Literal(LiteralExpr {
+        node_id: NodeId::DUMMY,
+        kind: LiteralKind::Integer(0),
+        span,
+        ty: AstTy::Unknown,
+    });
+    let sub_expr = std::mem::replace(expr, placeholder);
+    *expr = Expr::Cast(CastExpr {
+        node_id: NodeId::DUMMY,
+        sub_expr: Box::new(sub_expr),
+        span,
+        ty: ty.clone(),
+    });
+}
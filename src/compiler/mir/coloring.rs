@@ -0,0 +1,510 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::compiler::mir::{
+    liveness::Liveness,
+    loops::MachineLoops,
+    BlockId, FrameObjId, FrameObjKind, MachineFunc, MachineInst, Reg, RegClass, RegisterInfo,
+};
+
+/// Allocates registers by coloring the interference graph, with the
+/// iterated register coalescing of George and Appel: nodes of few
+/// neighbours are taken off the graph, and the two sides of a move merged
+/// whenever that cannot make the graph harder to color, so that the copies
+/// instruction selection leaves at calls, parameters and the like go away.
+///
+/// When a register has to be spilled, the one of least cost is picked, its
+/// uses and definitions weighted by how often their block runs, and the
+/// function colored again once [`Spiller::spill`] has rewritten it.
+///
+/// Each class of registers is colored on its own, as registers of
+/// different classes never share a physical one.
+pub fn allocate<I: MachineInst>(func: &mut MachineFunc<I>, regs: &RegisterInfo) {
+    let mut spiller = Spiller::default();
+    for class in RegClass::ALL {
+        loop {
+            let mut graph = Graph::new(func, regs.allocatable(class), class, &spiller.unspillable);
+            graph.reduce();
+            match graph.colors() {
+                Ok(colors) => {
+                    for block in &mut func.blocks {
+                        for inst in &mut block.insts {
+                            inst.map_regs(&mut |x| colors.get(&x).copied().unwrap_or(x));
+                        }
+                    }
+                    break;
+                }
+                Err(uncolored) => spiller.spill(func, regs, &uncolored),
+            }
+        }
+    }
+    func.remove_identity_moves();
+}
+
+/// What spilling has left behind, across the rounds of coloring.
+#[derive(Debug, Default)]
+struct Spiller {
+    /// Registers carrying a spilled value into or out of one instruction.
+    unspillable: HashSet<Reg>,
+    /// Registers a spilled value was loaded into ahead of a loop, with the
+    /// slot it was loaded from.
+    homes: HashMap<Reg, FrameObjId>,
+}
+
+impl Spiller {
+    /// Takes each register of `spilled` out of registers but around the
+    /// instructions using it. One whose only definition builds a constant
+    /// is built again before each use. Any other is stored to a slot after
+    /// each definition, and loaded back before each use, into registers of
+    /// their own that are never spilled, but in loops not defining it,
+    /// where it is loaded once ahead of the loop instead.
+    fn spill<I: MachineInst>(&mut self, func: &mut MachineFunc<I>, regs: &RegisterInfo, spilled: &[Reg]) {
+        let loops = MachineLoops::new(func);
+        let mut remat: HashMap<Reg, I> = HashMap::new();
+        let mut slots: HashMap<Reg, FrameObjId> = HashMap::new();
+        for &reg in spilled {
+            let mut defs = func.blocks.iter().flat_map(|x| &x.insts).filter(|x| x.defs().contains(&reg));
+            if let (Some(def), None) = (defs.next(), defs.next()) {
+                if def.is_rematerializable() {
+                    remat.insert(reg, def.clone());
+                    continue;
+                }
+            }
+            let slot = if let Some(&slot) = self.homes.get(&reg) {
+                slot
+            } else {
+                let (size, align) = regs.slot_size(func.class_of(reg));
+                func.frame.add(size, align, FrameObjKind::Spill)
+            };
+            slots.insert(reg, slot);
+            self.split_around_loops(func, &loops, reg, slot);
+        }
+        // values built again need not be built in the first place, and those
+        // loaded ahead of a loop are in their slot already
+        for block in &mut func.blocks {
+            block.insts.retain(|x| {
+                !x.defs().iter().any(|x| remat.contains_key(x) || slots.contains_key(x) && self.homes.contains_key(x))
+            });
+        }
+
+        for block in 0..func.blocks.len() {
+            let insts = std::mem::take(&mut func.blocks[block].insts);
+            let mut new_insts = Vec::with_capacity(insts.len());
+            for mut inst in insts {
+                let (uses, defs) = (inst.uses(), inst.defs());
+                let mut temps: HashMap<Reg, Reg> = HashMap::new();
+                for reg in uses.iter().chain(&defs) {
+                    if temps.contains_key(reg) || !slots.contains_key(reg) && !remat.contains_key(reg) {
+                        continue;
+                    }
+                    let class = func.class_of(*reg);
+                    let temp = func.new_vreg_in(class);
+                    self.unspillable.insert(temp);
+                    temps.insert(*reg, temp);
+                    if let Some(def) = remat.get(reg) {
+                        let mut def = def.clone();
+                        def.map_regs(&mut |_| temp);
+                        new_insts.push(def);
+                    } else if uses.contains(reg) {
+                        new_insts.push(I::load_slot(temp, slots[reg], class));
+                    }
+                }
+                inst.map_regs(&mut |x| temps.get(&x).copied().unwrap_or(x));
+                new_insts.push(inst);
+                let mut stored = HashSet::new();
+                for reg in defs.iter().filter(|x| temps.contains_key(x)) {
+                    if stored.insert(*reg) {
+                        new_insts.push(I::store_slot(temps[reg], slots[reg], func.class_of(*reg)));
+                    }
+                }
+            }
+            func.blocks[block].insts = new_insts;
+        }
+    }
+
+    /// Gives `reg`, spilled to `slot`, a register of its own in each
+    /// outermost loop using but not defining it, loaded in the preheader, so
+    /// that a value used in a hot loop is not loaded at each use there if it
+    /// can be helped.
+    fn split_around_loops<I: MachineInst>(&mut self, func: &mut MachineFunc<I>, loops: &MachineLoops, reg: Reg, slot: FrameObjId) {
+        let blocks_where = |f: &dyn Fn(&I) -> bool| -> HashSet<BlockId> {
+            func.block_ids().filter(|x| func.blocks[x.0].insts.iter().any(f)).collect()
+        };
+        let def_blocks = blocks_where(&|x| x.defs().contains(&reg));
+        let use_blocks = blocks_where(&|x| x.uses().contains(&reg));
+
+        let mut picked = vec![];
+        let mut stack: Vec<usize> = loops.top_level().collect();
+        while let Some(idx) = stack.pop() {
+            let lp = &loops.loops[idx];
+            if !use_blocks.iter().any(|&x| lp.contains(x)) {
+                continue;
+            }
+            let defined_inside = def_blocks.iter().any(|&x| lp.contains(x));
+            // only loaded for this loop already, if split before
+            let defined_for_it = lp.preheader.is_some_and(|pre| def_blocks.iter().all(|&x| x == pre));
+            match lp.preheader {
+                Some(preheader) if !defined_inside && !defined_for_it => picked.push((idx, preheader)),
+                _ => stack.extend(&lp.children),
+            }
+        }
+
+        let class = func.class_of(reg);
+        for (idx, preheader) in picked {
+            let split = func.new_vreg_in(class);
+            self.homes.insert(split, slot);
+            for block in &loops.loops[idx].blocks {
+                for inst in &mut func.blocks[block.0].insts {
+                    inst.map_regs(&mut |x| if x == reg { split } else { x });
+                }
+            }
+            let insts = &mut func.blocks[preheader.0].insts;
+            let at = insts.iter().position(|x| !x.targets().is_empty()).unwrap_or(insts.len());
+            insts.insert(at, I::load_slot(split, slot, class));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MoveState {
+    /// Yet to be considered for coalescing.
+    Worklist,
+    /// Not coalesced for now, but may be once the graph is simpler.
+    Active,
+    /// Coalesced, found impossible to coalesce, or given up on.
+    Done,
+}
+
+/// The interference graph of the registers of one class of a function being
+/// colored. The first `k` nodes are the allocatable physical registers, the
+/// others the virtual ones.
+struct Graph {
+    k: usize,
+    allocatable: &'static [u8],
+    /// Whether each virtual register is of the class colored.
+    in_class: Vec<bool>,
+    adj_set: HashSet<(usize, usize)>,
+    /// Neighbours of each virtual register.
+    adj_list: Vec<Vec<usize>>,
+    degree: Vec<usize>,
+    /// How costly spilling each node would be.
+    cost: Vec<f64>,
+    /// `(dst, src)` of each move between nodes.
+    moves: Vec<(usize, usize)>,
+    move_state: Vec<MoveState>,
+    move_list: Vec<Vec<usize>>,
+    worklist_moves: BTreeSet<usize>,
+    simplify_worklist: BTreeSet<usize>,
+    freeze_worklist: BTreeSet<usize>,
+    spill_worklist: BTreeSet<usize>,
+    select_stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    /// The node each node has been merged into, if it has.
+    alias: Vec<Option<usize>>,
+}
+
+impl Graph {
+    fn new<I: MachineInst>(func: &MachineFunc<I>, allocatable: &'static [u8], class: RegClass, unspillable: &HashSet<Reg>) -> Graph {
+        let k = allocatable.len();
+        let count = k + func.vreg_count() as usize;
+        let mut graph = Graph {
+            k,
+            allocatable,
+            in_class: (0..func.vreg_count()).map(|x| func.class_of(Reg::Virt(x)) == class).collect(),
+            adj_set: HashSet::new(),
+            adj_list: vec![vec![]; count],
+            // physical registers can take any number of neighbours
+            degree: (0..count).map(|x| if x < k { usize::MAX } else { 0 }).collect(),
+            cost: vec![0.0; count],
+            moves: vec![],
+            move_state: vec![],
+            move_list: vec![vec![]; count],
+            worklist_moves: BTreeSet::new(),
+            simplify_worklist: BTreeSet::new(),
+            freeze_worklist: BTreeSet::new(),
+            spill_worklist: BTreeSet::new(),
+            select_stack: vec![],
+            on_stack: vec![false; count],
+            alias: vec![None; count],
+        };
+
+        let liveness = Liveness::new(func);
+        let mut present = BTreeSet::new();
+        for (block, live_out) in func.blocks.iter().zip(&liveness.live_out) {
+            let mut live: HashSet<usize> = live_out.iter().filter_map(|&x| graph.node(x)).collect();
+            for inst in block.insts.iter().rev() {
+                let uses: Vec<_> = inst.uses().into_iter().filter_map(|x| graph.node(x)).collect();
+                let defs: Vec<_> = inst.defs().into_iter().chain(inst.clobbers()).filter_map(|x| graph.node(x)).collect();
+                // a value built from constants is not stored when spilled
+                let def_cost = if inst.is_rematerializable() { 0.0 } else { block.freq };
+                for &node in &uses {
+                    graph.cost[node] += block.freq;
+                }
+                for &node in &defs {
+                    graph.cost[node] += def_cost;
+                }
+                present.extend(uses.iter().chain(&defs));
+                // the two sides of a move may share a register, the source
+                // dying as the destination is born
+                if let Some((dst, src)) = inst.as_move() {
+                    if let (Some(dst), Some(src)) = (graph.node(dst), graph.node(src)) {
+                        live.remove(&src);
+                        graph.move_list[dst].push(graph.moves.len());
+                        graph.move_list[src].push(graph.moves.len());
+                        graph.worklist_moves.insert(graph.moves.len());
+                        graph.moves.push((dst, src));
+                        graph.move_state.push(MoveState::Worklist);
+                    }
+                }
+                live.extend(&defs);
+                for &def in &defs {
+                    for &other in &live {
+                        graph.add_edge(def, other);
+                    }
+                }
+                for def in &defs {
+                    live.remove(def);
+                }
+                live.extend(uses);
+            }
+        }
+        for reg in unspillable {
+            if let Some(node) = graph.node(*reg) {
+                graph.cost[node] = f64::INFINITY;
+            }
+        }
+
+        for node in present.into_iter().filter(|&x| x >= k) {
+            if graph.degree[node] >= k {
+                graph.spill_worklist.insert(node);
+            } else if graph.is_move_related(node) {
+                graph.freeze_worklist.insert(node);
+            } else {
+                graph.simplify_worklist.insert(node);
+            }
+        }
+        graph
+    }
+
+    /// The node of `reg`, if it may be allocated.
+    fn node(&self, reg: Reg) -> Option<usize> {
+        match reg {
+            Reg::Phys(x) => self.allocatable.iter().position(|&y| y == x),
+            Reg::Virt(x) => self.in_class[x as usize].then_some(self.k + x as usize),
+        }
+    }
+
+    fn is_precolored(&self, node: usize) -> bool {
+        node < self.k
+    }
+
+    fn add_edge(&mut self, u: usize, v: usize) {
+        if u == v || self.adj_set.contains(&(u, v)) {
+            return;
+        }
+        self.adj_set.insert((u, v));
+        self.adj_set.insert((v, u));
+        for (x, y) in [(u, v), (v, u)] {
+            if !self.is_precolored(x) {
+                self.adj_list[x].push(y);
+                self.degree[x] += 1;
+            }
+        }
+    }
+
+    /// Neighbours of `node` still in the graph.
+    fn adjacent(&self, node: usize) -> Vec<usize> {
+        self.adj_list[node].iter()
+            .copied()
+            .filter(|&x| !self.on_stack[x] && self.alias[x].is_none())
+            .collect()
+    }
+
+    /// Moves of `node` that may yet be coalesced.
+    fn node_moves(&self, node: usize) -> Vec<usize> {
+        self.move_list[node].iter()
+            .copied()
+            .filter(|&x| self.move_state[x] != MoveState::Done)
+            .collect()
+    }
+
+    fn is_move_related(&self, node: usize) -> bool {
+        !self.node_moves(node).is_empty()
+    }
+
+    /// The node `node` has ended up merged into.
+    fn get_alias(&self, mut node: usize) -> usize {
+        while let Some(alias) = self.alias[node] {
+            node = alias;
+        }
+        node
+    }
+
+    /// Takes nodes off the graph until none are left, pushing them to be
+    /// colored in the reverse order.
+    fn reduce(&mut self) {
+        loop {
+            if let Some(node) = self.simplify_worklist.pop_first() {
+                self.simplify(node);
+            } else if let Some(mv) = self.worklist_moves.pop_first() {
+                self.coalesce(mv);
+            } else if let Some(node) = self.freeze_worklist.pop_first() {
+                self.simplify_worklist.insert(node);
+                self.freeze_moves(node);
+            } else if !self.spill_worklist.is_empty() {
+                self.select_spill();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn simplify(&mut self, node: usize) {
+        self.select_stack.push(node);
+        self.on_stack[node] = true;
+        for other in self.adjacent(node) {
+            self.decrement_degree(other);
+        }
+    }
+
+    fn decrement_degree(&mut self, node: usize) {
+        if self.is_precolored(node) {
+            return;
+        }
+        self.degree[node] -= 1;
+        if self.degree[node] + 1 == self.k && self.spill_worklist.remove(&node) {
+            let mut nodes = self.adjacent(node);
+            nodes.push(node);
+            self.enable_moves(&nodes);
+            if self.is_move_related(node) {
+                self.freeze_worklist.insert(node);
+            } else {
+                self.simplify_worklist.insert(node);
+            }
+        }
+    }
+
+    fn enable_moves(&mut self, nodes: &[usize]) {
+        for &node in nodes {
+            for mv in self.node_moves(node) {
+                if self.move_state[mv] == MoveState::Active {
+                    self.move_state[mv] = MoveState::Worklist;
+                    self.worklist_moves.insert(mv);
+                }
+            }
+        }
+    }
+
+    fn coalesce(&mut self, mv: usize) {
+        let (x, y) = self.moves[mv];
+        let (x, y) = (self.get_alias(x), self.get_alias(y));
+        let (u, v) = if self.is_precolored(y) { (y, x) } else { (x, y) };
+        if u == v {
+            self.move_state[mv] = MoveState::Done;
+            self.add_work_list(u);
+        } else if self.is_precolored(v) || self.adj_set.contains(&(u, v)) {
+            self.move_state[mv] = MoveState::Done;
+            self.add_work_list(u);
+            self.add_work_list(v);
+        } else if self.can_combine(u, v) {
+            self.move_state[mv] = MoveState::Done;
+            self.combine(u, v);
+            self.add_work_list(u);
+        } else {
+            self.move_state[mv] = MoveState::Active;
+        }
+    }
+
+    /// Whether merging `v` into `u` keeps the graph as easy to color: by the
+    /// test of George if `u` is a physical register, whose neighbours are not
+    /// known, and by that of Briggs otherwise.
+    fn can_combine(&self, u: usize, v: usize) -> bool {
+        if self.is_precolored(u) {
+            self.adjacent(v).into_iter().all(|t| self.degree[t] < self.k || self.is_precolored(t) || self.adj_set.contains(&(t, u)))
+        } else {
+            let nodes: HashSet<usize> = self.adjacent(u).into_iter().chain(self.adjacent(v)).collect();
+            nodes.into_iter().filter(|&x| self.degree[x] >= self.k).count() < self.k
+        }
+    }
+
+    fn add_work_list(&mut self, node: usize) {
+        if !self.is_precolored(node) && !self.is_move_related(node) && self.degree[node] < self.k && self.freeze_worklist.remove(&node) {
+            self.simplify_worklist.insert(node);
+        }
+    }
+
+    fn combine(&mut self, u: usize, v: usize) {
+        if !self.freeze_worklist.remove(&v) {
+            self.spill_worklist.remove(&v);
+        }
+        self.alias[v] = Some(u);
+        self.cost[u] += self.cost[v];
+        let moves = self.move_list[v].clone();
+        self.move_list[u].extend(moves);
+        self.enable_moves(&[v]);
+        for t in self.adjacent(v) {
+            self.add_edge(t, u);
+            self.decrement_degree(t);
+        }
+        if self.degree[u] >= self.k && self.freeze_worklist.remove(&u) {
+            self.spill_worklist.insert(u);
+        }
+    }
+
+    /// Gives up on coalescing the moves of `node`.
+    fn freeze_moves(&mut self, node: usize) {
+        for mv in self.node_moves(node) {
+            let (x, y) = self.moves[mv];
+            let other = if self.get_alias(y) == self.get_alias(node) { self.get_alias(x) } else { self.get_alias(y) };
+            self.move_state[mv] = MoveState::Done;
+            self.worklist_moves.remove(&mv);
+            if !self.is_precolored(other) && !self.is_move_related(other) && self.degree[other] < self.k && self.freeze_worklist.remove(&other) {
+                self.simplify_worklist.insert(other);
+            }
+        }
+    }
+
+    /// Takes off the graph the node cheapest to spill for each neighbour it
+    /// has, hoping it gets a register all the same.
+    fn select_spill(&mut self) {
+        let node = self.spill_worklist.iter()
+            .copied()
+            .min_by(|&x, &y| {
+                #[allow(clippy::cast_precision_loss)]
+                let weight = |node: usize| self.cost[node] / self.degree[node] as f64;
+                weight(x).total_cmp(&weight(y))
+            })
+            .unwrap();
+        self.spill_worklist.remove(&node);
+        self.simplify_worklist.insert(node);
+        self.freeze_moves(node);
+    }
+
+    /// The physical register of each virtual one, or those that have to be
+    /// spilled if some get none.
+    fn colors(mut self) -> Result<HashMap<Reg, Reg>, Vec<Reg>> {
+        let mut colors: Vec<Option<usize>> = (0..self.adj_list.len()).map(|x| (x < self.k).then_some(x)).collect();
+        let mut spilled = vec![];
+        while let Some(node) = self.select_stack.pop() {
+            let mut free = vec![true; self.k];
+            for &other in &self.adj_list[node] {
+                if let Some(color) = colors[self.get_alias(other)] {
+                    free[color] = false;
+                }
+            }
+            match free.iter().position(|&x| x) {
+                Some(color) => colors[node] = Some(color),
+                None => spilled.push(self.reg(node)),
+            }
+        }
+        if !spilled.is_empty() {
+            return Err(spilled);
+        }
+        Ok((self.k..self.adj_list.len())
+            .filter_map(|x| colors[self.get_alias(x)].map(|color| (self.reg(x), Reg::Phys(self.allocatable[color]))))
+            .collect())
+    }
+
+    fn reg(&self, node: usize) -> Reg {
+        Reg::Virt(u32::try_from(node - self.k).unwrap())
+    }
+}
@@ -0,0 +1,61 @@
+use crate::compiler::mir::{BlockId, MachineBlock, MachineFunc, MachineInst};
+
+/// Orders the blocks of `func` for control to fall through from each into
+/// its hottest successor where it can, the entry staying first. Blocks
+/// left empty, with nothing branching to them, are dropped.
+///
+/// Blocks are linked into chains along the edges, the most often taken
+/// first, each edge linking the end of one chain to the start of another.
+/// An edge is taken as often as its source runs, shared among its targets
+/// by their frequencies. The chains are laid out in the order of the blocks
+/// they start with.
+pub fn lay_out<I: MachineInst>(func: &mut MachineFunc<I>) {
+    let count = func.blocks.len();
+    let freq = |x: BlockId| func.blocks[x.0].freq;
+    let mut edges = vec![];
+    for block in func.block_ids() {
+        let succs = func.succs(block);
+        let total: f64 = succs.iter().map(|&x| freq(x)).sum();
+        for succ in succs {
+            let share = if total > 0.0 { freq(succ) / total } else { 0.0 };
+            edges.push((freq(block) * share, block, succ));
+        }
+    }
+    // stable, for ties to keep the original order
+    edges.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    // each chain is kept at the index of the block it starts with
+    let mut chains: Vec<Vec<usize>> = (0..count).map(|x| vec![x]).collect();
+    let mut chain_of: Vec<usize> = (0..count).collect();
+    for (_, from, to) in edges {
+        let (chain, next) = (chain_of[from.0], chain_of[to.0]);
+        if chain == next || to.0 == 0 || chains[chain].last() != Some(&from.0) || chains[next][0] != to.0 {
+            continue;
+        }
+        let moved = std::mem::take(&mut chains[next]);
+        for &x in &moved {
+            chain_of[x] = chain;
+        }
+        chains[chain].extend(moved);
+    }
+    let order: Vec<usize> = chains.into_iter()
+        .flatten()
+        .filter(|&x| x == 0 || !func.blocks[x].insts.is_empty())
+        .collect();
+
+    let mut index = vec![BlockId(usize::MAX); count];
+    for (new, &old) in order.iter().enumerate() {
+        index[old] = BlockId(new);
+    }
+    let mut blocks: Vec<(BlockId, MachineBlock<I>)> = std::mem::take(&mut func.blocks)
+        .into_iter()
+        .enumerate()
+        .filter(|&(old, _)| index[old].0 != usize::MAX)
+        .map(|(old, block)| (index[old], block))
+        .collect();
+    blocks.sort_by_key(|x| x.0);
+    func.blocks = blocks.into_iter().map(|x| x.1).collect();
+    for inst in func.blocks.iter_mut().flat_map(|x| &mut x.insts) {
+        inst.map_targets(&mut |x| index[x.0]);
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::HashSet;
+
+use crate::compiler::mir::{MachineFunc, MachineInst, Reg};
+
+/// Registers live into and out of each block of a machine function, by
+/// block index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Liveness {
+    pub live_in: Vec<HashSet<Reg>>,
+    pub live_out: Vec<HashSet<Reg>>,
+}
+
+impl Liveness {
+    #[must_use] pub fn new<I: MachineInst>(func: &MachineFunc<I>) -> Liveness {
+        let count = func.blocks.len();
+        // registers read before being written in each block, and written
+        let mut gen = vec![HashSet::new(); count];
+        let mut kill = vec![HashSet::new(); count];
+        for (block, (gen, kill)) in func.blocks.iter().zip(gen.iter_mut().zip(&mut kill)) {
+            for inst in &block.insts {
+                gen.extend(inst.uses().into_iter().filter(|x| !kill.contains(x)));
+                kill.extend(inst.defs().into_iter().chain(inst.clobbers()));
+            }
+        }
+
+        let succs: Vec<_> = func.block_ids().map(|x| func.succs(x)).collect();
+        let mut live_in: Vec<HashSet<Reg>> = gen.clone();
+        let mut live_out: Vec<HashSet<Reg>> = vec![HashSet::new(); count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in (0..count).rev() {
+                let out: HashSet<Reg> = succs[block].iter().flat_map(|x| live_in[x.0].iter().copied()).collect();
+                let new_in: HashSet<Reg> = gen[block].iter()
+                    .copied()
+                    .chain(out.iter().copied().filter(|x| !kill[block].contains(x)))
+                    .collect();
+                if new_in.len() != live_in[block].len() {
+                    live_in[block] = new_in;
+                    changed = true;
+                }
+                live_out[block] = out;
+            }
+        }
+        Liveness { live_in, live_out }
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+
+use crate::compiler::mir::{BlockId, MachineFunc, MachineInst};
+
+/// A natural loop of a machine function: the blocks that can reach a back
+/// edge to `header` without passing through it.
+#[derive(Debug, Clone)]
+pub struct MachineLoop {
+    pub header: BlockId,
+    pub blocks: HashSet<BlockId>,
+    /// The only block entering the loop, if it jumps nowhere else.
+    pub preheader: Option<BlockId>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+impl MachineLoop {
+    #[must_use] pub fn contains(&self, block: BlockId) -> bool {
+        self.blocks.contains(&block)
+    }
+}
+
+/// Every natural loop of a machine function, parents before their children.
+#[derive(Debug, Clone, Default)]
+pub struct MachineLoops {
+    pub loops: Vec<MachineLoop>,
+}
+
+impl MachineLoops {
+    #[must_use] pub fn new<I: MachineInst>(func: &MachineFunc<I>) -> MachineLoops {
+        let order = func.reverse_postorder();
+        let preds = func.preds();
+        let idoms = idoms(&order, &preds, func.blocks.len());
+        let dominates = |a: BlockId, mut b: BlockId| loop {
+            if a == b {
+                return true;
+            }
+            match idoms[b.0] {
+                Some(idom) if idom != b => b = idom,
+                _ => return false,
+            }
+        };
+
+        let mut loops: Vec<MachineLoop> = vec![];
+        for &header in &order {
+            let latches: Vec<_> = preds[header.0].iter()
+                .copied()
+                .filter(|&x| idoms[x.0].is_some() && dominates(header, x))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            let mut blocks = HashSet::from([header]);
+            let mut stack = latches;
+            while let Some(cur) = stack.pop() {
+                if idoms[cur.0].is_some() && blocks.insert(cur) {
+                    stack.extend(&preds[cur.0]);
+                }
+            }
+            let outside: Vec<_> = preds[header.0].iter().filter(|x| !blocks.contains(x)).collect();
+            let preheader = match outside[..] {
+                [&pred] if func.succs(pred) == [header] => Some(pred),
+                _ => None,
+            };
+            loops.push(MachineLoop { header, blocks, preheader, parent: None, children: vec![] });
+        }
+
+        // headers are in reverse postorder, so parents come first
+        for idx in 0..loops.len() {
+            let parent = (0..loops.len())
+                .filter(|&x| x != idx && loops[x].contains(loops[idx].header))
+                .min_by_key(|&x| loops[x].blocks.len());
+            if let Some(parent) = parent {
+                loops[idx].parent = Some(parent);
+                loops[parent].children.push(idx);
+            }
+        }
+        MachineLoops { loops }
+    }
+
+    pub fn top_level(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.loops.len()).filter(|&x| self.loops[x].parent.is_none())
+    }
+}
+
+/// The immediate dominator of each block, by the algorithm of Cooper, Harvey
+/// and Kennedy. The entry is its own, and unreachable blocks have none.
+fn idoms(order: &[BlockId], preds: &[Vec<BlockId>], count: usize) -> Vec<Option<BlockId>> {
+    let mut index = vec![usize::MAX; count];
+    for (i, block) in order.iter().enumerate() {
+        index[block.0] = i;
+    }
+    let mut idoms: Vec<Option<BlockId>> = vec![None; count];
+    idoms[order[0].0] = Some(order[0]);
+    let intersect = |idoms: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+        while a != b {
+            while index[a.0] > index[b.0] {
+                a = idoms[a.0].unwrap();
+            }
+            while index[b.0] > index[a.0] {
+                b = idoms[b.0].unwrap();
+            }
+        }
+        a
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order[1..] {
+            let new_idom = preds[block.0].iter()
+                .copied()
+                .filter(|x| idoms[x.0].is_some())
+                .reduce(|a, b| intersect(&idoms, a, b));
+            if new_idom.is_some() && idoms[block.0] != new_idom {
+                idoms[block.0] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idoms
+}
@@ -0,0 +1,443 @@
+//! Machine IR: the code of a module in the instructions of a target, over
+//! an unbounded supply of virtual registers, with the stack frame of each
+//! function as a set of objects yet to be placed.
+//!
+//! Instruction selection for a target builds it from the SSA IR, and the
+//! passes here work on it whatever the target: register allocation replaces
+//! virtual registers with physical ones, spilling to new frame objects,
+//! before the target lays out the frame and prints the assembly.
+
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::compiler::ir::value::{constant::Constant, global::Global, ty::IrTy, value::Linkage};
+
+pub mod coloring;
+pub mod liveness;
+pub mod layout;
+pub mod loops;
+pub mod regalloc;
+pub mod schedule;
+pub mod slots;
+
+/// A register an instruction reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Reg {
+    /// A register of the target, by its number there.
+    Phys(u8),
+    /// A value yet to be given a physical register or a stack slot.
+    Virt(u32),
+}
+
+impl Reg {
+    #[must_use] pub fn is_virt(self) -> bool {
+        matches!(self, Reg::Virt(_))
+    }
+}
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reg::Phys(x) => write!(f, "r{x}"),
+            Reg::Virt(x) => write!(f, "%v{x}"),
+        }
+    }
+}
+
+/// The kind of register a value lives in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RegClass {
+    /// The integer registers, for words and addresses.
+    #[default]
+    General,
+    /// The registers of the SIMD unit, each holding a vector of words.
+    Vector,
+}
+
+impl RegClass {
+    pub const ALL: [RegClass; 2] = [RegClass::General, RegClass::Vector];
+}
+
+/// A block of a [`MachineFunc`], by its index in [`MachineFunc::blocks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+/// An object of a [`Frame`], by its index in [`Frame::objects`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FrameObjId(pub usize);
+
+/// What a frame object holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameObjKind {
+    /// The memory of an `alloca`.
+    Local,
+    /// A register spilled by the allocator.
+    Spill,
+    /// An argument the caller passed on the stack, this many bytes into
+    /// those, placed by the caller rather than the target's layout.
+    Incoming(u32),
+    /// The copy of the stack guard that stack protection checks on return,
+    /// placed above every other object, between them and the saved
+    /// registers.
+    Canary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameObject {
+    pub size: u32,
+    pub align: u32,
+    pub kind: FrameObjKind,
+    /// Bytes from the frame pointer to the start of the object, once the
+    /// target has laid out the frame.
+    pub offset: Option<i32>,
+    /// Another object whose memory this one shares, the two never being
+    /// live at once.
+    pub alias: Option<FrameObjId>,
+}
+
+/// The stack frame of a function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub objects: Vec<FrameObject>,
+    /// Bytes of arguments the function passes on the stack to the call
+    /// taking the most, which the frame keeps room for at its bottom.
+    pub outgoing: u32,
+}
+
+impl Frame {
+    pub fn add(&mut self, size: u32, align: u32, kind: FrameObjKind) -> FrameObjId {
+        self.objects.push(FrameObject { size, align, kind, offset: None, alias: None });
+        FrameObjId(self.objects.len() - 1)
+    }
+
+    /// The offset of `obj` from the frame pointer.
+    ///
+    /// # Panics
+    ///
+    /// If the frame has not been laid out yet.
+    #[must_use] pub fn offset(&self, obj: FrameObjId) -> i32 {
+        let obj = self.objects[obj.0].alias.unwrap_or(obj);
+        self.objects[obj.0].offset.expect("frame objects are placed before their offsets are asked for")
+    }
+}
+
+/// How an instruction touches a frame object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRef {
+    Load(FrameObjId),
+    /// Writes `size` bytes at `offset` into the object.
+    Store { obj: FrameObjId, offset: i32, size: u32 },
+    /// Takes the address of the object, which may then be accessed anywhere.
+    Address(FrameObjId),
+}
+
+/// Memory an instruction reads or writes, as the scheduler sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+    pub store: bool,
+    /// The base register and the bytes from `offset` to `offset + size`
+    /// off it, if the address is known so.
+    pub at: Option<(Reg, i32, u32)>,
+}
+
+/// An instruction of a target, as the target-independent passes see it.
+///
+/// Registers an instruction reads and writes are told apart by
+/// [`MachineInst::uses`] and [`MachineInst::defs`]. One that only writes a
+/// register under a condition also reads it, as the old value stays when
+/// the condition fails.
+pub trait MachineInst: Clone + Debug {
+    /// Registers written.
+    fn defs(&self) -> Vec<Reg>;
+
+    /// Registers read.
+    fn uses(&self) -> Vec<Reg>;
+
+    /// Replaces each register read or written with `f` of it.
+    fn map_regs(&mut self, f: &mut dyn FnMut(Reg) -> Reg);
+
+    /// Physical registers overwritten on top of [`MachineInst::defs`], as a
+    /// call does to those the callee need not preserve.
+    fn clobbers(&self) -> Vec<Reg> {
+        vec![]
+    }
+
+    /// Whether the instruction only writes its one register, from nothing
+    /// but constants, so that it may be repeated wherever the value is
+    /// needed rather than the value kept.
+    fn is_rematerializable(&self) -> bool {
+        false
+    }
+
+    /// Frame objects the instruction accesses directly, or takes the
+    /// address of.
+    fn frame_refs(&self) -> Vec<FrameRef> {
+        vec![]
+    }
+
+    /// Cycles from the instruction issuing until an instruction reading
+    /// what it writes can issue without stalling.
+    fn latency(&self) -> u32 {
+        1
+    }
+
+    /// Memory the instruction reads or writes, not counting the constants
+    /// of the code.
+    fn memory(&self) -> Option<MemAccess> {
+        None
+    }
+
+    /// Whether the instruction reads or writes the condition flags or the
+    /// like, which instructions doing so must keep the order of.
+    fn touches_flags(&self) -> bool {
+        false
+    }
+
+    /// Whether no instruction may be moved across this one, as a branch, a
+    /// call or the setting up of the frame.
+    fn is_schedule_barrier(&self) -> bool {
+        !self.targets().is_empty() || self.is_terminator() || !self.clobbers().is_empty()
+    }
+
+    /// `(dst, src)` if the instruction copies one register to another.
+    fn as_move(&self) -> Option<(Reg, Reg)>;
+
+    /// Blocks the instruction may branch to.
+    fn targets(&self) -> Vec<BlockId>;
+
+    /// Replaces each block the instruction may branch to with `f` of it.
+    fn map_targets(&mut self, f: &mut dyn FnMut(BlockId) -> BlockId);
+
+    /// Whether control never goes on to the next instruction, as after an
+    /// unconditional branch or a return.
+    fn is_terminator(&self) -> bool;
+
+    /// Loads `dst`, a register of `class`, from the spill slot `slot`.
+    fn load_slot(dst: Reg, slot: FrameObjId, class: RegClass) -> Self;
+
+    /// Stores `src`, a register of `class`, to the spill slot `slot`.
+    fn store_slot(src: Reg, slot: FrameObjId, class: RegClass) -> Self;
+}
+
+/// The registers of a target, as the allocator sees them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterInfo {
+    /// The registers values may be given, in the order to try them.
+    pub allocatable: &'static [u8],
+    /// The registers a function must give back to its caller as it got
+    /// them, saving them in its frame if it uses them.
+    pub callee_saved: &'static [u8],
+    /// The vector registers values may be given, in the order to try them.
+    pub vector_allocatable: &'static [u8],
+    /// Bytes a vector register holds, and a slot spilling it takes.
+    pub vector_bytes: u32,
+}
+
+impl RegisterInfo {
+    /// The registers of `class` values may be given.
+    #[must_use] pub fn allocatable(&self, class: RegClass) -> &'static [u8] {
+        match class {
+            RegClass::General => self.allocatable,
+            RegClass::Vector => self.vector_allocatable,
+        }
+    }
+
+    /// Bytes a slot spilling a register of `class` takes, and its alignment.
+    #[must_use] pub fn slot_size(&self, class: RegClass) -> (u32, u32) {
+        match class {
+            RegClass::General => (4, 4),
+            RegClass::Vector => (self.vector_bytes, 8),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MachineBlock<I> {
+    pub insts: Vec<I>,
+    /// How often the block runs per call of its function, estimated.
+    pub freq: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MachineFunc<I> {
+    pub name: String,
+    pub linkage: Linkage,
+    /// Blocks in layout order, the entry first. Control reaches the next
+    /// block only by branching to it.
+    pub blocks: Vec<MachineBlock<I>>,
+    pub frame: Frame,
+    /// The class of each virtual register made, by number.
+    vreg_classes: Vec<RegClass>,
+}
+
+impl<I: MachineInst> MachineFunc<I> {
+    #[must_use] pub fn new(name: &str, linkage: Linkage) -> MachineFunc<I> {
+        MachineFunc { name: String::from(name), linkage, blocks: vec![], frame: Frame::default(), vreg_classes: vec![] }
+    }
+
+    /// A new virtual register of [`RegClass::General`].
+    pub fn new_vreg(&mut self) -> Reg {
+        self.new_vreg_in(RegClass::General)
+    }
+
+    /// A new virtual register of `class`.
+    ///
+    /// # Panics
+    ///
+    /// If more virtual registers are made than a `u32` numbers.
+    pub fn new_vreg_in(&mut self, class: RegClass) -> Reg {
+        self.vreg_classes.push(class);
+        Reg::Virt(u32::try_from(self.vreg_classes.len() - 1).unwrap())
+    }
+
+    /// The class of `reg`, a virtual register.
+    ///
+    /// # Panics
+    ///
+    /// If `reg` is a physical register, whose class only the target knows.
+    #[must_use] pub fn class_of(&self, reg: Reg) -> RegClass {
+        match reg {
+            Reg::Virt(x) => self.vreg_classes[x as usize],
+            Reg::Phys(_) => panic!("the class of a physical register is the target's to tell"),
+        }
+    }
+
+    /// How many virtual registers have been made, each of which numbered
+    /// below this.
+    ///
+    /// # Panics
+    ///
+    /// If more virtual registers are made than a `u32` numbers.
+    #[must_use] pub fn vreg_count(&self) -> u32 {
+        u32::try_from(self.vreg_classes.len()).unwrap()
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.blocks.len()).map(BlockId)
+    }
+
+    /// Successors of `block`, without duplicates.
+    #[must_use] pub fn succs(&self, block: BlockId) -> Vec<BlockId> {
+        let mut succs = vec![];
+        for target in self.blocks[block.0].insts.iter().flat_map(MachineInst::targets) {
+            if !succs.contains(&target) {
+                succs.push(target);
+            }
+        }
+        succs
+    }
+
+    /// Predecessors of every block, by index.
+    #[must_use] pub fn preds(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![vec![]; self.blocks.len()];
+        for block in self.block_ids() {
+            for succ in self.succs(block) {
+                preds[succ.0].push(block);
+            }
+        }
+        preds
+    }
+
+    /// Blocks reachable from the entry, each before its successors but for
+    /// back edges.
+    #[must_use] pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = vec![];
+        // each block with the successors yet to be visited
+        let mut stack = vec![(BlockId(0), self.succs(BlockId(0)))];
+        visited[0] = true;
+        while let Some((block, succs)) = stack.last_mut() {
+            if let Some(succ) = succs.pop() {
+                if !visited[succ.0] {
+                    visited[succ.0] = true;
+                    let succs = self.succs(succ);
+                    stack.push((succ, succs));
+                }
+            } else {
+                postorder.push(*block);
+                stack.pop();
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    /// Physical registers written anywhere in the function, calls aside.
+    #[must_use] pub fn written_phys_regs(&self) -> HashSet<u8> {
+        self.blocks.iter()
+            .flat_map(|x| &x.insts)
+            .flat_map(MachineInst::defs)
+            .filter_map(|x| match x {
+                Reg::Phys(x) => Some(x),
+                Reg::Virt(_) => None,
+            })
+            .collect()
+    }
+
+    /// Drops copies of a register to itself, as allocation leaves where
+    /// both sides got the same register.
+    pub fn remove_identity_moves(&mut self) {
+        for block in &mut self.blocks {
+            block.insts.retain(|x| x.as_move().is_none_or(|(dst, src)| dst != src));
+        }
+    }
+}
+
+/// Initialized memory of a module, from a global.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataObject {
+    pub name: String,
+    pub linkage: Linkage,
+    pub readonly: bool,
+    /// The leading words of the object, the rest being zero.
+    pub words: Vec<i32>,
+    /// The size of the object in words.
+    pub size: usize,
+}
+
+impl DataObject {
+    /// # Panics
+    ///
+    /// If `global` is not a pointer, as every global is.
+    #[must_use] pub fn new(global: &Global) -> DataObject {
+        let ty = IrTy::deptr_of(&global.ty).unwrap();
+        let mut words = vec![];
+        flatten(&global.init_val, &mut words);
+        let len = words.iter().rposition(|&x| x != 0).map_or(0, |x| x + 1);
+        words.truncate(len);
+        DataObject {
+            name: global.name.clone(),
+            linkage: global.linkage,
+            readonly: global.is_const,
+            words,
+            size: ty.size_in_words(),
+        }
+    }
+
+    /// Whether the object is all zeros, to be placed in `.bss`.
+    #[must_use] pub fn is_zero(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+/// Appends the words of `val` to `words`, undef being zero. Zero
+/// initializers and elements left out of arrays are also written out.
+fn flatten(val: &Constant, words: &mut Vec<i32>) {
+    match val {
+        Constant::Int(x) => words.push(*x),
+        Constant::Array { ty, elems } => {
+            let start = words.len();
+            for elem in elems {
+                flatten(elem, words);
+            }
+            words.resize(start + ty.size_in_words(), 0);
+        }
+        Constant::Undef(ty) | Constant::Poison(ty) => words.extend(std::iter::repeat_n(0, ty.size_in_words())),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MachineModule<I> {
+    pub funcs: Vec<MachineFunc<I>>,
+    pub data: Vec<DataObject>,
+}
@@ -0,0 +1,85 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::mir::{FrameObjId, FrameObjKind, MachineFunc, MachineInst, Reg, RegisterInfo};
+
+/// Gives every virtual register a stack slot of its own and none of the
+/// registers: each instruction reading one is preceded by a load of it into
+/// a free register, and each writing one followed by a store of it.
+///
+/// The code is slow, but the allocation takes no time at all and every
+/// value can be found in memory while debugging. Physical registers the
+/// instruction selector used are left alone, and so are any live across
+/// the instruction, which at most live from an argument being moved to its
+/// register to the call.
+///
+/// # Panics
+///
+/// If an instruction needs more registers than are free around it.
+pub fn spill_everywhere<I: MachineInst>(func: &mut MachineFunc<I>, regs: &RegisterInfo) {
+    let mut slots: HashMap<Reg, FrameObjId> = HashMap::new();
+    for block in 0..func.blocks.len() {
+        let insts = std::mem::take(&mut func.blocks[block].insts);
+        let live_after = phys_live_after(&insts);
+        let mut new_insts = Vec::with_capacity(insts.len());
+        for (mut inst, live_after) in insts.into_iter().zip(live_after) {
+            let (uses, defs) = (inst.uses(), inst.defs());
+            let mut busy: HashSet<Reg> = live_after.iter().copied()
+                .chain(uses.iter().copied())
+                .chain(defs.iter().copied())
+                .chain(inst.clobbers())
+                .filter(|x| !x.is_virt())
+                .collect();
+            let mut free = |class| {
+                let reg = regs.allocatable(class).iter().map(|&x| Reg::Phys(x)).find(|x| !busy.contains(x));
+                reg.inspect(|&x| {
+                    busy.insert(x);
+                })
+            };
+
+            let mut assigned: HashMap<Reg, Reg> = HashMap::new();
+            let mut slot_of = |vreg: Reg, func: &mut MachineFunc<I>| {
+                *slots.entry(vreg).or_insert_with(|| {
+                    let (size, align) = regs.slot_size(func.class_of(vreg));
+                    func.frame.add(size, align, FrameObjKind::Spill)
+                })
+            };
+            for &vreg in uses.iter().chain(&defs).filter(|x| x.is_virt()) {
+                if assigned.contains_key(&vreg) {
+                    continue;
+                }
+                let class = func.class_of(vreg);
+                let reg = free(class).expect("an instruction uses fewer registers than the target has");
+                assigned.insert(vreg, reg);
+                if uses.contains(&vreg) {
+                    new_insts.push(I::load_slot(reg, slot_of(vreg, func), class));
+                }
+            }
+            inst.map_regs(&mut |x| assigned.get(&x).copied().unwrap_or(x));
+            new_insts.push(inst);
+            let mut stored = HashSet::new();
+            for &vreg in defs.iter().filter(|x| x.is_virt()) {
+                if stored.insert(vreg) {
+                    new_insts.push(I::store_slot(assigned[&vreg], slot_of(vreg, func), func.class_of(vreg)));
+                }
+            }
+        }
+        func.blocks[block].insts = new_insts;
+    }
+    func.remove_identity_moves();
+}
+
+/// The physical registers live after each instruction of a block. None are
+/// live out of a block, as instruction selection only uses them to pass
+/// values between neighbouring instructions.
+fn phys_live_after<I: MachineInst>(insts: &[I]) -> Vec<HashSet<Reg>> {
+    let mut live = HashSet::new();
+    let mut live_after = vec![HashSet::new(); insts.len()];
+    for (i, inst) in insts.iter().enumerate().rev() {
+        live_after[i].clone_from(&live);
+        for reg in inst.defs().into_iter().chain(inst.clobbers()) {
+            live.remove(&reg);
+        }
+        live.extend(inst.uses().into_iter().filter(|x| !x.is_virt()));
+    }
+    live_after
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use crate::compiler::mir::{MachineFunc, MachineInst, MemAccess, Reg};
+
+/// Reorders the instructions of each block of `func` for an in-order core
+/// to stall less, issuing one instruction a cycle, once registers are
+/// allocated.
+///
+/// Blocks are split at the instructions nothing may move across, and the
+/// instructions between are scheduled as a list: each cycle, of those whose
+/// operands are ready, the one heading the longest chain of latencies to
+/// the end issues, or none if none is ready. An instruction must stay after
+/// those writing what it reads, and after those reading or writing what it
+/// writes, as physical registers are reused. Loads may pass each other, and
+/// accesses off the same base register not since written pass each other
+/// if they touch different bytes.
+pub fn schedule<I: MachineInst>(func: &mut MachineFunc<I>) {
+    for block in &mut func.blocks {
+        let insts = std::mem::take(&mut block.insts);
+        let mut region = vec![];
+        for inst in insts {
+            if inst.is_schedule_barrier() {
+                block.insts.extend(schedule_region(std::mem::take(&mut region)));
+                block.insts.push(inst);
+            } else {
+                region.push(inst);
+            }
+        }
+        block.insts.extend(schedule_region(region));
+    }
+}
+
+/// `insts` in the order they issue in with the fewest stalls found.
+fn schedule_region<I: MachineInst>(insts: Vec<I>) -> Vec<I> {
+    let count = insts.len();
+    if count < 2 {
+        return insts;
+    }
+
+    // the instructions each must follow, with the cycles it must wait
+    let mut preds: Vec<Vec<(usize, u32)>> = vec![vec![]; count];
+    let mut last_def: HashMap<Reg, usize> = HashMap::new();
+    let mut uses_since: HashMap<Reg, Vec<usize>> = HashMap::new();
+    let mut last_flags = None;
+    // the accesses so far, with how often their base had been written
+    let mut accesses: Vec<(usize, MemAccess, usize)> = vec![];
+    let mut writes: HashMap<Reg, usize> = HashMap::new();
+    for (i, inst) in insts.iter().enumerate() {
+        let defs: Vec<Reg> = inst.defs().into_iter().chain(inst.clobbers()).collect();
+        for reg in inst.uses() {
+            if let Some(&def) = last_def.get(&reg) {
+                preds[i].push((def, insts[def].latency()));
+            }
+        }
+        for reg in &defs {
+            if let Some(&def) = last_def.get(reg) {
+                preds[i].push((def, 1));
+            }
+            for &user in uses_since.get(reg).into_iter().flatten() {
+                preds[i].push((user, 0));
+            }
+        }
+        if inst.touches_flags() {
+            if let Some(prev) = last_flags {
+                preds[i].push((prev, 1));
+            }
+            last_flags = Some(i);
+        }
+        if let Some(access) = inst.memory() {
+            let this = access.at.map_or(0, |(base, ..)| writes.get(&base).copied().unwrap_or(0));
+            for &(prev, other, version) in &accesses {
+                if (access.store || other.store) && !disjoint(&access, this, &other, version) {
+                    preds[i].push((prev, 0));
+                }
+            }
+            accesses.push((i, access, this));
+        }
+
+        for reg in inst.uses() {
+            uses_since.entry(reg).or_default().push(i);
+        }
+        for reg in defs {
+            last_def.insert(reg, i);
+            uses_since.remove(&reg);
+            *writes.entry(reg).or_default() += 1;
+        }
+    }
+
+    // the longest chain of latencies from each instruction to the end
+    let mut succs: Vec<Vec<(usize, u32)>> = vec![vec![]; count];
+    for (i, preds) in preds.iter().enumerate() {
+        for &(pred, latency) in preds {
+            succs[pred].push((i, latency));
+        }
+    }
+    let mut height = vec![0; count];
+    for i in (0..count).rev() {
+        height[i] = succs[i].iter().map(|&(x, latency)| latency + height[x]).fold(insts[i].latency(), u32::max);
+    }
+
+    let mut waiting: Vec<usize> = preds.iter().map(Vec::len).collect();
+    let mut earliest = vec![0; count];
+    let mut order = Vec::with_capacity(count);
+    let mut cycle = 0;
+    while order.len() < count {
+        // ties go to the instruction first in the block, for a stable order
+        let next = (0..count)
+            .filter(|&x| waiting[x] == 0 && earliest[x] <= cycle)
+            .max_by_key(|&x| (height[x], std::cmp::Reverse(x)));
+        if let Some(next) = next {
+            waiting[next] = usize::MAX;
+            order.push(next);
+            for &(succ, latency) in &succs[next] {
+                waiting[succ] -= 1;
+                earliest[succ] = earliest[succ].max(cycle + latency);
+            }
+        }
+        cycle += 1;
+    }
+
+    let mut insts: Vec<Option<I>> = insts.into_iter().map(Some).collect();
+    order.into_iter().map(|x| insts[x].take().unwrap()).collect()
+}
+
+/// Whether two accesses off the same version of the same base register
+/// touch different bytes.
+fn disjoint(a: &MemAccess, a_version: usize, b: &MemAccess, b_version: usize) -> bool {
+    match (a.at, b.at) {
+        (Some((a_base, a_offset, a_size)), Some((b_base, b_offset, b_size))) => {
+            a_base == b_base
+                && a_version == b_version
+                && (i64::from(a_offset) + i64::from(a_size) <= i64::from(b_offset)
+                    || i64::from(b_offset) + i64::from(b_size) <= i64::from(a_offset))
+        }
+        _ => false,
+    }
+}
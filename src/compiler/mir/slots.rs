@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+
+use crate::compiler::mir::{FrameObjId, FrameObjKind, FrameRef, MachineFunc, MachineInst};
+
+/// Lets frame objects that are never live at once share their memory, as
+/// the spill slots of values from different parts of a function mostly are.
+///
+/// An object is live much like a register: from a store writing all of it
+/// to the last load after. Storing part of it only adds to what was there.
+/// Objects whose address is taken may be accessed through pointers anywhere,
+/// and keep memory of their own, as do arguments passed on the stack and
+/// the canary of stack protection.
+pub fn share_slots<I: MachineInst>(func: &mut MachineFunc<I>) {
+    let objects = &func.frame.objects;
+    let refs: Vec<Vec<Vec<FrameRef>>> = func.blocks.iter()
+        .map(|x| x.insts.iter().map(MachineInst::frame_refs).collect())
+        .collect();
+    let pinned: HashSet<usize> = refs.iter()
+        .flatten()
+        .flatten()
+        .filter_map(|x| match x {
+            FrameRef::Address(obj) => Some(obj.0),
+            _ => None,
+        })
+        .collect();
+    // objects read, including by a partial store, and wholly overwritten
+    let access = |refs: &[FrameRef]| {
+        let (mut uses, mut kills) = (vec![], vec![]);
+        for x in refs {
+            match *x {
+                FrameRef::Store { obj, offset: 0, size } if size >= objects[obj.0].size => kills.push(obj.0),
+                FrameRef::Load(obj) | FrameRef::Store { obj, .. } => uses.push(obj.0),
+                FrameRef::Address(_) => {}
+            }
+        }
+        (uses, kills)
+    };
+
+    let count = func.blocks.len();
+    let mut gen = vec![HashSet::new(); count];
+    let mut kill = vec![HashSet::new(); count];
+    for (block, refs) in refs.iter().enumerate() {
+        for refs in refs {
+            let (uses, kills) = access(refs);
+            gen[block].extend(uses.into_iter().filter(|x| !kill[block].contains(x)));
+            kill[block].extend(kills);
+        }
+    }
+    let succs: Vec<_> = func.block_ids().map(|x| func.succs(x)).collect();
+    let mut live_in: Vec<HashSet<usize>> = gen.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in (0..count).rev() {
+            let new_in: HashSet<usize> = succs[block].iter()
+                .flat_map(|x| live_in[x.0].iter().copied())
+                .filter(|x| !kill[block].contains(x))
+                .chain(gen[block].iter().copied())
+                .collect();
+            if new_in.len() != live_in[block].len() {
+                live_in[block] = new_in;
+                changed = true;
+            }
+        }
+    }
+
+    // whatever is written interferes with whatever is live past the write
+    let mut interfere = HashSet::new();
+    for (block, refs) in refs.iter().enumerate() {
+        let mut live: HashSet<usize> = succs[block].iter().flat_map(|x| live_in[x.0].iter().copied()).collect();
+        for refs in refs.iter().rev() {
+            let (uses, kills) = access(refs);
+            let writes = refs.iter().filter_map(|x| match x {
+                FrameRef::Store { obj, .. } => Some(obj.0),
+                _ => None,
+            });
+            for obj in writes {
+                for &other in live.iter().filter(|&&x| x != obj) {
+                    interfere.insert((obj, other));
+                    interfere.insert((other, obj));
+                }
+            }
+            for obj in kills {
+                live.remove(&obj);
+            }
+            live.extend(uses);
+        }
+    }
+
+    // the largest first, each sharing the memory of the first object it does
+    // not interfere with, nor any that object already shares with
+    let mut candidates: Vec<usize> = (0..objects.len())
+        .filter(|&x| !pinned.contains(&x) && !matches!(objects[x].kind, FrameObjKind::Incoming(_) | FrameObjKind::Canary))
+        .collect();
+    candidates.sort_by_key(|&x| std::cmp::Reverse(objects[x].size));
+    let mut classes: Vec<Vec<usize>> = vec![];
+    for obj in candidates {
+        match classes.iter_mut().find(|class| class.iter().all(|&x| !interfere.contains(&(obj, x)))) {
+            Some(class) => {
+                let owner = class[0];
+                let frame = &mut func.frame.objects;
+                frame[owner].align = frame[owner].align.max(frame[obj].align);
+                frame[obj].alias = Some(FrameObjId(owner));
+                class.push(obj);
+            }
+            None => classes.push(vec![obj]),
+        }
+    }
+}
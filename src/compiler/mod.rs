@@ -8,8 +8,15 @@ trivial_numeric_casts,
 unused_allocation
 )]
 
-mod span;
+pub mod span;
+pub mod diagnostic;
 mod intrusive_linkedlist;
 pub mod syntax;
 pub mod ir;
 pub mod ir_builder;
+pub mod interpreter;
+pub mod analysis;
+pub mod pass;
+pub mod mir;
+pub mod backend;
+pub mod target;
@@ -0,0 +1,178 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::interpreter::{Interpreter, Limits};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{CastOp, InstKind},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::ModulePass;
+
+/// Calls deeper than this are not evaluated.
+const MAX_CALL_DEPTH: usize = 1000;
+/// Bytes of stack the calls evaluated may use.
+const MAX_MEMORY: usize = 1 << 24;
+
+/// Replaces calls to pure functions with constant arguments by what they
+/// return, found by running them in the interpreter:
+///
+/// ```text
+/// %r = call i32 @fib(i32 20)
+/// ```
+///
+/// uses 6765 instead of `%r`.
+///
+/// A function is pure if it takes and returns `i32`, only touches memory
+/// in its own frame, and only calls pure functions, so that running it has
+/// no effect but its result. A call that does not return within the fuel
+/// left, or fails, is left to fail at run time. Calls with the same
+/// arguments are only run once.
+#[derive(Debug, Clone, Copy)]
+pub struct ConstEval {
+    /// Instructions the interpreter may run in all, over every call
+    /// evaluated, bounding the time spent on a module.
+    pub fuel: u64,
+}
+
+impl Default for ConstEval {
+    fn default() -> Self {
+        ConstEval { fuel: 1_000_000 }
+    }
+}
+
+impl ModulePass for ConstEval {
+    fn name(&self) -> &'static str {
+        "const-eval"
+    }
+
+    fn run(&mut self, module: &mut Module, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let pure = pure_funcs(module);
+        if pure.is_empty() {
+            return PreservedAnalyses::all();
+        }
+        let calls: Vec<_> = module.func_arena.items_iter(module.first_func, None)
+            .filter(|(_, func)| !func.is_builtin)
+            .flat_map(|(func_id, func)| func.bb_ids()
+                .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None))
+                .filter_map(move |(inst_id, inst)| {
+                    let InstKind::Call(call) = &inst.kind else {
+                        return None;
+                    };
+                    let args: Option<Vec<_>> = call.args.iter()
+                        .map(|x| x.as_const().and_then(Constant::as_int).copied())
+                        .collect();
+                    Some((func_id, inst_id, call.func_id, args?))
+                }))
+            .filter(|(_, _, callee, _)| pure.contains(callee))
+            .collect();
+
+        let mut fuel = self.fuel;
+        let mut results: HashMap<(FuncId, Vec<i32>), Option<i32>> = HashMap::new();
+        let folded: Vec<(FuncId, InstId, i32)> = calls.into_iter()
+            .filter_map(|(func_id, inst_id, callee, args)| {
+                let key = (callee, args);
+                let ret_val = *results.entry(key.clone()).or_insert_with(|| {
+                    let limits = Limits { max_steps: Some(fuel), max_call_depth: Some(MAX_CALL_DEPTH), max_memory: Some(MAX_MEMORY) };
+                    let mut interpreter = Interpreter::new(module, std::io::empty(), std::io::sink()).with_limits(limits);
+                    let ret_val = interpreter.call_func(callee, &key.1);
+                    fuel = fuel.saturating_sub(interpreter.steps());
+                    ret_val.ok().flatten()
+                });
+                ret_val.map(|x| (func_id, inst_id, x))
+            })
+            .collect();
+        if folded.is_empty() {
+            return PreservedAnalyses::all();
+        }
+
+        let mut changed_funcs = HashSet::new();
+        for (func_id, inst_id, val) in folded {
+            let func = &mut module.func_arena[func_id];
+            func.replace_all_uses_with(&Operand::Inst(inst_id), &Operand::Const(Constant::Int(val)));
+            func.remove_inst(inst_id);
+            changed_funcs.insert(func_id);
+        }
+        // the calls are gone, not the blocks
+        let preserved = PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>();
+        for func_id in changed_funcs {
+            analyses.invalidate(func_id, &preserved);
+        }
+        preserved
+    }
+}
+
+/// The functions running which has no effect but returning an `i32`.
+fn pure_funcs(module: &Module) -> HashSet<FuncId> {
+    let mut pure: HashMap<FuncId, Vec<FuncId>> = module.func_arena.items_iter(module.first_func, None)
+        .filter_map(|(func_id, func)| Some((func_id, callees_if_pure(func)?)))
+        .collect();
+    // a function calling one that is not pure is not pure either
+    loop {
+        let impure: Vec<_> = pure.iter()
+            .filter(|(_, callees)| callees.iter().any(|x| !pure.contains_key(x)))
+            .map(|(&func_id, _)| func_id)
+            .collect();
+        if impure.is_empty() {
+            break;
+        }
+        for func_id in impure {
+            pure.remove(&func_id);
+        }
+    }
+    pure.into_keys().collect()
+}
+
+/// The functions `func` calls, if it is pure as long as they are.
+fn callees_if_pure(func: &IrFunc) -> Option<Vec<FuncId>> {
+    let int_params = func.params.iter().all(|&x| func.param_arena[x].ty == IrTy::int());
+    if func.is_builtin || func.first_block.is_none() || func.ret_ty != IrTy::int() || !int_params {
+        return None;
+    }
+    let in_frame = |addr: &Operand| {
+        let mut base = addr.clone();
+        loop {
+            base = match base.as_inst().map(|&x| &func.inst_arena[x].kind) {
+                Some(InstKind::Alloca(_)) => return true,
+                Some(InstKind::GEP(gep)) => gep.ptr.clone(),
+                Some(InstKind::Cast(cast)) if cast.op == CastOp::Bitcast => cast.ori_val.clone(),
+                _ => return false,
+            };
+        }
+    };
+    let mut callees = vec![];
+    for bb in func.bb_ids() {
+        for (_, inst) in func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None) {
+            let pure = match &inst.kind {
+                InstKind::Load(load) => in_frame(&load.addr),
+                InstKind::Store(store) => in_frame(&store.addr),
+                InstKind::MemSet(memset) => in_frame(&memset.dst),
+                InstKind::MemCpy(memcpy) => in_frame(&memcpy.dst) && in_frame(&memcpy.src),
+                InstKind::Call(call) => {
+                    callees.push(call.func_id);
+                    true
+                }
+                kind => !kind.operands().iter().any(|x| matches!(x, Operand::Global(_))),
+            };
+            if !pure {
+                return None;
+            }
+        }
+    }
+    Some(callees)
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Br, InstKind, Select},
+        ty::IrTy,
+        value::{Operand, Value},
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Folds instructions whose operands are constant, and branches on constant
+/// conditions, which become jumps. Blocks left unreachable are kept.
+///
+/// There are no `i1` constants, so comparisons are not replaced by their
+/// value but folded into the branches, selects and casts using them, and
+/// deleted once unused.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConstFold;
+
+impl FuncPass for ConstFold {
+    fn name(&self) -> &'static str {
+        "const-fold"
+    }
+
+    fn run(&mut self, _func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let mut folder = Folder { func, known: HashMap::new(), changed: false, cfg_changed: false };
+        // definitions come before uses in reverse postorder, so one sweep
+        // sees every operand folded that can be
+        for bb in folder.func.reverse_postorder() {
+            let inst_ids: Vec<_> = folder.func.inst_arena.items_iter(folder.func.bb_arena[bb].insts_head, None)
+                .map(|(inst_id, _)| inst_id)
+                .collect();
+            for inst_id in inst_ids {
+                folder.fold(inst_id);
+            }
+        }
+        folder.remove_dead_known();
+
+        match (folder.changed, folder.cfg_changed) {
+            (false, _) => PreservedAnalyses::all(),
+            (true, true) => PreservedAnalyses::none(),
+            (true, false) => PreservedAnalyses::none()
+                .preserve::<Dominators>()
+                .preserve::<PostDominators>()
+                .preserve::<Loops>(),
+        }
+    }
+}
+
+struct Folder<'a> {
+    func: &'a mut IrFunc,
+    /// Values of the instructions folded that could not be replaced by a
+    /// constant of their type.
+    known: HashMap<InstId, i32>,
+    changed: bool,
+    cfg_changed: bool,
+}
+
+impl Folder<'_> {
+    fn fold(&mut self, inst_id: InstId) {
+        let inst = &self.func.inst_arena[inst_id];
+        // constants are 32 bits wide
+        if matches!(inst.ty, IrTy::Int(bits) if bits > 32) {
+            return;
+        }
+        match &inst.kind {
+            InstKind::Binary(binary) => {
+                let (Some(left), Some(right)) = (self.value(&binary.left), self.value(&binary.right)) else {
+                    return;
+                };
+                let folded = match (&left, &right) {
+                    (Constant::Int(left), Constant::Int(right)) => binary.op.fold(*left, *right).map(Constant::Int),
+                    _ => Constant::fold_binary_undef(binary.op, &left, &right),
+                };
+                if let Some(folded) = folded {
+                    self.replace(inst_id, folded);
+                }
+            }
+            InstKind::Cast(cast) => {
+                let (Some(val), Some(from), IrTy::Int(to)) = (self.value(&cast.ori_val), self.int_bits(&cast.ori_val), &cast.target_ty) else {
+                    return;
+                };
+                let folded = match val {
+                    Constant::Int(x) => Constant::Int(cast.op.fold(x, from, *to)),
+                    // whatever the operand, the result fits in the new type
+                    Constant::Undef(_) => Constant::Int(0),
+                    Constant::Poison(_) => Constant::Poison(cast.target_ty.clone()),
+                    Constant::Array { .. } => return,
+                };
+                self.replace(inst_id, folded);
+            }
+            InstKind::GEP(gep) => {
+                let folded = match &gep.ptr {
+                    _ if gep.indices.iter().any(|x| matches!(x, Operand::Const(Constant::Poison(_)))) => Constant::Poison(inst.ty.clone()),
+                    Operand::Const(Constant::Poison(_)) => Constant::Poison(inst.ty.clone()),
+                    Operand::Const(Constant::Undef(_)) => Constant::Undef(inst.ty.clone()),
+                    // a single zero index steps over nothing and keeps the type
+                    ptr if matches!(gep.indices.as_slice(), [Operand::Const(Constant::Int(0))]) => {
+                        let ptr = ptr.clone();
+                        self.replace_with(inst_id, &ptr);
+                        return;
+                    }
+                    _ => return,
+                };
+                self.replace(inst_id, folded);
+            }
+            InstKind::Select(select) => {
+                let cond = match self.value(&select.cond) {
+                    Some(cond @ Constant::Int(_)) => Operand::Const(cond),
+                    _ => select.cond.clone(),
+                };
+                let select = Select { cond, ..select.clone() };
+                if let Some(folded) = select.fold() {
+                    let folded = folded.clone();
+                    self.replace_with(inst_id, &folded);
+                }
+            }
+            InstKind::Br(br) => {
+                let target = match br {
+                    Br::Br { cond, true_bb, false_bb } => match self.value(cond) {
+                        Some(Constant::Int(0)) => *false_bb,
+                        Some(Constant::Int(_)) => *true_bb,
+                        _ => return,
+                    },
+                    Br::Switch { cond, cases, default } => match self.value(cond) {
+                        Some(Constant::Int(x)) => cases.iter().find(|case| case.0 == x).map_or(*default, |case| case.1),
+                        _ => return,
+                    },
+                    Br::Jump { .. } => return,
+                };
+                self.func.set_inst_kind(inst_id, InstKind::Br(Br::Jump { nxt_bb: target }));
+                self.changed = true;
+                self.cfg_changed = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// The constant `operand` is known to be.
+    fn value(&self, operand: &Operand) -> Option<Constant> {
+        match operand {
+            Operand::Const(Constant::Array { .. }) => None,
+            Operand::Const(x) => Some(x.clone()),
+            Operand::Inst(x) => self.known.get(x).copied().map(Constant::Int),
+            _ => None,
+        }
+    }
+
+    fn int_bits(&self, operand: &Operand) -> Option<usize> {
+        let ty = match operand {
+            Operand::Const(x) => x.get_ty(),
+            Operand::Inst(x) => &self.func.inst_arena[*x].ty,
+            _ => return None,
+        };
+        match *ty {
+            IrTy::Int(bits) => Some(bits),
+            _ => None,
+        }
+    }
+
+    /// Replaces `inst_id` by `folded`, or records its value if `folded` is
+    /// an integer of another type than the instruction's.
+    fn replace(&mut self, inst_id: InstId, folded: Constant) {
+        match folded {
+            Constant::Int(x) if self.func.inst_arena[inst_id].ty != IrTy::Int(32) => {
+                self.known.insert(inst_id, x);
+            }
+            folded => self.replace_with(inst_id, &Operand::Const(folded)),
+        }
+    }
+
+    fn replace_with(&mut self, inst_id: InstId, operand: &Operand) {
+        self.func.replace_all_uses_with(&Operand::Inst(inst_id), operand);
+        self.func.remove_inst(inst_id);
+        self.changed = true;
+    }
+
+    /// Deletes the instructions of known value whose users were all folded.
+    fn remove_dead_known(&mut self) {
+        let mut removed = true;
+        while removed {
+            removed = false;
+            let dead: Vec<_> = self.known.keys()
+                .copied()
+                .filter(|&x| !self.func.has_uses(&Operand::Inst(x)))
+                .collect();
+            for inst_id in dead {
+                self.known.remove(&inst_id);
+                self.func.remove_inst(inst_id);
+                self.changed = true;
+                removed = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::compiler::analysis::{
+    loops::{insert_preheader, Loops},
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::FuncId,
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Cast, CastOp, InstKind},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::FuncPass;
+use crate::compiler::target::Target;
+
+/// Materializes the constants loops use that no instruction takes as an
+/// immediate and that take a `movw` and `movt` pair to put in a register
+/// once, ahead of the loop, instead of on every iteration:
+///
+/// ```text
+/// loop:  %x = add i32 %i, 1000001
+/// ```
+///
+/// gets `%c = bitcast i32 1000001 to i32` in the preheader and adds `%c`
+/// instead. The bitcast does nothing but hold the constant in a value of its
+/// own, which constant folding undoes, so the pass is meant to run last.
+///
+/// Each outermost loop gets one definition of a constant for all its uses,
+/// those in nested loops included. Indices of `getelementptr` are left
+/// alone, as they end up in address computations.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConstHoist {
+    /// The machine whose instructions decide which constants are expensive.
+    pub target: Target,
+}
+
+impl FuncPass for ConstHoist {
+    fn name(&self) -> &'static str {
+        "const-hoist"
+    }
+
+    fn run(&mut self, func_id: FuncId, func: &mut IrFunc, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let loop_info = analyses.get::<Loops>(func_id, func);
+        let mut changed = false;
+        for loop_id in loop_info.top_level() {
+            let lp = loop_info.get(loop_id);
+            let users: Vec<_> = func.bb_ids()
+                .filter(|&bb| lp.contains(bb))
+                .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None))
+                .filter(|(_, inst)| match &inst.kind {
+                    // the `i64` products of division by constants stay as they are
+                    InstKind::Binary(binary) => [&binary.left, &binary.right].into_iter().all(|x| is_int(func, x)),
+                    kind => matches!(kind, InstKind::Store(_) | InstKind::Select(_) | InstKind::Call(_)),
+                })
+                .map(|(inst_id, _)| inst_id)
+                .collect();
+            let constants: Vec<_> = users.iter()
+                .flat_map(|&x| func.inst_arena[x].kind.operands())
+                .filter_map(|x| match x {
+                    Operand::Const(Constant::Int(x)) if is_expensive(&self.target, *x) => Some(*x),
+                    _ => None,
+                })
+                .unique()
+                .collect();
+            if constants.is_empty() {
+                continue;
+            }
+
+            let preheader = insert_preheader(func, lp);
+            let at = func.terminator(preheader).unwrap();
+            let hoisted: HashMap<_, _> = constants.into_iter()
+                .map(|x| {
+                    let cast = Cast { op: CastOp::Bitcast, ori_val: Operand::Const(Constant::Int(x)), target_ty: IrTy::int() };
+                    (x, Operand::from(func.build_inst_before_cur(InstKind::Cast(cast), IrTy::int(), at)))
+                })
+                .collect();
+            for user in users {
+                let mut kind = func.inst_arena[user].kind.clone();
+                for operand in kind.operands_mut() {
+                    if let Some(hoisted) = operand.as_const().and_then(Constant::as_int).and_then(|x| hoisted.get(x)) {
+                        *operand = hoisted.clone();
+                    }
+                }
+                func.set_inst_kind(user, kind);
+            }
+            changed = true;
+        }
+        if changed { PreservedAnalyses::none() } else { PreservedAnalyses::all() }
+    }
+}
+
+/// Whether `operand` is an `i32`, or a constant that may stand for one.
+fn is_int(func: &IrFunc, operand: &Operand) -> bool {
+    match operand {
+        Operand::Inst(x) => func.inst_arena[*x].ty == IrTy::int(),
+        Operand::Param(x) => func.param_arena[*x].ty == IrTy::int(),
+        _ => true,
+    }
+}
+
+fn is_expensive(target: &Target, imm: i32) -> bool {
+    !target.is_legal_immediate(imm) && target.materialize_cost(imm) > 1
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    stats::Stats,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::value::{module::Module, value::Operand};
+use crate::compiler::pass::ModulePass;
+
+/// Merges constant globals with the same initial value into the first of
+/// them, shrinking the data section.
+///
+/// The module is taken to be the whole program, so the names of the merged
+/// globals are dropped even if they are external.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConstMerge;
+
+impl ModulePass for ConstMerge {
+    fn name(&self) -> &'static str {
+        "const-merge"
+    }
+
+    fn run(&mut self, module: &mut Module, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        // the printed initial value includes its type, so globals printing
+        // the same hold the same bytes
+        let mut merged = vec![];
+        let mut survivors = HashMap::new();
+        for (global_id, global) in module.global_arena.items_iter(module.first_global, None) {
+            if !global.is_const {
+                continue;
+            }
+            let survivor = *survivors.entry(global.init_val.to_string()).or_insert(global_id);
+            if survivor != global_id {
+                merged.push((global_id, survivor));
+            }
+        }
+        if merged.is_empty() {
+            return PreservedAnalyses::all();
+        }
+
+        for (global_id, survivor) in merged {
+            let (old, new) = (Operand::Global(global_id), Operand::Global(survivor));
+            for func in module.func_arena.values_mut() {
+                func.replace_all_uses_with(&old, &new);
+            }
+            module.debug_info.relocate_global(&old, &new);
+            module.remove_global(global_id);
+        }
+
+        // only operands were replaced
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+            .preserve::<Stats>()
+    }
+}
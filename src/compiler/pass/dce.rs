@@ -0,0 +1,61 @@
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::FuncId,
+    value::{func::IrFunc, inst::{Inst, InstKind}, value::Operand},
+};
+use crate::compiler::pass::FuncPass;
+
+/// Deletes the instructions whose result is unused and that do nothing else,
+/// as arithmetic, casts, address computations and loads, then those only
+/// they used, until none is left.
+///
+/// Allocas are left alone, as the debug info may still say a variable lives
+/// there.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Dce;
+
+impl FuncPass for Dce {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&mut self, _func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let mut dead: Vec<_> = func.bb_ids()
+            .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None))
+            .filter(|(_, inst)| is_pure(inst))
+            .map(|(inst_id, _)| inst_id)
+            .collect();
+        let mut changed = false;
+        while let Some(inst_id) = dead.pop() {
+            // an instruction may be queued again once deleted
+            if !func.inst_arena.get(inst_id).is_some_and(is_pure) || func.has_uses(&Operand::Inst(inst_id)) {
+                continue;
+            }
+            let inst = func.remove_inst(inst_id);
+            dead.extend(inst.kind.operands().into_iter().filter_map(Operand::as_inst));
+            changed = true;
+        }
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        // no block gains or loses a branch
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+    }
+}
+
+/// Whether `inst` only computes its result.
+fn is_pure(inst: &Inst) -> bool {
+    matches!(
+        inst.kind,
+        InstKind::Binary(_) | InstKind::Cast(_) | InstKind::Select(_) | InstKind::GEP(_) | InstKind::Load(_) | InstKind::InsertElement(_)
+    )
+}
@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    stats::Stats,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, GlobalId},
+    value::{inst::InstKind, module::Module, value::{Linkage, Operand}},
+};
+use crate::compiler::pass::ModulePass;
+
+/// Deletes the functions and globals nothing reachable from the roots of the
+/// module refers to, runtime library declarations included.
+///
+/// A module with a `main` is taken to be the whole program, rooted at `main`
+/// alone. Any other module is rooted at everything it exports.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalDce;
+
+impl ModulePass for GlobalDce {
+    fn name(&self) -> &'static str {
+        "global-dce"
+    }
+
+    fn run(&mut self, module: &mut Module, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let (live_funcs, live_globals) = live(module);
+        let dead_funcs: Vec<_> = module.func_arena.keys().filter(|x| !live_funcs.contains(x)).collect();
+        let dead_globals: Vec<_> = module.global_arena.keys().filter(|x| !live_globals.contains(x)).collect();
+        if dead_funcs.is_empty() && dead_globals.is_empty() {
+            return PreservedAnalyses::all();
+        }
+
+        for func_id in dead_funcs {
+            module.remove_func(func_id);
+            module.debug_info.remove_func(func_id);
+        }
+        for global_id in dead_globals {
+            module.remove_global(global_id);
+            module.debug_info.remove_global(&Operand::Global(global_id));
+        }
+
+        // the functions left are untouched
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+            .preserve::<Stats>()
+    }
+}
+
+/// The functions and globals reachable from the roots of `module`.
+fn live(module: &Module) -> (HashSet<FuncId>, HashSet<GlobalId>) {
+    let main = module.func_arena.iter()
+        .find(|(_, func)| func.name == "main" && !func.is_builtin)
+        .map(|(func_id, _)| func_id);
+    let mut live_globals: HashSet<_> = match main {
+        Some(_) => HashSet::new(),
+        None => module.global_arena.items_iter(module.first_global, None)
+            .filter(|(_, global)| global.linkage == Linkage::External)
+            .map(|(global_id, _)| global_id)
+            .collect(),
+    };
+    let mut worklist: Vec<_> = match main {
+        Some(main) => vec![main],
+        None => module.func_arena.items_iter(module.first_func, None)
+            .filter(|(_, func)| func.linkage == Linkage::External && !func.is_builtin)
+            .map(|(func_id, _)| func_id)
+            .collect(),
+    };
+
+    let mut live_funcs = HashSet::new();
+    while let Some(func_id) = worklist.pop() {
+        if !live_funcs.insert(func_id) {
+            continue;
+        }
+        for (_, inst) in module.func_arena[func_id].inst_arena.iter() {
+            if let InstKind::Call(call) = &inst.kind {
+                worklist.push(call.func_id);
+            }
+            let globals = inst.kind.operands().into_iter().filter_map(Operand::as_global);
+            live_globals.extend(globals);
+        }
+    }
+    (live_funcs, live_globals)
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, InstId},
+    value::{
+        func::IrFunc,
+        inst::{BinaryInstOp, CastOp, InstKind},
+        value::Operand,
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Replaces instructions computing what an instruction of a dominating
+/// block already computed by that instruction.
+///
+/// Pure instructions are matched anywhere in the dominator tree. Loads are
+/// only matched within a block, up to the next instruction that may write
+/// memory, as variables are reloaded from their allocas at every use and
+/// matching them is what makes repeated address computations match too.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gvn;
+
+impl FuncPass for Gvn {
+    fn name(&self) -> &'static str {
+        "gvn"
+    }
+
+    fn run(&mut self, func_id: FuncId, func: &mut IrFunc, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let dom_tree = analyses.get::<Dominators>(func_id, func);
+        let mut numbering = Numbering { func, available: HashMap::new(), mem_gen: 0, changed: false };
+
+        // walk the dominator tree, forgetting the expressions of a block
+        // once done with the blocks it dominates
+        let mut stack: Vec<(BBId, usize, Vec<Expr>)> = vec![];
+        for &root in dom_tree.roots() {
+            stack.push((root, 0, numbering.number_bb(root)));
+            while let Some((bb, idx, _)) = stack.last_mut() {
+                if let Some(&child) = dom_tree.children(*bb).get(*idx) {
+                    *idx += 1;
+                    let exprs = numbering.number_bb(child);
+                    stack.push((child, 0, exprs));
+                } else if let Some((_, _, exprs)) = stack.pop() {
+                    for expr in exprs {
+                        numbering.available.remove(&expr);
+                    }
+                }
+            }
+        }
+
+        if !numbering.changed {
+            return PreservedAnalyses::all();
+        }
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+    }
+}
+
+/// What an instruction computes, equal for instructions always giving the
+/// same value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) enum Expr {
+    Binary(BinaryInstOp, Operand, Operand),
+    /// The target type is kept printed, as types compare loosely.
+    Cast(CastOp, Operand, String),
+    Gep(Operand, Vec<Operand>),
+    Select(Operand, Operand, Operand),
+    /// A load in the given state of memory.
+    Load(Operand, u32),
+}
+
+struct Numbering<'a> {
+    func: &'a mut IrFunc,
+    available: HashMap<Expr, InstId>,
+    /// Bumped whenever memory may have changed.
+    mem_gen: u32,
+    changed: bool,
+}
+
+impl Numbering<'_> {
+    /// Replaces the instructions of `bb` already available and makes the
+    /// others available, returning the expressions added.
+    fn number_bb(&mut self, bb: BBId) -> Vec<Expr> {
+        // the memory a block starts with depends on the path taken to it
+        self.mem_gen += 1;
+        let inst_ids: Vec<_> = self.func.inst_arena.items_iter(self.func.bb_arena[bb].insts_head, None)
+            .map(|(inst_id, _)| inst_id)
+            .collect();
+        let mut added = vec![];
+        for inst_id in inst_ids {
+            let kind = &self.func.inst_arena[inst_id].kind;
+            if matches!(kind, InstKind::Store(_) | InstKind::Call(_) | InstKind::MemSet(_) | InstKind::MemCpy(_)) {
+                self.mem_gen += 1;
+                continue;
+            }
+            let Some(expr) = self.expr(kind) else {
+                continue;
+            };
+            if let Some(&prev) = self.lookup(&expr) {
+                self.func.replace_all_uses_with(&Operand::Inst(inst_id), &Operand::Inst(prev));
+                self.func.remove_inst(inst_id);
+                self.changed = true;
+            } else {
+                self.available.insert(expr.clone(), inst_id);
+                added.push(expr);
+            }
+        }
+        added
+    }
+
+    fn expr(&self, kind: &InstKind) -> Option<Expr> {
+        match kind {
+            InstKind::Load(x) => Some(Expr::Load(x.addr.clone(), self.mem_gen)),
+            _ => Expr::of_pure(kind),
+        }
+    }
+
+    /// The instruction computing `expr`, also trying the operands of a
+    /// binary instruction the other way round.
+    fn lookup(&self, expr: &Expr) -> Option<&InstId> {
+        self.available.get(expr).or_else(|| self.available.get(&expr.swapped()?))
+    }
+}
+
+impl Expr {
+    /// What `kind` computes, if it does not touch memory.
+    pub(super) fn of_pure(kind: &InstKind) -> Option<Expr> {
+        Some(match kind {
+            InstKind::Binary(x) => Expr::Binary(x.op, x.left.clone(), x.right.clone()),
+            InstKind::Cast(x) => Expr::Cast(x.op, x.ori_val.clone(), x.target_ty.to_string()),
+            InstKind::GEP(x) => Expr::Gep(x.ptr.clone(), x.indices.clone()),
+            InstKind::Select(x) => Expr::Select(x.cond.clone(), x.true_val.clone(), x.false_val.clone()),
+            _ => return None,
+        })
+    }
+
+    /// The same binary expression with its operands the other way round.
+    pub(super) fn swapped(&self) -> Option<Expr> {
+        let Expr::Binary(op, left, right) = self else {
+            return None;
+        };
+        let op = if op.is_commutative() { Some(*op) } else { op.swapped() };
+        op.map(|op| Expr::Binary(op, right.clone(), left.clone()))
+    }
+}
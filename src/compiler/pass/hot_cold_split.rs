@@ -0,0 +1,65 @@
+use crate::compiler::analysis::{
+    block_freq::BlockFreqs,
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    scev::InductionVars,
+    stats::Stats,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{arena::FuncId, value::func::IrFunc};
+use crate::compiler::pass::FuncPass;
+
+/// Moves the blocks [`BlockFreqs`] estimates to run rarely, such as error
+/// paths and early exits out of loops, after all the others, keeping the
+/// order of each group. The hot blocks are then laid out next to each other
+/// and the cold ones are marked so that a backend can place them out of
+/// line.
+///
+/// The entry block always stays first. Blocks no longer found cold are
+/// unmarked but left where they are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HotColdSplit;
+
+impl FuncPass for HotColdSplit {
+    fn name(&self) -> &'static str {
+        "hot-cold-split"
+    }
+
+    fn run(&mut self, func_id: FuncId, func: &mut IrFunc, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let freqs = analyses.get::<BlockFreqs>(func_id, func);
+        let order: Vec<_> = func.bb_ids().collect();
+        let mut changed = false;
+        for &bb in &order {
+            let is_cold = Some(bb) != func.first_block && freqs.is_cold(bb);
+            changed |= func.bb_arena[bb].is_cold != is_cold;
+            func.bb_arena[bb].is_cold = is_cold;
+        }
+
+        let (mut hot, cold): (Vec<_>, Vec<_>) = order.iter().partition(|&&x| !func.bb_arena[x].is_cold);
+        hot.extend(&cold);
+        if hot != order {
+            for bb in cold {
+                let last = func.bb_ids().last().unwrap();
+                if last != bb {
+                    func.bb_arena.detach(bb);
+                    func.set_bb_after_cur(bb, last);
+                }
+            }
+            changed = true;
+        }
+
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        // only the layout changed, not the graph
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+            .preserve::<InductionVars>()
+            .preserve::<BlockFreqs>()
+            .preserve::<Stats>()
+    }
+}
@@ -0,0 +1,232 @@
+use crate::compiler::analysis::{AnalysisManager, PreservedAnalyses};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, InstId},
+    value::{
+        func::IrFunc,
+        inst::{BinaryInstOp, Br, InstKind, Load, Select, Store},
+        ty::IrTy,
+        value::{Operand, Value},
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Instructions each side of a branch may compute besides its stores to be
+/// converted, as both sides then always run.
+const MAX_SPECULATED: usize = 4;
+
+/// Stores each side of a branch may end with to be converted, each becoming
+/// a select and a store.
+const MAX_STORES: usize = 2;
+
+/// Turns branches whose sides only compute values and store them into
+/// selects, so that both sides run and a single store per address is left:
+///
+/// ```text
+/// bb:    br %c, %t, %f
+/// t:     store %x, %p
+///        br %join
+/// f:     store %y, %p
+///        br %join
+/// ```
+///
+/// becomes `store (select %c, %x, %y), %p` ending in a jump to `join`,
+/// leaving `t` and `f` unreachable. Either side may be missing, the branch
+/// going straight to `join`. The sides may also compute what they store,
+/// if that cannot trap.
+///
+/// Without phis, values meet in memory: an address stored to by only one
+/// side is stored back what it held before on the other, so it must be a
+/// local or global, which can always be read and written.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IfConvert;
+
+impl FuncPass for IfConvert {
+    fn name(&self) -> &'static str {
+        "if-convert"
+    }
+
+    fn run(&mut self, _func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let mut changed = false;
+        for bb in func.bb_ids().collect::<Vec<_>>() {
+            // the sides of an earlier branch are unreachable, not deleted yet
+            if func.get_bb(bb).is_some() {
+                changed |= if_convert(func, bb);
+            }
+        }
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        func.remove_unreachable_bbs();
+        PreservedAnalyses::none()
+    }
+}
+
+/// One side of a branch that can be converted: the instructions it can
+/// compute ahead of the branch, its stores and the block it then jumps to.
+#[derive(Default)]
+struct Side {
+    speculated: Vec<InstId>,
+    stores: Vec<InstId>,
+    join: Option<BBId>,
+}
+
+/// The side of the branch ending `head` going to `bb`, if `bb` only has
+/// `head` as predecessor and holds stores and instructions that can run
+/// ahead of the branch, then a jump.
+fn side(func: &IrFunc, head: BBId, bb: BBId) -> Option<Side> {
+    if func.preds(bb) != [head] {
+        return None;
+    }
+    let inst_ids: Vec<_> = func.inst_arena.items_iter(func.get_bb(bb)?.insts_head, None)
+        .map(|(inst_id, _)| inst_id)
+        .collect();
+    let [body @ .., jump] = &inst_ids[..] else {
+        return None;
+    };
+    let InstKind::Br(Br::Jump { nxt_bb: join }) = func.inst_arena[*jump].kind else {
+        return None;
+    };
+    let can_speculate = |inst_id: &InstId| match &func.inst_arena[*inst_id].kind {
+        InstKind::Binary(binary) => !matches!(binary.op, BinaryInstOp::Div | BinaryInstOp::Mod),
+        InstKind::Cast(_) | InstKind::GEP(_) | InstKind::Select(_) => true,
+        InstKind::Load(load) => is_object(func, &load.addr),
+        _ => false,
+    };
+    let (mut speculated, mut stores) = (vec![], vec![]);
+    for inst_id in body {
+        match &func.inst_arena[*inst_id].kind {
+            InstKind::Store(_) => stores.push(*inst_id),
+            _ if !can_speculate(inst_id) => return None,
+            // a load moved ahead of the stores before it must not read
+            // what they write
+            InstKind::Load(load) if stores.iter().any(|&x| may_write(func, x, &load.addr)) => return None,
+            _ => speculated.push(*inst_id),
+        }
+    }
+    if speculated.len() > MAX_SPECULATED || stores.len() > MAX_STORES {
+        return None;
+    }
+    Some(Side { speculated, stores, join: Some(join) })
+}
+
+/// Whether `addr` is a local or global itself, which can always be read and
+/// written, and is a different object from any other such address.
+fn is_object(func: &IrFunc, addr: &Operand) -> bool {
+    match addr {
+        Operand::Inst(addr) => matches!(func.inst_arena[*addr].kind, InstKind::Alloca(_)),
+        Operand::Global(_) => true,
+        _ => false,
+    }
+}
+
+/// Whether `store` may write to the local or global `addr`.
+fn may_write(func: &IrFunc, store: InstId, addr: &Operand) -> bool {
+    let InstKind::Store(store) = &func.inst_arena[store].kind else {
+        return false;
+    };
+    store.addr == *addr || !is_object(func, &store.addr)
+}
+
+/// The sides of the branch ending `head`, each empty if the branch goes
+/// straight to where the other side joins it.
+fn sides(func: &IrFunc, head: BBId, true_bb: BBId, false_bb: BBId) -> Option<(Side, Side)> {
+    match (side(func, head, true_bb), side(func, head, false_bb)) {
+        (Some(true_side), Some(false_side)) if true_side.join == false_side.join => Some((true_side, false_side)),
+        (Some(true_side), _) if true_side.join == Some(false_bb) => Some((true_side, Side::default())),
+        (_, Some(false_side)) if false_side.join == Some(true_bb) => Some((Side::default(), false_side)),
+        _ => None,
+    }
+}
+
+/// The addresses `side` stores to and the last value stored to each, in the
+/// order they are first stored to.
+fn stored(func: &IrFunc, side: &Side) -> Vec<(Operand, Operand)> {
+    let mut stored: Vec<(Operand, Operand)> = vec![];
+    for &store in &side.stores {
+        let InstKind::Store(Store { addr, data }) = &func.inst_arena[store].kind else {
+            unreachable!()
+        };
+        match stored.iter_mut().find(|(x, _)| x == addr) {
+            Some((_, val)) => *val = data.clone(),
+            None => stored.push((addr.clone(), data.clone())),
+        }
+    }
+    stored
+}
+
+/// Converts the branch ending `head`, see [`IfConvert`].
+fn if_convert(func: &mut IrFunc, head: BBId) -> bool {
+    let Some(terminator) = func.terminator(head) else {
+        return false;
+    };
+    let InstKind::Br(Br::Br { cond, true_bb, false_bb }) = &func.inst_arena[terminator].kind else {
+        return false;
+    };
+    let (cond, true_bb, false_bb) = (cond.clone(), *true_bb, *false_bb);
+    if true_bb == false_bb || true_bb == head || false_bb == head {
+        return false;
+    }
+    let Some((true_side, false_side)) = sides(func, head, true_bb, false_bb) else {
+        return false;
+    };
+    let join = true_side.join.or(false_side.join).unwrap();
+
+    // (address, value stored by the true side, by the false side)
+    let (true_stored, false_stored) = (stored(func, &true_side), stored(func, &false_side));
+    let mut merged: Vec<(Operand, Option<Operand>, Option<Operand>)> = vec![];
+    for (addr, val) in true_stored {
+        merged.push((addr, Some(val), None));
+    }
+    for (addr, val) in false_stored {
+        match merged.iter_mut().find(|(x, _, _)| *x == addr) {
+            Some((_, _, false_val)) => *false_val = Some(val),
+            None => merged.push((addr, None, Some(val))),
+        }
+    }
+    // stores to addresses that may overlap must keep their order, which
+    // may differ between the sides, unless there is only one of them
+    let stored_by_both = matches!(&merged[..], [(_, Some(_), Some(_))]);
+    if !stored_by_both && !merged.iter().all(|(addr, _, _)| is_object(func, addr)) {
+        return false;
+    }
+    let mut tys = vec![];
+    for (_, true_val, false_val) in &merged {
+        let Some(ty) = true_val.as_ref().or(false_val.as_ref()).and_then(|x| value_ty(func, x)) else {
+            return false;
+        };
+        tys.push(ty);
+    }
+
+    for inst_id in true_side.speculated.into_iter().chain(false_side.speculated) {
+        func.move_inst_before(inst_id, terminator);
+    }
+    // what a side leaves alone is read before either side writes
+    for ((addr, true_val, false_val), ty) in merged.iter_mut().zip(&tys) {
+        if true_val.is_none() || false_val.is_none() {
+            let load = func.build_inst_before_cur(InstKind::Load(Load { addr: addr.clone() }), ty.clone(), terminator);
+            true_val.get_or_insert(load.into());
+            false_val.get_or_insert(load.into());
+        }
+    }
+    for ((addr, true_val, false_val), ty) in merged.into_iter().zip(tys) {
+        let select = Select { cond: cond.clone(), true_val: true_val.unwrap(), false_val: false_val.unwrap() };
+        let data = match select.fold().cloned() {
+            Some(val) => val,
+            None => func.build_inst_before_cur(InstKind::Select(select), ty, terminator).into(),
+        };
+        func.build_inst_before_cur(InstKind::Store(Store { addr, data }), IrTy::Void, terminator);
+    }
+    func.set_inst_kind(terminator, InstKind::Br(Br::Jump { nxt_bb: join }));
+    true
+}
+
+/// Type of a value stored, if it can be told without the module.
+fn value_ty(func: &IrFunc, operand: &Operand) -> Option<IrTy> {
+    match operand {
+        Operand::Inst(x) => Some(func.inst_arena[*x].ty.clone()),
+        Operand::Param(x) => Some(func.param_arena[*x].ty.clone()),
+        Operand::Const(x) => Some(x.get_ty().clone()),
+        Operand::Global(_) | Operand::BB(_) => None,
+    }
+}
@@ -0,0 +1,192 @@
+use crate::compiler::analysis::{AnalysisManager, PreservedAnalyses};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, InstId},
+    clone::ValueMap,
+    value::{
+        constant::Constant,
+        func::{InlineHint, IrFunc},
+        inst::{Alloca, Br, Call, InstKind, Load, RetInst, Store},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::ir_builder::call_graph::CallGraph;
+use crate::compiler::pass::ModulePass;
+
+/// Instructions a function may reach through inlining, past which only
+/// calls to functions asking for it are inlined into it.
+const MAX_CALLER_SIZE: usize = 4000;
+
+/// Replaces calls by a copy of the body of the callee, walking the call
+/// graph bottom-up so that callees are copied with their own calls already
+/// inlined.
+///
+/// A call is inlined if the callee asks for it with `always_inline`, or if
+/// it is small, counting the instructions in it less what the call costs
+/// and a bonus for each constant argument, which tends to fold much of the
+/// copy away. Callees asking for `noinline` and calls within a recursive
+/// cycle are left alone.
+///
+/// The copy starts with a jump from where the call was and its returns jump
+/// to the rest of the block, split off into a continuation block. Its
+/// allocas move to the entry block so that they are not allocated again at
+/// each iteration of a loop around the call.
+#[derive(Debug, Clone, Copy)]
+pub struct Inline {
+    /// Largest cost, in instructions, of a call worth inlining.
+    pub threshold: usize,
+}
+
+impl Default for Inline {
+    fn default() -> Self {
+        Inline { threshold: 80 }
+    }
+}
+
+impl ModulePass for Inline {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn run(&mut self, module: &mut Module, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let graph = call_graph(module);
+        let mut changed = false;
+        for scc in graph.sccs() {
+            let recursive = graph.is_recursive(&scc);
+            for &func_id in &scc {
+                let calls: Vec<_> = call_sites(&module.func_arena[func_id]).collect();
+                for (call, callee) in calls {
+                    if recursive && scc.contains(&callee) {
+                        continue;
+                    }
+                    if self.should_inline(module, func_id, call, callee) {
+                        inline_call(module, func_id, call, callee);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if changed {
+            PreservedAnalyses::none()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+impl Inline {
+    /// Whether `call` in `func_id`, calling `callee`, is worth inlining.
+    fn should_inline(self, module: &Module, func_id: FuncId, call: InstId, callee: FuncId) -> bool {
+        let callee_func = &module.func_arena[callee];
+        if callee_func.is_builtin {
+            return false;
+        }
+        match callee_func.inline_hint {
+            InlineHint::Always => return true,
+            InlineHint::Never => return false,
+            InlineHint::Auto => {}
+        }
+        let func = &module.func_arena[func_id];
+        let InstKind::Call(call) = &func.inst_arena[call].kind else {
+            return false;
+        };
+        let size = callee_func.inst_arena.len();
+        if func.inst_arena.len() + size > MAX_CALLER_SIZE {
+            return false;
+        }
+        // the call itself, passing each argument, and the return
+        let saved = 2 + call.args.len();
+        let bonus = 10 * call.args.iter().filter(|x| matches!(x, Operand::Const(_))).count();
+        size.saturating_sub(saved + bonus) <= self.threshold
+    }
+}
+
+/// The call graph of the functions defined in `module`.
+fn call_graph(module: &Module) -> CallGraph<FuncId> {
+    let mut graph = CallGraph::default();
+    for (func_id, func) in module.func_arena.items_iter(module.first_func, None) {
+        if func.is_builtin {
+            continue;
+        }
+        graph.add_func(func_id);
+        for (_, callee) in call_sites(func) {
+            graph.add_call(func_id, callee);
+        }
+    }
+    graph
+}
+
+/// The calls in `func` and the functions they call.
+fn call_sites(func: &IrFunc) -> impl Iterator<Item = (InstId, FuncId)> + '_ {
+    func.bb_ids()
+        .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None))
+        .filter_map(|(inst_id, inst)| match &inst.kind {
+            InstKind::Call(call) => Some((inst_id, call.func_id)),
+            _ => None,
+        })
+}
+
+/// Replaces `call` in `func_id` by a copy of `callee`.
+fn inline_call(module: &mut Module, func_id: FuncId, call: InstId, callee: FuncId) {
+    let callee_func = std::mem::take(&mut module.func_arena[callee]);
+    let func = &mut module.func_arena[func_id];
+    let InstKind::Call(call_inst) = &func.inst_arena[call].kind else {
+        unreachable!()
+    };
+    let mut map = ValueMap::new();
+    for (&param_id, arg) in callee_func.params.iter().zip(&call_inst.args) {
+        map.insert(param_id.into(), arg.clone());
+    }
+
+    let bb = func.inst_arena[call].bb;
+    let cont = func.split_bb(call);
+    let bbs = callee_func.clone_blocks_into(func, &mut map);
+    let entry_head = func.first_block.and_then(|x| func.bb_arena[x].insts_head).unwrap();
+    let copied: Vec<_> = bbs.iter()
+        .flat_map(|&bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None).map(|(inst_id, _)| inst_id))
+        .collect();
+    let mut rets = vec![];
+    for inst_id in copied {
+        match &func.inst_arena[inst_id].kind {
+            InstKind::Alloca(_) => func.move_inst_before(inst_id, entry_head),
+            InstKind::RetInst(RetInst { val }) => rets.push((inst_id, val.clone())),
+            // no longer followed by a return, and maybe passed the caller's stack
+            InstKind::Call(call) if call.is_tail => {
+                let kind = InstKind::Call(Call { is_tail: false, ..call.clone() });
+                func.set_inst_kind(inst_id, kind);
+            }
+            _ => {}
+        }
+    }
+
+    // without phis, a value returned from several places goes through memory
+    let ty = func.inst_arena[call].ty.clone();
+    let ret_val = match &rets[..] {
+        _ if ty == IrTy::Void => None,
+        [] => Some(Operand::Const(Constant::Undef(ty))),
+        [(_, val)] => val.clone(),
+        _ => {
+            let alloca = InstKind::Alloca(Alloca { alloca_ty: ty.clone() });
+            let slot: Operand = func.build_inst_before_cur(alloca, IrTy::ptr_of(&ty), entry_head).into();
+            for (ret, val) in &rets {
+                let store = InstKind::Store(Store { addr: slot.clone(), data: val.clone().unwrap() });
+                func.build_inst_before_cur(store, IrTy::Void, *ret);
+            }
+            Some(func.build_inst_at_start(InstKind::Load(Load { addr: slot }), ty, cont).into())
+        }
+    };
+    for (ret, _) in rets {
+        func.set_inst_kind(ret, InstKind::Br(Br::Jump { nxt_bb: cont }));
+    }
+    if let Some(ret_val) = ret_val {
+        func.replace_all_uses_with(&Operand::Inst(call), &ret_val);
+    }
+    func.remove_inst(call);
+    func.build_inst_at_end(InstKind::Br(Br::Jump { nxt_bb: bbs[0] }), IrTy::Void, bb);
+
+    module.func_arena[callee] = callee_func;
+    module.debug_info.merge_func(callee, func_id, |x| map.get(x).cloned());
+}
@@ -0,0 +1,337 @@
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, InstId},
+    pattern::{
+        m_add, m_any, m_bind, m_bind_int, m_binary, m_cast, m_cmp, m_div, m_int, m_mod, m_mul, m_neg, m_one_use, m_same, m_sub,
+        Capture,
+        Pattern,
+    },
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Binary, BinaryInstOp, Cast, CastOp, InstKind, GEP},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Algebraic simplification of single instructions, repeated until none
+/// applies:
+///
+/// - identities such as `x + 0`, `x * 1`, `x - x` and `0 - (0 - x)`;
+/// - chains of constants, as `(x + 1) + 2` becoming `x + 3`;
+/// - canonical forms: constants on the right, `x - c` as `x + -c`, and
+///   `x <= c` as `x < c + 1`, so that fewer shapes need matching later;
+/// - conditions turned into integers and back, as `zext %c != 0` becoming
+///   `%c`, and tests of comparisons against 0 becoming the comparison or
+///   its opposite;
+/// - address computations from an address computation merged into one from
+///   the object, and offsets by a single 0 dropped, so that each address
+///   is computed one way.
+///
+/// Instructions left unused are deleted along the way.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InstCombine;
+
+impl FuncPass for InstCombine {
+    fn name(&self) -> &'static str {
+        "instcombine"
+    }
+
+    fn run(&mut self, _func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let mut worklist: Vec<_> = func.bb_ids()
+            .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None).map(|(inst_id, _)| inst_id))
+            .collect();
+        worklist.reverse();
+        let mut changed = false;
+        while let Some(inst_id) = worklist.pop() {
+            if !func.inst_arena.contains_key(inst_id) {
+                continue;
+            }
+            let Some(combined) = combine(func, inst_id) else {
+                continue;
+            };
+            let old_operands: Vec<_> = func.inst_arena[inst_id].kind.operands().into_iter().cloned().collect();
+            worklist.extend(func.users(&Operand::Inst(inst_id)));
+            match combined {
+                Combined::Value(val) => {
+                    func.replace_all_uses_with(&Operand::Inst(inst_id), &val);
+                    func.remove_inst(inst_id);
+                }
+                Combined::Inst(kind) => {
+                    func.set_inst_kind(inst_id, kind);
+                    worklist.push(inst_id);
+                }
+            }
+            remove_dead(func, &old_operands);
+            changed = true;
+        }
+
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+    }
+}
+
+/// What an instruction simplifies to.
+enum Combined {
+    /// A value computed elsewhere, which replaces it.
+    Value(Operand),
+    /// A simpler instruction of the same type.
+    Inst(InstKind),
+}
+
+fn combine(func: &IrFunc, inst_id: InstId) -> Option<Combined> {
+    let inst = &func.inst_arena[inst_id];
+    let val = Operand::Inst(inst_id);
+    match &inst.kind {
+        InstKind::Binary(binary) if binary.op.is_cmp() => combine_cmp(func, &val, binary),
+        InstKind::Binary(binary) if inst.ty == IrTy::int() => combine_arith(func, &val, binary),
+        InstKind::Binary(binary) if inst.ty == IrTy::bool() => combine_logic(func, &val),
+        InstKind::Cast(cast) => combine_cast(func, cast, &inst.ty),
+        InstKind::Select(select) => select.fold().cloned().map(Combined::Value),
+        InstKind::GEP(gep) => combine_gep(func, gep),
+        _ => None,
+    }
+}
+
+fn int(val: i32) -> Operand {
+    Operand::Const(Constant::Int(val))
+}
+
+fn rebuild(op: BinaryInstOp, left: Operand, right: Operand) -> Combined {
+    Combined::Inst(InstKind::Binary(Binary { op, left, right }))
+}
+
+/// Puts a constant left operand of a commutative operation or comparison on
+/// the right.
+fn constant_to_right(binary: &Binary) -> Option<Combined> {
+    if !matches!(binary.left, Operand::Const(_)) || matches!(binary.right, Operand::Const(_)) {
+        return None;
+    }
+    let op = if binary.op.is_commutative() { binary.op } else { binary.op.swapped()? };
+    Some(rebuild(op, binary.right.clone(), binary.left.clone()))
+}
+
+fn combine_arith(func: &IrFunc, val: &Operand, binary: &Binary) -> Option<Combined> {
+    if let Some(combined) = constant_to_right(binary) {
+        return Some(combined);
+    }
+    let (x, y) = (Capture::new(), Capture::new());
+    let (c1, c2) = (Capture::new(), Capture::new());
+    let value = |x: &Capture<Operand>| Some(Combined::Value(x.get()));
+
+    // identities
+    if m_add(m_bind(&x), m_int(0)).matches(func, val)
+        || m_sub(m_bind(&x), m_int(0)).matches(func, val)
+        || m_mul(m_bind(&x), m_int(1)).matches(func, val)
+        || m_div(m_bind(&x), m_int(1)).matches(func, val)
+        || m_neg(m_neg(m_bind(&x))).matches(func, val)
+        || m_sub(m_add(m_bind(&x), m_bind(&y)), m_same(&y)).matches(func, val)
+        || m_sub(m_add(m_bind(&y), m_bind(&x)), m_same(&y)).matches(func, val)
+        || m_add(m_sub(m_bind(&x), m_bind(&y)), m_same(&y)).matches(func, val) {
+        return value(&x);
+    }
+    if m_mul(m_any(), m_int(0)).matches(func, val)
+        || m_sub(m_bind(&x), m_same(&x)).matches(func, val)
+        || m_mod(m_any(), m_int(1)).matches(func, val)
+        || m_mod(m_any(), m_int(-1)).matches(func, val) {
+        return Some(Combined::Value(int(0)));
+    }
+    for shift in [BinaryInstOp::Shl, BinaryInstOp::AShr, BinaryInstOp::LShr] {
+        if m_binary(shift, m_bind(&x), m_int(0)).matches(func, val) {
+            return value(&x);
+        }
+    }
+
+    // negations
+    if m_mul(m_bind(&x), m_int(-1)).matches(func, val) || m_div(m_bind(&x), m_int(-1)).matches(func, val) {
+        return Some(rebuild(BinaryInstOp::Sub, int(0), x.get()));
+    }
+    if m_add(m_bind(&x), m_neg(m_bind(&y))).matches(func, val) || m_add(m_neg(m_bind(&y)), m_bind(&x)).matches(func, val) {
+        return Some(rebuild(BinaryInstOp::Sub, x.get(), y.get()));
+    }
+    if m_sub(m_bind(&x), m_neg(m_bind(&y))).matches(func, val) {
+        return Some(rebuild(BinaryInstOp::Add, x.get(), y.get()));
+    }
+
+    // chains of constants, when the inner result is not needed elsewhere
+    if m_add(m_one_use(m_add(m_bind(&x), m_bind_int(&c1))), m_bind_int(&c2)).matches(func, val) {
+        return Some(rebuild(BinaryInstOp::Add, x.get(), int(c1.get().wrapping_add(c2.get()))));
+    }
+    if m_mul(m_one_use(m_mul(m_bind(&x), m_bind_int(&c1))), m_bind_int(&c2)).matches(func, val) {
+        return Some(rebuild(BinaryInstOp::Mul, x.get(), int(c1.get().wrapping_mul(c2.get()))));
+    }
+
+    if m_sub(m_bind(&x), m_bind_int(&c1)).matches(func, val) {
+        return Some(rebuild(BinaryInstOp::Add, x.get(), int(c1.get().wrapping_neg())));
+    }
+    None
+}
+
+/// `i1` conditions combined with `and` and `or`.
+fn combine_logic(func: &IrFunc, val: &Operand) -> Option<Combined> {
+    let x = Capture::new();
+    let same_sides = m_binary(BinaryInstOp::And, m_bind(&x), m_same(&x)).matches(func, val)
+        || m_binary(BinaryInstOp::Or, m_bind(&x), m_same(&x)).matches(func, val);
+    same_sides.then(|| Combined::Value(x.get()))
+}
+
+fn is_bool(func: &IrFunc, operand: &Operand) -> bool {
+    matches!(operand, Operand::Inst(x) if func.inst_arena[*x].ty == IrTy::bool())
+}
+
+fn combine_cmp(func: &IrFunc, val: &Operand, binary: &Binary) -> Option<Combined> {
+    if let Some(combined) = constant_to_right(binary) {
+        return Some(combined);
+    }
+    let (x, y) = (Capture::new(), Capture::new());
+    let pred = Capture::new();
+
+    // conditions turned into integers and tested again
+    if (m_binary(BinaryInstOp::Ne, m_cast(CastOp::ZExt, m_bind(&x)), m_int(0)).matches(func, val)
+        || m_binary(BinaryInstOp::Eq, m_cast(CastOp::ZExt, m_bind(&x)), m_int(1)).matches(func, val))
+        && is_bool(func, &x.get()) {
+        return Some(Combined::Value(x.get()));
+    }
+    if (m_binary(BinaryInstOp::Eq, m_cast(CastOp::ZExt, m_bind(&x)), m_int(0)).matches(func, val)
+        || m_binary(BinaryInstOp::Ne, m_cast(CastOp::ZExt, m_bind(&x)), m_int(1)).matches(func, val))
+        && is_bool(func, &x.get()) {
+        return Some(rebuild(BinaryInstOp::Eq, x.get(), int(0)));
+    }
+    if m_binary(BinaryInstOp::Ne, m_bind(&x), m_int(0)).matches(func, val) && is_bool(func, &x.get()) {
+        return Some(Combined::Value(x.get()));
+    }
+    if m_binary(BinaryInstOp::Eq, m_one_use(m_cmp(&pred, m_bind(&x), m_bind(&y))), m_int(0)).matches(func, val) {
+        return Some(rebuild(pred.get().negated()?, x.get(), y.get()));
+    }
+
+    // equality survives subtracting the same from both sides, even wrapping
+    if matches!(binary.op, BinaryInstOp::Eq | BinaryInstOp::Ne) {
+        if m_binary(binary.op, m_sub(m_bind(&x), m_bind(&y)), m_int(0)).matches(func, val) {
+            return Some(rebuild(binary.op, x.get(), y.get()));
+        }
+        let (c1, c2) = (Capture::new(), Capture::new());
+        if m_binary(binary.op, m_add(m_bind(&x), m_bind_int(&c1)), m_bind_int(&c2)).matches(func, val) {
+            return Some(rebuild(binary.op, x.get(), int(c2.get().wrapping_sub(c1.get()))));
+        }
+    }
+
+    // non-strict comparisons with constants become strict ones
+    if let (Operand::Const(Constant::Int(bound)), false) = (&binary.right, is_bool(func, &binary.left)) {
+        let bound = *bound;
+        match binary.op {
+            BinaryInstOp::Le if bound != i32::MAX => return Some(rebuild(BinaryInstOp::Lt, binary.left.clone(), int(bound + 1))),
+            BinaryInstOp::Ge if bound != i32::MIN => return Some(rebuild(BinaryInstOp::Gt, binary.left.clone(), int(bound - 1))),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn combine_cast(func: &IrFunc, cast: &Cast, ty: &IrTy) -> Option<Combined> {
+    let x = Capture::new();
+    let val = &cast.ori_val;
+    // widening twice widens once
+    if cast.op == CastOp::ZExt && m_cast(CastOp::ZExt, m_bind(&x)).matches(func, val) {
+        let kind = InstKind::Cast(Cast { op: CastOp::ZExt, ori_val: x.get(), target_ty: ty.clone() });
+        return Some(Combined::Inst(kind));
+    }
+    // narrowing back to where it came from
+    let widened = m_cast(CastOp::ZExt, m_bind(&x)).matches(func, val) || m_cast(CastOp::SExt, m_bind(&x)).matches(func, val);
+    if cast.op == CastOp::Trunc && widened && int_ty(func, &x.get()).is_some_and(|x| x == ty) {
+        return Some(Combined::Value(x.get()));
+    }
+    None
+}
+
+fn combine_gep(func: &IrFunc, gep: &GEP) -> Option<Combined> {
+    // `gep T* %p, 0` is `%p`
+    if let [Operand::Const(Constant::Int(0))] = &gep.indices[..] {
+        return Some(Combined::Value(gep.ptr.clone()));
+    }
+    // the last index of the inner address and the first of the outer one
+    // step through the same type, so they add up
+    let InstKind::GEP(inner) = &func.inst_arena[*gep.ptr.as_inst()?].kind else {
+        return None;
+    };
+    let (last, inner_indices) = inner.indices.split_last()?;
+    let (first, indices) = gep.indices.split_first()?;
+    let joined = match (last, first) {
+        (_, Operand::Const(Constant::Int(0))) => last.clone(),
+        (Operand::Const(Constant::Int(0)), _) => first.clone(),
+        (Operand::Const(Constant::Int(x)), Operand::Const(Constant::Int(y))) => int(x.wrapping_add(*y)),
+        _ => return None,
+    };
+    let indices = inner_indices.iter().cloned().chain([joined]).chain(indices.iter().cloned()).collect();
+    Some(Combined::Inst(InstKind::GEP(GEP { ptr: inner.ptr.clone(), indices })))
+}
+
+/// Type of an integer operand that is not a constant.
+fn int_ty<'f>(func: &'f IrFunc, operand: &Operand) -> Option<&'f IrTy> {
+    match operand {
+        Operand::Inst(x) => Some(&func.inst_arena[*x].ty),
+        Operand::Param(x) => Some(&func.param_arena[*x].ty),
+        _ => None,
+    }
+}
+
+/// Deletes the instructions among `operands` left unused, and those only
+/// they used.
+fn remove_dead(func: &mut IrFunc, operands: &[Operand]) {
+    let mut dead: Vec<_> = operands.iter().filter_map(Operand::as_inst).copied().collect();
+    while let Some(inst_id) = dead.pop() {
+        let removable = func.inst_arena.get(inst_id).is_some_and(|x| {
+            matches!(x.kind, InstKind::Binary(_) | InstKind::Cast(_) | InstKind::Select(_) | InstKind::GEP(_) | InstKind::Load(_))
+        });
+        if !removable || func.has_uses(&Operand::Inst(inst_id)) {
+            continue;
+        }
+        let inst = func.remove_inst(inst_id);
+        dead.extend(inst.kind.operands().into_iter().filter_map(Operand::as_inst));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `a op bound` combines to, as an operator and a bound.
+    fn combine_bound(op: BinaryInstOp, bound: i32) -> Option<(BinaryInstOp, i32)> {
+        let mut func = IrFunc::new("f", IrTy::int(), false);
+        let a = Operand::from(func.build_func_param(IrTy::int()));
+        let bb = func.build_bb();
+        let cmp = func.build_inst_at_end(InstKind::Binary(Binary { op, left: a.clone(), right: int(bound) }), IrTy::bool(), bb);
+        match combine(&func, cmp)? {
+            Combined::Inst(InstKind::Binary(binary)) if binary.left == a => {
+                Some((binary.op, *binary.right.as_const()?.as_int()?))
+            }
+            _ => panic!("`a {} {bound}` combines to something other than a comparison of `a`", op.name()),
+        }
+    }
+
+    #[test]
+    fn makes_non_strict_bounds_strict() {
+        assert_eq!(combine_bound(BinaryInstOp::Le, 5), Some((BinaryInstOp::Lt, 6)));
+        assert_eq!(combine_bound(BinaryInstOp::Ge, 5), Some((BinaryInstOp::Gt, 4)));
+        assert_eq!(combine_bound(BinaryInstOp::Le, i32::MAX - 1), Some((BinaryInstOp::Lt, i32::MAX)));
+        assert_eq!(combine_bound(BinaryInstOp::Ge, i32::MIN + 1), Some((BinaryInstOp::Gt, i32::MIN)));
+    }
+
+    #[test]
+    fn keeps_bounds_that_would_overflow() {
+        assert_eq!(combine_bound(BinaryInstOp::Le, i32::MAX), None);
+        assert_eq!(combine_bound(BinaryInstOp::Ge, i32::MIN), None);
+    }
+}
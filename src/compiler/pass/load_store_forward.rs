@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::analysis::{
+    alias::{AliasAnalysis, AliasResult, BasicAliasAnalysis},
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, InstId},
+    value::{func::IrFunc, inst::InstKind, module::Module, value::Operand},
+};
+use crate::compiler::pass::ModulePass;
+
+/// Replaces loads by the value last stored to or loaded from the same
+/// address, when nothing in between may have written to it.
+///
+/// Blocks are visited down the dominator tree, each starting with what its
+/// immediate dominator ended with, less what the blocks on the paths from
+/// the dominator may write. Calls only keep the values of locals whose
+/// address is never passed to a callee, and `memset` and `memcpy` those of
+/// the objects they cannot write.
+///
+/// Needs the module for the alias analysis, hence a module pass.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadStoreForward;
+
+impl ModulePass for LoadStoreForward {
+    fn name(&self) -> &'static str {
+        "load-store-forward"
+    }
+
+    fn run(&mut self, module: &mut Module, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let func_ids: Vec<_> = module.func_arena.items_iter(module.first_func, None)
+            .filter(|(_, func)| !func.is_builtin)
+            .map(|(func_id, _)| func_id)
+            .collect();
+        let mut changed = false;
+        for func_id in func_ids {
+            // the function is taken out so that the analysis can borrow the
+            // module, which it only needs for the types of globals
+            let mut func = std::mem::take(&mut module.func_arena[func_id]);
+            let dom_tree = analyses.get::<Dominators>(func_id, &func);
+            let mut preds: HashMap<_, Vec<_>> = HashMap::new();
+            for (from, to) in func.edges() {
+                preds.entry(to).or_default().push(from);
+            }
+            let mut forwarding = Forwarding {
+                alias: BasicAliasAnalysis::new(module),
+                module,
+                escaped: escaped_allocas(&func),
+                preds,
+                changed: false,
+            };
+            for &root in dom_tree.roots() {
+                let mut stack = vec![(root, forwarding.forward_bb(&mut func, root, vec![]), 0)];
+                while let Some((bb, available, idx)) = stack.last_mut() {
+                    let Some(&child) = dom_tree.children(*bb).get(*idx) else {
+                        stack.pop();
+                        continue;
+                    };
+                    *idx += 1;
+                    let mut available = available.clone();
+                    forwarding.kill_between(&func, *bb, child, &mut available);
+                    let available = forwarding.forward_bb(&mut func, child, available);
+                    stack.push((child, available, 0));
+                }
+            }
+            changed |= forwarding.changed;
+            module.func_arena[func_id] = func;
+        }
+
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+    }
+}
+
+/// Values known to be in memory, as `(address, value)`.
+type Available = Vec<(Operand, Operand)>;
+
+struct Forwarding<'a> {
+    alias: BasicAliasAnalysis<'a>,
+    module: &'a Module,
+    escaped: HashSet<InstId>,
+    preds: HashMap<BBId, Vec<BBId>>,
+    changed: bool,
+}
+
+impl Forwarding<'_> {
+    /// Forwards to the loads of `bb`, starting from `available`, and
+    /// returns what is available at its end.
+    fn forward_bb(&mut self, func: &mut IrFunc, bb: BBId, mut available: Available) -> Available {
+        let inst_ids: Vec<_> = func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None)
+            .map(|(inst_id, _)| inst_id)
+            .collect();
+        for inst_id in inst_ids {
+            if let InstKind::Load(load) = &func.inst_arena[inst_id].kind {
+                let addr = load.addr.clone();
+                if let Some(val) = self.lookup(func, &available, inst_id, &addr) {
+                    func.replace_all_uses_with(&Operand::Inst(inst_id), &val);
+                    func.remove_inst(inst_id);
+                    self.changed = true;
+                } else {
+                    available.push((addr, Operand::Inst(inst_id)));
+                }
+                continue;
+            }
+            self.kill(func, inst_id, &mut available);
+            if let InstKind::Store(store) = &func.inst_arena[inst_id].kind {
+                available.push((store.addr.clone(), store.data.clone()));
+            }
+        }
+        available
+    }
+
+    /// The value of the same type as `load` known to be at `addr`.
+    fn lookup(&self, func: &IrFunc, available: &Available, load: InstId, addr: &Operand) -> Option<Operand> {
+        let ty = &func.inst_arena[load].ty;
+        available.iter()
+            .rev()
+            .find(|(x, _)| self.alias.alias(func, x, addr) == AliasResult::MustAlias)
+            .map(|(_, val)| val)
+            .filter(|val| self.module.operand_ty(func, val) == *ty)
+            .cloned()
+    }
+
+    /// Forgets what `inst_id` may overwrite.
+    fn kill(&self, func: &IrFunc, inst_id: InstId, available: &mut Available) {
+        match &func.inst_arena[inst_id].kind {
+            InstKind::Store(store) => available.retain(|(addr, _)| !self.alias.may_alias(func, addr, &store.addr)),
+            InstKind::Call(_) => available.retain(|(addr, _)| {
+                base(func, addr).as_inst().is_some_and(|x| is_alloca(func, *x) && !self.escaped.contains(x))
+            }),
+            InstKind::MemSet(x) => self.kill_object(func, &x.dst, available),
+            InstKind::MemCpy(x) => self.kill_object(func, &x.dst, available),
+            _ => {}
+        }
+    }
+
+    /// Forgets what is in the object `ptr` points into, its length being
+    /// unknown to the alias analysis.
+    fn kill_object(&self, func: &IrFunc, ptr: &Operand, available: &mut Available) {
+        let object = base(func, ptr);
+        available.retain(|(addr, _)| !self.alias.may_alias(func, &base(func, addr), &object));
+    }
+
+    /// Forgets what the blocks on the paths from the end of `idom` to the
+    /// start of `bb` may write.
+    fn kill_between(&self, func: &IrFunc, idom: BBId, bb: BBId, available: &mut Available) {
+        let mut visited = HashSet::new();
+        let mut worklist = self.preds.get(&bb).cloned().unwrap_or_default();
+        while let Some(pred) = worklist.pop() {
+            if pred == idom || !visited.insert(pred) {
+                continue;
+            }
+            for (inst_id, _) in func.inst_arena.items_iter(func.bb_arena[pred].insts_head, None) {
+                self.kill(func, inst_id, available);
+            }
+            worklist.extend(self.preds.get(&pred).into_iter().flatten());
+        }
+    }
+}
+
+/// The pointer `ptr` is an offset into.
+fn base(func: &IrFunc, ptr: &Operand) -> Operand {
+    let mut base = ptr;
+    while let Some(InstKind::GEP(gep)) = base.as_inst().map(|x| &func.inst_arena[*x].kind) {
+        base = &gep.ptr;
+    }
+    base.clone()
+}
+
+fn is_alloca(func: &IrFunc, inst_id: InstId) -> bool {
+    matches!(func.inst_arena[inst_id].kind, InstKind::Alloca(_))
+}
+
+/// Allocas whose address, or one into them, may reach a callee: used other
+/// than to load, store or offset from.
+fn escaped_allocas(func: &IrFunc) -> HashSet<InstId> {
+    let mut escaped = HashSet::new();
+    for (_, inst) in func.inst_arena.iter() {
+        let operands = match &inst.kind {
+            InstKind::Load(_) | InstKind::GEP(_) => vec![],
+            InstKind::Store(store) => vec![&store.data],
+            kind => kind.operands(),
+        };
+        let allocas = operands.into_iter()
+            .filter_map(|x| base(func, x).as_inst().copied())
+            .filter(|&x| is_alloca(func, x));
+        escaped.extend(allocas);
+    }
+    escaped
+}
@@ -0,0 +1,93 @@
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, GlobalId},
+    value::{
+        inst::{Alloca, InstKind, Store},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::ModulePass;
+
+/// Turns the scalar globals only used by `main` into locals of it, stored
+/// their initial value on entry, so that passes following values through
+/// locals follow them too.
+///
+/// `main` runs once, unless the program calls it, so nothing can tell the
+/// local from the global. Any other function may run several times, and
+/// the value would not survive from one call to the next. The global must
+/// only be loaded from and stored to, as a pointer to it could reach other
+/// functions.
+///
+/// Like [`ConstMerge`](super::const_merge::ConstMerge), this takes the
+/// module to be the whole program.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalizeGlobals;
+
+impl ModulePass for LocalizeGlobals {
+    fn name(&self) -> &'static str {
+        "localize-globals"
+    }
+
+    fn run(&mut self, module: &mut Module, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let Some(main) = main(module) else {
+            return PreservedAnalyses::all();
+        };
+        let globals: Vec<_> = module.global_arena.items_iter(module.first_global, None)
+            .map(|(global_id, _)| global_id)
+            .filter(|&global_id| is_local_to(module, global_id, main))
+            .collect();
+        if globals.is_empty() {
+            return PreservedAnalyses::all();
+        }
+
+        for global_id in globals {
+            let global = module.remove_global(global_id).unwrap();
+            let func = &mut module.func_arena[main];
+            let entry = func.first_block.unwrap();
+            let ty = IrTy::deptr_of(&global.ty).unwrap();
+            let alloca = func.build_inst_at_start(InstKind::Alloca(Alloca { alloca_ty: ty }), global.ty, entry);
+            let store = InstKind::Store(Store { addr: alloca.into(), data: Operand::Const(global.init_val) });
+            func.build_inst_after_cur(store, IrTy::Void, alloca);
+
+            let old = Operand::Global(global_id);
+            func.replace_all_uses_with(&old, &alloca.into());
+            module.debug_info.localize_global(main, &old, &alloca.into());
+        }
+
+        // the entry blocks got new instructions, but no new edges
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+    }
+}
+
+/// The definition of `main`, if nothing in the module calls it.
+fn main(module: &Module) -> Option<FuncId> {
+    let (main, _) = module.func_arena.iter().find(|(_, func)| func.name == "main" && !func.is_builtin)?;
+    let called = module.func_arena.values()
+        .flat_map(|func| func.inst_arena.iter())
+        .any(|(_, inst)| matches!(&inst.kind, InstKind::Call(call) if call.func_id == main));
+    (!called && module.func_arena[main].first_block.is_some()).then_some(main)
+}
+
+/// Whether `global_id` holds a scalar that only `func_id` loads and stores.
+fn is_local_to(module: &Module, global_id: GlobalId, func_id: FuncId) -> bool {
+    let global = Operand::Global(global_id);
+    let is_scalar = IrTy::deptr_of(&module.global_arena[global_id].ty).is_some_and(|x| x == IrTy::int());
+    is_scalar && module.global_users(global_id).into_iter().all(|(user_func, user)| {
+        user_func == func_id && match &module.func_arena[user_func].inst_arena[user].kind {
+            InstKind::Load(_) => true,
+            InstKind::Store(store) => store.data != global,
+            _ => false,
+        }
+    })
+}
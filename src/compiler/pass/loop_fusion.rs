@@ -0,0 +1,298 @@
+use crate::compiler::analysis::{
+    alias::{AliasAnalysis, BasicAliasAnalysis},
+    loops::{Loop, LoopId, LoopInfo, Loops},
+    scev::{InductionVars, ScalarEvolution},
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, InstId},
+    value::{
+        func::IrFunc,
+        inst::{Br, InstKind},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::ModulePass;
+
+/// Fuses a loop into the one right before it when both run the same known
+/// number of times, so that their bodies share one header and one exit
+/// test per iteration, and each element is worked on by both while it is
+/// still in cache.
+///
+/// Nothing may run between the loops but setting up the induction variable
+/// of the second, which moves ahead of the first. The fused loop runs the body of the second after that of
+/// the first, going back to the header of the first, whose test stands for
+/// both.
+///
+/// The second loop must not touch what the first writes, nor write what it
+/// reads, unless both address the same element on the same iteration: the
+/// same array, indexed by values evolving alike in each loop. Loops calling
+/// functions are left alone.
+///
+/// Needs the module for the alias analysis, hence a module pass.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoopFusion;
+
+impl ModulePass for LoopFusion {
+    fn name(&self) -> &'static str {
+        "loop-fusion"
+    }
+
+    fn run(&mut self, module: &mut Module, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let func_ids: Vec<_> = module.func_arena.items_iter(module.first_func, None)
+            .filter(|(_, func)| !func.is_builtin && func.first_block.is_some())
+            .map(|(func_id, _)| func_id)
+            .collect();
+        let mut changed = false;
+        for func_id in func_ids {
+            // the function is taken out so that the analysis can borrow the
+            // module, which it only needs for the types of globals
+            let mut func = std::mem::take(&mut module.func_arena[func_id]);
+            loop {
+                let loop_info = analyses.get::<Loops>(func_id, &func);
+                let scev = analyses.get::<InductionVars>(func_id, &func);
+                let fusion = Fusions { alias: BasicAliasAnalysis::new(module), module, func: &func, scev: &scev }
+                    .find(&loop_info);
+                let Some(fusion) = fusion else {
+                    break;
+                };
+                fuse(&mut func, &fusion);
+                analyses.invalidate(func_id, &PreservedAnalyses::none());
+                changed = true;
+            }
+            module.func_arena[func_id] = func;
+        }
+
+        if changed { PreservedAnalyses::none() } else { PreservedAnalyses::all() }
+    }
+}
+
+/// Two adjacent loops to fuse, and the blocks rewired to do so.
+struct Fusion {
+    preheader: BBId,
+    first_latch: BBId,
+    first_header: BBId,
+    /// Where the first loop exits to, the preheader of the second.
+    between: BBId,
+    second_header: BBId,
+    second_latch: BBId,
+    /// Where the header of the second loop enters its body.
+    second_body: BBId,
+    second_exit: BBId,
+}
+
+/// The branch ending the header of `lp`, as the block it enters the loop
+/// through and the one it exits to.
+fn header_targets(func: &IrFunc, lp: &Loop) -> Option<(BBId, BBId)> {
+    let InstKind::Br(Br::Br { true_bb, false_bb, .. }) = func.inst_arena[func.terminator(lp.header)?].kind else {
+        return None;
+    };
+    match (lp.contains(true_bb), lp.contains(false_bb)) {
+        (true, false) => Some((true_bb, false_bb)),
+        (false, true) => Some((false_bb, true_bb)),
+        _ => None,
+    }
+}
+
+/// The only latch of `lp`, if it jumps back unconditionally.
+fn latch(func: &IrFunc, lp: &Loop) -> Option<BBId> {
+    let [latch] = lp.latches[..] else {
+        return None;
+    };
+    let terminator = func.terminator(latch)?;
+    matches!(func.inst_arena[terminator].kind, InstKind::Br(Br::Jump { .. })).then_some(latch)
+}
+
+fn insts(func: &IrFunc, bb: BBId) -> Vec<InstId> {
+    func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None).map(|(inst_id, _)| inst_id).collect()
+}
+
+fn loop_insts(func: &IrFunc, lp: &Loop) -> Vec<InstId> {
+    func.bb_ids().filter(|&bb| lp.contains(bb)).flat_map(|bb| insts(func, bb)).collect()
+}
+
+struct Fusions<'a> {
+    alias: BasicAliasAnalysis<'a>,
+    module: &'a Module,
+    func: &'a IrFunc,
+    scev: &'a ScalarEvolution,
+}
+
+impl Fusions<'_> {
+    /// The first pair of loops that can be fused.
+    fn find(&self, loop_info: &LoopInfo) -> Option<Fusion> {
+        let loops: Vec<_> = loop_info.loops().collect();
+        loops.iter().find_map(|&(first_id, first)| {
+            loops.iter().find_map(|&(second_id, second)| {
+                let fusion = self.adjacent(first, second)?;
+                (first.parent == second.parent && self.independent(first_id, first, second_id, second))
+                    .then_some(fusion)
+            })
+        })
+    }
+
+    /// How to fuse `first` and `second` if the one follows the other with
+    /// nothing in between, and they run the same known number of times.
+    fn adjacent(&self, first: &Loop, second: &Loop) -> Option<Fusion> {
+        let func = self.func;
+        if first.exiting_blocks(func) != [first.header] || second.exiting_blocks(func) != [second.header] {
+            return None;
+        }
+        let (_, between) = header_targets(func, first)?;
+        let (second_body, second_exit) = header_targets(func, second)?;
+        let preheader = first.preheader(func)?;
+        if func.preds(between) != [first.header] || second.preheader(func) != Some(between)
+            || func.preds(second_body) != [second.header] {
+            return None;
+        }
+        let (first_latch, second_latch) = (latch(func, first)?, latch(func, second)?);
+
+        // what the first loop computes stays in it, and the header of the
+        // second only computes its exit test and values for its body, as the
+        // induction variable once loads are forwarded
+        let used_in = |inst_id: InstId, blocks: &dyn Fn(BBId) -> bool| {
+            func.users(&Operand::Inst(inst_id)).into_iter().all(|x| blocks(func.inst_arena[x].bb))
+        };
+        let first_escapes = loop_insts(func, first).into_iter().any(|x| !used_in(x, &|bb| first.contains(bb)));
+        let header_insts = insts(func, second.header);
+        let header_ok = header_insts[..header_insts.len() - 1].iter().all(|&x| {
+            matches!(func.inst_arena[x].kind, InstKind::Load(_) | InstKind::Binary(_) | InstKind::Cast(_))
+                && used_in(x, &|bb| second.contains(bb))
+        });
+        // in between, only locals and constants stored to those the first
+        // loop does not touch, as the setup of the second moves ahead of it
+        let between_insts = insts(func, between);
+        let between_ok = between_insts[..between_insts.len() - 1].iter().all(|&x| match &func.inst_arena[x].kind {
+            InstKind::Alloca(_) => true,
+            InstKind::Store(store) => {
+                let local = store.addr.as_inst()
+                    .filter(|&&x| matches!(func.inst_arena[x].kind, InstKind::Alloca(_)))
+                    .is_some_and(|&x| used_in(x, &|bb| !first.contains(bb)));
+                local && matches!(store.data, Operand::Const(_))
+            }
+            _ => false,
+        });
+        if first_escapes || !header_ok || !between_ok {
+            return None;
+        }
+
+        Some(Fusion {
+            preheader,
+            first_latch,
+            first_header: first.header,
+            between,
+            second_header: second.header,
+            second_latch,
+            second_body,
+            second_exit,
+        })
+    }
+
+    /// Whether running an iteration of `second` right after the same
+    /// iteration of `first` gives the same result as running it after all
+    /// of `first`.
+    fn independent(&self, first_id: LoopId, first: &Loop, second_id: LoopId, second: &Loop) -> bool {
+        let (Some(first_trips), Some(second_trips)) = (self.scev.trip_count(first_id), self.scev.trip_count(second_id)) else {
+            return false;
+        };
+        if first_trips != second_trips {
+            return false;
+        }
+        let (Some(first_accesses), Some(second_accesses)) = (self.accesses(first), self.accesses(second)) else {
+            return false;
+        };
+        first_accesses.iter().all(|(a, a_writes)| {
+            second_accesses.iter().all(|(b, b_writes)| {
+                !(*a_writes || *b_writes) || !self.alias.may_alias(self.func, a, b)
+                    || self.same_element(first_id, first, a, second_id, second, b)
+            })
+        })
+    }
+
+    /// The addresses `lp` loads from or stores to, the latter marked true,
+    /// if it touches memory no other way.
+    fn accesses(&self, lp: &Loop) -> Option<Vec<(Operand, bool)>> {
+        let mut accesses = vec![];
+        for inst_id in loop_insts(self.func, lp) {
+            match &self.func.inst_arena[inst_id].kind {
+                InstKind::Load(load) => accesses.push((load.addr.clone(), false)),
+                InstKind::Store(store) => accesses.push((store.addr.clone(), true)),
+                InstKind::Call(_) | InstKind::MemSet(_) | InstKind::MemCpy(_) => return None,
+                _ => {}
+            }
+        }
+        Some(accesses)
+    }
+
+    /// Whether `a` in `first` and `b` in `second` address the same element
+    /// on the same iteration and a different one on each: elements of the
+    /// same array at indices evolving alike, together moving through it.
+    fn same_element(&self, first_id: LoopId, first: &Loop, a: &Operand, second_id: LoopId, second: &Loop, b: &Operand) -> bool {
+        let func = self.func;
+        let gep = |x: &Operand| match &func.inst_arena[*x.as_inst()?].kind {
+            InstKind::GEP(gep) => Some(gep),
+            _ => None,
+        };
+        let (Some(a), Some(b)) = (gep(a), gep(b)) else {
+            return false;
+        };
+        let invariant = |x: &Operand| match x {
+            Operand::Inst(x) => !first.contains(func.inst_arena[*x].bb) && !second.contains(func.inst_arena[*x].bb),
+            _ => true,
+        };
+        if a.ptr != b.ptr || !invariant(&a.ptr) || a.indices.len() != b.indices.len() {
+            return false;
+        }
+
+        // the step of the address through the array, in words
+        let mut ty = IrTy::deptr_of(&self.module.operand_ty(func, &a.ptr)).unwrap_or_default();
+        let mut step = 0_i64;
+        for (pos, (x, y)) in a.indices.iter().zip(&b.indices).enumerate() {
+            if pos > 0 {
+                ty = ty.as_array().map(|x| x.1.as_ref().clone()).unwrap_or_default();
+            }
+            if x == y && invariant(x) {
+                continue;
+            }
+            let (Some(x), Some(y)) = (self.scev.evolution_of(func, first_id, x), self.scev.evolution_of(func, second_id, y)) else {
+                return false;
+            };
+            let scale = i64::try_from(ty.size_in_words()).unwrap_or(i64::MAX);
+            if x != y || x.start.is_none() {
+                return false;
+            }
+            let Some(next) = x.step.checked_mul(scale).and_then(|x| step.checked_add(x)) else {
+                return false;
+            };
+            step = next;
+        }
+        step != 0
+    }
+}
+
+/// Rewires the loops of `fusion` into one.
+fn fuse(func: &mut IrFunc, fusion: &Fusion) {
+    let setup = insts(func, fusion.between);
+    let preheader_end = func.terminator(fusion.preheader).unwrap();
+    for &inst_id in &setup[..setup.len() - 1] {
+        func.move_inst_before(inst_id, preheader_end);
+    }
+    // the header of the second loop goes, what its body uses from it moving
+    // into the body, which it was the only way into
+    let header = insts(func, fusion.second_header);
+    let body_start = insts(func, fusion.second_body)[0];
+    for &inst_id in &header[..header.len() - 1] {
+        let users = func.users(&Operand::Inst(inst_id));
+        if users.iter().any(|&x| func.inst_arena[x].bb != fusion.second_header) {
+            func.move_inst_before(inst_id, body_start);
+        }
+    }
+    func.replace_succ(fusion.first_latch, fusion.first_header, fusion.second_body);
+    func.replace_succ(fusion.second_latch, fusion.second_header, fusion.first_header);
+    func.replace_succ(fusion.first_header, fusion.between, fusion.second_exit);
+    func.remove_unreachable_bbs();
+}
@@ -0,0 +1,387 @@
+use crate::compiler::analysis::{
+    alias::{AliasAnalysis, BasicAliasAnalysis},
+    loops::{Loop, LoopInfo, Loops},
+    scev::{InductionVar, InductionVars, ScalarEvolution},
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use std::collections::HashSet;
+
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{BinaryInstOp, Br, InstKind},
+        module::Module,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::ModulePass;
+
+/// Swaps the loops of a perfect two-level nest when the inner loop walks
+/// down the columns of the arrays it accesses, so that it walks along their
+/// rows instead and consecutive iterations touch consecutive words.
+///
+/// The nest must be as the builder emits it: the outer loop only sets up
+/// the inner one, runs it and steps its own induction variable. The loops
+/// swap their setup, exit test and step, leaving the body as it is, so the
+/// bounds of each loop must not depend on the other.
+///
+/// The iterations then run in another order, so memory the body writes must
+/// only be accessed at one address, indexed by both induction variables,
+/// making each iteration touch its own element. Nests calling functions are
+/// left alone.
+///
+/// Needs the module for the alias analysis, hence a module pass.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoopInterchange;
+
+impl ModulePass for LoopInterchange {
+    fn name(&self) -> &'static str {
+        "loop-interchange"
+    }
+
+    fn run(&mut self, module: &mut Module, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let func_ids: Vec<_> = module.func_arena.items_iter(module.first_func, None)
+            .filter(|(_, func)| !func.is_builtin && func.first_block.is_some())
+            .map(|(func_id, _)| func_id)
+            .collect();
+        let mut changed = false;
+        for func_id in func_ids {
+            // the function is taken out so that the analysis can borrow the
+            // module, which it only needs for the types of globals
+            let mut func = std::mem::take(&mut module.func_arena[func_id]);
+            let loop_info = analyses.get::<Loops>(func_id, &func);
+            let scev = analyses.get::<InductionVars>(func_id, &func);
+            let alias = BasicAliasAnalysis::new(module);
+            let nests: Vec<_> = loop_info.loops()
+                .filter_map(|(_, outer)| perfect_nest(&func, &loop_info, &scev, outer))
+                .filter(|nest| is_legal(&func, alias, nest) && is_profitable(&func, nest))
+                .collect();
+            // nests found on the same loops do not overlap, as only
+            // innermost loops can be the inner one
+            for nest in &nests {
+                interchange(&mut func, nest);
+            }
+            if !nests.is_empty() {
+                analyses.invalidate(func_id, &PreservedAnalyses::none());
+                changed = true;
+            }
+            module.func_arena[func_id] = func;
+        }
+
+        if changed { PreservedAnalyses::none() } else { PreservedAnalyses::all() }
+    }
+}
+
+/// The parts of a loop controlling it.
+struct Control {
+    header: BBId,
+    /// The instructions of the header computing its exit test.
+    test: Vec<InstId>,
+    /// The store of the first value of the induction variable.
+    init: InstId,
+    /// The load, add and store stepping the induction variable, right
+    /// before the jump back to the header, without the load if that of the
+    /// test was forwarded to the add.
+    step: Vec<InstId>,
+    var: Operand,
+    /// Whether the test holds to stay in the loop, rather than to leave.
+    stays_on_true: bool,
+}
+
+/// A perfect nest of two loops.
+struct Nest {
+    outer: Control,
+    inner: Control,
+    /// The blocks of the inner loop.
+    body: Vec<BBId>,
+    /// The blocks of the outer loop.
+    blocks: HashSet<BBId>,
+}
+
+fn insts(func: &IrFunc, bb: BBId) -> Vec<InstId> {
+    func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None).map(|(inst_id, _)| inst_id).collect()
+}
+
+/// The nest of `outer` and the only loop in it, if `outer` does nothing but
+/// run it.
+fn perfect_nest(func: &IrFunc, loop_info: &LoopInfo, scev: &ScalarEvolution, outer: &Loop) -> Option<Nest> {
+    let [inner_id] = outer.children[..] else {
+        return None;
+    };
+    let inner = loop_info.get(inner_id);
+    if !inner.children.is_empty() || outer.blocks.len() != inner.blocks.len() + 3 {
+        return None;
+    }
+    let outer_id = loop_info.loop_of(outer.header)?;
+    let preheader = outer.preheader(func)?;
+    let inner_preheader = inner.preheader(func)?;
+    let (outer_body, _) = header_targets(func, outer)?;
+    let (_, inner_exit) = header_targets(func, inner)?;
+    let [outer_latch] = outer.latches[..] else {
+        return None;
+    };
+    if outer_body != inner_preheader || inner_exit != outer_latch || func.preds(inner_exit) != [inner.header] {
+        return None;
+    }
+
+    let body = func.bb_ids().filter(|&x| inner.contains(x)).collect();
+    let blocks = outer.blocks.clone();
+    let outer = control(func, scev.induction_vars(outer_id), outer, preheader)?;
+    let inner = control(func, scev.induction_vars(inner_id), inner, inner_preheader)?;
+    // the block between the headers only sets up the inner loop, and the
+    // one after the inner loop only steps the outer one
+    let setup_ok = insts(func, inner_preheader).into_iter().all(|x| {
+        x == inner.init || matches!(func.inst_arena[x].kind, InstKind::Alloca(_) | InstKind::Br(_))
+    });
+    if !setup_ok || insts(func, outer_latch).len() != outer.step.len() + 1 || outer.var == inner.var
+        || outer.stays_on_true != inner.stays_on_true {
+        return None;
+    }
+    Some(Nest { outer, inner, body, blocks })
+}
+
+/// The branch ending the header of `lp`, as the block it enters the loop
+/// through and the one it exits to.
+fn header_targets(func: &IrFunc, lp: &Loop) -> Option<(BBId, BBId)> {
+    let InstKind::Br(Br::Br { true_bb, false_bb, .. }) = func.inst_arena[func.terminator(lp.header)?].kind else {
+        return None;
+    };
+    match (lp.contains(true_bb), lp.contains(false_bb)) {
+        (true, false) => Some((true_bb, false_bb)),
+        (false, true) => Some((false_bb, true_bb)),
+        _ => None,
+    }
+}
+
+/// How `lp`, entered from `preheader`, is controlled by one of `ivs`: exits
+/// only from its header, which only computes the test, and steps the
+/// variable last in its only latch.
+fn control(func: &IrFunc, ivs: &[InductionVar], lp: &Loop, preheader: BBId) -> Option<Control> {
+    let [latch] = lp.latches[..] else {
+        return None;
+    };
+    if lp.exiting_blocks(func) != [lp.header] {
+        return None;
+    }
+    let latch_end = func.terminator(latch)?;
+    let update = func.inst_arena[latch_end].prev?;
+    let iv = ivs.iter().find(|x| x.update == update)?;
+    let var = Operand::Inst(iv.var);
+
+    // the load and add feeding the store, used by nothing else
+    let add = *func.inst_arena[update].kind.as_store()?.data.as_inst()?;
+    let InstKind::Binary(binary) = &func.inst_arena[add].kind else {
+        return None;
+    };
+    let load = [&binary.left, &binary.right].into_iter()
+        .filter_map(|x| x.as_inst())
+        .copied()
+        .find(|&x| matches!(&func.inst_arena[x].kind, InstKind::Load(load) if load.addr == var))?;
+    let only_used_by = |inst_id: InstId, user: InstId| func.users(&Operand::Inst(inst_id)) == [user];
+    let load_in_latch = func.inst_arena[load].bb == latch;
+    if !only_used_by(add, update) || func.inst_arena[add].bb != latch || (load_in_latch && !only_used_by(load, add)) {
+        return None;
+    }
+
+    // the test moves to the header of the other loop, where a load of the
+    // variable still reads its value on this iteration, so the loop may use
+    // that, as once loads are forwarded
+    let header_insts = insts(func, lp.header);
+    let (&header_end, test) = header_insts.split_last()?;
+    let test_ok = test.iter().all(|&x| {
+        let inst = &func.inst_arena[x];
+        let loads_var = matches!(&inst.kind, InstKind::Load(load) if load.addr == var);
+        matches!(inst.kind, InstKind::Load(_) | InstKind::Binary(_) | InstKind::Cast(_))
+            && func.users(&Operand::Inst(x)).into_iter().all(|user| {
+                let bb = func.inst_arena[user].bb;
+                bb == lp.header || (loads_var && lp.contains(bb))
+            })
+    });
+    if !load_in_latch && !test.contains(&load) {
+        return None;
+    }
+    let init = insts(func, preheader).into_iter()
+        .rev()
+        .find(|&x| matches!(&func.inst_arena[x].kind, InstKind::Store(store) if store.addr == var))?;
+    let init_ok = matches!(&func.inst_arena[init].kind, InstKind::Store(store) if matches!(store.data, Operand::Const(_)));
+    if !test_ok || !init_ok {
+        return None;
+    }
+    let (stay, _) = header_targets(func, lp)?;
+    let stays_on_true = matches!(func.inst_arena[header_end].kind, InstKind::Br(Br::Br { true_bb, .. }) if true_bb == stay);
+    let step = if load_in_latch { vec![load, add, update] } else { vec![add, update] };
+    Some(Control { header: lp.header, test: test.to_vec(), init, step, var, stays_on_true })
+}
+
+/// An index of an access as a function of the induction variables.
+#[derive(Debug, PartialEq)]
+enum Index {
+    /// A value the nest does not change.
+    Invariant(Operand),
+    /// An induction variable plus a constant.
+    Var(Operand, i64),
+}
+
+/// `index` in the nest, as loaded or computed in it.
+fn index(func: &IrFunc, nest: &Nest, index: &Operand) -> Option<Index> {
+    let inst = match index {
+        Operand::Inst(inst_id) if nest.blocks.contains(&func.inst_arena[*inst_id].bb) => &func.inst_arena[*inst_id],
+        _ => return Some(Index::Invariant(index.clone())),
+    };
+    match &inst.kind {
+        InstKind::Load(load) if load.addr == nest.outer.var || load.addr == nest.inner.var => Some(Index::Var(load.addr.clone(), 0)),
+        InstKind::Binary(binary) => {
+            let (var, offset) = match (binary.op, &binary.left, &binary.right) {
+                (BinaryInstOp::Add, x, Operand::Const(Constant::Int(c)))
+                | (BinaryInstOp::Add, Operand::Const(Constant::Int(c)), x) => (x, i64::from(*c)),
+                (BinaryInstOp::Sub, x, Operand::Const(Constant::Int(c))) => (x, -i64::from(*c)),
+                _ => return None,
+            };
+            let Index::Var(var, base) = self::index(func, nest, var)? else {
+                return None;
+            };
+            Some(Index::Var(var, base.checked_add(offset)?))
+        }
+        _ => None,
+    }
+}
+
+/// An access as what it indexes and how.
+fn access(func: &IrFunc, nest: &Nest, addr: &Operand) -> Option<(Operand, Vec<Index>)> {
+    let gep = addr.as_inst().and_then(|&x| func.inst_arena[x].kind.as_gep());
+    match gep {
+        Some(gep) => {
+            let indices = gep.indices.iter().map(|x| index(func, nest, x)).collect::<Option<_>>()?;
+            match index(func, nest, &gep.ptr)? {
+                Index::Invariant(ptr) => Some((ptr, indices)),
+                Index::Var(..) => None,
+            }
+        }
+        None => match index(func, nest, addr)? {
+            Index::Invariant(addr) => Some((addr, vec![])),
+            Index::Var(..) => None,
+        },
+    }
+}
+
+/// The loads and stores in the body of `nest` other than those controlling
+/// it, the stores marked true, if it touches memory no other way.
+fn body_accesses(func: &IrFunc, nest: &Nest) -> Option<Vec<(Operand, bool)>> {
+    let control: Vec<_> = nest.inner.test.iter().chain(&nest.inner.step).copied().collect();
+    let mut accesses = vec![];
+    for inst_id in nest.body.iter().flat_map(|&bb| insts(func, bb)) {
+        if control.contains(&inst_id) {
+            continue;
+        }
+        match &func.inst_arena[inst_id].kind {
+            InstKind::Load(load) => accesses.push((load.addr.clone(), false)),
+            InstKind::Store(store) => accesses.push((store.addr.clone(), true)),
+            InstKind::Call(_) | InstKind::MemSet(_) | InstKind::MemCpy(_) => return None,
+            _ => {}
+        }
+    }
+    Some(accesses)
+}
+
+/// Whether running the iterations of `nest` in the other order gives the
+/// same result, and each test still gives what it did.
+fn is_legal(func: &IrFunc, alias: BasicAliasAnalysis, nest: &Nest) -> bool {
+    let Some(accesses) = body_accesses(func, nest) else {
+        return false;
+    };
+    let is_var = |x: &Index, var: &Operand| matches!(x, Index::Var(v, _) if v == var);
+    // each iteration touches its own element
+    let own_element = |a: &Operand, b: &Operand| {
+        let (Some(a), Some(b)) = (access(func, nest, a), access(func, nest, b)) else {
+            return false;
+        };
+        a == b && a.1.iter().any(|x| is_var(x, &nest.outer.var)) && a.1.iter().any(|x| is_var(x, &nest.inner.var))
+    };
+    let body_ok = accesses.iter().all(|(a, a_writes)| {
+        accesses.iter().all(|(b, b_writes)| !(*a_writes || *b_writes) || !alias.may_alias(func, a, b) || own_element(a, b))
+    });
+
+    // the tests only read their own variable and what the nest leaves alone
+    let written: Vec<_> = accesses.iter()
+        .filter(|(_, writes)| *writes)
+        .map(|(addr, _)| addr)
+        .chain([&nest.outer.var, &nest.inner.var])
+        .collect();
+    let tests_ok = [&nest.outer, &nest.inner].into_iter().all(|control| {
+        control.test.iter().all(|&x| match &func.inst_arena[x].kind {
+            InstKind::Load(load) => load.addr == control.var || written.iter().all(|addr| !alias.may_alias(func, addr, &load.addr)),
+            _ => true,
+        })
+    });
+    body_ok && tests_ok
+}
+
+/// Whether more accesses in the body index their last dimension by the
+/// outer variable than by the inner one.
+fn is_profitable(func: &IrFunc, nest: &Nest) -> bool {
+    let Some(accesses) = body_accesses(func, nest) else {
+        return false;
+    };
+    let mut score = 0_i32;
+    for (addr, _) in accesses {
+        if let Some((_, indices)) = access(func, nest, &addr) {
+            match indices.last() {
+                Some(Index::Var(var, _)) if *var == nest.outer.var => score += 1,
+                Some(Index::Var(var, _)) if *var == nest.inner.var => score -= 1,
+                _ => {}
+            }
+        }
+    }
+    score > 0
+}
+
+/// Swaps the setup, exit test and step of the loops of `nest`.
+fn interchange(func: &mut IrFunc, nest: &Nest) {
+    let (outer, inner) = (&nest.outer, &nest.inner);
+    let end = |func: &IrFunc, bb: BBId| func.terminator(bb).unwrap();
+
+    // the inner variable is now set once ahead of the nest, so its alloca
+    // must be there already
+    let outer_preheader = func.inst_arena[outer.init].bb;
+    let inner_preheader = func.inst_arena[inner.init].bb;
+    for inst_id in insts(func, inner_preheader) {
+        if matches!(func.inst_arena[inst_id].kind, InstKind::Alloca(_)) {
+            func.move_inst_before(inst_id, end(func, outer_preheader));
+        }
+    }
+    func.move_inst_before(inner.init, end(func, outer_preheader));
+    func.move_inst_before(outer.init, end(func, inner_preheader));
+
+    for &inst_id in &outer.test {
+        func.move_inst_before(inst_id, end(func, inner.header));
+    }
+    for &inst_id in &inner.test {
+        func.move_inst_before(inst_id, end(func, outer.header));
+    }
+    let (outer_end, inner_end) = (end(func, outer.header), end(func, inner.header));
+    let (InstKind::Br(outer_br), InstKind::Br(inner_br)) = (func.inst_arena[outer_end].kind.clone(), func.inst_arena[inner_end].kind.clone()) else {
+        unreachable!()
+    };
+    let (Br::Br { cond: outer_cond, .. }, Br::Br { cond: inner_cond, .. }) = (&outer_br, &inner_br) else {
+        unreachable!()
+    };
+    let swap_cond = |br: &Br, cond: &Operand| match br {
+        Br::Br { true_bb, false_bb, .. } => InstKind::Br(Br::Br { cond: cond.clone(), true_bb: *true_bb, false_bb: *false_bb }),
+        _ => unreachable!(),
+    };
+    func.set_inst_kind(outer_end, swap_cond(&outer_br, inner_cond));
+    func.set_inst_kind(inner_end, swap_cond(&inner_br, outer_cond));
+
+    let outer_latch = func.inst_arena[*outer.step.last().unwrap()].bb;
+    let inner_latch = func.inst_arena[*inner.step.last().unwrap()].bb;
+    for &inst_id in &outer.step {
+        func.move_inst_before(inst_id, end(func, inner_latch));
+    }
+    for &inst_id in &inner.step {
+        func.move_inst_before(inst_id, end(func, outer_latch));
+    }
+}
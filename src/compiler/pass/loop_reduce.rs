@@ -0,0 +1,432 @@
+use crate::compiler::analysis::{
+    loops::{insert_preheader, Loop, Loops},
+    scev::{AddRec, InductionVar, InductionVars},
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Alloca, Binary, BinaryInstOp, Br, InstKind, Load, Store, GEP},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Strength reduction of induction variables: computations that scale an
+/// induction variable by a constant are replaced by a variable stepped
+/// alongside it, set up in the preheader and bumped right after the
+/// induction variable is.
+///
+/// - Element addresses `gep %a, ..., i * c + d` become a pointer variable
+///   stepped by `step * c` elements, so that walking an array no longer
+///   recomputes the address of every element from its base.
+/// - Other products `i * c` become an integer variable stepped by
+///   `step * c`. When the loop exits on comparing `i` with a constant, the
+///   comparison is moved over to the new variable, and `i` is dropped if
+///   nothing else reads it.
+///
+/// Pointers cannot be compared in this IR, so exits are only rewritten for
+/// integer variables.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoopStrengthReduce;
+
+impl FuncPass for LoopStrengthReduce {
+    fn name(&self) -> &'static str {
+        "loop-reduce"
+    }
+
+    fn run(&mut self, func_id: FuncId, func: &mut IrFunc, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        if func.first_block.is_none() {
+            return PreservedAnalyses::all();
+        }
+        // reductions only add instructions once the loops they are in have
+        // preheaders, so the loops and their induction variables are found
+        // once, after inserting those
+        let mut changed = insert_preheaders(func_id, func, analyses);
+        if changed {
+            analyses.invalidate(func_id, &PreservedAnalyses::none());
+        }
+        let loop_info = analyses.get::<Loops>(func_id, func);
+        let scev = analyses.get::<InductionVars>(func_id, func);
+        for loop_id in loop_info.innermost_first() {
+            let lp = loop_info.get(loop_id);
+            let mut ivs = scev.induction_vars(loop_id).to_vec();
+            while let Some(reduction) = find_reduction(func, lp, &ivs) {
+                let factor = product_factor(func, reduction.inst_id, reduction.load);
+                let is_int = matches!(func.inst_arena[reduction.inst_id].ty, IrTy::Int(_));
+                let acc = reduce(func, lp, &reduction);
+                if let Some(factor) = factor {
+                    rewrite_exit(func, lp, reduction.iv, factor, acc.var, scev.trip_count(loop_id));
+                    // the induction variable is gone if only the test read it
+                    ivs.retain(|x| func.inst_arena.get(x.update).is_some());
+                }
+                // an integer variable is one more whose products are reduced
+                if is_int {
+                    ivs.push(acc);
+                }
+                changed = true;
+            }
+        }
+        if changed { PreservedAnalyses::none() } else { PreservedAnalyses::all() }
+    }
+}
+
+/// Inserts a preheader into each loop with a computation to reduce and
+/// none yet, returning whether there was any.
+fn insert_preheaders(func_id: FuncId, func: &mut IrFunc, analyses: &mut AnalysisManager) -> bool {
+    let loop_info = analyses.get::<Loops>(func_id, func);
+    let scev = analyses.get::<InductionVars>(func_id, func);
+    let mut inserted = false;
+    for loop_id in loop_info.innermost_first() {
+        let lp = loop_info.get(loop_id);
+        if lp.preheader(func).is_none() && find_reduction(func, lp, scev.induction_vars(loop_id)).is_some() {
+            insert_preheader(func, lp);
+            inserted = true;
+        }
+    }
+    inserted
+}
+
+/// The first computation in `lp` to reduce, addresses first, as reducing
+/// one also reduces the products in it.
+fn find_reduction(func: &IrFunc, lp: &Loop, ivs: &[InductionVar]) -> Option<Reduction> {
+    if ivs.is_empty() {
+        return None;
+    }
+    let candidates: Vec<_> = func.bb_ids()
+        .filter(|&bb| lp.contains(bb))
+        .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None).map(|(inst_id, _)| inst_id))
+        .collect();
+    candidates.iter().find_map(|&inst_id| reducible_gep(func, lp, ivs, inst_id))
+        .or_else(|| candidates.iter().find_map(|&inst_id| reducible_mul(func, lp, ivs, inst_id)))
+}
+
+/// A computation to replace by a variable stepped along an induction
+/// variable.
+struct Reduction {
+    inst_id: InstId,
+    iv: InductionVar,
+    /// The load of the induction variable the computation uses.
+    load: InstId,
+    /// What the variable is stepped by on each update of the induction
+    /// variable, in elements for addresses.
+    step: i32,
+}
+
+/// The computation of `operand` as an affine function of a single load of
+/// an induction variable.
+struct Affine {
+    load: Option<(InductionVar, InstId)>,
+    scale: i32,
+}
+
+/// Follows `operand` through additions, subtractions and multiplications by
+/// constants down to a load of one of `ivs` and values that do not change
+/// in `lp`.
+fn affine(func: &IrFunc, lp: &Loop, ivs: &[InductionVar], operand: &Operand) -> Option<Affine> {
+    if is_invariant(func, lp, operand) {
+        return Some(Affine { load: None, scale: 0 });
+    }
+    let inst_id = *operand.as_inst()?;
+    match &func.inst_arena[inst_id].kind {
+        InstKind::Load(load) => {
+            let iv = ivs.iter().find(|x| load.addr == Operand::Inst(x.var))?;
+            Some(Affine { load: Some((*iv, inst_id)), scale: 1 })
+        }
+        InstKind::Binary(binary) => {
+            let left = affine(func, lp, ivs, &binary.left)?;
+            let right = affine(func, lp, ivs, &binary.right)?;
+            let load = match (left.load, right.load) {
+                (Some(_), Some(_)) => return None,
+                (load, None) | (None, load) => load,
+            };
+            let scale = match binary.op {
+                BinaryInstOp::Add => left.scale.wrapping_add(right.scale),
+                BinaryInstOp::Sub => left.scale.wrapping_sub(right.scale),
+                BinaryInstOp::Mul => match (&binary.left, &binary.right) {
+                    _ if load.is_none() => 0,
+                    (Operand::Const(Constant::Int(c)), _) => right.scale.wrapping_mul(*c),
+                    (_, Operand::Const(Constant::Int(c))) => left.scale.wrapping_mul(*c),
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            Some(Affine { load, scale })
+        }
+        _ => None,
+    }
+}
+
+/// Whether `operand` has the same value throughout `lp` and can be computed
+/// again in its preheader: constants, arguments, globals, values computed
+/// before the loop, loads of locals the loop does not store to, and
+/// arithmetic and addresses computed from them, which cannot trap.
+fn is_invariant(func: &IrFunc, lp: &Loop, operand: &Operand) -> bool {
+    let Operand::Inst(inst_id) = operand else {
+        return !matches!(operand, Operand::BB(_));
+    };
+    let inst = &func.inst_arena[*inst_id];
+    if !lp.contains(inst.bb) {
+        return true;
+    }
+    match &inst.kind {
+        InstKind::Load(load) => is_unstored_local(func, lp, &load.addr),
+        InstKind::Binary(binary) => {
+            matches!(binary.op, BinaryInstOp::Add | BinaryInstOp::Sub | BinaryInstOp::Mul)
+                && is_invariant(func, lp, &binary.left)
+                && is_invariant(func, lp, &binary.right)
+        }
+        InstKind::GEP(gep) => is_invariant(func, lp, &gep.ptr) && gep.indices.iter().all(|x| is_invariant(func, lp, x)),
+        _ => false,
+    }
+}
+
+/// Whether `addr` is a local only ever loaded and stored, and never stored
+/// to in `lp`.
+fn is_unstored_local(func: &IrFunc, lp: &Loop, addr: &Operand) -> bool {
+    let Operand::Inst(var) = addr else {
+        return false;
+    };
+    matches!(func.inst_arena[*var].kind, InstKind::Alloca(_)) && func.users(addr).into_iter().all(|user| {
+        match &func.inst_arena[user].kind {
+            InstKind::Load(_) => true,
+            InstKind::Store(store) => store.data != *addr && !lp.contains(func.inst_arena[user].bb),
+            _ => false,
+        }
+    })
+}
+
+/// An address `gep %p, ...` in `lp` whose indices are invariant but for one
+/// affine in an induction variable.
+fn reducible_gep(func: &IrFunc, lp: &Loop, ivs: &[InductionVar], inst_id: InstId) -> Option<Reduction> {
+    let inst = &func.inst_arena[inst_id];
+    let InstKind::GEP(gep) = &inst.kind else {
+        return None;
+    };
+    if !is_invariant(func, lp, &gep.ptr) {
+        return None;
+    }
+    let mut varying = gep.indices.iter().enumerate().filter(|(_, x)| !is_invariant(func, lp, x));
+    let (pos, index) = varying.next()?;
+    if varying.next().is_some() {
+        return None;
+    }
+    let Affine { load: Some((iv, load)), scale } = affine(func, lp, ivs, index)? else {
+        return None;
+    };
+
+    // how many elements of the result a step of the index moves over
+    let elems = if pos + 1 == gep.indices.len() {
+        1
+    } else {
+        let ptr_ty = match &gep.ptr {
+            Operand::Inst(x) => &func.inst_arena[*x].ty,
+            Operand::Param(x) => &func.param_arena[*x].ty,
+            _ => return None,
+        };
+        let mut ty = IrTy::deptr_of(ptr_ty)?;
+        for _ in 0..pos {
+            ty = ty.as_array()?.1.as_ref().clone();
+        }
+        ty.size_in_words() / IrTy::deptr_of(&inst.ty)?.size_in_words().max(1)
+    };
+    let step = i64::from(scale)
+        .checked_mul(iv.rec.step)?
+        .checked_mul(i64::try_from(elems).ok()?)?;
+    let step = i32::try_from(step).ok().filter(|&x| x != 0)?;
+    Some(Reduction { inst_id, iv, load, step })
+}
+
+/// A product `i * c` in `lp` with `i` affine in an induction variable.
+fn reducible_mul(func: &IrFunc, lp: &Loop, ivs: &[InductionVar], inst_id: InstId) -> Option<Reduction> {
+    let InstKind::Binary(Binary { op: BinaryInstOp::Mul, .. }) = &func.inst_arena[inst_id].kind else {
+        return None;
+    };
+    let Affine { load: Some((iv, load)), scale } = affine(func, lp, ivs, &Operand::Inst(inst_id))? else {
+        return None;
+    };
+    let step = i32::try_from(iv.rec.step).ok()?.wrapping_mul(scale);
+    (step != 0).then_some(Reduction { inst_id, iv, load, step })
+}
+
+/// Replaces the computation of `reduction` by a load of a new variable,
+/// returning the variable, stepped along the induction variable.
+fn reduce(func: &mut IrFunc, lp: &Loop, reduction: &Reduction) -> InductionVar {
+    let ty = func.inst_arena[reduction.inst_id].ty.clone();
+    let entry = func.first_block.unwrap();
+    let acc = func.build_inst_at_start(InstKind::Alloca(Alloca { alloca_ty: ty.clone() }), IrTy::ptr_of(&ty), entry);
+
+    // the value on entry, computed from that of the induction variable
+    let preheader = insert_preheader(func, lp);
+    let before = func.terminator(preheader).unwrap();
+    let init_iv = func.build_inst_before_cur(InstKind::Load(Load { addr: reduction.iv.var.into() }), IrTy::int(), before);
+    let init = clone_into_preheader(func, lp, &Operand::Inst(reduction.inst_id), reduction.load, init_iv, before);
+    func.build_inst_before_cur(InstKind::Store(Store { addr: acc.into(), data: init }), IrTy::Void, before);
+
+    // stepped whenever the induction variable is
+    let cur = func.build_inst_after_cur(InstKind::Load(Load { addr: acc.into() }), ty.clone(), reduction.iv.update);
+    let step = Operand::Const(Constant::Int(reduction.step));
+    let stepped = if matches!(ty, IrTy::Ptr(_)) {
+        InstKind::GEP(GEP { ptr: cur.into(), indices: vec![step] })
+    } else {
+        InstKind::Binary(Binary { op: BinaryInstOp::Add, left: cur.into(), right: step })
+    };
+    let stepped = func.build_inst_after_cur(stepped, ty.clone(), cur);
+    let update = func.build_inst_after_cur(InstKind::Store(Store { addr: acc.into(), data: stepped.into() }), IrTy::Void, stepped);
+
+    // read where the induction variable was, as it may be updated in between
+    let val = func.build_inst_after_cur(InstKind::Load(Load { addr: acc.into() }), ty, reduction.load);
+    func.replace_all_uses_with(&Operand::Inst(reduction.inst_id), &Operand::Inst(val));
+    remove_dead(func, reduction.inst_id);
+    InductionVar { var: acc, update, rec: AddRec { start: None, step: i64::from(reduction.step) } }
+}
+
+/// Computes `operand` again before `before`, in the preheader of `lp`, with
+/// `init_iv` for the induction variable loaded by `load`.
+fn clone_into_preheader(func: &mut IrFunc, lp: &Loop, operand: &Operand, load: InstId, init_iv: InstId, before: InstId) -> Operand {
+    let Operand::Inst(inst_id) = operand else {
+        return operand.clone();
+    };
+    if *inst_id == load {
+        return init_iv.into();
+    }
+    let inst = &func.inst_arena[*inst_id];
+    if !lp.contains(inst.bb) {
+        return operand.clone();
+    }
+    let ty = inst.ty.clone();
+    let kind = match inst.kind.clone() {
+        InstKind::Binary(binary) => InstKind::Binary(Binary {
+            left: clone_into_preheader(func, lp, &binary.left, load, init_iv, before),
+            right: clone_into_preheader(func, lp, &binary.right, load, init_iv, before),
+            ..binary
+        }),
+        InstKind::GEP(gep) => InstKind::GEP(GEP {
+            ptr: clone_into_preheader(func, lp, &gep.ptr, load, init_iv, before),
+            indices: gep.indices.iter()
+                .map(|x| clone_into_preheader(func, lp, x, load, init_iv, before))
+                .collect(),
+        }),
+        kind @ InstKind::Load(_) => kind,
+        _ => unreachable!(),
+    };
+    func.build_inst_before_cur(kind, ty, before).into()
+}
+
+/// Deletes `inst_id` and the computations only it used.
+fn remove_dead(func: &mut IrFunc, inst_id: InstId) {
+    let mut dead = vec![inst_id];
+    while let Some(inst_id) = dead.pop() {
+        let removable = func.inst_arena.get(inst_id).is_some_and(|x| {
+            matches!(x.kind, InstKind::Binary(_) | InstKind::GEP(_) | InstKind::Load(_))
+        });
+        if !removable || func.has_uses(&Operand::Inst(inst_id)) {
+            continue;
+        }
+        let inst = func.remove_inst(inst_id);
+        let operands = match inst.kind {
+            InstKind::Binary(binary) => vec![binary.left, binary.right],
+            InstKind::GEP(gep) => std::iter::once(gep.ptr).chain(gep.indices).collect(),
+            _ => vec![],
+        };
+        dead.extend(operands.iter().filter_map(Operand::as_inst));
+    }
+}
+
+/// The constant `c` if `mul_id` computes `i * c` for the load `load` of an
+/// induction variable `i`, with nothing added.
+fn product_factor(func: &IrFunc, mul_id: InstId, load: InstId) -> Option<i32> {
+    let InstKind::Binary(Binary { op: BinaryInstOp::Mul, left, right }) = &func.inst_arena[mul_id].kind else {
+        return None;
+    };
+    match (left, right) {
+        (Operand::Inst(x), Operand::Const(Constant::Int(c))) | (Operand::Const(Constant::Int(c)), Operand::Inst(x)) if *x == load => Some(*c),
+        _ => None,
+    }
+}
+
+/// Moves the exit test of `lp` from the induction variable `iv` over to
+/// `acc`, which holds `iv * factor`, when `lp` exits on comparing `iv` with
+/// a constant `k`: `i < k` becomes `acc < k * factor`.
+///
+/// The products must not wrap around for that, which is checked over the
+/// values the test sees, and the factor must be positive to keep the order.
+/// The induction variable is then deleted if only its update reads it.
+fn rewrite_exit(func: &mut IrFunc, lp: &Loop, iv: InductionVar, factor: i32, acc: InstId, trip_count: Option<u64>) {
+    let Some(terminator) = func.terminator(lp.header) else {
+        return;
+    };
+    let InstKind::Br(Br::Br { cond: Operand::Inst(cmp), .. }) = func.inst_arena[terminator].kind else {
+        return;
+    };
+    let InstKind::Binary(binary) = &func.inst_arena[cmp].kind else {
+        return;
+    };
+    let is_iv_load = |operand: &Operand| operand.as_inst().is_some_and(|&x| {
+        matches!(&func.inst_arena[x].kind, InstKind::Load(load) if load.addr == Operand::Inst(iv.var))
+    });
+    let (load, bound, iv_left) = match (&binary.left, &binary.right) {
+        (Operand::Inst(load), Operand::Const(Constant::Int(k))) if is_iv_load(&binary.left) => (*load, *k, true),
+        (Operand::Const(Constant::Int(k)), Operand::Inst(load)) if is_iv_load(&binary.right) => (*load, *k, false),
+        _ => return,
+    };
+    let op = binary.op;
+    if !op.is_cmp() || factor <= 0 {
+        return;
+    }
+
+    // the values the test sees, from the first to the one leaving the loop
+    let (Some(start), Some(trip_count)) = (iv.rec.start, trip_count) else {
+        return;
+    };
+    let Some(end) = i64::try_from(trip_count).ok()
+        .and_then(|x| x.checked_mul(iv.rec.step))
+        .and_then(|x| x.checked_add(start)) else {
+        return;
+    };
+    let scaled = |x: i64| x.checked_mul(i64::from(factor)).and_then(|x| i32::try_from(x).ok());
+    let (Some(_), Some(_), Some(bound)) = (scaled(start), scaled(end), scaled(i64::from(bound))) else {
+        return;
+    };
+
+    let val = func.build_inst_after_cur(InstKind::Load(Load { addr: acc.into() }), IrTy::int(), load);
+    let (left, right) = if iv_left {
+        (val.into(), Operand::Const(Constant::Int(bound)))
+    } else {
+        (Operand::Const(Constant::Int(bound)), val.into())
+    };
+    func.set_inst_kind(cmp, InstKind::Binary(Binary { op, left, right }));
+    remove_dead(func, load);
+    remove_unread_iv(func, lp, iv);
+}
+
+/// Deletes the update of `iv` if nothing but the update itself and the
+/// preheader of `lp` reads the variable. `lp` must not be nested, or it
+/// could be entered again expecting the variable to have moved on.
+fn remove_unread_iv(func: &mut IrFunc, lp: &Loop, iv: InductionVar) {
+    let (Some(preheader), None) = (lp.preheader(func), lp.parent) else {
+        return;
+    };
+    let InstKind::Store(update) = &func.inst_arena[iv.update].kind else {
+        return;
+    };
+    let Operand::Inst(stepped) = update.data else {
+        return;
+    };
+    let unread = func.users(&Operand::Inst(iv.var)).into_iter().all(|user| {
+        let inst = &func.inst_arena[user];
+        match inst.kind {
+            InstKind::Load(_) => inst.bb == preheader || func.users(&Operand::Inst(user)) == [stepped],
+            _ => true,
+        }
+    });
+    if unread && func.users(&Operand::Inst(stepped)) == [iv.update] {
+        func.remove_inst(iv.update);
+        remove_dead(func, stepped);
+    }
+}
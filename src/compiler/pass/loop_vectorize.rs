@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+
+use crate::compiler::analysis::{
+    alias::{AliasAnalysis, BasicAliasAnalysis},
+    dominance::{Dominators, PostDominators},
+    loops::{Loop, LoopId, Loops},
+    scev::{InductionVars, ScalarEvolution},
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, InstId},
+    clone::ValueMap,
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Alloca, Binary, BinaryInstOp, Br, Cast, CastOp, InsertElement, InstKind, Load, Store},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::ModulePass;
+
+/// Widens innermost loops doing the same arithmetic on consecutive array
+/// elements, so that each iteration does the work of `factor` of them with
+/// vector instructions:
+///
+/// ```text
+/// while (i < 64) { a[i] = b[i] * k + i; i = i + 1; }
+/// ```
+///
+/// loads `<4 x i32>` from `b`, multiplies it by a vector holding `k` in
+/// every lane, adds `<i, i+1, i+2, i+3>`, stores the result to `a` and
+/// steps `i` by 4.
+///
+/// The loop must run a known number of times, at least `factor`, through a
+/// header only computing its exit test and a body of one block. If the
+/// count is not a multiple of `factor`, the widened loop runs as many times
+/// as it can, counted in a variable of its own, and a copy of the original
+/// loop after it runs the iterations left over. Addresses are only computed for the first lane,
+/// so the body may only store to its induction variables and to arrays of
+/// `i32` it walks one element per iteration. Memory a store may touch must
+/// only be accessed at the element it stores to, as the iterations run side
+/// by side.
+///
+/// Needs the module for the alias analysis, hence a module pass.
+#[derive(Debug, Clone, Copy)]
+pub struct LoopVectorize {
+    /// Iterations run at once, as lanes of each vector. Loops are left alone
+    /// unless it is between 2 and [`IrTy::MAX_LANES`].
+    pub factor: usize,
+}
+
+impl Default for LoopVectorize {
+    fn default() -> Self {
+        // four words fill a 128-bit NEON register
+        LoopVectorize { factor: 4 }
+    }
+}
+
+impl ModulePass for LoopVectorize {
+    fn name(&self) -> &'static str {
+        "loop-vectorize"
+    }
+
+    fn run(&mut self, module: &mut Module, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        if !(2..=IrTy::MAX_LANES).contains(&self.factor) {
+            return PreservedAnalyses::all();
+        }
+        let func_ids: Vec<_> = module.func_arena.items_iter(module.first_func, None)
+            .filter(|(_, func)| !func.is_builtin && func.first_block.is_some())
+            .map(|(func_id, _)| func_id)
+            .collect();
+        // the blocks are left as they are, unless loops are copied for the
+        // iterations left over
+        let preserved = PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>();
+        let mut changed = false;
+        let mut copied = false;
+        for func_id in func_ids {
+            // the function is taken out so that the analysis can borrow the
+            // module, which it only needs for the types of globals
+            let mut func = std::mem::take(&mut module.func_arena[func_id]);
+            let loop_info = analyses.get::<Loops>(func_id, &func);
+            let scev = analyses.get::<InductionVars>(func_id, &func);
+            let planner = Planner { alias: BasicAliasAnalysis::new(module), func: &func, scev: &scev, factor: self.factor };
+            let plans: Vec<_> = loop_info.loops()
+                .filter_map(|(loop_id, lp)| planner.plan(loop_id, lp))
+                .collect();
+            // only innermost loops are widened, which do not overlap
+            for plan in &plans {
+                if let Some(trips) = plan.epilogue {
+                    add_epilogue(&mut func, plan, trips);
+                }
+                widen(&mut func, plan, self.factor);
+            }
+            if plans.iter().any(|x| x.epilogue.is_some()) {
+                analyses.invalidate(func_id, &PreservedAnalyses::none());
+                copied = true;
+            } else if !plans.is_empty() {
+                analyses.invalidate(func_id, &preserved);
+            }
+            changed |= !plans.is_empty();
+            module.func_arena[func_id] = func;
+        }
+
+        match (changed, copied) {
+            (_, true) => PreservedAnalyses::none(),
+            (true, false) => preserved,
+            (false, false) => PreservedAnalyses::all(),
+        }
+    }
+}
+
+/// What a value of the loop is in each lane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lanes {
+    /// The same value.
+    Uniform,
+    /// The value of the first lane plus the step times the lane.
+    Affine(i64),
+    /// A value of its own, computed by a widened instruction.
+    Vector,
+}
+
+/// How to widen a loop.
+struct Plan {
+    preheader: BBId,
+    header: BBId,
+    body: BBId,
+    /// The loads, arithmetic and stores of the body to widen, in order.
+    widened: Vec<InstId>,
+    /// The operands of those, besides addresses, that are not widened.
+    lanes: HashMap<Operand, Lanes>,
+    /// The stores updating the induction variables, with their step.
+    steps: Vec<(InstId, i64)>,
+    /// The iterations of the widened loop, if some are left over for a copy
+    /// of the original loop to run.
+    epilogue: Option<i32>,
+}
+
+struct Planner<'a> {
+    alias: BasicAliasAnalysis<'a>,
+    func: &'a IrFunc,
+    scev: &'a ScalarEvolution,
+    factor: usize,
+}
+
+/// What the planner found out about the values of one loop so far.
+struct Scope<'a> {
+    loop_id: LoopId,
+    lp: &'a Loop,
+    body: BBId,
+    lanes: HashMap<Operand, Lanes>,
+}
+
+impl Planner<'_> {
+    fn insts(&self, bb: BBId) -> Vec<InstId> {
+        self.func.inst_arena.items_iter(self.func.bb_arena[bb].insts_head, None).map(|(inst_id, _)| inst_id).collect()
+    }
+
+    /// How to widen `lp`, if it can be.
+    fn plan(&self, loop_id: LoopId, lp: &Loop) -> Option<Plan> {
+        let func = self.func;
+        let [body] = lp.latches[..] else {
+            return None;
+        };
+        let trips = self.scev.trip_count(loop_id)?;
+        let factor = u64::try_from(self.factor).ok()?;
+        if !lp.children.is_empty() || lp.blocks.len() != 2 || body == lp.header || trips < factor
+            || lp.exiting_blocks(func) != [lp.header] {
+            return None;
+        }
+        let epilogue = if trips % factor == 0 { None } else { Some(i32::try_from(trips / factor).ok()?) };
+        let preheader = lp.preheader(func)?;
+        let header_insts = self.insts(lp.header);
+        // the copy of the loop computes the values of the header anew
+        let header_ok = header_insts[..header_insts.len() - 1].iter().all(|&x| {
+            matches!(func.inst_arena[x].kind, InstKind::Load(_) | InstKind::Binary(_) | InstKind::Cast(_))
+                && (epilogue.is_none() || func.users(&x.into()).into_iter().all(|user| lp.contains(func.inst_arena[user].bb)))
+        });
+        let body_insts = self.insts(body);
+        let (&body_end, body_insts) = body_insts.split_last()?;
+        if !header_ok || !matches!(func.inst_arena[body_end].kind, InstKind::Br(Br::Jump { .. })) {
+            return None;
+        }
+
+        let mut scope = Scope { loop_id, lp, body, lanes: HashMap::new() };
+        let mut steps = vec![];
+        let mut widened = vec![];
+        for (pos, &inst_id) in body_insts.iter().enumerate() {
+            match &func.inst_arena[inst_id].kind {
+                InstKind::Store(_) if self.scev.induction_vars(loop_id).iter().any(|x| x.update == inst_id) => {
+                    steps.push((inst_id, self.step(inst_id, &body_insts[pos + 1..])?));
+                }
+                InstKind::Store(store) => {
+                    if !self.consecutive(&scope, &store.addr) {
+                        return None;
+                    }
+                    self.lanes(&mut scope, &store.data)?;
+                    widened.push(inst_id);
+                }
+                InstKind::Load(_) | InstKind::Binary(_) | InstKind::GEP(_) => {}
+                _ => return None,
+            }
+        }
+
+        // what is widened is only used as a value by what is widened
+        let vectors: Vec<_> = scope.lanes.iter()
+            .filter(|(_, lanes)| **lanes == Lanes::Vector)
+            .filter_map(|(operand, _)| operand.as_inst().copied())
+            .collect();
+        let is_widened = |user: InstId| vectors.contains(&user) || widened.contains(&user);
+        let used_as_vector = vectors.iter().all(|&x| func.users(&Operand::Inst(x)).into_iter().all(|user| {
+            is_widened(user) && !matches!(&func.inst_arena[user].kind, InstKind::Store(store) if store.addr == Operand::Inst(x))
+        }));
+        if !used_as_vector || !self.independent(&scope, body_insts) {
+            return None;
+        }
+        widened.extend(vectors);
+        widened.sort_by_key(|&x| body_insts.iter().position(|&y| y == x));
+        let lanes = scope.lanes.into_iter().filter(|(_, lanes)| *lanes != Lanes::Vector).collect();
+        Some(Plan { preheader, header: lp.header, body, widened, lanes, steps, epilogue })
+    }
+
+    /// The step of the induction variable `update` stores to, if it is
+    /// stepped by a constant that can be multiplied by the factor, and not
+    /// loaded again in `after`, the rest of the body.
+    fn step(&self, update: InstId, after: &[InstId]) -> Option<i64> {
+        let func = self.func;
+        let InstKind::Store(store) = &func.inst_arena[update].kind else {
+            return None;
+        };
+        let InstKind::Binary(binary) = &func.inst_arena[*store.data.as_inst()?].kind else {
+            return None;
+        };
+        let step = match (&binary.left, &binary.right) {
+            (_, Operand::Const(Constant::Int(x))) | (Operand::Const(Constant::Int(x)), _) => *x,
+            _ => return None,
+        };
+        let reloaded = after.iter().any(|&x| matches!(&func.inst_arena[x].kind, InstKind::Load(load) if load.addr == store.addr));
+        if reloaded {
+            return None;
+        }
+        step.checked_mul(i32::try_from(self.factor).ok()?).map(i64::from)
+    }
+
+    fn is_invariant(scope: &Scope, func: &IrFunc, operand: &Operand) -> bool {
+        match operand {
+            Operand::Inst(x) => !scope.lp.contains(func.inst_arena[*x].bb),
+            Operand::BB(_) => false,
+            _ => true,
+        }
+    }
+
+    /// Whether `addr` steps through an array of `i32` one element per
+    /// iteration, everything but its last index staying the same.
+    fn consecutive(&self, scope: &Scope, addr: &Operand) -> bool {
+        let func = self.func;
+        let Some(InstKind::GEP(gep)) = addr.as_inst().map(|&x| &func.inst_arena[x].kind) else {
+            return false;
+        };
+        let Some((last, indices)) = gep.indices.split_last() else {
+            return false;
+        };
+        let is_int = IrTy::deptr_of(&func.inst_arena[*addr.as_inst().unwrap()].ty) == Some(IrTy::int());
+        is_int && scope.lp.contains(func.inst_arena[*addr.as_inst().unwrap()].bb)
+            && std::iter::once(&gep.ptr).chain(indices).all(|x| Self::is_invariant(scope, func, x))
+            && self.scev.evolution_of(func, scope.loop_id, last).is_some_and(|x| x.step == 1)
+    }
+
+    /// Whether a store of the body may write to `addr`.
+    fn is_written(&self, scope: &Scope, addr: &Operand) -> bool {
+        self.insts(scope.body).into_iter().any(|x| match &self.func.inst_arena[x].kind {
+            InstKind::Store(store) => self.alias.may_alias(self.func, &store.addr, addr),
+            _ => false,
+        })
+    }
+
+    /// What `operand`, an `i32` used by the body, is in each lane, if it can
+    /// be told or widened.
+    fn lanes(&self, scope: &mut Scope, operand: &Operand) -> Option<Lanes> {
+        if let Some(&lanes) = scope.lanes.get(operand) {
+            return Some(lanes);
+        }
+        let func = self.func;
+        let lanes = match operand {
+            Operand::Const(Constant::Int(_)) | Operand::Param(_) => Lanes::Uniform,
+            Operand::Inst(x) if Self::is_invariant(scope, func, operand) && func.inst_arena[*x].ty == IrTy::int() => Lanes::Uniform,
+            Operand::Inst(inst_id) if func.inst_arena[*inst_id].ty == IrTy::int() => {
+                let inst = &func.inst_arena[*inst_id];
+                let in_body = inst.bb == scope.body;
+                // the steps to the last lane must stay within `i32`
+                let last_lane = i64::try_from(self.factor - 1).unwrap_or(i64::MAX);
+                let evolution = self.scev.evolution_of(func, scope.loop_id, operand)
+                    .filter(|x| x.step.checked_mul(last_lane).and_then(|x| i32::try_from(x).ok()).is_some());
+                match &inst.kind {
+                    _ if let Some(rec) = evolution => {
+                        if rec.step == 0 { Lanes::Uniform } else { Lanes::Affine(rec.step) }
+                    }
+                    InstKind::Load(load) if Self::is_invariant(scope, func, &load.addr) && !self.is_written(scope, &load.addr) => Lanes::Uniform,
+                    InstKind::Load(load) if in_body && self.consecutive(scope, &load.addr) => Lanes::Vector,
+                    InstKind::Binary(binary) if !binary.op.is_cmp() => {
+                        match (self.lanes(scope, &binary.left)?, self.lanes(scope, &binary.right)?) {
+                            (Lanes::Uniform, Lanes::Uniform) => Lanes::Uniform,
+                            _ if in_body => Lanes::Vector,
+                            _ => return None,
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        scope.lanes.insert(operand.clone(), lanes);
+        Some(lanes)
+    }
+
+    /// Whether running the iterations side by side keeps every access to
+    /// memory a store of the body may touch in order: the other accesses
+    /// are to the same element on the same iteration.
+    fn independent(&self, scope: &Scope, body_insts: &[InstId]) -> bool {
+        let func = self.func;
+        let accesses: Vec<_> = self.insts(scope.lp.header).into_iter().chain(body_insts.iter().copied())
+            .filter_map(|x| match &func.inst_arena[x].kind {
+                InstKind::Load(load) => Some((&load.addr, false)),
+                InstKind::Store(store) => Some((&store.addr, true)),
+                _ => None,
+            })
+            .collect();
+        accesses.iter().filter(|(_, writes)| *writes).all(|(a, _)| {
+            accesses.iter().all(|(b, _)| !self.alias.may_alias(func, a, b) || self.same_element(scope, a, b))
+        })
+    }
+
+    /// Whether `a` and `b` address the same element on each iteration.
+    fn same_element(&self, scope: &Scope, a: &Operand, b: &Operand) -> bool {
+        let func = self.func;
+        if a == b {
+            return true;
+        }
+        let gep = |x: &Operand| match &func.inst_arena[*x.as_inst()?].kind {
+            InstKind::GEP(gep) => Some(gep),
+            _ => None,
+        };
+        let (Some(a), Some(b)) = (gep(a), gep(b)) else {
+            return false;
+        };
+        a.ptr == b.ptr && a.indices.len() == b.indices.len() && a.indices.iter().zip(&b.indices).all(|(x, y)| {
+            let evolution = |x| self.scev.evolution_of(func, scope.loop_id, x);
+            x == y || evolution(x).is_some_and(|x| x.start.is_some()) && evolution(x) == evolution(y)
+        })
+    }
+}
+
+/// Widens the loop of `plan` to run `factor` iterations at once.
+fn widen(func: &mut IrFunc, plan: &Plan, factor: usize) {
+    let vector_ty = IrTy::Vector(factor, Box::new(IrTy::int()));
+    let preheader_end = func.terminator(plan.preheader).unwrap();
+    let mut built: HashMap<Operand, Operand> = HashMap::new();
+    let mut step_vectors: HashMap<i64, Operand> = HashMap::new();
+
+    for &inst_id in &plan.widened {
+        let mut vector_of = |func: &mut IrFunc, operand: &Operand| -> Operand {
+            let Some(&lanes) = plan.lanes.get(operand) else {
+                return operand.clone();
+            };
+            if let Some(vector) = built.get(operand) {
+                return vector.clone();
+            }
+            let invariant = operand.as_inst().is_none_or(|&x| ![plan.header, plan.body].contains(&func.inst_arena[x].bb));
+            let at = if invariant { preheader_end } else { inst_id };
+            let mut vector = splat(func, factor, operand, at);
+            if let Lanes::Affine(step) = lanes {
+                let steps = step_vectors.entry(step).or_insert_with(|| {
+                    let lanes = (0..factor).map(|x| i64::try_from(x).unwrap() * step);
+                    build_vector(func, lanes.map(|x| Operand::Const(Constant::Int(i32::try_from(x).unwrap()))), preheader_end)
+                });
+                let add = Binary { op: BinaryInstOp::Add, left: vector, right: steps.clone() };
+                vector = func.build_inst_before_cur(InstKind::Binary(add), IrTy::Vector(factor, Box::new(IrTy::int())), at).into();
+            }
+            built.insert(operand.clone(), vector.clone());
+            vector
+        };
+        let kind = match func.inst_arena[inst_id].kind.clone() {
+            InstKind::Load(load) => InstKind::Load(Load { addr: cast_to(func, &load.addr, &vector_ty, inst_id) }),
+            InstKind::Binary(binary) => {
+                let (left, right) = (vector_of(func, &binary.left), vector_of(func, &binary.right));
+                InstKind::Binary(Binary { op: binary.op, left, right })
+            }
+            InstKind::Store(store) => {
+                let data = vector_of(func, &store.data);
+                InstKind::Store(Store { addr: cast_to(func, &store.addr, &vector_ty, inst_id), data })
+            }
+            _ => unreachable!(),
+        };
+        func.set_inst_kind(inst_id, kind);
+        if !matches!(func.inst_arena[inst_id].kind, InstKind::Store(_)) {
+            func.inst_arena[inst_id].ty = vector_ty.clone();
+        }
+    }
+
+    for &(update, step) in &plan.steps {
+        let InstKind::Store(store) = func.inst_arena[update].kind.clone() else {
+            unreachable!()
+        };
+        let InstKind::Binary(binary) = func.inst_arena[*store.data.as_inst().unwrap()].kind.clone() else {
+            unreachable!()
+        };
+        let step = Operand::Const(Constant::Int(i32::try_from(step).unwrap()));
+        let (left, right) = match (binary.left, binary.right) {
+            (Operand::Const(_), right) => (step, right),
+            (left, _) => (left, step),
+        };
+        let data = func.build_inst_before_cur(InstKind::Binary(Binary { op: binary.op, left, right }), IrTy::int(), update);
+        func.set_inst_kind(update, InstKind::Store(Store { addr: store.addr, data: data.into() }));
+    }
+}
+
+/// Copies the loop of `plan` after it, for the iterations left over once it
+/// is widened, and makes it exit into the copy after `trips` iterations.
+fn add_epilogue(func: &mut IrFunc, plan: &Plan, trips: i32) {
+    let mut map = ValueMap::new();
+    let mut last = plan.body;
+    for bb in [plan.header, plan.body] {
+        last = func.build_bb_after_cur(last);
+        map.insert_bb(bb, last);
+    }
+    let mut copies = vec![];
+    for bb in [plan.header, plan.body] {
+        let insts: Vec<_> = func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None)
+            .map(|(inst_id, inst)| (inst_id, inst.kind.clone(), inst.ty.clone()))
+            .collect();
+        for (inst_id, kind, ty) in insts {
+            let copy = func.build_inst_at_end(kind, ty, map.map_bb(bb));
+            map.insert(inst_id.into(), copy.into());
+            copies.push(copy);
+        }
+    }
+    for copy in copies {
+        let kind = map.map_inst_kind(&func.inst_arena[copy].kind);
+        func.set_inst_kind(copy, kind);
+    }
+
+    // the widened loop counts its own iterations
+    let entry = func.first_block.unwrap();
+    let count = func.build_inst_at_start(InstKind::Alloca(Alloca { alloca_ty: IrTy::int() }), IrTy::ptr_of(&IrTy::int()), entry);
+    let preheader_end = func.terminator(plan.preheader).unwrap();
+    let zero = Operand::Const(Constant::Int(0));
+    func.build_inst_before_cur(InstKind::Store(Store { addr: count.into(), data: zero }), IrTy::Void, preheader_end);
+
+    let header_end = func.terminator(plan.header).unwrap();
+    let cur = func.build_inst_before_cur(InstKind::Load(Load { addr: count.into() }), IrTy::int(), header_end);
+    let test = Binary { op: BinaryInstOp::Lt, left: cur.into(), right: Operand::Const(Constant::Int(trips)) };
+    let test = func.build_inst_before_cur(InstKind::Binary(test), IrTy::bool(), header_end);
+    let exit = Br::Br { cond: test.into(), true_bb: plan.body, false_bb: map.map_bb(plan.header) };
+    func.set_inst_kind(header_end, InstKind::Br(exit));
+
+    let body_end = func.terminator(plan.body).unwrap();
+    let cur = func.build_inst_before_cur(InstKind::Load(Load { addr: count.into() }), IrTy::int(), body_end);
+    let next = Binary { op: BinaryInstOp::Add, left: cur.into(), right: Operand::Const(Constant::Int(1)) };
+    let next = func.build_inst_before_cur(InstKind::Binary(next), IrTy::int(), body_end);
+    func.build_inst_before_cur(InstKind::Store(Store { addr: count.into(), data: next.into() }), IrTy::Void, body_end);
+}
+
+/// `addr` cast to a pointer to `ty`, before `at`.
+fn cast_to(func: &mut IrFunc, addr: &Operand, ty: &IrTy, at: InstId) -> Operand {
+    let target_ty = IrTy::ptr_of(ty);
+    let cast = Cast { op: CastOp::Bitcast, ori_val: addr.clone(), target_ty: target_ty.clone() };
+    func.build_inst_before_cur(InstKind::Cast(cast), target_ty, at).into()
+}
+
+/// A vector holding `elem` in every lane, built before `at`.
+fn splat(func: &mut IrFunc, factor: usize, elem: &Operand, at: InstId) -> Operand {
+    build_vector(func, std::iter::repeat_n(elem.clone(), factor), at)
+}
+
+/// A vector of `elems`, built before `at` by inserting them one by one.
+fn build_vector(func: &mut IrFunc, elems: impl ExactSizeIterator<Item = Operand>, at: InstId) -> Operand {
+    let ty = IrTy::Vector(elems.len(), Box::new(IrTy::int()));
+    let mut vector = Operand::Const(Constant::Undef(ty.clone()));
+    for (lane, elem) in elems.enumerate() {
+        let insert = InsertElement { vector, elem, lane };
+        vector = func.build_inst_before_cur(InstKind::InsertElement(insert), ty.clone(), at).into();
+    }
+    vector
+}
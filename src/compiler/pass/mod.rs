@@ -0,0 +1,166 @@
+use crate::compiler::analysis::{AnalysisManager, PreservedAnalyses};
+use crate::compiler::ir::{
+    arena::FuncId,
+    value::{func::IrFunc, module::Module},
+};
+
+pub mod const_eval;
+pub mod const_fold;
+pub mod const_hoist;
+pub mod const_merge;
+pub mod dce;
+pub mod global_dce;
+pub mod gvn;
+pub mod hot_cold_split;
+pub mod if_convert;
+pub mod inline;
+pub mod instcombine;
+pub mod load_store_forward;
+pub mod localize_globals;
+pub mod loop_fusion;
+pub mod loop_interchange;
+pub mod loop_reduce;
+pub mod loop_vectorize;
+pub mod pipeline;
+pub mod pre;
+pub mod print_ir;
+pub mod sanitize;
+pub mod sccp;
+pub mod simplify_cfg;
+pub mod sroa;
+pub mod strength_reduce;
+pub mod tail_call;
+
+/// A transformation of a whole module, for passes that look across
+/// functions.
+pub trait ModulePass {
+    fn name(&self) -> &'static str;
+
+    /// Returns what the pass kept valid, on every function.
+    fn run(&mut self, module: &mut Module, analyses: &mut AnalysisManager) -> PreservedAnalyses;
+}
+
+/// A transformation of one function at a time. Runtime library functions are
+/// skipped.
+pub trait FuncPass {
+    fn name(&self) -> &'static str;
+
+    fn run(&mut self, func_id: FuncId, func: &mut IrFunc, analyses: &mut AnalysisManager) -> PreservedAnalyses;
+}
+
+/// Hooks a [`PassManager`] calls around each pass it runs, to watch the
+/// pipeline from outside, as when dumping the IR between passes.
+pub trait PassInstrumentation {
+    fn before_pass(&mut self, _pass: &str, _module: &Module) {}
+
+    fn after_pass(&mut self, _pass: &str, _module: &Module) {}
+}
+
+enum Pass {
+    Module(Box<dyn ModulePass>),
+    Func(Box<dyn FuncPass>),
+}
+
+impl Pass {
+    fn name(&self) -> &'static str {
+        match self {
+            Pass::Module(pass) => pass.name(),
+            Pass::Func(pass) => pass.name(),
+        }
+    }
+}
+
+/// Runs passes in the order they were added, sharing analysis results between
+/// them until a pass invalidates them.
+pub struct PassManager {
+    passes: Vec<Pass>,
+    analyses: AnalysisManager,
+    instrumentations: Vec<Box<dyn PassInstrumentation>>,
+}
+
+impl std::fmt::Debug for PassManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PassManager")
+            .field("passes", &self.pass_names())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager::new()
+    }
+}
+
+impl PassManager {
+    #[must_use] pub fn new() -> PassManager {
+        PassManager {
+            passes: vec![],
+            analyses: AnalysisManager::new(),
+            instrumentations: vec![],
+        }
+    }
+
+    /// Has `instrumentation` called around every pass from now on, after
+    /// those added before it.
+    pub fn add_instrumentation(&mut self, instrumentation: impl PassInstrumentation + 'static) -> &mut PassManager {
+        self.instrumentations.push(Box::new(instrumentation));
+        self
+    }
+
+    pub fn add_module_pass(&mut self, pass: impl ModulePass + 'static) -> &mut PassManager {
+        self.passes.push(Pass::Module(Box::new(pass)));
+        self
+    }
+
+    pub fn add_func_pass(&mut self, pass: impl FuncPass + 'static) -> &mut PassManager {
+        self.passes.push(Pass::Func(Box::new(pass)));
+        self
+    }
+
+    #[must_use] pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(Pass::name).collect()
+    }
+
+    /// Analysis results cached by the last run, still valid for the module
+    /// it ran on.
+    #[must_use] pub fn analyses(&mut self) -> &mut AnalysisManager {
+        &mut self.analyses
+    }
+
+    /// Runs every pass once over `module`, returning whether any of them
+    /// changed it.
+    pub fn run(&mut self, module: &mut Module) -> bool {
+        // results from another module would be keyed by stale ids
+        self.analyses = AnalysisManager::new();
+        let mut changed = false;
+        for pass in &mut self.passes {
+            for instrumentation in &mut self.instrumentations {
+                instrumentation.before_pass(pass.name(), module);
+            }
+            match pass {
+                Pass::Module(pass) => {
+                    let preserved = pass.run(module, &mut self.analyses);
+                    self.analyses.invalidate_all(&preserved);
+                    changed |= !preserved.is_all();
+                }
+                Pass::Func(pass) => {
+                    let func_ids: Vec<_> = module.func_arena.iter()
+                        .filter(|(_, func)| !func.is_builtin)
+                        .map(|(func_id, _)| func_id)
+                        .collect();
+                    for func_id in func_ids {
+                        let func = &mut module.func_arena[func_id];
+                        let preserved = pass.run(func_id, func, &mut self.analyses);
+                        self.analyses.invalidate(func_id, &preserved);
+                        changed |= !preserved.is_all();
+                    }
+                }
+            }
+            for instrumentation in &mut self.instrumentations {
+                instrumentation.after_pass(pass.name(), module);
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::compiler::pass::{
+    const_eval::ConstEval,
+    const_fold::ConstFold,
+    const_hoist::ConstHoist,
+    const_merge::ConstMerge,
+    dce::Dce,
+    global_dce::GlobalDce,
+    gvn::Gvn,
+    hot_cold_split::HotColdSplit,
+    if_convert::IfConvert,
+    inline::Inline,
+    instcombine::InstCombine,
+    load_store_forward::LoadStoreForward,
+    localize_globals::LocalizeGlobals,
+    loop_fusion::LoopFusion,
+    loop_interchange::LoopInterchange,
+    loop_reduce::LoopStrengthReduce,
+    loop_vectorize::LoopVectorize,
+    pre::Pre,
+    sanitize::{SanitizeBounds, SanitizeOverflow},
+    sccp::Sccp,
+    simplify_cfg::SimplifyCfg,
+    sroa::Sroa,
+    strength_reduce::StrengthReduce,
+    tail_call::TailCallElim,
+    Pass,
+    PassManager,
+};
+use crate::compiler::target::Target;
+
+type Constructor = fn(Target) -> Pass;
+
+/// Every pass by its name, built for a target with its default settings.
+const REGISTRY: &[(&str, Constructor)] = &[
+    ("const-eval", |_| Pass::Module(Box::new(ConstEval::default()))),
+    ("const-fold", |_| Pass::Func(Box::new(ConstFold))),
+    ("const-hoist", |target| Pass::Func(Box::new(ConstHoist { target }))),
+    ("const-merge", |_| Pass::Module(Box::new(ConstMerge))),
+    ("dce", |_| Pass::Func(Box::new(Dce))),
+    ("global-dce", |_| Pass::Module(Box::new(GlobalDce))),
+    ("gvn", |_| Pass::Func(Box::new(Gvn))),
+    ("hot-cold-split", |_| Pass::Func(Box::new(HotColdSplit))),
+    ("if-convert", |_| Pass::Func(Box::new(IfConvert))),
+    ("inline", |_| Pass::Module(Box::new(Inline::default()))),
+    ("instcombine", |_| Pass::Func(Box::new(InstCombine))),
+    ("load-store-forward", |_| Pass::Module(Box::new(LoadStoreForward))),
+    ("localize-globals", |_| Pass::Module(Box::new(LocalizeGlobals))),
+    ("loop-fusion", |_| Pass::Module(Box::new(LoopFusion))),
+    ("loop-interchange", |_| Pass::Module(Box::new(LoopInterchange))),
+    ("loop-reduce", |_| Pass::Func(Box::new(LoopStrengthReduce))),
+    ("loop-vectorize", |_| Pass::Module(Box::new(LoopVectorize::default()))),
+    ("pre", |_| Pass::Func(Box::new(Pre))),
+    ("sanitize-bounds", |_| Pass::Module(Box::new(SanitizeBounds))),
+    ("sanitize-overflow", |_| Pass::Module(Box::new(SanitizeOverflow))),
+    ("sccp", |_| Pass::Func(Box::new(Sccp))),
+    ("simplify-cfg", |_| Pass::Func(Box::new(SimplifyCfg))),
+    ("sroa", |_| Pass::Func(Box::new(Sroa))),
+    ("strength-reduce", |_| Pass::Func(Box::new(StrengthReduce))),
+    ("tail-call-elim", |_| Pass::Func(Box::new(TailCallElim))),
+];
+
+/// Other names of the passes, as LLVM spells them, so that pipelines written
+/// for `opt` carry over. Only passes doing what LLVM's do are named so.
+const ALIASES: &[(&str, &str)] = &[
+    ("constmerge", "const-merge"),
+    ("consthoist", "const-hoist"),
+    ("globaldce", "global-dce"),
+    ("hotcoldsplit", "hot-cold-split"),
+    ("simplifycfg", "simplify-cfg"),
+    ("tailcallelim", "tail-call-elim"),
+];
+
+/// The names of every pass [`PassManager::add_pass`] knows.
+pub fn pass_names() -> impl Iterator<Item = &'static str> {
+    REGISTRY.iter().map(|x| x.0)
+}
+
+/// The other names [`PassManager::add_pass`] takes for the pass `name`.
+pub fn pass_aliases(name: &str) -> impl Iterator<Item = &'static str> + '_ {
+    ALIASES.iter().filter(move |x| x.1 == name).map(|x| x.0)
+}
+
+pub(super) fn create(name: &str, target: Target) -> Option<Pass> {
+    let name = ALIASES.iter().find(|x| x.0 == name).map_or(name, |x| x.1);
+    REGISTRY.iter().find(|x| x.0 == name).map(|x| x.1(target))
+}
+
+/// A name given to [`PassManager::add_pass`] that no pass has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPass(pub String);
+
+impl Display for UnknownPass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown pass `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPass {}
+
+/// How much to optimize, as the `-O` levels of other compilers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum OptLevel {
+    /// The IR as built.
+    #[default]
+    O0,
+    /// Cheap cleanups within each function.
+    O1,
+    /// Everything that does not grow the code much, across functions too.
+    O2,
+    /// Loop transformations and layout on top of `O2`.
+    O3,
+}
+
+impl OptLevel {
+    /// The passes run at this level, in order.
+    #[must_use] pub fn pipeline(self) -> Vec<&'static str> {
+        // locals first become values the scalar passes can follow, and
+        // instcombine cleans up after each pass leaving simplifiable code
+        const O1: &[&str] = &[
+            "simplify-cfg", "sroa", "load-store-forward", "sccp", "instcombine", "gvn", "simplify-cfg",
+        ];
+        const O2: &[&str] = &[
+            "localize-globals", "simplify-cfg", "sroa", "load-store-forward",
+            "inline", "tail-call-elim", "simplify-cfg", "load-store-forward",
+            "sccp", "const-eval", "instcombine", "const-fold", "simplify-cfg",
+            "gvn", "pre", "instcombine", "if-convert", "simplify-cfg",
+        ];
+        // loop-reduce turns indexing into pointer increments, so it comes
+        // after the loop passes of O3, which look for the indices
+        const O2_LATE: &[&str] = &[
+            "loop-reduce", "strength-reduce", "instcombine", "gvn",
+            "const-merge", "global-dce", "simplify-cfg",
+        ];
+        const O3_LOOPS: &[&str] = &[
+            "loop-fusion", "loop-interchange", "loop-vectorize", "instcombine", "gvn", "simplify-cfg",
+        ];
+        // const-hoist materializes what the folding passes would fold back
+        const O3_LATE: &[&str] = &["hot-cold-split", "const-hoist"];
+        match self {
+            OptLevel::O0 => vec![],
+            OptLevel::O1 => O1.to_vec(),
+            OptLevel::O2 => [O2, O2_LATE].concat(),
+            OptLevel::O3 => [O2, O3_LOOPS, O2_LATE, O3_LATE].concat(),
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            _ => Err("Allowed optimization levels: 0, 1, 2, 3"),
+        }
+    }
+}
+
+impl PassManager {
+    /// A pass manager running the pipeline of `level`, for `target`.
+    ///
+    /// # Panics
+    ///
+    /// If the pipeline names a pass missing from the registry.
+    #[must_use] pub fn with_opt_level(level: OptLevel, target: Target) -> PassManager {
+        PassManager::with_passes(&level.pipeline(), target).expect("pipelines only name registered passes")
+    }
+
+    /// A pass manager running the passes named `names`, in order, for
+    /// `target`.
+    ///
+    /// # Errors
+    ///
+    /// The first name no pass has.
+    pub fn with_passes<S: AsRef<str>>(names: &[S], target: Target) -> Result<PassManager, UnknownPass> {
+        let mut pass_manager = PassManager::new();
+        for name in names {
+            pass_manager.add_pass(name.as_ref(), target)?;
+        }
+        Ok(pass_manager)
+    }
+
+    /// Adds the pass named `name`, for `target` with its default settings.
+    ///
+    /// # Errors
+    ///
+    /// If no pass has that name, see [`pass_names`].
+    pub fn add_pass(&mut self, name: &str, target: Target) -> Result<&mut PassManager, UnknownPass> {
+        let pass = create(name, target).ok_or_else(|| UnknownPass(name.to_string()))?;
+        self.passes.push(pass);
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fmt::Write;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use crate::compiler::ir::value::module::Module;
+    use crate::compiler::ir_builder::{ir_builder::IrBuilder, name_resolver::NameResolver, type_checker::TypeChecker};
+    use crate::compiler::pass::{PassInstrumentation, PassManager};
+    use crate::compiler::syntax::{lexer::Lexer, parser::Parser};
+    use crate::compiler::target::Target;
+
+    use super::OptLevel;
+
+    /// A vectorizable loop.
+    const VECTORIZE: &str = "
+        int a[64], b[64];
+        int main() {
+            int k = getint();
+            int i = 0;
+            while (i < 64) { b[i] = i; i = i + 1; }
+            i = 0;
+            while (i < 64) { a[i] = b[i] * k + i; i = i + 1; }
+            putint(a[63]);
+            return 0;
+        }";
+
+    /// Two loops over the same range, the second reading what the first wrote.
+    const FUSE: &str = "
+        int a[100], b[100];
+        int main() {
+            int i = 0;
+            while (i < 100) { a[i] = i; i = i + 1; }
+            int j = 0;
+            while (j < 100) { b[j] = a[j] * 2; j = j + 1; }
+            putint(b[99]);
+            return 0;
+        }";
+
+    /// A nest walking an array by columns.
+    const INTERCHANGE: &str = "
+        int m[50][50];
+        int main() {
+            int j = 0;
+            while (j < 50) {
+                int i = 0;
+                while (i < 50) {
+                    m[i][j] = i + j;
+                    i = i + 1;
+                }
+                j = j + 1;
+            }
+            putint(m[49][48]);
+            return 0;
+        }";
+
+    /// The passes changing the module, in the order they ran.
+    #[derive(Default)]
+    struct Changes {
+        before: String,
+        changed: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl PassInstrumentation for Changes {
+        fn before_pass(&mut self, _pass: &str, module: &Module) {
+            self.before = module.to_string();
+        }
+
+        fn after_pass(&mut self, pass: &str, module: &Module) {
+            if module.to_string() != self.before {
+                self.changed.borrow_mut().push(pass.to_string());
+            }
+        }
+    }
+
+    fn build(source: &str) -> Module {
+        let mut program = Parser::new(Lexer::new(source.chars())).parse().unwrap();
+        NameResolver::new().resolve(&mut program).unwrap();
+        let typed = TypeChecker::new().check(&program).unwrap();
+        let mut ir_builder = IrBuilder::new();
+        ir_builder.visit(typed.program()).unwrap();
+        ir_builder.ctx.cur_module
+    }
+
+    fn changing_passes(source: &str, level: OptLevel) -> Vec<String> {
+        let mut module = build(source);
+        let changes = Changes::default();
+        let changed = changes.changed.clone();
+        let mut pass_manager = PassManager::with_opt_level(level, Target::default());
+        pass_manager.add_instrumentation(changes);
+        pass_manager.run(&mut module);
+        changed.take()
+    }
+
+    fn assert_fires(source: &str, level: OptLevel, pass: &str) {
+        let changed = changing_passes(source, level);
+        assert!(changed.iter().any(|x| x == pass), "{pass} does not change the IR at {level:?}, only {changed:?} do");
+    }
+
+    #[test]
+    fn o2_loop_passes_fire() {
+        assert_fires(VECTORIZE, OptLevel::O2, "loop-reduce");
+    }
+
+    #[test]
+    fn o3_loop_passes_fire() {
+        assert_fires(FUSE, OptLevel::O3, "loop-fusion");
+        assert_fires(INTERCHANGE, OptLevel::O3, "loop-interchange");
+        assert_fires(VECTORIZE, OptLevel::O3, "loop-vectorize");
+        assert_fires(VECTORIZE, OptLevel::O3, "loop-reduce");
+    }
+
+    /// Loop passes must not take time growing much faster than the number of
+    /// loops: loop-reduce once took 9 seconds on 40 loops.
+    #[test]
+    fn many_loops_compile_quickly() {
+        let mut source = String::from("int a[100];\nint main() {\n    int s = 0;\n");
+        for k in 0..60 {
+            writeln!(source, "    int i{k} = 0;").unwrap();
+            writeln!(source, "    while (i{k} < 100) {{ a[i{k}] = a[i{k}] + i{k} * {}; s = s + a[i{k}]; i{k} = i{k} + 1; }}", k + 3).unwrap();
+        }
+        source.push_str("    putint(s);\n    return 0;\n}\n");
+        let mut module = build(&source);
+
+        let start = Instant::now();
+        let changes = Changes::default();
+        let changed = changes.changed.clone();
+        let mut pass_manager = PassManager::with_passes(&["loop-reduce"], Target::default()).unwrap();
+        pass_manager.add_instrumentation(changes);
+        pass_manager.run(&mut module);
+        let elapsed = start.elapsed();
+        assert_eq!(changed.take(), ["loop-reduce"]);
+        assert!(elapsed < Duration::from_secs(5), "loop-reduce took {elapsed:?} on 60 loops");
+    }
+
+    #[test]
+    fn aliases_name_passes() {
+        let pass_manager = PassManager::with_passes(&["tailcallelim", "gvn", "simplifycfg", "dce"], Target::default()).unwrap();
+        assert_eq!(pass_manager.pass_names(), ["tail-call-elim", "gvn", "simplify-cfg", "dce"]);
+        // no pass promotes locals to registers as LLVM's does
+        assert!(PassManager::with_passes(&["mem2reg"], Target::default()).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::analysis::{
+    dominance::{DomTree, Dominators, PostDominators},
+    loops::{LoopInfo, Loops},
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{BinaryInstOp, InstKind},
+        value::Operand,
+    },
+};
+use crate::compiler::pass::{gvn::Expr, FuncPass};
+
+/// Partial redundancy elimination: computes a pure instruction once, ahead
+/// of paths that compute it only on some of the ways through them, so that
+/// the copies of it on those paths go away:
+///
+/// ```text
+/// head:  br %c, %then, %join
+/// then:  %t = mul %x, %y
+///        br %join
+/// join:  %u = mul %x, %y
+/// ```
+///
+/// `%u` is redundant when coming from `then` only, which [`Gvn`](super::gvn::Gvn)
+/// cannot remove. Moving it to the end of `head` adds it on the path that
+/// lacked it and makes `%t` fully redundant.
+///
+/// Without phis to merge the copies computed on each path, a computation
+/// is moved up the dominator tree instead, as far as its operands are
+/// available and as long as every path from there goes through a copy of
+/// it, so that it runs on no path that did not compute it already. The
+/// copies below are then redundant. It is moved when that removes another
+/// copy, as when both sides of a branch compute it, or takes it out of a
+/// loop, and never into one. Divisions that may trap are left alone, and so are loads, which
+/// [`LoadStoreForward`](super::load_store_forward::LoadStoreForward) deals
+/// with.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pre;
+
+impl FuncPass for Pre {
+    fn name(&self) -> &'static str {
+        "pre"
+    }
+
+    fn run(&mut self, func_id: FuncId, func: &mut IrFunc, analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let dom_tree = analyses.get::<Dominators>(func_id, func);
+        let loop_info = analyses.get::<Loops>(func_id, func);
+        let ctx = Context { dom_tree: &dom_tree, loop_info: &loop_info };
+
+        // copies found redundant use the moved instruction, which may make
+        // expressions on them match, so the pass goes on until nothing moves
+        let mut changed = false;
+        while ctx.eliminate(func) {
+            changed = true;
+        }
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+    }
+}
+
+struct Context<'a> {
+    dom_tree: &'a DomTree,
+    loop_info: &'a LoopInfo,
+}
+
+impl Context<'_> {
+    /// Moves the instructions that pay off, returning whether any did.
+    fn eliminate(&self, func: &mut IrFunc) -> bool {
+        // the instructions computing each expression, in reverse postorder
+        let mut exprs: Vec<Expr> = vec![];
+        let mut copies: HashMap<Expr, Vec<InstId>> = HashMap::new();
+        for bb in func.reverse_postorder() {
+            for (inst_id, inst) in func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None) {
+                let Some(expr) = Expr::of_pure(&inst.kind).filter(|_| can_speculate(&inst.kind)) else {
+                    continue;
+                };
+                let expr = match expr.swapped() {
+                    Some(swapped) if !copies.contains_key(&expr) && copies.contains_key(&swapped) => swapped,
+                    _ => expr,
+                };
+                if !copies.contains_key(&expr) {
+                    exprs.push(expr.clone());
+                }
+                copies.entry(expr).or_default().push(inst_id);
+            }
+        }
+
+        let mut changed = false;
+        for expr in exprs {
+            let all = copies.remove(&expr).unwrap_or_default();
+            let copy_bbs: HashSet<_> = all.iter().map(|&x| func.inst_arena[x].bb).collect();
+            let anticipated = anticipated(func, &copy_bbs);
+            for &inst_id in &all {
+                // copies found redundant are gone
+                let Some(bb) = func.get_inst(inst_id).map(|x| x.bb) else {
+                    continue;
+                };
+                let Some(target) = self.hoist_target(func, inst_id, &anticipated) else {
+                    continue;
+                };
+                let others: Vec<_> = all.iter()
+                    .copied()
+                    .filter(|&x| x != inst_id && func.get_inst(x).is_some_and(|x| self.dom_tree.dominates(target, x.bb)))
+                    .collect();
+                // a copy in the target block itself already makes this one
+                // fully redundant, which is for GVN to remove
+                if others.iter().any(|&x| func.inst_arena[x].bb == target) {
+                    continue;
+                }
+                let redundant = others.iter().any(|&x| !self.dom_tree.dominates(bb, func.inst_arena[x].bb));
+                if !redundant && self.loop_info.depth(target) == self.loop_info.depth(bb) {
+                    continue;
+                }
+                let at = func.terminator(target).unwrap();
+                func.move_inst_before(inst_id, at);
+                for copy in others {
+                    func.replace_all_uses_with(&Operand::Inst(copy), &Operand::Inst(inst_id));
+                    func.remove_inst(copy);
+                }
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// The block highest up the dominator tree from that of `inst_id` at
+    /// the end of which it can be computed instead: its operands are
+    /// available there, it is in `anticipated`, and it is in no loop the
+    /// instruction is not.
+    fn hoist_target(&self, func: &IrFunc, inst_id: InstId, anticipated: &HashSet<BBId>) -> Option<BBId> {
+        let bb = func.inst_arena[inst_id].bb;
+        let operands = func.inst_arena[inst_id].kind.operands();
+        let mut target = None;
+        let mut cur = bb;
+        while let Some(idom) = self.dom_tree.idom(cur) {
+            let available = operands.iter()
+                .all(|x| x.as_inst().is_none_or(|&x| self.dom_tree.dominates(func.inst_arena[x].bb, idom)));
+            let in_loop = self.loop_info.loop_of(idom).is_some_and(|x| !self.loop_info.get(x).contains(bb));
+            if !available || in_loop || !anticipated.contains(&idom) {
+                break;
+            }
+            target = Some(idom);
+            cur = idom;
+        }
+        target
+    }
+}
+
+/// The blocks every path from the end of which goes through one of
+/// `copy_bbs` before returning, so that an expression computed in those can
+/// be computed there without running on a path it did not.
+fn anticipated(func: &IrFunc, copy_bbs: &HashSet<BBId>) -> HashSet<BBId> {
+    let order = func.postorder();
+    // from every block down, as a path going round a loop forever never
+    // reaches a return either
+    let mut ant_in: HashSet<_> = order.iter().copied().collect();
+    let ant_out = |ant_in: &HashSet<BBId>, bb: BBId| {
+        let succs = func.succs(bb);
+        !succs.is_empty() && succs.iter().all(|x| ant_in.contains(x))
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &bb in &order {
+            if ant_in.contains(&bb) && !copy_bbs.contains(&bb) && !ant_out(&ant_in, bb) {
+                ant_in.remove(&bb);
+                changed = true;
+            }
+        }
+    }
+    order.into_iter().filter(|&x| ant_out(&ant_in, x)).collect()
+}
+
+/// Whether computing `kind` on a path that did not cannot trap.
+fn can_speculate(kind: &InstKind) -> bool {
+    match kind {
+        InstKind::Binary(binary) if matches!(binary.op, BinaryInstOp::Div | BinaryInstOp::Mod) => {
+            matches!(binary.right, Operand::Const(Constant::Int(x)) if x != 0 && x != -1)
+        }
+        _ => true,
+    }
+}
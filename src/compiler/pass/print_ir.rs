@@ -0,0 +1,86 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::value::module::Module;
+use crate::compiler::pass::PassInstrumentation;
+
+/// Dumps the IR before or after the passes named, `all` naming every pass,
+/// to bisect the pipeline for the pass breaking a program.
+///
+/// Dumps go to stderr under a header naming the pass, or each to a file of
+/// its own in a directory, numbered in the order they are made, as
+/// `003-after-gvn.ir`.
+#[derive(Debug, Clone, Default)]
+pub struct PrintIr {
+    before: Vec<String>,
+    after: Vec<String>,
+    /// The functions dumped, the whole module if empty.
+    funcs: Vec<String>,
+    dir: Option<PathBuf>,
+    /// Dumps made so far.
+    count: usize,
+}
+
+impl PrintIr {
+    #[must_use] pub fn new(before: Vec<String>, after: Vec<String>) -> PrintIr {
+        PrintIr { before, after, ..PrintIr::default() }
+    }
+
+    /// Dumps the functions named `funcs` alone, rather than the module.
+    #[must_use] pub fn with_funcs(mut self, funcs: Vec<String>) -> PrintIr {
+        self.funcs = funcs;
+        self
+    }
+
+    /// Writes the dumps to files in `dir`, which must exist, rather than to
+    /// stderr.
+    #[must_use] pub fn with_dir(mut self, dir: PathBuf) -> PrintIr {
+        self.dir = Some(dir);
+        self
+    }
+
+    fn dump(&mut self, when: &str, pass: &str, module: &Module) {
+        let ir = if self.funcs.is_empty() {
+            module.to_string()
+        } else {
+            let mut ir = String::new();
+            for (_, func) in module.func_arena.items_iter(module.first_func, None) {
+                if !func.is_builtin && self.funcs.contains(&func.name) {
+                    writeln!(ir, "{}", func.display(module)).unwrap();
+                }
+            }
+            ir
+        };
+        self.count += 1;
+        match &self.dir {
+            None => eprint!("; *** IR dump {when} {pass} ***\n{ir}"),
+            Some(dir) => {
+                let path = dir.join(format!("{:03}-{when}-{pass}.ir", self.count));
+                if let Err(e) = fs::write(&path, ir) {
+                    eprintln!("warning: could not write `{}`: {e}", path.display());
+                }
+            }
+        }
+    }
+}
+
+impl PassInstrumentation for PrintIr {
+    fn before_pass(&mut self, pass: &str, module: &Module) {
+        if selects(&self.before, pass) {
+            self.dump("before", pass, module);
+        }
+    }
+
+    fn after_pass(&mut self, pass: &str, module: &Module) {
+        if selects(&self.after, pass) {
+            self.dump("after", pass, module);
+        }
+    }
+}
+
+/// Whether `names` names `pass`.
+fn selects(names: &[String], pass: &str) -> bool {
+    names.iter().any(|x| x == "all" || x == pass)
+}
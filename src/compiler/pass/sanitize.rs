@@ -0,0 +1,270 @@
+use crate::compiler::analysis::{AnalysisManager, PreservedAnalyses};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, InstId},
+    value::{
+        func::IrFunc,
+        constant::Constant,
+        inst::{Binary, BinaryInstOp, Br, Call, Cast, CastOp, InstKind, GEP},
+        module::Module,
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::ModulePass;
+
+/// The runtime library function the checks call when they fail, with the
+/// [`Check`] that failed and the line and column of the source, from 1. It
+/// reports the failure and aborts the program.
+pub const TRAP_HANDLER: &str = "__racoon_trap";
+
+/// What a failed runtime check caught, passed to [`TRAP_HANDLER`] as its
+/// number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Overflow = 0,
+    DivisionByZero = 1,
+    OutOfBounds = 2,
+}
+
+impl Check {
+    /// The check numbered `code`.
+    #[must_use] pub fn from_code(code: i32) -> Option<Check> {
+        match code {
+            0 => Some(Check::Overflow),
+            1 => Some(Check::DivisionByZero),
+            2 => Some(Check::OutOfBounds),
+            _ => None,
+        }
+    }
+
+    /// What went wrong, as the runtime reports it.
+    #[must_use] pub fn message(self) -> &'static str {
+        match self {
+            Check::Overflow => "signed integer overflow",
+            Check::DivisionByZero => "division by zero",
+            Check::OutOfBounds => "index out of bounds",
+        }
+    }
+}
+
+/// Checks each `i32` addition, subtraction, multiplication, division and
+/// remainder for overflowing, and divisions and remainders for dividing by
+/// zero, calling [`TRAP_HANDLER`] with where in the source the operation is
+/// if it does, to find the cause of wrong answers that wrapping arithmetic
+/// hides.
+///
+/// Sums, differences and products are computed again in 64 bits, the
+/// operation overflowing if the high word is not the sign of the low one:
+///
+/// ```text
+/// %x = add i32 %a, %b
+/// ```
+///
+/// becomes
+///
+/// ```text
+///   %a.w = sext i32 %a to i64
+///   %b.w = sext i32 %b to i64
+///   %x.w = add i64 %a.w, %b.w
+///   %lo = trunc i64 %x.w to i32
+///   %x.hi = ashr i64 %x.w, 32
+///   %hi = trunc i64 %x.hi to i32
+///   %sign = ashr i32 %lo, 31
+///   %bad = icmp ne i32 %hi, %sign
+///   br i1 %bad, label %trap, label %ok
+/// trap:
+///   call void @__racoon_trap(i32 0, i32 3, i32 9)
+///   br label %ok
+/// ok:
+///   %x = add i32 %a, %b
+/// ```
+///
+/// The handler does not return, but the IR has no way to say so. The pass
+/// is meant to run first, on operations that still have their source
+/// locations.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SanitizeOverflow;
+
+impl ModulePass for SanitizeOverflow {
+    fn name(&self) -> &'static str {
+        "sanitize-overflow"
+    }
+
+    fn run(&mut self, module: &mut Module, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let handler = trap_handler(module);
+        let mut changed = false;
+        for func_id in defined_funcs(module) {
+            let func = &mut module.func_arena[func_id];
+            let checked: Vec<_> = func.bb_ids()
+                .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None))
+                .filter_map(|(inst_id, inst)| match &inst.kind {
+                    InstKind::Binary(binary) if inst.ty == IrTy::int() => Some((inst_id, binary.clone())),
+                    _ => None,
+                })
+                .collect();
+            for (inst_id, binary) in checked {
+                changed |= check_binary(func, handler, inst_id, &binary);
+            }
+        }
+        if changed { PreservedAnalyses::none() } else { PreservedAnalyses::all() }
+    }
+}
+
+/// Inserts the checks of `binary`, the operation `inst_id`, before it.
+/// Returns whether it is an operation that may overflow.
+fn check_binary(func: &mut IrFunc, handler: FuncId, inst_id: InstId, binary: &Binary) -> bool {
+    let build = |func: &mut IrFunc, kind, ty| func.build_inst_before_cur(kind, ty, inst_id);
+    let compare = |func: &mut IrFunc, op, left: &Operand, right: i32| {
+        let kind = InstKind::Binary(Binary { op, left: left.clone(), right: right.into() });
+        Operand::from(build(func, kind, IrTy::bool()))
+    };
+    match binary.op {
+        BinaryInstOp::Add | BinaryInstOp::Sub | BinaryInstOp::Mul => {
+            let widen = |func: &mut IrFunc, val: &Operand| {
+                let cast = Cast { op: CastOp::SExt, ori_val: val.clone(), target_ty: IrTy::Int(64) };
+                Operand::from(build(func, InstKind::Cast(cast), IrTy::Int(64)))
+            };
+            let narrow = |func: &mut IrFunc, val: Operand| {
+                let cast = Cast { op: CastOp::Trunc, ori_val: val, target_ty: IrTy::int() };
+                Operand::from(build(func, InstKind::Cast(cast), IrTy::int()))
+            };
+            let (left, right) = (widen(func, &binary.left), widen(func, &binary.right));
+            let wide = build(func, InstKind::Binary(Binary { op: binary.op, left, right }), IrTy::Int(64));
+            let lo = narrow(func, wide.into());
+            let high = Binary { op: BinaryInstOp::AShr, left: wide.into(), right: 32.into() };
+            let high = build(func, InstKind::Binary(high), IrTy::Int(64));
+            let hi = narrow(func, high.into());
+            let sign = Binary { op: BinaryInstOp::AShr, left: lo, right: 31.into() };
+            let sign = build(func, InstKind::Binary(sign), IrTy::int());
+            let bad = Binary { op: BinaryInstOp::Ne, left: hi, right: sign.into() };
+            let bad = build(func, InstKind::Binary(bad), IrTy::bool());
+            insert_trap(func, handler, inst_id, bad.into(), Check::Overflow);
+            true
+        }
+        BinaryInstOp::Div | BinaryInstOp::Mod => {
+            let zero = compare(func, BinaryInstOp::Eq, &binary.right, 0);
+            insert_trap(func, handler, inst_id, zero, Check::DivisionByZero);
+            // `i32::MIN / -1`, whose quotient does not fit
+            let min = compare(func, BinaryInstOp::Eq, &binary.left, i32::MIN);
+            let minus_one = compare(func, BinaryInstOp::Eq, &binary.right, -1);
+            let bad = Binary { op: BinaryInstOp::And, left: min, right: minus_one };
+            let bad = build(func, InstKind::Binary(bad), IrTy::bool());
+            insert_trap(func, handler, inst_id, bad.into(), Check::Overflow);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Checks each index of each `getelementptr` into an array against its
+/// length, calling [`TRAP_HANDLER`] with where in the source the access is
+/// if it is out of bounds, so that writing past the end of an array stops
+/// the program there instead of overwriting what lies next to it:
+///
+/// ```text
+/// %p = getelementptr [4 x [8 x i32]], [4 x [8 x i32]]* %a, i32 0, i32 %i, i32 %j
+/// ```
+///
+/// gets `%i` checked against 4 and `%j` against 8, each with a branch to a
+/// call of the handler on `%i < 0 || %i >= 4`. The first index steps over
+/// whole objects, of a length unknown for pointers passed as parameters, and
+/// is left alone, as are constant indices known to be in bounds.
+///
+/// Like [`SanitizeOverflow`], the pass is meant to run first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SanitizeBounds;
+
+impl ModulePass for SanitizeBounds {
+    fn name(&self) -> &'static str {
+        "sanitize-bounds"
+    }
+
+    fn run(&mut self, module: &mut Module, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let handler = trap_handler(module);
+        let mut changed = false;
+        for func_id in defined_funcs(module) {
+            let func = &module.func_arena[func_id];
+            let checked: Vec<_> = func.bb_ids()
+                .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None))
+                .flat_map(|(inst_id, inst)| match &inst.kind {
+                    InstKind::GEP(gep) => bounds(module, func, gep).into_iter().map(|x| (inst_id, x)).collect(),
+                    _ => vec![],
+                })
+                .collect();
+            let func = &mut module.func_arena[func_id];
+            for (inst_id, (idx, len)) in checked {
+                let build = |func: &mut IrFunc, kind| Operand::from(func.build_inst_before_cur(kind, IrTy::bool(), inst_id));
+                let below = build(func, InstKind::Binary(Binary { op: BinaryInstOp::Lt, left: idx.clone(), right: 0.into() }));
+                let above = build(func, InstKind::Binary(Binary { op: BinaryInstOp::Ge, left: idx, right: len.into() }));
+                let bad = build(func, InstKind::Binary(Binary { op: BinaryInstOp::Or, left: below, right: above }));
+                insert_trap(func, handler, inst_id, bad, Check::OutOfBounds);
+                changed = true;
+            }
+        }
+        if changed { PreservedAnalyses::none() } else { PreservedAnalyses::all() }
+    }
+}
+
+/// The indices of `gep` into arrays that may be out of bounds, each with the
+/// length of its array.
+fn bounds(module: &Module, func: &IrFunc, gep: &GEP) -> Vec<(Operand, i32)> {
+    let Some(mut ty) = IrTy::deptr_of(&module.operand_ty(func, &gep.ptr)) else {
+        return vec![];
+    };
+    let mut bounds = vec![];
+    for idx in gep.indices.iter().skip(1) {
+        let Some((len, elem_ty)) = ty.as_array() else {
+            break;
+        };
+        let len = i32::try_from(*len).unwrap();
+        let in_bounds = matches!(idx, Operand::Const(Constant::Int(x)) if (0..len).contains(x));
+        if !in_bounds {
+            bounds.push((idx.clone(), len));
+        }
+        ty = elem_ty.as_ref().clone();
+    }
+    bounds
+}
+
+/// Calls `handler` before `inst_id` if `bad` holds, with `check` and where
+/// in the source `inst_id` is. The block is split before `inst_id`, which
+/// goes on in a block of its own whether or not the handler is called.
+pub(super) fn insert_trap(func: &mut IrFunc, handler: FuncId, inst_id: InstId, bad: Operand, check: Check) {
+    let loc = func.inst_arena[inst_id].loc;
+    let (line, col) = loc.map_or((0, 0), |x| (x.span.start.lineno + 1, x.span.start.colno + 1));
+    let args = vec![(check as i32).into(), i32::try_from(line).unwrap().into(), i32::try_from(col).unwrap().into()];
+
+    let prev = func.inst_arena[inst_id].prev.expect("a check is built before the instruction");
+    let bb = func.inst_arena[inst_id].bb;
+    let ok = func.split_bb(prev);
+    let trap = func.build_bb_after_cur(bb);
+    func.bb_arena[trap].is_cold = true;
+    func.build_inst_at_end(InstKind::Br(Br::Br { cond: bad, true_bb: trap, false_bb: ok }), IrTy::Void, bb);
+    let call = func.build_inst_at_end(InstKind::Call(Call { func_id: handler, args, is_tail: false }), IrTy::Void, trap);
+    func.inst_arena[call].loc = loc;
+    func.build_inst_at_end(InstKind::Br(Br::Jump { nxt_bb: ok }), IrTy::Void, trap);
+}
+
+/// The declaration of [`TRAP_HANDLER`], added to `module` if it is not there
+/// yet.
+pub(super) fn trap_handler(module: &mut Module) -> FuncId {
+    let declared = module.func_arena.items_iter(module.first_func, None)
+        .find(|(_, func)| func.is_builtin && func.name == TRAP_HANDLER);
+    if let Some((func_id, _)) = declared {
+        return func_id;
+    }
+    let mut handler = IrFunc::new(TRAP_HANDLER, IrTy::Void, true);
+    for _ in 0..3 {
+        handler.build_func_param(IrTy::int());
+    }
+    module.build_func(handler)
+}
+
+/// The functions `module` defines.
+pub(super) fn defined_funcs(module: &Module) -> Vec<FuncId> {
+    module.func_arena.items_iter(module.first_func, None)
+        .filter(|(_, func)| !func.is_builtin && func.first_block.is_some())
+        .map(|(func_id, _)| func_id)
+        .collect()
+}
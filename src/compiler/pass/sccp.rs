@@ -0,0 +1,297 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::analysis::{AnalysisManager, PreservedAnalyses};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Br, InstKind},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Sparse conditional constant propagation: finds the values that are
+/// constant on every path that can run, assuming branches only go where
+/// their conditions allow, so that constants and untaken branches are
+/// found together.
+///
+/// There are no phis. Scalar locals only ever loaded and stored stand in for
+/// them, their value at the start of a block being the meet of their values
+/// at the end of the predecessors that can reach it.
+///
+/// Loads and computations found constant are replaced, branches that can
+/// only go one way become jumps, and blocks that cannot run are deleted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sccp;
+
+impl FuncPass for Sccp {
+    fn name(&self) -> &'static str {
+        "sccp"
+    }
+
+    fn run(&mut self, _func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        if func.first_block.is_none() {
+            return PreservedAnalyses::all();
+        }
+        let mut solver = Solver::new(func);
+        solver.solve();
+        let Solution { values, edges } = solver.into_solution();
+        if rewrite(func, &values, &edges) {
+            PreservedAnalyses::none()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+    /// No value seen yet.
+    Top,
+    Const(i32),
+    Overdefined,
+}
+
+impl Lattice {
+    fn meet(self, other: Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Top, x) | (x, Lattice::Top) => x,
+            (Lattice::Const(x), Lattice::Const(y)) if x == y => Lattice::Const(x),
+            _ => Lattice::Overdefined,
+        }
+    }
+}
+
+/// Values of the promoted locals, those not listed being [`Lattice::Top`].
+type State = HashMap<InstId, Lattice>;
+
+struct Solver<'a> {
+    func: &'a IrFunc,
+    /// Allocas of scalars only loaded from and stored to.
+    promoted: HashSet<InstId>,
+    values: HashMap<InstId, Lattice>,
+    executable: HashSet<BBId>,
+    edges: HashSet<(BBId, BBId)>,
+    out_states: HashMap<BBId, State>,
+}
+
+struct Solution {
+    values: HashMap<InstId, Lattice>,
+    /// Edges that can be taken.
+    edges: HashSet<(BBId, BBId)>,
+}
+
+impl<'a> Solver<'a> {
+    fn new(func: &'a IrFunc) -> Solver<'a> {
+        let promoted = func.inst_arena.iter()
+            .filter(|(_, inst)| matches!(&inst.kind, InstKind::Alloca(x) if matches!(x.alloca_ty, IrTy::Int(_))))
+            .map(|(inst_id, _)| inst_id)
+            .filter(|&inst_id| is_promotable(func, inst_id))
+            .collect();
+        Solver {
+            func,
+            promoted,
+            values: HashMap::new(),
+            executable: func.first_block.into_iter().collect(),
+            edges: HashSet::new(),
+            out_states: HashMap::new(),
+        }
+    }
+
+    /// Evaluates the blocks found executable in reverse postorder until
+    /// nothing changes. Values only ever move down the lattice, so this
+    /// ends.
+    fn solve(&mut self) {
+        let order = self.func.reverse_postorder();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in &order {
+                if self.executable.contains(&bb) {
+                    changed |= self.visit(bb);
+                }
+            }
+        }
+    }
+
+    fn into_solution(self) -> Solution {
+        Solution { values: self.values, edges: self.edges }
+    }
+
+    /// Evaluates `bb`, returning whether anything changed.
+    fn visit(&mut self, bb: BBId) -> bool {
+        let func = self.func;
+        let mut state = self.in_state(bb);
+        let mut changed = false;
+        for (inst_id, inst) in func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None) {
+            let val = match &inst.kind {
+                // constants are 32 bits wide
+                InstKind::Binary(_) | InstKind::Cast(_) if matches!(inst.ty, IrTy::Int(bits) if bits > 32) => Lattice::Overdefined,
+                InstKind::Store(store) => {
+                    if let Operand::Inst(addr) = store.addr {
+                        if self.promoted.contains(&addr) {
+                            state.insert(addr, self.operand(&store.data));
+                        }
+                    }
+                    continue;
+                }
+                InstKind::Br(br) => {
+                    for succ in self.feasible_succs(br) {
+                        changed |= self.edges.insert((bb, succ));
+                        changed |= self.executable.insert(succ);
+                    }
+                    continue;
+                }
+                InstKind::Load(load) => match load.addr {
+                    Operand::Inst(addr) if self.promoted.contains(&addr) => state.get(&addr).copied().unwrap_or(Lattice::Top),
+                    _ => Lattice::Overdefined,
+                },
+                InstKind::Binary(binary) => match (self.operand(&binary.left), self.operand(&binary.right)) {
+                    (Lattice::Const(left), Lattice::Const(right)) => binary.op.fold(left, right).map_or(Lattice::Overdefined, Lattice::Const),
+                    (Lattice::Overdefined, _) | (_, Lattice::Overdefined) => Lattice::Overdefined,
+                    _ => Lattice::Top,
+                },
+                InstKind::Cast(cast) => match (self.operand(&cast.ori_val), int_bits(func, &cast.ori_val), &cast.target_ty) {
+                    (Lattice::Const(x), Some(from), IrTy::Int(to)) => Lattice::Const(cast.op.fold(x, from, *to)),
+                    (Lattice::Top, _, _) => Lattice::Top,
+                    _ => Lattice::Overdefined,
+                },
+                InstKind::Select(select) => match self.operand(&select.cond) {
+                    Lattice::Const(0) => self.operand(&select.false_val),
+                    Lattice::Const(_) => self.operand(&select.true_val),
+                    Lattice::Top => Lattice::Top,
+                    Lattice::Overdefined => self.operand(&select.true_val).meet(self.operand(&select.false_val)),
+                },
+                _ => Lattice::Overdefined,
+            };
+            let old = self.values.get(&inst_id).copied().unwrap_or(Lattice::Top);
+            let new = old.meet(val);
+            if new != old {
+                self.values.insert(inst_id, new);
+                changed = true;
+            }
+        }
+        if self.out_states.get(&bb) != Some(&state) {
+            self.out_states.insert(bb, state);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Values of the promoted locals on entry to `bb`, met over the edges
+    /// into it that can be taken. Locals start out uninitialized, which may
+    /// be taken to hold anything.
+    fn in_state(&self, bb: BBId) -> State {
+        let mut state = State::new();
+        let outs = self.edges.iter()
+            .filter(|&&(_, to)| to == bb)
+            .filter_map(|(from, _)| self.out_states.get(from));
+        for out in outs {
+            for &alloca in &self.promoted {
+                let val = out.get(&alloca).copied().unwrap_or(Lattice::Top);
+                let met = state.get(&alloca).copied().unwrap_or(Lattice::Top).meet(val);
+                state.insert(alloca, met);
+            }
+        }
+        state
+    }
+
+    /// Targets of `br` that can be taken. A condition with no value yet may
+    /// go either way.
+    fn feasible_succs(&self, br: &Br) -> Vec<BBId> {
+        match br {
+            Br::Jump { nxt_bb } => vec![*nxt_bb],
+            Br::Br { cond, true_bb, false_bb } => match self.operand(cond) {
+                Lattice::Const(0) => vec![*false_bb],
+                Lattice::Const(_) => vec![*true_bb],
+                _ => vec![*true_bb, *false_bb],
+            },
+            Br::Switch { cond, cases, default } => match self.operand(cond) {
+                Lattice::Const(x) => vec![cases.iter().find(|case| case.0 == x).map_or(*default, |case| case.1)],
+                _ => br.targets(),
+            },
+        }
+    }
+
+    fn operand(&self, operand: &Operand) -> Lattice {
+        match operand {
+            Operand::Const(Constant::Int(x)) => Lattice::Const(*x),
+            Operand::Inst(x) => self.values.get(x).copied().unwrap_or(Lattice::Top),
+            _ => Lattice::Overdefined,
+        }
+    }
+}
+
+/// Whether `alloca` is only loaded from and stored to, so that its value
+/// can be followed like that of a register.
+fn is_promotable(func: &IrFunc, alloca: InstId) -> bool {
+    let operand = Operand::Inst(alloca);
+    func.users(&operand).into_iter().all(|user| match &func.inst_arena[user].kind {
+        InstKind::Load(_) => true,
+        InstKind::Store(store) => store.addr == operand && store.data != operand,
+        _ => false,
+    })
+}
+
+fn int_bits(func: &IrFunc, operand: &Operand) -> Option<usize> {
+    match operand {
+        Operand::Const(Constant::Int(_)) => Some(32),
+        Operand::Inst(x) => func.inst_arena[*x].ty.as_int().copied(),
+        _ => None,
+    }
+}
+
+/// Applies the solution, returning whether anything changed.
+fn rewrite(func: &mut IrFunc, values: &HashMap<InstId, Lattice>, edges: &HashSet<(BBId, BBId)>) -> bool {
+    let mut changed = false;
+
+    // replace what can be by its value, comparisons only reach branches
+    let folded: Vec<_> = values.iter()
+        .filter_map(|(&inst_id, &val)| match val {
+            Lattice::Const(x) => Some((inst_id, x)),
+            _ => None,
+        })
+        .filter(|&(inst_id, _)| func.inst_arena[inst_id].ty == IrTy::Int(32))
+        .collect();
+    for (inst_id, x) in folded {
+        func.replace_all_uses_with(&Operand::Inst(inst_id), &Operand::Const(Constant::Int(x)));
+        func.remove_inst(inst_id);
+        changed = true;
+    }
+
+    for bb in func.bb_ids().collect::<Vec<_>>() {
+        let Some(terminator) = func.terminator(bb) else {
+            continue;
+        };
+        if !matches!(func.inst_arena[terminator].kind, InstKind::Br(Br::Br { .. } | Br::Switch { .. })) {
+            continue;
+        }
+        let taken: Vec<_> = func.succs(bb).into_iter().filter(|&succ| edges.contains(&(bb, succ))).collect();
+        if let [nxt_bb] = taken[..] {
+            func.set_inst_kind(terminator, InstKind::Br(Br::Jump { nxt_bb }));
+            changed = true;
+        }
+    }
+    changed |= func.remove_unreachable_bbs() > 0;
+
+    // comparisons whose branches were all folded
+    loop {
+        let dead: Vec<_> = values.iter()
+            .filter(|&(_, &val)| matches!(val, Lattice::Const(_)))
+            .map(|(&inst_id, _)| inst_id)
+            .filter(|&inst_id| func.inst_arena.contains_key(inst_id) && !func.has_uses(&Operand::Inst(inst_id)))
+            .filter(|&inst_id| matches!(func.inst_arena[inst_id].kind, InstKind::Binary(_) | InstKind::Cast(_) | InstKind::Select(_)))
+            .collect();
+        if dead.is_empty() {
+            return changed;
+        }
+        for inst_id in dead {
+            func.remove_inst(inst_id);
+        }
+        changed = true;
+    }
+}
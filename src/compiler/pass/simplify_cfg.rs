@@ -0,0 +1,108 @@
+use crate::compiler::analysis::{AnalysisManager, PreservedAnalyses};
+use crate::compiler::ir::{
+    arena::{BBId, FuncId},
+    value::{func::IrFunc, inst::{Br, InstKind}},
+};
+use crate::compiler::pass::FuncPass;
+
+/// Tidies the control flow graph until nothing changes:
+///
+/// - deletes unreachable blocks, such as those the builder leaves after a
+///   `return` or `break`;
+/// - sends branches through blocks that only jump elsewhere straight to
+///   where they go, and turns branches with a single target into jumps;
+/// - merges a block into its predecessor when each is the other's only
+///   neighbour.
+///
+/// Branches that only choose what to store are left to
+/// [`IfConvert`](super::if_convert::IfConvert).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimplifyCfg;
+
+impl FuncPass for SimplifyCfg {
+    fn name(&self) -> &'static str {
+        "simplify-cfg"
+    }
+
+    fn run(&mut self, _func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let mut changed = false;
+        loop {
+            let mut changed_now = func.remove_unreachable_bbs() > 0;
+            for bb in func.bb_ids().collect::<Vec<_>>() {
+                if func.get_bb(bb).is_none() {
+                    continue;
+                }
+                changed_now |= skip_forwarder(func, bb)
+                    || fold_single_target(func, bb)
+                    || merge_into_pred(func, bb);
+            }
+            if !changed_now {
+                break;
+            }
+            changed = true;
+        }
+
+        if changed { PreservedAnalyses::none() } else { PreservedAnalyses::all() }
+    }
+}
+
+/// Where `bb` jumps to, if it does nothing else.
+fn forwarded_to(func: &IrFunc, bb: BBId) -> Option<BBId> {
+    let block = func.get_bb(bb)?;
+    if block.insts_head != block.insts_tail {
+        return None;
+    }
+    match func.inst_arena[block.insts_head?].kind {
+        InstKind::Br(Br::Jump { nxt_bb }) if nxt_bb != bb => Some(nxt_bb),
+        _ => None,
+    }
+}
+
+/// Sends the predecessors of `bb` straight to where it jumps, if it does
+/// nothing else, leaving it unreachable.
+fn skip_forwarder(func: &mut IrFunc, bb: BBId) -> bool {
+    let Some(target) = forwarded_to(func, bb) else {
+        return false;
+    };
+    let preds = func.preds(bb);
+    if func.first_block == Some(bb) || preds.is_empty() {
+        return false;
+    }
+    for pred in preds {
+        func.replace_succ(pred, bb, target);
+    }
+    true
+}
+
+/// Turns a branch or switch ending `bb` whose targets are all the same block
+/// into a jump.
+fn fold_single_target(func: &mut IrFunc, bb: BBId) -> bool {
+    let Some(terminator) = func.terminator(bb) else {
+        return false;
+    };
+    if !matches!(func.inst_arena[terminator].kind, InstKind::Br(Br::Br { .. } | Br::Switch { .. })) {
+        return false;
+    }
+    let [nxt_bb] = func.succs(bb)[..] else {
+        return false;
+    };
+    func.set_inst_kind(terminator, InstKind::Br(Br::Jump { nxt_bb }));
+    true
+}
+
+/// Merges `bb` into its predecessor if that only jumps to it.
+fn merge_into_pred(func: &mut IrFunc, bb: BBId) -> bool {
+    if func.first_block == Some(bb) {
+        return false;
+    }
+    let [pred] = func.preds(bb)[..] else {
+        return false;
+    };
+    let jumps_to_bb = func.terminator(pred)
+        .is_some_and(|x| matches!(func.inst_arena[x].kind, InstKind::Br(Br::Jump { nxt_bb }) if nxt_bb == bb));
+    if pred == bb || !jumps_to_bb {
+        return false;
+    }
+    func.merge_bbs(pred, bb);
+    true
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::ir::{
+    arena::{FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Alloca, InstKind, Load, Store},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Largest array, in words, split into scalars.
+const MAX_WORDS: usize = 32;
+
+/// Scalar replacement of aggregates: splits a small local array only ever
+/// indexed by constants into one alloca per element used, so that its
+/// elements are followed like any other scalar local, and the address
+/// computations go away.
+///
+/// The array may be cleared with `memset`, which becomes a store to each
+/// element, but not copied or passed anywhere.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sroa;
+
+impl FuncPass for Sroa {
+    fn name(&self) -> &'static str {
+        "sroa"
+    }
+
+    fn run(&mut self, _func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let arrays: Vec<_> = func.inst_arena.iter()
+            .filter(|(_, inst)| matches!(&inst.kind, InstKind::Alloca(Alloca { alloca_ty: IrTy::Array(..) })))
+            .map(|(inst_id, _)| inst_id)
+            .collect();
+        let mut changed = false;
+        for alloca in arrays {
+            if let Some(accesses) = accesses(func, alloca) {
+                split(func, alloca, &accesses);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+    }
+}
+
+/// Everything done with an array, by instruction.
+#[derive(Debug, Default)]
+struct Accesses {
+    /// Loads and stores of an element, at a word offset.
+    scalars: Vec<(InstId, usize)>,
+    /// `memset`s from a word offset.
+    memsets: Vec<(InstId, usize)>,
+    /// Address computations, each after those it is computed from.
+    geps: Vec<InstId>,
+}
+
+/// What is done with `alloca`, if it can be split.
+fn accesses(func: &IrFunc, alloca: InstId) -> Option<Accesses> {
+    let InstKind::Alloca(Alloca { alloca_ty }) = &func.inst_arena[alloca].kind else {
+        return None;
+    };
+    let words = alloca_ty.size_in_words();
+    if words > MAX_WORDS {
+        return None;
+    }
+
+    let mut accesses = Accesses::default();
+    let mut worklist = vec![(alloca, 0)];
+    while let Some((ptr_id, offset)) = worklist.pop() {
+        let ptr = Operand::Inst(ptr_id);
+        let pointee = IrTy::deptr_of(&func.inst_arena[ptr_id].ty)?;
+        for user in func.users(&ptr) {
+            match &func.inst_arena[user].kind {
+                InstKind::Load(_) if pointee == IrTy::int() && offset < words => accesses.scalars.push((user, offset)),
+                InstKind::Store(store) if store.addr == ptr && store.data != ptr && pointee == IrTy::int() && offset < words => {
+                    accesses.scalars.push((user, offset));
+                }
+                InstKind::MemSet(memset) if offset + memset.len <= words => accesses.memsets.push((user, offset)),
+                InstKind::GEP(gep) if gep.ptr == ptr => {
+                    let offset = gep_offset(&pointee, &gep.indices).and_then(|x| offset.checked_add_signed(x))?;
+                    accesses.geps.push(user);
+                    worklist.push((user, offset));
+                }
+                _ => return None,
+            }
+        }
+    }
+    Some(accesses)
+}
+
+/// Offset in words of `gep ptr, indices` from `ptr`, pointing to
+/// `pointee`, if the indices are constant.
+fn gep_offset(pointee: &IrTy, indices: &[Operand]) -> Option<isize> {
+    let mut ty = pointee;
+    let mut offset = 0_isize;
+    for (pos, idx) in indices.iter().enumerate() {
+        if pos > 0 {
+            ty = ty.as_array()?.1.as_ref();
+        }
+        let Operand::Const(Constant::Int(idx)) = idx else {
+            return None;
+        };
+        let scale = isize::try_from(ty.size_in_words()).ok()?;
+        offset = isize::try_from(*idx).ok()?.checked_mul(scale).and_then(|x| offset.checked_add(x))?;
+    }
+    Some(offset)
+}
+
+/// Replaces `alloca` by one alloca per element accessed.
+fn split(func: &mut IrFunc, alloca: InstId, accesses: &Accesses) {
+    let mut scalars = BTreeMap::new();
+    for &(_, offset) in &accesses.scalars {
+        scalars.entry(offset).or_insert_with(|| {
+            let scalar = InstKind::Alloca(Alloca { alloca_ty: IrTy::int() });
+            Operand::from(func.build_inst_before_cur(scalar, IrTy::ptr_of(&IrTy::int()), alloca))
+        });
+    }
+
+    for &(inst_id, offset) in &accesses.scalars {
+        let addr = scalars[&offset].clone();
+        let kind = match &func.inst_arena[inst_id].kind {
+            InstKind::Load(_) => InstKind::Load(Load { addr }),
+            InstKind::Store(store) => InstKind::Store(Store { addr, data: store.data.clone() }),
+            _ => unreachable!(),
+        };
+        func.set_inst_kind(inst_id, kind);
+    }
+    for &(inst_id, offset) in &accesses.memsets {
+        let InstKind::MemSet(memset) = func.inst_arena[inst_id].kind.clone() else {
+            unreachable!()
+        };
+        let word = Operand::Const(Constant::Int(i32::from_ne_bytes([memset.byte; 4])));
+        for (_, addr) in scalars.range(offset..offset + memset.len) {
+            let store = InstKind::Store(Store { addr: addr.clone(), data: word.clone() });
+            func.build_inst_before_cur(store, IrTy::Void, inst_id);
+        }
+        func.remove_inst(inst_id);
+    }
+    for &gep in accesses.geps.iter().rev() {
+        func.remove_inst(gep);
+    }
+    func.remove_inst(alloca);
+}
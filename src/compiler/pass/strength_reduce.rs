@@ -0,0 +1,226 @@
+use crate::compiler::analysis::{
+    dominance::{Dominators, PostDominators},
+    loops::Loops,
+    AnalysisManager,
+    PreservedAnalyses,
+};
+use crate::compiler::intrusive_linkedlist::IntrusiveLinkedList;
+use crate::compiler::ir::{
+    arena::{FuncId, InstId},
+    value::{
+        constant::Constant,
+        func::IrFunc,
+        inst::{Binary, BinaryInstOp, Cast, CastOp, InstKind},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Lowers multiplications, divisions and remainders by constants into
+/// shifts, additions and multiplications, for targets where division, and
+/// to a lesser extent multiplication, is slow:
+///
+/// - `x * c` becomes one or two shifts, added or subtracted, when `c` has
+///   at most two bits set or is a run of set bits;
+/// - `x / 2^k` and `x % 2^k` become shifts and masks, biased so that
+///   negative `x` still rounds towards zero;
+/// - `x / c` and `x % c` for any other `c` become a multiplication by a
+///   "magic number" keeping the high word, following Hacker's Delight.
+///
+/// Multiplying by 0 or 1 and dividing by 1 or -1 are left to instruction
+/// combining, and dividing by `i32::MIN` or 0 to whatever consumes the IR.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StrengthReduce;
+
+impl FuncPass for StrengthReduce {
+    fn name(&self) -> &'static str {
+        "strength-reduce"
+    }
+
+    fn run(&mut self, _func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let mut worklist: Vec<_> = func.bb_ids()
+            .flat_map(|bb| func.inst_arena.items_iter(func.bb_arena[bb].insts_head, None).map(|(inst_id, _)| inst_id))
+            .collect();
+        worklist.reverse();
+        let mut changed = false;
+        while let Some(inst_id) = worklist.pop() {
+            let Some(lowered) = lower(func, inst_id, &mut worklist) else {
+                continue;
+            };
+            func.replace_all_uses_with(&Operand::Inst(inst_id), &lowered);
+            func.remove_inst(inst_id);
+            changed = true;
+        }
+
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        PreservedAnalyses::none()
+            .preserve::<Dominators>()
+            .preserve::<PostDominators>()
+            .preserve::<Loops>()
+    }
+}
+
+/// Builds instructions before `before`.
+struct Lowering<'a> {
+    func: &'a mut IrFunc,
+    before: InstId,
+}
+
+impl Lowering<'_> {
+    fn binary(&mut self, op: BinaryInstOp, left: Operand, right: Operand) -> Operand {
+        let ty = if let Operand::Inst(x) = &left { self.func.inst_arena[*x].ty.clone() } else { IrTy::int() };
+        self.func.build_inst_before_cur(InstKind::Binary(Binary { op, left, right }), ty, self.before).into()
+    }
+
+    fn binary_imm(&mut self, op: BinaryInstOp, left: Operand, right: i32) -> Operand {
+        self.binary(op, left, Operand::Const(Constant::Int(right)))
+    }
+
+    fn shl(&mut self, x: &Operand, amount: u32) -> Operand {
+        if amount == 0 {
+            return x.clone();
+        }
+        self.binary_imm(BinaryInstOp::Shl, x.clone(), amount.cast_signed())
+    }
+
+    fn cast(&mut self, op: CastOp, val: Operand, to: IrTy) -> Operand {
+        let kind = InstKind::Cast(Cast { op, ori_val: val, target_ty: to.clone() });
+        self.func.build_inst_before_cur(kind, to, self.before).into()
+    }
+
+    fn neg(&mut self, x: Operand) -> Operand {
+        self.binary(BinaryInstOp::Sub, Operand::Const(Constant::Int(0)), x)
+    }
+
+    /// `x * c` as shifts, if `c` is made of at most two of them.
+    fn mul(&mut self, x: &Operand, c: i32) -> Option<Operand> {
+        // the low 32 bits of a product only depend on those of `c`
+        let c = c.cast_unsigned();
+        let low = c & c.wrapping_neg();
+        let rest = c - low;
+        if c.is_power_of_two() {
+            return Some(self.shl(x, c.trailing_zeros()));
+        }
+        if c.count_ones() == 2 {
+            let high = self.shl(x, rest.trailing_zeros());
+            let low = self.shl(x, low.trailing_zeros());
+            return Some(self.binary(BinaryInstOp::Add, high, low));
+        }
+        // a run of set bits is the difference of two powers of two, the
+        // higher one possibly 2^32, which is 0
+        let high = c.wrapping_add(low);
+        if high & high.wrapping_sub(1) != 0 {
+            return None;
+        }
+        let low = self.shl(x, low.trailing_zeros());
+        if high == 0 {
+            return Some(self.neg(low));
+        }
+        let high = self.shl(x, high.trailing_zeros());
+        Some(self.binary(BinaryInstOp::Sub, high, low))
+    }
+
+    /// What to add to `x` before shifting it right by `k` so that the
+    /// shift rounds towards zero: `2^k - 1` if `x` is negative, else 0.
+    fn round_bias(&mut self, x: &Operand, k: u32) -> Operand {
+        let sign = if k == 1 { x.clone() } else { self.binary_imm(BinaryInstOp::AShr, x.clone(), 31) };
+        self.binary_imm(BinaryInstOp::LShr, sign, (32 - k).cast_signed())
+    }
+
+    /// `x / d` for `d` > 1, rounding towards zero.
+    fn div(&mut self, x: &Operand, d: u32) -> Operand {
+        if d.is_power_of_two() {
+            let k = d.trailing_zeros();
+            let bias = self.round_bias(x, k);
+            let biased = self.binary(BinaryInstOp::Add, x.clone(), bias);
+            return self.binary_imm(BinaryInstOp::AShr, biased, k.cast_signed());
+        }
+
+        // the high word of the 64-bit product
+        let (multiplier, shift) = magic(d);
+        let wide = self.cast(CastOp::SExt, x.clone(), IrTy::Int(64));
+        let product = self.binary_imm(BinaryInstOp::Mul, wide, multiplier);
+        let high = self.binary_imm(BinaryInstOp::AShr, product, 32);
+        let mut quotient = self.cast(CastOp::Trunc, high, IrTy::int());
+        if multiplier < 0 {
+            // the multiplier was read as `m - 2^32`
+            quotient = self.binary(BinaryInstOp::Add, quotient, x.clone());
+        }
+        if shift > 0 {
+            quotient = self.binary_imm(BinaryInstOp::AShr, quotient, shift.cast_signed());
+        }
+        // floor to truncation: add 1 for negative `x`
+        let sign = self.binary_imm(BinaryInstOp::LShr, x.clone(), 31);
+        self.binary(BinaryInstOp::Add, quotient, sign)
+    }
+
+    /// `x % d` for `d` > 1, taking the sign of `x`.
+    fn rem(&mut self, x: &Operand, d: u32, worklist: &mut Vec<InstId>) -> Operand {
+        let multiple = if d.is_power_of_two() {
+            let k = d.trailing_zeros();
+            let bias = self.round_bias(x, k);
+            let biased = self.binary(BinaryInstOp::Add, x.clone(), bias);
+            self.binary_imm(BinaryInstOp::And, biased, d.wrapping_neg().cast_signed())
+        } else {
+            let q = self.div(x, d);
+            let multiple = self.binary_imm(BinaryInstOp::Mul, q, d.cast_signed());
+            // the product may be lowered in turn
+            worklist.extend(multiple.as_inst());
+            multiple
+        };
+        self.binary(BinaryInstOp::Sub, x.clone(), multiple)
+    }
+}
+
+/// The lowered value of `inst_id`, built before it, if it is a
+/// multiplication, division or remainder of an `i32` by a constant.
+fn lower(func: &mut IrFunc, inst_id: InstId, worklist: &mut Vec<InstId>) -> Option<Operand> {
+    let inst = func.inst_arena.get(inst_id)?;
+    let InstKind::Binary(binary) = &inst.kind else {
+        return None;
+    };
+    if inst.ty != IrTy::int() {
+        return None;
+    }
+    let (x, c) = match (binary.op, &binary.left, &binary.right) {
+        (BinaryInstOp::Mul, Operand::Const(Constant::Int(c)), x) | (_, x, Operand::Const(Constant::Int(c))) => (x.clone(), *c),
+        _ => return None,
+    };
+    let op = binary.op;
+    if matches!(x, Operand::Const(_)) {
+        return None;
+    }
+    let mut lowering = Lowering { func, before: inst_id };
+    match op {
+        BinaryInstOp::Mul if !matches!(c, 0 | 1) => lowering.mul(&x, c),
+        // truncating division is odd in the divisor
+        BinaryInstOp::Div if c.unsigned_abs() > 1 && c != i32::MIN => {
+            let q = lowering.div(&x, c.unsigned_abs());
+            Some(if c < 0 { lowering.neg(q) } else { q })
+        }
+        // the remainder takes the sign of `x` only
+        BinaryInstOp::Mod if c.unsigned_abs() > 1 && c != i32::MIN => Some(lowering.rem(&x, c.unsigned_abs(), worklist)),
+        _ => None,
+    }
+}
+
+/// The multiplier `m` and shift `s` such that `x / d`, rounding down, is the
+/// high word of `x * m` shifted right by `s`, for `1 < d < 2^31`. `m` is
+/// below 2^32 but may not fit an `i32`, in which case it is returned as
+/// `m - 2^32`.
+#[must_use] pub fn magic(d: u32) -> (i32, u32) {
+    let d = u64::from(d);
+    let two_31 = 1_u64 << 31;
+    // the largest dividend leaving a remainder of `d - 1`
+    let nc = two_31 - 1 - two_31 % d;
+    let mut p = 32;
+    while (1_u64 << p) <= nc * (d - (1_u64 << p) % d) {
+        p += 1;
+    }
+    let m = ((1_u64 << p) + d - (1_u64 << p) % d) / d;
+    let [b0, b1, b2, b3, ..] = m.to_le_bytes();
+    (i32::from_le_bytes([b0, b1, b2, b3]), p - 32)
+}
@@ -0,0 +1,157 @@
+use crate::compiler::analysis::{AnalysisManager, PreservedAnalyses};
+use crate::compiler::ir::{
+    arena::{BBId, FuncId, InstId},
+    value::{
+        func::IrFunc,
+        inst::{Alloca, Br, Call, InstKind, Load, Store},
+        ty::IrTy,
+        value::Operand,
+    },
+};
+use crate::compiler::pass::FuncPass;
+
+/// Finds the calls in tail position, those whose value is returned right
+/// away, so that deep recursion does not overflow the stack:
+///
+/// - calls of the function itself become a jump back to its start, the
+///   arguments stored to the parameters;
+/// - other calls are marked [`Call::is_tail`] for the backend to emit as
+///   jumps.
+///
+/// Calls passed a pointer that may point to the caller's stack are left
+/// alone, as its frame must outlive them.
+///
+/// There are no phis, so each parameter gets an alloca, stored to on entry
+/// and by each recursive call and loaded at the start of the loop.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TailCallElim;
+
+impl FuncPass for TailCallElim {
+    fn name(&self) -> &'static str {
+        "tail-call-elim"
+    }
+
+    fn run(&mut self, func_id: FuncId, func: &mut IrFunc, _analyses: &mut AnalysisManager) -> PreservedAnalyses {
+        let tail_calls: Vec<_> = func.bb_ids().filter_map(|bb| tail_call(func, bb)).collect();
+        if tail_calls.is_empty() {
+            return PreservedAnalyses::all();
+        }
+
+        let mut recursive = vec![];
+        for (call, ret) in tail_calls {
+            let InstKind::Call(call_inst) = &func.inst_arena[call].kind else {
+                unreachable!()
+            };
+            if call_inst.func_id == func_id {
+                recursive.push((call, ret));
+            } else if !call_inst.is_tail {
+                let kind = InstKind::Call(Call { is_tail: true, ..call_inst.clone() });
+                func.set_inst_kind(call, kind);
+            }
+        }
+        if !recursive.is_empty() {
+            into_loop(func, &recursive);
+        }
+        PreservedAnalyses::none()
+    }
+}
+
+/// The call ending `bb` in tail position and the return after it, either
+/// in `bb` or in a block `bb` jumps to holding just the return, unless the
+/// call is passed a pointer that may point to the caller's stack.
+#[must_use] pub fn tail_call(func: &IrFunc, bb: BBId) -> Option<(InstId, InstId)> {
+    let terminator = func.terminator(bb)?;
+    let ret = match func.inst_arena[terminator].kind {
+        InstKind::RetInst(_) => terminator,
+        InstKind::Br(Br::Jump { nxt_bb }) => {
+            let ret = func.bb_arena[nxt_bb].insts_head?;
+            (func.terminator(nxt_bb) == Some(ret)).then_some(ret)?
+        }
+        _ => return None,
+    };
+    let call = func.inst_arena[terminator].prev?;
+    let InstKind::Call(_) = func.inst_arena[call].kind else {
+        return None;
+    };
+    let InstKind::RetInst(ret_inst) = &func.inst_arena[ret].kind else {
+        return None;
+    };
+    let returns_call = match &ret_inst.val {
+        Some(val) => *val == Operand::Inst(call),
+        None => func.inst_arena[call].ty == IrTy::Void,
+    };
+    (returns_call && !reaches_frame(func, call)).then_some((call, ret))
+}
+
+/// Whether a pointer passed to `call` may point to an alloca of the caller,
+/// i.e. is not computed from a parameter or global.
+fn reaches_frame(func: &IrFunc, call: InstId) -> bool {
+    let InstKind::Call(call) = &func.inst_arena[call].kind else {
+        return false;
+    };
+    call.args.iter().any(|arg| {
+        let mut base = arg;
+        loop {
+            let Operand::Inst(inst_id) = base else {
+                return false;
+            };
+            let inst = &func.inst_arena[*inst_id];
+            match &inst.kind {
+                InstKind::GEP(gep) => base = &gep.ptr,
+                _ => return matches!(inst.ty, IrTy::Ptr(_)),
+            }
+        }
+    })
+}
+
+/// Turns the self-recursive `calls`, each with the return after it, into
+/// jumps back to the start of `func`.
+fn into_loop(func: &mut IrFunc, calls: &[(InstId, InstId)]) {
+    let header = func.first_block.unwrap();
+    let entry = func.build_bb_before_cur(header);
+    func.first_block = Some(entry);
+    let jump = func.build_inst_at_end(InstKind::Br(Br::Jump { nxt_bb: header }), IrTy::Void, entry);
+
+    // allocas run once, before the loop
+    let allocas: Vec<_> = func.inst_arena.iter()
+        .filter(|(_, inst)| matches!(inst.kind, InstKind::Alloca(_)))
+        .map(|(inst_id, _)| inst_id)
+        .collect();
+    for alloca in allocas {
+        func.move_inst_before(alloca, jump);
+    }
+
+    let mut slots = vec![];
+    for param_id in func.params.clone() {
+        let ty = func.param_arena[param_id].ty.clone();
+        let param = Operand::Param(param_id);
+        let alloca = InstKind::Alloca(Alloca { alloca_ty: ty.clone() });
+        let slot: Operand = func.build_inst_before_cur(alloca, IrTy::ptr_of(&ty), jump).into();
+        let val = func.build_inst_at_start(InstKind::Load(Load { addr: slot.clone() }), ty, header);
+        func.replace_all_uses_with(&param, &val.into());
+        func.build_inst_before_cur(InstKind::Store(Store { addr: slot.clone(), data: param }), IrTy::Void, jump);
+        slots.push(slot);
+    }
+
+    for &(call, ret) in calls {
+        let InstKind::Call(call_inst) = func.inst_arena[call].kind.clone() else {
+            unreachable!()
+        };
+        for (slot, arg) in slots.iter().zip(call_inst.args) {
+            func.build_inst_before_cur(InstKind::Store(Store { addr: slot.clone(), data: arg }), IrTy::Void, call);
+        }
+        // a return of the value is only reached from here, and a return
+        // of nothing may be shared with other blocks
+        let bb = func.inst_arena[call].bb;
+        let terminator = func.terminator(bb).unwrap();
+        if terminator != ret {
+            func.remove_inst(terminator);
+        }
+        if terminator == ret || func.has_uses(&Operand::Inst(call)) {
+            func.remove_inst(ret);
+        }
+        func.remove_inst(call);
+        func.build_inst_at_end(InstKind::Br(Br::Jump { nxt_bb: header }), IrTy::Void, bb);
+    }
+    func.remove_unreachable_bbs();
+}
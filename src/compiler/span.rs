@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize)]
 pub struct Pos {
     pub lineno: usize,
     pub colno: usize,
@@ -6,7 +8,7 @@ pub struct Pos {
 }
 
 impl Pos {
-    pub fn new(lineno: usize, colno: usize, idx: usize) -> Pos {
+    #[must_use] pub fn new(lineno: usize, colno: usize, idx: usize) -> Pos {
         Pos { lineno, colno, idx }
     }
 
@@ -25,7 +27,7 @@ impl Pos {
         self.idx += 1;
     }
 
-    pub fn get_next_pos(&self) -> Pos {
+    #[must_use] pub fn get_next_pos(&self) -> Pos {
         Pos {
             lineno: self.lineno,
             colno: self.colno + 1,
@@ -52,13 +54,18 @@ impl PartialOrd for Pos {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy, Serialize)]
 pub struct Span {
     pub start: Pos,
     pub end: Pos,
 }
 
 impl Span {
+    /// Whether `pos` falls in `[start, end)`.
+    #[must_use] pub fn contains(&self, pos: Pos) -> bool {
+        self.start.idx <= pos.idx && pos.idx < self.end.idx
+    }
+
     pub const MAX: Span = Span {
         start: Pos::MAX,
         end: Pos::MAX,
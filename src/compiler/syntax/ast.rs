@@ -1,21 +1,40 @@
+use std::fmt::{Display, Formatter};
+
 use enum_as_inner::EnumAsInner;
+use itertools::Itertools;
+use serde::Serialize;
 
 use crate::compiler::span::Span;
 
 use super::token::TokenType;
 
-#[derive(Debug, Clone)]
+/// Identifies a named entity (function, variable or parameter); assigned by
+/// the name resolver and shared by all later passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct DefId(pub usize);
+
+/// Identifies an expression node; assigned by the parser in creation order,
+/// so sub-expressions always get smaller ids than their parents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct NodeId(pub usize);
+
+impl NodeId {
+    /// Id of nodes synthesized after parsing (e.g. folded constants).
+    pub const DUMMY: NodeId = NodeId(usize::MAX);
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Program {
     pub program_items: Vec<ProgramItem>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum ProgramItem {
     Decl(Decl),
     Func(AstFunc)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Decl {
     pub is_const: bool,
     pub ty_ident: TypeIdent,
@@ -23,54 +42,70 @@ pub struct Decl {
     pub span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SubDecl {
     pub ident: Ident,
+    pub def_id: Option<DefId>,
     pub subs: Option<Subs>,
     pub init_val: Option<InitVal>,
     pub span: Span,
     pub ty: AstTy,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct InitVal {
     pub ty: AstTy,
     pub kind: InitValKind,
     pub span: Span,
 }
 
-#[derive(Debug, Clone, EnumAsInner)]
+#[derive(Debug, Clone, EnumAsInner, Serialize)]
 pub enum InitValKind {
     Expr(Expr),
     ArrayVal(Vec<InitVal>),
     Const(LiteralExpr),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AstFunc {
     pub ident: Ident,
+    pub def_id: Option<DefId>,
+    /// From `__attribute__((...))` before the return type.
+    pub attrs: Vec<FuncAttr>,
     pub params: Vec<FuncParam>,
     pub ret_ty_ident: TypeIdent,
-    pub body: BlockStmt,
+    /// `None` for a prototype such as `int f(int a);`.
+    pub body: Option<BlockStmt>,
     pub span: Span,
 }
 
-#[derive(Debug, Clone)]
+/// A GCC function attribute, kept so that sources using them still compile
+/// as C.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FuncAttr {
+    /// `always_inline`
+    AlwaysInline,
+    /// `noinline`
+    NoInline,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct FuncParam {
     pub ident: Ident,
+    pub def_id: Option<DefId>,
     pub subs: Option<Subs>,
     pub ty_ident: TypeIdent,
     pub ty: AstTy,
     pub span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct BlockStmt {
     pub block_items: Vec<BlockItem>,
     pub span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum BlockItem {
     Stmt(Stmt),
     Decl(Decl),
@@ -85,7 +120,7 @@ impl BlockItem {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Stmt {
     Expr(Expr),
     Block(BlockStmt),
@@ -110,7 +145,7 @@ impl Stmt {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct IfStmt {
     pub cond: Box<Expr>,
     pub then_block: Box<Stmt>,
@@ -118,20 +153,20 @@ pub struct IfStmt {
     pub span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct WhileStmt {
     pub cond: Box<Expr>,
     pub body: Box<Stmt>,
     pub span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ReturnStmt {
     pub val: Option<Box<Expr>>,
     pub span: Span,
 }
 
-#[derive(Debug, Clone, EnumAsInner)]
+#[derive(Debug, Clone, EnumAsInner, Serialize)]
 pub enum Expr {
     LVal(LVal),
     Assign(AssignExpr),
@@ -139,6 +174,7 @@ pub enum Expr {
     Unary(UnaryExpr),
     Binary(BinaryExpr),
     Call(CallExpr),
+    Cast(CastExpr),
 }
 
 impl Expr {
@@ -150,6 +186,19 @@ impl Expr {
             Expr::Unary(x) => x.span,
             Expr::Binary(x) => x.span,
             Expr::Call(x) => x.span,
+            Expr::Cast(x) => x.span,
+        }
+    }
+
+    #[must_use] pub fn node_id(&self) -> NodeId {
+        match self {
+            Expr::LVal(x) => x.node_id,
+            Expr::Assign(x) => x.node_id,
+            Expr::Literal(x) => x.node_id,
+            Expr::Unary(x) => x.node_id,
+            Expr::Binary(x) => x.node_id,
+            Expr::Call(x) => x.node_id,
+            Expr::Cast(x) => x.node_id,
         }
     }
 
@@ -161,12 +210,14 @@ impl Expr {
             Expr::Unary(x) => x.ty.clone(),
             Expr::Binary(x) => x.ty.clone(),
             Expr::Call(x) => x.ty.clone(),
+            Expr::Cast(x) => x.ty.clone(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AssignExpr {
+    pub node_id: NodeId,
     pub lhs: Box<Expr>,
     pub rhs: Box<Expr>,
     pub allow_assign_const: bool,
@@ -174,14 +225,15 @@ pub struct AssignExpr {
     pub ty: AstTy,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LiteralExpr {
+    pub node_id: NodeId,
     pub kind: LiteralKind,
     pub span: Span,
     pub ty: AstTy,
 }
 
-#[derive(Debug, Clone, EnumAsInner)]
+#[derive(Debug, Clone, EnumAsInner, Serialize)]
 pub enum LiteralKind {
     Integer(i32),
     Array(usize, Vec<LiteralExpr>)
@@ -193,16 +245,18 @@ impl LiteralExpr {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UnaryExpr {
+    pub node_id: NodeId,
     pub op: UnaryOp,
     pub sub_expr: Box<Expr>,
     pub span: Span,
     pub ty: AstTy,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct BinaryExpr {
+    pub node_id: NodeId,
     pub op: BinaryOp,
     pub lhs: Box<Expr>,
     pub rhs: Box<Expr>,
@@ -210,60 +264,75 @@ pub struct BinaryExpr {
     pub ty: AstTy,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CallExpr {
+    pub node_id: NodeId,
     pub func: Ident,
+    pub def_id: Option<DefId>,
     pub args: Vec<Expr>,
     pub span: Span,
     pub ty: AstTy,
 }
 
-#[derive(Debug, Clone)]
+/// A conversion of `sub_expr` to `ty`. Never written in source: the type
+/// checker inserts one wherever a value is used at another scalar type, so
+/// later stages see every conversion explicitly.
+#[derive(Debug, Clone, Serialize)]
+pub struct CastExpr {
+    pub node_id: NodeId,
+    pub sub_expr: Box<Expr>,
+    pub span: Span,
+    pub ty: AstTy,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct LVal {
+    pub node_id: NodeId,
     pub ident: Ident,
+    pub def_id: Option<DefId>,
     pub subs: Option<Subs>,
     pub span: Span,
     pub ty: AstTy,
     pub is_lvalue: bool
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Subs {
     pub subs: Vec<Expr>,
     pub span: Span,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct TypeIdent {
     pub kind: TyIdentKind,
     pub span: Span,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub enum TyIdentKind {
     Primitive(PrimitiveTy),
     Void,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub enum PrimitiveTy {
     Integer,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Ident {
     pub name: String,
     pub span: Span,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize)]
 pub enum UnaryOp {
     Neg,
     Pos,
     Not,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize)]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -280,7 +349,7 @@ pub enum BinaryOp {
     Or,
 }
 
-#[derive(Debug, Clone, Eq, EnumAsInner)]
+#[derive(Debug, Clone, Eq, EnumAsInner, Serialize)]
 pub enum AstTy {
     Unknown,
     Void,
@@ -308,6 +377,41 @@ impl PartialEq for AstTy {
     }
 }
 
+/// Spelled as in the source, as `int`, `int[10]` or `int[][3]` for a
+/// parameter, with functions as `int(int, int[])`.
+impl Display for AstTy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AstTy::Unknown => write!(f, "{{unknown}}"),
+            AstTy::Void => write!(f, "void"),
+            AstTy::Int => write!(f, "int"),
+            AstTy::Bool => write!(f, "bool"),
+            AstTy::Func { ret_ty, param_tys } => write!(f, "{ret_ty}({})", param_tys.iter().join(", ")),
+            AstTy::Array { .. } | AstTy::Ptr(_) => {
+                let mut elem_ty = self;
+                while let AstTy::Array { elem_ty: x, .. } | AstTy::Ptr(x) = elem_ty {
+                    elem_ty = x;
+                }
+                write!(f, "{elem_ty}")?;
+                let mut ty = self;
+                loop {
+                    match ty {
+                        AstTy::Array { siz, elem_ty } => {
+                            write!(f, "[{siz}]")?;
+                            ty = elem_ty;
+                        }
+                        AstTy::Ptr(elem_ty) => {
+                            write!(f, "[]")?;
+                            ty = elem_ty;
+                        }
+                        _ => return Ok(()),
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl TokenType {
     pub fn is_binary_op(&self) -> bool {
         use super::token::TokenType::{And, Assign, Div, Eq, Ge, Gt, Le, Lt, Minus, Mod, Mul, Ne, Not, Or, Plus};
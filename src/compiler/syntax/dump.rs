@@ -0,0 +1,105 @@
+use std::fmt::Write;
+
+use serde_json::{Map, Value};
+
+use super::ast::Program;
+
+/// `program` as JSON, on one line, spans counting lines and columns from 0.
+#[must_use] pub fn to_json(program: &Program) -> String {
+    // plain data with string keys, serialization cannot fail
+    serde_json::to_string(program).unwrap_or_default()
+}
+
+/// `program` as an indented tree, a node a line with where it starts in the
+/// source, as `@3:5`, and the items of lists marked with `-`. Fields that are
+/// unset, empty or of a type not yet inferred are left out, as are the ids
+/// of expressions, and nodes only holding values are kept on one line:
+///
+/// ```text
+/// Program
+///   program_items:
+///     - Func @1:1
+///       ident: name=main @1:5
+///       def_id: 6
+///       ret_ty_ident: kind=Primitive Integer @1:1
+///       body: @1:12
+///         block_items:
+///           - Stmt Return @1:14
+///             val: Literal kind=Integer 0 @1:21
+/// ```
+#[must_use] pub fn to_tree(program: &Program) -> String {
+    let mut tree = String::new();
+    let value = serde_json::to_value(program).unwrap_or_default();
+    write_node(&mut tree, 0, "Program", &value);
+    tree
+}
+
+/// Writes `value` as a node `depth` deep, its line starting with `head`.
+fn write_node(tree: &mut String, depth: usize, head: &str, value: &Value) {
+    let indent = "  ".repeat(depth);
+    let join = |text: &str| if head.is_empty() { text.to_string() } else { format!("{head} {text}") };
+    if let Some(text) = scalar(value) {
+        writeln!(tree, "{indent}{}", join(&text)).unwrap();
+        return;
+    }
+    match value {
+        Value::Array(items) => {
+            writeln!(tree, "{indent}{head}").unwrap();
+            for item in items {
+                write_node(tree, depth + 1, "-", item);
+            }
+        }
+        Value::Object(fields) => {
+            if let Some((name, inner)) = variant(fields) {
+                return write_node(tree, depth, &join(name), inner);
+            }
+            let shown: Vec<_> = fields.iter().filter(|(name, value)| is_shown(name, value)).collect();
+            let span = fields.get("span").and_then(scalar);
+            if let Some(values) = shown.iter().map(|(name, value)| Some(format!("{name}={}", scalar(value)?))).collect::<Option<Vec<_>>>() {
+                let line = values.into_iter().chain(span).collect::<Vec<_>>().join(" ");
+                writeln!(tree, "{indent}{}", join(&line)).unwrap();
+                return;
+            }
+            writeln!(tree, "{indent}{}", span.map_or_else(|| head.to_string(), |x| join(&x))).unwrap();
+            for (name, value) in shown {
+                write_node(tree, depth + 1, &format!("{name}:"), value);
+            }
+        }
+        _ => unreachable!("other values are scalars"),
+    }
+}
+
+/// Whether the field `name` holding `value` is worth showing.
+fn is_shown(name: &str, value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Array(items) => !items.is_empty(),
+        Value::String(x) if name == "ty" => x != "Unknown",
+        _ => name != "span" && name != "node_id",
+    }
+}
+
+/// `value` on one line, if it is a plain value, a span or a variant
+/// holding one.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::Null | Value::Array(_) => None,
+        Value::String(x) => Some(x.clone()),
+        Value::Bool(_) | Value::Number(_) => Some(value.to_string()),
+        Value::Object(fields) => {
+            if let Some((name, inner)) = variant(fields) {
+                return Some(format!("{name} {}", scalar(inner)?));
+            }
+            let line = |pos: &Value| Some(pos.get("lineno")?.as_u64()? + 1);
+            let col = |pos: &Value| Some(pos.get("colno")?.as_u64()? + 1);
+            let start = fields.get("start").filter(|_| fields.len() == 2 && fields.contains_key("end"))?;
+            Some(format!("@{}:{}", line(start)?, col(start)?))
+        }
+    }
+}
+
+/// The name and contents of a variant of an enum, as serde writes it.
+fn variant(fields: &Map<String, Value>) -> Option<(&str, &Value)> {
+    let (name, inner) = fields.iter().next().filter(|_| fields.len() == 1)?;
+    name.starts_with(char::is_uppercase).then_some((name.as_str(), inner))
+}
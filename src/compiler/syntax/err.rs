@@ -1,5 +1,10 @@
+use std::fmt::{Display, Formatter};
+
+use crate::compiler::diagnostic::{Diagnostic, Suggestion};
 use crate::compiler::span::{Pos, Span};
 
+use super::token::{Token, TokenType};
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub struct LexError {
     pub lex_error_kind: LexErrorKind,
@@ -13,13 +18,103 @@ pub enum LexErrorKind {
     UnexpectedCharacter(char)
 }
 
+impl LexErrorKind {
+    #[must_use] pub fn code(&self) -> &'static str {
+        match self {
+            LexErrorKind::IllegalLiteral => "E0001",
+            LexErrorKind::UnexpectedEOF => "E0002",
+            LexErrorKind::UnexpectedCharacter(_) => "E0003",
+        }
+    }
+}
+
+impl Display for LexErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexErrorKind::IllegalLiteral => write!(f, "illegal integer literal"),
+            LexErrorKind::UnexpectedEOF => write!(f, "unexpected end of file"),
+            LexErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character `{c}`"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct ParseError {
     pub parse_error_kind: ParseErrorKind,
     pub span: Span,
+    pub suggestion: Option<Suggestion>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum ParseErrorKind {
-    ExpectedPattern(String)
-}
\ No newline at end of file
+    ExpectedPattern(String),
+    IllegalToken(LexErrorKind),
+}
+
+impl ParseError {
+    /// Builds the error for a failed expectation, reporting the lexer error
+    /// instead when the offending token could not be lexed. A missing
+    /// closing token gets a fix-it inserting it after the previous token,
+    /// which ends at `last_end`.
+    #[must_use] pub fn expected(pattern: &str, found: Option<&Token>, last_end: Pos) -> ParseError {
+        let (parse_error_kind, suggestion) = match found.map(|x| &x.token_type) {
+            Some(TokenType::Err(e)) => (ParseErrorKind::IllegalToken(e.lex_error_kind), None),
+            _ => (
+                ParseErrorKind::ExpectedPattern(String::from(pattern)),
+                closing_token_text(pattern).map(|text| Suggestion::insert(last_end, text)),
+            ),
+        };
+        ParseError {
+            parse_error_kind,
+            span: found.map_or(Span::MAX, |x| x.span),
+            suggestion,
+        }
+    }
+}
+
+fn closing_token_text(pattern: &str) -> Option<&'static str> {
+    match pattern {
+        "TokenType::Semicolon" => Some(";"),
+        "TokenType::RParen" => Some(")"),
+        "TokenType::RBracket" => Some("]"),
+        _ => None,
+    }
+}
+
+impl ParseErrorKind {
+    #[must_use] pub fn code(&self) -> &'static str {
+        match self {
+            ParseErrorKind::ExpectedPattern(_) => "E0100",
+            ParseErrorKind::IllegalToken(kind) => kind.code(),
+        }
+    }
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::ExpectedPattern(pattern) => {
+                write!(f, "expected {}", pattern.trim_start_matches("TokenType::"))
+            }
+            ParseErrorKind::IllegalToken(kind) => write!(f, "{kind}"),
+        }
+    }
+}
+
+impl From<LexError> for Diagnostic {
+    fn from(err: LexError) -> Self {
+        let span = Span { start: err.span, end: err.span.get_next_pos() };
+        Diagnostic::error(err.lex_error_kind.code(), err.lex_error_kind.to_string(), Some(span))
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(err: ParseError) -> Self {
+        let span = (err.span != Span::MAX).then_some(err.span);
+        let diagnostic = Diagnostic::error(err.parse_error_kind.code(), err.parse_error_kind.to_string(), span);
+        match err.suggestion {
+            Some(suggestion) => diagnostic.with_suggestion(suggestion),
+            None => diagnostic,
+        }
+    }
+}
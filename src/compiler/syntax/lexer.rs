@@ -144,13 +144,9 @@ impl<T> Lexer<T>
     }
 
     fn skip_error_token(&mut self) -> Pos {
-        loop {
-            if self.iter.next_if(|(_, c)| {
-                c.is_whitespace() || *c == '\0'
-            }).is_some() {
-                break;
-            }
-        }
+        while self.iter.next_if(|(_, c)| {
+            !c.is_whitespace() && *c != '\0'
+        }).is_some() {}
         self.iter.peek().map_or(Pos::MAX, |(pos, _)| *pos)
     }
 
@@ -4,6 +4,8 @@ mod token;
 pub mod lexer;
 
 pub mod ast;
+pub mod dump;
 pub mod parser;
+pub mod unparse;
 
 pub mod visitor;
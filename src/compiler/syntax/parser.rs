@@ -1,21 +1,18 @@
 use std::iter::Peekable;
 
-use crate::compiler::span::Span;
+use crate::compiler::span::{Pos, Span};
 
 use super::{
-    ast::{AssignExpr, AstFunc, AstTy, BinaryExpr, BlockItem, BlockStmt, CallExpr, Decl, Expr, FuncParam, Ident, IfStmt, InitVal, InitValKind, LVal, LiteralExpr, LiteralKind, Program, ProgramItem, ReturnStmt, Stmt, SubDecl, Subs, TypeIdent, UnaryExpr, WhileStmt},
-    err::{ParseError, ParseErrorKind},
+    ast::{AssignExpr, AstFunc, AstTy, BinaryExpr, BlockItem, BlockStmt, CallExpr, Decl, Expr, FuncAttr, FuncParam, Ident, IfStmt, InitVal, InitValKind, LVal, LiteralExpr, LiteralKind, NodeId, Program, ProgramItem, ReturnStmt, Stmt, SubDecl, Subs, TypeIdent, UnaryExpr, WhileStmt},
+    err::ParseError,
     lexer::Lexer,
-    token::TokenType,
+    token::{Token, TokenType},
 };
 
 macro_rules! expect_token {
     ($self:expr, $pat:pat) => {
         $self.next_if(|token| matches!(token.token_type, $pat))
-        .ok_or(ParseError {
-            parse_error_kind: ParseErrorKind::ExpectedPattern(stringify!($pat).to_owned()),
-            span: $self.peek().map_or(Span::MAX, |x| x.span),
-        })
+        .ok_or_else(|| $self.expected(stringify!($pat)))
     };
 }
 
@@ -43,11 +40,53 @@ macro_rules! parse_separate_match {
     }};
 }
 
+/// Token iterator that remembers where the last consumed token ended, so
+/// errors can suggest insertions right after it.
+#[derive(Debug)]
+struct TokenStream<T>
+    where T: Iterator<Item=char>,
+{
+    tokens: Peekable<Lexer<T>>,
+    last_end: Pos,
+}
+
+impl<T> Iterator for TokenStream<T>
+    where T: Iterator<Item=char>,
+{
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.next()?;
+        self.last_end = token.span.end;
+        Some(token)
+    }
+}
+
+impl<T> TokenStream<T>
+    where T: Iterator<Item=char>,
+{
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek()
+    }
+
+    fn next_if(&mut self, func: impl FnOnce(&Token) -> bool) -> Option<Token> {
+        let token = self.tokens.next_if(func)?;
+        self.last_end = token.span.end;
+        Some(token)
+    }
+
+    fn expected(&mut self, pattern: &str) -> ParseError {
+        let last_end = self.last_end;
+        ParseError::expected(pattern, self.tokens.peek(), last_end)
+    }
+}
+
 #[derive(Debug)]
 pub struct Parser<T>
     where T: Iterator<Item=char>,
 {
-    iter: Peekable<Lexer<T>>,
+    iter: TokenStream<T>,
+    next_node_id: usize,
 }
 
 impl<T> Parser<T>
@@ -55,10 +94,19 @@ impl<T> Parser<T>
 {
     pub fn new(lexer: Lexer<T>) -> Parser<T> {
         Parser {
-            iter: lexer.into_iter().peekable()
+            iter: TokenStream {
+                tokens: lexer.into_iter().peekable(),
+                last_end: Pos::ZERO,
+            },
+            next_node_id: 0,
         }
     }
 
+    fn new_node_id(&mut self) -> NodeId {
+        self.next_node_id += 1;
+        NodeId(self.next_node_id - 1)
+    }
+
     pub fn parse(&mut self) -> Result<Program, ParseError> {
         self.parse_program()
     }
@@ -67,12 +115,13 @@ impl<T> Parser<T>
         let mut program_items = vec![];
 
         while self.iter.peek().is_some() {
+            let attrs = self.parse_func_attrs()?;
             let is_const = next_if_match!(self.iter, TokenType::ConstKw);
             let ty = self.parse_ty()?;
             let lvalue = self.parse_lvalue()?;
 
-            if is_next!(self.iter, TokenType::LParen) {
-                let func = self.parse_func(ty, lvalue.ident)?;
+            if is_next!(self.iter, TokenType::LParen) || !attrs.is_empty() {
+                let func = self.parse_func(attrs, ty, lvalue.ident)?;
                 program_items.push(ProgramItem::Func(func));
             } else {
                 let decl = self.parse_decl(is_const, ty, lvalue)?;
@@ -117,6 +166,7 @@ impl<T> Parser<T>
 
         Ok(SubDecl {
             ident: lvalue.ident,
+            def_id: None,
             subs: lvalue.subs,
             init_val,
             span,
@@ -155,7 +205,26 @@ impl<T> Parser<T>
         Ok(init_val)
     }
 
-    fn parse_func(&mut self, ret_ty: TypeIdent, name: Ident) -> Result<AstFunc, ParseError> {
+    /// `__attribute__((noinline))` and the like, any number of them.
+    fn parse_func_attrs(&mut self) -> Result<Vec<FuncAttr>, ParseError> {
+        let mut attrs = vec![];
+        while self.iter.next_if(|x| matches!(&x.token_type, TokenType::Ident(x) if x == "__attribute__")).is_some() {
+            expect_token!(self.iter, TokenType::LParen)?;
+            expect_token!(self.iter, TokenType::LParen)?;
+            let attr = match self.iter.peek().map(|x| &x.token_type) {
+                Some(TokenType::Ident(x)) if x == "always_inline" => FuncAttr::AlwaysInline,
+                Some(TokenType::Ident(x)) if x == "noinline" => FuncAttr::NoInline,
+                _ => return Err(self.iter.expected("always_inline | noinline")),
+            };
+            self.iter.next();
+            attrs.push(attr);
+            expect_token!(self.iter, TokenType::RParen)?;
+            expect_token!(self.iter, TokenType::RParen)?;
+        }
+        Ok(attrs)
+    }
+
+    fn parse_func(&mut self, attrs: Vec<FuncAttr>, ret_ty: TypeIdent, name: Ident) -> Result<AstFunc, ParseError> {
         expect_token!(self.iter, TokenType::LParen)?;
         let params = if is_next!(self.iter, TokenType::RParen) {
             vec![]
@@ -164,11 +233,19 @@ impl<T> Parser<T>
         };
         expect_token!(self.iter, TokenType::RParen)?;
 
-        let body = self.parse_block_stmt()?;
+        let (body, end) = if is_next!(self.iter, TokenType::Semicolon) {
+            (None, expect_token!(self.iter, TokenType::Semicolon)?.span.end)
+        } else {
+            let body = self.parse_block_stmt()?;
+            let end = body.span.end;
+            (Some(body), end)
+        };
 
-        let (start, end) = (ret_ty.span.start, body.span.end);
+        let start = ret_ty.span.start;
         Ok(AstFunc {
             ident: name,
+            def_id: None,
+            attrs,
             params,
             ret_ty_ident: ret_ty,
             body,
@@ -200,6 +277,7 @@ impl<T> Parser<T>
         };
         Ok(FuncParam {
             ident: name,
+            def_id: None,
             subs,
             ty_ident: ty,
             ty: AstTy::Unknown,
@@ -384,6 +462,7 @@ impl<T> Parser<T>
             lhs = match op {
                 TokenType::Assign => {
                     Expr::Assign(AssignExpr {
+                        node_id: self.new_node_id(),
                         lhs: Box::new(lhs),
                         rhs: Box::new(rhs),
                         allow_assign_const: false,
@@ -392,6 +471,7 @@ impl<T> Parser<T>
                     })
                 }
                 _ => Expr::Binary(BinaryExpr {
+                    node_id: self.new_node_id(),
                     lhs: Box::new(lhs),
                     rhs: Box::new(rhs),
                     op: op.to_binary_op().unwrap(),
@@ -414,6 +494,7 @@ impl<T> Parser<T>
         for prec_op in pre_op_tokens.drain(..).rev() {
             let op = prec_op.token_type.to_unary_op().unwrap();
             expr_item = Expr::Unary(UnaryExpr {
+                node_id: self.new_node_id(),
                 op,
                 sub_expr: Box::new(expr_item),
                 span: Span { start: prec_op.span.start, end },
@@ -436,6 +517,7 @@ impl<T> Parser<T>
         } else if is_next!(self.iter, TokenType::IntLiteral(_)) {
             let int_literal = expect_token!(self.iter, TokenType::IntLiteral(_))?;
             Ok(Expr::Literal(LiteralExpr {
+                node_id: self.new_node_id(),
                 kind: LiteralKind::Integer(*int_literal.token_type.as_int_literal().unwrap()),
                 span: int_literal.span,
                 ty: AstTy::Unknown,
@@ -446,12 +528,7 @@ impl<T> Parser<T>
             expect_token!(self.iter, TokenType::RParen)?;
             Ok(expr)
         } else {
-            Err(ParseError {
-                parse_error_kind: ParseErrorKind::ExpectedPattern(
-                    String::from("Literal or Identifier or Function call or Parenthesis")
-                ),
-                span: self.iter.peek().map_or(Span::MAX, |x| x.span),
-            })
+            Err(self.iter.expected("Literal or Identifier or Function call or Parenthesis"))
         }
     }
 
@@ -466,7 +543,9 @@ impl<T> Parser<T>
         let end = expect_token!(self.iter, TokenType::RParen)?.span.end;
 
         Ok(CallExpr {
+            node_id: self.new_node_id(),
             func,
+            def_id: None,
             args: params,
             span: Span { start, end },
             ty: AstTy::Unknown,
@@ -485,7 +564,9 @@ impl<T> Parser<T>
         };
 
         Ok(LVal {
+            node_id: self.new_node_id(),
             ident: name,
+            def_id: None,
             subs,
             span,
             ty: AstTy::Unknown,
@@ -0,0 +1,194 @@
+use std::fmt::Write;
+
+use super::ast::{AstFunc, BinaryOp, BlockItem, BlockStmt, Decl, Expr, FuncAttr, InitVal, InitValKind, LVal, LiteralExpr, LiteralKind, PrimitiveTy, Program, ProgramItem, Stmt, TyIdentKind, UnaryOp};
+
+/// `program` as `SysY` source, which parses back to the same tree, up to
+/// spans and ids. Operands of binary operators are parenthesized, and casts
+/// the type checker inserted are left out.
+#[must_use] pub fn to_source(program: &Program) -> String {
+    let mut source = String::new();
+    for item in &program.program_items {
+        match item {
+            ProgramItem::Decl(decl) => write_decl(&mut source, 0, decl),
+            ProgramItem::Func(func) => write_func(&mut source, func),
+        }
+    }
+    source
+}
+
+fn write_func(source: &mut String, func: &AstFunc) {
+    for attr in &func.attrs {
+        let name = match attr {
+            FuncAttr::AlwaysInline => "always_inline",
+            FuncAttr::NoInline => "noinline",
+        };
+        write!(source, "__attribute__(({name})) ").unwrap();
+    }
+    let params: Vec<_> = func.params.iter().map(|param| {
+        let subs = param.subs.as_ref().map_or_else(String::new, |x| format!("[]{}", subs(&x.subs)));
+        format!("{} {}{subs}", ty(param.ty_ident.kind), param.ident.name)
+    }).collect();
+    write!(source, "{} {}({})", ty(func.ret_ty_ident.kind), func.ident.name, params.join(", ")).unwrap();
+    match &func.body {
+        Some(body) => {
+            source.push(' ');
+            write_block(source, 0, body);
+            source.push('\n');
+        }
+        None => source.push_str(";\n"),
+    }
+}
+
+fn ty(kind: TyIdentKind) -> &'static str {
+    match kind {
+        TyIdentKind::Primitive(PrimitiveTy::Integer) => "int",
+        TyIdentKind::Void => "void",
+    }
+}
+
+fn write_decl(source: &mut String, depth: usize, decl: &Decl) {
+    let sub_decls: Vec<_> = decl.sub_decls.iter().map(|sub_decl| {
+        let mut text = sub_decl.ident.name.clone();
+        if let Some(x) = &sub_decl.subs {
+            text.push_str(&subs(&x.subs));
+        }
+        if let Some(init_val) = &sub_decl.init_val {
+            write!(text, " = {}", self::init_val(init_val)).unwrap();
+        }
+        text
+    }).collect();
+    let konst = if decl.is_const { "const " } else { "" };
+    writeln!(source, "{}{konst}{} {};", "    ".repeat(depth), ty(decl.ty_ident.kind), sub_decls.join(", ")).unwrap();
+}
+
+fn init_val(init_val: &InitVal) -> String {
+    match &init_val.kind {
+        InitValKind::Expr(x) => expr(x),
+        InitValKind::ArrayVal(vals) => format!("{{{}}}", vals.iter().map(self::init_val).collect::<Vec<_>>().join(", ")),
+        InitValKind::Const(x) => literal(x),
+    }
+}
+
+/// Writes `block` from where the line it starts on is, ending at its `}`.
+fn write_block(source: &mut String, depth: usize, block: &BlockStmt) {
+    source.push_str("{\n");
+    for item in &block.block_items {
+        match item {
+            BlockItem::Decl(decl) => write_decl(source, depth + 1, decl),
+            BlockItem::Stmt(stmt) => {
+                source.push_str(&"    ".repeat(depth + 1));
+                write_stmt(source, depth + 1, stmt);
+                source.push('\n');
+            }
+        }
+    }
+    write!(source, "{}}}", "    ".repeat(depth)).unwrap();
+}
+
+/// Writes `stmt` from where the line it starts on is, without the newline.
+fn write_stmt(source: &mut String, depth: usize, stmt: &Stmt) {
+    match stmt {
+        Stmt::Expr(x) => write!(source, "{};", expr(x)).unwrap(),
+        Stmt::Block(block) => write_block(source, depth, block),
+        Stmt::If(x) => {
+            write!(source, "if ({}) ", expr(&x.cond)).unwrap();
+            match (&*x.then_block, &x.else_block) {
+                // else would go with the inner if
+                (then_block @ Stmt::If(_), Some(_)) => {
+                    write!(source, "{{\n{}", "    ".repeat(depth + 1)).unwrap();
+                    write_stmt(source, depth + 1, then_block);
+                    write!(source, "\n{}}}", "    ".repeat(depth)).unwrap();
+                }
+                (then_block, _) => write_stmt(source, depth, then_block),
+            }
+            if let Some(else_block) = &x.else_block {
+                source.push_str(" else ");
+                write_stmt(source, depth, else_block);
+            }
+        }
+        Stmt::While(x) => {
+            write!(source, "while ({}) ", expr(&x.cond)).unwrap();
+            write_stmt(source, depth, &x.body);
+        }
+        Stmt::Break(_) => source.push_str("break;"),
+        Stmt::Continue(_) => source.push_str("continue;"),
+        Stmt::Return(x) => match &x.val {
+            Some(val) => write!(source, "return {};", expr(val)).unwrap(),
+            None => source.push_str("return;"),
+        },
+        Stmt::Empty(_) => source.push(';'),
+    }
+}
+
+fn expr(expr: &Expr) -> String {
+    match expr {
+        Expr::LVal(x) => lval(x),
+        Expr::Assign(x) => format!("{} = {}", self::expr(&x.lhs), self::expr(&x.rhs)),
+        Expr::Literal(x) => literal(x),
+        Expr::Unary(x) => {
+            let op = match x.op {
+                UnaryOp::Neg => '-',
+                UnaryOp::Pos => '+',
+                UnaryOp::Not => '!',
+            };
+            match &*x.sub_expr {
+                // not to write `--`
+                sub_expr @ Expr::Unary(_) => format!("{op}({})", self::expr(sub_expr)),
+                sub_expr => format!("{op}{}", operand(sub_expr)),
+            }
+        }
+        Expr::Binary(x) => {
+            let op = match x.op {
+                BinaryOp::Add => "+",
+                BinaryOp::Sub => "-",
+                BinaryOp::Mul => "*",
+                BinaryOp::Div => "/",
+                BinaryOp::Mod => "%",
+                BinaryOp::Gt => ">",
+                BinaryOp::Lt => "<",
+                BinaryOp::Ge => ">=",
+                BinaryOp::Le => "<=",
+                BinaryOp::Eq => "==",
+                BinaryOp::Ne => "!=",
+                BinaryOp::And => "&&",
+                BinaryOp::Or => "||",
+            };
+            format!("{} {op} {}", operand(&x.lhs), operand(&x.rhs))
+        }
+        Expr::Call(x) => format!("{}({})", x.func.name, x.args.iter().map(self::expr).collect::<Vec<_>>().join(", ")),
+        Expr::Cast(x) => self::expr(&x.sub_expr),
+    }
+}
+
+/// `expr` as the operand of an operator.
+fn operand(expr: &Expr) -> String {
+    match expr {
+        Expr::Assign(_) | Expr::Binary(_) => format!("({})", self::expr(expr)),
+        Expr::Cast(x) => operand(&x.sub_expr),
+        _ => self::expr(expr),
+    }
+}
+
+fn lval(lval: &LVal) -> String {
+    match &lval.subs {
+        Some(x) => format!("{}{}", lval.ident.name, subs(&x.subs)),
+        None => lval.ident.name.clone(),
+    }
+}
+
+fn subs(subs: &[Expr]) -> String {
+    subs.iter().fold(String::new(), |mut text, x| {
+        write!(text, "[{}]", expr(x)).unwrap();
+        text
+    })
+}
+
+fn literal(literal: &LiteralExpr) -> String {
+    match &literal.kind {
+        // negative numbers are negated literals in the source
+        LiteralKind::Integer(i32::MIN) => String::from("(-2147483647 - 1)"),
+        LiteralKind::Integer(x) if *x < 0 => format!("(-{})", -x),
+        LiteralKind::Integer(x) => x.to_string(),
+        LiteralKind::Array(_, elems) => format!("{{{}}}", elems.iter().map(self::literal).collect::<Vec<_>>().join(", ")),
+    }
+}
@@ -1,4 +1,4 @@
-use super::ast::{AssignExpr, AstFunc, BinaryExpr, BlockStmt, CallExpr, Decl, Expr, FuncParam, IfStmt, InitVal, LiteralExpr, Program, ReturnStmt, Stmt, TypeIdent, UnaryExpr, WhileStmt};
+use super::ast::{AssignExpr, AstFunc, BinaryExpr, BlockStmt, CallExpr, CastExpr, Decl, Expr, FuncParam, IfStmt, InitVal, LiteralExpr, Program, ReturnStmt, Stmt, TypeIdent, UnaryExpr, WhileStmt};
 use super::super::span::Span;
 
 pub trait AstVisitor {
@@ -56,6 +56,8 @@ pub trait AstVisitor {
 
     fn visit_call_expr(&mut self, expr: &CallExpr) -> Self::ExprResult;
 
+    fn visit_cast_expr(&mut self, expr: &CastExpr) -> Self::ExprResult;
+
     fn visit_ty(&mut self, ty_def: &TypeIdent) -> Self::TyResult;
 }
 
@@ -114,6 +116,8 @@ pub trait AstVisitorMut {
 
     fn visit_call_expr(&mut self, expr: &mut CallExpr) -> Self::ExprResult;
 
+    fn visit_cast_expr(&mut self, expr: &mut CastExpr) -> Self::ExprResult;
+
     fn visit_ty(&mut self, ty_def: &mut TypeIdent) -> Self::TyResult;
 }
 
@@ -0,0 +1,42 @@
+use crate::compiler::backend::arm::{
+    inst::{ARG_REGS, R0, REGISTER_INFO},
+    Features,
+};
+use crate::compiler::target::{Arch, CallingConvention, Target};
+
+/// ARMv7-A with the procedure call standard of the hard-float ABI, and with
+/// every feature the backend uses, as the Cortex-A7 and A15 have.
+pub const ARMV7A: Target = Target {
+    arch: Arch::Arm,
+    pointer_bytes: 4,
+    registers: REGISTER_INFO,
+    calling_convention: CallingConvention { arg_regs: ARG_REGS, result_reg: R0, stack_align: 8 },
+    features: Features { movw_movt: true, hwdiv: true, neon: true },
+};
+
+/// Whether `imm` fits the immediate operand of a data-processing
+/// instruction, an 8-bit value rotated right by an even amount.
+#[must_use] pub fn is_operand2(imm: u32) -> bool {
+    (0..16).any(|rot| imm.rotate_left(rot * 2) <= 0xff)
+}
+
+/// Whether an instruction can take `imm` as its immediate operand, possibly
+/// once swapped for its counterpart on the negated or complemented value, as
+/// `add` for `sub`, `cmp` for `cmn`, `and` for `bic` and `mov` for `mvn`.
+#[must_use] pub fn is_legal_immediate(imm: i32) -> bool {
+    let imm = imm.cast_unsigned();
+    is_operand2(imm) || is_operand2(imm.wrapping_neg()) || is_operand2(!imm)
+}
+
+/// Instructions needed to put `imm` in a register: a `mov` or `mvn` of an
+/// operand2, else with `features`, a `movw` for 16 bits or a `movw` and
+/// `movt` pair, else a load from a literal pool, counting as two for the
+/// memory access.
+#[must_use] pub fn materialize_cost(imm: i32, features: Features) -> usize {
+    let imm = imm.cast_unsigned();
+    if is_operand2(imm) || is_operand2(!imm) || (features.movw_movt && imm <= 0xffff) {
+        1
+    } else {
+        2
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::compiler::backend::arm::Features;
+use crate::compiler::mir::RegisterInfo;
+
+pub mod arm;
+
+/// A machine to generate code for, as the backend and the passes tailoring
+/// the IR to a machine see it, picked by its target triple.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub arch: Arch,
+    /// Bytes a pointer takes.
+    pub pointer_bytes: u32,
+    /// The registers, as the allocator and frame lowering see them.
+    pub registers: RegisterInfo,
+    pub calling_convention: CallingConvention,
+    /// Optional parts of the architecture the code may use.
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    /// 32-bit ARM, from ARMv7-A on, in the ARM instruction set.
+    Arm,
+}
+
+/// How functions pass their arguments and results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallingConvention {
+    /// Registers the first words of the arguments are passed in, numbered
+    /// from 0, the rest going on the stack.
+    pub arg_regs: u8,
+    /// The register a result of a word comes back in.
+    pub result_reg: u8,
+    /// Bytes the stack pointer is aligned to at calls.
+    pub stack_align: u32,
+}
+
+impl Target {
+    /// The target `triple` names, as `armv7a-linux-gnueabihf`: an
+    /// architecture, an optional vendor, a system and an environment, the
+    /// environment being that of the hard-float ABI.
+    ///
+    /// # Errors
+    ///
+    /// If racoon cannot generate code for the triple.
+    pub fn from_triple(triple: &str) -> Result<Target, TargetError> {
+        let parts: Vec<_> = triple.split('-').collect();
+        let unknown = || TargetError::Unknown(String::from(triple));
+        if !(3..=4).contains(&parts.len()) {
+            return Err(unknown());
+        }
+        let target = match parts[0] {
+            "arm" | "armv7" | "armv7a" | "armv7ve" => arm::ARMV7A,
+            _ => return Err(unknown()),
+        };
+        if !parts[parts.len() - 1].ends_with("eabihf") {
+            return Err(TargetError::SoftFloat(String::from(triple)));
+        }
+        Ok(target)
+    }
+
+    /// Whether an instruction can take `imm` as its immediate operand, on
+    /// its own or once negated or complemented.
+    #[must_use] pub fn is_legal_immediate(&self, imm: i32) -> bool {
+        match self.arch {
+            Arch::Arm => arm::is_legal_immediate(imm),
+        }
+    }
+
+    /// Instructions needed to put `imm` in a register.
+    #[must_use] pub fn materialize_cost(&self, imm: i32) -> usize {
+        match self.arch {
+            Arch::Arm => arm::materialize_cost(imm, self.features),
+        }
+    }
+}
+
+impl Default for Target {
+    /// ARMv7-A Linux with the hard-float ABI, as on the boards the course
+    /// judges on.
+    fn default() -> Self {
+        arm::ARMV7A
+    }
+}
+
+impl Display for Target {
+    /// The triple of the target, as the linker and other tools take it.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.arch {
+            Arch::Arm => write!(f, "armv7a-linux-gnueabihf"),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = TargetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Target::from_triple(s)
+    }
+}
+
+/// Why a triple names no target racoon generates code for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetError {
+    Unknown(String),
+    /// The triple is of an ABI passing floating-point values in core
+    /// registers, which the code does not follow.
+    SoftFloat(String),
+}
+
+impl Display for TargetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TargetError::Unknown(triple) => write!(f, "unknown target `{triple}`, racoon targets armv7a-linux-gnueabihf"),
+            TargetError::SoftFloat(triple) => write!(f, "target `{triple}` is not of the hard-float ABI"),
+        }
+    }
+}
+
+impl std::error::Error for TargetError {}
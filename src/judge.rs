@@ -0,0 +1,188 @@
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+use racoon::compiler::backend::arm;
+use racoon::compiler::interpreter::Interpreter;
+use racoon::compiler::ir::value::module::Module;
+use racoon::compiler::ir_builder::lint::LintConfig;
+use racoon::testing::collect;
+use racoon::testing::qemu::{self, Outcome};
+
+use crate::options::{Options, TestMode, TestOptions};
+use crate::timing::Timings;
+use crate::{compile_file, limits, link, lint_config, optimize, target, Compiled};
+
+/// Lines shown before and from where the outputs first differ.
+const CONTEXT: usize = 3;
+
+/// Runs the tests as `racoon test` does, printing how each went, with how
+/// long it ran natively, and then how many passed. Returns whether all of
+/// them did.
+pub fn run(test_options: &TestOptions, options: &Options, timings: &Timings) -> bool {
+    let lint_config = lint_config(options);
+    let mut tests = vec![];
+    let needs_out = test_options.mode != TestMode::Diff;
+    for path in &test_options.tests {
+        if let Err(e) = collect(path, needs_out, &mut tests) {
+            eprintln!("error: could not read `{}`: {e}", path.display());
+            return false;
+        }
+    }
+    tests.sort();
+
+    let (mut passed, mut failed) = (0, 0);
+    let mut total = Duration::ZERO;
+    for test in &tests {
+        // a compiler bug fails the test it shows in alone
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run_test(test, test_options, options, &lint_config, timings)));
+        match result.unwrap_or_else(|_| Err(String::from("the compiler panicked"))) {
+            Ok(None) => {
+                passed += 1;
+                println!("PASS {}", test.display());
+            }
+            Ok(Some(time)) => {
+                passed += 1;
+                total += time;
+                println!("PASS {} in {:.3} s", test.display(), time.as_secs_f64());
+            }
+            Err(why) => {
+                failed += 1;
+                println!("FAIL {}: {why}", test.display());
+            }
+        }
+    }
+    match test_options.mode {
+        TestMode::Interpret => println!("{passed} passed, {failed} failed"),
+        TestMode::Native | TestMode::Diff => println!("{passed} passed, {failed} failed, passing in {:.3} s", total.as_secs_f64()),
+    }
+    failed == 0
+}
+
+/// Compiles and runs `test` as `test_options` say, comparing what it does
+/// with its `.out` file, or natively with what it does in the interpreter.
+/// Returns why it failed if it did, or how long it ran if it was run
+/// natively.
+fn run_test(test: &Path, test_options: &TestOptions, options: &Options, lint_config: &LintConfig, timings: &Timings)
+    -> Result<Option<Duration>, String> {
+    let Some(Compiled::Module(mut ir)) = compile_file(test, options, lint_config, timings) else {
+        return Err(String::from("does not compile"));
+    };
+    let input = test.with_extension("in");
+    let input = input.is_file().then_some(input);
+    match test_options.mode {
+        TestMode::Interpret => {
+            optimize(&mut ir, options, timings);
+            let (stdout, exit_code) = interpret(&ir, input.as_deref(), options)?;
+            diff(&expected(test)?, &judged(&stdout, exit_code & 0xff))?;
+            Ok(None)
+        }
+        TestMode::Native => {
+            optimize(&mut ir, options, timings);
+            let (stdout, exit_code, time) = run_native(&ir, input.as_deref(), test_options, options, timings)?;
+            diff(&expected(test)?, &judged(&stdout, exit_code & 0xff))?;
+            Ok(Some(time))
+        }
+        TestMode::Diff => {
+            // the IR as the front end built it is what the program means,
+            // untouched by the passes under test
+            let (stdout, exit_code) = interpret(&ir, input.as_deref(), options)
+                .map_err(|why| format!("in the interpreter, {why}"))?;
+            optimize(&mut ir, options, timings);
+            let (native_stdout, native_exit_code, time) = run_native(&ir, input.as_deref(), test_options, options, timings)?;
+            diff(&judged(&stdout, exit_code & 0xff), &judged(&native_stdout, native_exit_code & 0xff))
+                .map_err(|why| format!("differs natively from the interpreter, {why}"))?;
+            Ok(Some(time))
+        }
+    }
+}
+
+/// The `.out` file of `test`.
+fn expected(test: &Path) -> Result<String, String> {
+    fs::read_to_string(test.with_extension("out")).map_err(|e| format!("could not read the expected output: {e}"))
+}
+
+/// Runs `ir` in the interpreter on `input`, returning what it printed and
+/// its exit code.
+fn interpret(ir: &Module, input: Option<&Path>, options: &Options) -> Result<(Vec<u8>, i32), String> {
+    let input: Box<dyn BufRead> = match input {
+        Some(input) => Box::new(BufReader::new(File::open(input).map_err(|e| format!("could not read the input: {e}"))?)),
+        None => Box::new(io::empty()),
+    };
+    let mut stdout = vec![];
+    let exit_code = Interpreter::new(ir, input, &mut stdout)
+        .with_limits(limits(options))
+        .run()
+        .map_err(|e| format!("runtime error: {e}"))?;
+    Ok((stdout, exit_code))
+}
+
+/// Compiles and links `ir` and runs it under QEMU on `input`, returning what
+/// it printed, its exit code and how long it ran.
+fn run_native(ir: &Module, input: Option<&Path>, test_options: &TestOptions, options: &Options, timings: &Timings)
+    -> Result<(Vec<u8>, i32, Duration), String> {
+    let target = target(options);
+    let asm = timings.time("codegen", || arm::compile(ir, options.opt_level, &target, options.stack_protector))
+        .map_err(|e| format!("does not compile: {e}"))?;
+    let object = timings.time("encode", || arm::object(&asm));
+    let executable = std::env::temp_dir().join(format!("racoon-test-{}.out", process::id()));
+    timings.time("link", || link::link(&object, &executable, &options.linker, &target))?;
+
+    let timeout = Duration::from_secs(test_options.timeout);
+    let result = qemu::run(&test_options.qemu, &test_options.sysroot, &executable, input, timeout);
+    let _ = fs::remove_file(&executable);
+    match result {
+        Ok((Outcome::Exited { code: Some(code), stdout }, time)) => Ok((stdout, code, time)),
+        Ok((Outcome::Exited { code: None, .. }, _)) => Err(String::from("killed by a signal")),
+        Ok((Outcome::TimedOut, _)) => Err(format!("timed out after {} s", test_options.timeout)),
+        Err(e) => Err(format!("could not run `{}`: {e}", test_options.qemu)),
+    }
+}
+
+/// What a run printed and returned, as the `.out` files of the SysY test
+/// suites have it: the output, ended by a newline if it is not, then the
+/// exit code on a line of its own.
+fn judged(stdout: &[u8], exit_code: i32) -> String {
+    let mut judged = String::from_utf8_lossy(stdout).into_owned();
+    if !judged.is_empty() && !judged.ends_with('\n') {
+        judged.push('\n');
+    }
+    writeln!(judged, "{exit_code}").unwrap();
+    judged
+}
+
+/// Compares `actual` with `expected` line by line, ignoring whitespace at
+/// the end of lines and of the output. Returns the lines around the first
+/// difference, if any, as `-` expected and `+` actual, in the interpreter
+/// and natively with --mode=diff.
+fn diff(expected: &str, actual: &str) -> Result<(), String> {
+    let expected: Vec<_> = expected.trim_end().lines().map(str::trim_end).collect();
+    let actual: Vec<_> = actual.trim_end().lines().map(str::trim_end).collect();
+    let len = expected.len().max(actual.len());
+    let Some(first) = (0..len).find(|&i| expected.get(i) != actual.get(i)) else {
+        return Ok(());
+    };
+
+    let mut why = format!("output differs from line {}", first + 1);
+    for line in &expected[first.saturating_sub(CONTEXT)..first] {
+        write!(why, "\n     {line}").unwrap();
+    }
+    for i in first..len.min(first + CONTEXT) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(x), Some(y)) if x == y => write!(why, "\n     {x}").unwrap(),
+            (x, y) => {
+                if let Some(x) = x {
+                    write!(why, "\n    -{x}").unwrap();
+                }
+                if let Some(y) = y {
+                    write!(why, "\n    +{y}").unwrap();
+                }
+            }
+        }
+    }
+    Err(why)
+}
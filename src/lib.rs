@@ -1 +1,2 @@
-pub mod compiler;
\ No newline at end of file
+pub mod compiler;
+pub mod testing;
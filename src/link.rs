@@ -0,0 +1,45 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+use racoon::compiler::target::Target;
+
+/// The SysY runtime library, compiled along with every program linked.
+const RUNTIME: &str = include_str!("runtime/sylib.c");
+
+/// Links `object`, the program compiled, with the SysY runtime library into
+/// the executable `output`.
+///
+/// `linker` is a C compiler driver for `target`, as `arm-linux-gnueabihf-gcc`,
+/// which knows where the C library and start-up files of the target are;
+/// `clang` is told the target triple, and links with `lld`. The runtime is bundled
+/// as C source, which the driver compiles on the way.
+pub fn link(object: &[u8], output: &Path, linker: &str, target: &Target) -> Result<(), String> {
+    let dir = env::temp_dir().join(format!("racoon-{}", process::id()));
+    let result = link_in(&dir, object, output, linker, target);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// [`link`], with the files the linker reads written to `dir`.
+fn link_in(dir: &Path, object: &[u8], output: &Path, linker: &str, target: &Target) -> Result<(), String> {
+    let (object_file, runtime_file) = (dir.join("main.o"), dir.join("sylib.c"));
+    let write = |path: &Path, contents: &[u8]| {
+        fs::write(path, contents).map_err(|e| format!("could not write `{}`: {e}", path.display()))
+    };
+    fs::create_dir_all(dir).map_err(|e| format!("could not create `{}`: {e}", dir.display()))?;
+    write(&object_file, object)?;
+    write(&runtime_file, RUNTIME.as_bytes())?;
+
+    let mut command = Command::new(linker);
+    if Path::new(linker).file_name().is_some_and(|x| x.to_string_lossy().contains("clang")) {
+        command.arg(format!("--target={target}")).arg("-fuse-ld=lld");
+    }
+    command.args(["-march=armv7-a", "-mfloat-abi=hard", "-O2", "-o"]).arg(output).arg(object_file).arg(runtime_file);
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("`{linker}` failed: {status}")),
+        Err(e) => Err(format!("could not run `{linker}`: {e}")),
+    }
+}
@@ -1,49 +1,323 @@
+use std::env;
+use std::fmt::Write as _;
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
 use clap::Parser;
 
 use racoon::compiler::{
+    analysis::stats::ModuleStats,
+    backend::{arm, c::CSource},
+    diagnostic::Diagnostic,
+    interpreter::{Interpreter, Limits},
+    ir::value::module::Module,
     ir_builder::*,
-    syntax::{*, visitor::AstVisitorMut},
+    pass::{pipeline, print_ir::PrintIr, sanitize::{SanitizeBounds, SanitizeOverflow}, PassManager},
+    syntax::*,
+    target::Target,
 };
 
+use timing::Timings;
+
+mod judge;
+mod link;
 mod options;
+mod reduce;
+mod timing;
+
+#[global_allocator]
+static ALLOC: timing::CountingAlloc = timing::CountingAlloc;
+
+/// What the front end made of a source file.
+enum Compiled {
+    /// The tokens or syntax tree, as text, with --emit=tokens or ast.
+    Dump(String),
+    Module(Module),
+}
 
 fn main() {
     let options = options::Options::parse();
+    let timings = Timings::default();
+    let passed = match &options.command {
+        Some(options::Command::Test(test_options)) => judge::run(test_options, &options, &timings),
+        Some(options::Command::Reduce(reduce_options)) => reduce::run(reduce_options),
+        None => {
+            run(&options, &timings);
+            true
+        }
+    };
+    if options.time_passes {
+        eprint!("{timings}");
+    }
+    if !passed {
+        process::exit(1);
+    }
+}
 
-    let input_file = options.input_file;
-    let input = fs::read_to_string(input_file)
-        .expect("Failed to read from input file");
+/// Compiles as `options` say, exiting on errors.
+fn run(options: &options::Options, timings: &Timings) {
+    let emit = options.emit();
+    let output_file = options.output_file();
+    let lint_config = lint_config(options);
 
-    let lexer = lexer::Lexer::new(input.chars());
-    let mut parser = parser::Parser::new(lexer);
+    // every file is compiled, to report the errors of all of them
+    let mut dump = String::new();
+    let mut modules = vec![];
+    let mut failed = false;
+    for input_file in &options.input_files {
+        match compile_file(input_file, options, &lint_config, timings) {
+            Some(Compiled::Dump(text)) => dump.push_str(&text),
+            Some(Compiled::Module(module)) => modules.push(module),
+            None => failed = true,
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    if matches!(emit, options::EmitOption::Tokens | options::EmitOption::Ast) {
+        check_written(create_output(&output_file).write_all(dump.as_bytes()));
+        return;
+    }
 
-    let mut ast = match parser.parse() {
-        Ok(p) => p,
-        Err(e) => {
-            println!("{:?}", e);
-            return;
+    let mut modules = modules.into_iter();
+    let mut ir = modules.next().expect("at least one input file");
+    for module in modules {
+        if let Err(e) = timings.time("link modules", || ir.link(module)) {
+            eprintln!("error: {e}");
+            process::exit(1);
         }
-    };
+    }
+
+    optimize(&mut ir, options, timings);
 
-    let mut ty_checker = type_checker::TypeChecker::new();
-    if let Err(e) = ty_checker.visit_program(&mut ast) {
-        println!("{:?}", e);
+    if options.stats {
+        eprint!("{}", ModuleStats::new(&ir));
+    }
+
+    if options.run {
+        // the program may not return
+        if options.time_passes {
+            eprint!("{timings}");
+        }
+        let mut interpreter = Interpreter::new(&ir, io::stdin().lock(), io::stdout().lock())
+            .with_limits(limits(options));
+        match interpreter.run() {
+            Ok(exit_code) => process::exit(exit_code & 0xff),
+            Err(e) => {
+                eprintln!("error: {e}");
+                process::exit(1);
+            }
+        }
+    }
+
+    let target = target(options);
+    let compile = |ir| timings.time("codegen", || arm::compile(ir, options.opt_level, &target, options.stack_protector))
+        .unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            process::exit(1);
+        });
+    let object = |asm| timings.time("encode", || arm::object(asm));
+
+    if emit == options::EmitOption::Exe {
+        // the linker writes the output itself, to a file
+        let object = object(&compile(&ir));
+        let linked = if options::is_stdio(&output_file) {
+            env::temp_dir().join(format!("racoon-{}.out", process::id()))
+        } else {
+            output_file.clone()
+        };
+        if let Err(e) = timings.time("link", || link::link(&object, &linked, &options.linker, &target)) {
+            eprintln!("error: {e}");
+            process::exit(1);
+        }
+        if linked != output_file {
+            let executable = fs::read(&linked).expect("Failed to read linked executable");
+            let _ = fs::remove_file(&linked);
+            check_written(create_output(&output_file).write_all(&executable));
+        }
         return;
+    }
+
+    let mut output = create_output(&output_file);
+    check_written(match emit {
+        options::EmitOption::Tokens | options::EmitOption::Ast => unreachable!("emitted before lowering to IR"),
+        options::EmitOption::Ir => writeln!(output, "{ir}"),
+        options::EmitOption::DebugIr => writeln!(output, "{}", ir.debug_display()),
+        options::EmitOption::Llvm => writeln!(output, "{}", ir.llvm_display(options.opaque_pointers)),
+        options::EmitOption::C => write!(output, "{}", CSource::new(&ir)),
+        options::EmitOption::Asm => write!(output, "{}", compile(&ir)),
+        options::EmitOption::Obj => output.write_all(&object(&compile(&ir))),
+        options::EmitOption::Exe => unreachable!("executables are linked above"),
+    }.and_then(|()| output.flush()));
+}
+
+/// The target `options` generate code for, less the features turned off.
+fn target(options: &options::Options) -> Target {
+    let mut target = options.target;
+    target.features.movw_movt &= !options.no_movt;
+    target.features.hwdiv &= !options.no_hwdiv;
+    target.features.neon &= !options.no_neon;
+    target
+}
+
+/// The lints as `options` set them, exiting on names of no lint.
+fn lint_config(options: &options::Options) -> lint::LintConfig {
+    let mut lint_config = lint::LintConfig::default();
+    let lint_levels = [
+        (&options.allow_lints, lint::LintLevel::Allow),
+        (&options.warn_lints, lint::LintLevel::Warn),
+        (&options.deny_lints, lint::LintLevel::Deny),
+    ];
+    for (names, level) in lint_levels {
+        for name in names {
+            if !lint_config.set(name, level) {
+                eprintln!("error: unknown lint `{name}`");
+                process::exit(1);
+            }
+        }
+    }
+    lint_config
+}
+
+/// Runs the sanitizers and passes `options` ask for over `ir`.
+fn optimize(ir: &mut Module, options: &options::Options, timings: &Timings) {
+    // the checks go in first, while operations have their source locations
+    let mut sanitizers = PassManager::new();
+    for sanitizer in &options.sanitizers {
+        match sanitizer {
+            options::Sanitizer::Overflow => sanitizers.add_module_pass(SanitizeOverflow),
+            options::Sanitizer::Bounds => sanitizers.add_module_pass(SanitizeBounds),
+        };
+    }
+    sanitizers.add_instrumentation(timings.clone());
+    sanitizers.run(ir);
+
+    let mut pass_manager = match &options.passes {
+        Some(names) => PassManager::with_passes(names, target(options)).unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            eprintln!("  = note: the passes are {}", pipeline::pass_names().collect::<Vec<_>>().join(", "));
+            process::exit(1);
+        }),
+        None => PassManager::with_opt_level(options.opt_level, target(options)),
     };
+    if !options.print_ir_before.is_empty() || !options.print_ir_after.is_empty() {
+        let mut print_ir = PrintIr::new(options.print_ir_before.clone(), options.print_ir_after.clone())
+            .with_funcs(options.print_ir_func.clone());
+        if let Some(dir) = &options.print_ir_dir {
+            if let Err(e) = fs::create_dir_all(dir) {
+                eprintln!("error: could not create `{}`: {e}", dir.display());
+                process::exit(1);
+            }
+            print_ir = print_ir.with_dir(dir.clone());
+        }
+        pass_manager.add_instrumentation(print_ir);
+    }
+    pass_manager.add_instrumentation(timings.clone());
+    pass_manager.run(ir);
+}
 
-    let mut ir_builder = ir_builder::IrBuilder::new();
-    let ir = match ir_builder.visit(&ast) {
-        Ok(_) => ir_builder.ctx.cur_module,
+/// The limits of interpreted runs `options` set.
+fn limits(options: &options::Options) -> Limits {
+    Limits {
+        max_steps: options.max_steps,
+        max_call_depth: options.max_call_depth,
+        max_memory: options.max_memory,
+    }
+}
+
+/// Opens `output_file` for writing, or stdout if it is `-`.
+fn create_output(output_file: &Path) -> Box<dyn Write> {
+    if options::is_stdio(output_file) {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(output_file).expect("Failed to open or create output file"))
+    }
+}
+
+/// Exits on an error writing the output, quietly if stdout was a pipe closed
+/// early, as by `head`.
+fn check_written(result: io::Result<()>) {
+    match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => process::exit(0),
         Err(e) => {
-            println!("{:?}", e);
-            return;
+            eprintln!("error: could not write the output: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs the front end on `input_file`, or stdin if it is `-`, as far as
+/// what `options` emit needs, printing its diagnostics. Returns `None` if it
+/// has errors.
+fn compile_file(input_file: &Path, options: &options::Options, lint_config: &lint::LintConfig, timings: &Timings) -> Option<Compiled> {
+    let emit = options.emit();
+    let file = if options::is_stdio(input_file) { String::from("<stdin>") } else { input_file.display().to_string() };
+    let report = |diagnostic: Diagnostic| {
+        let diagnostic = diagnostic.with_file(&file);
+        match options.error_format {
+            options::ErrorFormat::Human => eprintln!("{diagnostic}"),
+            options::ErrorFormat::Json => eprintln!("{}", diagnostic.to_json()),
         }
     };
+    let input = if options::is_stdio(input_file) { io::read_to_string(io::stdin()) } else { fs::read_to_string(input_file) };
+    let input = match input {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("error: could not read `{file}`: {e}");
+            return None;
+        }
+    };
+
+    if emit == options::EmitOption::Tokens {
+        let mut tokens = String::new();
+        for token in timings.time("lex", || lexer::Lexer::new(input.chars()).collect::<Vec<_>>()) {
+            if let Some(e) = token.token_type.as_err() {
+                report((**e).into());
+                return None;
+            }
+            let start = token.span.start;
+            writeln!(tokens, "{}:{} {token:?}", start.lineno + 1, start.colno + 1).unwrap();
+        }
+        return Some(Compiled::Dump(tokens));
+    }
+
+    let lexer = lexer::Lexer::new(input.chars());
+    let mut parser = parser::Parser::new(lexer);
+
+    let mut ast = timings.time("parse", || parser.parse()).map_err(|e| report(e.into())).ok()?;
+    timings.time("resolve names", || name_resolver::NameResolver::new().resolve(&mut ast)).map_err(|e| report(e.into())).ok()?;
+
+    if let Some(format) = options.dump_ast {
+        eprint!("{}", dump_ast(&ast, format));
+    }
+    if emit == options::EmitOption::Ast {
+        return Some(Compiled::Dump(dump::to_tree(&ast)));
+    }
+
+    let typed_ast = timings.time("type check", || type_checker::TypeChecker::new().check(&ast)).map_err(|e| report(e.into())).ok()?;
+    if let Some(format) = options.dump_typed_ast {
+        eprint!("{}", dump_ast(typed_ast.program(), format));
+    }
+
+    let diagnostics = timings.time("lint", || lint::check_program(&typed_ast, lint_config));
+    let has_errors = diagnostics.iter().any(Diagnostic::is_error);
+    diagnostics.into_iter().for_each(report);
+    if has_errors {
+        return None;
+    }
+
+    let mut ir_builder = ir_builder::IrBuilder::new();
+    timings.time("build IR", || ir_builder.visit(typed_ast.program())).map_err(|e| report(e.into())).ok()?;
+    let mut ir = ir_builder.ctx.cur_module;
+    ir.debug_info.file = Some(file);
+    Some(Compiled::Module(ir))
+}
 
-    let output_file = options.output_file;
-    let mut output = File::create(output_file)
-        .expect("Failed to open or create output file");
-    writeln!(output, "{}", ir).expect("Failed to write output file");
+fn dump_ast(program: &ast::Program, format: options::AstFormat) -> String {
+    match format {
+        options::AstFormat::Pretty => dump::to_tree(program),
+        options::AstFormat::Json => dump::to_json(program) + "\n",
+    }
 }
@@ -1,27 +1,303 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use clap::Parser;
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use clap::{Args, Parser, Subcommand};
+
+use racoon::compiler::pass::pipeline::{self, OptLevel};
+use racoon::compiler::target::Target;
 
 #[derive(Parser, Debug)]
 #[structopt(name = "racoon",
             about = "An implementation for mini-SysY compiler in Rust",
             author = "roife <roifewu@gmail.com>")]
+#[command(subcommand_negates_reqs = true)]
 pub struct Options {
-    pub input_file: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The SysY source files, compiled into one program, `-` for stdin
+    #[arg(required = true)]
+    pub input_files: Vec<PathBuf>,
+
+    /// Write the output to this file, `-` for stdout, by default `a.out` for
+    /// executables, stdout for anything else read from stdin, and the first
+    /// input file with the extension of what is emitted otherwise
+    #[arg(short, long = "output", value_name = "FILE")]
+    pub output_file: Option<PathBuf>,
+
+    /// What to emit, by default an executable
+    #[arg(value_enum, long = "emit", value_name = "KIND", conflicts_with_all = ["assembly", "object"])]
+    pub emit_option: Option<EmitOption>,
 
-    #[arg(short, long, default_value = "a.out")]
-    pub output_file: PathBuf,
+    /// Emit assembly, as --emit=asm
+    #[arg(short = 'S', conflicts_with = "object")]
+    pub assembly: bool,
 
-    #[arg(value_enum, long="emit", default_value = "ir")]
-    pub emit_option: EmitOption,
+    /// Emit an object file, as --emit=obj
+    #[arg(short = 'c')]
+    pub object: bool,
 
-    #[arg(short, long)]
+    /// With --emit=llvm, spell every pointer type `ptr`, for LLVM 17 and
+    /// later
+    #[arg(long)]
+    pub opaque_pointers: bool,
+
+    /// Optimize at the given level, from 0 to 3
+    #[arg(short = 'O', value_name = "LEVEL", default_value = "0")]
+    pub opt_level: OptLevel,
+
+    /// Run the given passes, in order, instead of those of -O, as
+    /// `--passes=sroa,gvn,simplify-cfg,dce`
+    #[arg(short, long, value_name = "PASS", value_delimiter = ',', value_parser = pass_parser())]
     pub passes: Option<Vec<String>>,
+
+    /// Print the IR before each of the given passes, or `all`, to stderr
+    #[arg(long, value_name = "PASS", value_delimiter = ',')]
+    pub print_ir_before: Vec<String>,
+
+    /// Print the IR after each of the given passes, or `all`, to stderr
+    #[arg(long, value_name = "PASS", value_delimiter = ',')]
+    pub print_ir_after: Vec<String>,
+
+    /// With --print-ir-before or after, print only the given functions
+    #[arg(long, value_name = "FUNC", value_delimiter = ',')]
+    pub print_ir_func: Vec<String>,
+
+    /// With --print-ir-before or after, write each dump to a numbered file
+    /// in this directory rather than to stderr
+    #[arg(long, value_name = "DIR")]
+    pub print_ir_dir: Option<PathBuf>,
+
+    /// With --emit=asm, obj or exe, generate code for this target triple
+    #[arg(long, value_name = "TRIPLE", default_value = "armv7a-linux-gnueabihf")]
+    pub target: Target,
+
+    /// With --emit=asm, obj or exe, load constants from literal pools rather than build
+    /// them with movw and movt, for cores older than ARMv6T2
+    #[arg(long)]
+    pub no_movt: bool,
+
+    /// With --emit=asm, obj or exe, divide by calling the run-time library of the ABI
+    /// rather than with sdiv, for cores without a hardware divider
+    #[arg(long)]
+    pub no_hwdiv: bool,
+
+    /// With --emit=asm, obj or exe, split vectors into a register a lane rather than
+    /// hold them in NEON registers, for cores without Advanced SIMD
+    #[arg(long)]
+    pub no_neon: bool,
+
+    /// With --emit=exe, link with this C compiler driver for ARMv7-A, gcc or
+    /// clang
+    #[arg(long, value_name = "PROGRAM", default_value = "arm-linux-gnueabihf-gcc")]
+    pub linker: String,
+
+    /// Check for the given errors as the program runs, reporting the first
+    /// found and aborting
+    #[arg(long = "sanitize", value_name = "CHECK")]
+    pub sanitizers: Vec<Sanitizer>,
+
+    /// With --emit=asm, obj or exe, check on return from functions with
+    /// local arrays that a canary below the saved registers is intact,
+    /// aborting if a write past the end of an array overwrote it
+    #[arg(long)]
+    pub stack_protector: bool,
+
+    /// Print the size of each function to stderr
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Print the time and the most memory each phase and pass took to
+    /// stderr, slowest first
+    #[arg(long)]
+    pub time_passes: bool,
+
+    /// Interpret the program instead of writing IR, exiting with the value
+    /// returned by `main`
+    #[arg(long)]
+    pub run: bool,
+
+    /// With --run, abort after executing this many IR instructions
+    #[arg(long, value_name = "N")]
+    pub max_steps: Option<u64>,
+
+    /// With --run, abort when calls nest deeper than this
+    #[arg(long, value_name = "N")]
+    pub max_call_depth: Option<usize>,
+
+    /// With --run, abort when globals and stack exceed this many bytes
+    #[arg(long, value_name = "BYTES")]
+    pub max_memory: Option<usize>,
+
+    /// Print the syntax tree of each file to stderr once names are resolved,
+    /// as an indented tree or JSON
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = "pretty")]
+    pub dump_ast: Option<AstFormat>,
+
+    /// Print the syntax tree of each file to stderr once type checked, with
+    /// the type of every expression and constants folded
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = "pretty")]
+    pub dump_typed_ast: Option<AstFormat>,
+
+    /// Print diagnostics for people, or as JSON objects a line, for tools
+    #[arg(long, value_name = "FORMAT", default_value = "human")]
+    pub error_format: ErrorFormat,
+
+    /// Silence the given lint
+    #[arg(short = 'A', long = "allow", value_name = "LINT")]
+    pub allow_lints: Vec<String>,
+
+    /// Report the given lint as a warning
+    #[arg(short = 'W', long = "warn", value_name = "LINT")]
+    pub warn_lints: Vec<String>,
+
+    /// Report the given lint as an error
+    #[arg(short = 'D', long = "deny", value_name = "LINT")]
+    pub deny_lints: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run each SysY source with a `.out` file next to it, on the `.in` file
+    /// next to it if any, and check that it prints the output and returns
+    /// the exit code on the last line of the `.out` file, or with
+    /// --mode=diff, that it does the same natively as in the interpreter.
+    /// The options given before apply to every test
+    Test(TestOptions),
+    /// Shrink a SysY source while a check still succeeds on it, removing
+    /// declarations and statements and simplifying expressions, to make a
+    /// small program showing a bug
+    Reduce(ReduceOptions),
+}
+
+#[derive(Args, Debug)]
+pub struct TestOptions {
+    /// The sources, or directories searched for them
+    #[arg(required = true)]
+    pub tests: Vec<PathBuf>,
+
+    /// Run the tests in the interpreter, compiled for ARMv7-A, linked with
+    /// --linker and run under QEMU, or both ways to compare them
+    #[arg(long, value_name = "MODE", default_value = "interpret")]
+    pub mode: TestMode,
+
+    /// With --mode=native or diff, the QEMU user mode emulator to run the tests in
+    #[arg(long, value_name = "PROGRAM", default_value = "qemu-arm")]
+    pub qemu: String,
+
+    /// With --mode=native or diff, where the C library of the target is, for QEMU
+    /// to load
+    #[arg(long, value_name = "DIR", default_value = "/usr/arm-linux-gnueabihf")]
+    pub sysroot: PathBuf,
+
+    /// With --mode=native or diff, seconds a test may run before it fails
+    #[arg(long, value_name = "SECONDS", default_value = "10")]
+    pub timeout: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct ReduceOptions {
+    /// The source to reduce
+    pub file: PathBuf,
+
+    /// A shell command succeeding while the bug shows, as a crash or a
+    /// miscompilation, given the path of the source to check after its own
+    /// arguments
+    #[arg(long, value_name = "COMMAND")]
+    pub check: String,
+
+    /// Write the reduced source to this file, `-` for stdout
+    #[arg(short = 'o', long = "output", value_name = "FILE", default_value = "-")]
+    pub output: PathBuf,
+
+    /// Seconds a check may run before it is killed, as failing
+    #[arg(long, value_name = "SECONDS", default_value = "10")]
+    pub timeout: u64,
+}
+
+/// How `racoon test` runs the tests.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum TestMode {
+    /// In the IR interpreter, after the passes
+    Interpret,
+    /// Compiled and run under QEMU, timing each test
+    Native,
+    /// In the interpreter before the passes, then compiled and run under
+    /// QEMU, failing where the two differ, to catch the passes or the
+    /// backend miscompiling. Sources need no `.out` file
+    Diff,
+}
+
+impl FromStr for TestMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpret" => Ok(TestMode::Interpret),
+            "native" => Ok(TestMode::Native),
+            "diff" => Ok(TestMode::Diff),
+            _ => Err("Allowed test modes: interpret, native, diff"),
+        }
+    }
+}
+
+impl Options {
+    /// What to emit, from --emit, -S or -c.
+    pub fn emit(&self) -> EmitOption {
+        if self.assembly {
+            EmitOption::Asm
+        } else if self.object {
+            EmitOption::Obj
+        } else {
+            self.emit_option.clone().unwrap_or(EmitOption::Exe)
+        }
+    }
+
+    /// Where to write the output, from -o or else after the input file.
+    pub fn output_file(&self) -> PathBuf {
+        let emit = self.emit();
+        match &self.output_file {
+            Some(output_file) => output_file.clone(),
+            None if emit == EmitOption::Exe => PathBuf::from("a.out"),
+            None if is_stdio(&self.input_files[0]) => PathBuf::from("-"),
+            // in the working directory, as C compilers do
+            None => self.input_files[0].file_name().map_or_else(|| PathBuf::from("out"), PathBuf::from)
+                .with_extension(emit.extension()),
+        }
+    }
+}
+
+/// Takes the name of a pass, or one of its aliases, listing the names in the
+/// help and in the error for any other.
+fn pass_parser() -> PossibleValuesParser {
+    PossibleValuesParser::new(pipeline::pass_names().map(|name| PossibleValue::new(name).aliases(pipeline::pass_aliases(name))))
+}
+
+/// Whether `path` is `-`, standing for stdin or stdout.
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum EmitOption {
+    /// The tokens of the source, one a line with where they start
+    Tokens,
+    /// The syntax tree, once names are resolved, as --dump-ast prints it
+    Ast,
     Ir,
+    /// IR with values named after the source variables they came from
+    DebugIr,
+    /// LLVM IR for `llc` or `clang`
+    Llvm,
+    /// C source for any C compiler
+    C,
+    /// Assembly for ARMv7-A
+    Asm,
+    /// A relocatable ELF object for ARMv7-A, as assembling the assembly
+    /// would make
+    Obj,
+    /// An executable for ARMv7-A Linux, linked with the SysY runtime
+    Exe,
 }
 
 impl FromStr for EmitOption {
@@ -29,8 +305,95 @@ impl FromStr for EmitOption {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "tokens" => Ok(EmitOption::Tokens),
+            "ast" => Ok(EmitOption::Ast),
             "ir" => Ok(EmitOption::Ir),
-            _ => Err("Allowed emit options: ir"),
+            "debug-ir" => Ok(EmitOption::DebugIr),
+            "llvm" => Ok(EmitOption::Llvm),
+            "c" => Ok(EmitOption::C),
+            "asm" => Ok(EmitOption::Asm),
+            "obj" => Ok(EmitOption::Obj),
+            "exe" => Ok(EmitOption::Exe),
+            _ => Err("Allowed emit options: tokens, ast, ir, debug-ir, llvm, c, asm, obj, exe"),
+        }
+    }
+}
+
+impl EmitOption {
+    /// The extension of files holding what is emitted.
+    fn extension(&self) -> &'static str {
+        match self {
+            EmitOption::Tokens => "tokens",
+            EmitOption::Ast => "ast",
+            EmitOption::Ir | EmitOption::DebugIr => "ir",
+            EmitOption::Llvm => "ll",
+            EmitOption::C => "c",
+            EmitOption::Asm => "s",
+            EmitOption::Obj => "o",
+            EmitOption::Exe => "out",
+        }
+    }
+}
+
+/// How syntax trees are printed.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum AstFormat {
+    /// An indented tree, a node a line
+    Pretty,
+    /// A JSON object on one line
+    Json,
+}
+
+impl FromStr for AstFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(AstFormat::Pretty),
+            "json" => Ok(AstFormat::Json),
+            _ => Err("Allowed syntax tree formats: pretty, json"),
+        }
+    }
+}
+
+/// How diagnostics are printed.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ErrorFormat {
+    Human,
+    /// A JSON object a line, with the code, severity, message, file, span,
+    /// notes and suggestions
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err("Allowed error formats: human, json"),
+        }
+    }
+}
+
+/// Errors the program can be checked for as it runs.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Sanitizer {
+    /// Signed overflow of `int` arithmetic, and division by zero
+    Overflow,
+    /// Indices out of the bounds of arrays
+    Bounds,
+}
+
+impl FromStr for Sanitizer {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overflow" => Ok(Sanitizer::Overflow),
+            "bounds" => Ok(Sanitizer::Bounds),
+            _ => Err("Allowed checks: overflow, bounds"),
         }
     }
 }
@@ -0,0 +1,325 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use racoon::compiler::diagnostic::Diagnostic;
+use racoon::compiler::syntax::ast::{AstTy, BlockItem, BlockStmt, Decl, Expr, IfStmt, InitVal, InitValKind, LiteralExpr, LiteralKind, NodeId, Program, ProgramItem, Stmt};
+use racoon::compiler::syntax::{lexer::Lexer, parser::Parser, unparse};
+
+use crate::options::ReduceOptions;
+use crate::{check_written, create_output};
+
+/// Reduces the source as `racoon reduce` does, printing its size after each
+/// round, and writes what is left. Returns whether it could.
+pub fn run(reduce_options: &ReduceOptions) -> bool {
+    let file = reduce_options.file.display().to_string();
+    let input = match fs::read_to_string(&reduce_options.file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("error: could not read `{file}`: {e}");
+            return false;
+        }
+    };
+    let mut program = match Parser::new(Lexer::new(input.chars())).parse() {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", Diagnostic::from(e).with_file(&file));
+            return false;
+        }
+    };
+
+    // the check is given a file of the same name, in a directory of its own
+    let dir = TempDir(env::temp_dir().join(format!("racoon-reduce-{}", process::id())));
+    let candidate = dir.0.join(reduce_options.file.file_name().unwrap_or("test.sy".as_ref()));
+    if let Err(e) = fs::create_dir_all(&dir.0) {
+        eprintln!("error: could not create `{}`: {e}", dir.0.display());
+        return false;
+    }
+    // interrupted, the check running is killed and the directory removed
+    if let Err(e) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+        eprintln!("error: could not handle interruption: {e}");
+        return false;
+    }
+    let mut checker = Checker {
+        command: &reduce_options.check,
+        candidate: &candidate,
+        timeout: Duration::from_secs(reduce_options.timeout),
+        checks: 0,
+    };
+    let result = reduce(&mut program, &mut checker);
+    drop(dir);
+
+    match result {
+        Ok(source) => {
+            check_written(create_output(&reduce_options.output).write_all(source.as_bytes()));
+            true
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            false
+        }
+    }
+}
+
+/// Set once the reduction is interrupted.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// A directory removed with what is in it when dropped.
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the check on candidates.
+struct Checker<'a> {
+    command: &'a str,
+    candidate: &'a Path,
+    timeout: Duration,
+    checks: usize,
+}
+
+impl Checker<'_> {
+    /// Whether the check succeeds on `source`, within the timeout.
+    fn check(&mut self, source: &str) -> Result<bool, String> {
+        self.checks += 1;
+        fs::write(self.candidate, source).map_err(|e| format!("could not write `{}`: {e}", self.candidate.display()))?;
+        let mut command = Command::new("sh");
+        command
+            .arg("-c").arg(format!("{} \"$1\"", self.command))
+            .arg("sh").arg(self.candidate)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // in a group of its own, to kill what it runs with it
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command.spawn().map_err(|e| format!("could not run the check: {e}"))?;
+        let start = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait().map_err(|e| format!("could not run the check: {e}"))? {
+                return Ok(status.success());
+            }
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                kill(&mut child);
+                return Err(String::from("interrupted"));
+            }
+            if start.elapsed() >= self.timeout {
+                kill(&mut child);
+                return Ok(false);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Kills `child` and, where there are process groups, its group, giving
+/// them a second to end on `SIGTERM` first.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        let group = format!("-{}", child.id());
+        let signal = |name: &str| Command::new("kill").arg(name).arg("--").arg(&group).status();
+        let _ = signal("-TERM");
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) && matches!(child.try_wait(), Ok(None)) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = signal("-KILL");
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Reduces `program` while `checker` succeeds on it, returning the source
+/// left. Edits are tried greedily, each kept if the source gets shorter and
+/// the check still succeeds, until a round of all of them keeps none.
+fn reduce(program: &mut Program, checker: &mut Checker) -> Result<String, String> {
+    let mut source = unparse::to_source(program);
+    if !checker.check(&source)? {
+        return Err(String::from("the check does not succeed on the source to reduce, as racoon prints it"));
+    }
+    loop {
+        let before = source.len();
+        // no list is longer than the source is in lines
+        let longest = source.lines().count().next_power_of_two();
+        let removals = (0..=longest.trailing_zeros()).rev().map(|x| Edit::Remove(1 << x));
+        for edit in removals.chain([Edit::Simplify]) {
+            let mut target = 0;
+            loop {
+                let mut candidate = program.clone();
+                if !(Editor { edit, target, sites: 0 }).program(&mut candidate) {
+                    break;
+                }
+                let candidate_source = unparse::to_source(&candidate);
+                if candidate_source.len() < source.len() && checker.check(&candidate_source)? {
+                    *program = candidate;
+                    source = candidate_source;
+                } else {
+                    target += 1;
+                }
+            }
+        }
+        eprintln!("{} bytes after {} checks", source.len(), checker.checks);
+        if source.len() == before {
+            return Ok(source);
+        }
+    }
+}
+
+/// A kind of edit to the tree.
+#[derive(Debug, Clone, Copy)]
+enum Edit {
+    /// Removing so many items of a list, declarations, statements or
+    /// initializers, from an index that is a multiple of it.
+    Remove(usize),
+    /// Replacing a statement with one inside it, or an expression with one
+    /// inside it or `0`, or dropping an initializer.
+    Simplify,
+}
+
+/// Makes `edit` at the site numbered `target`, numbering the sites it can be
+/// made at in the order the tree is walked.
+struct Editor {
+    edit: Edit,
+    target: usize,
+    sites: usize,
+}
+
+impl Editor {
+    /// Whether the edit was made, at the site targeted or inside `program`.
+    fn program(&mut self, program: &mut Program) -> bool {
+        self.list(&mut program.program_items, 0) || program.program_items.iter_mut().any(|item| match item {
+            ProgramItem::Decl(decl) => self.decl(decl),
+            ProgramItem::Func(func) => func.body.as_mut().is_some_and(|body| self.block(body)),
+        })
+    }
+
+    /// Removes items from `items` leaving at least `min`.
+    fn list<T>(&mut self, items: &mut Vec<T>, min: usize) -> bool {
+        let Edit::Remove(len) = self.edit else {
+            return false;
+        };
+        for start in (0..items.len()).step_by(len) {
+            let end = items.len().min(start + len);
+            if items.len() - (end - start) >= min && self.hit() {
+                items.drain(start..end);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Replaces `node` with `make(node, i)`, the `i`th of its `count` simpler
+    /// alternatives, if one of them is targeted.
+    fn replace<T>(&mut self, node: &mut T, count: usize, make: impl FnOnce(&T, usize) -> T) -> bool {
+        if !matches!(self.edit, Edit::Simplify) {
+            return false;
+        }
+        let hit = (self.sites..self.sites + count).contains(&self.target);
+        if hit {
+            *node = make(node, self.target - self.sites);
+        }
+        self.sites += count;
+        hit
+    }
+
+    fn hit(&mut self) -> bool {
+        self.sites += 1;
+        self.sites - 1 == self.target
+    }
+
+    fn decl(&mut self, decl: &mut Decl) -> bool {
+        self.list(&mut decl.sub_decls, 1) || decl.sub_decls.iter_mut().any(|sub_decl| {
+            // constants must be initialized
+            let count = usize::from(!decl.is_const && sub_decl.init_val.is_some());
+            self.replace(&mut sub_decl.init_val, count, |_, _| None)
+                || sub_decl.init_val.as_mut().is_some_and(|x| self.init_val(x))
+        })
+    }
+
+    fn init_val(&mut self, init_val: &mut InitVal) -> bool {
+        match &mut init_val.kind {
+            InitValKind::Expr(x) => self.expr(x),
+            InitValKind::ArrayVal(vals) => self.list(vals, 0) || vals.iter_mut().any(|x| self.init_val(x)),
+            InitValKind::Const(_) => false,
+        }
+    }
+
+    fn block(&mut self, block: &mut BlockStmt) -> bool {
+        self.list(&mut block.block_items, 0) || block.block_items.iter_mut().any(|item| match item {
+            BlockItem::Decl(decl) => self.decl(decl),
+            BlockItem::Stmt(stmt) => self.stmt(stmt),
+        })
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) -> bool {
+        let count = match stmt {
+            // the branches, and the if without its else
+            Stmt::If(x) => if x.else_block.is_some() { 3 } else { 1 },
+            Stmt::While(_) => 1,
+            _ => 0,
+        };
+        let replaced = self.replace(stmt, count, |stmt, i| match (stmt, i) {
+            (Stmt::If(x), 0) => (*x.then_block).clone(),
+            (Stmt::If(x), 1) => (**x.else_block.as_ref().unwrap()).clone(),
+            (Stmt::If(x), _) => Stmt::If(IfStmt { else_block: None, ..x.clone() }),
+            (Stmt::While(x), _) => (*x.body).clone(),
+            _ => unreachable!("no other statement has alternatives"),
+        });
+        replaced || match stmt {
+            Stmt::Expr(x) => self.expr(x),
+            Stmt::Block(x) => self.block(x),
+            Stmt::If(x) => {
+                self.expr(&mut x.cond) || self.stmt(&mut x.then_block)
+                    || x.else_block.as_mut().is_some_and(|x| self.stmt(x))
+            }
+            Stmt::While(x) => self.expr(&mut x.cond) || self.stmt(&mut x.body),
+            Stmt::Return(x) => x.val.as_mut().is_some_and(|x| self.expr(x)),
+            Stmt::Break(_) | Stmt::Continue(_) | Stmt::Empty(_) => false,
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) -> bool {
+        // `0`, then the operands
+        let count = match expr {
+            Expr::Literal(_) => 0,
+            Expr::Binary(_) => 3,
+            Expr::Assign(_) | Expr::Unary(_) | Expr::Cast(_) => 2,
+            Expr::LVal(_) | Expr::Call(_) => 1,
+        };
+        let replaced = self.replace(expr, count, |expr, i| match (expr, i) {
+            (_, 0) => Expr::Literal(LiteralExpr {
+                node_id: NodeId::DUMMY,
+                kind: LiteralKind::Integer(0),
+                span: expr.span(),
+                ty: AstTy::Unknown,
+            }),
+            (Expr::Binary(x), 1) => (*x.lhs).clone(),
+            (Expr::Binary(x), _) => (*x.rhs).clone(),
+            (Expr::Assign(x), _) => (*x.rhs).clone(),
+            (Expr::Unary(x), _) => (*x.sub_expr).clone(),
+            (Expr::Cast(x), _) => (*x.sub_expr).clone(),
+            _ => unreachable!("no other expression has operands"),
+        });
+        replaced || match expr {
+            // what is assigned to stays assignable
+            Expr::Assign(x) => self.expr(&mut x.rhs),
+            Expr::Binary(x) => self.expr(&mut x.lhs) || self.expr(&mut x.rhs),
+            Expr::Unary(x) => self.expr(&mut x.sub_expr),
+            Expr::Cast(x) => self.expr(&mut x.sub_expr),
+            Expr::Call(x) => x.args.iter_mut().any(|x| self.expr(x)),
+            Expr::LVal(x) => x.subs.as_mut().is_some_and(|x| x.subs.iter_mut().any(|x| self.expr(x))),
+            Expr::Literal(_) => false,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+// The SysY runtime library, linked into every executable racoon makes. Its
+// functions behave as those of the interpreter do.
+
+#define _DEFAULT_SOURCE
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/time.h>
+
+int getint(void) {
+    int x;
+    scanf("%d", &x);
+    return x;
+}
+
+int getch(void) {
+    return getchar();
+}
+
+int getarray(int a[]) {
+    int n;
+    scanf("%d", &n);
+    for (int i = 0; i < n; i++) {
+        scanf("%d", &a[i]);
+    }
+    return n;
+}
+
+void putint(int x) {
+    printf("%d", x);
+}
+
+void putch(int x) {
+    putchar(x);
+}
+
+void putarray(int n, int a[]) {
+    printf("%d:", n);
+    for (int i = 0; i < n; i++) {
+        printf(" %d", a[i]);
+    }
+    putchar('\n');
+}
+
+// Called by the checks racoon inserts with --sanitize when they fail, with
+// what failed, numbered as `Check` in the compiler, and where.
+void __racoon_trap(int check, int line, int col) {
+    static const char *const messages[] = {
+        "signed integer overflow",
+        "division by zero",
+        "index out of bounds",
+    };
+    const char *message = check >= 0 && check < (int)(sizeof messages / sizeof *messages) ? messages[check] : "check failed";
+    fflush(stdout);
+    fprintf(stderr, "runtime error: %s at %d:%d\n", message, line, col);
+    abort();
+}
+
+// Timing, as the reference runtime has it, for code calling `_sysy_starttime`
+// and `_sysy_stoptime` with the line they are on. The time between each pair
+// is reported on stderr at exit.
+
+#define MAX_TIMERS 1024
+
+static struct timeval start, total;
+static int timers;
+static int start_lines[MAX_TIMERS], stop_lines[MAX_TIMERS];
+static struct timeval elapsed[MAX_TIMERS];
+
+void _sysy_starttime(int line) {
+    if (timers < MAX_TIMERS) {
+        start_lines[timers] = line;
+    }
+    gettimeofday(&start, NULL);
+}
+
+void _sysy_stoptime(int line) {
+    struct timeval stop;
+    gettimeofday(&stop, NULL);
+    if (timers >= MAX_TIMERS) {
+        return;
+    }
+    stop_lines[timers] = line;
+    timersub(&stop, &start, &elapsed[timers]);
+    timeradd(&total, &elapsed[timers], &total);
+    timers++;
+}
+
+static void report(const char *name, struct timeval t) {
+    long s = t.tv_sec;
+    fprintf(stderr, "%s: %ldH-%ldM-%ldS-%ldus\n", name, s / 3600, s / 60 % 60, s % 60, (long)t.tv_usec);
+}
+
+__attribute__((destructor)) static void report_timers(void) {
+    char name[32];
+    for (int i = 0; i < timers; i++) {
+        snprintf(name, sizeof name, "Timer@%04d-%04d", start_lines[i], stop_lines[i]);
+        report(name, elapsed[i]);
+    }
+    if (timers > 0) {
+        report("TOTAL", total);
+    }
+}
@@ -0,0 +1,19 @@
+// Declarations of the SysY runtime library, for C compilers building SysY
+// programs, which call its functions undeclared.
+
+#ifndef SYLIB_H
+#define SYLIB_H
+
+int getint(void);
+int getch(void);
+int getarray(int a[]);
+void putint(int x);
+void putch(int x);
+void putarray(int n, int a[]);
+void _sysy_starttime(int line);
+void _sysy_stoptime(int line);
+
+#define starttime() _sysy_starttime(__LINE__)
+#define stoptime() _sysy_stoptime(__LINE__)
+
+#endif
@@ -0,0 +1,40 @@
+//! What `racoon test` and the difftest harness share: finding the tests and
+//! running them under QEMU.
+
+#![forbid(unsafe_code)]
+#![warn(
+clippy::pedantic,
+missing_copy_implementations,
+missing_debug_implementations,
+rustdoc::broken_intra_doc_links,
+trivial_numeric_casts,
+unused_allocation
+)]
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub mod qemu;
+
+/// Adds `path` to `tests` if it is a file, or the `SysY` sources under it if it
+/// is a directory, those with an expected output alone if `needs_out`.
+///
+/// # Errors
+///
+/// Fails if a directory cannot be read.
+pub fn collect(path: &Path, needs_out: bool, tests: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        tests.push(path.to_owned());
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(&path, needs_out, tests)?;
+        } else if path.extension().is_some_and(|x| x == "sy") && (!needs_out || path.with_extension("out").is_file()) {
+            tests.push(path);
+        }
+    }
+    Ok(())
+}
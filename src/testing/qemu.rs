@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::panic;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How a program run under QEMU ended.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// With the exit code, `None` if killed by a signal, and the output.
+    Exited { code: Option<i32>, stdout: Vec<u8> },
+    TimedOut,
+}
+
+/// Runs the ARM Linux executable `program` with the user mode emulator
+/// `qemu`, loading the C library from `sysroot`, on `input` or nothing,
+/// and kills it after `timeout`. Returns how it ended and how long it ran.
+///
+/// # Errors
+///
+/// Fails if `input` cannot be opened, or `qemu` run or waited for.
+pub fn run(qemu: &str, sysroot: &Path, program: &Path, input: Option<&Path>, timeout: Duration) -> io::Result<(Outcome, Duration)> {
+    let stdin = match input {
+        Some(input) => Stdio::from(File::open(input)?),
+        None => Stdio::null(),
+    };
+    let start = Instant::now();
+    let mut child = Command::new(qemu)
+        .arg("-L").arg(sysroot)
+        .arg(program)
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // read as it runs, so that it does not block on a full pipe
+    let Some(mut stdout) = child.stdout.take() else { unreachable!("stdout is piped") };
+    let reader = thread::spawn(move || {
+        let mut buf = vec![];
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if start.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            break None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let time = start.elapsed();
+    let stdout = reader.join().unwrap_or_else(|e| panic::resume_unwind(e))?;
+    let outcome = match status {
+        Some(status) => Outcome::Exited { code: status.code(), stdout },
+        None => Outcome::TimedOut,
+    };
+    Ok((outcome, time))
+}
@@ -0,0 +1,169 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use racoon::compiler::ir::value::module::Module;
+use racoon::compiler::pass::PassInstrumentation;
+
+/// The system allocator, counting the bytes in use, to find the most each
+/// phase of the compiler needs at once.
+pub struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                grow(new_size - layout.size());
+            } else {
+                ALLOCATED.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        new_ptr
+    }
+}
+
+fn grow(size: usize) {
+    let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(allocated, Ordering::Relaxed);
+}
+
+/// What a phase took, over every time it ran.
+#[derive(Debug)]
+struct Phase {
+    name: String,
+    time: Duration,
+    /// The most bytes allocated at once on top of those in use when it
+    /// started.
+    peak: usize,
+    runs: usize,
+}
+
+/// A phase being timed.
+#[derive(Debug, Clone, Copy)]
+struct Running {
+    start: Instant,
+    allocated: usize,
+}
+
+impl Running {
+    fn start() -> Running {
+        let allocated = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(allocated, Ordering::Relaxed);
+        Running { start: Instant::now(), allocated }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    /// In the order they first ran.
+    phases: Vec<Phase>,
+    /// The pass started, see [`PassInstrumentation`].
+    pass: Option<Running>,
+}
+
+/// The wall time and memory each phase of the compiler takes, passes
+/// included, recorded by name, for `--time-passes`. Phases must not nest.
+///
+/// Clones record into the same table, so that one can be handed to a
+/// [`PassManager`](racoon::compiler::pass::PassManager).
+#[derive(Debug, Clone, Default)]
+pub struct Timings(Rc<RefCell<Recorded>>);
+
+impl Timings {
+    /// Runs `f` as the phase `name`.
+    pub fn time<T>(&self, name: &str, f: impl FnOnce() -> T) -> T {
+        let running = Running::start();
+        let result = f();
+        self.record(name, running);
+        result
+    }
+
+    fn record(&self, name: &str, running: Running) {
+        let time = running.start.elapsed();
+        let peak = PEAK.load(Ordering::Relaxed).saturating_sub(running.allocated);
+        let phases = &mut self.0.borrow_mut().phases;
+        match phases.iter_mut().find(|x| x.name == name) {
+            Some(phase) => {
+                phase.time += time;
+                phase.peak = phase.peak.max(peak);
+                phase.runs += 1;
+            }
+            None => phases.push(Phase { name: String::from(name), time, peak, runs: 1 }),
+        }
+    }
+}
+
+impl PassInstrumentation for Timings {
+    fn before_pass(&mut self, _pass: &str, _module: &Module) {
+        self.0.borrow_mut().pass = Some(Running::start());
+    }
+
+    fn after_pass(&mut self, pass: &str, _module: &Module) {
+        let running = self.0.borrow_mut().pass.take();
+        if let Some(running) = running {
+            self.record(pass, running);
+        }
+    }
+}
+
+/// A table of the phases, slowest first.
+impl Display for Timings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let recorded = self.0.borrow();
+        let mut phases: Vec<_> = recorded.phases.iter().collect();
+        phases.sort_by(|x, y| y.time.cmp(&x.time));
+        let total: Duration = phases.iter().map(|x| x.time).sum();
+
+        writeln!(f, "{:>12} {:>7} {:>12} {:>5}  phase", "time", "", "peak memory", "runs")?;
+        for phase in phases {
+            let share = if total.is_zero() { 0.0 } else { phase.time.as_secs_f64() / total.as_secs_f64() * 100.0 };
+            writeln!(f, "{:>9.3} ms {share:>6.1}% {:>12} {:>5}  {}",
+                     phase.time.as_secs_f64() * 1000.0, Bytes(phase.peak), phase.runs, phase.name)?;
+        }
+        writeln!(f, "{:>9.3} ms {:>6.1}% {:>12} {:>5}  total", total.as_secs_f64() * 1000.0, 100.0, "", "")
+    }
+}
+
+/// A size in bytes, in the largest unit it is at least one of.
+struct Bytes(usize);
+
+impl Display for Bytes {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let size = self.0 as f64;
+        let text = match self.0 {
+            0..=1023 => format!("{} B", self.0),
+            1024..=0xf_ffff => format!("{:.1} KiB", size / 1024.0),
+            _ => format!("{:.1} MiB", size / (1024.0 * 1024.0)),
+        };
+        f.pad(&text)
+    }
+}